@@ -0,0 +1,85 @@
+//! How long each encoding takes to serialize and deserialize the same
+//! [`Fixture`], and how big the result is.
+//!
+//! ```bash
+//! cargo bench --features ssr --bench codecs
+//! ```
+
+use criterion::{
+    criterion_group, criterion_main, measurement::WallTime, BenchmarkGroup,
+    Criterion, Throughput,
+};
+use server_fn::{
+    codec::{CborEncoding, JsonEncoding, PostcardEncoding, SerdeLiteEncoding},
+    Decodes, Encodes,
+};
+use server_fns_axum::{codec::AlignedRkyvEncoding, fixtures::Fixture};
+use std::{fmt::Debug, hint::black_box};
+
+/// Row counts to run every encoding with: a typical page, and a bulk export.
+const SIZES: [usize; 2] = [20, 2_000];
+
+fn bench_encoding<E>(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    name: &str,
+    fixture: &Fixture,
+) where
+    E: Encodes<Fixture> + Decodes<Fixture>,
+    <E as Encodes<Fixture>>::Error: Debug,
+    <E as Decodes<Fixture>>::Error: Debug,
+{
+    let encoded = E::encode(fixture).unwrap();
+    assert_eq!(&E::decode(encoded.clone()).unwrap(), fixture);
+    report_size(name, fixture, encoded.len());
+
+    group.throughput(Throughput::Bytes(encoded.len() as u64));
+    group.bench_function(format!("{name}/serialize"), |b| {
+        b.iter(|| E::encode(black_box(fixture)).unwrap())
+    });
+    group.bench_function(format!("{name}/deserialize"), |b| {
+        b.iter(|| E::decode(black_box(encoded.clone())).unwrap())
+    });
+}
+
+/// TOML goes through `toml` directly, like the `Toml` codec in `app` does,
+/// since it is a custom encoding rather than an [`Encodes`] one.
+fn bench_toml(group: &mut BenchmarkGroup<'_, WallTime>, fixture: &Fixture) {
+    let encoded = toml::to_string(fixture).unwrap();
+    assert_eq!(&toml::from_str::<Fixture>(&encoded).unwrap(), fixture);
+    report_size("toml", fixture, encoded.len());
+
+    group.throughput(Throughput::Bytes(encoded.len() as u64));
+    group.bench_function("toml/serialize", |b| {
+        b.iter(|| toml::to_string(black_box(fixture)).unwrap())
+    });
+    group.bench_function("toml/deserialize", |b| {
+        b.iter(|| toml::from_str::<Fixture>(black_box(&encoded)).unwrap())
+    });
+}
+
+/// Criterion only reports times and throughput, so sizes are printed as
+/// each encoding is set up.
+fn report_size(name: &str, fixture: &Fixture, bytes: usize) {
+    println!(
+        "{name:>10} encodes {} rows in {bytes} bytes ({:.1} per row)",
+        fixture.rows.len(),
+        bytes as f64 / fixture.rows.len() as f64
+    );
+}
+
+fn codecs(c: &mut Criterion) {
+    for rows in SIZES {
+        let fixture = Fixture::sample(rows);
+        let mut group = c.benchmark_group(format!("{rows} rows"));
+        bench_encoding::<JsonEncoding>(&mut group, "json", &fixture);
+        bench_encoding::<SerdeLiteEncoding>(&mut group, "serde-lite", &fixture);
+        bench_encoding::<PostcardEncoding>(&mut group, "postcard", &fixture);
+        bench_encoding::<AlignedRkyvEncoding>(&mut group, "rkyv", &fixture);
+        bench_encoding::<CborEncoding>(&mut group, "cbor", &fixture);
+        bench_toml(&mut group, &fixture);
+        group.finish();
+    }
+}
+
+criterion_group!(benches, codecs);
+criterion_main!(benches);
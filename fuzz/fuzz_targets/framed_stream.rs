@@ -0,0 +1,26 @@
+//! Feeds arbitrary bodies to the reader behind `Framed` responses, such as
+//! the upload progress stream.
+//!
+//! The first byte picks the size of the network chunks the body arrives in.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use server_fns_axum::codec::FrameReader;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&chunk_size, body)) = data.split_first() else {
+        return;
+    };
+    let mut reader = FrameReader::<serde_json::Value>::new();
+    let mut errors = 0;
+    for chunk in body.chunks(usize::from(chunk_size).max(1)) {
+        reader.push(chunk);
+        while let Some(item) = reader.next_item() {
+            errors += usize::from(item.is_err());
+        }
+    }
+    errors += usize::from(reader.finish().is_some_and(|item| item.is_err()));
+    // the first error is always the last item
+    assert!(errors <= 1);
+    assert!(reader.is_done());
+});
@@ -0,0 +1,37 @@
+//! Feeds arbitrary bodies to the chunk loop behind the upload server fns.
+//!
+//! The first byte picks the size of the network chunks the body arrives in,
+//! and the rest, up to the first newline, is the boundary.
+#![no_main]
+
+use bytes::Bytes;
+use futures::{executor::block_on, stream};
+use libfuzzer_sys::fuzz_target;
+use server_fns_axum::multipart::for_each_chunk;
+use std::convert::Infallible;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&chunk_size, rest)) = data.split_first() else {
+        return;
+    };
+    let Some(newline) = rest.iter().position(|b| *b == b'\n') else {
+        return;
+    };
+    let (boundary, body) = (&rest[..newline], &rest[newline + 1..]);
+    let Ok(boundary) = std::str::from_utf8(boundary) else {
+        return;
+    };
+    let chunks = body
+        .chunks(usize::from(chunk_size).max(1))
+        .map(|chunk| Ok::<_, Infallible>(Bytes::copy_from_slice(chunk)))
+        .collect::<Vec<_>>();
+    let multipart = multer::Multipart::new(stream::iter(chunks), boundary);
+
+    let mut total = 0;
+    let result = block_on(for_each_chunk(multipart, |_, chunk| {
+        total += chunk.len();
+        Ok(())
+    }));
+    // whatever a field contains, it came out of the body
+    assert!(result.is_err() || total <= body.len());
+});
@@ -0,0 +1,282 @@
+#[cfg(feature = "ssr")]
+use crate::{
+    auth::{require_admin, USERS},
+    cache::CACHE,
+    metrics::METRICS,
+    storage::ROWS,
+};
+use crate::{
+    branding::BrandingEditor,
+    cache::CacheStats,
+    client_errors::ClientErrors,
+    commands::TaskRunner,
+    flags::{get_flags, SetFlag},
+    metrics::{ServerFnUsage, UploadVolume},
+    prefetch::{cached, PrefetchLink, RouteData},
+    progress::{ProgressKind, ProgressStream},
+    replicas::ReplicaPanel,
+    seo::PageMeta,
+    snapshots::SnapshotPanel,
+    tail::LogTail,
+};
+use chrono::NaiveDate;
+use leptos::prelude::*;
+use leptos_router::components::A;
+use serde::{Deserialize, Serialize};
+use server_fn::codec::GetUrl;
+
+/// How many days [`AdminStats::rows_per_day`] goes back.
+pub const STATS_DAYS: u64 = 14;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdminStats {
+    pub users: usize,
+    pub rows: usize,
+    pub completed_rows: usize,
+    /// Rows added on each of the last [`STATS_DAYS`] days (in UTC), oldest
+    /// first, including the days without any.
+    pub rows_per_day: Vec<(NaiveDate, usize)>,
+    pub uploads: UploadVolume,
+    pub cache: CacheStats,
+    pub server_fns: Vec<ServerFnUsage>,
+}
+
+#[server(input = GetUrl)]
+pub async fn admin_stats() -> Result<AdminStats, ServerFnError> {
+    use chrono::{Days, Utc};
+
+    require_admin()?;
+    let rows = ROWS.stats();
+    let today = Utc::now().date_naive();
+    let rows_per_day = (0..STATS_DAYS)
+        .rev()
+        .filter_map(|ago| today.checked_sub_days(Days::new(ago)))
+        .map(|day| {
+            (
+                day,
+                rows.added_per_day.get(&day).copied().unwrap_or_default(),
+            )
+        })
+        .collect();
+    Ok(AdminStats {
+        users: USERS.len(),
+        rows: rows.rows,
+        completed_rows: rows.completed,
+        rows_per_day,
+        uploads: METRICS.uploads(),
+        cache: CACHE.stats(),
+        server_fns: METRICS.server_fns(),
+    })
+}
+
+/// Aggregate stats over every user, for admins only.
+#[component]
+pub fn AdminPage() -> impl IntoView {
+    let stats = Resource::new(
+        || (),
+        |_| cached(RouteData::AdminStats.key(), admin_stats),
+    );
+
+    view! {
+        <PageMeta title="Admin" description="Usage across every user." private=true />
+        <h2>"Admin"</h2>
+        <p>
+            <A href="/">"Back to the demo"</A>
+            " "
+            <PrefetchLink href="/load">"Load testing"</PrefetchLink>
+        </p>
+        <Suspense fallback=|| view! { <p>"Loading..."</p> }>
+            {move || Suspend::new(async move {
+                match stats.await {
+                    Ok(stats) => view! { <StatsView stats /> }.into_any(),
+                    Err(e) => view! { <p>{e.to_string()}</p> }.into_any(),
+                }
+            })}
+        </Suspense>
+        <ClientErrors />
+        <FlagToggles />
+        <ReplicaPanel />
+        <SnapshotPanel />
+        <BrandingEditor />
+        <LogTail />
+        <TaskRunner />
+        <h3>"Task runs"</h3>
+        <ProgressStream kind=ProgressKind::Task />
+        <h3>"Background jobs"</h3>
+        <ProgressStream kind=ProgressKind::Job />
+    }
+}
+
+/// A button per feature flag, to switch the example it controls on or off
+/// for everyone. Pages already open keep what they loaded with.
+#[component]
+fn FlagToggles() -> impl IntoView {
+    let set_flag = ServerAction::<SetFlag>::new();
+    let flags =
+        Resource::new(move || set_flag.version().get(), |_| get_flags());
+
+    view! {
+        <h3>"Feature flags"</h3>
+        <Transition>
+            {move || Suspend::new(async move {
+                flags
+                    .await
+                    .map(|flags| {
+                        flags
+                            .0
+                            .into_iter()
+                            .map(|(flag, enabled)| {
+                                let label = if enabled { "Turn off" } else { "Turn on" };
+                                view! {
+                                    <p>
+                                        {format!("{flag}: {} ", if enabled { "on" } else { "off" })}
+                                        <button on:click=move |_| {
+                                            set_flag.dispatch(SetFlag { flag, enabled: !enabled });
+                                        }>{label}</button>
+                                    </p>
+                                }
+                            })
+                            .collect::<Vec<_>>()
+                    })
+            })}
+        </Transition>
+        {move || {
+            set_flag
+                .value()
+                .get()
+                .and_then(Result::err)
+                .map(|e| view! { <p>{e.to_string()}</p> })
+        }}
+    }
+}
+
+#[component]
+fn StatsView(stats: AdminStats) -> impl IntoView {
+    let per_day = stats
+        .rows_per_day
+        .iter()
+        .map(|(day, rows)| (day.format("%m-%d").to_string(), *rows as f64))
+        .collect::<Vec<_>>();
+
+    view! {
+        <table>
+            <tr>
+                <th>"Users"</th>
+                <td>{stats.users}</td>
+            </tr>
+            <tr>
+                <th>"Rows"</th>
+                <td>{format!("{} ({} completed)", stats.rows, stats.completed_rows)}</td>
+            </tr>
+            <tr>
+                <th>"Uploads"</th>
+                <td>
+                    {format!(
+                        "{} files, {} KiB",
+                        stats.uploads.files,
+                        stats.uploads.bytes.div_ceil(1024),
+                    )}
+                </td>
+            </tr>
+            <tr>
+                <th>"Response cache"</th>
+                <td>
+                    {format!(
+                        "{} entries, {:.1}% of {} lookups hit, {} misses coalesced",
+                        stats.cache.entries,
+                        stats.cache.hit_rate() * 100.0,
+                        stats.cache.hits + stats.cache.misses,
+                        stats.cache.coalesced,
+                    )}
+                </td>
+            </tr>
+        </table>
+        <h3>"Rows added per day (UTC)"</h3>
+        <BarChart bars=per_day />
+        <h3>"Server fns"</h3>
+        <table>
+            <tr>
+                <th>"Path"</th>
+                <th>"Calls"</th>
+                <th>"Errors"</th>
+                <th>"Error rate"</th>
+                <th>"Slow"</th>
+            </tr>
+            {stats
+                .server_fns
+                .into_iter()
+                .map(|usage| {
+                    view! {
+                        <tr>
+                            <td>
+                                <code>{usage.path}</code>
+                            </td>
+                            <td>{usage.calls}</td>
+                            <td>{usage.errors}</td>
+                            <td>
+                                <Meter fraction=usage.error_rate() />
+                                {format!(" {:.1}%", usage.error_rate() * 100.0)}
+                            </td>
+                            <td>{usage.slow}</td>
+                        </tr>
+                    }
+                })
+                .collect::<Vec<_>>()}
+        </table>
+    }
+}
+
+const CHART_HEIGHT: f64 = 120.0;
+const BAR_WIDTH: f64 = 28.0;
+const LABEL_HEIGHT: f64 = 16.0;
+
+/// A vertical bar chart of `(label, value)` pairs, drawn as SVG on the
+/// server so it needs no charting library or script.
+#[component]
+fn BarChart(bars: Vec<(String, f64)>) -> impl IntoView {
+    let max = bars.iter().map(|(_, value)| *value).fold(0.0, f64::max);
+    let width = BAR_WIDTH * bars.len().max(1) as f64;
+    let view_box = format!("0 0 {width} {}", CHART_HEIGHT + LABEL_HEIGHT);
+
+    view! {
+        <svg class="chart" viewBox=view_box role="img">
+            {bars
+                .into_iter()
+                .enumerate()
+                .map(|(i, (label, value))| {
+                    let height = if max > 0.0 {
+                        value / max * CHART_HEIGHT
+                    } else {
+                        0.0
+                    };
+                    let x = i as f64 * BAR_WIDTH;
+                    view! {
+                        <g>
+                            <title>{format!("{label}: {value:.0}")}</title>
+                            <rect
+                                x=x + 2.0
+                                y=CHART_HEIGHT - height
+                                width=BAR_WIDTH - 4.0
+                                height=height
+                            />
+                            <text x=x + BAR_WIDTH / 2.0 y=CHART_HEIGHT + LABEL_HEIGHT - 4.0>
+                                {label}
+                            </text>
+                        </g>
+                    }
+                })
+                .collect::<Vec<_>>()}
+        </svg>
+    }
+}
+
+/// A horizontal bar filled to `fraction` (from 0 to 1) of its width.
+#[component]
+pub fn Meter(fraction: f64) -> impl IntoView {
+    view! {
+        <svg class="meter" viewBox="0 0 100 8" role="img">
+            <rect class="meter-track" width="100" height="8" />
+            <rect width=fraction.clamp(0.0, 1.0) * 100.0 height="8" />
+        </svg>
+    }
+}
@@ -0,0 +1,291 @@
+#[cfg(feature = "ssr")]
+use crate::auth::{current_user, require_user};
+use crate::{
+    jwt::TokenIssuer,
+    prefetch::{cached, RouteData},
+    seo::PageMeta,
+};
+use chrono::{DateTime, Local, Utc};
+use leptos::prelude::*;
+use leptos_router::components::A;
+use serde::{Deserialize, Serialize};
+use server_fn::codec::GetUrl;
+
+/// The longest API key name, in characters.
+pub const MAX_API_KEY_NAME_LEN: usize = 64;
+/// The most API keys a single user can have.
+pub const MAX_API_KEYS: usize = 10;
+/// What every API key starts with, so a leaked one is easy to recognize.
+pub const API_KEY_PREFIX: &str = "tda_";
+
+/// An API key as its owner sees it. The key itself is only ever shown
+/// once, when it is created.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: String,
+    /// The start of the key, to tell keys apart by.
+    pub hint: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// A key that was just created, with the only copy of the key there is.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewApiKey {
+    pub info: ApiKeyInfo,
+    pub key: String,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{
+        ApiKeyInfo, NewApiKey, API_KEY_PREFIX, MAX_API_KEYS,
+        MAX_API_KEY_NAME_LEN,
+    };
+    use crate::{
+        auth::{User, UserId},
+        errors::ApiKeyError,
+    };
+    use chrono::Utc;
+    use sha2::{Digest, Sha256};
+    use std::{
+        collections::HashMap,
+        sync::{LazyLock, Mutex},
+    };
+
+    /// Every user's API keys.
+    pub static API_KEYS: LazyLock<ApiKeyStore> =
+        LazyLock::new(ApiKeyStore::default);
+
+    /// API keys by their hash, so the keys themselves are never stored.
+    #[derive(Debug, Default)]
+    pub struct ApiKeyStore {
+        inner: Mutex<HashMap<String, StoredKey>>,
+    }
+
+    #[derive(Debug)]
+    struct StoredKey {
+        user: User,
+        info: ApiKeyInfo,
+    }
+
+    /// Keys are random rather than chosen by people, so unlike passwords
+    /// they can't be guessed and a fast hash is enough.
+    fn hash_key(key: &str) -> String {
+        format!("{:x}", Sha256::digest(key.as_bytes()))
+    }
+
+    impl ApiKeyStore {
+        /// Creates a key that acts as `user`.
+        pub fn create(
+            &self,
+            user: User,
+            name: String,
+        ) -> Result<NewApiKey, ApiKeyError> {
+            let name = name.trim().to_string();
+            if !(1..=MAX_API_KEY_NAME_LEN).contains(&name.chars().count()) {
+                return Err(ApiKeyError::InvalidName {
+                    max: MAX_API_KEY_NAME_LEN,
+                });
+            }
+            let mut keys = self.inner.lock().unwrap();
+            if keys.values().filter(|key| key.user.id == user.id).count()
+                >= MAX_API_KEYS
+            {
+                return Err(ApiKeyError::TooMany { max: MAX_API_KEYS });
+            }
+            let key =
+                format!("{API_KEY_PREFIX}{}", uuid::Uuid::new_v4().simple());
+            let info = ApiKeyInfo {
+                id: uuid::Uuid::new_v4().simple().to_string(),
+                name,
+                hint: format!("{}...", &key[..API_KEY_PREFIX.len() + 4]),
+                created_at: Utc::now(),
+                last_used_at: None,
+            };
+            keys.insert(
+                hash_key(&key),
+                StoredKey {
+                    user,
+                    info: info.clone(),
+                },
+            );
+            Ok(NewApiKey { info, key })
+        }
+
+        /// `owner`'s keys, oldest first.
+        pub fn list(&self, owner: UserId) -> Vec<ApiKeyInfo> {
+            let mut keys = self
+                .inner
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|key| key.user.id == owner)
+                .map(|key| key.info.clone())
+                .collect::<Vec<_>>();
+            keys.sort_by_key(|key| key.created_at);
+            keys
+        }
+
+        /// Deletes one of `owner`'s keys, which stops working right away.
+        pub fn revoke(
+            &self,
+            owner: UserId,
+            id: &str,
+        ) -> Result<(), ApiKeyError> {
+            let mut keys = self.inner.lock().unwrap();
+            let before = keys.len();
+            keys.retain(|_, key| !(key.user.id == owner && key.info.id == id));
+            if keys.len() < before {
+                Ok(())
+            } else {
+                Err(ApiKeyError::NotFound { id: id.to_string() })
+            }
+        }
+
+        /// The user `key` acts as, and the key's details, if it is valid.
+        /// Also notes that the key was used.
+        pub fn authenticate(&self, key: &str) -> Option<(User, ApiKeyInfo)> {
+            let mut keys = self.inner.lock().unwrap();
+            let stored = keys.get_mut(&hash_key(key))?;
+            stored.info.last_used_at = Some(Utc::now());
+            Some((stored.user.clone(), stored.info.clone()))
+        }
+    }
+}
+
+/// The signed-in user's API keys, oldest first.
+#[server(input = GetUrl)]
+pub async fn list_api_keys() -> Result<Vec<ApiKeyInfo>, ServerFnError> {
+    Ok(current_user()
+        .map(|user| API_KEYS.list(user.id))
+        .unwrap_or_default())
+}
+
+/// Creates an API key for the REST API that acts as the signed-in user.
+#[server]
+pub async fn create_api_key(name: String) -> Result<NewApiKey, ServerFnError> {
+    let user = require_user()?;
+    Ok(API_KEYS.create(user, name)?)
+}
+
+#[server]
+pub async fn revoke_api_key(id: String) -> Result<(), ServerFnError> {
+    let user = require_user()?;
+    Ok(API_KEYS.revoke(user.id, &id)?)
+}
+
+/// The signed-in user's API keys, with forms to create and revoke them, and
+/// a button to mint a short-lived token instead.
+#[component]
+pub fn ApiKeysPage() -> impl IntoView {
+    let create = ServerAction::<CreateApiKey>::new();
+    let revoke = ServerAction::<RevokeApiKey>::new();
+    let keys = Resource::new(
+        move || (create.version().get(), revoke.version().get()),
+        |_| cached(RouteData::ApiKeys.key(), list_api_keys),
+    );
+    let created = move || {
+        create.value().get().and_then(Result::ok).map(|new| {
+            view! {
+                <p>
+                    "Your new key " <strong>{new.info.name}</strong> " is "
+                    <code>{new.key}</code>
+                    ". Copy it now, as it won't be shown again."
+                </p>
+            }
+        })
+    };
+    let error = move || {
+        create
+            .value()
+            .get()
+            .and_then(Result::err)
+            .or_else(|| revoke.value().get().and_then(Result::err))
+            .map(|e| view! { <p>{e.to_string()}</p> })
+    };
+    let format_time = |at: DateTime<Utc>| {
+        at.with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    };
+
+    view! {
+        <PageMeta title="API keys" description="Keys for the REST API." private=true />
+        <h2>"API keys"</h2>
+        <p>
+            <A href="/">"Back to the demo"</A>
+        </p>
+        <p>
+            "Send a key as " <code>"Authorization: Bearer <key>"</code>
+            " to use the REST API under " <code>"/rest"</code>
+            " as yourself. Changes made with a key show up in each row's activity."
+        </p>
+        <ActionForm action=create>
+            <input
+                name="name"
+                placeholder="Key name"
+                maxlength=MAX_API_KEY_NAME_LEN
+                required
+            />
+            <input type="submit" value="Create key" />
+        </ActionForm>
+        {created}
+        {error}
+        <Transition fallback=|| view! { <p>"Loading..."</p> }>
+            {move || Suspend::new(async move {
+                match keys.await {
+                    Ok(keys) if keys.is_empty() => {
+                        view! { <p>"You have no API keys."</p> }.into_any()
+                    }
+                    Ok(keys) => {
+                        view! {
+                            <table>
+                                <tr>
+                                    <th>"Name"</th>
+                                    <th>"Key"</th>
+                                    <th>"Created"</th>
+                                    <th>"Last used"</th>
+                                    <th></th>
+                                </tr>
+                                {keys
+                                    .into_iter()
+                                    .map(|key| {
+                                        view! {
+                                            <tr>
+                                                <td>{key.name}</td>
+                                                <td>
+                                                    <code>{key.hint}</code>
+                                                </td>
+                                                <td>{format_time(key.created_at)}</td>
+                                                <td>
+                                                    {key
+                                                        .last_used_at
+                                                        .map_or_else(|| "never".to_string(), format_time)}
+                                                </td>
+                                                <td>
+                                                    <ActionForm action=revoke>
+                                                        <input type="hidden" name="id" value=key.id />
+                                                        <input type="submit" value="Revoke" />
+                                                    </ActionForm>
+                                                </td>
+                                            </tr>
+                                        }
+                                    })
+                                    .collect::<Vec<_>>()}
+                            </table>
+                        }
+                            .into_any()
+                    }
+                    Err(e) => view! { <p>{e.to_string()}</p> }.into_any(),
+                }
+            })}
+        </Transition>
+        <h3>"Access tokens"</h3>
+        <TokenIssuer />
+    }
+}
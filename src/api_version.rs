@@ -0,0 +1,174 @@
+use crate::base_path::use_base_path;
+#[cfg(feature = "ssr")]
+use crate::{
+    sandbox::current_owner,
+    storage::{RowQuery, RowStatus, ROWS},
+};
+use chrono::NaiveDate;
+use leptos::{prelude::*, task::spawn_local};
+use serde::{Deserialize, Serialize};
+use server_fn::{codec::GetUrl, ServerFn};
+use strum::Display;
+
+/// A version of the server fn API, named by the path prefix its server fns
+/// are registered under: `#[server(prefix = "/api/v1")]` and so on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum ApiVersion {
+    #[strum(serialize = "v1")]
+    V1,
+    #[strum(serialize = "v2")]
+    V2,
+}
+
+/// When a deprecated [`ApiVersion`] stopped being recommended, and when it
+/// may stop being served.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retirement {
+    pub deprecated: NaiveDate,
+    pub sunset: NaiveDate,
+}
+
+impl ApiVersion {
+    pub const ALL: [ApiVersion; 2] = [ApiVersion::V1, ApiVersion::V2];
+    /// The version new server fns are added to.
+    pub const CURRENT: ApiVersion = ApiVersion::V2;
+
+    /// `None` while the version is supported.
+    pub fn retirement(self) -> Option<Retirement> {
+        match self {
+            ApiVersion::V1 => Some(Retirement {
+                deprecated: NaiveDate::from_ymd_opt(2026, 9, 1).unwrap(),
+                sunset: NaiveDate::from_ymd_opt(2027, 3, 1).unwrap(),
+            }),
+            ApiVersion::V2 => None,
+        }
+    }
+
+    /// The version a server fn path is under, and the rest of the path
+    /// after its prefix.
+    pub fn split_path(path: &str) -> Option<(ApiVersion, &str)> {
+        let rest = path.strip_prefix("/api/")?;
+        let (version, endpoint) = rest.split_once('/')?;
+        Self::ALL
+            .into_iter()
+            .find(|v| v.to_string() == version)
+            .map(|version| (version, endpoint))
+    }
+}
+
+/// A user's rows, as version 2 counts them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RowCounts {
+    pub total: usize,
+    pub completed: usize,
+}
+
+/// Version 1: just the number of rows.
+#[server(prefix = "/api/v1", endpoint = "row_count", input = GetUrl)]
+pub async fn row_count_v1() -> Result<usize, ServerFnError> {
+    Ok(current_owner().map_or(0, |owner| ROWS.len(owner)))
+}
+
+/// Version 2 also says how many are completed.
+#[server(prefix = "/api/v2", endpoint = "row_count", input = GetUrl)]
+pub async fn row_count_v2() -> Result<RowCounts, ServerFnError> {
+    let Some(owner) = current_owner() else {
+        return Ok(RowCounts {
+            total: 0,
+            completed: 0,
+        });
+    };
+    let completed = RowQuery {
+        status: RowStatus::Completed,
+        ..RowQuery::default()
+    };
+    Ok(RowCounts {
+        total: ROWS.len(owner),
+        completed: ROWS.list(owner, &completed, usize::MAX).len(),
+    })
+}
+
+/// The response headers that tell a client about deprecation.
+const DEPRECATION_HEADERS: [&str; 3] = ["deprecation", "sunset", "link"];
+
+/// What calling one version's `row_count` got back.
+#[derive(Debug, Clone, PartialEq)]
+struct VersionReply {
+    body: String,
+    headers: Vec<(&'static str, String)>,
+}
+
+async fn call_version(path: String) -> Result<VersionReply, String> {
+    let res = gloo_net::http::Request::get(&path)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let headers = DEPRECATION_HEADERS
+        .into_iter()
+        .filter_map(|name| Some((name, res.headers().get(name)?)))
+        .collect();
+    let body = res.text().await.map_err(|e| e.to_string())?;
+    Ok(VersionReply { body, headers })
+}
+
+/// Calls both versions of `row_count`, showing what each returned and the
+/// deprecation headers the old one comes with.
+#[component]
+pub fn ApiVersionsExample() -> impl IntoView {
+    let base_path = use_base_path();
+    let versions = [
+        (ApiVersion::V1, <RowCountV1 as ServerFn>::PATH),
+        (ApiVersion::V2, <RowCountV2 as ServerFn>::PATH),
+    ];
+
+    view! {
+        <h3>"API versions"</h3>
+        <p>
+            "The same endpoint under " <code>"/api/v1"</code> " and "
+            <code>"/api/v2"</code>
+            ". Responses from the deprecated version carry Deprecation, Sunset and successor Link headers."
+        </p>
+        {versions
+            .into_iter()
+            .map(|(version, path)| {
+                let path = base_path.join(path);
+                let (reply, set_reply) = signal(None::<Result<VersionReply, String>>);
+                let call = move |_| {
+                    let path = path.clone();
+                    spawn_local(async move {
+                        set_reply.set(Some(call_version(path).await));
+                    });
+                };
+                view! {
+                    <button on:click=call>{format!("Call {version}")}</button>
+                    <ShowLet some=reply let:reply>
+                        {match reply {
+                            Ok(reply) => {
+                                view! {
+                                    <p>
+                                        <code>{reply.body}</code>
+                                    </p>
+                                    <ul>
+                                        {reply
+                                            .headers
+                                            .into_iter()
+                                            .map(|(name, value)| {
+                                                view! {
+                                                    <li>
+                                                        <code>{format!("{name}: {value}")}</code>
+                                                    </li>
+                                                }
+                                            })
+                                            .collect::<Vec<_>>()}
+                                    </ul>
+                                }
+                                    .into_any()
+                            }
+                            Err(e) => view! { <p>{e}</p> }.into_any(),
+                        }}
+                    </ShowLet>
+                }
+            })
+            .collect::<Vec<_>>()}
+    }
+}
@@ -1,41 +1,147 @@
+use crate::{
+    admin::AdminPage,
+    api_keys::ApiKeysPage,
+    api_version::ApiVersionsExample,
+    auth::Account,
+    base_path::{use_base_path, BASE_PATH_META},
+    branding::{use_branding, BrandTitle, BRANDING_META},
+    channels::{ChannelStats, Polled, Tick},
+    client_errors::ReportingErrorBoundary,
+    clients::{
+        last_trace_id, set_cross_origin_target, with_priority, AppClient,
+        CrossOriginClient, Priority, PriorityClient, TracingClient,
+        MAX_IN_FLIGHT,
+    },
+    codec::{
+        AlignedRkyv, AlignedRkyvEncoding, FormEncoded, FormUrl, Framed,
+        FramedStream, Negotiate, Negotiated, NegotiatedFormat, QueryEncoded,
+        QueryUrl, RkyvChunkStream, RkyvChunks, WithStatus,
+    },
+    components::{
+        async_button::AsyncButton,
+        bound_form::{BoundForm, Field},
+        flow::{AwaitWith, MatchResult},
+        hint::Hint,
+    },
+    counter::CounterSocketExample,
+    crawlers::is_crawler,
+    dev_overlay::DevErrorOverlay,
+    dev_panel::DevPanel,
+    docs::{AboutPage, GuidePage},
+    error_messages::{error_message, show_result},
+    errors::{
+        AddRowError, AppError, ErrorCode, ErrorParams, FormError,
+        HasStatusCode, UploadError,
+    },
+    file_rows::FileRowsToggle,
+    fixtures::{Fixture, FixtureRow},
+    flags::{provide_flags, Flag, IfFlag},
+    flash::{provide_flash_messages, FlashMessages},
+    generation::ChatExample,
+    i18n::{provide_locale, request_locale},
+    load::LoadPage,
+    offline::{is_unreachable, use_offline_queue, QueuedMutation},
+    profile::ProfilePage,
+    progress::{ProgressKind, ProgressStream},
+    proxy::ProxyExample,
+    query::to_query_string,
+    quotas::UsageMeter,
+    reminders::Reminders,
+    replicas::Staleness,
+    resilience::BreakerPanel,
+    rows::{RowDetail, RowEditPage, RowExport, RowImport, RowList, RowSearch},
+    sandbox::ResetSandbox,
+    seo::PageMeta,
+    storage::Tag,
+    supervisor::{supervise_or_poll, ConnectionState},
+    theme::ThemePicker,
+    transcription::TranscriptionExample,
+    transports::TransportComparison,
+    trash::TrashPage,
+    url_state::{use_url_state, use_url_text},
+};
+#[cfg(feature = "ssr")]
+use crate::{
+    audit::AuditAction,
+    cache::{self, CacheTag, CACHE},
+    channels::Sequenced,
+    errors::ImportError,
+    flags,
+    forms::FieldErrors,
+    multipart::for_each_chunk,
+    quotas::QuotaKind,
+    replicas::reader,
+    resilience::{Policy, ResilienceLayer},
+    rows::{validate_text, ROW_LIST_LIMIT},
+    sandbox::{current_owner, require_owner, until_reset},
+    storage::{check_tag_count, RowQuery, ROWS},
+};
 use futures::{Sink, Stream, StreamExt};
-use http::Method;
+use http::{Method, StatusCode};
 use leptos::{html::Input, prelude::*, task::spawn_local};
+use leptos_meta::{provide_meta_context, HashedStylesheet, MetaTags, Title};
+use leptos_router::{
+    components::{Route, Router, Routes, A},
+    path,
+    static_routes::StaticRoute,
+    SsrMode,
+};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use server_fn::{
     client::{browser::BrowserClient, Client},
     codec::{
-        Encoding, FromReq, FromRes, GetUrl, IntoReq, IntoRes, MultipartData,
-        MultipartFormData, Postcard, Rkyv, RkyvEncoding, SerdeLite,
-        StreamingText, TextStream,
+        ByteStream, Encoding, FromReq, FromRes, GetUrl, IntoReq, IntoRes,
+        MultipartData, MultipartFormData, Postcard, SerdeLite, Streaming,
     },
     error::{FromServerFnError, IntoAppError, ServerFnErrorErr},
     request::{browser::BrowserRequest, ClientReq, Req},
     response::{browser::BrowserResponse, ClientRes, TryRes},
-    ContentType, Format, FormatType,
+    ContentType, Format, FormatType, ServerFn,
 };
-use std::future::Future;
 #[cfg(feature = "ssr")]
-use std::sync::{
-    atomic::{AtomicU8, Ordering},
-    Mutex,
-};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::{future::Future, ops::ControlFlow};
 use strum::{Display, EnumString};
 use wasm_bindgen::JsCast;
 use web_sys::{FormData, HtmlFormElement, SubmitEvent};
 
 pub fn shell(options: LeptosOptions) -> impl IntoView {
+    let base_path = use_base_path();
+    // crawlers get the page as rendered, without the scripts to hydrate it
+    let hydrate = !is_crawler();
+    let lang = request_locale().tag();
+    #[cfg(feature = "ssr")]
+    crate::security::set_content_security_policy();
+    #[cfg(feature = "ssr")]
+    provide_context(crate::branding::current_branding());
+    let branding = use_branding();
+    let branding_json = serde_json::to_string(&branding).unwrap_or_default();
+    let css_variables = branding.css_variables();
+
     view! {
         <!DOCTYPE html>
-        <html lang="en">
+        <html lang=lang style=css_variables>
             <head>
                 <meta charset="utf-8" />
                 <meta name="viewport" content="width=device-width, initial-scale=1" />
-                <AutoReload options=options.clone() />
-                <HydrationScripts options />
+                <meta name=BASE_PATH_META content=base_path.as_str().to_string() />
+                <meta name=BRANDING_META content=branding_json />
+                <meta name="application-name" content=branding.title.clone() />
+                <meta property="og:site_name" content=branding.title />
+                <meta name="theme-color" content=branding.accent_color />
+                {hydrate.then(|| view! { <AutoReload options=options.clone() /> })}
+                <HashedStylesheet
+                    options=options.clone()
+                    id="leptos"
+                    root=base_path.as_str().to_string()
+                />
+                {hydrate
+                    .then(|| {
+                        view! { <HydrationScripts options root=base_path.as_str().to_string() /> }
+                    })}
                 <meta name="color-scheme" content="dark light" />
-                <link rel="shortcut icon" type="image/ico" href="/favicon.ico" />
-                <link rel="stylesheet" id="leptos" href="/pkg/server_fns_axum.css" />
+                <link rel="shortcut icon" type="image/ico" href=base_path.join("/favicon.ico") />
+                <MetaTags />
             </head>
             <body>
                 <App />
@@ -46,40 +152,131 @@ pub fn shell(options: LeptosOptions) -> impl IntoView {
 
 #[component]
 pub fn App() -> impl IntoView {
+    // axum's `nest` strips the base path before requests reach the router on
+    // the server, but the browser's location still includes it
+    let router_base = if cfg!(feature = "hydrate") {
+        use_base_path().as_str().to_string()
+    } else {
+        String::new()
+    };
+    provide_flags();
+    provide_flash_messages();
+    provide_locale();
+    provide_meta_context();
+    let branding = use_branding();
+    let site_name = branding.title.clone();
+
     view! {
-        <header>
-            <h1>"Server Function Demo"</h1>
-        </header>
-        <main>
-            <HomePage />
-        </main>
+        <Title formatter=move |text: String| format!("{text} | {site_name}") />
+        <Router base=router_base>
+            <header>
+                <BrandTitle branding />
+                <nav>
+                    <A href="/">"Demo"</A>
+                    " "
+                    <A href="/about">"About"</A>
+                    " "
+                    <A href="/guide">"Guide"</A>
+                </nav>
+                <Account />
+                <ThemePicker />
+            </header>
+            <FlashMessages />
+            <main>
+                <ReportingErrorBoundary>
+                    <Routes fallback=|| "Page not found.">
+                        <Route path=path!("") view=HomePage />
+                        // read nothing per request, so they're pre-rendered
+                        <Route
+                            path=path!("about")
+                            view=AboutPage
+                            ssr=SsrMode::Static(StaticRoute::new())
+                        />
+                        <Route
+                            path=path!("guide")
+                            view=GuidePage
+                            ssr=SsrMode::Static(StaticRoute::new())
+                        />
+                        <Route path=path!("admin") view=AdminPage />
+                        <Route path=path!("api-keys") view=ApiKeysPage />
+                        <Route path=path!("load") view=LoadPage />
+                        <Route path=path!("profile") view=ProfilePage />
+                        <Route path=path!("rows/:id") view=RowDetail />
+                        <Route path=path!("rows/:id/edit") view=RowEditPage />
+                        <Route path=path!("trash") view=TrashPage />
+                    </Routes>
+                </ReportingErrorBoundary>
+            </main>
+            {cfg!(debug_assertions).then(|| view! { <DevPanel /> })}
+            {cfg!(debug_assertions).then(|| view! { <DevErrorOverlay /> })}
+        </Router>
     }
 }
 
 #[component]
 pub fn HomePage() -> impl IntoView {
+    // start a signed-out visitor's sandbox with the page, so the first
+    // calls the examples make, some at the same time, all share it
+    #[cfg(feature = "ssr")]
+    if !is_crawler() {
+        _ = require_owner();
+    }
+
     view! {
+        <PageMeta
+            title="Demo"
+            description="Leptos server functions with every built-in encoding, streaming, middleware and custom clients."
+        />
+        <Hint id="sandbox">
+            "Rows you add are kept for this browser only, and go away after a while without a visit."
+        </Hint>
         <h2>"Some Simple Server Functions"</h2>
         <SpawnLocal />
         <WithAnAction />
         <WithActionForm />
+        <AddTodoForm />
+        <h2>"Working With Rows"</h2>
+        <UsageMeter />
+        <ResetSandbox />
+        <RowExport />
+        <RowImport />
+        <RowSearch />
+        <RowList />
+        <Reminders />
         <h2>"Custom Error Types"</h2>
         <CustomErrorTypes />
         <h2>"Alternative Encodings"</h2>
         <ServerFnArgumentExample />
+        <QueryUrlExample />
         <RkyvExample />
+        <RkyvChunksExample />
+        <CounterSocketExample />
+        <TransportComparison />
         <PostcardExample />
+        <PostcardOldClientExample />
+        <NegotiationExample />
         <FileUpload />
         <FileUploadWithProgress />
-        <FileWatcher />
+        <TranscriptionExample />
+        <ChatExample />
+        // a crawler would never see an event, so don't start the watcher
+        <IfFlag flag=Flag::FileWatcher>
+            {(!is_crawler()).then(|| view! { <FileWatcher /> })}
+        </IfFlag>
+        <ChannelLag />
+        <GeneratedDownload />
         <CustomEncoding />
         <CustomClientExample />
+        <PriorityClientExample />
+        <CrossOriginExample />
+        <ApiVersionsExample />
+        <ProxyExample />
     }
 }
 
 #[component]
 pub fn SpawnLocal() -> impl IntoView {
-    #[server]
+    #[server(client = AppClient)]
     pub async fn shouting_text(input: String) -> Result<String, ServerFnError> {
         // insert a simulated wait
         tokio::time::sleep(std::time::Duration::from_millis(250)).await;
@@ -93,50 +290,87 @@ pub fn SpawnLocal() -> impl IntoView {
         <h3>Using <code>spawn_local</code></h3>
         <p>
             "You can call a server function by using " <code>"spawn_local"</code>
-            " in an event listener. "
+            " in an event listener, as " <code>"<AsyncButton>"</code> " does. "
             "Clicking this button should alert with the uppercase version of the input."
         </p>
         <input node_ref=input_ref placeholder="Type something here." />
-        <button on:click=move |_| {
+        <AsyncButton action=move || {
             let value = input_ref.get().unwrap().value();
-            spawn_local(async move {
-                let uppercase_text = shouting_text(value).await.unwrap_or_else(|e| e.to_string());
-                set_shout_result.set(uppercase_text);
-            });
+            async move {
+                shouting_text(value).await.map(|uppercase_text| set_shout_result.set(uppercase_text))
+            }
         }>
-
             {shout_result}
-        </button>
+        </AsyncButton>
     }
 }
 
+/// Chaos mode makes `add_row` fail every third call, so its breaker opens
+/// after a handful of calls.
 #[cfg(feature = "ssr")]
-static ROWS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+const ADD_ROW_POLICY: Policy = Policy {
+    timeout: std::time::Duration::from_secs(2),
+    max_concurrent: 8,
+    failure_threshold: 2,
+    failure_window: std::time::Duration::from_secs(30),
+    open_for: std::time::Duration::from_secs(10),
+};
 
-#[server]
-pub async fn add_row(text: String) -> Result<usize, ServerFnError> {
+#[server(client = AppClient)]
+#[middleware(ResilienceLayer::new("add_row", ADD_ROW_POLICY))]
+pub async fn add_row(text: String) -> Result<usize, AppError> {
     static N: AtomicU8 = AtomicU8::new(0);
 
     // insert a simulated wait
     tokio::time::sleep(std::time::Duration::from_millis(250)).await;
 
+    let owner = require_owner()?;
+    let text = row_text(&text)?.to_string();
     let nth_run = N.fetch_add(1, Ordering::Relaxed);
     // this will print on the server, like any server function
     println!("Adding {text:?} to the database!");
-    if nth_run % 3 == 2 {
-        Err(ServerFnError::new("Oh no! Couldn't add to database!"))
+    if flags::is_enabled(Flag::ChaosMode) && nth_run % 3 == 2 {
+        Err(AddRowError::ChaosMode.into())
     } else {
-        let mut rows = ROWS.lock().unwrap();
-        rows.push(text);
-        Ok(rows.len())
+        ROWS.transaction(owner, |tx| {
+            tx.consume_quota(QuotaKind::Rows, 1)?;
+            let row = tx.insert(text);
+            tx.audit(row.id, AuditAction::Created);
+            Ok(tx.len())
+        })
     }
 }
 
-#[server]
-pub async fn get_rows() -> Result<usize, ServerFnError> {
-    tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+/// Trims a new row's text, failing as [`add_row`] and [`add_todo`] do if it
+/// cannot be stored.
+#[cfg(feature = "ssr")]
+fn row_text(text: &str) -> Result<&str, AddRowError> {
+    validate_text(text).map_err(|e| match e {
+        ImportError::TextTooLong { max } => AddRowError::TextTooLong { max },
+        _ => AddRowError::EmptyText,
+    })
+}
 
-    Ok(ROWS.lock().unwrap().len())
+/// How many rows the caller has, which may come from a read replica unless
+/// `staleness` asks for the primary.
+#[server(client = AppClient)]
+pub async fn get_rows(
+    #[server(default)] staleness: Staleness,
+) -> Result<usize, ServerFnError> {
+    let Some(owner) = current_owner() else {
+        return Ok(0);
+    };
+    // between writes, only the first call pays the simulated wait
+    CACHE
+        .get_or_compute(
+            cache::key("get_rows", &owner),
+            &[CacheTag::Rows(owner)],
+            async move {
+                tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+                Ok(reader(owner, staleness).len(owner))
+            },
+        )
+        .await
 }
 
 #[component]
@@ -144,9 +378,32 @@ pub fn WithAnAction() -> impl IntoView {
     let input_ref = NodeRef::<Input>::new();
 
     let action = ServerAction::<AddRow>::new();
+    let offline = use_offline_queue();
 
-    let row_count =
-        Resource::new(move || action.version().get(), |_| get_rows());
+    let row_count = Resource::new(
+        move || (action.version().get(), offline.sent().get()),
+        |_| get_rows(Staleness::Tolerated),
+    );
+    // a row that couldn't reach the server waits to be sent again
+    let submitted = StoredValue::new(String::new());
+    Effect::new(move |_| {
+        if let Some(Err(e)) = action.value().get() {
+            if is_unreachable(&e) {
+                let text = submitted.get_value();
+                offline.push(QueuedMutation::AddRow { text });
+            }
+        }
+    });
+    // counts a submitted row straight away, then settles on what the server
+    // says: the new total if adding it worked, or the old one if it didn't
+    let pending = action.pending();
+    let total = move |loaded: usize| {
+        let confirmed = match action.value().get() {
+            Some(Ok(count)) => count,
+            _ => loaded,
+        };
+        confirmed + usize::from(pending.get())
+    };
 
     view! {
         <h3>Using <code>Action::new</code></h3>
@@ -157,24 +414,44 @@ pub fn WithAnAction() -> impl IntoView {
         <input node_ref=input_ref placeholder="Type something here." />
         <button on:click=move |_| {
             let text = input_ref.get().unwrap().value();
+            submitted.set_value(text.clone());
             action.dispatch(text.into());
         }>
 
             Submit
         </button>
-        <p>You submitted: {move || format!("{:?}", action.input().get())}</p>
-        <p>The result was: {move || format!("{:?}", action.value().get())}</p>
-        <Transition>
-            <p>Total rows: {row_count}</p>
-        </Transition>
+        <p>You submitted: {move || action.input().get().map(|input| input.text)}</p>
+        <p>The result was: {move || action.value().get().map(show_result)}</p>
+        <Show when=move || !offline.is_empty()>
+            <p class="pending">
+                {move || offline.len()}
+                " rows waiting to be sent when the server can be reached."
+            </p>
+        </Show>
+        <AwaitWith resource=row_count let:row_count>
+            <p>
+                {show_result(
+                    row_count
+                        .map(|loaded| {
+                            view! {
+                                "Total rows: " {move || total(loaded)}
+                                {move || pending.get().then_some(" (saving...)")}
+                            }
+                        }),
+                )}
+            </p>
+        </AwaitWith>
+        <BreakerPanel refresh=action.version() />
     }
 }
 
 #[component]
 pub fn WithActionForm() -> impl IntoView {
     let action = ServerAction::<AddRow>::new();
-    let row_count =
-        Resource::new(move || action.version().get(), |_| get_rows());
+    let row_count = Resource::new(
+        move || action.version().get(),
+        |_| get_rows(Staleness::Tolerated),
+    );
 
     view! {
         <h3>Using <code>"<ActionForm/>"</code></h3>
@@ -192,24 +469,138 @@ pub fn WithActionForm() -> impl IntoView {
             />
             <button>Submit</button>
         </ActionForm>
-        <p>You submitted: {move || format!("{:?}", action.input().get())}</p>
-        <p>The result was: {move || format!("{:?}", action.value().get())}</p>
+        <p>You submitted: {move || action.input().get().map(|input| input.text)}</p>
+        <p>The result was: {move || action.value().get().map(show_result)}</p>
         <Transition>
-            archive underaligned: need alignment 4 but have alignment 1
             <p>Total rows: {row_count}</p>
         </Transition>
     }
 }
 
+/// A row as [`AddTodoForm`] fills it in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewTodo {
+    pub text: String,
+    /// One input each; blank ones are skipped.
+    pub tags: Vec<String>,
+    /// A checkbox.
+    pub completed: bool,
+    pub due: DueIn,
+}
+
+/// When a [`NewTodo`] is due, counted from today.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DueIn {
+    /// Left blank for no due date.
+    pub days: Option<u16>,
+    /// Counts only Monday to Friday.
+    pub weekdays_only: bool,
+}
+
+/// How far ahead [`add_todo`] lets a row be due.
+pub const MAX_DUE_DAYS: u16 = 365;
+
+/// Adds a row with its tags, due date and state at once, from a form that
+/// works with or without JavaScript. Fields it rejects each get their own
+/// error.
+#[server(input = FormUrl, custom = FormEncoded)]
+pub async fn add_todo(todo: NewTodo) -> Result<usize, FormError> {
+    use chrono::{Datelike, Local};
+
+    let owner = require_owner()?;
+    let mut errors = FieldErrors::new();
+    let text = errors
+        .check("todo.text", row_text(&todo.text))
+        .unwrap_or_default();
+    let tags = todo
+        .tags
+        .into_iter()
+        .filter_map(|tag| errors.check("todo.tags", Tag::try_from(tag)))
+        .collect::<Vec<_>>();
+    errors.check("todo.tags", check_tag_count(&tags));
+    if todo.due.days.is_some_and(|days| days > MAX_DUE_DAYS) {
+        let max = MAX_DUE_DAYS;
+        errors.add("todo.due.days", AddRowError::DueTooLate { max });
+    }
+    errors.finish()?;
+
+    let due = todo.due.days.map(|days| {
+        let mut date = Local::now().date_naive();
+        let mut left = days;
+        while left > 0 {
+            date = date.succ_opt().unwrap_or(date);
+            let weekend = date.weekday().number_from_monday() > 5;
+            if !(todo.due.weekdays_only && weekend) {
+                left -= 1;
+            }
+        }
+        date
+    });
+    ROWS.transaction(owner, |tx| {
+        tx.consume_quota(QuotaKind::Rows, 1)?;
+        let row = tx.insert(text.to_string());
+        tx.set_tags(row.id, tags);
+        tx.set_completed(row.id, todo.completed);
+        tx.set_due(row.id, due);
+        tx.audit(row.id, AuditAction::Created);
+        Ok(tx.len())
+    })
+}
+
+#[component]
+pub fn AddTodoForm() -> impl IntoView {
+    let action = ServerAction::<FormEncoded<AddTodo>>::new();
+    // a couple of spare tag inputs, and more on request
+    let (tag_inputs, set_tag_inputs) = signal(2);
+
+    view! {
+        <h3>Binding a form to a struct</h3>
+        <p>
+            <code>"<BoundForm/>"</code>
+            " reads nested fields, repeated inputs, checkboxes and numbers into the server function's arguments, and shows each field's error next to it."
+        </p>
+        <BoundForm action>
+            <Field name="todo.text" label="Text">
+                <input name="todo.text" placeholder="What needs doing?" />
+            </Field>
+            <Field name="todo.tags" label="Tags">
+                {move || {
+                    (0..tag_inputs.get())
+                        .map(|_| view! { <input name="todo.tags" size="10" /> })
+                        .collect::<Vec<_>>()
+                }}
+                <button type="button" on:click=move |_| set_tag_inputs.update(|n| *n += 1)>
+                    "+"
+                </button>
+            </Field>
+            <Field name="todo.completed" label="Done already">
+                <input type="checkbox" name="todo.completed" />
+            </Field>
+            <Field name="todo.due.days" label="Due in days">
+                <input name="todo.due.days" inputmode="numeric" size="4" />
+            </Field>
+            <Field name="todo.due.weekdays_only" label="Weekdays only">
+                <input type="checkbox" name="todo.due.weekdays_only" />
+            </Field>
+            <button>Add</button>
+        </BoundForm>
+        <p>
+            "Total rows: "
+            {move || action.value().get().map(show_result)}
+        </p>
+    }
+}
+
 #[server(
     prefix = "/api2",
     endpoint = "custom_path",
-    input = GetUrl,
+    input = QueryUrl,
     output = SerdeLite,
+    client = TracingClient,
+    custom = QueryEncoded,
 )]
-#[middleware(crate::middleware::LoggingLayer)]
+#[middleware(crate::middleware::standard())]
 pub async fn length_of_input(input: String) -> Result<usize, ServerFnError> {
-    println!("2. Running server function.");
     tokio::time::sleep(std::time::Duration::from_millis(250)).await;
     Ok(input.len())
 }
@@ -218,6 +609,7 @@ pub async fn length_of_input(input: String) -> Result<usize, ServerFnError> {
 pub fn ServerFnArgumentExample() -> impl IntoView {
     let input_ref = NodeRef::<Input>::new();
     let (result, set_result) = signal(0);
+    let (trace_id, set_trace_id) = signal(None::<String>);
 
     view! {
         <h3>Custom arguments to the <code>#[server]</code> " macro"</h3>
@@ -226,6 +618,7 @@ pub fn ServerFnArgumentExample() -> impl IntoView {
             <li>Specific server function <strong>paths</strong></li>
             <li>Mixing and matching input and output <strong>encodings</strong></li>
             <li>Adding custom <strong>middleware</strong>on a per-server-fn basis</li>
+            <li>Using a custom <strong>client</strong> that propagates trace context</li>
         </ul>
         <input node_ref=input_ref placeholder="Type something here." />
         <button on:click=move |_| {
@@ -233,18 +626,128 @@ pub fn ServerFnArgumentExample() -> impl IntoView {
             spawn_local(async move {
                 let length = length_of_input(value).await.unwrap_or(0);
                 set_result.set(length);
+                set_trace_id.set(last_trace_id());
             });
         }>
 
             Click to see length
         </button>
         <p>Length is {result}</p>
+        <ShowLet some=trace_id let:trace_id>
+            <p>
+                "Traced as " <code>{trace_id}</code>
+                " (look it up in your OTLP backend when built with the "
+                <code>"otel"</code> " feature)"
+            </p>
+        </ShowLet>
+    }
+}
+
+/// The texts of the caller's rows that match, with arguments that
+/// read well in a URL: `?limit=5&q=milk&tag=home&tag=urgent`.
+#[server(input = QueryUrl, custom = QueryEncoded, endpoint = "find_rows")]
+pub async fn find_rows(
+    #[server(default)]
+    #[server(rename = "q")]
+    contains: String,
+    #[server(default)]
+    #[server(rename = "tag")]
+    tags: Vec<Tag>,
+    limit: Option<usize>,
+) -> Result<Vec<String>, ServerFnError> {
+    let query = RowQuery {
+        contains,
+        tags,
+        ..RowQuery::default()
+    };
+    let limit = limit.unwrap_or(ROW_LIST_LIMIT).min(ROW_LIST_LIMIT);
+    Ok(current_owner()
+        .map(|owner| ROWS.list(owner, &query, limit))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|row| row.text)
+        .collect())
+}
+
+#[component]
+pub fn QueryUrlExample() -> impl IntoView {
+    let text_ref = NodeRef::<Input>::new();
+    let tags_ref = NodeRef::<Input>::new();
+    let limit_ref = NodeRef::<Input>::new();
+    let (url, set_url) = signal(None::<String>);
+    let (error, set_error) = signal(None::<String>);
+    let search = Action::new(|args: &FindRows| {
+        let args = args.clone();
+        find_rows(args.contains, args.tags, args.limit)
+    });
+    let base_path = use_base_path();
+
+    let on_click = move |_| {
+        set_error.set(None);
+        let tags = tags_ref
+            .get()
+            .unwrap()
+            .value()
+            .split_whitespace()
+            .map(|tag| Tag::try_from(tag.to_string()))
+            .collect::<Result<Vec<_>, _>>();
+        let tags = match tags {
+            Ok(tags) => tags,
+            Err(e) => {
+                set_error.set(Some(e.to_string()));
+                return;
+            }
+        };
+        let args = FindRows {
+            contains: text_ref.get().unwrap().value(),
+            tags,
+            limit: limit_ref.get().unwrap().value().parse().ok(),
+        };
+        match to_query_string(&args) {
+            Ok(query) => set_url.set(Some(format!(
+                "{}?{query}",
+                base_path.join(QueryEncoded::<FindRows>::url())
+            ))),
+            Err(e) => set_error.set(Some(e)),
+        }
+        search.dispatch(args);
+    };
+
+    view! {
+        <h3>Readable query parameters</h3>
+        <p>
+            "With the " <code>"QueryUrl"</code>
+            " input encoding, each argument is its own query parameter and lists are repeated parameters, so the URL can be bookmarked, shared, or cached."
+        </p>
+        <input node_ref=text_ref placeholder="Text contains" />
+        <input node_ref=tags_ref placeholder="Tags, separated by spaces" />
+        <input node_ref=limit_ref type="number" min="1" placeholder="Limit" />
+        <button on:click=on_click>"Search"</button>
+        <ShowLet some=url let:url>
+            <p>
+                <a href=url.clone()>
+                    <code>{url}</code>
+                </a>
+            </p>
+        </ShowLet>
+        <ShowLet some=error let:error>
+            <p>{error}</p>
+        </ShowLet>
+        <MatchResult
+            result=search.value()
+            let:rows
+            err=|e| view! { <p>{e.to_string()}</p> }
+        >
+            <ul>
+                {rows.into_iter().map(|text| view! { <li>{text}</li> }).collect::<Vec<_>>()}
+            </ul>
+        </MatchResult>
     }
 }
 
 #[server(
-    input = Rkyv,
-    output = Rkyv
+    input = AlignedRkyv,
+    output = AlignedRkyv
 )]
 pub async fn rkyv_example(input: String) -> Result<String, ServerFnError> {
     // insert a simulated wait
@@ -255,15 +758,19 @@ pub async fn rkyv_example(input: String) -> Result<String, ServerFnError> {
 #[component]
 pub fn RkyvExample() -> impl IntoView {
     let input_ref = NodeRef::<Input>::new();
-    let (input, set_input) = signal(String::new());
+    let (input, set_input) = use_url_text("rkyv");
     let rkyv_result = Resource::new(move || input.get(), rkyv_example);
 
     view! {
         <h3>Using <code>rkyv</code>encoding</h3>
-        <input node_ref=input_ref placeholder="Type something here." />
+        <input
+            node_ref=input_ref
+            placeholder="Type something here."
+            value=move || input.get()
+        />
         <button on:click=move |_| {
             let value = input_ref.get().unwrap().value();
-            set_input.set(value);
+            set_input(value);
         }>
 
             Click to capitalize
@@ -273,29 +780,171 @@ pub fn RkyvExample() -> impl IntoView {
     }
 }
 
+/// How many rows [`large_dataset`] and [`large_dataset_chunked`] send.
+const DATASET_ROWS: usize = 10_000;
+/// How many of those rows [`large_dataset_chunked`] puts in each chunk.
+const DATASET_CHUNK: usize = 500;
+
+/// Rows `start..start + DATASET_CHUNK` of the dataset, from a simulated slow
+/// source.
+#[cfg(feature = "ssr")]
+async fn dataset_batch(start: usize) -> Vec<FixtureRow> {
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    (start..start + DATASET_CHUNK)
+        .map(FixtureRow::sample)
+        .collect()
+}
+
+/// The whole dataset in one `rkyv` archive, sent once every row is ready.
+#[server(
+    input = AlignedRkyv,
+    output = AlignedRkyv
+)]
+pub async fn large_dataset() -> Result<Vec<FixtureRow>, ServerFnError> {
+    let mut rows = Vec::with_capacity(DATASET_ROWS);
+    for start in (0..DATASET_ROWS).step_by(DATASET_CHUNK) {
+        rows.extend(dataset_batch(start).await);
+    }
+    Ok(rows)
+}
+
+/// The same dataset, one archived chunk at a time as each is ready.
+#[server(output = RkyvChunks)]
+pub async fn large_dataset_chunked(
+) -> Result<RkyvChunkStream<FixtureRow>, ServerFnError> {
+    let batches =
+        futures::stream::iter((0..DATASET_ROWS).step_by(DATASET_CHUNK))
+            .then(|start| async move { Ok(dataset_batch(start).await) });
+    Ok(RkyvChunkStream::from_batches(batches))
+}
+
+/// What fetching the dataset one way took, in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DatasetTiming {
+    first_row_ms: f64,
+    all_rows_ms: f64,
+    rows: usize,
+    /// Adds up every row's score, so each row is actually read.
+    total_score: f64,
+}
+
+async fn time_buffered() -> Result<DatasetTiming, ServerFnError> {
+    let started = js_sys::Date::now();
+    let rows = large_dataset().await?;
+    let elapsed = js_sys::Date::now() - started;
+    Ok(DatasetTiming {
+        first_row_ms: elapsed,
+        all_rows_ms: elapsed,
+        rows: rows.len(),
+        total_score: rows.iter().map(|row| row.score).sum(),
+    })
+}
+
+async fn time_chunked() -> Result<DatasetTiming, ServerFnError> {
+    let started = js_sys::Date::now();
+    let mut chunks = large_dataset_chunked().await?.into_inner();
+    let mut first_row_ms = None;
+    let mut rows = 0;
+    let mut total_score = 0.0;
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk?;
+        // read in place, without deserializing
+        let items = chunk.items();
+        if !items.is_empty() {
+            first_row_ms.get_or_insert_with(|| js_sys::Date::now() - started);
+        }
+        rows += items.len();
+        total_score +=
+            items.iter().map(|row| row.score.to_native()).sum::<f64>();
+    }
+    Ok(DatasetTiming {
+        first_row_ms: first_row_ms.unwrap_or_default(),
+        all_rows_ms: js_sys::Date::now() - started,
+        rows,
+        total_score,
+    })
+}
+
+#[component]
+pub fn RkyvChunksExample() -> impl IntoView {
+    let (buffered, set_buffered) = signal(None);
+    let (chunked, set_chunked) = signal(None);
+    let run = move |_| {
+        set_buffered.set(None);
+        set_chunked.set(None);
+        spawn_local(async move {
+            let timing = time_buffered().await.map_err(|e| e.to_string());
+            set_buffered.set(Some(timing));
+            let timing = time_chunked().await.map_err(|e| e.to_string());
+            set_chunked.set(Some(timing));
+        });
+    };
+
+    view! {
+        <h3>Streaming <code>rkyv</code> chunks</h3>
+        <p>
+            {format!(
+                "Fetches {DATASET_ROWS} rows as one archive, then as archived chunks of {DATASET_CHUNK} that are read in place as they arrive."
+            )}
+        </p>
+        <button on:click=run>"Compare"</button>
+        <table>
+            <tr>
+                <th></th>
+                <th>"First row"</th>
+                <th>"All rows"</th>
+                <th>"Rows"</th>
+            </tr>
+            {timing_row("Buffered", buffered)}
+            {timing_row("Chunked", chunked)}
+        </table>
+    }
+}
+
+fn timing_row(
+    label: &'static str,
+    timing: ReadSignal<Option<Result<DatasetTiming, String>>>,
+) -> impl IntoView {
+    view! {
+        <tr>
+            <th>{label}</th>
+            <MatchResult
+                result=timing
+                let:timing
+                err=|e| view! { <td colspan="3">{e}</td> }
+                fallback=|| view! { <td colspan="3">"..."</td> }
+            >
+                <td>{format!("{:.0} ms", timing.first_row_ms)}</td>
+                <td>{format!("{:.0} ms", timing.all_rows_ms)}</td>
+                <td>{format!("{} (scores add up to {:.0})", timing.rows, timing.total_score)}</td>
+            </MatchResult>
+        </tr>
+    }
+}
+
 #[component]
 pub fn FileUpload() -> impl IntoView {
     #[server(
         input = MultipartFormData,
+        client = AppClient,
     )]
-    pub async fn file_length(
-        data: MultipartData,
-    ) -> Result<usize, ServerFnError> {
-        let mut data = data.into_inner().unwrap();
+    pub async fn file_length(data: MultipartData) -> Result<usize, AppError> {
+        use crate::flash::{set_flash, Flash};
+
+        let data = data.into_inner().ok_or(UploadError::NotMultipart)?;
 
         let mut count = 0;
-        while let Ok(Some(mut field)) = data.next_field().await {
-            println!("\n[NEXT FIELD]\n");
-            let name = field.name().unwrap_or_default().to_string();
-            println!("  [NAME] {name}");
-            while let Ok(Some(chunk)) = field.chunk().await {
-                let len = chunk.len();
-                count += len;
-                println!("      [CHUNK] {len}");
-                // in a real server function, you'd do something like saving the file here
-            }
-        }
+        for_each_chunk(data, |field, chunk| {
+            let name = field.name.as_deref().unwrap_or_default();
+            let len = chunk.len();
+            count += len;
+            println!("[{name}] [CHUNK] {len}");
+            // in a real server function, you'd do something like saving the file here
+            Ok(())
+        })
+        .await?;
 
+        set_flash(Flash::Notice(format!("Uploaded {count} bytes.")));
         Ok(count)
     }
 
@@ -306,12 +955,18 @@ pub fn FileUpload() -> impl IntoView {
     view! {
         <h3>File Upload</h3>
         <p>Uploading files is fairly easy using multipart form data.</p>
-        <form on:submit=move |ev: SubmitEvent| {
-            ev.prevent_default();
-            let target = ev.target().unwrap().unchecked_into::<HtmlFormElement>();
-            let form_data = FormData::new_with_form(&target).unwrap();
-            upload_action.dispatch_local(form_data);
-        }>
+        // posted as it is without JavaScript
+        <form
+            action=FileLength::url()
+            method="post"
+            enctype="multipart/form-data"
+            on:submit=move |ev: SubmitEvent| {
+                ev.prevent_default();
+                let target = ev.target().unwrap().unchecked_into::<HtmlFormElement>();
+                let form_data = FormData::new_with_form(&target).unwrap();
+                upload_action.dispatch_local(form_data);
+            }
+        >
             <input type="file" name="file_to_upload" />
             <input type="submit" />
         </form>
@@ -319,13 +974,11 @@ pub fn FileUpload() -> impl IntoView {
             {move || {
                 if upload_action.input().read().is_none() && upload_action.value().read().is_none()
                 {
-                    "Upload a file.".to_string()
+                    "Upload a file.".into_any()
                 } else if upload_action.pending().get() {
-                    "Uploading...".to_string()
-                } else if let Some(Ok(value)) = upload_action.value().get() {
-                    value.to_string()
+                    "Uploading...".into_any()
                 } else {
-                    format!("{:?}", upload_action.value().get())
+                    upload_action.value().get().map(show_result).into_any()
                 }
             }}
 
@@ -335,186 +988,169 @@ pub fn FileUpload() -> impl IntoView {
 
 #[component]
 pub fn FileUploadWithProgress() -> impl IntoView {
-    #[cfg(feature = "ssr")]
-    mod progress {
-        use async_broadcast::{broadcast, Receiver, Sender};
-        use dashmap::DashMap;
-        use futures::Stream;
-        use std::sync::LazyLock;
-
-        struct File {
-            total: usize,
-            tx: Sender<usize>,
-            rx: Receiver<usize>,
-        }
-
-        static FILES: LazyLock<DashMap<String, File>> =
-            LazyLock::new(DashMap::new);
-
-        pub async fn add_chunk(filename: &str, len: usize) {
-            println!("[{filename}]\tadding {len}");
-            let mut entry =
-                FILES.entry(filename.to_string()).or_insert_with(|| {
-                    println!("[{filename}]\tinserting channel");
-                    let (tx, rx) = broadcast(1048);
-                    File { total: 0, tx, rx }
-                });
-            entry.total += len;
-            let new_total = entry.total;
-
-            let tx = entry.tx.clone();
-            drop(entry);
-
-            tx.broadcast(new_total)
-                .await
-                .expect("couldn't send a message over channel");
-        }
-
-        pub fn for_file(filename: &str) -> impl Stream<Item = usize> {
-            let entry =
-                FILES.entry(filename.to_string()).or_insert_with(|| {
-                    println!("[{filename}]\tinserting channel");
-                    let (tx, rx) = broadcast(128);
-                    File { total: 0, tx, rx }
-                });
-            entry.rx.clone()
-        }
-    }
-
     #[server(
         input = MultipartFormData,
     )]
     pub async fn upload_file(data: MultipartData) -> Result<(), ServerFnError> {
-        let mut data = data.into_inner().unwrap();
-
-        while let Ok(Some(mut field)) = data.next_field().await {
-            let name =
-                field.file_name().expect("no filename on field").to_string();
-            while let Ok(Some(chunk)) = field.chunk().await {
-                let len = chunk.len();
-                println!("[{name}]\t{len}");
-                progress::add_chunk(&name, len).await;
+        use crate::progress::{self, Audience, Reporter};
+        use std::collections::HashMap;
+
+        let owner = require_owner()?;
+        let data = data
+            .into_inner()
+            .ok_or_else(|| ServerFnError::new(UploadError::NotMultipart))?;
+
+        // the client sends each file's size in a field before it
+        let mut size = String::new();
+        let mut files = HashMap::<String, (Reporter, u64)>::new();
+        let uploaded = for_each_chunk(data, |field, chunk| {
+            if field.name.as_deref() == Some("size") {
+                size.push_str(&String::from_utf8_lossy(chunk));
+                return Ok(());
+            }
+            let name = field.file_name.as_deref().ok_or_else(|| {
+                ServerFnError::new(UploadError::MissingFileName {
+                    field: field.name.clone().unwrap_or_default(),
+                })
+            })?;
+            let (reporter, done) =
+                files.entry(name.to_string()).or_insert_with(|| {
+                    let total = std::mem::take(&mut size).trim().parse().ok();
+                    let reporter = progress::start(
+                        Audience::Owner(owner),
+                        ProgressKind::Upload,
+                        name,
+                        total,
+                    );
+                    (reporter, 0)
+                });
+            *done += chunk.len() as u64;
+            reporter.advance(*done);
+            Ok(())
+        })
+        .await;
+        for (reporter, done) in files.into_values() {
+            match &uploaded {
+                Ok(()) => {
+                    reporter.complete(Some(format!("{done} bytes uploaded")))
+                }
+                Err(e) => reporter.fail(e.to_string()),
             }
         }
-
-        Ok(())
-    }
-
-    #[server(output = StreamingText)]
-    pub async fn file_progress(
-        filename: String,
-    ) -> Result<TextStream, ServerFnError> {
-        println!("getting progress on {filename}");
-        let progress = progress::for_file(&filename);
-        let progress = progress.map(|bytes| Ok(format!("{bytes}\n")));
-        Ok(TextStream::new(progress))
+        uploaded
     }
 
-    let (filename, set_filename) = signal(None);
-    let (max, set_max) = signal(None);
-    let (current, set_current) = signal(None);
+    let (upload_error, set_upload_error) = signal(None::<String>);
     let on_submit = move |ev: SubmitEvent| {
         ev.prevent_default();
         let target = ev.target().unwrap().unchecked_into::<HtmlFormElement>();
-        let form_data = FormData::new_with_form(&target).unwrap();
-        let file = form_data
+        let file = FormData::new_with_form(&target)
+            .unwrap()
             .get("file_to_upload")
             .unchecked_into::<web_sys::File>();
-        let filename = file.name();
-        let size = file.size() as usize;
-        set_filename.set(Some(filename.clone()));
-        set_max.set(Some(size));
-        set_current.set(None);
+        // the size goes first, so the server knows it before the file
+        let form_data = FormData::new().unwrap();
+        form_data
+            .append_with_str("size", &file.size().to_string())
+            .unwrap();
+        form_data
+            .append_with_blob_and_filename(
+                "file_to_upload",
+                &file,
+                &file.name(),
+            )
+            .unwrap();
+        set_upload_error.set(None);
 
         spawn_local(async move {
-            let mut progress = file_progress(filename)
-                .await
-                .expect("couldn't initialize stream")
-                .into_inner();
-            while let Some(Ok(len)) = progress.next().await {
-                let len = len
-                    .split('\n')
-                    .filter(|n| !n.is_empty())
-                    .next_back()
-                    .expect(
-                        "expected at least one non-empty value from \
-                         newline-delimited rows",
-                    )
-                    .parse::<usize>()
-                    .expect("invalid length");
-                set_current.set(Some(len));
+            if let Err(e) = upload_file(form_data.into()).await {
+                set_upload_error.set(Some(e.to_string()));
             }
         });
-        spawn_local(async move {
-            upload_file(form_data.into())
-                .await
-                .expect("couldn't upload file");
-        });
     };
 
     view! {
         <h3>File Upload with Progress</h3>
-        <p>A file upload with progress can be handled with two separate server functions.</p>
-        <aside>See the doc comment on the component for an explanation.</aside>
+        <p>
+            "The upload's progress comes back on a second server function, the stream every kind of long-running work reports on."
+        </p>
         <form on:submit=on_submit>
             <input type="file" name="file_to_upload" />
             <input type="submit" />
         </form>
-        {move || filename.get().map(|filename| view! { <p>Uploading {filename}</p> })}
-        <ShowLet some=max let:max>
-            <progress
-                max=max
-                value=move || current.get().unwrap_or_default()
-            ></progress>
+        <ProgressStream kind=ProgressKind::Upload />
+        <ShowLet some=upload_error let:e>
+            <p>"Upload failed: " {e}</p>
         </ShowLet>
     }
 }
 #[component]
 pub fn FileWatcher() -> impl IntoView {
-    #[server(input = GetUrl, output = StreamingText)]
-    pub async fn watched_files() -> Result<TextStream, ServerFnError> {
-        use notify::{
-            Config, Error, Event, RecommendedWatcher, RecursiveMode, Watcher,
-        };
-        use std::path::Path;
-
-        let (tx, rx) = futures::channel::mpsc::unbounded();
-
-        let mut watcher = RecommendedWatcher::new(
-            move |res: Result<Event, Error>| {
-                if let Ok(ev) = res {
-                    if let Some(path) = ev.paths.last() {
-                        let filename = path
-                            .file_name()
-                            .unwrap()
-                            .to_str()
-                            .unwrap()
-                            .to_string();
-                        _ = tx.unbounded_send(filename); //res);
-                    }
-                }
-            },
-            Config::default(),
-        )?;
-        watcher
-            .watch(Path::new("./watched_files"), RecursiveMode::Recursive)?;
-        std::mem::forget(watcher);
+    #[server(input = GetUrl, output = Framed)]
+    pub async fn watched_files(
+        after: Option<u64>,
+    ) -> Result<FramedStream<Tick<Result<String, String>>>, ServerFnError> {
+        crate::flags::require(Flag::FileWatcher)?;
+        crate::crawlers::refuse_crawlers()?;
+        let owner = require_owner()?;
+        let events = crate::watcher::subscribe(after)?
+            .filter_map(|event| {
+                let seq = event.seq;
+                let value = watched_file(event.value);
+                let event = value.map(|value| Sequenced { seq, value });
+                futures::future::ready(event)
+            })
+            .take_until(until_reset(owner));
+        let ticks = crate::channels::with_heartbeat(
+            events,
+            crate::channels::HEARTBEAT_INTERVAL,
+        );
+        Ok(FramedStream::new(ticks.map(Ok)))
+    }
 
-        Ok(TextStream::from(rx))
+    /// [`watched_files`] as long polls, for when its stream doesn't get
+    /// through.
+    #[server]
+    pub async fn poll_watched_files(
+        after: Option<u64>,
+    ) -> Result<Polled<Result<String, String>>, ServerFnError> {
+        crate::flags::require(Flag::FileWatcher)?;
+        crate::crawlers::refuse_crawlers()?;
+        require_owner()?;
+        let wait = crate::channels::LONG_POLL_WAIT;
+        Ok(crate::watcher::poll(after, wait, watched_file).await?)
+    }
+
+    /// The name of the file that changed, if it has one. Watcher errors are
+    /// sent as events rather than ending the stream, so a reconnecting
+    /// client's cursor moves past them.
+    #[cfg(feature = "ssr")]
+    fn watched_file(
+        change: Result<crate::watcher::Change, String>,
+    ) -> Option<Result<String, String>> {
+        match change {
+            Ok(change) => change.file_name().map(|name| Ok(name.into())),
+            Err(e) => Some(Err(e)),
+        }
     }
 
     let (files, set_files) = signal(Vec::new());
+    let (watcher_error, set_watcher_error) = signal(None::<String>);
+    let (connection, set_connection) = signal(ConnectionState::Connecting);
 
     Effect::new(move |_| {
-        spawn_local(async move {
-            while let Some(res) =
-                watched_files().await.unwrap().into_inner().next().await
-            {
-                if let Ok(filename) = res {
-                    set_files.update(|n| n.push(filename));
+        spawn_local(supervise_or_poll(
+            watched_files,
+            poll_watched_files,
+            set_connection,
+            move |event| {
+                match event {
+                    Ok(filename) => set_files.update(|n| n.push(filename)),
+                    Err(e) => set_watcher_error.set(Some(e)),
                 }
-            }
-        });
+                ControlFlow::Continue(())
+            },
+        ));
     });
 
     view! {
@@ -536,16 +1172,190 @@ pub fn FileWatcher() -> impl IntoView {
             }}
 
         </ul>
+        <p>"Watcher stream: " {move || connection.get().to_string()}</p>
+        <ShowLet some=watcher_error let:error>
+            <p>"Last watcher error: " {error}</p>
+        </ShowLet>
         <p>
             <em>
                 Add or remove some text files in the <code>watched_files</code>
                 directory and see the list of changes here.
             </em>
         </p>
+        <FileRowsToggle />
     }
 }
 
-#[server]
+/// Each line of the generated report is exactly this long, so the client
+/// knows the total size up front and can show progress.
+#[cfg(feature = "ssr")]
+const REPORT_LINE_LEN: usize = 64;
+#[cfg(feature = "ssr")]
+const REPORT_LINES_PER_CHUNK: usize = 1024;
+#[cfg(feature = "ssr")]
+const REPORT_CHUNKS_PER_MB: usize =
+    1024 * 1024 / (REPORT_LINE_LEN * REPORT_LINES_PER_CHUNK);
+
+#[server(output = Streaming)]
+pub async fn generate_report(size_mb: u8) -> Result<ByteStream, ServerFnError> {
+    // chunks are built one at a time as the client reads them, so the
+    // payload is never held in memory as a whole
+    let chunks = usize::from(size_mb) * REPORT_CHUNKS_PER_MB;
+    let report = futures::stream::iter(0..chunks).map(|chunk| {
+        let mut bytes =
+            Vec::with_capacity(REPORT_LINE_LEN * REPORT_LINES_PER_CHUNK);
+        for line in 0..REPORT_LINES_PER_CHUNK {
+            let n = chunk * REPORT_LINES_PER_CHUNK + line;
+            let row =
+                format!("row {n:010}: {:047x}\n", n.wrapping_mul(2654435761));
+            debug_assert_eq!(row.len(), REPORT_LINE_LEN);
+            bytes.extend_from_slice(row.as_bytes());
+        }
+        Ok::<_, ServerFnError>(bytes)
+    });
+    Ok(ByteStream::new(report))
+}
+
+#[component]
+pub fn GeneratedDownload() -> impl IntoView {
+    let (size_mb, set_size_mb) = signal(10u8);
+    let (received, set_received) = signal(None::<usize>);
+    let (error, set_error) = signal(None::<String>);
+    let total = move || usize::from(size_mb.get()) * 1024 * 1024;
+
+    let download = move |_| {
+        let size_mb = size_mb.get_untracked();
+        set_received.set(Some(0));
+        set_error.set(None);
+        spawn_local(async move {
+            let result = async {
+                let mut chunks = generate_report(size_mb).await?.into_inner();
+                // keep the chunks as JS buffers and let the Blob stitch them
+                // together, rather than growing one big Vec in WASM memory
+                let parts = js_sys::Array::new();
+                let mut len = 0;
+                while let Some(chunk) = chunks.next().await {
+                    let chunk = chunk?;
+                    len += chunk.len();
+                    parts.push(&js_sys::Uint8Array::from(chunk.as_ref()));
+                    set_received.set(Some(len));
+                }
+                save_blob(&parts, "report.txt").map_err(|e| {
+                    ServerFnError::new(format!("couldn't save file: {e:?}"))
+                })
+            };
+            if let Err(e) = result.await {
+                set_error.set(Some(e.to_string()));
+            }
+        });
+    };
+
+    view! {
+        <h3>Streaming a binary download</h3>
+        <p>
+            "The " <code>"Streaming"</code> " output encoding sends a "
+            <code>"ByteStream"</code>
+            " as chunked application/octet-stream, so a large generated file can be "
+            "downloaded with progress and saved via a Blob."
+        </p>
+        <select on:change=move |ev| {
+            if let Ok(size) = event_target_value(&ev).parse() {
+                set_size_mb.set(size);
+            }
+        }>
+            <option value="1">"1 MB"</option>
+            <option value="10" selected>"10 MB"</option>
+            <option value="50">"50 MB"</option>
+        </select>
+        <button on:click=download>"Download report"</button>
+        <ShowLet some=received let:received>
+            <progress max=total value=received></progress>
+            <span>{move || format!(" {} / {} KB", received / 1024, total() / 1024)}</span>
+        </ShowLet>
+        <ShowLet some=error let:error>
+            <p>"Download failed: " {error}</p>
+        </ShowLet>
+    }
+}
+
+/// Offers `parts` (an array of `Uint8Array`s) to the user as a file download.
+fn save_blob(
+    parts: &js_sys::Array,
+    filename: &str,
+) -> Result<(), wasm_bindgen::JsValue> {
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type("text/plain");
+    let blob =
+        web_sys::Blob::new_with_u8_array_sequence_and_options(parts, &options)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+    let link = document()
+        .create_element("a")?
+        .unchecked_into::<web_sys::HtmlAnchorElement>();
+    link.set_href(&url);
+    link.set_download(filename);
+    link.click();
+    web_sys::Url::revoke_object_url(&url)
+}
+
+#[server(input = GetUrl)]
+pub async fn channel_stats() -> Result<Vec<ChannelStats>, ServerFnError> {
+    Ok(crate::channels::stats())
+}
+
+#[component]
+pub fn ChannelLag() -> impl IntoView {
+    let refresh = RwSignal::new(0);
+    let stats = Resource::new(move || refresh.get(), |_| channel_stats());
+
+    view! {
+        <h3>Channel lag</h3>
+        <p>
+            "The progress and watcher streams never make the server wait on a slow client. "
+            "Progress channels coalesce to the latest total; the watcher channel drops its oldest events. "
+            "These counters show how much each subscriber skipped."
+        </p>
+        <button on:click=move |_| refresh.update(|n| *n += 1)>"Refresh"</button>
+        <Transition>
+            {move || Suspend::new(async move {
+                stats
+                    .await
+                    .map(|stats| {
+                        view! {
+                            <table>
+                                <tr>
+                                    <th>"Channel"</th>
+                                    <th>"Policy"</th>
+                                    <th>"Subscribers"</th>
+                                    <th>"Sent"</th>
+                                    <th>"Delivered"</th>
+                                    <th>"Dropped"</th>
+                                </tr>
+                                {stats
+                                    .into_iter()
+                                    .map(|stat| {
+                                        view! {
+                                            <tr>
+                                                <td>
+                                                    <code>{stat.name}</code>
+                                                </td>
+                                                <td>{format!("{:?}", stat.policy)}</td>
+                                                <td>{stat.subscribers}</td>
+                                                <td>{stat.sent}</td>
+                                                <td>{stat.delivered}</td>
+                                                <td>{stat.dropped}</td>
+                                            </tr>
+                                        }
+                                    })
+                                    .collect::<Vec<_>>()}
+                            </table>
+                        }
+                    })
+            })}
+        </Transition>
+    }
+}
+
+#[server(client = AppClient)]
 pub async fn ascii_uppercase(text: String) -> Result<String, MyErrors> {
     other_error()?;
     Ok(ascii_uppercase_inner(text)?)
@@ -567,11 +1377,18 @@ pub fn ascii_uppercase_inner(text: String) -> Result<String, InvalidArgument> {
     }
 }
 
-#[server]
+#[server(client = AppClient)]
 pub async fn ascii_uppercase_classic(
     text: String,
 ) -> Result<String, ServerFnError<InvalidArgument>> {
-    Ok(ascii_uppercase_inner(text)?)
+    use crate::errors::set_error_status;
+
+    // `ServerFnError` brings its own encoding, so it can't set the status
+    let result = ascii_uppercase_inner(text);
+    if let Err(e) = &result {
+        set_error_status(e);
+    }
+    Ok(result?)
 }
 
 #[derive(
@@ -592,6 +1409,22 @@ pub enum InvalidArgument {
     NotAscii,
 }
 
+impl ErrorCode for InvalidArgument {
+    fn code(&self) -> &str {
+        match self {
+            InvalidArgument::TooShort => "argument.too_short",
+            InvalidArgument::TooLong => "argument.too_long",
+            InvalidArgument::NotAscii => "argument.not_ascii",
+        }
+    }
+}
+
+impl HasStatusCode for InvalidArgument {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+}
+
 #[derive(
     thiserror::Error,
     Debug,
@@ -609,6 +1442,33 @@ pub enum MyErrors {
     Other(String),
 }
 
+impl ErrorCode for MyErrors {
+    fn code(&self) -> &str {
+        match self {
+            MyErrors::InvalidArgument(e) => e.code(),
+            MyErrors::ServerFnError(e) => e.code(),
+            MyErrors::Other(_) => "app.other",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            MyErrors::ServerFnError(e) => e.params(),
+            _ => ErrorParams::new(),
+        }
+    }
+}
+
+impl HasStatusCode for MyErrors {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            MyErrors::InvalidArgument(e) => e.status_code(),
+            MyErrors::ServerFnError(e) => e.status_code(),
+            MyErrors::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
 impl From<InvalidArgument> for MyErrors {
     fn from(value: InvalidArgument) -> Self {
         MyErrors::InvalidArgument(value)
@@ -622,7 +1482,7 @@ impl From<String> for MyErrors {
 }
 
 impl FromServerFnError for MyErrors {
-    type Encoder = RkyvEncoding;
+    type Encoder = WithStatus<AlignedRkyvEncoding>;
 
     fn from_server_fn_error(value: ServerFnErrorErr) -> Self {
         MyErrors::ServerFnError(value)
@@ -645,27 +1505,29 @@ pub fn CustomErrorTypes() -> impl IntoView {
             the rules!"
         </p>
         <input node_ref=input_ref placeholder="Type something here." />
-        <button on:click=move |_| {
+        // the errors are the point here, so they're shown with the results
+        // rather than by the button
+        <AsyncButton action=move || {
             let value = input_ref.get().unwrap().value();
-            spawn_local(async move {
+            async move {
                 let data = ascii_uppercase(value.clone()).await;
                 let data_classic = ascii_uppercase_classic(value).await;
                 set_result.set(Some(data));
                 set_result_classic.set(Some(data_classic));
-            });
+                Ok::<_, AppError>(())
+            }
         }>
-
             "Submit"
-        </button>
-        <p>{move || format!("{:?}", result.get())}</p>
-        <p>{move || format!("{:?}", result_classic.get())}</p>
+        </AsyncButton>
+        <p>{move || result.get().map(show_result)}</p>
+        <p>{move || result_classic.get().map(show_result)}</p>
     }
 }
 
 pub struct Toml;
 
 #[derive(Serialize, Deserialize)]
-pub struct TomlEncoded<T>(T);
+pub struct TomlEncoded<T>(pub T);
 
 impl ContentType for Toml {
     const CONTENT_TYPE: &'static str = "application/toml";
@@ -703,7 +1565,10 @@ where
         let string_data = req.try_into_string().await?;
         toml::from_str::<T>(&string_data)
             .map(TomlEncoded)
-            .map_err(|e| ServerFnErrorErr::Args(e.to_string()).into_app_error())
+            .map_err(|e| {
+                ServerFnErrorErr::Deserialization(e.to_string())
+                    .into_app_error()
+            })
     }
 }
 
@@ -768,16 +1633,16 @@ pub fn CustomEncoding() -> impl IntoView {
             "This example creates a custom encoding that sends server fn data using TOML. Why? Well... why not?"
         </p>
         <input node_ref=input_ref placeholder="Type something here." />
-        <button on:click=move |_| {
+        <AsyncButton action=move || {
             let value = input_ref.get().unwrap().value();
-            spawn_local(async move {
-                let new_value = why_not(value, ", but in TOML!!!".to_string()).await.unwrap();
-                set_result.set(new_value.0.modified);
-            });
+            async move {
+                why_not(value, ", but in TOML!!!".to_string())
+                    .await
+                    .map(|new_value| set_result.set(new_value.0.modified))
+            }
         }>
-
-            Submit
-        </button>
+            "Submit"
+        </AsyncButton>
         <p>{result}</p>
     }
 }
@@ -851,24 +1716,152 @@ pub fn CustomClientExample() -> impl IntoView {
     }
 }
 
+/// How many background calls [`PriorityClientExample`] queues ahead of its
+/// urgent one.
+const PREFETCHES: usize = 12;
+
+#[server(client = PriorityClient)]
+pub async fn slow_prefetch(n: usize) -> Result<usize, ServerFnError> {
+    // insert a simulated wait
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    Ok(n)
+}
+
+#[server(client = PriorityClient)]
+pub async fn urgent_mutation() -> Result<(), ServerFnError> {
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    Ok(())
+}
+
+#[component]
+pub fn PriorityClientExample() -> impl IntoView {
+    let finished = RwSignal::new(Vec::<String>::new());
+    let done = move |label: String| {
+        _ = finished.try_update(|finished| finished.push(label));
+    };
+
+    let run = move |_| {
+        finished.set(Vec::new());
+        for n in 0..PREFETCHES {
+            spawn_local(async move {
+                let call = slow_prefetch(n);
+                done(match with_priority(Priority::Background, call).await {
+                    Ok(n) => format!("prefetch {n}"),
+                    Err(e) => format!("prefetch {n} failed: {e}"),
+                });
+            });
+        }
+        spawn_local(async move {
+            let call = urgent_mutation();
+            done(match with_priority(Priority::Urgent, call).await {
+                Ok(()) => "mutation".to_string(),
+                Err(e) => format!("mutation failed: {e}"),
+            });
+        });
+    };
+
+    view! {
+        <h3>"Prioritized calls"</h3>
+        <p>
+            "Queues " {PREFETCHES} " background prefetches, then one urgent mutation, through a client that sends "
+            {MAX_IN_FLIGHT}
+            " calls at a time. The mutation is sent as soon as a call finishes, ahead of the prefetches still waiting."
+        </p>
+        <button on:click=run>"Queue the calls"</button>
+        <ol>
+            {move || {
+                finished
+                    .get()
+                    .into_iter()
+                    .map(|label| view! { <li>{label}</li> })
+                    .collect::<Vec<_>>()
+            }}
+        </ol>
+    }
+}
+
+/// Postcard writes fields in order, without names, so a payload of an
+/// older or newer shape is misread or rejected rather than adapted; it's
+/// always sent as a [`VersionedPostcardData`].
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PostcardData {
     name: String,
     age: u32,
     hobbies: Vec<String>,
+    /// Since version 2.
+    email: Option<String>,
+}
+
+/// [`PostcardData`] as version 1 clients know it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PostcardDataV1 {
+    name: String,
+    age: u32,
+    hobbies: Vec<String>,
+}
+
+/// [`PostcardData`] in any version a client might send.
+///
+/// Postcard writes the variant's index first, which for the first 128
+/// variants is one byte: the version byte. So variants may be added at the
+/// end, but never reordered or removed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum VersionedPostcardData {
+    V1(PostcardDataV1),
+    V2(PostcardData),
+}
+
+impl VersionedPostcardData {
+    pub fn version(&self) -> u8 {
+        match self {
+            Self::V1(_) => 1,
+            Self::V2(_) => 2,
+        }
+    }
+
+    /// The data in the current version, with defaults for whatever its own
+    /// version didn't have.
+    pub fn upgrade(self) -> PostcardData {
+        match self {
+            Self::V1(data) => PostcardData {
+                name: data.name,
+                age: data.age,
+                hobbies: data.hobbies,
+                email: None,
+            },
+            Self::V2(data) => data,
+        }
+    }
+
+    /// `data` in `version`, without whatever that version doesn't have.
+    pub fn downgrade(data: PostcardData, version: u8) -> Self {
+        match version {
+            1 => Self::V1(PostcardDataV1 {
+                name: data.name,
+                age: data.age,
+                hobbies: data.hobbies,
+            }),
+            _ => Self::V2(data),
+        }
+    }
 }
 
 #[server(input = Postcard, output = Postcard)]
 pub async fn postcard_example(
-    data: PostcardData,
-) -> Result<PostcardData, ServerFnError> {
+    data: VersionedPostcardData,
+) -> Result<VersionedPostcardData, ServerFnError> {
     tokio::time::sleep(std::time::Duration::from_millis(250)).await;
 
-    let mut modified_data = data.clone();
+    // reply in the version the client spoke
+    let version = data.version();
+    let mut modified_data = data.upgrade();
     modified_data.age += 1;
     modified_data.hobbies.push("Rust programming".to_string());
+    modified_data.email.get_or_insert_with(|| {
+        format!("{}@example.com", modified_data.name.to_lowercase())
+    });
 
-    Ok(modified_data)
+    Ok(VersionedPostcardData::downgrade(modified_data, version))
 }
 
 #[component]
@@ -877,11 +1870,14 @@ pub fn PostcardExample() -> impl IntoView {
         name: "Alice".to_string(),
         age: 30,
         hobbies: vec!["reading".to_string(), "hiking".to_string()],
+        email: None,
     });
 
     let postcard_result = Resource::new(
         move || input.get(),
-        |data| async move { postcard_example(data).await },
+        |data| async move {
+            postcard_example(VersionedPostcardData::V2(data)).await
+        },
     );
 
     view! {
@@ -895,7 +1891,248 @@ pub fn PostcardExample() -> impl IntoView {
         }>"Increment Age"</button>
         <p>"Input: " {move || format!("{:?}", input.get())}</p>
         <Transition>
-            <p>"Result: " {move || postcard_result.get().map(|r| format!("{:?}", r))}</p>
+            <p>
+                "Result: "
+                {move || {
+                    postcard_result.get().map(|r| show_result(r.map(|data| format!("{data:?}"))))
+                }}
+            </p>
         </Transition>
     }
 }
+
+/// Calls [`postcard_example`] the way a client built before
+/// [`PostcardData`] had an `email` would, to show the server still
+/// understands it and replies in kind.
+#[component]
+pub fn PostcardOldClientExample() -> impl IntoView {
+    let sent = VersionedPostcardData::V1(PostcardDataV1 {
+        name: "Bob".to_string(),
+        age: 52,
+        hobbies: vec!["chess".to_string()],
+    });
+    let call = Action::new(|data: &VersionedPostcardData| {
+        postcard_example(data.clone())
+    });
+
+    view! {
+        <h3>"An old client and a new server"</h3>
+        <p>
+            "Version 1 clients send a " <code>"PostcardData"</code>
+            " without an email. The server upgrades it, fills one in, and downgrades its reply to version 1 again, which drops the email."
+        </p>
+        <button on:click={
+            let sent = sent.clone();
+            move |_| {
+                call.dispatch(sent.clone());
+            }
+        }>"Send as a version 1 client"</button>
+        <p>{format!("Sent version {}: {sent:?}", sent.version())}</p>
+        {move || {
+            call.value()
+                .get()
+                .map(|reply| match reply {
+                    Ok(reply) => {
+                        format!("Got version {}: {reply:?}", reply.version())
+                    }
+                    Err(e) => e.to_string(),
+                })
+                .map(|reply| view! { <p>{reply}</p> })
+        }}
+    }
+}
+
+/// A few rows of [`Fixture::sample`], in whichever format the request's
+/// `Accept` header prefers.
+#[server(input = GetUrl, output = Negotiate, endpoint = "negotiated_fixture")]
+pub async fn negotiated_fixture(
+    rows: usize,
+) -> Result<Negotiated<Fixture>, ServerFnError> {
+    Negotiated::for_request(Fixture::sample(rows.min(10))).await
+}
+
+/// `Accept` headers to try [`negotiated_fixture`] with.
+const ACCEPT_EXAMPLES: [&str; 5] = [
+    "application/json",
+    "application/cbor",
+    "application/toml",
+    "application/toml;q=0.5, application/cbor;q=0.8",
+    "text/html, */*;q=0.1",
+];
+
+/// What a request to [`negotiated_fixture`] got back.
+#[derive(Debug, Clone, PartialEq)]
+struct NegotiatedReply {
+    content_type: String,
+    len: usize,
+    /// The body as text, or as hex if it's binary.
+    body: String,
+}
+
+async fn fetch_negotiated(
+    url: String,
+    accept: String,
+) -> Result<NegotiatedReply, String> {
+    let res = gloo_net::http::Request::get(&url)
+        .header("Accept", &accept)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let content_type = res.headers().get("content-type").unwrap_or_default();
+    let bytes = res.binary().await.map_err(|e| e.to_string())?;
+    let body = if content_type == NegotiatedFormat::Cbor.to_string() {
+        bytes
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    } else {
+        String::from_utf8_lossy(&bytes).into_owned()
+    };
+    Ok(NegotiatedReply {
+        content_type,
+        len: bytes.len(),
+        body,
+    })
+}
+
+#[component]
+pub fn NegotiationExample() -> impl IntoView {
+    let (accept_param, set_accept) = use_url_state::<String>("accept");
+    // only the examples, so a shared link can't send any header it likes
+    let accept = Memo::new(move |_| {
+        accept_param
+            .get()
+            .filter(|accept| ACCEPT_EXAMPLES.contains(&accept.as_str()))
+            .unwrap_or_else(|| ACCEPT_EXAMPLES[0].to_string())
+    });
+    let (reply, set_reply) = signal(None::<Result<NegotiatedReply, String>>);
+    let (typed, set_typed) = signal(None::<String>);
+    let url =
+        use_base_path().join(&format!("{}?rows=2", NegotiatedFixture::PATH));
+
+    let fetch = move |_| {
+        let (url, accept) = (url.clone(), accept.get_untracked());
+        spawn_local(async move {
+            set_reply.set(Some(fetch_negotiated(url, accept).await));
+        });
+    };
+    let call = move |_| {
+        spawn_local(async move {
+            let result = match negotiated_fixture(2).await {
+                Ok(fixture) => {
+                    let fixture = fixture.into_inner();
+                    format!(
+                        "{:?} with {} rows",
+                        fixture.title,
+                        fixture.rows.len()
+                    )
+                }
+                Err(e) => e.to_string(),
+            };
+            set_typed.set(Some(result));
+        });
+    };
+
+    view! {
+        <h3>"Content negotiation"</h3>
+        <p>
+            "One server function answers in JSON, CBOR or TOML, depending on the "
+            <code>"Accept"</code> " header."
+        </p>
+        <select on:change=move |ev| set_accept.set(Some(event_target_value(&ev)))>
+            {ACCEPT_EXAMPLES
+                .iter()
+                .map(|example| {
+                    view! {
+                        <option value=*example selected=move || accept.get() == *example>
+                            {*example}
+                        </option>
+                    }
+                })
+                .collect::<Vec<_>>()}
+        </select>
+        <button on:click=fetch>"Fetch"</button>
+        <MatchResult result=reply let:reply err=|e| view! { <p>{e}</p> }>
+            <p>
+                {format!("{} bytes of ", reply.len)}
+                <code>{reply.content_type}</code>
+            </p>
+            <pre>{reply.body}</pre>
+        </MatchResult>
+        <button on:click=call>"Call it as a server function"</button>
+        <ShowLet some=typed let:typed>
+            <p>{typed}</p>
+        </ShowLet>
+    }
+}
+
+#[server(input = GetUrl, client = CrossOriginClient)]
+pub async fn cross_origin_echo(text: String) -> Result<String, ServerFnError> {
+    use http::{header::ORIGIN, HeaderMap};
+    use leptos_axum::extract;
+
+    let headers: HeaderMap = extract().await?;
+    let origin = headers
+        .get(ORIGIN)
+        .and_then(|origin| origin.to_str().ok())
+        .unwrap_or("the same origin");
+    Ok(format!("Got {text:?} from {origin}"))
+}
+
+#[component]
+pub fn CrossOriginExample() -> impl IntoView {
+    let input_ref = NodeRef::<Input>::new();
+    let origin_ref = NodeRef::<Input>::new();
+    let (result, set_result) = signal(None::<Result<String, ServerFnError>>);
+
+    view! {
+        <h3>Calling a server function from another origin</h3>
+        <p>
+            "This server function uses a client that sends the request to another origin, so it only succeeds if that origin allows this page in its "
+            <code>"[cors] allowed_origins"</code>
+            " setting (and this page's CSP allows connecting to it)."
+        </p>
+        <p>
+            "If you leave the origin empty, the demo swaps "<code>"localhost"</code>" and "
+            <code>"127.0.0.1"</code>", which the browser treats as different origins."
+        </p>
+        <input node_ref=origin_ref placeholder="http://127.0.0.1:3000" />
+        <input node_ref=input_ref placeholder="Type something here." />
+        <button on:click=move |_| {
+            let origin = origin_ref.get().unwrap().value();
+            let origin = if origin.is_empty() {
+                window()
+                    .location()
+                    .origin()
+                    .map(|origin| swap_loopback_host(&origin))
+                    .ok()
+            } else {
+                Some(origin)
+            };
+            set_cross_origin_target(origin);
+            let value = input_ref.get().unwrap().value();
+            spawn_local(async move {
+                set_result.set(Some(cross_origin_echo(value).await));
+            });
+        }>"Send cross-origin"</button>
+        <p>
+            {move || {
+                result
+                    .get()
+                    .map(|result| match result {
+                        Ok(message) => message,
+                        Err(e) => format!("Request failed: {e}"),
+                    })
+            }}
+        </p>
+    }
+}
+
+fn swap_loopback_host(origin: &str) -> String {
+    if origin.contains("localhost") {
+        origin.replace("localhost", "127.0.0.1")
+    } else {
+        origin.replace("127.0.0.1", "localhost")
+    }
+}
@@ -0,0 +1,164 @@
+//! Several attachments in one zip, built as it's sent: each file is read
+//! from the blob store and compressed into the response a chunk at a
+//! time, so neither the files nor the archive are ever held in memory.
+
+use crate::codec::{QueryEncoded, QueryUrl};
+use leptos::prelude::*;
+use server_fn::codec::{ByteStream, Streaming};
+
+/// The most attachments one archive can hold.
+pub const MAX_ARCHIVE_FILES: usize = 100;
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use crate::{blobs, errors::ArchiveError, storage::Attachment};
+    use async_zip::{base::write::ZipFileWriter, Compression, ZipEntryBuilder};
+    use futures::{AsyncWriteExt, StreamExt};
+    use std::collections::HashSet;
+    use tokio::io::DuplexStream;
+
+    /// How much of the archive can be written ahead of what's been sent.
+    pub const PIPE_CAPACITY: usize = 64 * 1024;
+
+    /// Writes `attachments` into a zip on `pipe`, one after the other.
+    pub async fn write_archive(
+        attachments: Vec<Attachment>,
+        pipe: DuplexStream,
+    ) -> Result<(), ArchiveError> {
+        let zip_error =
+            |e: async_zip::error::ZipError| ArchiveError::Zip(e.to_string());
+        let mut zip = ZipFileWriter::with_tokio(pipe);
+        let mut names = HashSet::new();
+        for attachment in attachments {
+            // images are compressed already
+            let compression = if attachment.is_image() {
+                Compression::Stored
+            } else {
+                Compression::Deflate
+            };
+            let name = entry_name(&attachment.file_name, &mut names);
+            let mut entry = zip
+                .write_entry_stream(ZipEntryBuilder::new(
+                    name.into(),
+                    compression,
+                ))
+                .await
+                .map_err(zip_error)?;
+            let mut chunks = blobs::store().get(&attachment.id, None).await?;
+            while let Some(chunk) = chunks.next().await {
+                entry
+                    .write_all(&chunk?)
+                    .await
+                    .map_err(|e| ArchiveError::Zip(e.to_string()))?;
+            }
+            entry.close().await.map_err(zip_error)?;
+        }
+        zip.close().await.map_err(zip_error)?;
+        Ok(())
+    }
+
+    /// `file_name` as a name inside the archive: without directories, so
+    /// unzipping can't write anywhere else, and numbered if another file
+    /// already has it.
+    fn entry_name(file_name: &str, taken: &mut HashSet<String>) -> String {
+        let base = file_name.replace(['/', '\\'], "_");
+        let base = if base.is_empty() || base.chars().all(|c| c == '.') {
+            "attachment".to_string()
+        } else {
+            base
+        };
+        let (stem, extension) = match base.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => {
+                (stem.to_string(), format!(".{extension}"))
+            }
+            _ => (base.clone(), String::new()),
+        };
+        let mut name = base;
+        let mut n = 1;
+        while !taken.insert(name.clone()) {
+            n += 1;
+            name = format!("{stem} ({n}){extension}");
+        }
+        name
+    }
+}
+
+/// The caller's attachments `names` (their IDs) as one zip, which is
+/// streamed as it's built. Every one has to have been cleared by the
+/// scanner.
+#[server(
+    input = QueryUrl,
+    output = Streaming,
+    custom = QueryEncoded,
+    endpoint = "download_archive"
+)]
+pub async fn download_archive(
+    #[server(default)]
+    #[server(rename = "name")]
+    names: Vec<String>,
+) -> Result<ByteStream, ServerFnError> {
+    use crate::{
+        attachments::require_clean, errors::ArchiveError,
+        sandbox::require_owner, storage::ROWS,
+    };
+    use futures::stream;
+    use http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+        HeaderValue,
+    };
+    use tokio::io::AsyncReadExt;
+
+    let owner = require_owner()?;
+    if names.is_empty() {
+        return Err(ServerFnError::new(ArchiveError::Empty));
+    }
+    if names.len() > MAX_ARCHIVE_FILES {
+        return Err(ServerFnError::new(ArchiveError::TooManyFiles {
+            max: MAX_ARCHIVE_FILES,
+        }));
+    }
+    let mut attachments = Vec::with_capacity(names.len());
+    for name in names {
+        let attachment = ROWS
+            .find_attachment(owner, &name)
+            .ok_or_else(|| ServerFnError::new("there is no such attachment"))?;
+        require_clean(&attachment).await?;
+        attachments.push(attachment);
+    }
+
+    let response = expect_context::<leptos_axum::ResponseOptions>();
+    response.insert_header(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/zip"),
+    );
+    response.insert_header(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("attachment; filename=\"attachments.zip\""),
+    );
+
+    let (pipe, reader) = tokio::io::duplex(PIPE_CAPACITY);
+    let writing = tokio::spawn(write_archive(attachments, pipe));
+    // once the archive has been read to the end, a writer that failed
+    // turns into an error, so the download is cut off rather than looking
+    // complete
+    let chunks = stream::unfold(Some((reader, writing)), |state| async move {
+        let (mut reader, writing) = state?;
+        let mut buf = vec![0; PIPE_CAPACITY];
+        match reader.read(&mut buf).await {
+            Ok(0) => match writing.await {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some((Err(ServerFnError::new(e)), None)),
+                Err(e) => Some((Err(ServerFnError::new(e)), None)),
+            },
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok(buf), Some((reader, writing))))
+            }
+            Err(e) => Some((Err(ServerFnError::from(e)), None)),
+        }
+    });
+    Ok(ByteStream::new(chunks))
+}
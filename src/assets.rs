@@ -0,0 +1,84 @@
+use axum::{
+    body::Body,
+    extract::{FromRef, State},
+    response::{IntoResponse, Response},
+};
+use http::{
+    header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+    HeaderMap, HeaderValue, Request, StatusCode, Uri,
+};
+use leptos::prelude::{IntoView, LeptosOptions};
+use rust_embed::RustEmbed;
+use std::{future::Future, pin::Pin};
+
+/// The compiled site (JS/WASM/CSS under `pkg/` plus everything copied from
+/// `public/`), embedded into the server binary at compile time.
+///
+/// Release builds read the files from the binary; debug builds read them from
+/// disk so `cargo leptos watch` keeps working.
+#[derive(RustEmbed)]
+#[folder = "target/site"]
+struct SiteAssets;
+
+type HandlerFuture = Pin<Box<dyn Future<Output = Response> + Send + 'static>>;
+
+/// Like [`leptos_axum::file_and_error_handler_with_context`], but serves
+/// assets from memory before falling back to disk and the 404 page.
+pub fn embedded_file_and_error_handler<S, IV>(
+    additional_context: impl Fn() + 'static + Clone + Send,
+    shell: impl Fn(LeptosOptions) -> IV + 'static + Clone + Send,
+) -> impl Fn(Uri, State<S>, Request<Body>) -> HandlerFuture + Clone + Send + 'static
+where
+    IV: IntoView + 'static,
+    S: Send + Sync + Clone + 'static,
+    LeptosOptions: FromRef<S>,
+{
+    let fallback = leptos_axum::file_and_error_handler_with_context(
+        additional_context,
+        shell,
+    );
+    move |uri: Uri, state: State<S>, req: Request<Body>| {
+        let embedded = embedded_response(uri.path(), req.headers());
+        match embedded {
+            Some(res) => Box::pin(async move { res }) as HandlerFuture,
+            None => fallback(uri, state, req),
+        }
+    }
+}
+
+fn embedded_response(path: &str, headers: &HeaderMap) -> Option<Response> {
+    let file = SiteAssets::get(path.trim_start_matches('/'))?;
+    let etag = format!(
+        "\"{}\"",
+        file.metadata
+            .sha256_hash()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>()
+    );
+    let etag = HeaderValue::from_str(&etag).ok()?;
+    let content_type = HeaderValue::from_str(file.metadata.mimetype()).ok()?;
+    let cache_control = HeaderValue::from_static("public, no-cache");
+
+    if headers.get(IF_NONE_MATCH) == Some(&etag) {
+        return Some(
+            (
+                StatusCode::NOT_MODIFIED,
+                [(ETAG, etag), (CACHE_CONTROL, cache_control)],
+            )
+                .into_response(),
+        );
+    }
+
+    Some(
+        (
+            [
+                (CONTENT_TYPE, content_type),
+                (ETAG, etag),
+                (CACHE_CONTROL, cache_control),
+            ],
+            file.data,
+        )
+            .into_response(),
+    )
+}
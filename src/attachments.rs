@@ -0,0 +1,618 @@
+use crate::{
+    archives::DownloadArchive,
+    base_path::use_base_path,
+    channels::Tick,
+    codec::{
+        Framed, FramedStream, MixedPart, MixedParts, MultipartMixed,
+        QueryEncoded,
+    },
+    query::to_query_string,
+    scanning::ScanStatus,
+    storage::{Attachment, Row},
+    supervisor::{supervise, ConnectionState},
+    thumbnails::{ThumbnailPreview, ThumbnailSize},
+    uploads::{DropZone, UploadQueue},
+};
+#[cfg(feature = "ssr")]
+use crate::{
+    audit::AuditAction,
+    blobs,
+    errors::{QuotaExceeded, ScanError, UploadError},
+    metrics::METRICS,
+    multipart::multipart_error,
+    quotas::{QuotaKind, QUOTAS},
+    sandbox::{require_owner, until_reset},
+    scanning::{self, quarantine_path, QUARANTINE_DIR},
+    storage::ROWS,
+    thumbnails,
+};
+use leptos::{prelude::*, task::spawn_local};
+use server_fn::{
+    codec::{ByteStream, GetUrl, MultipartData, MultipartFormData, Streaming},
+    ServerFn,
+};
+use std::{collections::HashSet, ops::ControlFlow};
+
+/// The largest file that can be attached, in bytes.
+pub const MAX_ATTACHMENT_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Where `attachment` is in its scan. One with no scan on record was
+/// either cleared before the server restarted, or never will be.
+#[cfg(feature = "ssr")]
+async fn scan_status(attachment: &Attachment) -> ScanStatus {
+    if let Some(status) = scanning::status(&attachment.id) {
+        return status;
+    }
+    match blobs::store().exists(&attachment.id).await {
+        Ok(true) => ScanStatus::Clean,
+        Ok(false) => {
+            ScanStatus::Failed("the server restarted mid-scan".to_string())
+        }
+        Err(e) => ScanStatus::Failed(e.to_string()),
+    }
+}
+
+/// An error unless `attachment` has been cleared for download.
+#[cfg(feature = "ssr")]
+pub(crate) async fn require_clean(
+    attachment: &Attachment,
+) -> Result<(), ScanError> {
+    let file_name = attachment.file_name.clone();
+    match scan_status(attachment).await {
+        ScanStatus::Clean => Ok(()),
+        ScanStatus::Quarantined => Err(ScanError::Quarantined { file_name }),
+        ScanStatus::Rejected(reason) => {
+            Err(ScanError::Rejected { file_name, reason })
+        }
+        ScanStatus::Failed(message) => {
+            Err(ScanError::Failed { file_name, message })
+        }
+    }
+}
+
+/// Attaches the uploaded file to the row named by the `row_id` field, which
+/// has to come before the file in the form. The file is quarantined until
+/// it has been scanned; [`scan_events`] says how that goes.
+#[server(input = MultipartFormData)]
+#[middleware(crate::middleware::uploads())]
+pub async fn attach_file(
+    data: MultipartData,
+) -> Result<Attachment, ServerFnError> {
+    use tokio::{fs, io::AsyncWriteExt};
+
+    let owner = require_owner()?;
+    let mut data = data
+        .into_inner()
+        .ok_or_else(|| ServerFnError::new(UploadError::NotMultipart))?;
+    let invalid = |field: &str| {
+        ServerFnError::new(UploadError::InvalidField {
+            field: field.to_string(),
+        })
+    };
+
+    let mut row_id = None;
+    while let Some(mut field) =
+        data.next_field().await.map_err(multipart_error)?
+    {
+        if field.name() == Some("row_id") {
+            let text = field.text().await.map_err(multipart_error)?;
+            row_id = text.trim().parse::<u64>().ok();
+            continue;
+        }
+        let row_id = row_id.ok_or_else(|| invalid("row_id"))?;
+        let file_name =
+            field.file_name().map(str::to_string).ok_or_else(|| {
+                ServerFnError::new(UploadError::MissingFileName {
+                    field: field.name().unwrap_or_default().to_string(),
+                })
+            })?;
+        let content_type = field.content_type().map_or_else(
+            || "application/octet-stream".to_string(),
+            ToString::to_string,
+        );
+
+        fs::create_dir_all(QUARANTINE_DIR).await?;
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let path = quarantine_path(&id);
+        let mut file = fs::File::create(&path).await?;
+        let mut size = 0;
+        // the quota is only used up once the file is attached, but there's
+        // no point going on with one that won't fit in what's left of it
+        let usage = QUOTAS.usage(owner);
+        let room = usage.max_upload_bytes.saturating_sub(usage.upload_bytes);
+        // written as it arrives, and abandoned as soon as it is too big
+        let written = async {
+            while let Some(chunk) =
+                field.chunk().await.map_err(multipart_error)?
+            {
+                size += chunk.len() as u64;
+                if size > MAX_ATTACHMENT_SIZE {
+                    return Err(ServerFnError::new(UploadError::TooLarge {
+                        max: MAX_ATTACHMENT_SIZE,
+                    }));
+                }
+                if size > room {
+                    return Err(ServerFnError::new(QuotaExceeded {
+                        kind: QuotaKind::UploadBytes,
+                        limit: usage.max_upload_bytes,
+                    }));
+                }
+                file.write_all(&chunk).await?;
+            }
+            file.flush().await?;
+            Ok::<_, ServerFnError>(())
+        }
+        .await;
+        if let Err(e) = written {
+            _ = fs::remove_file(&path).await;
+            return Err(e);
+        }
+
+        METRICS.record_upload(size);
+        let attachment = Attachment {
+            id,
+            file_name,
+            content_type,
+            size,
+        };
+        let attached = ROWS.transaction(owner, |tx| {
+            tx.consume_quota(QuotaKind::UploadBytes, size)?;
+            tx.add_attachment(row_id, attachment.clone())
+                .ok_or_else(|| {
+                    ServerFnError::new(format!("there is no row {row_id}"))
+                })?;
+            tx.audit(
+                row_id,
+                AuditAction::AttachmentAdded {
+                    file_name: attachment.file_name.clone(),
+                },
+            );
+            Ok::<_, ServerFnError>(())
+        });
+        if let Err(e) = attached {
+            _ = fs::remove_file(&path).await;
+            return Err(e);
+        }
+        let on_clean = {
+            let id = attachment.id.clone();
+            let image = attachment.is_image();
+            move || {
+                if image {
+                    thumbnails::spawn_job(id);
+                }
+            }
+        };
+        scanning::spawn_scan(
+            attachment.id.clone(),
+            attachment.content_type.clone(),
+            size,
+            on_clean,
+        );
+        return Ok(attachment);
+    }
+    Err(invalid("file"))
+}
+
+/// `file_name`, which came from a client, cut down to what fits in a
+/// quoted header parameter.
+#[cfg(feature = "ssr")]
+fn header_file_name(file_name: &str) -> String {
+    file_name
+        .chars()
+        .map(|c| match c {
+            ' ' => c,
+            '"' | '\\' => '_',
+            c if c.is_ascii_graphic() => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// An attached file. Single `Range` requests are answered with just those
+/// bytes, so downloads can be resumed and media seeked through, and
+/// `If-None-Match` and `If-Range` are checked against the ETag, which is
+/// the attachment's ID: a stored file never changes.
+#[server(input = GetUrl, output = Streaming)]
+pub async fn download_attachment(
+    row_id: u64,
+    id: String,
+) -> Result<ByteStream, ServerFnError> {
+    use crate::ranges::{self, RangeAnswer};
+    use futures::{stream, StreamExt};
+    use http::{
+        header::{
+            ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE,
+            CONTENT_TYPE, ETAG,
+        },
+        request::Parts,
+        HeaderMap, HeaderValue, StatusCode,
+    };
+
+    // only IDs the store knows about are ever used as keys
+    let owner = require_owner()?;
+    let attachment = ROWS
+        .attachment(owner, row_id, &id)
+        .ok_or_else(|| ServerFnError::new("there is no such attachment"))?;
+    require_clean(&attachment).await?;
+
+    let etag = format!("\"{}\"", attachment.id);
+    let size = attachment.size;
+    let headers = use_context::<Parts>()
+        .map(|parts| parts.headers)
+        .unwrap_or_else(HeaderMap::new);
+    let response = expect_context::<leptos_axum::ResponseOptions>();
+    response.insert_header(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    response.insert_header(ETAG, HeaderValue::from_str(&etag)?);
+    let nothing =
+        || ByteStream::new(stream::empty::<Result<Vec<u8>, ServerFnError>>());
+    let range = match ranges::answer(&headers, &etag, size) {
+        RangeAnswer::NotModified => {
+            response.set_status(StatusCode::NOT_MODIFIED);
+            return Ok(nothing());
+        }
+        RangeAnswer::Unsatisfiable => {
+            response.set_status(StatusCode::RANGE_NOT_SATISFIABLE);
+            response.insert_header(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes */{size}"))?,
+            );
+            return Ok(nothing());
+        }
+        RangeAnswer::Partial(range) => {
+            response.set_status(StatusCode::PARTIAL_CONTENT);
+            response.insert_header(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&ranges::content_range(&range, size))?,
+            );
+            Some(range)
+        }
+        RangeAnswer::Full => None,
+    };
+    let length = range.as_ref().map_or(size, |range| range.end - range.start);
+    response.insert_header(CONTENT_LENGTH, HeaderValue::from(length));
+    let chunks = blobs::store().get(&attachment.id, range).await?;
+
+    let file_name = header_file_name(&attachment.file_name);
+    response.insert_header(
+        CONTENT_TYPE,
+        HeaderValue::from_str(&attachment.content_type)?,
+    );
+    response.insert_header(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!(
+            "attachment; filename=\"{file_name}\""
+        ))?,
+    );
+
+    Ok(ByteStream::new(
+        chunks.map(|chunk| chunk.map_err(ServerFnError::from)),
+    ))
+}
+
+/// A URL to download an attachment straight from the blob store, good for
+/// a few minutes, or `None` if downloads have to go through
+/// [`download_attachment`].
+#[server(input = GetUrl)]
+pub async fn attachment_url(
+    row_id: u64,
+    id: String,
+) -> Result<Option<String>, ServerFnError> {
+    let owner = require_owner()?;
+    let attachment = ROWS
+        .attachment(owner, row_id, &id)
+        .ok_or_else(|| ServerFnError::new("there is no such attachment"))?;
+    require_clean(&attachment).await?;
+    Ok(blobs::store().presigned_url(
+        &attachment.id,
+        &header_file_name(&attachment.file_name),
+        &attachment.content_type,
+    ))
+}
+
+/// A row and one of its attached files in one response: the row as JSON in
+/// the `row` part, and the file in the `file` part.
+#[server(input = GetUrl, output = MultipartMixed)]
+pub async fn get_row_with_attachment(
+    row_id: u64,
+    id: String,
+) -> Result<MixedParts, ServerFnError> {
+    let owner = require_owner()?;
+    let row = ROWS.get(owner, row_id).ok_or_else(|| {
+        ServerFnError::new(format!("there is no row {row_id}"))
+    })?;
+    let attachment = row
+        .attachments
+        .iter()
+        .find(|attachment| attachment.id == id)
+        .cloned()
+        .ok_or_else(|| ServerFnError::new("there is no such attachment"))?;
+    require_clean(&attachment).await?;
+    let file = blobs::read(&attachment.id).await?;
+    Ok(MixedParts::new(vec![
+        MixedPart::json("row", &row)?,
+        MixedPart::new("file", attachment.content_type, file)
+            .with_file_name(attachment.file_name),
+    ]))
+}
+
+/// An attachment's scan status, then every change to it.
+#[server(input = GetUrl, output = Framed)]
+pub async fn scan_events(
+    row_id: u64,
+    id: String,
+    after: Option<u64>,
+) -> Result<FramedStream<Tick<ScanStatus>>, ServerFnError> {
+    use crate::channels::{with_heartbeat, HEARTBEAT_INTERVAL};
+    use futures::StreamExt;
+
+    let owner = require_owner()?;
+    let attachment = ROWS
+        .attachment(owner, row_id, &id)
+        .ok_or_else(|| ServerFnError::new("there is no such attachment"))?;
+    let current = scan_status(&attachment).await;
+    let statuses = scanning::subscribe(&attachment.id, current, after)
+        .take_until(until_reset(owner));
+    let ticks = with_heartbeat(statuses, HEARTBEAT_INTERVAL);
+    Ok(FramedStream::new(ticks.map(Ok)))
+}
+
+/// Deletes the stored files of `attachments`, logging any that can't be.
+#[cfg(feature = "ssr")]
+pub async fn remove_files(attachments: &[Attachment]) {
+    for attachment in attachments {
+        scanning::remove(&attachment.id).await;
+        if let Err(e) = blobs::store().delete(&attachment.id).await {
+            tracing::warn!(id = %attachment.id, "couldn't delete attachment: {e}");
+        }
+        thumbnails::remove(&attachment.id).await;
+    }
+}
+
+/// A row's attachments as download links, a link to download them (or
+/// those picked) as one zip, and a drop zone to attach more.
+#[component]
+pub fn RowAttachments(
+    row_id: u64,
+    attachments: Vec<Attachment>,
+    uploads: UploadQueue,
+) -> impl IntoView {
+    let base_path = use_base_path();
+    let ids = attachments
+        .iter()
+        .map(|attachment| attachment.id.clone())
+        .collect::<Vec<_>>();
+    let clean = RwSignal::new(HashSet::<String>::new());
+    let selected = RwSignal::new(HashSet::<String>::new());
+    let archive = move || {
+        let (names, label) = selected.with(|selected| {
+            if selected.is_empty() {
+                let names = clean.with(|clean| {
+                    ids.iter()
+                        .filter(|id| clean.contains(*id))
+                        .cloned()
+                        .collect::<Vec<_>>()
+                });
+                (names, "Download all as a zip".to_string())
+            } else {
+                let names = ids
+                    .iter()
+                    .filter(|id| selected.contains(*id))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let label = format!("Download {} as a zip", names.len());
+                (names, label)
+            }
+        });
+        // one file is better downloaded as it is
+        let worth_it = names.len() > 1 || selected.with(|s| !s.is_empty());
+        let query = to_query_string(&DownloadArchive { names }).ok()?;
+        let href = format!(
+            "{}?{query}",
+            base_path.join(QueryEncoded::<DownloadArchive>::url())
+        );
+        worth_it.then(|| {
+            view! {
+                <p>
+                    <a href=href download="attachments.zip">
+                        {label}
+                    </a>
+                </p>
+            }
+        })
+    };
+
+    view! {
+        <ul class="attachments">
+            {attachments
+                .into_iter()
+                .map(|attachment| {
+                    view! { <AttachmentItem row_id attachment clean selected /> }
+                })
+                .collect::<Vec<_>>()}
+        </ul>
+        {archive}
+        <DropZone row_id queue=uploads />
+    }
+}
+
+/// One attachment: how its scan is going, and once it's been cleared, a
+/// link to download it and a box to pick it for a zip. Cleared attachments
+/// are added to `clean`, and picked ones to `selected`.
+#[component]
+fn AttachmentItem(
+    row_id: u64,
+    attachment: Attachment,
+    clean: RwSignal<HashSet<String>>,
+    selected: RwSignal<HashSet<String>>,
+) -> impl IntoView {
+    let base_path = use_base_path();
+    let href = base_path.join(&format!(
+        "{}?row_id={row_id}&id={}",
+        DownloadAttachment::PATH,
+        attachment.id,
+    ));
+    let (status, set_status) = signal(ScanStatus::Quarantined);
+    let (connection, set_connection) = signal(ConnectionState::Connecting);
+
+    Effect::new({
+        let id = attachment.id.clone();
+        move |_| {
+            let id = id.clone();
+            let cleared = id.clone();
+            spawn_local(supervise(
+                move |after| scan_events(row_id, id.clone(), after),
+                set_connection,
+                move |status: ScanStatus| {
+                    if status == ScanStatus::Clean {
+                        clean.update(|clean| {
+                            clean.insert(cleared.clone());
+                        });
+                    }
+                    let done = status.is_done();
+                    set_status.set(status);
+                    if done {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                },
+            ));
+        }
+    });
+
+    let image = attachment.is_image();
+    let Attachment {
+        id,
+        file_name,
+        size,
+        ..
+    } = attachment;
+    let file = move || {
+        match status.get() {
+        ScanStatus::Quarantined => view! {
+            {file_name.clone()}
+            " "
+            <small title=move || connection.get().to_string()>"Scanning..."</small>
+        }
+        .into_any(),
+        ScanStatus::Clean => view! {
+            <input
+                type="checkbox"
+                title="Pick for a zip"
+                prop:checked={
+                    let id = id.clone();
+                    move || selected.with(|selected| selected.contains(&id))
+                }
+                on:change={
+                    let id = id.clone();
+                    move |ev| {
+                        let picked = event_target_checked(&ev);
+                        selected
+                            .update(|selected| {
+                                if picked {
+                                    selected.insert(id.clone());
+                                } else {
+                                    selected.remove(&id);
+                                }
+                            });
+                    }
+                }
+            />
+            {image
+                .then(|| {
+                    view! { <ThumbnailPreview name=id.clone() size=ThumbnailSize::Small /> }
+                })}
+            <a
+                href=href.clone()
+                download=file_name.clone()
+                on:click={
+                    let id = id.clone();
+                    let href = href.clone();
+                    move |ev| {
+                        ev.prevent_default();
+                        let id = id.clone();
+                        let href = href.clone();
+                        spawn_local(async move {
+                            // straight from the bucket, if it's kept in one
+                            let url = match attachment_url(row_id, id).await {
+                                Ok(Some(url)) => url,
+                                _ => href,
+                            };
+                            _ = window().location().set_href(&url);
+                        });
+                    }
+                }
+            >
+                {file_name.clone()}
+            </a>
+        }
+        .into_any(),
+        ScanStatus::Rejected(reason) => {
+            view! { {file_name.clone()} " was rejected: " {reason} }.into_any()
+        }
+        ScanStatus::Failed(e) => {
+            view! { {file_name.clone()} " couldn't be scanned: " {e} }
+                .into_any()
+        }
+    }
+    };
+
+    view! {
+        <li>
+            {file}
+            {format!(" ({} KiB)", size.div_ceil(1024))}
+        </li>
+    }
+}
+
+/// Fetches a row together with one of its attachments, with
+/// [`get_row_with_attachment`], and shows what came back.
+#[component]
+pub fn RowWithAttachment(row_id: u64, attachment_id: String) -> impl IntoView {
+    let fetch = Action::new(move |_: &()| {
+        get_row_with_attachment(row_id, attachment_id.clone())
+    });
+
+    view! {
+        <button on:click=move |_| {
+            fetch.dispatch(());
+        }>"Fetch with its row"</button>
+        {move || {
+            fetch
+                .value()
+                .get()
+                .map(|parts| match unbundle(parts) {
+                    Ok((row, file)) => {
+                        let preview = file
+                            .content_type
+                            .starts_with("text/")
+                            .then(|| {
+                                view! { <pre>{String::from_utf8_lossy(&file.body).into_owned()}</pre> }
+                            });
+                        view! {
+                            <p>
+                                {format!(
+                                    "One response held row {} (\"{}\") and {} ({}, {} bytes).",
+                                    row.id,
+                                    row.text,
+                                    file.file_name.unwrap_or_default(),
+                                    file.content_type,
+                                    file.body.len(),
+                                )}
+                            </p>
+                            {preview}
+                        }
+                            .into_any()
+                    }
+                    Err(e) => view! { <p>{e.to_string()}</p> }.into_any(),
+                })
+        }}
+    }
+}
+
+fn unbundle(
+    parts: Result<MixedParts, ServerFnError>,
+) -> Result<(Row, MixedPart), ServerFnError> {
+    let parts = parts?;
+    Ok((parts.get("row")?.to_json()?, parts.get("file")?.clone()))
+}
@@ -0,0 +1,284 @@
+use crate::{
+    auth::UserId,
+    channels::Tick,
+    codec::{Framed, FramedStream},
+    storage::Tag,
+    supervisor::{supervise, ConnectionState},
+};
+#[cfg(feature = "ssr")]
+use crate::{
+    channels::{with_heartbeat, HEARTBEAT_INTERVAL},
+    sandbox::require_owner,
+};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use leptos::{prelude::*, task::spawn_local};
+use serde::{Deserialize, Serialize};
+use server_fn::codec::GetUrl;
+use std::{fmt, ops::ControlFlow};
+
+/// Something that happened to a row.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditAction {
+    Created,
+    Edited,
+    Completed,
+    Reopened,
+    TagsSet(Vec<Tag>),
+    TagAdded(Tag),
+    TagRemoved(Tag),
+    DueSet(Option<NaiveDate>),
+    AttachmentAdded {
+        file_name: String,
+    },
+    Moved,
+    /// Moved to the trash.
+    Deleted,
+    Restored,
+    Purged,
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditAction::Created => f.write_str("created"),
+            AuditAction::Edited => f.write_str("edited"),
+            AuditAction::Completed => f.write_str("completed"),
+            AuditAction::Reopened => f.write_str("reopened"),
+            AuditAction::TagsSet(tags) if tags.is_empty() => {
+                f.write_str("untagged")
+            }
+            AuditAction::TagsSet(tags) => {
+                f.write_str("tagged")?;
+                for tag in tags {
+                    write!(f, " #{tag}")?;
+                }
+                Ok(())
+            }
+            AuditAction::TagAdded(tag) => write!(f, "tagged #{tag}"),
+            AuditAction::TagRemoved(tag) => write!(f, "untagged #{tag}"),
+            AuditAction::DueSet(Some(due)) => write!(f, "due {due}"),
+            AuditAction::DueSet(None) => f.write_str("due date cleared"),
+            AuditAction::AttachmentAdded { file_name } => {
+                write!(f, "attached {file_name}")
+            }
+            AuditAction::Moved => f.write_str("moved"),
+            AuditAction::Deleted => f.write_str("moved to the trash"),
+            AuditAction::Restored => f.write_str("restored"),
+            AuditAction::Purged => f.write_str("purged"),
+        }
+    }
+}
+
+/// One entry of the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    pub user_id: UserId,
+    pub row_id: u64,
+    pub action: AuditAction,
+    /// The name of the API key the change was made with, if it was made
+    /// through the REST API rather than the app.
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{AuditAction, AuditEntry};
+    use crate::{
+        auth::UserId,
+        channels::{initial_seq, Sequenced},
+    };
+    use chrono::Utc;
+    use futures::{stream, Stream};
+    use std::{
+        collections::{BTreeMap, VecDeque},
+        sync::{LazyLock, Mutex},
+    };
+    use tokio::sync::broadcast;
+
+    /// How far a live subscriber may fall behind before it is cut off and
+    /// has to catch up from the log instead.
+    const LIVE_CAPACITY: usize = 256;
+
+    /// Every change made to any row since the server started.
+    pub static AUDIT: LazyLock<AuditLog> = LazyLock::new(AuditLog::new);
+
+    pub struct AuditLog {
+        inner: Mutex<Log>,
+        tx: broadcast::Sender<Sequenced<AuditEntry>>,
+    }
+
+    struct Log {
+        last_seq: u64,
+        /// `(owner, row ID)` -> that row's entries, oldest first.
+        by_row: BTreeMap<(UserId, u64), Vec<Sequenced<AuditEntry>>>,
+    }
+
+    impl AuditLog {
+        fn new() -> Self {
+            let log = Log {
+                last_seq: initial_seq(),
+                by_row: BTreeMap::new(),
+            };
+            Self {
+                inner: Mutex::new(log),
+                tx: broadcast::channel(LIVE_CAPACITY).0,
+            }
+        }
+
+        pub fn record(
+            &self,
+            user_id: UserId,
+            row_id: u64,
+            action: AuditAction,
+        ) {
+            self.push(AuditEntry {
+                at: Utc::now(),
+                user_id,
+                row_id,
+                action,
+                api_key: None,
+            });
+        }
+
+        /// Like [`record`](Self::record), for a change made through the
+        /// REST API with the key named `api_key`.
+        pub fn record_with_key(
+            &self,
+            user_id: UserId,
+            row_id: u64,
+            action: AuditAction,
+            api_key: &str,
+        ) {
+            self.push(AuditEntry {
+                at: Utc::now(),
+                user_id,
+                row_id,
+                action,
+                api_key: Some(api_key.to_string()),
+            });
+        }
+
+        fn push(&self, entry: AuditEntry) {
+            // the log and live subscribers must agree on the order, so both
+            // are updated under the lock
+            let mut log = self.inner.lock().unwrap();
+            log.last_seq += 1;
+            let item = Sequenced {
+                seq: log.last_seq,
+                value: entry,
+            };
+            log.by_row
+                .entry((item.value.user_id, item.value.row_id))
+                .or_default()
+                .push(item.clone());
+            // only fails when nobody is subscribed, which is fine
+            _ = self.tx.send(item);
+        }
+
+        /// A row's entries after `after` (or all of them), then each new one
+        /// as it is recorded. Ends if the subscriber falls too far behind,
+        /// so it can reconnect and catch up from the log.
+        pub fn subscribe_row(
+            &self,
+            user_id: UserId,
+            row_id: u64,
+            after: Option<u64>,
+        ) -> impl Stream<Item = Sequenced<AuditEntry>> + Send + 'static
+        {
+            let (rx, backlog) = {
+                let log = self.inner.lock().unwrap();
+                let rx = self.tx.subscribe();
+                let backlog = log
+                    .by_row
+                    .get(&(user_id, row_id))
+                    .into_iter()
+                    .flatten()
+                    .filter(|item| after.is_none_or(|after| item.seq > after))
+                    .cloned()
+                    .collect::<VecDeque<_>>();
+                (rx, backlog)
+            };
+            stream::unfold(
+                (rx, backlog),
+                move |(mut rx, mut backlog)| async move {
+                    if let Some(item) = backlog.pop_front() {
+                        return Some((item, (rx, backlog)));
+                    }
+                    loop {
+                        let item = rx.recv().await.ok()?;
+                        if item.value.user_id == user_id
+                            && item.value.row_id == row_id
+                        {
+                            return Some((item, (rx, backlog)));
+                        }
+                    }
+                },
+            )
+        }
+    }
+}
+
+/// A row's audit log entries, then new ones live as they are recorded.
+#[server(input = GetUrl, output = Framed)]
+pub async fn row_activity(
+    row_id: u64,
+    after: Option<u64>,
+) -> Result<FramedStream<Tick<AuditEntry>>, ServerFnError> {
+    use futures::StreamExt;
+
+    let owner = require_owner()?;
+    let ticks = with_heartbeat(
+        AUDIT.subscribe_row(owner, row_id, after),
+        HEARTBEAT_INTERVAL,
+    );
+    Ok(FramedStream::new(ticks.map(Ok)))
+}
+
+/// What has happened to a row, newest first, kept up to date while shown.
+#[component]
+pub fn ActivityTimeline(row_id: u64) -> impl IntoView {
+    let (entries, set_entries) = signal(Vec::<AuditEntry>::new());
+    let (connection, set_connection) = signal(ConnectionState::Connecting);
+
+    Effect::new(move |_| {
+        spawn_local(supervise(
+            move |after| row_activity(row_id, after),
+            set_connection,
+            move |entry| {
+                set_entries.update(|entries| entries.push(entry));
+                ControlFlow::Continue(())
+            },
+        ));
+    });
+
+    view! {
+        <ol class="timeline" reversed>
+            {move || {
+                entries
+                    .get()
+                    .into_iter()
+                    .rev()
+                    .map(|entry| {
+                        let at = entry.at.with_timezone(&Local);
+                        view! {
+                            <li>
+                                <time datetime=entry.at.to_rfc3339()>
+                                    {at.format("%Y-%m-%d %H:%M:%S").to_string()}
+                                </time>
+                                " "
+                                {entry.action.to_string()}
+                                {entry.api_key.map(|name| format!(" (with API key {name})"))}
+                            </li>
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            }}
+        </ol>
+        <p>"Activity stream: " {move || connection.get().to_string()}</p>
+    }
+}
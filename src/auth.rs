@@ -0,0 +1,553 @@
+#[cfg(feature = "ssr")]
+use crate::{errors::AuthError, totp::TOTP};
+use crate::{passkeys::PasskeyLogin, prefetch::PrefetchLink};
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// The longest user name, in characters.
+pub const MAX_USER_NAME_LEN: usize = 32;
+/// The shortest password, in characters.
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+/// Identifies a user; every row belongs to exactly one.
+pub type UserId = u64;
+
+/// What a user is allowed to do besides managing their own rows.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum Role {
+    #[default]
+    Member,
+    /// Can see the admin dashboard.
+    Admin,
+}
+
+/// Someone who has signed up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct User {
+    pub id: UserId,
+    pub name: String,
+    pub role: Role,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{Role, User, UserId, MAX_USER_NAME_LEN, MIN_PASSWORD_LEN};
+    use crate::{errors::AuthError, settings::AuthSettings};
+    use argon2::{
+        password_hash::{
+            PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+        },
+        Argon2,
+    };
+    use dashmap::{mapref::one::RefMut, DashMap};
+    use http::{
+        header::{COOKIE, SET_COOKIE},
+        request::Parts,
+        HeaderMap, HeaderValue,
+    };
+    use leptos::prelude::use_context;
+    use leptos_axum::ResponseOptions;
+    use std::{
+        collections::HashMap,
+        sync::{LazyLock, Mutex, OnceLock},
+        time::{Duration, Instant},
+    };
+
+    /// The cookie holding a signed-in browser's session token.
+    pub const SESSION_COOKIE: &str = "session";
+
+    /// The accounts of everyone who has signed up.
+    pub static USERS: LazyLock<UserStore> = LazyLock::new(UserStore::default);
+
+    /// Names that sign up as admins, from [`AuthSettings::admins`].
+    static ADMINS: OnceLock<Vec<String>> = OnceLock::new();
+
+    /// How long sessions last, from [`AuthSettings`].
+    static SESSION_TTL: OnceLock<SessionTtl> = OnceLock::new();
+
+    /// Whether the server serves HTTPS itself, so cookies can be `Secure`.
+    static TLS: OnceLock<bool> = OnceLock::new();
+
+    /// Session token -> who it is signed in as.
+    static SESSIONS: LazyLock<DashMap<String, Session>> =
+        LazyLock::new(DashMap::new);
+
+    struct Session {
+        user: User,
+        /// Whether signing in took a second factor: a TOTP or recovery
+        /// code, or a passkey.
+        two_factor: bool,
+        started: Instant,
+        last_used: Instant,
+    }
+
+    #[derive(Clone, Copy)]
+    struct SessionTtl {
+        idle: Duration,
+        max: Duration,
+    }
+
+    impl From<&AuthSettings> for SessionTtl {
+        fn from(settings: &AuthSettings) -> Self {
+            Self {
+                idle: Duration::from_secs(settings.session_idle_mins * 60),
+                max: Duration::from_secs(settings.session_max_hours * 3600),
+            }
+        }
+    }
+
+    impl Session {
+        fn live(&self, ttl: SessionTtl) -> bool {
+            self.started.elapsed() <= ttl.max
+                && self.last_used.elapsed() <= ttl.idle
+        }
+    }
+
+    fn session_ttl() -> SessionTtl {
+        SESSION_TTL
+            .get()
+            .copied()
+            .unwrap_or_else(|| SessionTtl::from(&AuthSettings::default()))
+    }
+
+    /// Applies the `[auth]` settings; call it once, before serving. `tls`
+    /// is whether the server serves HTTPS itself.
+    pub fn init(settings: &AuthSettings, tls: bool) {
+        _ = ADMINS.set(settings.admins.clone());
+        _ = SESSION_TTL.set(SessionTtl::from(settings));
+        _ = TLS.set(tls);
+    }
+
+    /// Accounts keyed by user name.
+    #[derive(Debug, Default)]
+    pub struct UserStore {
+        inner: Mutex<Accounts>,
+    }
+
+    #[derive(Debug, Default)]
+    struct Accounts {
+        last_id: UserId,
+        by_name: HashMap<String, Account>,
+    }
+
+    #[derive(Debug)]
+    struct Account {
+        user: User,
+        /// A PHC string, which carries its own salt and parameters.
+        password_hash: String,
+        /// Where reminders are emailed, if anywhere.
+        email: Option<String>,
+    }
+
+    impl UserStore {
+        /// Creates an account, failing if the name is taken.
+        pub fn register(
+            &self,
+            name: String,
+            password_hash: String,
+            email: Option<String>,
+        ) -> Result<User, AuthError> {
+            let mut accounts = self.inner.lock().unwrap();
+            if accounts.by_name.contains_key(&name) {
+                return Err(AuthError::NameTaken { name });
+            }
+            accounts.last_id += 1;
+            let admin =
+                ADMINS.get().is_some_and(|admins| admins.contains(&name));
+            let user = User {
+                id: accounts.last_id,
+                name: name.clone(),
+                role: if admin { Role::Admin } else { Role::Member },
+            };
+            accounts.by_name.insert(
+                name,
+                Account {
+                    user: user.clone(),
+                    password_hash,
+                    email,
+                },
+            );
+            Ok(user)
+        }
+
+        pub fn len(&self) -> usize {
+            self.inner.lock().unwrap().by_name.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// The user named `name`, with their password hash.
+        pub fn find(&self, name: &str) -> Option<(User, String)> {
+            let accounts = self.inner.lock().unwrap();
+            let account = accounts.by_name.get(name)?;
+            Some((account.user.clone(), account.password_hash.clone()))
+        }
+
+        /// The email address of user `id`, if they gave one.
+        pub fn email(&self, id: UserId) -> Option<String> {
+            let accounts = self.inner.lock().unwrap();
+            accounts
+                .by_name
+                .values()
+                .find(|account| account.user.id == id)?
+                .email
+                .clone()
+        }
+    }
+
+    pub fn validate_name(name: &str) -> Result<(), AuthError> {
+        let valid = (1..=MAX_USER_NAME_LEN).contains(&name.chars().count())
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        if valid {
+            Ok(())
+        } else {
+            Err(AuthError::InvalidName {
+                max: MAX_USER_NAME_LEN,
+            })
+        }
+    }
+
+    /// Only checks the overall shape; whether mail arrives is up to the
+    /// user.
+    pub fn validate_email(email: &str) -> Result<(), AuthError> {
+        let valid = email.split_once('@').is_some_and(|(local, domain)| {
+            !local.is_empty() && domain.contains('.')
+        }) && !email.contains(char::is_whitespace);
+        if valid {
+            Ok(())
+        } else {
+            Err(AuthError::InvalidEmail {
+                email: email.to_string(),
+            })
+        }
+    }
+
+    /// Hashes a new password with Argon2. Slow on purpose, so call it off
+    /// the async runtime.
+    pub fn hash_password(password: &str) -> Result<String, AuthError> {
+        if password.chars().count() < MIN_PASSWORD_LEN {
+            return Err(AuthError::PasswordTooShort {
+                min: MIN_PASSWORD_LEN,
+            });
+        }
+        let hashing =
+            |e: argon2::password_hash::Error| AuthError::Hashing(e.to_string());
+        let salt = SaltString::encode_b64(uuid::Uuid::new_v4().as_bytes())
+            .map_err(hashing)?;
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(hashing)
+    }
+
+    /// Whether `password` is the one `hash` was made from. Slow on purpose,
+    /// like [`hash_password`].
+    pub fn verify_password(password: &str, hash: &str) -> bool {
+        PasswordHash::new(hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    }
+
+    /// The value of the cookie called `name` in request `headers`.
+    pub(crate) fn cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+        headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .find_map(|cookie| {
+                cookie.trim().strip_prefix(name)?.strip_prefix('=')
+            })
+            .map(str::to_string)
+    }
+
+    /// The session cookie's token in request `headers`, whether or not it's
+    /// still signed in.
+    pub(crate) fn session_token(headers: &HeaderMap) -> Option<String> {
+        cookie(headers, SESSION_COOKIE)
+    }
+
+    /// Signs the browser making the current request in as `user`, who
+    /// used a second factor if `two_factor`. Also forgets sessions that
+    /// ran out.
+    pub fn start_session(user: User, two_factor: bool) {
+        let ttl = session_ttl();
+        SESSIONS.retain(|_, session| session.live(ttl));
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        set_cookie(&format!(
+            "{SESSION_COOKIE}={token}; Max-Age={}; {}",
+            ttl.max.as_secs(),
+            session_cookie_attributes(),
+        ));
+        let now = Instant::now();
+        SESSIONS.insert(
+            token,
+            Session {
+                user,
+                two_factor,
+                started: now,
+                last_used: now,
+            },
+        );
+    }
+
+    /// `Secure` only over HTTPS, served by this server or by a proxy in
+    /// front of it, since browsers drop `Secure` cookies sent over HTTP.
+    fn session_cookie_attributes() -> &'static str {
+        let https = TLS.get().copied().unwrap_or(false)
+            || use_context::<Parts>().is_some_and(|parts| {
+                parts
+                    .headers
+                    .get("x-forwarded-proto")
+                    .is_some_and(|proto| proto == "https")
+            });
+        if https {
+            "Path=/; HttpOnly; SameSite=Lax; Secure"
+        } else {
+            "Path=/; HttpOnly; SameSite=Lax"
+        }
+    }
+
+    /// The session in request `headers`, if it hasn't run out, which now
+    /// counts as used.
+    fn live_session(
+        headers: &HeaderMap,
+    ) -> Option<RefMut<'static, String, Session>> {
+        let token = session_token(headers)?;
+        let ttl = session_ttl();
+        SESSIONS.remove_if(&token, |_, session| !session.live(ttl));
+        let mut session = SESSIONS.get_mut(&token)?;
+        session.last_used = Instant::now();
+        Some(session)
+    }
+
+    /// Notes that the current request's session has just used a second
+    /// factor after all.
+    pub fn confirm_two_factor() {
+        if let Some(mut session) = use_context::<Parts>()
+            .and_then(|parts| live_session(&parts.headers))
+        {
+            session.two_factor = true;
+        }
+    }
+
+    /// Whether the session in request `headers` is an admin's that didn't
+    /// use a second factor.
+    pub fn admin_without_two_factor(headers: &HeaderMap) -> bool {
+        live_session(headers).is_some_and(|session| {
+            session.user.role == Role::Admin && !session.two_factor
+        })
+    }
+
+    /// Signs the browser making the current request out, if it was signed
+    /// in.
+    pub fn end_session() {
+        if let Some(token) = use_context::<Parts>()
+            .and_then(|parts| session_token(&parts.headers))
+        {
+            SESSIONS.remove(&token);
+        }
+        set_cookie(&format!(
+            "{SESSION_COOKIE}=; Max-Age=0; {}",
+            session_cookie_attributes(),
+        ));
+    }
+
+    pub(crate) fn set_cookie(cookie: &str) {
+        if let (Some(response), Ok(cookie)) = (
+            use_context::<ResponseOptions>(),
+            HeaderValue::from_str(cookie),
+        ) {
+            response.append_header(SET_COOKIE, cookie);
+        }
+    }
+
+    /// The user the current request is signed in as, if any.
+    pub fn current_user() -> Option<User> {
+        session_user(&use_context::<Parts>()?.headers)
+    }
+
+    /// The user a request with `headers` is signed in as, for handlers
+    /// outside Leptos, where [`current_user`] has no request to look at.
+    pub fn session_user(headers: &HeaderMap) -> Option<User> {
+        live_session(headers).map(|session| session.user.clone())
+    }
+
+    /// Like [`current_user`], but an error for anonymous requests, which
+    /// are never allowed to change anything.
+    pub fn require_user() -> Result<User, AuthError> {
+        current_user().ok_or(AuthError::NotSignedIn)
+    }
+
+    /// Like [`require_user`], but also an error for anyone but admins.
+    pub fn require_admin() -> Result<User, AuthError> {
+        let user = require_user()?;
+        if user.role == Role::Admin {
+            Ok(user)
+        } else {
+            Err(AuthError::Forbidden)
+        }
+    }
+}
+
+#[server]
+pub async fn get_current_user() -> Result<Option<User>, ServerFnError> {
+    Ok(current_user())
+}
+
+/// Creates an account and signs in with it. Reminders are emailed to
+/// `email`, if it isn't empty.
+#[server]
+pub async fn sign_up(
+    name: String,
+    password: String,
+    #[server(default)] email: Option<String>,
+) -> Result<User, ServerFnError> {
+    let name = name.trim().to_string();
+    validate_name(&name)?;
+    let email = email
+        .map(|email| email.trim().to_string())
+        .filter(|email| !email.is_empty());
+    if let Some(email) = &email {
+        validate_email(email)?;
+    }
+    let hash =
+        tokio::task::spawn_blocking(move || hash_password(&password)).await??;
+    let user = USERS.register(name, hash, email)?;
+    start_session(user.clone(), false);
+    Ok(user)
+}
+
+/// Signs in with a password, and with `code` from an authenticator app or
+/// a recovery code if two-factor authentication is on.
+#[server]
+pub async fn sign_in(
+    name: String,
+    password: String,
+    #[server(default)] code: Option<String>,
+) -> Result<User, ServerFnError> {
+    let (user, hash) = USERS
+        .find(name.trim())
+        .ok_or(AuthError::InvalidCredentials)?;
+    let valid =
+        tokio::task::spawn_blocking(move || verify_password(&password, &hash))
+            .await?;
+    if !valid {
+        return Err(AuthError::InvalidCredentials.into());
+    }
+    let two_factor = TOTP.is_enabled(user.id);
+    if two_factor {
+        let code = code.filter(|code| !code.trim().is_empty());
+        let code = code.ok_or(AuthError::TwoFactorRequired)?;
+        TOTP.verify(user.id, &code)?;
+    }
+    start_session(user.clone(), two_factor);
+    Ok(user)
+}
+
+#[server]
+pub async fn sign_out() -> Result<(), ServerFnError> {
+    end_session();
+    Ok(())
+}
+
+/// Who is signed in, with forms to sign up, in or out.
+#[component]
+pub fn Account() -> impl IntoView {
+    let sign_up = ServerAction::<SignUp>::new();
+    let sign_in = ServerAction::<SignIn>::new();
+    let sign_out = ServerAction::<SignOut>::new();
+    let user = Resource::new(|| (), |_| get_current_user());
+
+    // everything else on the page belongs to whoever was signed in, so
+    // start over rather than refetching it piece by piece
+    Effect::new(move |_| {
+        let changed = sign_up.value().with(|v| matches!(v, Some(Ok(_))))
+            || sign_in.value().with(|v| matches!(v, Some(Ok(_))))
+            || sign_out.value().with(|v| matches!(v, Some(Ok(_))));
+        if changed {
+            _ = window().location().reload();
+        }
+    });
+    let error = move || {
+        sign_up
+            .value()
+            .get()
+            .and_then(Result::err)
+            .or_else(|| sign_in.value().get().and_then(Result::err))
+            .or_else(|| sign_out.value().get().and_then(Result::err))
+            .map(|e| view! { <p>{e.to_string()}</p> })
+    };
+
+    view! {
+        <Suspense>
+            {move || Suspend::new(async move {
+                match user.await {
+                    Ok(Some(user)) => {
+                        let admin = (user.role == Role::Admin)
+                            .then(|| view! { <PrefetchLink href="/admin">"Admin"</PrefetchLink> " " });
+                        view! {
+                            <ActionForm action=sign_out>
+                                "Signed in as " <strong>{user.name}</strong> " "
+                                <PrefetchLink href="/trash">"Trash"</PrefetchLink> " "
+                                <PrefetchLink href="/api-keys">"API keys"</PrefetchLink> " "
+                                <PrefetchLink href="/profile">"Profile"</PrefetchLink> " " {admin}
+                                <input type="submit" value="Sign out" />
+                            </ActionForm>
+                        }
+                            .into_any()
+                    }
+                    _ => {
+                        view! {
+                            <ActionForm action=sign_in>
+                                <input name="name" placeholder="Name" required />
+                                <input
+                                    type="password"
+                                    name="password"
+                                    placeholder="Password"
+                                    required
+                                />
+                                <input
+                                    name="code"
+                                    placeholder="2FA code, if on"
+                                    autocomplete="one-time-code"
+                                />
+                                <input type="submit" value="Sign in" />
+                            </ActionForm>
+                            <PasskeyLogin />
+                            <ActionForm action=sign_up>
+                                <input name="name" placeholder="Name" required />
+                                <input
+                                    type="password"
+                                    name="password"
+                                    placeholder="Password"
+                                    minlength=MIN_PASSWORD_LEN
+                                    required
+                                />
+                                <input
+                                    type="email"
+                                    name="email"
+                                    placeholder="Email for reminders (optional)"
+                                />
+                                <input type="submit" value="Sign up" />
+                            </ActionForm>
+                        }
+                            .into_any()
+                    }
+                }
+            })}
+        </Suspense>
+        {error}
+    }
+}
@@ -0,0 +1,51 @@
+use leptos::prelude::*;
+
+/// Name of the `<meta>` tag the shell uses to hand the base path to the client.
+pub const BASE_PATH_META: &str = "base-path";
+
+/// The URL prefix the app is mounted under when served behind a reverse
+/// proxy (e.g. `/demo`), or empty when the app lives at the origin root.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BasePath(String);
+
+impl BasePath {
+    /// Normalizes `raw` to either an empty string or `/segment[/segment...]`
+    /// without a trailing slash, so it can be prepended to absolute paths.
+    pub fn new(raw: &str) -> Self {
+        let trimmed = raw.trim().trim_matches('/');
+        if trimmed.is_empty() {
+            Self(String::new())
+        } else {
+            Self(format!("/{trimmed}"))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Prefixes an absolute path such as `/pkg/app.css` with the base path.
+    pub fn join(&self, path: &str) -> String {
+        format!("{}{path}", self.0)
+    }
+
+    /// Reads the base path rendered into the shell by the server.
+    #[cfg(feature = "hydrate")]
+    pub fn from_document() -> Self {
+        let raw = document()
+            .query_selector(&format!("meta[name={BASE_PATH_META}]"))
+            .ok()
+            .flatten()
+            .and_then(|meta| meta.get_attribute("content"))
+            .unwrap_or_default();
+        Self::new(&raw)
+    }
+}
+
+pub fn use_base_path() -> BasePath {
+    use_context::<BasePath>().unwrap_or_default()
+}
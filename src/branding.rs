@@ -0,0 +1,274 @@
+//! Each tenant's title, logo and colors, which admins set from the admin
+//! page and the server renders into every page's shell.
+//!
+//! The colors go on `<html>` as CSS variables the style sheet reads, and
+//! the title and accent color into `<meta>` tags. The shell also hands the
+//! whole branding to the client in a `<meta>` tag, the way it does the base
+//! path, so hydrating renders the same header. Branding is only kept in
+//! memory, and pre-rendered static pages always have the default one.
+
+#[cfg(feature = "ssr")]
+use crate::{errors::AppError, tenants::current_tenant};
+use crate::{errors::BrandingError, seo::SITE_NAME};
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Name of the `<meta>` tag the shell uses to hand the branding to the
+/// client.
+pub const BRANDING_META: &str = "branding";
+
+/// The longest title a tenant may have.
+pub const MAX_TITLE_LEN: usize = 60;
+
+/// How a tenant's pages look.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Branding {
+    /// Shown in the header and at the end of every page's title.
+    pub title: String,
+    /// An image shown before the title: a path on this site, since the
+    /// content security policy only lets pages load images from it.
+    pub logo_url: Option<String>,
+    /// `#rrggbb` for the title, links and the browser's own UI.
+    pub accent_color: String,
+    /// `#rrggbb` behind the header, or `None` for the page's background.
+    pub header_color: Option<String>,
+}
+
+impl Default for Branding {
+    fn default() -> Self {
+        Self {
+            title: SITE_NAME.to_string(),
+            logo_url: None,
+            accent_color: "#3b5bdb".to_string(),
+            header_color: None,
+        }
+    }
+}
+
+impl Branding {
+    /// The CSS variables the style sheet takes the colors from, for a
+    /// `style` attribute.
+    pub fn css_variables(&self) -> String {
+        format!(
+            "--brand-accent: {}; --brand-header: {}",
+            self.accent_color,
+            self.header_color.as_deref().unwrap_or("transparent"),
+        )
+    }
+
+    /// Checks what an admin asked for. Colors are checked strictly since
+    /// they end up in a `style` attribute.
+    pub fn check(&self) -> Result<(), BrandingError> {
+        let title = self.title.trim();
+        if title.is_empty() {
+            return Err(BrandingError::EmptyTitle);
+        }
+        if title.chars().count() > MAX_TITLE_LEN {
+            return Err(BrandingError::TitleTooLong { max: MAX_TITLE_LEN });
+        }
+        let colors = [Some(&self.accent_color), self.header_color.as_ref()];
+        if let Some(color) = colors
+            .into_iter()
+            .flatten()
+            .find(|color| !is_hex_color(color))
+        {
+            return Err(BrandingError::InvalidColor {
+                value: color.clone(),
+            });
+        }
+        // browsers read `/\` as `//`, the start of another origin
+        let logo_ok = self.logo_url.as_deref().is_none_or(|url| {
+            url.starts_with('/')
+                && !url.starts_with("//")
+                && !url.starts_with("/\\")
+        });
+        if !logo_ok {
+            return Err(BrandingError::InvalidLogoUrl);
+        }
+        Ok(())
+    }
+
+    /// Reads the branding rendered into the shell by the server.
+    #[cfg(feature = "hydrate")]
+    pub fn from_document() -> Self {
+        document()
+            .query_selector(&format!("meta[name={BRANDING_META}]"))
+            .ok()
+            .flatten()
+            .and_then(|meta| meta.get_attribute("content"))
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Whether `color` is `#rrggbb`.
+fn is_hex_color(color: &str) -> bool {
+    color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+pub fn use_branding() -> Branding {
+    use_context::<Branding>().unwrap_or_default()
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::Branding;
+    use crate::tenants::current_tenant;
+    use dashmap::DashMap;
+    use std::sync::LazyLock;
+
+    /// Tenant name (`None` for the default tenant) -> its branding, for the
+    /// tenants whose admins changed it.
+    static BRANDING: LazyLock<DashMap<Option<String>, Branding>> =
+        LazyLock::new(DashMap::new);
+
+    /// The current request's tenant's branding.
+    pub fn current_branding() -> Branding {
+        BRANDING
+            .get(&current_tenant().name)
+            .map(|branding| branding.clone())
+            .unwrap_or_default()
+    }
+
+    /// Changes the current request's tenant's branding, which must have
+    /// passed [`Branding::check`].
+    pub fn set_current_branding(branding: Branding) {
+        BRANDING.insert(current_tenant().name, branding);
+    }
+}
+
+/// Changes the branding of the tenant the request is in, and no other: the
+/// admin role is shared by every tenant. Pages show it from their next load.
+#[server]
+#[middleware(crate::middleware::TwoFactorLayer)]
+pub async fn set_branding(mut branding: Branding) -> Result<(), AppError> {
+    crate::auth::require_admin()?;
+    branding.title = branding.title.trim().to_string();
+    branding.check()?;
+    set_current_branding(branding);
+    tracing::info!(tenant = ?current_tenant().name, "branding changed");
+    Ok(())
+}
+
+/// The header's title, with the logo before it.
+#[component]
+pub fn BrandTitle(branding: Branding) -> impl IntoView {
+    view! {
+        <h1 class="brand-title">
+            {branding.logo_url.map(|src| view! { <img class="brand-logo" src=src alt="" /> })}
+            {branding.title}
+        </h1>
+    }
+}
+
+/// A form to change the tenant's branding, with a preview of the header
+/// that follows every change before it's saved.
+#[component]
+pub fn BrandingEditor() -> impl IntoView {
+    let draft = RwSignal::new(use_branding());
+    let save = ServerAction::<SetBranding>::new();
+    let check = move || draft.with(Branding::check);
+
+    view! {
+        <h3>"Branding"</h3>
+        <p>
+            <label>
+                "Title "
+                <input
+                    maxlength=MAX_TITLE_LEN.to_string()
+                    prop:value=move || draft.with(|draft| draft.title.clone())
+                    on:input=move |ev| draft.update(|draft| draft.title = event_target_value(&ev))
+                />
+            </label>
+            " "
+            <label>
+                "Logo path "
+                <input
+                    placeholder="none, or a path like /logo.svg"
+                    prop:value=move || draft.with(|draft| draft.logo_url.clone().unwrap_or_default())
+                    on:input=move |ev| {
+                        let url = event_target_value(&ev);
+                        draft.update(|draft| draft.logo_url = (!url.is_empty()).then_some(url));
+                    }
+                />
+            </label>
+        </p>
+        <p>
+            <label>
+                "Accent "
+                {color_input(
+                    draft,
+                    |draft| Some(&draft.accent_color),
+                    |draft, color| draft.accent_color = color,
+                )}
+            </label>
+            " "
+            <label>
+                <input
+                    type="checkbox"
+                    prop:checked=move || draft.with(|draft| draft.header_color.is_some())
+                    on:change=move |ev| {
+                        let colored = event_target_checked(&ev);
+                        draft.update(|draft| {
+                            draft.header_color = colored.then(|| "#e7f5ff".to_string());
+                        });
+                    }
+                />
+                " Header "
+                {color_input(
+                    draft,
+                    |draft| draft.header_color.as_ref(),
+                    |draft, color| draft.header_color = Some(color),
+                )}
+            </label>
+        </p>
+        <header class="brand-preview" style=move || draft.with(Branding::css_variables)>
+            {move || view! { <BrandTitle branding=draft.get() /> }}
+        </header>
+        <p>
+            <button
+                on:click=move |_| {
+                    save.dispatch(SetBranding { branding: draft.get_untracked() });
+                }
+                disabled=move || save.pending().get() || check().is_err()
+            >
+                "Save"
+            </button>
+            " "
+            {move || {
+                check()
+                    .err()
+                    .map(|e| e.to_string())
+                    .or_else(|| {
+                        save.value()
+                            .get()
+                            .map(|result| match result {
+                                Ok(()) => "Saved; pages show it from their next load.".to_string(),
+                                Err(e) => e.to_string(),
+                            })
+                    })
+            }}
+        </p>
+    }
+}
+
+/// A color picker for the color of `draft` that `get` reads and `set`
+/// changes.
+fn color_input(
+    draft: RwSignal<Branding>,
+    get: fn(&Branding) -> Option<&String>,
+    set: fn(&mut Branding, String),
+) -> impl IntoView {
+    view! {
+        <input
+            type="color"
+            prop:value=move || draft.with(|draft| get(draft).cloned().unwrap_or_default())
+            on:input=move |ev| draft.update(|draft| set(draft, event_target_value(&ev)))
+        />
+    }
+}
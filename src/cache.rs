@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+
+/// How well [`CACHE`] has been doing since the server started.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Misses that waited for an identical computation already in flight
+    /// rather than starting their own.
+    pub coalesced: u64,
+    pub entries: usize,
+}
+
+impl CacheStats {
+    /// The share of lookups answered from the cache, from 0 to 1.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::CacheStats;
+    use crate::{auth::UserId, singleflight::SingleFlight};
+    use leptos::prelude::ServerFnError;
+    use serde::Serialize;
+    use std::{
+        any::Any,
+        collections::{HashMap, HashSet},
+        future::Future,
+        sync::{Arc, LazyLock, Mutex},
+    };
+
+    /// Past this many entries, new results are returned without being
+    /// cached until an invalidation makes room.
+    const MAX_ENTRIES: usize = 10_000;
+
+    /// Server fn results shared by all requests.
+    pub static CACHE: LazyLock<ResponseCache> =
+        LazyLock::new(ResponseCache::default);
+
+    /// What a cached result was computed from, so it can be dropped when
+    /// that changes.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum CacheTag {
+        /// Anything read from one user's rows.
+        Rows(UserId),
+    }
+
+    /// The cache key for server fn `name` called with `args`, which should
+    /// include whatever else the result depends on, like the user.
+    pub fn key(name: &str, args: &impl Serialize) -> String {
+        format!("{name}:{}", serde_json::to_string(args).unwrap_or_default())
+    }
+
+    /// Results keyed by server fn and arguments, each with the tags it was
+    /// computed from.
+    #[derive(Default)]
+    pub struct ResponseCache {
+        inner: Mutex<Entries>,
+        in_flight: SingleFlight,
+    }
+
+    #[derive(Default)]
+    struct Entries {
+        by_key: HashMap<String, Entry>,
+        /// Tag -> keys of the entries that have it.
+        tagged: HashMap<CacheTag, HashSet<String>>,
+        /// Tag -> how many times it has been invalidated.
+        generations: HashMap<CacheTag, u64>,
+        hits: u64,
+        misses: u64,
+    }
+
+    struct Entry {
+        value: Arc<dyn Any + Send + Sync>,
+        tags: Vec<CacheTag>,
+    }
+
+    impl Entries {
+        fn generations(&self, tags: &[CacheTag]) -> Vec<u64> {
+            tags.iter()
+                .map(|tag| self.generations.get(tag).copied().unwrap_or(0))
+                .collect()
+        }
+
+        fn remove(&mut self, key: &str) {
+            let Some(entry) = self.by_key.remove(key) else {
+                return;
+            };
+            for tag in entry.tags {
+                if let Some(keys) = self.tagged.get_mut(&tag) {
+                    keys.remove(key);
+                    if keys.is_empty() {
+                        self.tagged.remove(&tag);
+                    }
+                }
+            }
+        }
+    }
+
+    impl ResponseCache {
+        /// The result cached under `key`, or else the result of `compute`,
+        /// which is cached under `key` with `tags` if it succeeds. Errors are
+        /// never cached.
+        ///
+        /// Concurrent misses for the same key share one run of `compute`,
+        /// as long as none of `tags` was invalidated in between, so it must
+        /// not depend on the caller's request; see [`SingleFlight::run`].
+        pub async fn get_or_compute<T, F>(
+            &self,
+            key: String,
+            tags: &[CacheTag],
+            compute: F,
+        ) -> Result<T, ServerFnError>
+        where
+            T: Clone + Send + Sync + 'static,
+            F: Future<Output = Result<T, ServerFnError>> + Send + 'static,
+        {
+            let generations = {
+                let mut entries = self.inner.lock().unwrap();
+                let hit = entries
+                    .by_key
+                    .get(&key)
+                    .and_then(|entry| entry.value.downcast_ref::<T>())
+                    .cloned();
+                if let Some(value) = hit {
+                    entries.hits += 1;
+                    return Ok(value);
+                }
+                entries.misses += 1;
+                entries.generations(tags)
+            };
+
+            // a caller arriving after a write must not join a computation
+            // that started before it
+            let flight = format!("{key}@{generations:?}");
+            let value = self.in_flight.run(flight, compute).await?;
+
+            let mut entries = self.inner.lock().unwrap();
+            // a write while computing may have made the result stale already
+            let fresh = entries.generations(tags) == generations;
+            if fresh && entries.by_key.len() < MAX_ENTRIES {
+                for tag in tags {
+                    entries.tagged.entry(*tag).or_default().insert(key.clone());
+                }
+                entries.by_key.insert(
+                    key,
+                    Entry {
+                        value: Arc::new(value.clone()),
+                        tags: tags.to_vec(),
+                    },
+                );
+            }
+            Ok(value)
+        }
+
+        /// Drops every result computed from `tag`; call it after changing
+        /// what `tag` stands for.
+        pub fn invalidate(&self, tag: CacheTag) {
+            let mut entries = self.inner.lock().unwrap();
+            *entries.generations.entry(tag).or_default() += 1;
+            for key in entries.tagged.remove(&tag).unwrap_or_default() {
+                entries.remove(&key);
+            }
+        }
+
+        pub fn stats(&self) -> CacheStats {
+            let entries = self.inner.lock().unwrap();
+            CacheStats {
+                hits: entries.hits,
+                misses: entries.misses,
+                coalesced: self.in_flight.coalesced(),
+                entries: entries.by_key.len(),
+            }
+        }
+    }
+}
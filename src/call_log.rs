@@ -0,0 +1,330 @@
+use crate::{
+    api_keys::CreateApiKey,
+    auth::{SignIn, SignUp},
+    errors::CallLogError,
+    jwt::CreateToken,
+    middleware::is_server_fn_path,
+    passkeys::{
+        FinishPasskeyLogin, FinishPasskeyRegistration, StartPasskeyLogin,
+        StartPasskeyRegistration,
+    },
+    settings::CallLogSettings,
+    totp::{ConfirmTotpEnrollment, DisableTotp, StartTotpEnrollment},
+};
+use axum::{
+    body::{Body, HttpBody},
+    extract::Request,
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use server_fn::ServerFn;
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+use tokio::task::JoinSet;
+
+/// Headers that aren't recorded: credentials, which shouldn't end up on
+/// disk, and ones the client sets for itself when the call is replayed.
+const SKIPPED_HEADERS: [header::HeaderName; 7] = [
+    header::COOKIE,
+    header::AUTHORIZATION,
+    header::HOST,
+    header::CONTENT_LENGTH,
+    header::CONNECTION,
+    header::TRANSFER_ENCODING,
+    header::ACCEPT_ENCODING,
+];
+
+/// Server fns whose bodies aren't recorded, since they carry passwords,
+/// codes, keys or tokens going one way or the other.
+const SECRET_PATHS: [&str; 11] = [
+    SignUp::PATH,
+    SignIn::PATH,
+    StartTotpEnrollment::PATH,
+    ConfirmTotpEnrollment::PATH,
+    DisableTotp::PATH,
+    StartPasskeyRegistration::PATH,
+    FinishPasskeyRegistration::PATH,
+    StartPasskeyLogin::PATH,
+    FinishPasskeyLogin::PATH,
+    CreateApiKey::PATH,
+    CreateToken::PATH,
+];
+
+/// One server fn call, as recorded by [`record`] and re-issued by
+/// [`replay`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedCall {
+    pub at: DateTime<Utc>,
+    pub method: String,
+    /// The path and query.
+    pub uri: String,
+    pub headers: Vec<(String, String)>,
+    /// Base64; `None` if it was too large or too secret to record.
+    pub request_body: Option<String>,
+    pub status: u16,
+    /// Base64; `None` if it was streamed, or too large or too secret to
+    /// record.
+    pub response_body: Option<String>,
+    pub elapsed_ms: f64,
+}
+
+struct CallLog {
+    file: Mutex<File>,
+    max_body_bytes: usize,
+}
+
+static CALL_LOG: OnceLock<CallLog> = OnceLock::new();
+
+/// Opens (or appends to) the `[call_log]` file; call it once, before
+/// serving. Until it is called, [`record`] passes every call through.
+pub fn init(settings: &CallLogSettings) -> Result<(), CallLogError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&settings.path)
+        .map_err(|e| io_error(&settings.path, e))?;
+    _ = CALL_LOG.set(CallLog {
+        file: Mutex::new(file),
+        max_body_bytes: settings.max_body_bytes,
+    });
+    Ok(())
+}
+
+/// Middleware that appends every server fn call to the call log, one JSON
+/// line each. Bodies are buffered to be recorded, except streamed
+/// responses, which are passed through with only their status recorded,
+/// and both bodies of the server fns in [`SECRET_PATHS`].
+pub async fn record(req: Request, next: Next) -> Response {
+    let Some(log) = CALL_LOG.get() else {
+        return next.run(req).await;
+    };
+    // a websocket can't be replayed as a plain request
+    if !is_server_fn_path(req.uri().path())
+        || req.headers().contains_key(header::UPGRADE)
+    {
+        return next.run(req).await;
+    }
+    let at = Utc::now();
+    let started = Instant::now();
+    let secret = SECRET_PATHS.contains(&req.uri().path());
+
+    let (parts, body) = req.into_parts();
+    let fits = !secret
+        && parts
+            .headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok()?.parse::<usize>().ok())
+            .is_some_and(|len| len <= log.max_body_bytes);
+    let (request_body, body) = if fits {
+        match axum::body::to_bytes(body, log.max_body_bytes).await {
+            Ok(bytes) => (Some(STANDARD.encode(&bytes)), Body::from(bytes)),
+            Err(e) => {
+                return (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+            }
+        }
+    } else {
+        (None, body)
+    };
+    let mut call = RecordedCall {
+        at,
+        method: parts.method.to_string(),
+        uri: parts.uri.to_string(),
+        headers: recorded_headers(&parts.headers),
+        request_body,
+        status: 0,
+        response_body: None,
+        elapsed_ms: 0.0,
+    };
+
+    let res = next.run(Request::from_parts(parts, body)).await;
+    let (parts, body) = res.into_parts();
+    let buffered = !secret
+        && body
+            .size_hint()
+            .exact()
+            .is_some_and(|len| len <= log.max_body_bytes as u64);
+    let body = if buffered {
+        match axum::body::to_bytes(body, log.max_body_bytes).await {
+            Ok(bytes) => {
+                call.response_body = Some(STANDARD.encode(&bytes));
+                Body::from(bytes)
+            }
+            Err(e) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+                    .into_response()
+            }
+        }
+    } else {
+        body
+    };
+    call.status = parts.status.as_u16();
+    call.elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+    log.append(&call);
+    Response::from_parts(parts, body)
+}
+
+impl CallLog {
+    fn append(&self, call: &RecordedCall) {
+        let Ok(mut line) = serde_json::to_vec(call) else {
+            return;
+        };
+        line.push(b'\n');
+        if let Err(e) = self.file.lock().unwrap().write_all(&line) {
+            tracing::warn!("couldn't write to the call log: {e}");
+        }
+    }
+}
+
+fn recorded_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter(|(name, _)| !SKIPPED_HEADERS.contains(name))
+        .filter_map(|(name, value)| {
+            Some((name.to_string(), value.to_str().ok()?.to_string()))
+        })
+        .collect()
+}
+
+/// The calls recorded in the call log at `path`, oldest first.
+pub fn read(path: &Path) -> Result<Vec<RecordedCall>, CallLogError> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| CallLogError::InvalidLine {
+                line: i + 1,
+                message: e.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Re-issues `calls` against the instance at `target` (e.g.
+/// `http://127.0.0.1:3000`), printing how each one's status and body
+/// compare to the recording. Returns how many didn't match.
+///
+/// With `realtime`, calls are sent concurrently at the same offsets from
+/// the first one as when they were recorded, so races between them can
+/// play out again; otherwise one after another.
+pub async fn replay(
+    calls: Vec<RecordedCall>,
+    target: &str,
+    realtime: bool,
+) -> Result<usize, CallLogError> {
+    let client = reqwest::Client::new();
+    let target = target.trim_end_matches('/').to_string();
+    let Some(start) = calls.first().map(|call| call.at) else {
+        return Ok(0);
+    };
+
+    let mut mismatches = 0;
+    if realtime {
+        let mut replays = JoinSet::new();
+        for call in calls {
+            let offset = (call.at - start).to_std().unwrap_or_default();
+            let (client, target) = (client.clone(), target.clone());
+            replays.spawn(async move {
+                tokio::time::sleep(offset).await;
+                reissue(&client, &target, call).await
+            });
+        }
+        while let Some(replayed) = replays.join_next().await {
+            let replayed = replayed.map_err(|e| CallLogError::Request {
+                target: target.clone(),
+                message: e.to_string(),
+            })?;
+            mismatches += usize::from(!replayed?);
+        }
+    } else {
+        for call in calls {
+            mismatches += usize::from(!reissue(&client, &target, call).await?);
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Sends one call again, and prints whether it got the recorded reply.
+async fn reissue(
+    client: &reqwest::Client,
+    target: &str,
+    call: RecordedCall,
+) -> Result<bool, CallLogError> {
+    let request_error = |e: reqwest::Error| CallLogError::Request {
+        target: target.to_string(),
+        message: e.to_string(),
+    };
+    let method = reqwest::Method::from_bytes(call.method.as_bytes())
+        .map_err(|e| invalid(&call, e))?;
+    let mut req = client.request(method, format!("{target}{}", call.uri));
+    for (name, value) in &call.headers {
+        req = req.header(name, value);
+    }
+    if let Some(body) = &call.request_body {
+        req = req.body(STANDARD.decode(body).map_err(|e| invalid(&call, e))?);
+    }
+    let started = Instant::now();
+    let res = req.send().await.map_err(request_error)?;
+    let status = res.status().as_u16();
+    // a streamed response might never end, so only recorded bodies are read
+    let body_matches = match &call.response_body {
+        Some(recorded) => {
+            let body = res.bytes().await.map_err(request_error)?;
+            Some(STANDARD.encode(&body) == *recorded)
+        }
+        None => None,
+    };
+    let elapsed = started.elapsed();
+
+    let same_status = status == call.status;
+    println!(
+        "{} {} {} (recorded {}), {:.0} ms (recorded {:.0} ms){}",
+        call.method,
+        call.uri,
+        status,
+        call.status,
+        elapsed.as_secs_f64() * 1000.0,
+        call.elapsed_ms,
+        match (same_status, body_matches) {
+            (false, _) => " MISMATCH",
+            (true, Some(false)) => " MISMATCH: different body",
+            (true, _) => "",
+        }
+    );
+    Ok(same_status && body_matches != Some(false))
+}
+
+/// How long a replay in real time takes, from the first call to the last.
+pub fn span(calls: &[RecordedCall]) -> Duration {
+    match (calls.first(), calls.last()) {
+        (Some(first), Some(last)) => {
+            (last.at - first.at).to_std().unwrap_or_default()
+        }
+        _ => Duration::ZERO,
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> CallLogError {
+    CallLogError::Io {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    }
+}
+
+fn invalid(call: &RecordedCall, e: impl std::fmt::Display) -> CallLogError {
+    CallLogError::InvalidCall {
+        uri: call.uri.clone(),
+        message: e.to_string(),
+    }
+}
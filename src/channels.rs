@@ -0,0 +1,490 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How often a long-lived stream sends [`Tick::Heartbeat`] while idle.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often a client pings a subscription socket.
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A subscription socket is given up on, at either end, once nothing has
+/// come over it for this long.
+pub const IDLE_TIMEOUT: Duration =
+    Duration::from_secs(KEEPALIVE_INTERVAL.as_secs() * 5 / 2);
+
+/// How long a long poll waits for something to happen before answering
+/// empty-handed, well within what proxies let a request take.
+pub const LONG_POLL_WAIT: Duration = Duration::from_secs(20);
+
+/// An item together with its position in the channel, which a reconnecting
+/// subscriber sends back as its cursor to resume after it.
+///
+/// Positions only ever go up, even across restarts, so a subscriber can
+/// drop anything at or before the last position it applied as a repeat.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sequenced<T> {
+    pub seq: u64,
+    pub value: T,
+}
+
+/// What a long poll answers with: the items after the cursor it was given,
+/// and the cursor to send with the next poll, which moves on even past
+/// items the caller doesn't get to see.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Polled<T> {
+    pub items: Vec<Sequenced<T>>,
+    pub next: u64,
+}
+
+/// A frame of a supervised stream: either an item, or proof of life while
+/// the channel is quiet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Tick<T> {
+    Event(Sequenced<T>),
+    Heartbeat,
+}
+
+/// What a client sends up a subscription socket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SocketRequest {
+    /// Where to start: after `after`, the sequence number of the last event
+    /// seen, or with new events only. Only the first one counts.
+    Resume { after: Option<u64> },
+    /// Answered with [`SocketMessage::Pong`].
+    Ping,
+}
+
+/// What a subscription socket sends back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SocketMessage<T> {
+    Event(Sequenced<T>),
+    Pong,
+}
+
+/// What a channel does when a subscriber falls behind the sender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OverflowPolicy {
+    /// Only the newest value is kept; a slow subscriber skips straight to it.
+    CoalesceToLatest,
+    /// A bounded buffer; a slow subscriber loses the oldest buffered items.
+    DropOldest,
+}
+
+/// Delivery counters for one channel, summed over all of its subscribers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelStats {
+    pub name: String,
+    pub policy: OverflowPolicy,
+    pub subscribers: usize,
+    pub sent: u64,
+    pub delivered: u64,
+    /// Items a subscriber never saw because it was lagging.
+    pub dropped: u64,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{
+        ChannelStats, OverflowPolicy, Polled, Sequenced, SocketMessage,
+        SocketRequest, Tick, IDLE_TIMEOUT,
+    };
+    use dashmap::DashMap;
+    use futures::{
+        channel::oneshot, future, stream, FutureExt, Stream, StreamExt,
+    };
+    use server_fn::{BoxedStream, ServerFnError};
+    use std::{
+        collections::VecDeque,
+        sync::{
+            atomic::{AtomicU64, AtomicUsize, Ordering},
+            Arc, LazyLock, Mutex,
+        },
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+    use tokio::sync::{
+        broadcast::{self, error::RecvError},
+        watch,
+    };
+
+    #[derive(Default)]
+    struct Counters {
+        subscribers: AtomicUsize,
+        sent: AtomicU64,
+        delivered: AtomicU64,
+        dropped: AtomicU64,
+    }
+
+    static REGISTRY: LazyLock<
+        DashMap<String, (OverflowPolicy, Arc<Counters>)>,
+    > = LazyLock::new(DashMap::new);
+
+    /// A channel's entry in [`REGISTRY`], shared by its clones. The last one
+    /// to go takes the entry with it, so channels made per scan or per job
+    /// don't pile up in [`stats`].
+    struct Registration {
+        name: String,
+        counters: Arc<Counters>,
+    }
+
+    impl Registration {
+        fn sent(&self) {
+            self.counters.sent.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    impl Drop for Registration {
+        fn drop(&mut self) {
+            // unless a newer channel has taken the name since
+            REGISTRY.remove_if(&self.name, |_, (_, counters)| {
+                Arc::ptr_eq(counters, &self.counters)
+            });
+        }
+    }
+
+    fn register(name: String, policy: OverflowPolicy) -> Arc<Registration> {
+        let counters = Arc::new(Counters::default());
+        REGISTRY.insert(name.clone(), (policy, Arc::clone(&counters)));
+        Arc::new(Registration { name, counters })
+    }
+
+    /// Current counters of every channel that's still around, sorted by
+    /// name.
+    pub fn stats() -> Vec<ChannelStats> {
+        let mut stats = REGISTRY
+            .iter()
+            .map(|entry| {
+                let (policy, counters) = entry.value();
+                ChannelStats {
+                    name: entry.key().clone(),
+                    policy: *policy,
+                    subscribers: counters.subscribers.load(Ordering::Relaxed),
+                    sent: counters.sent.load(Ordering::Relaxed),
+                    delivered: counters.delivered.load(Ordering::Relaxed),
+                    dropped: counters.dropped.load(Ordering::Relaxed),
+                }
+            })
+            .collect::<Vec<_>>();
+        stats.sort_by(|a, b| a.name.cmp(&b.name));
+        stats
+    }
+
+    /// Where a channel's sequence numbers start: when it was created, in
+    /// microseconds, so numbers from after a restart are higher than any
+    /// from before it.
+    pub fn initial_seq() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_micros() as u64)
+    }
+
+    /// Counts a subscriber for as long as its stream is alive.
+    struct Subscriber(Arc<Counters>);
+
+    impl Subscriber {
+        fn new(counters: &Arc<Counters>) -> Self {
+            counters.subscribers.fetch_add(1, Ordering::Relaxed);
+            Self(Arc::clone(counters))
+        }
+
+        fn delivered(&self, dropped: u64) {
+            self.0.delivered.fetch_add(1, Ordering::Relaxed);
+            self.0.dropped.fetch_add(dropped, Ordering::Relaxed);
+        }
+    }
+
+    impl Drop for Subscriber {
+        fn drop(&mut self) {
+            self.0.subscribers.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// A channel with [`OverflowPolicy::CoalesceToLatest`]: sending never
+    /// waits, and each subscriber only ever sees the newest value.
+    #[derive(Clone)]
+    pub struct Latest<T> {
+        tx: watch::Sender<(u64, Option<T>)>,
+        registration: Arc<Registration>,
+    }
+
+    impl<T> Latest<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        pub fn new(name: impl Into<String>) -> Self {
+            let (tx, _) = watch::channel((initial_seq(), None));
+            let registration =
+                register(name.into(), OverflowPolicy::CoalesceToLatest);
+            Self { tx, registration }
+        }
+
+        pub fn send(&self, value: T) {
+            self.registration.sent();
+            self.tx.send_modify(|(seq, latest)| {
+                *seq += 1;
+                *latest = Some(value);
+            });
+        }
+
+        /// Yields the newest value after `after` (or the current value, when
+        /// there is no cursor), then every newer one the subscriber gets
+        /// around to reading.
+        pub fn subscribe_from(
+            &self,
+            after: Option<u64>,
+        ) -> impl Stream<Item = Sequenced<T>> + Send + 'static {
+            let rx = self.tx.subscribe();
+            let current = rx.borrow().0;
+            // a cursor from the future means the clock went back across a
+            // restart
+            let seen = after
+                .filter(|after| *after <= current)
+                .unwrap_or(current.saturating_sub(1));
+            let subscriber = Subscriber::new(&self.registration.counters);
+            stream::unfold(
+                (rx, seen, subscriber),
+                |(mut rx, seen, subscriber)| async move {
+                    loop {
+                        let (seq, latest) = {
+                            let current = rx.borrow_and_update();
+                            (current.0, current.1.clone())
+                        };
+                        if let Some(value) = latest.filter(|_| seq > seen) {
+                            subscriber.delivered(seq - seen - 1);
+                            let item = Sequenced { seq, value };
+                            return Some((item, (rx, seq, subscriber)));
+                        }
+                        rx.changed().await.ok()?;
+                    }
+                },
+            )
+        }
+    }
+
+    /// A channel with [`OverflowPolicy::DropOldest`]: sending never waits,
+    /// and a subscriber more than `capacity` items behind loses the oldest.
+    ///
+    /// The last `capacity` items are also kept so a reconnecting subscriber
+    /// can catch up on what it missed.
+    #[derive(Clone)]
+    pub struct DropOldest<T> {
+        tx: broadcast::Sender<Sequenced<T>>,
+        history: Arc<Mutex<History<T>>>,
+        capacity: usize,
+        registration: Arc<Registration>,
+    }
+
+    struct History<T> {
+        /// The sequence number before the first item ever sent.
+        start: u64,
+        last_seq: u64,
+        items: VecDeque<Sequenced<T>>,
+    }
+
+    impl<T> DropOldest<T>
+    where
+        T: Clone + Send + 'static,
+    {
+        pub fn new(name: impl Into<String>, capacity: usize) -> Self {
+            let (tx, _) = broadcast::channel(capacity);
+            let start = initial_seq();
+            let history = History {
+                start,
+                last_seq: start,
+                items: VecDeque::with_capacity(capacity),
+            };
+            let registration =
+                register(name.into(), OverflowPolicy::DropOldest);
+            Self {
+                tx,
+                history: Arc::new(Mutex::new(history)),
+                capacity,
+                registration,
+            }
+        }
+
+        pub fn send(&self, value: T) {
+            self.registration.sent();
+            // sequence numbers, history and broadcast order must agree, so
+            // all three are updated under the lock
+            let mut history = self.history.lock().unwrap();
+            history.last_seq += 1;
+            let item = Sequenced {
+                seq: history.last_seq,
+                value,
+            };
+            if history.items.len() == self.capacity {
+                history.items.pop_front();
+            }
+            history.items.push_back(item.clone());
+            // only fails when nobody is subscribed, which is fine
+            _ = self.tx.send(item);
+        }
+
+        /// Replays the retained items after `after`, then yields every new
+        /// item, minus any the subscriber lagged behind on.
+        pub fn subscribe_from(
+            &self,
+            after: Option<u64>,
+        ) -> impl Stream<Item = Sequenced<T>> + Send + 'static {
+            let (rx, backlog, missed) = {
+                let history = self.history.lock().unwrap();
+                let rx = self.tx.subscribe();
+                // a cursor from the future means the clock went back across
+                // a restart, and one from the past that it's the first time
+                // this subscriber sees this channel since
+                match after.filter(|after| *after <= history.last_seq) {
+                    Some(after) => {
+                        let after = after.max(history.start);
+                        let backlog = history
+                            .items
+                            .iter()
+                            .filter(|item| item.seq > after)
+                            .cloned()
+                            .collect::<VecDeque<_>>();
+                        let first = backlog
+                            .front()
+                            .map_or(history.last_seq + 1, |item| item.seq);
+                        (rx, backlog, first - after - 1)
+                    }
+                    None => (rx, VecDeque::new(), 0),
+                }
+            };
+            let subscriber = Subscriber::new(&self.registration.counters);
+            stream::unfold(
+                (rx, backlog, missed, subscriber),
+                |(mut rx, mut backlog, mut lagged, subscriber)| async move {
+                    loop {
+                        let item = match backlog.pop_front() {
+                            Some(item) => item,
+                            None => match rx.recv().await {
+                                Ok(item) => item,
+                                Err(RecvError::Lagged(n)) => {
+                                    lagged += n;
+                                    continue;
+                                }
+                                Err(RecvError::Closed) => return None,
+                            },
+                        };
+                        subscriber.delivered(lagged);
+                        return Some((item, (rx, backlog, 0, subscriber)));
+                    }
+                },
+            )
+        }
+
+        /// Waits up to `wait` for items after `after`, then answers with
+        /// every one already here that `keep` turns into something. Without
+        /// a cursor, or with one from before a restart, only new items count.
+        pub async fn poll_from<U>(
+            &self,
+            after: Option<u64>,
+            wait: Duration,
+            mut keep: impl FnMut(T) -> Option<U>,
+        ) -> Polled<U> {
+            let after = {
+                let history = self.history.lock().unwrap();
+                after
+                    .filter(|after| *after <= history.last_seq)
+                    .map_or(history.last_seq, |after| after.max(history.start))
+            };
+            let mut items = Box::pin(self.subscribe_from(Some(after)));
+            let deadline = tokio::time::sleep(wait);
+            tokio::pin!(deadline);
+            let mut polled = Polled {
+                items: Vec::new(),
+                next: after,
+            };
+            while polled.items.len() < self.capacity {
+                // only the first item kept is waited for
+                let item = if polled.items.is_empty() {
+                    tokio::select! {
+                        item = items.next() => item,
+                        _ = &mut deadline => None,
+                    }
+                } else {
+                    items.next().now_or_never().flatten()
+                };
+                let Some(Sequenced { seq, value }) = item else {
+                    break;
+                };
+                polled.next = seq;
+                if let Some(value) = keep(value) {
+                    polled.items.push(Sequenced { seq, value });
+                }
+            }
+            polled
+        }
+    }
+
+    /// Interleaves `events` with [`Tick::Heartbeat`]s whenever it has been
+    /// quiet for `interval`, so clients can tell a dead connection from an
+    /// idle one. Starts with one too, so a client can tell straight away
+    /// whether frames get through at all.
+    pub fn with_heartbeat<T>(
+        events: impl Stream<Item = Sequenced<T>> + Send + 'static,
+        interval: Duration,
+    ) -> impl Stream<Item = Tick<T>> + Send + 'static
+    where
+        T: Send + 'static,
+    {
+        let ticks =
+            stream::unfold(Box::pin(events), move |mut events| async move {
+                tokio::select! {
+                    event = events.next() => {
+                        event.map(|event| (Tick::Event(event), events))
+                    }
+                    _ = tokio::time::sleep(interval) => {
+                        Some((Tick::Heartbeat, events))
+                    }
+                }
+            });
+        stream::once(future::ready(Tick::Heartbeat)).chain(ticks)
+    }
+
+    /// Serves a subscription socket: answers pings, and once the client
+    /// says where to resume, sends what `subscribe` returns for that. Ends
+    /// when the client closes the socket, sends something that can't be
+    /// read, or goes quiet for [`IDLE_TIMEOUT`].
+    pub fn serve_socket<T, S>(
+        requests: BoxedStream<SocketRequest, ServerFnError>,
+        subscribe: impl FnOnce(Option<u64>) -> S + Send + 'static,
+    ) -> impl Stream<Item = SocketMessage<T>> + Send + 'static
+    where
+        T: Send + 'static,
+        S: Stream<Item = Sequenced<T>> + Send + 'static,
+    {
+        let requests = stream::unfold(requests, |mut requests| async move {
+            match tokio::time::timeout(IDLE_TIMEOUT, requests.next()).await {
+                Ok(Some(Ok(request))) => Some((request, requests)),
+                _ => None,
+            }
+        });
+        let (resume, resumed) = oneshot::channel();
+        let mut resume = Some(resume);
+        let pongs = requests
+            .filter_map(move |request| {
+                future::ready(match request {
+                    SocketRequest::Ping => Some(Some(SocketMessage::Pong)),
+                    SocketRequest::Resume { after } => {
+                        if let Some(resume) = resume.take() {
+                            _ = resume.send(after);
+                        }
+                        None
+                    }
+                })
+            })
+            // marks the end of the requests, which ends the socket
+            .chain(stream::once(future::ready(None)));
+        let events =
+            stream::once(async move { resumed.await.ok().map(subscribe) })
+                .filter_map(future::ready)
+                .flatten()
+                .map(|event| Some(SocketMessage::Event(event)));
+        stream::select(pongs, events)
+            .take_while(|message| future::ready(message.is_some()))
+            .filter_map(future::ready)
+    }
+}
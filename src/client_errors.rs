@@ -0,0 +1,338 @@
+//! Errors from the browser, sent to the server so they show up on the admin
+//! page next to the server's own: panics, through the hook [`hydrate`]
+//! sets, and whatever a [`ReportingErrorBoundary`] catches.
+//!
+//! [`hydrate`]: crate::hydrate
+
+use crate::{
+    auth::User,
+    dev_overlay::show_panic,
+    errors::{AppError, TodoAppError},
+};
+use chrono::{DateTime, Local, Utc};
+use leptos::{error::ErrorId, prelude::*};
+use serde::{Deserialize, Serialize};
+use server_fn::codec::{GetUrl, Json};
+use std::{collections::HashSet, fmt};
+
+/// How many reports the server keeps, dropping the oldest.
+pub const MAX_CLIENT_ERRORS: usize = 100;
+
+/// Longer messages are cut to this many bytes.
+pub const MAX_MESSAGE_LEN: usize = 4 * 1024;
+
+/// Longer stack traces are cut to this many bytes.
+pub const MAX_STACK_LEN: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClientErrorKind {
+    Panic,
+    /// Caught by a [`ReportingErrorBoundary`].
+    Boundary,
+}
+
+impl fmt::Display for ClientErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientErrorKind::Panic => f.write_str("panic"),
+            ClientErrorKind::Boundary => f.write_str("error boundary"),
+        }
+    }
+}
+
+/// What the browser sends about one error.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientError {
+    pub kind: ClientErrorKind,
+    pub message: String,
+    /// The JavaScript stack where it was reported, which for a panic
+    /// includes the WASM frames that led to it.
+    pub stack: Option<String>,
+    /// The path the browser was on.
+    pub route: String,
+    /// The ID of the server request the error came from, if it carried one.
+    pub request_id: Option<String>,
+}
+
+/// A [`ClientError`] as the server received it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceivedClientError {
+    /// Logged with it, to find it in the server log.
+    pub id: String,
+    pub at: DateTime<Utc>,
+    /// Who was signed in, if anyone.
+    pub user: Option<User>,
+    pub error: ClientError,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{
+        ClientError, ReceivedClientError, MAX_CLIENT_ERRORS, MAX_MESSAGE_LEN,
+        MAX_STACK_LEN,
+    };
+    use crate::auth::User;
+    use chrono::Utc;
+    use std::{collections::VecDeque, sync::Mutex};
+
+    /// The latest reports, oldest first.
+    pub static CLIENT_ERRORS: Mutex<VecDeque<ReceivedClientError>> =
+        Mutex::new(VecDeque::new());
+
+    fn truncate(text: &mut String, max: usize) {
+        if text.len() > max {
+            let mut end = max;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            text.truncate(end);
+        }
+    }
+
+    /// Keeps `error`, sent by `user`, and logs it.
+    pub fn record(
+        mut error: ClientError,
+        user: Option<User>,
+    ) -> ReceivedClientError {
+        truncate(&mut error.message, MAX_MESSAGE_LEN);
+        if let Some(stack) = &mut error.stack {
+            truncate(stack, MAX_STACK_LEN);
+        }
+        let received = ReceivedClientError {
+            id: uuid::Uuid::new_v4().to_string(),
+            at: Utc::now(),
+            user,
+            error,
+        };
+        tracing::warn!(
+            id = %received.id,
+            kind = %received.error.kind,
+            route = %received.error.route,
+            request_id = received.error.request_id.as_deref(),
+            message = %received.error.message,
+            "client error reported",
+        );
+        let mut errors = CLIENT_ERRORS.lock().unwrap();
+        if errors.len() == MAX_CLIENT_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(received.clone());
+        received
+    }
+}
+
+/// Where the browser sends its errors. JSON, so a panic hook can send it
+/// with `sendBeacon` without going through server_fn's client.
+#[server(input = Json)]
+pub async fn report_client_error(
+    error: ClientError,
+) -> Result<(), ServerFnError> {
+    use crate::auth::current_user;
+
+    let received = record(error, current_user());
+    #[cfg(feature = "sentry")]
+    {
+        use crate::auth::session_token;
+        use http::request::Parts;
+
+        let token = use_context::<Parts>()
+            .and_then(|parts| session_token(&parts.headers));
+        crate::sentry::capture_client_error(&received, token.as_deref());
+    }
+    #[cfg(not(feature = "sentry"))]
+    _ = received;
+    Ok(())
+}
+
+/// The reports the server has kept, newest first, for admins only.
+#[server(input = GetUrl)]
+#[middleware(crate::middleware::TwoFactorLayer)]
+pub async fn client_errors() -> Result<Vec<ReceivedClientError>, AppError> {
+    use crate::auth::require_admin;
+
+    require_admin()?;
+    Ok(CLIENT_ERRORS
+        .lock()
+        .unwrap()
+        .iter()
+        .rev()
+        .cloned()
+        .collect())
+}
+
+fn current_route() -> String {
+    window().location().pathname().unwrap_or_default()
+}
+
+fn current_stack() -> Option<String> {
+    js_sys::Reflect::get(&js_sys::Error::new(""), &"stack".into())
+        .ok()
+        .and_then(|stack| stack.as_string())
+}
+
+/// Sends `error` with `sendBeacon`, which still goes out after a panic has
+/// left the app's executor unusable, or while the page unloads.
+pub fn send_client_error(error: &ClientError) {
+    use server_fn::ServerFn;
+    use wasm_bindgen::JsValue;
+    use web_sys::{Blob, BlobPropertyBag};
+
+    let Ok(body) = serde_json::to_string(&ReportClientError {
+        error: error.clone(),
+    }) else {
+        return;
+    };
+    let url = format!(
+        "{}{}",
+        server_fn::client::get_server_url(),
+        <ReportClientError as ServerFn>::PATH
+    );
+    let options = BlobPropertyBag::new();
+    options.set_type("application/json");
+    let parts = js_sys::Array::of1(&JsValue::from_str(&body));
+    if let Ok(blob) = Blob::new_with_str_sequence_and_options(&parts, &options)
+    {
+        _ = window()
+            .navigator()
+            .send_beacon_with_opt_blob(&url, Some(&blob));
+    }
+}
+
+/// Logs panics to the console, like `console_error_panic_hook`, and
+/// reports them too. Debug builds also show them in the
+/// [dev overlay](crate::dev_overlay).
+pub fn set_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        console_error_panic_hook::hook(info);
+        let error = ClientError {
+            kind: ClientErrorKind::Panic,
+            message: info.to_string(),
+            stack: current_stack(),
+            route: current_route(),
+            request_id: None,
+        };
+        show_panic(&error.message, error.stack.as_deref());
+        send_client_error(&error);
+    }));
+}
+
+/// An [`ErrorBoundary`] that reports each error it catches once, from the
+/// browser, and lists them in place of its children.
+#[component]
+pub fn ReportingErrorBoundary(children: Children) -> impl IntoView {
+    view! { <ErrorBoundary fallback=reported_errors>{children()}</ErrorBoundary> }
+}
+
+fn reported_errors(errors: ArcRwSignal<Errors>) -> impl IntoView {
+    let reported = StoredValue::new(HashSet::<ErrorId>::new());
+    let to_report = errors.clone();
+    Effect::new(move |_| {
+        to_report.with(|errors| {
+            for (id, error) in errors.iter() {
+                let new = reported.try_update_value(|r| r.insert(id.clone()));
+                if new != Some(true) {
+                    continue;
+                }
+                let request_id = match error.downcast_ref::<TodoAppError>() {
+                    Some(TodoAppError::Panic { request_id }) => {
+                        Some(request_id.clone())
+                    }
+                    _ => None,
+                };
+                send_client_error(&ClientError {
+                    kind: ClientErrorKind::Boundary,
+                    message: error.to_string(),
+                    stack: current_stack(),
+                    route: current_route(),
+                    request_id,
+                });
+            }
+        })
+    });
+
+    view! {
+        <div class="error-boundary">
+            <p>"Something went wrong here, and it has been reported:"</p>
+            <ul>
+                {move || {
+                    errors
+                        .get()
+                        .into_iter()
+                        .map(|(_, error)| view! { <li>{error.to_string()}</li> })
+                        .collect::<Vec<_>>()
+                }}
+            </ul>
+        </div>
+    }
+}
+
+/// The latest client error reports, for the admin page.
+#[component]
+pub fn ClientErrors() -> impl IntoView {
+    let (refreshed, set_refreshed) = signal(0);
+    let errors = Resource::new(move || refreshed.get(), |_| client_errors());
+
+    view! {
+        <h3>"Client errors"</h3>
+        <button on:click=move |_| set_refreshed.update(|n| *n += 1)>"Refresh"</button>
+        <Transition fallback=|| view! { <p>"Loading..."</p> }>
+            {move || Suspend::new(async move {
+                match errors.await {
+                    Ok(errors) if errors.is_empty() => {
+                        view! { <p>"None reported."</p> }.into_any()
+                    }
+                    Ok(errors) => {
+                        view! {
+                            <ul class="client-errors">
+                                {errors
+                                    .into_iter()
+                                    .map(|received| view! { <ClientErrorItem received /> })
+                                    .collect::<Vec<_>>()}
+                            </ul>
+                        }
+                            .into_any()
+                    }
+                    Err(e) => view! { <p>{e.to_string()}</p> }.into_any(),
+                }
+            })}
+        </Transition>
+    }
+}
+
+#[component]
+fn ClientErrorItem(received: ReceivedClientError) -> impl IntoView {
+    let ReceivedClientError {
+        id,
+        at,
+        user,
+        error,
+    } = received;
+    let at = at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S");
+    let user = user.map_or_else(|| "anonymous".to_string(), |user| user.name);
+
+    view! {
+        <li>
+            <strong>{error.kind.to_string()}</strong>
+            {format!(" on {} at {at}, {user}: ", error.route)}
+            {error.message}
+            <br />
+            <small>
+                {format!("report {id}")}
+                {error.request_id.map(|request_id| format!(", request {request_id}"))}
+            </small>
+            {error
+                .stack
+                .map(|stack| {
+                    view! {
+                        <details>
+                            <summary>"Stack"</summary>
+                            <pre>{stack}</pre>
+                        </details>
+                    }
+                })}
+        </li>
+    }
+}
@@ -0,0 +1,883 @@
+use crate::{
+    channels::{IDLE_TIMEOUT, KEEPALIVE_INTERVAL},
+    dev_overlay::{report_dev_error, truncate_body, DevError},
+    errors::REQUEST_ID_HEADER,
+    supervisor::sleep,
+};
+use chrono::Utc;
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{self, AbortHandle, Either},
+    stream, Sink, Stream, StreamExt,
+};
+use gloo_net::http::{Method, RequestBuilder, Response};
+use http::StatusCode;
+use leptos::prelude::set_timeout;
+use pin_project_lite::pin_project;
+use send_wrapper::SendWrapper;
+use serde::Serialize;
+use server_fn::{
+    client::{browser::BrowserClient, Client},
+    error::{FromServerFnError, IntoAppError, ServerFnErrorErr},
+    redirect::REDIRECT_HEADER,
+    request::browser::BrowserRequest,
+    response::{browser::BrowserResponse, ClientRes},
+    Bytes,
+};
+use std::{
+    cell::Cell,
+    cmp::Reverse,
+    collections::{BTreeMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// A `fetch` response received by one of the custom clients below.
+///
+/// `BrowserResponse` can only be constructed by `server_fn` itself, so clients
+/// that issue their own requests hand back this wrapper instead.
+pub struct FetchResponse(SendWrapper<Response>);
+
+impl FetchResponse {
+    pub fn new(res: Response) -> Self {
+        Self(SendWrapper::new(res))
+    }
+}
+
+impl<E: FromServerFnError> ClientRes<E> for FetchResponse {
+    fn try_into_string(self) -> impl Future<Output = Result<String, E>> + Send {
+        SendWrapper::new(async move {
+            self.0.text().await.map_err(|e| {
+                ServerFnErrorErr::Deserialization(e.to_string())
+                    .into_app_error()
+            })
+        })
+    }
+
+    fn try_into_bytes(self) -> impl Future<Output = Result<Bytes, E>> + Send {
+        SendWrapper::new(async move {
+            self.0.binary().await.map(Bytes::from).map_err(|e| {
+                ServerFnErrorErr::Deserialization(e.to_string())
+                    .into_app_error()
+            })
+        })
+    }
+
+    // reads the whole body before yielding it as a single chunk
+    fn try_into_stream(
+        self,
+    ) -> Result<
+        impl Stream<Item = Result<Bytes, Bytes>> + Send + Sync + 'static,
+        E,
+    > {
+        let body = SendWrapper::new(async move {
+            self.0.binary().await.map(Bytes::from).map_err(|e| {
+                E::from_server_fn_error(ServerFnErrorErr::Request(
+                    e.to_string(),
+                ))
+                .ser()
+            })
+        });
+        Ok(futures::stream::once(body))
+    }
+
+    fn status(&self) -> u16 {
+        self.0.status()
+    }
+
+    fn status_text(&self) -> String {
+        self.0.status_text()
+    }
+
+    fn location(&self) -> String {
+        self.0
+            .headers()
+            .get("Location")
+            .unwrap_or_else(|| self.0.url())
+    }
+
+    fn has_redirect(&self) -> bool {
+        self.0.headers().get(REDIRECT_HEADER).is_some()
+    }
+}
+
+static CROSS_ORIGIN_TARGET: Mutex<Option<String>> = Mutex::new(None);
+
+/// Sets the origin (e.g. `http://127.0.0.1:3000`) that [`CrossOriginClient`]
+/// sends requests to. `None` sends them to the page's own origin.
+pub fn set_cross_origin_target(origin: Option<String>) {
+    *CROSS_ORIGIN_TARGET.lock().unwrap() =
+        origin.map(|origin| origin.trim_end_matches('/').to_string());
+}
+
+/// A client that re-issues each request against another origin, so the call
+/// is subject to CORS. Request bodies are forwarded as text, which covers the
+/// URL-encoded and JSON encodings.
+pub struct CrossOriginClient;
+
+impl<E, IS, OS> Client<E, IS, OS> for CrossOriginClient
+where
+    E: FromServerFnError,
+    IS: FromServerFnError,
+    OS: FromServerFnError,
+{
+    type Request = BrowserRequest;
+    type Response = FetchResponse;
+
+    fn send(
+        req: Self::Request,
+    ) -> impl Future<Output = Result<Self::Response, E>> + Send {
+        SendWrapper::new(async move {
+            let req: gloo_net::http::Request = req.into();
+            let target = CROSS_ORIGIN_TARGET.lock().unwrap().clone();
+            let url = match target {
+                Some(origin) => retarget(&req.url(), &origin),
+                None => req.url(),
+            };
+
+            let builder = RequestBuilder::new(&url)
+                .method(req.method())
+                .headers(req.headers());
+            let req = if req.method() == Method::GET {
+                builder.build()
+            } else {
+                let body = req.text().await.map_err(|e| {
+                    ServerFnErrorErr::Request(e.to_string()).into_app_error()
+                })?;
+                builder.body(body)
+            }
+            .map_err(|e| {
+                ServerFnErrorErr::Request(e.to_string()).into_app_error()
+            })?;
+
+            req.send().await.map(FetchResponse::new).map_err(|e| {
+                ServerFnErrorErr::Request(e.to_string()).into_app_error()
+            })
+        })
+    }
+
+    fn open_websocket(
+        path: &str,
+    ) -> impl Future<
+        Output = Result<
+            (
+                impl Stream<Item = Result<Bytes, Bytes>> + Send + 'static,
+                impl Sink<Bytes> + Send + 'static,
+            ),
+            E,
+        >,
+    > + Send {
+        <BrowserClient as Client<E, IS, OS>>::open_websocket(path)
+    }
+
+    fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+        <BrowserClient as Client<E, IS, OS>>::spawn(future)
+    }
+}
+
+/// Replaces the origin of an absolute `url` with `origin`.
+fn retarget(url: &str, origin: &str) -> String {
+    format!("{origin}{}", &url[path_start(url)..])
+}
+
+/// Where the path starts in `url`, which may or may not be absolute.
+fn path_start(url: &str) -> usize {
+    match url.find("://") {
+        Some(scheme_end) => url[scheme_end + 3..]
+            .find('/')
+            .map_or(url.len(), |i| scheme_end + 3 + i),
+        None => 0,
+    }
+}
+
+static LAST_TRACE_ID: Mutex<Option<String>> = Mutex::new(None);
+
+/// The trace ID of the most recent call made with [`TracingClient`].
+pub fn last_trace_id() -> Option<String> {
+    LAST_TRACE_ID.lock().unwrap().clone()
+}
+
+/// Starts a new W3C trace for every call by sending a `traceparent` header,
+/// which the server continues when exporting its server fn spans.
+///
+/// In debug builds, every call that fails is also shown in the
+/// [dev overlay](crate::dev_overlay), with what was sent and answered.
+pub struct TracingClient;
+
+impl<E, IS, OS> Client<E, IS, OS> for TracingClient
+where
+    E: FromServerFnError,
+    IS: FromServerFnError,
+    OS: FromServerFnError,
+{
+    type Request = BrowserRequest;
+    type Response = FetchResponse;
+
+    fn send(
+        req: Self::Request,
+    ) -> impl Future<Output = Result<Self::Response, E>> + Send {
+        let trace_id = random_hex(16);
+        let span_id = random_hex(8);
+        req.headers()
+            .append("traceparent", &format!("00-{trace_id}-{span_id}-01"));
+        *LAST_TRACE_ID.lock().unwrap() = Some(trace_id);
+        SendWrapper::new(async move {
+            let req: web_sys::Request =
+                gloo_net::http::Request::from(req).into();
+            // sending uses the body up, so the overlay reads it from a copy
+            // (`web_sys::Request::clone`, not `Clone`)
+            let sent =
+                cfg!(debug_assertions).then(|| req.clone().ok()).flatten();
+            let method = req.method();
+            let url = req.url();
+            match gloo_net::http::Request::from(req).send().await {
+                Ok(res) => {
+                    if let (Some(sent), false) = (sent, res.ok()) {
+                        report_failed_call(method, url, sent, &res).await;
+                    }
+                    Ok(FetchResponse::new(res))
+                }
+                Err(e) => {
+                    if cfg!(debug_assertions) {
+                        report_dev_error(DevError {
+                            at: Utc::now(),
+                            message: e.to_string(),
+                            method,
+                            endpoint: url,
+                            status: None,
+                            request_id: None,
+                            request_body: None,
+                            response_body: None,
+                        });
+                    }
+                    Err(ServerFnErrorErr::Request(e.to_string())
+                        .into_app_error())
+                }
+            }
+        })
+    }
+
+    fn open_websocket(
+        path: &str,
+    ) -> impl Future<
+        Output = Result<
+            (
+                impl Stream<Item = Result<Bytes, Bytes>> + Send + 'static,
+                impl Sink<Bytes> + Send + 'static,
+            ),
+            E,
+        >,
+    > + Send {
+        <BrowserClient as Client<E, IS, OS>>::open_websocket(path)
+    }
+
+    fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+        <BrowserClient as Client<E, IS, OS>>::spawn(future)
+    }
+}
+
+/// Shows a call the server answered with an error in the dev overlay.
+async fn report_failed_call(
+    method: String,
+    url: String,
+    sent: web_sys::Request,
+    res: &Response,
+) {
+    let request_body = match gloo_net::http::Request::from(sent).text().await {
+        Ok(body) if !body.is_empty() => Some(truncate_body(body)),
+        _ => None,
+    };
+    let response_body = match res.as_raw().clone() {
+        Ok(copy) => Response::from(copy).text().await.ok().map(truncate_body),
+        Err(_) => None,
+    };
+    report_dev_error(DevError {
+        at: Utc::now(),
+        message: format!("{} {}", res.status(), res.status_text()),
+        method,
+        endpoint: url,
+        status: Some(res.status()),
+        request_id: res.headers().get(REQUEST_ID_HEADER),
+        request_body,
+        response_body,
+    });
+}
+
+fn random_hex(bytes: usize) -> String {
+    (0..bytes)
+        .map(|_| format!("{:02x}", (js_sys::Math::random() * 256.0) as u8))
+        .collect()
+}
+
+/// [`SocketRequest::Ping`](crate::channels::SocketRequest::Ping) as a
+/// websocket frame: the tag for an `Ok` item, then the JSON for the unit
+/// variant.
+const PING_FRAME: &[u8] = b"\0\"Ping\"";
+
+/// Keeps subscription sockets open through proxies that close idle
+/// connections, and notices when one has died anyway.
+///
+/// Pings the server as soon as the socket opens and then every
+/// [`KEEPALIVE_INTERVAL`], and ends the socket with an error once nothing
+/// has come back for [`IDLE_TIMEOUT`]. The pings are written into the
+/// socket as they are, so it's only for server fns taking JSON-encoded
+/// [`SocketRequest`](crate::channels::SocketRequest)s, which answer each
+/// one with a `Pong`.
+pub struct KeepaliveClient;
+
+impl<E, IS, OS> Client<E, IS, OS> for KeepaliveClient
+where
+    E: FromServerFnError,
+    IS: FromServerFnError,
+    OS: FromServerFnError,
+{
+    type Request = BrowserRequest;
+    type Response = BrowserResponse;
+
+    fn send(
+        req: Self::Request,
+    ) -> impl Future<Output = Result<Self::Response, E>> + Send {
+        <BrowserClient as Client<E, IS, OS>>::send(req)
+    }
+
+    fn open_websocket(
+        path: &str,
+    ) -> impl Future<
+        Output = Result<
+            (
+                impl Stream<Item = Result<Bytes, Bytes>> + Send + 'static,
+                impl Sink<Bytes> + Send + 'static,
+            ),
+            E,
+        >,
+    > + Send {
+        let open = <BrowserClient as Client<E, IS, OS>>::open_websocket(path);
+        async move {
+            let (incoming, sink) = open.await?;
+            let (outgoing, frames) = mpsc::channel(16);
+            let (pinging, stop) = future::abortable(keep_alive(frames, sink));
+            <BrowserClient as Client<E, IS, OS>>::spawn(async move {
+                _ = pinging.await;
+            });
+            Ok((until_idle::<OS>(incoming, StopOnDrop(stop)), outgoing))
+        }
+    }
+
+    fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+        <BrowserClient as Client<E, IS, OS>>::spawn(future)
+    }
+}
+
+/// Stops a socket's pings once its incoming stream is dropped.
+struct StopOnDrop(AbortHandle);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Writes `frames` to `sink` along with the pings, until the socket is
+/// closed.
+async fn keep_alive(
+    frames: mpsc::Receiver<Bytes>,
+    sink: impl Sink<Bytes> + Send + 'static,
+) {
+    let pings = stream::unfold(true, |first| async move {
+        if !first {
+            sleep(KEEPALIVE_INTERVAL).await;
+        }
+        Some((Bytes::from_static(PING_FRAME), false))
+    });
+    _ = stream::select(frames, pings).map(Ok).forward(sink).await;
+}
+
+/// `incoming`, ending with an error once it has been quiet for
+/// [`IDLE_TIMEOUT`]. `stop` is dropped along with it.
+fn until_idle<OS: FromServerFnError>(
+    incoming: impl Stream<Item = Result<Bytes, Bytes>> + Send + 'static,
+    stop: StopOnDrop,
+) -> impl Stream<Item = Result<Bytes, Bytes>> + Send + 'static {
+    let state = Some((Box::pin(incoming), stop));
+    stream::unfold(state, |state| async move {
+        let (mut incoming, stop) = state?;
+        let idle = Box::pin(sleep(IDLE_TIMEOUT));
+        match future::select(incoming.next(), idle).await {
+            Either::Left((Some(frame), _)) => {
+                Some((frame, Some((incoming, stop))))
+            }
+            Either::Left((None, _)) => None,
+            Either::Right(_) => {
+                let error = OS::from_server_fn_error(
+                    ServerFnErrorErr::Request(format!(
+                        "nothing from the server for {}s",
+                        IDLE_TIMEOUT.as_secs()
+                    )),
+                )
+                .ser();
+                Some((Err(error), None))
+            }
+        }
+    })
+}
+
+/// How soon a call made with [`PriorityClient`] should be sent, least
+/// urgent first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Prefetches, and other calls nobody is waiting on yet.
+    Background,
+    #[default]
+    Normal,
+    /// Mutations the user has just asked for.
+    Urgent,
+}
+
+/// How many [`PriorityClient`] calls are sent at once; the rest wait.
+pub const MAX_IN_FLIGHT: usize = 4;
+
+/// A waiting call counts as one level more urgent for every this long it
+/// has waited, so a steady stream of urgent calls can't hold the others
+/// back forever.
+pub const PRIORITY_AGING: Duration = Duration::from_secs(2);
+
+thread_local! {
+    static CURRENT_PRIORITY: Cell<Priority> = const {
+        Cell::new(Priority::Normal)
+    };
+}
+
+/// Makes `call`, a server fn call using [`PriorityClient`], at `priority`
+/// rather than [`Priority::Normal`].
+pub fn with_priority<F: Future>(
+    priority: Priority,
+    call: F,
+) -> impl Future<Output = F::Output> {
+    WithPriority { priority, call }
+}
+
+pin_project! {
+    struct WithPriority<F> {
+        priority: Priority,
+        #[pin]
+        call: F,
+    }
+}
+
+impl<F: Future> Future for WithPriority<F> {
+    type Output = F::Output;
+
+    // the request is only sent once the call is polled, so the priority is
+    // set around every poll rather than when the call is made
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = self.project();
+        let outer = CURRENT_PRIORITY.replace(*this.priority);
+        let poll = this.call.poll(cx);
+        CURRENT_PRIORITY.set(outer);
+        poll
+    }
+}
+
+/// How many [`PriorityClient`] calls are being sent, and how many wait.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueDepth {
+    pub in_flight: usize,
+    /// Waiting calls by the priority they were made at, least urgent first.
+    pub waiting: [usize; 3],
+}
+
+struct Waiter {
+    seq: u64,
+    priority: Priority,
+    /// When it started waiting, in milliseconds since the epoch.
+    queued_at: f64,
+    ready: oneshot::Sender<()>,
+}
+
+impl Waiter {
+    fn urgency(&self, now: f64) -> u64 {
+        let waited = (now - self.queued_at).max(0.0);
+        self.priority as u64
+            + (waited / PRIORITY_AGING.as_millis() as f64) as u64
+    }
+}
+
+struct Dispatch {
+    in_flight: usize,
+    next_seq: u64,
+    waiting: Vec<Waiter>,
+}
+
+impl Dispatch {
+    /// The most urgent waiter, counting how long each has waited, and the
+    /// one that has waited longest among equals.
+    fn take_next(&mut self, now: f64) -> Option<Waiter> {
+        let index = (0..self.waiting.len()).max_by_key(|&i| {
+            let waiter = &self.waiting[i];
+            (waiter.urgency(now), Reverse(waiter.seq))
+        })?;
+        Some(self.waiting.remove(index))
+    }
+}
+
+static DISPATCH: Mutex<Dispatch> = Mutex::new(Dispatch {
+    in_flight: 0,
+    next_seq: 0,
+    waiting: Vec::new(),
+});
+
+/// Where [`PriorityClient`]'s queue is at.
+pub fn queue_depth() -> QueueDepth {
+    let dispatch = DISPATCH.lock().unwrap();
+    let mut waiting = [0; 3];
+    for waiter in &dispatch.waiting {
+        waiting[waiter.priority as usize] += 1;
+    }
+    QueueDepth {
+        in_flight: dispatch.in_flight,
+        waiting,
+    }
+}
+
+/// A call's turn to be sent, which passes to the next waiter once it's
+/// dropped.
+struct Slot;
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut dispatch = DISPATCH.lock().unwrap();
+        let now = js_sys::Date::now();
+        while let Some(waiter) = dispatch.take_next(now) {
+            // handed over as it is, so `in_flight` stays the same
+            if waiter.ready.send(()).is_ok() {
+                return;
+            }
+        }
+        dispatch.in_flight -= 1;
+    }
+}
+
+/// A call waiting for its [`Slot`]. If the call is dropped while it waits,
+/// it leaves the queue, or passes on the slot it was just handed.
+struct Waiting {
+    seq: u64,
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        let mut dispatch = DISPATCH.lock().unwrap();
+        match dispatch.waiting.iter().position(|w| w.seq == self.seq) {
+            Some(index) => {
+                dispatch.waiting.remove(index);
+            }
+            None => {
+                drop(dispatch);
+                drop(Slot);
+            }
+        }
+    }
+}
+
+async fn acquire(priority: Priority) -> Slot {
+    let (seq, ready) = {
+        let mut dispatch = DISPATCH.lock().unwrap();
+        if dispatch.in_flight < MAX_IN_FLIGHT && dispatch.waiting.is_empty() {
+            dispatch.in_flight += 1;
+            return Slot;
+        }
+        let seq = dispatch.next_seq;
+        dispatch.next_seq += 1;
+        let (tx, rx) = oneshot::channel();
+        dispatch.waiting.push(Waiter {
+            seq,
+            priority,
+            queued_at: js_sys::Date::now(),
+            ready: tx,
+        });
+        (seq, rx)
+    };
+    let waiting = Waiting { seq };
+    // the sender is only dropped once it has been used to hand a slot over
+    _ = ready.await;
+    std::mem::forget(waiting);
+    Slot
+}
+
+/// Sends at most [`MAX_IN_FLIGHT`] calls at once, and queues the rest by
+/// [`Priority`], so a mutation isn't stuck behind a batch of prefetches.
+/// Calls are made at [`Priority::Normal`] unless they're wrapped in
+/// [`with_priority`]; [`queue_depth`] shows how far behind it is.
+///
+/// A call holds its place until its response arrives, but not while its
+/// body is read. Websockets aren't queued.
+pub struct PriorityClient;
+
+impl<E, IS, OS> Client<E, IS, OS> for PriorityClient
+where
+    E: FromServerFnError,
+    IS: FromServerFnError,
+    OS: FromServerFnError,
+{
+    type Request = BrowserRequest;
+    type Response = BrowserResponse;
+
+    fn send(
+        req: Self::Request,
+    ) -> impl Future<Output = Result<Self::Response, E>> + Send {
+        let priority = CURRENT_PRIORITY.get();
+        async move {
+            let _slot = acquire(priority).await;
+            <BrowserClient as Client<E, IS, OS>>::send(req).await
+        }
+    }
+
+    fn open_websocket(
+        path: &str,
+    ) -> impl Future<
+        Output = Result<
+            (
+                impl Stream<Item = Result<Bytes, Bytes>> + Send + 'static,
+                impl Sink<Bytes> + Send + 'static,
+            ),
+            E,
+        >,
+    > + Send {
+        <BrowserClient as Client<E, IS, OS>>::open_websocket(path)
+    }
+
+    fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+        <BrowserClient as Client<E, IS, OS>>::spawn(future)
+    }
+}
+
+/// What [`MockClient`] answers a call with.
+#[derive(Debug, Clone)]
+pub struct MockReply {
+    status: u16,
+    /// `Err` if the request should fail before reaching the server.
+    body: Result<Bytes, String>,
+    latency: Duration,
+}
+
+impl MockReply {
+    /// A successful response with `body` as-is, which has to match the
+    /// server fn's output encoding.
+    pub fn ok(body: impl Into<Bytes>) -> Self {
+        Self {
+            status: 200,
+            body: Ok(body.into()),
+            latency: Duration::ZERO,
+        }
+    }
+
+    /// A successful response for server fns with the default JSON output.
+    pub fn json<T: Serialize>(value: &T) -> Self {
+        Self::ok(serde_json::to_vec(value).expect("serializing a mock reply"))
+    }
+
+    /// The server fn returning `Err(error)`.
+    pub fn error<E: FromServerFnError>(error: E) -> Self {
+        Self {
+            status: 500,
+            body: Ok(error.ser()),
+            latency: Duration::ZERO,
+        }
+    }
+
+    /// The request failing without a response, as if the network were down.
+    pub fn unreachable(message: impl Into<String>) -> Self {
+        Self {
+            status: 0,
+            body: Err(message.into()),
+            latency: Duration::ZERO,
+        }
+    }
+
+    /// Delays the reply by `latency`, e.g. to look at pending states.
+    pub fn after(self, latency: Duration) -> Self {
+        Self { latency, ..self }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Stub {
+    /// Answered in order; the last one is repeated.
+    replies: VecDeque<MockReply>,
+    calls: usize,
+}
+
+/// Server fn path -> how [`MockClient`] answers it.
+static STUBS: Mutex<BTreeMap<String, Stub>> = Mutex::new(BTreeMap::new());
+
+/// Answers every call to the server fn at `path` with `reply`.
+///
+/// A server fn declared inside a component has no type to take its `PATH`
+/// from, so `/api/name` also stubs the nested server fn `name`, whose path
+/// ends in a hash of where it is declared.
+pub fn stub(path: &str, reply: MockReply) {
+    stub_sequence(path, [reply]);
+}
+
+/// Answers calls to the server fn at `path` with `replies` in order, then
+/// keeps answering with the last one.
+pub fn stub_sequence(path: &str, replies: impl IntoIterator<Item = MockReply>) {
+    let replies = replies.into_iter().collect::<VecDeque<_>>();
+    assert!(!replies.is_empty(), "no replies to stub {path} with");
+    STUBS
+        .lock()
+        .unwrap()
+        .insert(path.to_string(), Stub { replies, calls: 0 });
+}
+
+/// How often [`MockClient`] has been called for `path` since it was stubbed.
+pub fn mock_calls(path: &str) -> usize {
+    STUBS.lock().unwrap().get(path).map_or(0, |stub| stub.calls)
+}
+
+/// Forgets every stub, and how often it was called.
+pub fn clear_stubs() {
+    STUBS.lock().unwrap().clear();
+}
+
+/// Answers calls with the replies set up by [`stub`] instead of sending
+/// them, so components can be tested without a server. Calls to a path that
+/// isn't stubbed fail.
+///
+/// Server fns declared with `client = AppClient` use it when the
+/// `mock-client` feature is enabled.
+pub struct MockClient;
+
+impl<E, IS, OS> Client<E, IS, OS> for MockClient
+where
+    E: FromServerFnError,
+    IS: FromServerFnError,
+    OS: FromServerFnError,
+{
+    type Request = BrowserRequest;
+    type Response = MockResponse;
+
+    fn send(
+        req: Self::Request,
+    ) -> impl Future<Output = Result<Self::Response, E>> + Send {
+        let url = req.url();
+        let path = url[path_start(&url)..]
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let reply = {
+            let mut stubs = STUBS.lock().unwrap();
+            let key = if stubs.contains_key(&path) {
+                path.as_str()
+            } else {
+                path.trim_end_matches(|c: char| c.is_ascii_digit())
+            };
+            stubs.get_mut(key).map(|stub| {
+                stub.calls += 1;
+                if stub.replies.len() > 1 {
+                    stub.replies.pop_front().unwrap()
+                } else {
+                    stub.replies[0].clone()
+                }
+            })
+        };
+        SendWrapper::new(async move {
+            let reply = reply.ok_or_else(|| {
+                ServerFnErrorErr::Request(format!("{path} isn't stubbed"))
+                    .into_app_error()
+            })?;
+            if !reply.latency.is_zero() {
+                let (tx, rx) = oneshot::channel();
+                set_timeout(move || _ = tx.send(()), reply.latency);
+                _ = rx.await;
+            }
+            let body = reply
+                .body
+                .map_err(|e| ServerFnErrorErr::Request(e).into_app_error())?;
+            Ok(MockResponse {
+                status: reply.status,
+                body,
+                path,
+            })
+        })
+    }
+
+    fn open_websocket(
+        path: &str,
+    ) -> impl Future<
+        Output = Result<
+            (
+                impl Stream<Item = Result<Bytes, Bytes>> + Send + 'static,
+                impl Sink<Bytes> + Send + 'static,
+            ),
+            E,
+        >,
+    > + Send {
+        <BrowserClient as Client<E, IS, OS>>::open_websocket(path)
+    }
+
+    fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+        <BrowserClient as Client<E, IS, OS>>::spawn(future)
+    }
+}
+
+/// A canned response from [`MockClient`].
+pub struct MockResponse {
+    status: u16,
+    body: Bytes,
+    path: String,
+}
+
+impl<E: FromServerFnError> ClientRes<E> for MockResponse {
+    fn try_into_string(self) -> impl Future<Output = Result<String, E>> + Send {
+        future::ready(String::from_utf8(self.body.to_vec()).map_err(|e| {
+            ServerFnErrorErr::Deserialization(e.to_string()).into_app_error()
+        }))
+    }
+
+    fn try_into_bytes(self) -> impl Future<Output = Result<Bytes, E>> + Send {
+        future::ready(Ok(self.body))
+    }
+
+    fn try_into_stream(
+        self,
+    ) -> Result<
+        impl Stream<Item = Result<Bytes, Bytes>> + Send + Sync + 'static,
+        E,
+    > {
+        Ok(futures::stream::once(future::ready(Ok(self.body))))
+    }
+
+    fn status(&self) -> u16 {
+        self.status
+    }
+
+    fn status_text(&self) -> String {
+        StatusCode::from_u16(self.status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    fn location(&self) -> String {
+        self.path.clone()
+    }
+
+    fn has_redirect(&self) -> bool {
+        false
+    }
+}
+
+/// The client used by the server fns that components are tested against:
+/// [`MockClient`] with the `mock-client` feature, otherwise the default.
+#[cfg(feature = "mock-client")]
+pub type AppClient = MockClient;
+/// The client used by the server fns that components are tested against:
+/// [`MockClient`] with the `mock-client` feature, otherwise
+/// [`TracingClient`].
+#[cfg(not(feature = "mock-client"))]
+pub type AppClient = TracingClient;
@@ -0,0 +1,1330 @@
+use crate::{
+    errors::{FrameError, HasStatusCode},
+    forms::{from_form_string, to_form_string},
+    query::{from_query_string, to_query_string},
+};
+use futures::{future, stream, Stream, StreamExt};
+use http::Method;
+use rkyv::{
+    api::high::{HighDeserializer, HighSerializer, HighValidator},
+    bytecheck::CheckBytes,
+    rancor,
+    ser::allocator::ArenaHandle,
+    util::AlignedVec,
+    vec::ArchivedVec,
+    Archive,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use server_fn::{
+    codec::{CborEncoding, Encoding, FromReq, FromRes, IntoReq, IntoRes, Post},
+    error::{FromServerFnError, IntoAppError, ServerFnErrorErr},
+    request::{ClientReq, Req},
+    response::{ClientRes, TryRes},
+    Bytes, ContentType, Decodes, Encodes, Format, FormatType, ServerFnError,
+};
+use std::{fmt::Debug, marker::PhantomData, pin::Pin};
+use strum::{Display, EnumString};
+
+type RkyvSerializer<'a> =
+    HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>;
+type RkyvDeserializer = HighDeserializer<rancor::Error>;
+type RkyvValidator<'a> = HighValidator<'a, rancor::Error>;
+
+/// `rkyv` encoding that copies every payload into an [`AlignedVec`] before
+/// validating it.
+///
+/// Request and response bodies arrive in whatever buffer the HTTP stack or
+/// `fetch` happened to allocate, which gives no alignment guarantees, so
+/// reading an archive in place can fail with "archive underaligned". Uses the
+/// same content type as `RkyvEncoding`, so either side can be swapped alone.
+pub struct AlignedRkyvEncoding;
+
+impl ContentType for AlignedRkyvEncoding {
+    const CONTENT_TYPE: &'static str = "application/rkyv";
+}
+
+impl FormatType for AlignedRkyvEncoding {
+    const FORMAT_TYPE: Format = Format::Binary;
+}
+
+impl<T> Encodes<T> for AlignedRkyvEncoding
+where
+    T: Archive + for<'a> rkyv::Serialize<RkyvSerializer<'a>>,
+{
+    type Error = rancor::Error;
+
+    fn encode(value: &T) -> Result<Bytes, Self::Error> {
+        let encoded = rkyv::to_bytes::<rancor::Error>(value)?;
+        Ok(Bytes::copy_from_slice(encoded.as_ref()))
+    }
+}
+
+impl<T> Decodes<T> for AlignedRkyvEncoding
+where
+    T: Archive,
+    T::Archived: rkyv::Deserialize<T, RkyvDeserializer>
+        + for<'a> CheckBytes<RkyvValidator<'a>>,
+{
+    type Error = rancor::Error;
+
+    fn decode(bytes: Bytes) -> Result<T, Self::Error> {
+        let mut aligned = AlignedVec::<16>::with_capacity(bytes.len());
+        aligned.extend_from_slice(&bytes);
+        rkyv::from_bytes::<T, rancor::Error>(&aligned)
+    }
+}
+
+/// Pass arguments and receive responses as aligned `rkyv` in a `POST` request.
+pub type AlignedRkyv = Post<AlignedRkyvEncoding>;
+
+/// An error encoding that writes errors like `E` does, and on the server also
+/// sets the response's status from the error's [`HasStatusCode`], instead of
+/// the 500 server_fn answers every error with. In debug builds the server
+/// also keeps the error for the [dev overlay](crate::dev_overlay).
+///
+/// Use it as a custom error type's `FromServerFnError::Encoder`.
+pub struct WithStatus<E>(PhantomData<E>);
+
+impl<E: ContentType> ContentType for WithStatus<E> {
+    const CONTENT_TYPE: &'static str = E::CONTENT_TYPE;
+}
+
+impl<E: FormatType> FormatType for WithStatus<E> {
+    const FORMAT_TYPE: Format = E::FORMAT_TYPE;
+}
+
+impl<E, T> Encodes<T> for WithStatus<E>
+where
+    E: Encodes<T>,
+    T: HasStatusCode + std::error::Error,
+{
+    type Error = E::Error;
+
+    fn encode(value: &T) -> Result<Bytes, Self::Error> {
+        // errors are only encoded on the server to answer with them
+        #[cfg(feature = "ssr")]
+        {
+            crate::errors::set_error_status(value);
+            crate::dev_overlay::record_error_chain(value);
+        }
+        E::encode(value)
+    }
+}
+
+impl<E: Decodes<T>, T> Decodes<T> for WithStatus<E> {
+    type Error = E::Error;
+
+    fn decode(bytes: Bytes) -> Result<T, Self::Error> {
+        E::decode(bytes)
+    }
+}
+
+/// Arguments as flat, human-readable query parameters of a `GET` request.
+///
+/// Unlike `GetUrl`, which nests and indexes (`tags[0]=a&tags[1]=b`), every
+/// argument is one parameter under its serde name, sequences are repeated
+/// parameters (`tag=a&tag=b`), and parameters are always in the same order,
+/// so the URLs are easy to read, bookmark and cache. See [`crate::query`]
+/// for what the arguments can be. Use it with `custom = QueryEncoded`.
+pub struct QueryUrl;
+
+/// Wraps a server fn's arguments to encode them with [`QueryUrl`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct QueryEncoded<T>(pub T);
+
+impl ContentType for QueryUrl {
+    const CONTENT_TYPE: &'static str = "application/x-www-form-urlencoded";
+}
+
+impl Encoding for QueryUrl {
+    const METHOD: Method = Method::GET;
+}
+
+impl<T, Request, E> IntoReq<QueryUrl, Request, E> for QueryEncoded<T>
+where
+    Request: ClientReq<E>,
+    T: Serialize,
+    E: FromServerFnError,
+{
+    fn into_req(self, path: &str, accepts: &str) -> Result<Request, E> {
+        let query = to_query_string(&self.0)
+            .map_err(|e| ServerFnErrorErr::Serialization(e).into_app_error())?;
+        Request::try_new_get(path, QueryUrl::CONTENT_TYPE, accepts, &query)
+    }
+}
+
+impl<T, Request, E> FromReq<QueryUrl, Request, E> for QueryEncoded<T>
+where
+    Request: Req<E> + Send,
+    T: DeserializeOwned,
+    E: FromServerFnError,
+{
+    async fn from_req(req: Request) -> Result<Self, E> {
+        from_query_string(req.as_query().unwrap_or_default())
+            .map(QueryEncoded)
+            .map_err(|e| ServerFnErrorErr::Args(e).into_app_error())
+    }
+}
+
+/// Arguments as a form body, the way a browser posts a `<form>` without any
+/// JavaScript.
+///
+/// Unlike `PostUrl`, nested structs are dotted names (`todo.due.days`),
+/// sequences are repeated inputs, checkboxes are `bool`s and blank number
+/// inputs are `None`; see [`crate::forms`]. Use it with
+/// `custom = FormEncoded`, and [`BoundForm`] to show the errors of each
+/// field next to its input.
+///
+/// [`BoundForm`]: crate::components::bound_form::BoundForm
+pub struct FormUrl;
+
+/// Wraps a server fn's arguments to encode them with [`FormUrl`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FormEncoded<T>(pub T);
+
+impl ContentType for FormUrl {
+    const CONTENT_TYPE: &'static str = "application/x-www-form-urlencoded";
+}
+
+impl Encoding for FormUrl {
+    const METHOD: Method = Method::POST;
+}
+
+impl<T, Request, E> IntoReq<FormUrl, Request, E> for FormEncoded<T>
+where
+    Request: ClientReq<E>,
+    T: Serialize,
+    E: FromServerFnError,
+{
+    fn into_req(self, path: &str, accepts: &str) -> Result<Request, E> {
+        let body = to_form_string(&self.0)
+            .map_err(|e| ServerFnErrorErr::Serialization(e).into_app_error())?;
+        Request::try_new_post(path, FormUrl::CONTENT_TYPE, accepts, body)
+    }
+}
+
+impl<T, Request, E> FromReq<FormUrl, Request, E> for FormEncoded<T>
+where
+    Request: Req<E> + Send,
+    T: DeserializeOwned,
+    E: FromServerFnError,
+{
+    async fn from_req(req: Request) -> Result<Self, E> {
+        let body = req.try_into_string().await?;
+        from_form_string(&body)
+            .map(FormEncoded)
+            .map_err(|e| ServerFnErrorErr::Args(e.to_string()).into_app_error())
+    }
+}
+
+/// Newline-delimited JSON frames, one per stream item.
+///
+/// Unlike `StreamingText`, an error raised by the server while streaming is
+/// sent to the client as a final frame instead of silently cutting the
+/// response short. Use [`FramedStream`] as the server fn's return type.
+pub struct Framed;
+
+impl ContentType for Framed {
+    const CONTENT_TYPE: &'static str = "application/x-ndjson";
+}
+
+impl Encoding for Framed {
+    const METHOD: Method = Method::POST;
+}
+
+/// A stream of `T` whose first error, if any, is also its last item.
+pub struct FramedStream<T, E = ServerFnError>(
+    Pin<Box<dyn Stream<Item = Result<T, E>> + Send>>,
+);
+
+impl<T, E> FramedStream<T, E> {
+    pub fn new(
+        value: impl Stream<Item = Result<T, E>> + Send + 'static,
+    ) -> Self {
+        Self(Box::pin(value))
+    }
+
+    pub fn into_inner(self) -> impl Stream<Item = Result<T, E>> + Send {
+        self.0
+    }
+}
+
+impl<T, E> Debug for FramedStream<T, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FramedStream").finish()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Frame<T> {
+    Data(T),
+    /// The error, serialized with its own (text) encoding.
+    Error(String),
+    /// The error, serialized with its own (binary) encoding.
+    BinaryError(Vec<u8>),
+}
+
+fn encode_frame<T, E>(item: Result<T, E>) -> Bytes
+where
+    T: Serialize,
+    E: FromServerFnError,
+{
+    let frame = match item {
+        Ok(data) => Frame::Data(data),
+        Err(e) => match String::from_utf8(e.ser().to_vec()) {
+            Ok(text) => Frame::Error(text),
+            Err(e) => Frame::BinaryError(e.into_bytes()),
+        },
+    };
+    let mut line = serde_json::to_vec(&frame).unwrap_or_else(|e| {
+        let e = E::from_server_fn_error(ServerFnErrorErr::Serialization(
+            e.to_string(),
+        ));
+        serde_json::to_vec(&Frame::<()>::Error(
+            String::from_utf8_lossy(&e.ser()).into_owned(),
+        ))
+        .expect("a string frame always serializes")
+    });
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+fn decode_frame<T, E>(line: &[u8]) -> Result<T, E>
+where
+    T: DeserializeOwned,
+    E: FromServerFnError,
+{
+    match serde_json::from_slice::<Frame<T>>(line) {
+        Ok(Frame::Data(data)) => Ok(data),
+        Ok(Frame::Error(text)) => Err(E::de(Bytes::from(text))),
+        Ok(Frame::BinaryError(bytes)) => Err(E::de(Bytes::from(bytes))),
+        Err(e) => {
+            Err(ServerFnErrorErr::Deserialization(e.to_string())
+                .into_app_error())
+        }
+    }
+}
+
+/// The longest frame [`FrameReader`] buffers before giving up on the stream,
+/// so a response that never sends a newline can't grow it without bound.
+pub const MAX_FRAME_LEN: usize = 8 * 1024 * 1024;
+
+/// Splits the body of a [`Framed`] response back into items, however its
+/// frames are split across (or share) network chunks.
+///
+/// Like [`FramedStream`], yields nothing more after its first error.
+pub struct FrameReader<T, E = ServerFnError> {
+    buf: Vec<u8>,
+    /// How much of `buf` is known not to contain a newline.
+    scanned: usize,
+    done: bool,
+    _items: PhantomData<fn() -> (T, E)>,
+}
+
+impl<T, E> Default for FrameReader<T, E> {
+    fn default() -> Self {
+        Self {
+            buf: Vec::new(),
+            scanned: 0,
+            done: false,
+            _items: PhantomData,
+        }
+    }
+}
+
+impl<T, E> FrameReader<T, E>
+where
+    T: DeserializeOwned,
+    E: FromServerFnError,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends the next chunk of the body.
+    pub fn push(&mut self, chunk: &[u8]) {
+        if !self.done {
+            self.buf.extend_from_slice(chunk);
+        }
+    }
+
+    /// The next complete frame, if one has arrived.
+    pub fn next_item(&mut self) -> Option<Result<T, E>> {
+        if self.done {
+            return None;
+        }
+        let Some(end) = self.buf[self.scanned..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map(|i| self.scanned + i)
+        else {
+            self.scanned = self.buf.len();
+            return (self.buf.len() > MAX_FRAME_LEN).then(|| {
+                self.fail(format!("a frame is over {MAX_FRAME_LEN} bytes"))
+            });
+        };
+        let line = self.buf.drain(..=end).collect::<Vec<_>>();
+        self.scanned = 0;
+        let item = decode_frame(&line[..end]);
+        self.done = item.is_err();
+        Some(item)
+    }
+
+    /// Ends the body: an error if it stopped in the middle of a frame.
+    pub fn finish(&mut self) -> Option<Result<T, E>> {
+        if self.done || self.buf.is_empty() {
+            self.done = true;
+            return None;
+        }
+        Some(self.fail("stream ended in the middle of a frame".into()))
+    }
+
+    /// Whether no more items will be yielded.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Stops yielding items, after the stream itself failed.
+    pub fn close(&mut self) {
+        self.done = true;
+        self.buf = Vec::new();
+    }
+
+    fn fail(&mut self, message: String) -> Result<T, E> {
+        self.close();
+        Err(ServerFnErrorErr::Deserialization(message).into_app_error())
+    }
+}
+
+impl<T, E, Response> IntoRes<Framed, Response, E> for FramedStream<T, E>
+where
+    Response: TryRes<E>,
+    T: Serialize + Send + 'static,
+    E: FromServerFnError + Send,
+{
+    async fn into_res(self) -> Result<Response, E> {
+        // stop after the first error so it is always the final frame
+        let frames = self.0.scan(false, |errored, item| {
+            let frame = (!*errored).then(|| {
+                *errored = item.is_err();
+                Ok(encode_frame(item))
+            });
+            future::ready(frame)
+        });
+        Response::try_from_stream(Framed::CONTENT_TYPE, frames)
+    }
+}
+
+impl<T, E, Response> FromRes<Framed, Response, E> for FramedStream<T, E>
+where
+    Response: ClientRes<E> + Send,
+    T: DeserializeOwned + Send + 'static,
+    E: FromServerFnError + Send,
+{
+    async fn from_res(res: Response) -> Result<Self, E> {
+        let chunks = res.try_into_stream()?;
+        Ok(FramedStream::new(read_frames(chunks, FrameReader::new())))
+    }
+}
+
+/// Incremental parsing of a response body made of frames, like
+/// [`FrameReader`] and [`ChunkReader`].
+trait FrameDecoder<T, E>: Send + 'static {
+    fn push(&mut self, chunk: &[u8]);
+    fn next_item(&mut self) -> Option<Result<T, E>>;
+    fn finish(&mut self) -> Option<Result<T, E>>;
+    fn is_done(&self) -> bool;
+    fn close(&mut self);
+}
+
+impl<T, E> FrameDecoder<T, E> for FrameReader<T, E>
+where
+    T: DeserializeOwned + 'static,
+    E: FromServerFnError + 'static,
+{
+    fn push(&mut self, chunk: &[u8]) {
+        FrameReader::push(self, chunk)
+    }
+
+    fn next_item(&mut self) -> Option<Result<T, E>> {
+        FrameReader::next_item(self)
+    }
+
+    fn finish(&mut self) -> Option<Result<T, E>> {
+        FrameReader::finish(self)
+    }
+
+    fn is_done(&self) -> bool {
+        FrameReader::is_done(self)
+    }
+
+    fn close(&mut self) {
+        FrameReader::close(self)
+    }
+}
+
+/// The items `reader` finds in `chunks`, the body of a response.
+fn read_frames<T, E, D>(
+    chunks: impl Stream<Item = Result<Bytes, Bytes>> + Send + 'static,
+    reader: D,
+) -> impl Stream<Item = Result<T, E>> + Send
+where
+    T: Send + 'static,
+    E: FromServerFnError + Send,
+    D: FrameDecoder<T, E>,
+{
+    stream::unfold(
+        (Box::pin(chunks), reader),
+        |(mut chunks, mut reader)| async move {
+            loop {
+                if let Some(item) = reader.next_item() {
+                    return Some((item, (chunks, reader)));
+                }
+                if reader.is_done() {
+                    return None;
+                }
+                match chunks.next().await {
+                    Some(Ok(bytes)) => reader.push(&bytes),
+                    Some(Err(bytes)) => {
+                        reader.close();
+                        return Some((Err(E::de(bytes)), (chunks, reader)));
+                    }
+                    None => {
+                        let item = reader.finish()?;
+                        return Some((item, (chunks, reader)));
+                    }
+                }
+            }
+        },
+    )
+}
+
+/// Length-prefixed `rkyv` archives, each holding a batch of items, which the
+/// client reads in place as they arrive instead of waiting for the whole
+/// response and deserializing it. Use [`RkyvChunkStream`] as the server fn's
+/// return type.
+///
+/// Every frame is a little-endian `u32` length and that many bytes: an
+/// archived `Vec<T>`, or, if the length's top bit is set, the server's
+/// error, serialized with its own encoding, as the final frame.
+pub struct RkyvChunks;
+
+impl ContentType for RkyvChunks {
+    const CONTENT_TYPE: &'static str = "application/x-rkyv-chunks";
+}
+
+impl Encoding for RkyvChunks {
+    const METHOD: Method = Method::POST;
+}
+
+/// Marks a [`RkyvChunks`] frame that holds an error.
+const ERROR_FRAME: u32 = 1 << 31;
+
+/// A batch of `T`s archived with `rkyv`, read in place with
+/// [`ArchivedChunk::items`].
+pub struct ArchivedChunk<T> {
+    /// Always a valid archive of a `Vec<T>`.
+    bytes: AlignedVec,
+    _items: PhantomData<fn() -> T>,
+}
+
+impl<T> ArchivedChunk<T>
+where
+    T: Archive,
+    Vec<T>: for<'a> rkyv::Serialize<RkyvSerializer<'a>>,
+{
+    pub fn new(items: Vec<T>) -> Result<Self, rancor::Error> {
+        Ok(Self {
+            bytes: rkyv::to_bytes::<rancor::Error>(&items)?,
+            _items: PhantomData,
+        })
+    }
+}
+
+impl<T> ArchivedChunk<T>
+where
+    T: Archive,
+    T::Archived: for<'a> CheckBytes<RkyvValidator<'a>>,
+{
+    /// Checks that `bytes` is a valid archive, once, so that reading it
+    /// later is free.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, rancor::Error> {
+        let mut aligned = AlignedVec::<16>::with_capacity(bytes.len());
+        aligned.extend_from_slice(bytes);
+        rkyv::access::<ArchivedVec<T::Archived>, rancor::Error>(&aligned)?;
+        Ok(Self {
+            bytes: aligned,
+            _items: PhantomData,
+        })
+    }
+}
+
+impl<T: Archive> ArchivedChunk<T> {
+    /// The items, without deserializing them.
+    pub fn items(&self) -> &ArchivedVec<T::Archived> {
+        // SAFETY: `bytes` is a valid archive: either `new` just wrote it or
+        // `from_bytes` checked it with `rkyv::access`, and it's immutable
+        unsafe {
+            rkyv::access_unchecked::<ArchivedVec<T::Archived>>(&self.bytes)
+        }
+    }
+
+    /// The archived bytes, as sent.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// A stream of [`ArchivedChunk`]s whose first error, if any, is also its
+/// last item.
+pub struct RkyvChunkStream<T, E = ServerFnError>(
+    Pin<Box<dyn Stream<Item = Result<ArchivedChunk<T>, E>> + Send>>,
+);
+
+impl<T, E> RkyvChunkStream<T, E> {
+    pub fn new(
+        chunks: impl Stream<Item = Result<ArchivedChunk<T>, E>> + Send + 'static,
+    ) -> Self {
+        Self(Box::pin(chunks))
+    }
+
+    pub fn into_inner(
+        self,
+    ) -> impl Stream<Item = Result<ArchivedChunk<T>, E>> + Send {
+        self.0
+    }
+}
+
+impl<T, E> RkyvChunkStream<T, E>
+where
+    T: Archive + 'static,
+    Vec<T>: for<'a> rkyv::Serialize<RkyvSerializer<'a>>,
+    E: FromServerFnError + 'static,
+{
+    /// Archives each batch of items as it comes.
+    pub fn from_batches(
+        batches: impl Stream<Item = Result<Vec<T>, E>> + Send + 'static,
+    ) -> Self {
+        Self::new(batches.map(|batch| {
+            ArchivedChunk::new(batch?).map_err(|e| {
+                ServerFnErrorErr::Serialization(e.to_string()).into_app_error()
+            })
+        }))
+    }
+}
+
+impl<T, E> Debug for RkyvChunkStream<T, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("RkyvChunkStream").finish()
+    }
+}
+
+fn encode_chunk<T, E>(item: Result<ArchivedChunk<T>, E>) -> Bytes
+where
+    E: FromServerFnError,
+{
+    let (flag, body) = match item {
+        Ok(chunk) if chunk.bytes.len() <= MAX_FRAME_LEN => {
+            (0, Bytes::copy_from_slice(&chunk.bytes))
+        }
+        Ok(chunk) => {
+            let e = E::from_server_fn_error(ServerFnErrorErr::Serialization(
+                format!(
+                    "a chunk of {} bytes is over {MAX_FRAME_LEN}",
+                    chunk.bytes.len()
+                ),
+            ));
+            (ERROR_FRAME, e.ser())
+        }
+        Err(e) => (ERROR_FRAME, e.ser()),
+    };
+    let mut frame = Vec::with_capacity(4 + body.len());
+    frame.extend_from_slice(&(body.len() as u32 | flag).to_le_bytes());
+    frame.extend_from_slice(&body);
+    Bytes::from(frame)
+}
+
+/// Splits the body of a [`RkyvChunks`] response back into chunks, however
+/// its frames are split across network chunks.
+///
+/// Like [`RkyvChunkStream`], yields nothing more after its first error.
+pub struct ChunkReader<T, E = ServerFnError> {
+    buf: Vec<u8>,
+    done: bool,
+    _items: PhantomData<fn() -> (T, E)>,
+}
+
+impl<T, E> Default for ChunkReader<T, E> {
+    fn default() -> Self {
+        Self {
+            buf: Vec::new(),
+            done: false,
+            _items: PhantomData,
+        }
+    }
+}
+
+impl<T, E> ChunkReader<T, E>
+where
+    T: Archive,
+    T::Archived: for<'a> CheckBytes<RkyvValidator<'a>>,
+    E: FromServerFnError,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends the next piece of the body.
+    pub fn push(&mut self, chunk: &[u8]) {
+        if !self.done {
+            self.buf.extend_from_slice(chunk);
+        }
+    }
+
+    /// The next complete chunk, if one has arrived.
+    pub fn next_item(&mut self) -> Option<Result<ArchivedChunk<T>, E>> {
+        let header = self.buf.get(..4).filter(|_| !self.done)?;
+        let header = u32::from_le_bytes(header.try_into().unwrap());
+        let len = (header & !ERROR_FRAME) as usize;
+        if len > MAX_FRAME_LEN {
+            return Some(
+                self.fail(format!("a chunk is over {MAX_FRAME_LEN} bytes")),
+            );
+        }
+        if self.buf.len() < 4 + len {
+            return None;
+        }
+        let frame = self.buf.drain(..4 + len).skip(4).collect::<Vec<_>>();
+        let item = if header & ERROR_FRAME == 0 {
+            ArchivedChunk::from_bytes(&frame).map_err(|e| {
+                ServerFnErrorErr::Deserialization(e.to_string())
+                    .into_app_error()
+            })
+        } else {
+            Err(E::de(Bytes::from(frame)))
+        };
+        self.done = item.is_err();
+        Some(item)
+    }
+
+    /// Ends the body: an error if it stopped in the middle of a frame.
+    pub fn finish(&mut self) -> Option<Result<ArchivedChunk<T>, E>> {
+        if self.done || self.buf.is_empty() {
+            self.done = true;
+            return None;
+        }
+        Some(self.fail("stream ended in the middle of a chunk".into()))
+    }
+
+    /// Whether no more chunks will be yielded.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Stops yielding chunks, after the stream itself failed.
+    pub fn close(&mut self) {
+        self.done = true;
+        self.buf = Vec::new();
+    }
+
+    fn fail(&mut self, message: String) -> Result<ArchivedChunk<T>, E> {
+        self.close();
+        Err(ServerFnErrorErr::Deserialization(message).into_app_error())
+    }
+}
+
+impl<T, E> FrameDecoder<ArchivedChunk<T>, E> for ChunkReader<T, E>
+where
+    T: Archive + 'static,
+    T::Archived: for<'a> CheckBytes<RkyvValidator<'a>>,
+    E: FromServerFnError + 'static,
+{
+    fn push(&mut self, chunk: &[u8]) {
+        ChunkReader::push(self, chunk)
+    }
+
+    fn next_item(&mut self) -> Option<Result<ArchivedChunk<T>, E>> {
+        ChunkReader::next_item(self)
+    }
+
+    fn finish(&mut self) -> Option<Result<ArchivedChunk<T>, E>> {
+        ChunkReader::finish(self)
+    }
+
+    fn is_done(&self) -> bool {
+        ChunkReader::is_done(self)
+    }
+
+    fn close(&mut self) {
+        ChunkReader::close(self)
+    }
+}
+
+impl<T, E, Response> IntoRes<RkyvChunks, Response, E> for RkyvChunkStream<T, E>
+where
+    Response: TryRes<E>,
+    T: Send + 'static,
+    E: FromServerFnError + Send,
+{
+    async fn into_res(self) -> Result<Response, E> {
+        // stop after the first error so it is always the final frame
+        let frames = self.0.scan(false, |errored, item| {
+            let frame = (!*errored).then(|| {
+                *errored = item.is_err();
+                Ok(encode_chunk(item))
+            });
+            future::ready(frame)
+        });
+        Response::try_from_stream(RkyvChunks::CONTENT_TYPE, frames)
+    }
+}
+
+impl<T, E, Response> FromRes<RkyvChunks, Response, E> for RkyvChunkStream<T, E>
+where
+    Response: ClientRes<E> + Send,
+    T: Archive + Send + 'static,
+    T::Archived: for<'a> CheckBytes<RkyvValidator<'a>>,
+    E: FromServerFnError + Send,
+{
+    async fn from_res(res: Response) -> Result<Self, E> {
+        let chunks = res.try_into_stream()?;
+        Ok(RkyvChunkStream::new(read_frames(
+            chunks,
+            ChunkReader::new(),
+        )))
+    }
+}
+
+/// A message type sent over a [`TaggedRkyvEncoding`] socket.
+pub trait FrameType: Archive {
+    /// Tells this type's frames apart from other types'.
+    const TAG: u8;
+    /// Bumped whenever the archived layout changes, so frames from a client
+    /// or server built before that are rejected rather than misread.
+    const VERSION: u8;
+}
+
+/// Websocket frames of `rkyv` archives, read in place on arrival. Use
+/// [`ArchivedFrame`] as the item type of both streams.
+///
+/// Every frame is a [`FrameType::VERSION`] byte, a [`FrameType::TAG`]
+/// byte, and an archive of the message. A frame of another type or
+/// version fails to decode instead of being misread.
+pub struct TaggedRkyvEncoding;
+
+/// How many bytes of every [`TaggedRkyvEncoding`] frame come before the
+/// archive.
+pub const FRAME_HEADER_LEN: usize = 2;
+
+impl ContentType for TaggedRkyvEncoding {
+    const CONTENT_TYPE: &'static str = "application/x-tagged-rkyv";
+}
+
+impl FormatType for TaggedRkyvEncoding {
+    const FORMAT_TYPE: Format = Format::Binary;
+}
+
+impl<T: FrameType> Encodes<ArchivedFrame<T>> for TaggedRkyvEncoding {
+    type Error = FrameError;
+
+    fn encode(frame: &ArchivedFrame<T>) -> Result<Bytes, Self::Error> {
+        let mut bytes =
+            Vec::with_capacity(FRAME_HEADER_LEN + frame.bytes.len());
+        bytes.extend_from_slice(&[T::VERSION, T::TAG]);
+        bytes.extend_from_slice(&frame.bytes);
+        Ok(Bytes::from(bytes))
+    }
+}
+
+impl<T> Decodes<ArchivedFrame<T>> for TaggedRkyvEncoding
+where
+    T: FrameType,
+    T::Archived: for<'a> CheckBytes<RkyvValidator<'a>>,
+{
+    type Error = FrameError;
+
+    fn decode(bytes: Bytes) -> Result<ArchivedFrame<T>, Self::Error> {
+        let [version, tag, ..] = bytes[..] else {
+            return Err(FrameError::Truncated);
+        };
+        if version != T::VERSION {
+            return Err(FrameError::WrongVersion {
+                expected: T::VERSION,
+                found: version,
+            });
+        }
+        if tag != T::TAG {
+            return Err(FrameError::WrongType {
+                expected: T::TAG,
+                found: tag,
+            });
+        }
+        ArchivedFrame::from_bytes(&bytes[FRAME_HEADER_LEN..])
+            .map_err(|e| FrameError::Archive(e.to_string()))
+    }
+}
+
+/// One message archived with `rkyv`, read in place with
+/// [`ArchivedFrame::get`].
+pub struct ArchivedFrame<T> {
+    /// Always a valid archive of a `T`.
+    bytes: AlignedVec,
+    _message: PhantomData<fn() -> T>,
+}
+
+impl<T> ArchivedFrame<T>
+where
+    T: Archive + for<'a> rkyv::Serialize<RkyvSerializer<'a>>,
+{
+    pub fn new(message: &T) -> Result<Self, rancor::Error> {
+        Ok(Self {
+            bytes: rkyv::to_bytes::<rancor::Error>(message)?,
+            _message: PhantomData,
+        })
+    }
+}
+
+impl<T> ArchivedFrame<T>
+where
+    T: Archive,
+    T::Archived: for<'a> CheckBytes<RkyvValidator<'a>>,
+{
+    /// Checks that `bytes` is a valid archive, once, so that reading it
+    /// later is free. The bytes are copied once, since a websocket message
+    /// isn't aligned for reading in place.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, rancor::Error> {
+        let mut aligned = AlignedVec::<16>::with_capacity(bytes.len());
+        aligned.extend_from_slice(bytes);
+        rkyv::access::<T::Archived, rancor::Error>(&aligned)?;
+        Ok(Self {
+            bytes: aligned,
+            _message: PhantomData,
+        })
+    }
+}
+
+impl<T: Archive> ArchivedFrame<T> {
+    /// The message, without deserializing it.
+    pub fn get(&self) -> &T::Archived {
+        // SAFETY: `bytes` is a valid archive: either `new` just wrote it or
+        // `from_bytes` checked it with `rkyv::access`, and it's immutable
+        unsafe { rkyv::access_unchecked::<T::Archived>(&self.bytes) }
+    }
+
+    /// The archived bytes, without the header.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl<T> ArchivedFrame<T>
+where
+    T: Archive,
+    T::Archived: rkyv::Deserialize<T, RkyvDeserializer>,
+{
+    /// The message, deserialized.
+    pub fn deserialize(&self) -> Result<T, rancor::Error> {
+        rkyv::deserialize::<T, rancor::Error>(self.get())
+    }
+}
+
+impl<T> Debug for ArchivedFrame<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ArchivedFrame")
+            .field("len", &self.bytes.len())
+            .finish()
+    }
+}
+
+/// A `multipart/mixed` response of several named parts, such as a row's
+/// JSON and the bytes of one of its files, in one round trip. Use
+/// [`MixedParts`] as the server fn's return type.
+///
+/// Each part is sent as its own chunk, so large parts aren't copied into
+/// one buffer on the server. `ClientRes` doesn't expose the response's
+/// headers, so the client reads the boundary from the body's first line;
+/// the server never sends a preamble.
+pub struct MultipartMixed;
+
+impl ContentType for MultipartMixed {
+    const CONTENT_TYPE: &'static str = "multipart/mixed";
+}
+
+impl Encoding for MultipartMixed {
+    const METHOD: Method = Method::POST;
+}
+
+/// One part of a [`MultipartMixed`] response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MixedPart {
+    pub name: String,
+    /// Sent in the part's `Content-Disposition`, for parts that are files.
+    pub file_name: Option<String>,
+    pub content_type: String,
+    pub body: Bytes,
+}
+
+impl MixedPart {
+    pub fn new(
+        name: impl Into<String>,
+        content_type: impl Into<String>,
+        body: impl Into<Bytes>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            file_name: None,
+            content_type: content_type.into(),
+            body: body.into(),
+        }
+    }
+
+    /// A part holding `value` as JSON.
+    pub fn json(
+        name: impl Into<String>,
+        value: &impl Serialize,
+    ) -> Result<Self, ServerFnError> {
+        let body = serde_json::to_vec(value).map_err(|e| {
+            ServerFnErrorErr::Serialization(e.to_string()).into_app_error()
+        })?;
+        Ok(Self::new(name, "application/json", body))
+    }
+
+    pub fn with_file_name(mut self, file_name: impl Into<String>) -> Self {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    /// Decodes a part made by [`MixedPart::json`].
+    pub fn to_json<T: DeserializeOwned>(&self) -> Result<T, ServerFnError> {
+        serde_json::from_slice(&self.body).map_err(|e| {
+            ServerFnErrorErr::Deserialization(format!(
+                "part `{}`: {e}",
+                self.name
+            ))
+            .into_app_error()
+        })
+    }
+}
+
+/// The parts of a [`MultipartMixed`] response, in the order they're sent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MixedParts(pub Vec<MixedPart>);
+
+impl MixedParts {
+    pub fn new(parts: Vec<MixedPart>) -> Self {
+        Self(parts)
+    }
+
+    /// The first part named `name`, or an error saying it's missing.
+    pub fn get(&self, name: &str) -> Result<&MixedPart, ServerFnError> {
+        self.0.iter().find(|part| part.name == name).ok_or_else(|| {
+            ServerFnErrorErr::Deserialization(format!(
+                "the response has no `{name}` part"
+            ))
+            .into_app_error()
+        })
+    }
+}
+
+/// A boundary that occurs in none of `parts`.
+fn mixed_boundary(parts: &[MixedPart]) -> String {
+    (0_u64..)
+        .map(|n| format!("mixed-part-boundary-{n:x}"))
+        .find(|boundary| {
+            parts
+                .iter()
+                .all(|part| find(&part.body, boundary.as_bytes()).is_none())
+        })
+        .expect("some boundary is always free")
+}
+
+/// `value`, fit for a quoted header parameter.
+fn quotable(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+impl<E, Response> IntoRes<MultipartMixed, Response, E> for MixedParts
+where
+    Response: TryRes<E>,
+    E: FromServerFnError + Send,
+{
+    async fn into_res(self) -> Result<Response, E> {
+        let boundary = mixed_boundary(&self.0);
+        let mut chunks = Vec::with_capacity(self.0.len() * 2 + 1);
+        for part in self.0 {
+            let mut head = format!(
+                "--{boundary}\r\nContent-Disposition: inline; name=\"{}\"",
+                quotable(&part.name)
+            );
+            if let Some(file_name) = &part.file_name {
+                head.push_str(&format!(
+                    "; filename=\"{}\"",
+                    quotable(file_name)
+                ));
+            }
+            head.push_str(&format!(
+                "\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+                quotable(&part.content_type),
+                part.body.len()
+            ));
+            chunks.push(Ok(Bytes::from(head)));
+            chunks.push(Ok(part.body));
+            chunks.push(Ok(Bytes::from_static(b"\r\n")));
+        }
+        chunks.push(Ok(Bytes::from(format!("--{boundary}--\r\n"))));
+        Response::try_from_stream(
+            &format!("{}; boundary={boundary}", MultipartMixed::CONTENT_TYPE),
+            stream::iter(chunks),
+        )
+    }
+}
+
+impl<E, Response> FromRes<MultipartMixed, Response, E> for MixedParts
+where
+    Response: ClientRes<E> + Send,
+    E: FromServerFnError + Send,
+{
+    async fn from_res(res: Response) -> Result<Self, E> {
+        let body = res.try_into_bytes().await?;
+        parse_mixed(&body)
+            .map(MixedParts)
+            .map_err(|e| ServerFnErrorErr::Deserialization(e).into_app_error())
+    }
+}
+
+/// Splits a `multipart/mixed` body that starts with its first boundary, as
+/// [`MultipartMixed`] responses do.
+pub fn parse_mixed(body: &[u8]) -> Result<Vec<MixedPart>, String> {
+    let boundary = find(body, b"\r\n")
+        .and_then(|end| body[..end].strip_prefix(b"--"))
+        .filter(|boundary| !boundary.is_empty())
+        .ok_or("the body doesn't start with a boundary")?;
+    let mut rest = &body[boundary.len() + 4..];
+    // with no parts, the first line is also the closing one
+    if rest.is_empty() && boundary.ends_with(b"--") {
+        return Ok(Vec::new());
+    }
+    let delimiter = [&b"\r\n--"[..], boundary].concat();
+    let mut parts = Vec::new();
+    loop {
+        let head_end =
+            find(rest, b"\r\n\r\n").ok_or("a part's headers never end")?;
+        let head = std::str::from_utf8(&rest[..head_end])
+            .map_err(|_| "a part's headers aren't UTF-8")?;
+        rest = &rest[head_end + 4..];
+        let body_end = find(rest, &delimiter).ok_or("a part never ends")?;
+        parts.push(mixed_part(head, &rest[..body_end])?);
+        rest = &rest[body_end + delimiter.len()..];
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        rest = rest
+            .strip_prefix(b"\r\n")
+            .ok_or("a boundary is followed by something else")?;
+    }
+}
+
+fn mixed_part(head: &str, body: &[u8]) -> Result<MixedPart, String> {
+    let mut name = None;
+    let mut file_name = None;
+    let mut content_type = "application/octet-stream".to_string();
+    for line in head.split("\r\n") {
+        let (header, value) = line
+            .split_once(':')
+            .ok_or_else(|| format!("invalid part header `{line}`"))?;
+        let value = value.trim();
+        if header.eq_ignore_ascii_case("content-type") {
+            content_type = value.to_string();
+        } else if header.eq_ignore_ascii_case("content-disposition") {
+            for param in value.split(';').skip(1) {
+                let Some((key, value)) = param.trim().split_once('=') else {
+                    continue;
+                };
+                let value = value.trim_matches('"').to_string();
+                match key {
+                    "name" => name = Some(value),
+                    "filename" => file_name = Some(value),
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(MixedPart {
+        name: name.ok_or("a part has no name")?,
+        file_name,
+        content_type,
+        body: Bytes::copy_from_slice(body),
+    })
+}
+
+/// An output encoding that answers in JSON, CBOR or TOML, whichever the
+/// request's `Accept` header prefers. Use [`Negotiated`] as the server fn's
+/// return type.
+///
+/// Server fn clients always ask for (and decode) JSON, because the response
+/// headers aren't visible to them; the other formats are for other clients.
+pub struct Negotiate;
+
+impl ContentType for Negotiate {
+    const CONTENT_TYPE: &'static str = "application/json";
+}
+
+impl Encoding for Negotiate {
+    const METHOD: Method = Method::POST;
+}
+
+/// A format [`Negotiate`] can answer in.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Display,
+    EnumString,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum NegotiatedFormat {
+    #[default]
+    #[strum(serialize = "application/json")]
+    Json,
+    #[strum(serialize = "application/cbor")]
+    Cbor,
+    #[strum(serialize = "application/toml")]
+    Toml,
+}
+
+impl NegotiatedFormat {
+    pub const ALL: [NegotiatedFormat; 3] = [
+        NegotiatedFormat::Json,
+        NegotiatedFormat::Cbor,
+        NegotiatedFormat::Toml,
+    ];
+
+    /// The format `accept` gives the highest quality, or JSON if it accepts
+    /// none of them. On ties, formats named outright beat ones matched by a
+    /// wildcard, and then earlier ones in [`NegotiatedFormat::ALL`] win.
+    pub fn from_accept(accept: &str) -> Self {
+        let mut best = None::<((f32, bool), NegotiatedFormat)>;
+        for range in accept.split(',') {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default();
+            let quality = params
+                .find_map(|param| param.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let (formats, named) = match media_type {
+                "*/*" | "application/*" => (Self::ALL.to_vec(), false),
+                media_type => (media_type.parse().into_iter().collect(), true),
+            };
+            let rank = (quality, named);
+            for format in formats {
+                let better = best.is_none_or(|(best_rank, best)| {
+                    rank > best_rank || (rank == best_rank && format < best)
+                });
+                if quality > 0.0 && better {
+                    best = Some((rank, format));
+                }
+            }
+        }
+        best.map(|(_, format)| format).unwrap_or_default()
+    }
+}
+
+/// A server fn result, and the format [`Negotiate`] will send it in.
+#[derive(Debug, Clone)]
+pub struct Negotiated<T> {
+    pub value: T,
+    pub format: NegotiatedFormat,
+}
+
+impl<T> Negotiated<T> {
+    pub fn new(value: T, format: NegotiatedFormat) -> Self {
+        Self { value, format }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl<T> Negotiated<T> {
+    /// `value`, in the format the current request's `Accept` header
+    /// prefers.
+    pub async fn for_request(value: T) -> Result<Self, ServerFnError> {
+        let headers: http::HeaderMap = leptos_axum::extract().await?;
+        let accept = headers
+            .get(http::header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or_default();
+        Ok(Self::new(value, NegotiatedFormat::from_accept(accept)))
+    }
+}
+
+impl<T, E, Response> IntoRes<Negotiate, Response, E> for Negotiated<T>
+where
+    Response: TryRes<E>,
+    T: Serialize + Send,
+    E: FromServerFnError + Send,
+{
+    async fn into_res(self) -> Result<Response, E> {
+        let content_type = self.format.to_string();
+        let serialization_error = |e: String| -> E {
+            ServerFnErrorErr::Serialization(e).into_app_error()
+        };
+        match self.format {
+            NegotiatedFormat::Json => {
+                let body = serde_json::to_string(&self.value)
+                    .map_err(|e| serialization_error(e.to_string()))?;
+                Response::try_from_string(&content_type, body)
+            }
+            NegotiatedFormat::Cbor => {
+                let body = CborEncoding::encode(&self.value)
+                    .map_err(|e| serialization_error(e.to_string()))?;
+                Response::try_from_bytes(&content_type, body)
+            }
+            NegotiatedFormat::Toml => {
+                let body = toml::to_string(&self.value)
+                    .map_err(|e| serialization_error(e.to_string()))?;
+                Response::try_from_string(&content_type, body)
+            }
+        }
+    }
+}
+
+impl<T, E, Response> FromRes<Negotiate, Response, E> for Negotiated<T>
+where
+    Response: ClientRes<E> + Send,
+    T: DeserializeOwned,
+    E: FromServerFnError,
+{
+    async fn from_res(res: Response) -> Result<Self, E> {
+        let body = res.try_into_string().await?;
+        serde_json::from_str(&body)
+            .map(|value| Negotiated::new(value, NegotiatedFormat::Json))
+            .map_err(|e| {
+                ServerFnErrorErr::Deserialization(e.to_string())
+                    .into_app_error()
+            })
+    }
+}
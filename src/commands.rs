@@ -0,0 +1,310 @@
+//! Running a fixed set of commands on the server and streaming their
+//! output back, for admins. Nothing from the client ends up on a command
+//! line: it only picks a [`TaskId`].
+//!
+//! Commands run in the temp directory with stdin closed and only the
+//! environment they need, are killed after [`TASK_TIMEOUT`] or once the
+//! caller stops reading, and only [`MAX_RUNNING_TASKS`] run at once.
+
+use crate::codec::{Framed, FramedStream};
+use futures::StreamExt;
+use leptos::{prelude::*, task::spawn_local};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use strum::Display;
+
+/// A running command is killed after this long.
+pub const TASK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many commands can run at once, across every admin.
+pub const MAX_RUNNING_TASKS: usize = 2;
+
+/// The most lines of output the terminal keeps.
+pub const MAX_TERMINAL_LINES: usize = 1000;
+
+/// A command that can be run.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display,
+)]
+pub enum TaskId {
+    #[strum(serialize = "cargo --version")]
+    CargoVersion,
+    #[strum(serialize = "rustc --version --verbose")]
+    RustcVersion,
+    /// A script counting down on stdout with a note on stderr every
+    /// second, to show the two interleaving.
+    #[strum(serialize = "countdown script")]
+    Countdown,
+    /// A script that writes a line to each stream and exits with 3.
+    #[strum(serialize = "failing script")]
+    Failing,
+}
+
+impl TaskId {
+    pub const ALL: [TaskId; 4] = [
+        TaskId::CargoVersion,
+        TaskId::RustcVersion,
+        TaskId::Countdown,
+        TaskId::Failing,
+    ];
+}
+
+/// A piece of a running command's output, or how it ended.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TaskFrame {
+    Stdout(String),
+    Stderr(String),
+    /// Always the last frame. `code` is `None` if the command was killed.
+    Exited {
+        code: Option<i32>,
+        timed_out: bool,
+    },
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{TaskFrame, TaskId, MAX_RUNNING_TASKS, TASK_TIMEOUT};
+    use crate::{
+        errors::TaskError,
+        progress::{self, Audience, ProgressKind},
+    };
+    use futures::{stream, Stream, StreamExt};
+    use std::{process::Stdio, sync::LazyLock};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncRead, BufReader},
+        process::Command,
+        sync::Semaphore,
+        time::Instant,
+    };
+
+    /// Lines of output past this many are dropped.
+    pub const MAX_OUTPUT_LINES: usize = 10_000;
+
+    /// The environment variables passed on to commands, so `cargo` and
+    /// `rustc` can still find their toolchain.
+    const PASSED_ENV: [&str; 5] = [
+        "PATH",
+        "HOME",
+        "CARGO_HOME",
+        "RUSTUP_HOME",
+        "RUSTUP_TOOLCHAIN",
+    ];
+
+    static RUNNING: LazyLock<Semaphore> =
+        LazyLock::new(|| Semaphore::new(MAX_RUNNING_TASKS));
+
+    impl TaskId {
+        fn command(self) -> Command {
+            let (program, args): (&str, &[&str]) = match self {
+                TaskId::CargoVersion => ("cargo", &["--version"]),
+                TaskId::RustcVersion => ("rustc", &["--version", "--verbose"]),
+                TaskId::Countdown => (
+                    "sh",
+                    &[
+                        "-c",
+                        "for i in 5 4 3 2 1; do echo \"$i\"; \
+                         echo \"$i left\" >&2; sleep 1; done; echo liftoff",
+                    ],
+                ),
+                TaskId::Failing => (
+                    "sh",
+                    &[
+                        "-c",
+                        "echo starting; echo 'something broke' >&2; exit 3",
+                    ],
+                ),
+            };
+            let mut command = Command::new(program);
+            command.args(args);
+            command
+        }
+    }
+
+    /// Starts `task` and streams its output, a line per frame, then how
+    /// it exited.
+    pub fn run(
+        task: TaskId,
+    ) -> Result<impl Stream<Item = TaskFrame> + Send, TaskError> {
+        let permit = RUNNING.try_acquire().map_err(|_| TaskError::Busy {
+            max: MAX_RUNNING_TASKS,
+        })?;
+        let mut command = task.command();
+        command
+            .current_dir(std::env::temp_dir())
+            .env_clear()
+            .envs(PASSED_ENV.iter().filter_map(|name| {
+                std::env::var_os(name).map(|value| (name, value))
+            }))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let mut child = command.spawn().map_err(|e| TaskError::Spawn {
+            task: task.to_string(),
+            message: e.to_string(),
+        })?;
+        tracing::info!(%task, "running a task");
+        let report = progress::start(
+            Audience::Admins,
+            ProgressKind::Task,
+            task.to_string(),
+            None,
+        );
+
+        let deadline = Instant::now() + TASK_TIMEOUT;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let output = stream::select(
+            lines(stdout).map(TaskFrame::Stdout),
+            lines(stderr).map(TaskFrame::Stderr),
+        )
+        .take(MAX_OUTPUT_LINES)
+        .take_until(tokio::time::sleep_until(deadline));
+        let exited = stream::once(async move {
+            // held until the command is done with
+            let _permit = permit;
+            let (code, timed_out) =
+                match tokio::time::timeout_at(deadline, child.wait()).await {
+                    Ok(status) => (status.ok().and_then(|s| s.code()), false),
+                    Err(_) => {
+                        _ = child.kill().await;
+                        (None, true)
+                    }
+                };
+            match (code, timed_out) {
+                (_, true) => report.fail("timed out"),
+                (Some(0), _) => report.complete(Some("exited with 0".into())),
+                (Some(code), _) => report.fail(format!("exited with {code}")),
+                (None, _) => report.fail("killed"),
+            }
+            TaskFrame::Exited { code, timed_out }
+        });
+        Ok(output.chain(exited))
+    }
+
+    /// The lines `pipe` is written, until it's closed.
+    fn lines(
+        pipe: impl AsyncRead + Unpin + Send + 'static,
+    ) -> impl Stream<Item = String> + Send {
+        stream::unfold(BufReader::new(pipe).lines(), |mut lines| async move {
+            let line = lines.next_line().await.ok()??;
+            Some((line, lines))
+        })
+    }
+}
+
+/// Runs `task` and streams what it writes to stdout and stderr, in the
+/// order it's written, then its exit status.
+#[server(output = Framed)]
+#[middleware(crate::middleware::TwoFactorLayer)]
+pub async fn run_task(
+    task: TaskId,
+) -> Result<FramedStream<TaskFrame>, ServerFnError> {
+    crate::auth::require_admin()?;
+    let frames = run(task)?;
+    Ok(FramedStream::new(frames.map(Ok)))
+}
+
+/// A line of the terminal, and whether it came from stderr.
+type TerminalLine = (String, bool);
+
+/// Picks a [`TaskId`], runs it, and shows its output like a terminal.
+#[component]
+pub fn TaskRunner() -> impl IntoView {
+    let task = RwSignal::new(TaskId::CargoVersion);
+    let lines = RwSignal::new(Vec::<TerminalLine>::new());
+    let (running, set_running) = signal(false);
+    let (outcome, set_outcome) = signal(None::<String>);
+
+    let start = move |_| {
+        let task = task.get_untracked();
+        lines.set(vec![(format!("$ {task}"), false)]);
+        set_outcome.set(None);
+        set_running.set(true);
+        spawn_local(async move {
+            let add = move |line: TerminalLine| {
+                lines.update(|lines| {
+                    if lines.len() == MAX_TERMINAL_LINES {
+                        lines.remove(0);
+                    }
+                    lines.push(line);
+                })
+            };
+            let outcome = match run_task(task).await {
+                Ok(frames) => {
+                    let mut frames = frames.into_inner();
+                    let mut outcome = None;
+                    while let Some(frame) = frames.next().await {
+                        match frame {
+                            Ok(TaskFrame::Stdout(line)) => add((line, false)),
+                            Ok(TaskFrame::Stderr(line)) => add((line, true)),
+                            Ok(TaskFrame::Exited {
+                                timed_out: true, ..
+                            }) => {
+                                outcome = Some(format!(
+                                    "Killed after {}s.",
+                                    TASK_TIMEOUT.as_secs()
+                                ));
+                            }
+                            Ok(TaskFrame::Exited { code, .. }) => {
+                                outcome = Some(match code {
+                                    Some(code) => {
+                                        format!("Exited with {code}.")
+                                    }
+                                    None => "Killed.".to_string(),
+                                });
+                            }
+                            Err(e) => outcome = Some(e.to_string()),
+                        }
+                    }
+                    outcome.unwrap_or_else(|| "The stream ended early.".into())
+                }
+                Err(e) => e.to_string(),
+            };
+            _ = set_outcome.try_set(Some(outcome));
+            _ = set_running.try_set(false);
+        });
+    };
+
+    view! {
+        <h3>"Run a task"</h3>
+        <select on:change=move |ev| {
+            let index = event_target_value(&ev).parse::<usize>();
+            if let Some(id) = index.ok().and_then(|i| TaskId::ALL.get(i)) {
+                task.set(*id);
+            }
+        }>
+            {TaskId::ALL
+                .into_iter()
+                .enumerate()
+                .map(|(index, id)| {
+                    view! {
+                        <option value=index.to_string() selected=move || task.get() == id>
+                            {id.to_string()}
+                        </option>
+                    }
+                })
+                .collect::<Vec<_>>()}
+        </select>
+        <button on:click=start disabled=running>
+            "Run"
+        </button>
+        <div class="terminal">
+            {move || {
+                lines
+                    .get()
+                    .into_iter()
+                    .map(|(line, stderr)| {
+                        view! {
+                            <div class:stderr=stderr>{line}</div>
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            }}
+        </div>
+        {move || outcome.get().map(|outcome| view! { <p>{outcome}</p> })}
+    }
+}
@@ -0,0 +1,6 @@
+//! Components the examples share that aren't about any one of them.
+
+pub mod async_button;
+pub mod bound_form;
+pub mod flow;
+pub mod hint;
@@ -0,0 +1,122 @@
+//! A button that runs an async call, such as a server fn, on each click.
+
+use crate::{
+    error_messages::ErrorMessage,
+    errors::{ErrorCode, ErrorParams},
+};
+use leptos::{prelude::*, task::spawn_local};
+use std::{fmt::Display, future::Future, time::Duration};
+
+/// How long a button shows that its call worked.
+const SUCCESS_FLASH: Duration = Duration::from_millis(1500);
+
+/// How long a call's error stays next to the button.
+const ERROR_TOAST: Duration = Duration::from_secs(6);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CallState {
+    Idle,
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+/// An error as [`ErrorMessage`] shows it.
+#[derive(Debug, Clone)]
+struct ShownError {
+    code: String,
+    params: ErrorParams,
+    text: String,
+}
+
+/// A button that calls `action` when clicked, and is disabled until the
+/// call is done. It then briefly shows that the call worked, or shows its
+/// error next to itself, until the next click or for a few seconds.
+///
+/// `action` does whatever should happen with the call's result itself, such
+/// as setting a signal, and returns only whether it worked.
+#[component]
+pub fn AsyncButton<F, Fut, E>(action: F, children: Children) -> impl IntoView
+where
+    F: Fn() -> Fut + 'static,
+    Fut: Future<Output = Result<(), E>> + 'static,
+    E: ErrorCode + Display + 'static,
+{
+    let (state, set_state) = signal(CallState::Idle);
+    let (error, set_error) = signal(None::<ShownError>);
+    // bumped on every click; a flash or toast only ends if no newer click
+    // has happened since
+    let generation = StoredValue::new(0u64);
+    let settle = move |current: u64, after: Duration| {
+        set_timeout(
+            move || {
+                if generation.get_value() == current {
+                    set_state.set(CallState::Idle);
+                    set_error.set(None);
+                }
+            },
+            after,
+        );
+    };
+    let on_click = move |_| {
+        let current = generation.get_value() + 1;
+        generation.set_value(current);
+        set_state.set(CallState::Pending);
+        set_error.set(None);
+        let call = action();
+        spawn_local(async move {
+            let result = call.await;
+            if generation.get_value() != current {
+                return;
+            }
+            match result {
+                Ok(()) => {
+                    set_state.set(CallState::Succeeded);
+                    settle(current, SUCCESS_FLASH);
+                }
+                Err(e) => {
+                    set_state.set(CallState::Failed);
+                    set_error.set(Some(ShownError {
+                        code: e.code().to_string(),
+                        params: e.params(),
+                        text: e.to_string(),
+                    }));
+                    settle(current, ERROR_TOAST);
+                }
+            }
+        });
+    };
+    let pending = move || state.get() == CallState::Pending;
+
+    view! {
+        <span class="async-button">
+            <button
+                class:succeeded=move || state.get() == CallState::Succeeded
+                class:failed=move || state.get() == CallState::Failed
+                disabled=pending
+                aria-busy=move || pending().to_string()
+                on:click=on_click
+            >
+                {children()}
+            </button>
+            <span class="async-button-status" aria-live="polite">
+                {move || match state.get() {
+                    CallState::Pending => " ...",
+                    CallState::Succeeded => " \u{2713}",
+                    CallState::Idle | CallState::Failed => "",
+                }}
+            </span>
+            {move || {
+                error
+                    .get()
+                    .map(|ShownError { code, params, text }| {
+                        view! {
+                            <span class="async-button-error" role="alert">
+                                <ErrorMessage code params text />
+                            </span>
+                        }
+                    })
+            }}
+        </span>
+    }
+}
@@ -0,0 +1,133 @@
+//! Forms bound to a server fn's arguments with
+//! [`FormUrl`](crate::codec::FormUrl), which show the error of each field
+//! next to its input.
+
+use crate::{
+    error_messages::error_message,
+    errors::{AppError, FormError},
+    forms::from_form_string,
+};
+use leptos::{ev::SubmitEvent, form::form_data_from_event, prelude::*};
+use serde::de::DeserializeOwned;
+use server_fn::ServerFn;
+use std::collections::BTreeMap;
+
+/// The errors of the enclosing [`BoundForm`]'s fields, by their dotted
+/// names.
+#[derive(Clone, Copy)]
+struct BoundFields(RwSignal<BTreeMap<String, AppError>>);
+
+/// The error of the field named `name`, as the enclosing [`BoundForm`]
+/// shows it.
+fn use_field_error(
+    name: &'static str,
+) -> impl Fn() -> Option<AppError> + Copy + 'static {
+    let fields = use_context::<BoundFields>();
+    move || {
+        let BoundFields(errors) = fields?;
+        errors.with(|errors| errors.get(name).cloned())
+    }
+}
+
+/// A `<form>` that calls `action`'s server fn, like `<ActionForm/>`, but
+/// reads its inputs as [`crate::forms`] does: nested, repeated, checkboxes
+/// and typed numbers. Without JavaScript, the browser posts the same body
+/// on its own.
+///
+/// Inputs that can't be read are marked before anything is sent, and the
+/// server fn's [`FormError`] marks those it rejects; each shows in its
+/// [`FieldErrorMessage`].
+#[component]
+pub fn BoundForm<ServFn>(
+    action: ServerAction<ServFn>,
+    children: Children,
+) -> impl IntoView
+where
+    ServFn: ServerFn<Error = FormError>
+        + DeserializeOwned
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    ServFn::Output: Send + Sync + 'static,
+{
+    let errors = RwSignal::new(BTreeMap::new());
+    provide_context(BoundFields(errors));
+    let value = action.value();
+    // whatever the server says about the fields replaces what was shown
+    Effect::new(move |_| {
+        value.with(|result| {
+            if let Some(result) = result {
+                let fields = match result {
+                    Ok(_) => BTreeMap::new(),
+                    Err(e) => e.fields.clone(),
+                };
+                errors.set(fields);
+            }
+        })
+    });
+    let on_submit = move |ev: SubmitEvent| {
+        if ev.default_prevented() {
+            return;
+        }
+        ev.prevent_default();
+        let body = form_data_from_event(&ev)
+            .ok()
+            .and_then(|data| {
+                web_sys::UrlSearchParams::new_with_str_sequence_sequence(&data)
+                    .ok()
+            })
+            .and_then(|params| params.to_string().as_string())
+            .unwrap_or_default();
+        match from_form_string::<ServFn>(&body) {
+            Ok(input) => {
+                errors.set(BTreeMap::new());
+                action.dispatch(input);
+            }
+            Err(e) => value.set(Some(Err(e.into()))),
+        }
+    };
+
+    view! {
+        <form class="bound-form" action=ServFn::url() method="post" on:submit=on_submit>
+            {children()}
+        </form>
+    }
+}
+
+/// The error of the field named `name` in the enclosing [`BoundForm`], if
+/// it has one.
+#[component]
+pub fn FieldErrorMessage(
+    /// The field's dotted name, as its input's `name`.
+    name: &'static str,
+) -> impl IntoView {
+    let error = use_field_error(name);
+    move || {
+        error().map(|error| {
+            view! {
+                <span class="field-error" role="alert">
+                    {error_message(&error)}
+                </span>
+            }
+        })
+    }
+}
+
+/// A labelled input, or a few, with the field's error below them.
+#[component]
+pub fn Field(
+    /// The field's dotted name; the inputs still need it as their `name`.
+    name: &'static str,
+    label: &'static str,
+    children: Children,
+) -> impl IntoView {
+    let error = use_field_error(name);
+
+    view! {
+        <div class="field" class:invalid=move || error().is_some()>
+            <label>{label} {children()}</label>
+            <FieldErrorMessage name />
+        </div>
+    }
+}
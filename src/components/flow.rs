@@ -0,0 +1,109 @@
+//! Control flow for the examples' views, next to Leptos' own `<Show>` and
+//! `<For>`: each takes a value that may or may not be there yet and binds it
+//! with `let:` for its children.
+//!
+//! ```ignore
+//! <MatchResult result=action.value() let:count err=|e| error_message(&e)>
+//!     <p>{count} " rows"</p>
+//! </MatchResult>
+//! ```
+
+use leptos::{
+    control_flow::IntoOptionGetter,
+    either::{Either, EitherOf3},
+    prelude::*,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::marker::PhantomData;
+
+/// Shows its children with the value of an `Option` signal or closure while
+/// it's `Some`, and `fallback` while it's `None`. This is Leptos' own.
+pub use leptos::control_flow::ShowLet;
+
+/// Shows the latest result, if there is one yet: its children with the `Ok`
+/// value, or `err` with the error.
+#[component(transparent)]
+pub fn MatchResult<T, E, ChFn, V, ErrFn, W, M>(
+    /// Shown with the `Ok` value. Use `let:` to bind it.
+    children: ChFn,
+    /// Shown with the `Err` value.
+    err: ErrFn,
+    /// An `Option<Result<T, E>>` signal or closure, like an action's value.
+    result: impl IntoOptionGetter<Result<T, E>, M>,
+    /// Shown while there's no result. By default, nothing is.
+    #[prop(optional, into)]
+    fallback: ViewFn,
+    #[prop(optional)] _marker: PhantomData<(T, E, M)>,
+) -> impl IntoView
+where
+    ChFn: Fn(T) -> V + Send + 'static,
+    ErrFn: Fn(E) -> W + Send + 'static,
+    V: IntoView + 'static,
+    W: IntoView + 'static,
+    T: 'static,
+    E: 'static,
+{
+    let getter = result.into_option_getter();
+
+    move || match getter.run() {
+        Some(Ok(value)) => EitherOf3::A(children(value)),
+        Some(Err(e)) => EitherOf3::B(err(e)),
+        None => EitherOf3::C(fallback.run()),
+    }
+}
+
+/// Waits for `resource` in a `<Transition>`, then shows its children with
+/// the value. Unlike `<Await>`, the resource can load again, and keeps
+/// showing the old value meanwhile.
+#[component]
+pub fn AwaitWith<T, ChFn, V>(
+    resource: Resource<T>,
+    /// Shown with the loaded value. Use `let:` to bind it.
+    children: ChFn,
+    /// Shown until the first value has loaded.
+    #[prop(optional, into)]
+    fallback: ViewFn,
+) -> impl IntoView
+where
+    T: Clone + Send + Sync + Serialize + DeserializeOwned + 'static,
+    ChFn: Fn(T) -> V + Send + Sync + Clone + 'static,
+    V: IntoView + 'static,
+{
+    view! {
+        <Transition fallback>
+            {move || {
+                let children = children.clone();
+                Suspend::new(async move { children(resource.await) })
+            }}
+        </Transition>
+    }
+}
+
+/// Shows its children with a signal's value while `when` holds for it, and
+/// `fallback` otherwise, so `<Show>` can hand the value it checked on.
+#[component(transparent)]
+pub fn ShowWhen<T, P, ChFn, V>(
+    /// Shown with the value. Use `let:` to bind it.
+    children: ChFn,
+    #[prop(into)] value: Signal<T>,
+    /// Whether to show the children for a value.
+    when: P,
+    /// Shown while `when` doesn't hold. By default, nothing is.
+    #[prop(optional, into)]
+    fallback: ViewFn,
+) -> impl IntoView
+where
+    T: Clone + Send + Sync + 'static,
+    P: Fn(&T) -> bool + Send + 'static,
+    ChFn: Fn(T) -> V + Send + 'static,
+    V: IntoView + 'static,
+{
+    move || {
+        let value = value.get();
+        if when(&value) {
+            Either::Left(children(value))
+        } else {
+            Either::Right(fallback.run())
+        }
+    }
+}
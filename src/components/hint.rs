@@ -0,0 +1,39 @@
+//! Hints for first-time readers, which stay dismissed in their browser.
+
+use crate::persist::{use_persisted, Persisted};
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// The ids of the hints dismissed so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DismissedHints(pub BTreeSet<String>);
+
+impl Persisted for DismissedHints {
+    const KEY: &'static str = "dismissed_hints";
+}
+
+/// A hint that's shown until it's dismissed. `id` tells it apart from the
+/// other hints, and stays the same for as long as the hint says the same.
+#[component]
+pub fn Hint(id: &'static str, children: ChildrenFn) -> impl IntoView {
+    let dismissed = use_persisted::<DismissedHints>();
+    let shown = move || dismissed.with(|DismissedHints(ids)| !ids.contains(id));
+    let dismiss = move |_| {
+        dismissed.update(|DismissedHints(ids)| {
+            ids.insert(id.to_string());
+        })
+    };
+
+    view! {
+        <Show when=shown>
+            <aside class="hint">
+                {children()}
+                " "
+                <button on:click=dismiss title="Don't show this again">
+                    "×"
+                </button>
+            </aside>
+        </Show>
+    }
+}
@@ -0,0 +1,320 @@
+//! A counter streamed over two websockets at once, one with
+//! [`TaggedRkyvEncoding`] frames and one with JSON, to compare how many
+//! bytes each spends on the same ticks.
+//!
+//! server_fn sends every websocket message as a binary message, whatever
+//! the encoding, so what differs is only the payload: `rkyv` writes the
+//! fields as fixed-width integers, JSON writes their names and digits.
+
+use crate::{
+    codec::{ArchivedFrame, FrameType, TaggedRkyvEncoding, FRAME_HEADER_LEN},
+    supervisor::sleep,
+};
+use futures::{channel::oneshot, future, stream, Stream, StreamExt};
+use leptos::{prelude::*, task::spawn_local};
+use serde::{Deserialize, Serialize};
+use server_fn::{codec::JsonEncoding, BoxedStream, Websocket};
+use std::{pin::Pin, time::Duration};
+
+/// The rates the counter can be run at, in ticks per second.
+pub const COUNTER_RATES: [u32; 3] = [10, 100, 1000];
+
+/// How long each comparison runs.
+pub const COMPARISON_LENGTH: Duration = Duration::from_secs(5);
+
+/// What the client sends to start the counter, or to change its rate.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+)]
+pub struct CounterControl {
+    pub rate_hz: u32,
+}
+
+impl FrameType for CounterControl {
+    const TAG: u8 = 1;
+    const VERSION: u8 = 1;
+}
+
+/// One tick of the counter.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+)]
+pub struct CounterTick {
+    pub count: u64,
+    /// Since the counter started.
+    pub elapsed_micros: u64,
+}
+
+impl FrameType for CounterTick {
+    const TAG: u8 = 2;
+    const VERSION: u8 = 1;
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{CounterControl, CounterTick, COUNTER_RATES};
+    use futures::{stream, Stream, StreamExt};
+    use std::{pin::Pin, time::Duration};
+    use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+    struct Counter {
+        controls: Pin<Box<dyn Stream<Item = CounterControl> + Send>>,
+        /// `None` until the first control arrives.
+        interval: Option<Interval>,
+        count: u64,
+        started: Instant,
+    }
+
+    fn every(control: CounterControl) -> Interval {
+        let max = COUNTER_RATES[COUNTER_RATES.len() - 1];
+        let rate = control.rate_hz.clamp(1, max);
+        let mut interval = tokio::time::interval(Duration::from_secs(1) / rate);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        interval
+    }
+
+    /// Ticks at the rate the latest of `controls` asks for, until
+    /// `controls` ends.
+    pub fn count(
+        controls: impl Stream<Item = CounterControl> + Send + 'static,
+    ) -> impl Stream<Item = CounterTick> + Send + 'static {
+        let counter = Counter {
+            controls: Box::pin(controls),
+            interval: None,
+            count: 0,
+            started: Instant::now(),
+        };
+        stream::unfold(counter, |mut counter| async move {
+            loop {
+                let Some(interval) = &mut counter.interval else {
+                    let control = counter.controls.next().await?;
+                    counter.interval = Some(every(control));
+                    continue;
+                };
+                let control = tokio::select! {
+                    control = counter.controls.next() => control?,
+                    _ = interval.tick() => {
+                        counter.count += 1;
+                        let elapsed = counter.started.elapsed();
+                        let tick = CounterTick {
+                            count: counter.count,
+                            elapsed_micros: elapsed.as_micros() as u64,
+                        };
+                        return Some((tick, counter));
+                    }
+                };
+                counter.interval = Some(every(control));
+            }
+        })
+    }
+}
+
+/// The counter as [`TaggedRkyvEncoding`] frames.
+#[server(protocol = Websocket<TaggedRkyvEncoding, TaggedRkyvEncoding>)]
+pub async fn counter_rkyv(
+    controls: BoxedStream<ArchivedFrame<CounterControl>, ServerFnError>,
+) -> Result<BoxedStream<ArchivedFrame<CounterTick>, ServerFnError>, ServerFnError>
+{
+    let controls: Pin<Box<dyn Stream<Item = _> + Send>> = controls.into();
+    // a frame that can't be read ends the socket
+    let controls = controls
+        .map(|frame| frame.ok().and_then(|frame| frame.deserialize().ok()))
+        .take_while(|control| future::ready(control.is_some()))
+        .filter_map(future::ready);
+    let frames = count(controls).map(|tick| {
+        ArchivedFrame::new(&tick)
+            .map_err(|e| ServerFnError::Serialization(e.to_string()))
+    });
+    Ok(frames.into())
+}
+
+/// The counter as JSON frames.
+#[server(protocol = Websocket<JsonEncoding, JsonEncoding>)]
+pub async fn counter_json(
+    controls: BoxedStream<CounterControl, ServerFnError>,
+) -> Result<BoxedStream<CounterTick, ServerFnError>, ServerFnError> {
+    let controls: Pin<Box<dyn Stream<Item = _> + Send>> = controls.into();
+    let controls = controls
+        .map(Result::ok)
+        .take_while(|control| future::ready(control.is_some()))
+        .filter_map(future::ready);
+    Ok(count(controls).map(Ok).into())
+}
+
+/// What one socket got during a comparison.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SocketStats {
+    pub frames: u64,
+    /// Including each message's one byte marking it as an item rather
+    /// than an error.
+    pub bytes: u64,
+    pub last_count: u64,
+}
+
+impl SocketStats {
+    fn add(&mut self, count: u64, payload: usize) {
+        self.frames += 1;
+        self.bytes += 1 + payload as u64;
+        self.last_count = count;
+    }
+}
+
+/// A stream that sends `control` and then nothing more, ending once the
+/// returned sender is dropped, which closes the socket.
+fn controls<T: Send + 'static>(
+    control: T,
+) -> (
+    oneshot::Sender<()>,
+    impl Stream<Item = Result<T, ServerFnError>> + Send + 'static,
+) {
+    let (open, closed) = oneshot::channel::<()>();
+    let controls = stream::once(future::ready(Ok(control)))
+        .chain(stream::once(closed).filter_map(|_| future::ready(None)));
+    (open, controls)
+}
+
+async fn compare_rkyv(
+    rate_hz: u32,
+    stats: RwSignal<SocketStats>,
+) -> Result<(), ServerFnError> {
+    let control = ArchivedFrame::new(&CounterControl { rate_hz })
+        .map_err(|e| ServerFnError::Serialization(e.to_string()))?;
+    let (_open, controls) = controls(control);
+    let frames: Pin<Box<dyn Stream<Item = _> + Send>> =
+        counter_rkyv(controls.into()).await?.into();
+    let mut frames = frames.take_until(Box::pin(sleep(COMPARISON_LENGTH)));
+    while let Some(frame) = frames.next().await {
+        let frame = frame?;
+        // read in place: no deserializing, just a little-endian load
+        let count = frame.get().count.to_native();
+        let payload = FRAME_HEADER_LEN + frame.as_bytes().len();
+        stats.update(|stats| stats.add(count, payload));
+    }
+    Ok(())
+}
+
+async fn compare_json(
+    rate_hz: u32,
+    stats: RwSignal<SocketStats>,
+) -> Result<(), ServerFnError> {
+    let (_open, controls) = controls(CounterControl { rate_hz });
+    let ticks: Pin<Box<dyn Stream<Item = _> + Send>> =
+        counter_json(controls.into()).await?.into();
+    let mut ticks = ticks.take_until(Box::pin(sleep(COMPARISON_LENGTH)));
+    while let Some(tick) = ticks.next().await {
+        let tick = tick?;
+        // the same bytes the server's `JsonEncoding` wrote
+        let payload = serde_json::to_vec(&tick).map_or(0, |json| json.len());
+        stats.update(|stats| stats.add(tick.count, payload));
+    }
+    Ok(())
+}
+
+/// Runs the counter over both sockets side by side and shows what each
+/// spent on it.
+#[component]
+pub fn CounterSocketExample() -> impl IntoView {
+    let rate = RwSignal::new(COUNTER_RATES[1]);
+    let rkyv = RwSignal::new(SocketStats::default());
+    let json = RwSignal::new(SocketStats::default());
+    let (running, set_running) = signal(false);
+    let (error, set_error) = signal(None::<String>);
+
+    let start = move |_| {
+        let rate_hz = rate.get_untracked();
+        rkyv.set(SocketStats::default());
+        json.set(SocketStats::default());
+        set_error.set(None);
+        set_running.set(true);
+        spawn_local(async move {
+            let (rkyv_done, json_done) = future::join(
+                compare_rkyv(rate_hz, rkyv),
+                compare_json(rate_hz, json),
+            )
+            .await;
+            if let Err(e) = rkyv_done.and(json_done) {
+                _ = set_error.try_set(Some(e.to_string()));
+            }
+            _ = set_running.try_set(false);
+        });
+    };
+
+    let row = move |name: &'static str, stats: RwSignal<SocketStats>| {
+        view! {
+            <tr>
+                <td>{name}</td>
+                <td>{move || stats.get().frames}</td>
+                <td>{move || stats.get().bytes}</td>
+                <td>
+                    {move || {
+                        let stats = stats.get();
+                        if stats.frames == 0 {
+                            "-".to_string()
+                        } else {
+                            format!("{:.1}", stats.bytes as f64 / stats.frames as f64)
+                        }
+                    }}
+                </td>
+                <td>{move || stats.get().last_count}</td>
+            </tr>
+        }
+    };
+
+    view! {
+        <h3>"Binary websocket frames"</h3>
+        <p>
+            "Streams a counter over two websockets for "
+            {COMPARISON_LENGTH.as_secs()}
+            " seconds, one with tagged " <code>"rkyv"</code>
+            " frames and one with JSON, and counts the bytes each one got."
+        </p>
+        <select on:change=move |ev| {
+            if let Ok(rate_hz) = event_target_value(&ev).parse() {
+                rate.set(rate_hz);
+            }
+        }>
+            {COUNTER_RATES
+                .into_iter()
+                .map(|rate_hz| {
+                    view! {
+                        <option value=rate_hz.to_string() selected=move || rate.get() == rate_hz>
+                            {rate_hz} " ticks a second"
+                        </option>
+                    }
+                })
+                .collect::<Vec<_>>()}
+        </select>
+        <button on:click=start disabled=running>
+            "Compare"
+        </button>
+        <table>
+            <tr>
+                <th>"Encoding"</th>
+                <th>"Frames"</th>
+                <th>"Bytes"</th>
+                <th>"Bytes per frame"</th>
+                <th>"Count"</th>
+            </tr>
+            {row("rkyv", rkyv)}
+            {row("JSON", json)}
+        </table>
+        {move || error.get().map(|e| view! { <p>"The comparison failed: " {e}</p> })}
+    }
+}
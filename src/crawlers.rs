@@ -0,0 +1,181 @@
+//! Crawlers get a page that's complete as served: rendered in order, with
+//! no hydration scripts, and without the examples that hold a stream open.
+//! They can't use the app, so there's no point sending them the WASM or
+//! keeping a connection going for them.
+
+use leptos::prelude::*;
+
+/// Marks a page request as coming from a crawler. [`CrawlerLayer`] adds it
+/// to the request's extensions, and [`is_crawler`] reads it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crawler;
+
+/// Whether the page being rendered is for a crawler. Always false in the
+/// browser, since crawlers never hydrate.
+pub fn is_crawler() -> bool {
+    #[cfg(feature = "ssr")]
+    {
+        use_context::<http::request::Parts>()
+            .is_some_and(|parts| parts.extensions.get::<Crawler>().is_some())
+    }
+    #[cfg(not(feature = "ssr"))]
+    {
+        false
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::Crawler;
+    use crate::errors::CrawlerError;
+    use axum::body::Body;
+    use futures::future::Either;
+    use http::{header::USER_AGENT, HeaderMap, Method, Request, Response};
+    use leptos::prelude::{use_context, IntoView};
+    use leptos_axum::render_app_to_stream_in_order_with_context;
+    use std::{
+        collections::HashSet,
+        future::Future,
+        pin::Pin,
+        sync::Arc,
+        task::{Context, Poll},
+    };
+    use tower::{Layer, Service};
+
+    /// Lower-case fragments of the user agents crawlers, link previewers and
+    /// page auditors send. Most include "bot"; these are the ones that
+    /// don't, or are worth naming anyway.
+    const CRAWLER_AGENTS: [&str; 12] = [
+        "bot",
+        "crawler",
+        "spider",
+        "slurp",
+        "facebookexternalhit",
+        "embedly",
+        "quora link preview",
+        "bingpreview",
+        "lighthouse",
+        "headlesschrome",
+        "curl/",
+        "wget/",
+    ];
+
+    /// Whether `user_agent` is a crawler's, or some other client that only
+    /// wants the HTML.
+    pub fn is_crawler_agent(user_agent: &str) -> bool {
+        let user_agent = user_agent.to_ascii_lowercase();
+        CRAWLER_AGENTS
+            .iter()
+            .any(|fragment| user_agent.contains(fragment))
+    }
+
+    pub fn is_crawler_request(headers: &HeaderMap) -> bool {
+        headers
+            .get(USER_AGENT)
+            .and_then(|agent| agent.to_str().ok())
+            .is_some_and(is_crawler_agent)
+    }
+
+    /// An error for server fns that stream, if the current request is a
+    /// crawler's. The page they'd be called from isn't hydrated for
+    /// crawlers, so this only turns away those that find the URL anyway.
+    pub fn refuse_crawlers() -> Result<(), CrawlerError> {
+        let crawler = use_context::<http::request::Parts>()
+            .is_some_and(|parts| is_crawler_request(&parts.headers));
+        if crawler {
+            Err(CrawlerError::StreamRefused)
+        } else {
+            Ok(())
+        }
+    }
+
+    type PageFuture = Pin<Box<dyn Future<Output = Response<Body>> + Send>>;
+    type Render = Arc<dyn Fn(Request<Body>) -> PageFuture + Send + Sync>;
+
+    /// Renders crawlers' page requests itself, marked with [`Crawler`], and
+    /// passes everything else on. Crawlers' pages are streamed in order:
+    /// they don't run the scripts that put out-of-order chunks in place.
+    /// Apply it with `route_layer` to the router with the page routes; the
+    /// server fns registered alongside them are left alone.
+    #[derive(Clone)]
+    pub struct CrawlerLayer {
+        render: Render,
+        server_fn_paths: Arc<HashSet<&'static str>>,
+    }
+
+    impl CrawlerLayer {
+        /// Takes the same context and shell as the page routes.
+        pub fn new<IV: IntoView + 'static>(
+            additional_context: impl Fn() + Clone + Send + Sync + 'static,
+            app_fn: impl Fn() -> IV + Clone + Send + Sync + 'static,
+        ) -> Self {
+            Self {
+                render: Arc::new(move |req| {
+                    render_app_to_stream_in_order_with_context(
+                        additional_context.clone(),
+                        app_fn.clone(),
+                    )(req)
+                }),
+                server_fn_paths: Arc::new(
+                    server_fn::axum::server_fn_paths()
+                        .map(|(path, _)| path)
+                        .collect(),
+                ),
+            }
+        }
+    }
+
+    impl<S> Layer<S> for CrawlerLayer {
+        type Service = CrawlerService<S>;
+
+        fn layer(&self, inner: S) -> Self::Service {
+            CrawlerService {
+                inner,
+                render: Arc::clone(&self.render),
+                server_fn_paths: Arc::clone(&self.server_fn_paths),
+            }
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct CrawlerService<S> {
+        inner: S,
+        render: Render,
+        server_fn_paths: Arc<HashSet<&'static str>>,
+    }
+
+    impl<S> Service<Request<Body>> for CrawlerService<S>
+    where
+        S: Service<Request<Body>, Response = Response<Body>>,
+        S::Error: Send + 'static,
+    {
+        type Response = S::Response;
+        type Error = S::Error;
+        type Future = Either<
+            Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>,
+            S::Future,
+        >;
+
+        fn poll_ready(
+            &mut self,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+            let page = matches!(*req.method(), Method::GET | Method::HEAD)
+                && !self.server_fn_paths.contains(req.uri().path());
+            if page && is_crawler_request(req.headers()) {
+                req.extensions_mut().insert(Crawler);
+                let render = (self.render)(req);
+                Either::Left(Box::pin(async move { Ok(render.await) }))
+            } else {
+                Either::Right(self.inner.call(req))
+            }
+        }
+    }
+}
@@ -0,0 +1,574 @@
+//! An overlay over the page, in debug builds only, for the last server fn
+//! call that failed or the last panic: the error chain, the endpoint, what
+//! went over the wire, and what the server logged for that request.
+//!
+//! Failed calls are caught by [`TracingClient`](crate::clients::TracingClient)
+//! and panics by the hook [`hydrate`](crate::hydrate) sets, which shows them
+//! with [`show_panic`]. The server tags
+//! every server fn call with a request ID and keeps the log lines for the
+//! latest ones, which the overlay fetches with [`fetch_request_log`].
+
+use crate::errors::AppError;
+use chrono::{DateTime, Local, Utc};
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use server_fn::codec::GetUrl;
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+/// How many requests the server keeps log lines for.
+pub const MAX_LOGGED_REQUESTS: usize = 200;
+
+/// How many lines are kept for one request.
+pub const MAX_LOG_LINES: usize = 100;
+
+/// Longer request and response bodies are cut to this many bytes.
+pub const MAX_BODY_LEN: usize = 4 * 1024;
+
+/// How often the overlay looks for new errors.
+const OVERLAY_REFRESH: Duration = Duration::from_millis(250);
+
+/// One log line the server wrote while handling a request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogLine {
+    pub at: DateTime<Utc>,
+    pub level: String,
+    pub target: String,
+    /// The message, followed by the event's other fields.
+    pub text: String,
+}
+
+/// What the server kept about one request.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestLog {
+    pub lines: Vec<LogLine>,
+    /// The error the server fn failed with, then each of its sources.
+    pub chain: Vec<String>,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{LogLine, RequestLog, MAX_LOGGED_REQUESTS, MAX_LOG_LINES};
+    use crate::{errors::REQUEST_ID_HEADER, middleware::is_server_fn_path};
+    use chrono::Utc;
+    use http::{request::Parts, HeaderValue, Request, Response};
+    use leptos::prelude::use_context;
+    use pin_project_lite::pin_project;
+    use std::{
+        collections::VecDeque,
+        error::Error,
+        fmt::{self, Write},
+        future::Future,
+        pin::Pin,
+        sync::Mutex,
+        task::{ready, Context, Poll},
+    };
+    use tower::{Layer, Service};
+    use tracing::{
+        field::{Field, Visit},
+        instrument::{Instrument, Instrumented},
+        span::{Attributes, Id},
+        Event, Subscriber,
+    };
+    use tracing_subscriber::{layer, registry::LookupSpan};
+
+    /// The latest requests' logs, by request ID, oldest first.
+    static REQUEST_LOGS: Mutex<VecDeque<(String, RequestLog)>> =
+        Mutex::new(VecDeque::new());
+
+    /// Changes the log kept for `request_id`, starting one if there's none.
+    fn update_log(request_id: &str, update: impl FnOnce(&mut RequestLog)) {
+        let mut logs = REQUEST_LOGS.lock().unwrap();
+        let index = match logs.iter().position(|(id, _)| id == request_id) {
+            Some(index) => index,
+            None => {
+                if logs.len() == MAX_LOGGED_REQUESTS {
+                    logs.pop_front();
+                }
+                logs.push_back((request_id.to_string(), RequestLog::default()));
+                logs.len() - 1
+            }
+        };
+        update(&mut logs[index].1);
+    }
+
+    /// What the server kept about `request_id`, if it still has it.
+    pub fn request_log_for(request_id: &str) -> Option<RequestLog> {
+        REQUEST_LOGS
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(id, _)| id == request_id)
+            .map(|(_, log)| log.clone())
+    }
+
+    /// Keeps `error` and its sources for the request being handled, unless
+    /// an error was kept for it already: that's the one it started as, before
+    /// it was converted. Only debug builds keep anything.
+    pub fn record_error_chain(error: &dyn Error) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let request_id = use_context::<Parts>().and_then(|parts| {
+            let id = parts.headers.get(REQUEST_ID_HEADER)?;
+            Some(id.to_str().ok()?.to_string())
+        });
+        let Some(request_id) = request_id else {
+            return;
+        };
+        let mut chain = vec![error.to_string()];
+        let mut source = error.source();
+        while let Some(error) = source {
+            chain.push(error.to_string());
+            source = error.source();
+        }
+        update_log(&request_id, |log| {
+            if log.chain.is_empty() {
+                log.chain = chain;
+            }
+        });
+    }
+
+    /// The request ID a span was opened with.
+    struct SpanRequestId(String);
+
+    #[derive(Default)]
+    struct RequestIdVisitor(Option<String>);
+
+    impl Visit for RequestIdVisitor {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == "request_id" {
+                self.0 = Some(value.to_string());
+            }
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() == "request_id" {
+                self.0 = Some(format!("{value:?}").trim_matches('"').into());
+            }
+        }
+    }
+
+    /// Writes an event's message, then its other fields as `name=value`.
+    #[derive(Default)]
+    struct TextVisitor {
+        message: String,
+        fields: String,
+    }
+
+    impl Visit for TextVisitor {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() == "message" {
+                _ = write!(self.message, "{value:?}");
+            } else {
+                _ = write!(self.fields, " {}={value:?}", field.name());
+            }
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == "message" {
+                self.message.push_str(value);
+            } else {
+                _ = write!(self.fields, " {}={value}", field.name());
+            }
+        }
+    }
+
+    /// A `tracing` layer keeping every event logged inside a span with a
+    /// `request_id` field, as [`DevRequestLayer`] opens for each server fn
+    /// call, for [`fetch_request_log`](super::fetch_request_log).
+    pub struct DevLogCapture;
+
+    impl<S> layer::Layer<S> for DevLogCapture
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_new_span(
+            &self,
+            attrs: &Attributes<'_>,
+            id: &Id,
+            ctx: layer::Context<'_, S>,
+        ) {
+            let mut visitor = RequestIdVisitor::default();
+            attrs.record(&mut visitor);
+            if let (Some(request_id), Some(span)) = (visitor.0, ctx.span(id)) {
+                span.extensions_mut().insert(SpanRequestId(request_id));
+            }
+        }
+
+        fn on_event(&self, event: &Event<'_>, ctx: layer::Context<'_, S>) {
+            let Some(request_id) = ctx.event_scope(event).and_then(|scope| {
+                scope.into_iter().find_map(|span| {
+                    let extensions = span.extensions();
+                    extensions.get::<SpanRequestId>().map(|id| id.0.clone())
+                })
+            }) else {
+                return;
+            };
+            let mut text = TextVisitor::default();
+            event.record(&mut text);
+            let metadata = event.metadata();
+            let line = LogLine {
+                at: Utc::now(),
+                level: metadata.level().to_string(),
+                target: metadata.target().to_string(),
+                text: text.message + &text.fields,
+            };
+            update_log(&request_id, |log| {
+                if log.lines.len() < MAX_LOG_LINES {
+                    log.lines.push(line);
+                }
+            });
+        }
+    }
+
+    /// In debug builds, gives every server fn call a request ID, unless it
+    /// came with one, and runs it in a span carrying that ID, so
+    /// [`DevLogCapture`] keeps what's logged while handling it. The ID is
+    /// sent back in the [`REQUEST_ID_HEADER`].
+    #[derive(Clone, Copy, Default)]
+    pub struct DevRequestLayer;
+
+    impl<S> Layer<S> for DevRequestLayer {
+        type Service = DevRequestService<S>;
+
+        fn layer(&self, inner: S) -> Self::Service {
+            DevRequestService { inner }
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct DevRequestService<T> {
+        inner: T,
+    }
+
+    impl<T, ReqBody, ResBody> Service<Request<ReqBody>> for DevRequestService<T>
+    where
+        T: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    {
+        type Response = T::Response;
+        type Error = T::Error;
+        type Future = DevRequestFuture<T::Future>;
+
+        fn poll_ready(
+            &mut self,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+            let tagged =
+                cfg!(debug_assertions) && is_server_fn_path(req.uri().path());
+            let request_id = tagged.then(|| {
+                let request_id = req
+                    .headers()
+                    .get(REQUEST_ID_HEADER)
+                    .and_then(|id| id.to_str().ok())
+                    .map(str::to_string)
+                    .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+                if let Ok(value) = HeaderValue::from_str(&request_id) {
+                    req.headers_mut().insert(REQUEST_ID_HEADER, value);
+                }
+                request_id
+            });
+            let span = match &request_id {
+                Some(id) => tracing::info_span!("request", request_id = %id),
+                None => tracing::Span::none(),
+            };
+            DevRequestFuture {
+                inner: self.inner.call(req).instrument(span),
+                request_id,
+            }
+        }
+    }
+
+    pin_project! {
+        pub struct DevRequestFuture<T> {
+            #[pin]
+            inner: Instrumented<T>,
+            request_id: Option<String>,
+        }
+    }
+
+    impl<T, ResBody, E> Future for DevRequestFuture<T>
+    where
+        T: Future<Output = Result<Response<ResBody>, E>>,
+    {
+        type Output = T::Output;
+
+        fn poll(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Self::Output> {
+            let this = self.project();
+            let mut res = ready!(this.inner.poll(cx))?;
+            let Some(request_id) = this.request_id.take() else {
+                return Poll::Ready(Ok(res));
+            };
+            let answered = res
+                .headers()
+                .get(REQUEST_ID_HEADER)
+                .and_then(|id| id.to_str().ok())
+                .map(str::to_string);
+            match answered {
+                // a panic page comes with its own ID, which the logs are
+                // moved to, so the one the client sees finds them
+                Some(answered) if answered != request_id => {
+                    let log = {
+                        let mut logs = REQUEST_LOGS.lock().unwrap();
+                        let index =
+                            logs.iter().position(|(id, _)| *id == request_id);
+                        index.and_then(|index| logs.remove(index))
+                    };
+                    if let Some((_, log)) = log {
+                        update_log(&answered, |answered| {
+                            let mut lines = log.lines;
+                            lines.append(&mut answered.lines);
+                            answered.lines = lines;
+                        });
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    if let Ok(value) = HeaderValue::from_str(&request_id) {
+                        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+                    }
+                }
+            }
+            Poll::Ready(Ok(res))
+        }
+    }
+}
+
+/// What the server kept about `request_id`, for the overlay. Only debug
+/// builds keep anything; an unknown or forgotten ID has an empty log.
+#[server(input = GetUrl)]
+pub async fn fetch_request_log(
+    request_id: String,
+) -> Result<RequestLog, AppError> {
+    use crate::errors::DevLogError;
+
+    if !cfg!(debug_assertions) {
+        return Err(DevLogError::Disabled.into());
+    }
+    Ok(request_log_for(&request_id).unwrap_or_default())
+}
+
+/// A server fn call that didn't get a 2xx answer, or no answer at all, as
+/// the overlay shows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DevError {
+    pub at: DateTime<Utc>,
+    pub message: String,
+    pub method: String,
+    pub endpoint: String,
+    /// `None` if the server couldn't be reached.
+    pub status: Option<u16>,
+    pub request_id: Option<String>,
+    pub request_body: Option<String>,
+    pub response_body: Option<String>,
+}
+
+/// How many errors the overlay keeps until it's dismissed.
+const MAX_DEV_ERRORS: usize = 20;
+
+/// The errors the overlay hasn't been dismissed for yet, oldest first, and
+/// how many have been reported in all.
+static DEV_ERRORS: Mutex<(usize, VecDeque<DevError>)> =
+    Mutex::new((0, VecDeque::new()));
+
+/// Shows `error` in the overlay. Release builds drop it.
+pub fn report_dev_error(error: DevError) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    let mut errors = DEV_ERRORS.lock().unwrap();
+    errors.0 += 1;
+    errors.1.push_back(error);
+    if errors.1.len() > MAX_DEV_ERRORS {
+        errors.1.pop_front();
+    }
+}
+
+/// Shows a panic over the page right away. Nothing reactive runs after a
+/// panic, so this writes the overlay's HTML itself, with no way to dismiss
+/// it. Release builds show nothing.
+pub fn show_panic(message: &str, stack: Option<&str>) {
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    let Some(body) = document().body() else {
+        return;
+    };
+    let stack = stack
+        .map(|stack| {
+            format!("<dt>Stack</dt><dd><pre>{}</pre></dd>", escape(stack))
+        })
+        .unwrap_or_default();
+    _ = body.insert_adjacent_html(
+        "beforeend",
+        &format!(
+            "<div class=\"dev-overlay\" role=\"alertdialog\">\
+             <header><strong>Panic</strong> (reload the page to go on)</header>\
+             <dl><dt>Error chain</dt>\
+             <dd><ol class=\"chain\"><li>{}</li></ol></dd>{stack}</dl></div>",
+            escape(message)
+        ),
+    );
+}
+
+/// `body` cut to [`MAX_BODY_LEN`].
+pub fn truncate_body(mut body: String) -> String {
+    if body.len() > MAX_BODY_LEN {
+        let mut end = MAX_BODY_LEN;
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        body.truncate(end);
+        body.push_str("...");
+    }
+    body
+}
+
+/// The latest error reported with [`report_dev_error`], over the page until
+/// it's dismissed. Only debug builds show it.
+#[component]
+pub fn DevErrorOverlay() -> impl IntoView {
+    let (seen, set_seen) = signal(0_usize);
+    let (errors, set_errors) = signal(Vec::<DevError>::new());
+
+    Effect::new(move |_| {
+        if let Ok(handle) = set_interval_with_handle(
+            move || {
+                let (reported, pending) = {
+                    let errors = DEV_ERRORS.lock().unwrap();
+                    (errors.0, errors.1.iter().cloned().collect::<Vec<_>>())
+                };
+                if seen.get_untracked() != reported {
+                    set_seen.set(reported);
+                    set_errors.set(pending);
+                }
+            },
+            OVERLAY_REFRESH,
+        ) {
+            on_cleanup(move || handle.clear());
+        }
+    });
+    let dismiss = move |_| {
+        DEV_ERRORS.lock().unwrap().1.clear();
+        set_errors.set(Vec::new());
+    };
+
+    move || {
+        let errors = errors.get();
+        let count = errors.len();
+        errors.last().cloned().map(|error| {
+            view! {
+                <div class="dev-overlay" role="alertdialog">
+                    <header>
+                        <strong>"Server fn call failed"</strong>
+                        {(count > 1).then(|| format!(" (and {} more)", count - 1))}
+                        <button on:click=dismiss>"Dismiss"</button>
+                    </header>
+                    <DevErrorDetails error />
+                </div>
+            }
+        })
+    }
+}
+
+/// `lines` as the server would have printed them.
+fn log_text(lines: &[LogLine]) -> String {
+    lines
+        .iter()
+        .map(|line| {
+            format!(
+                "{} {:>5} {}: {}\n",
+                line.at.with_timezone(&Local).format("%H:%M:%S%.3f"),
+                line.level,
+                line.target,
+                line.text,
+            )
+        })
+        .collect()
+}
+
+#[component]
+fn DevErrorDetails(error: DevError) -> impl IntoView {
+    let log = error.request_id.clone().map(|request_id| {
+        LocalResource::new(move || fetch_request_log(request_id.clone()))
+    });
+    let at = error.at.with_timezone(&Local).format("%H:%M:%S");
+    let status = error
+        .status
+        .map_or_else(|| "no answer".to_string(), |status| status.to_string());
+    let endpoint = format!("{} {} → {status}", error.method, error.endpoint);
+
+    view! {
+        <dl>
+            <dt>"Error chain"</dt>
+            <dd>
+                <ol class="chain">
+                    <li>{error.message}</li>
+                    {log
+                        .map(|log| {
+                            view! {
+                                <Suspense>
+                                    {move || Suspend::new(async move {
+                                        log.await
+                                            .map(|log| {
+                                                log.chain
+                                                    .into_iter()
+                                                    .map(|cause| view! { <li>{cause}</li> })
+                                                    .collect::<Vec<_>>()
+                                            })
+                                            .unwrap_or_default()
+                                    })}
+                                </Suspense>
+                            }
+                        })}
+                </ol>
+            </dd>
+            <dt>"Endpoint"</dt>
+            <dd>
+                <code>{endpoint}</code>
+            </dd>
+            <dt>"When"</dt>
+            <dd>{at.to_string()}</dd>
+            {error.request_id.map(|id| view! { <dt>"Request ID"</dt><dd><code>{id}</code></dd> })}
+            {error.request_body.map(|body| view! { <dt>"Request body"</dt><dd><pre>{body}</pre></dd> })}
+            {error.response_body.map(|body| view! { <dt>"Response body"</dt><dd><pre>{body}</pre></dd> })}
+            {log
+                .map(|log| {
+                    view! {
+                        <dt>"Server log"</dt>
+                        <dd>
+                            <Suspense fallback=|| "Loading...">
+                                {move || Suspend::new(async move {
+                                    match log.await {
+                                        Ok(log) if log.lines.is_empty() => {
+                                            view! { <p>"Nothing logged."</p> }.into_any()
+                                        }
+                                        Ok(log) => {
+                                            view! { <pre class="log">{log_text(&log.lines)}</pre> }
+                                                .into_any()
+                                        }
+                                        Err(e) => view! { <p>{e.to_string()}</p> }.into_any(),
+                                    }
+                                })}
+                            </Suspense>
+                        </dd>
+                    }
+                })}
+        </dl>
+    }
+}
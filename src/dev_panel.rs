@@ -0,0 +1,86 @@
+//! A corner of the page showing what the client is up to, for development,
+//! and which tenant it's in. Only debug builds show it.
+
+use crate::{
+    clients::{queue_depth, Priority, QueueDepth, MAX_IN_FLIGHT},
+    persist::{use_persisted, Persisted},
+    tenants::TenantSwitcher,
+};
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How often the panel reads the client's state.
+const PANEL_REFRESH: Duration = Duration::from_millis(250);
+
+/// How the panel was left, which it stays as across reloads.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DevPanelPrefs {
+    /// Only the panel's title shows.
+    pub collapsed: bool,
+}
+
+impl Persisted for DevPanelPrefs {
+    const KEY: &'static str = "dev_panel";
+}
+
+/// How deep [`PriorityClient`](crate::clients::PriorityClient)'s queue is,
+/// by priority, and links to switch tenants if there are any.
+#[component]
+pub fn DevPanel() -> impl IntoView {
+    let (depth, set_depth) = signal(QueueDepth::default());
+    let prefs = use_persisted::<DevPanelPrefs>();
+    let collapsed = move || prefs.with(|prefs| prefs.collapsed);
+    let toggle = move |_| prefs.update(|prefs| prefs.collapsed ^= true);
+
+    Effect::new(move |_| {
+        if let Ok(handle) = set_interval_with_handle(
+            move || {
+                let now = queue_depth();
+                if depth.get_untracked() != now {
+                    set_depth.set(now);
+                }
+            },
+            PANEL_REFRESH,
+        ) {
+            on_cleanup(move || handle.clear());
+        }
+    });
+    let waiting = move |priority: Priority| {
+        move || depth.get().waiting[priority as usize]
+    };
+
+    view! {
+        <aside class="dev-panel">
+            <strong>"Call queue"</strong>
+            " "
+            <button
+                on:click=toggle
+                title=move || if collapsed() { "Show" } else { "Hide" }
+            >
+                {move || if collapsed() { "+" } else { "\u{2212}" }}
+            </button>
+            <table hidden=collapsed>
+                <tr>
+                    <th>"In flight"</th>
+                    <td>{move || depth.get().in_flight} " of " {MAX_IN_FLIGHT}</td>
+                </tr>
+                <tr>
+                    <th>"Urgent"</th>
+                    <td>{waiting(Priority::Urgent)}</td>
+                </tr>
+                <tr>
+                    <th>"Normal"</th>
+                    <td>{waiting(Priority::Normal)}</td>
+                </tr>
+                <tr>
+                    <th>"Background"</th>
+                    <td>{waiting(Priority::Background)}</td>
+                </tr>
+            </table>
+            <div hidden=collapsed>
+                <TenantSwitcher />
+            </div>
+        </aside>
+    }
+}
@@ -0,0 +1,92 @@
+//! Pages that explain the demo rather than run it. They read nothing from
+//! the server, so they're pre-rendered to HTML files (see
+//! [`generate_static_pages`](crate::router::generate_static_pages)) instead
+//! of being rendered per request.
+
+use crate::seo::PageMeta;
+use leptos::prelude::*;
+use leptos_router::components::A;
+
+/// The input and output encodings the examples use, and what they show.
+const ENCODINGS: [(&str, &str); 8] = [
+    (
+        "PostUrl / Json",
+        "the defaults: URL-encoded arguments, JSON results",
+    ),
+    ("GetUrl", "cacheable, bookmarkable calls"),
+    (
+        "QueryUrl",
+        "flat query strings that are easy to write by hand",
+    ),
+    ("MultipartFormData", "file uploads"),
+    (
+        "Streaming / StreamingText",
+        "results sent as they're produced",
+    ),
+    ("Rkyv / RkyvChunks", "zero-copy binary results"),
+    ("Postcard / Cbor", "compact binary arguments and results"),
+    ("MultipartMixed", "several typed parts in one response"),
+];
+
+#[component]
+pub fn AboutPage() -> impl IntoView {
+    view! {
+        <PageMeta
+            title="About"
+            description="What the server function demo shows, and how it's organised."
+        />
+        <h2>"About this demo"</h2>
+        <p>
+            "Every example on the " <A href="/">"demo page"</A>
+            " calls a Leptos server function: an async Rust function that runs on the server "
+            "and is called from the browser as if it were local. The macro generates the "
+            "HTTP endpoint, the client stub and the encoding of arguments and results."
+        </p>
+        <p>
+            "The examples are grouped by what they show: actions and forms, working with "
+            "the rows of a small todo list, custom encodings, streaming, middleware, and "
+            "calling server functions from outside the app."
+        </p>
+        <p>
+            "The " <A href="/guide">"guide"</A>
+            " lists the encodings the examples use."
+        </p>
+    }
+}
+
+#[component]
+pub fn GuidePage() -> impl IntoView {
+    view! {
+        <PageMeta
+            title="Guide"
+            description="The input and output encodings a Leptos server function can use."
+        />
+        <h2>"Encodings"</h2>
+        <p>
+            "A server function's " <code>"input"</code> " and " <code>"output"</code>
+            " encodings decide how its arguments and result travel over HTTP."
+        </p>
+        <table>
+            <tr>
+                <th>"Encoding"</th>
+                <th>"Used for"</th>
+            </tr>
+            {ENCODINGS
+                .into_iter()
+                .map(|(encoding, use_for)| {
+                    view! {
+                        <tr>
+                            <td>
+                                <code>{encoding}</code>
+                            </td>
+                            <td>{use_for}</td>
+                        </tr>
+                    }
+                })
+                .collect::<Vec<_>>()}
+        </table>
+        <p>
+            <A href="/about">"About this demo"</A>
+        </p>
+    }
+}
@@ -0,0 +1,109 @@
+//! What the client shows for an [`ErrorCode`]: a message for the reader, in
+//! their [`Locale`](crate::i18n::Locale), rather than the error's own text,
+//! and what they can do about it.
+//!
+//! Codes without a message in [`i18n`](crate::i18n) fall back to the error's
+//! text, so a server can add new codes before the client knows them.
+
+use crate::{
+    errors::{ErrorCode, ErrorParams},
+    i18n::{error_text, recovery_hint, use_locale},
+};
+use leptos::prelude::*;
+use std::fmt::Display;
+
+/// What the reader can do after an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// It may well work the second time.
+    TryAgain,
+    /// Something is busy or limited for now.
+    TryLater,
+    SignIn,
+    /// What was typed or picked needs changing.
+    Edit,
+    /// The page is out of date.
+    Reload,
+    /// Nothing the reader can do fixes it.
+    ContactAdmin,
+}
+
+/// What the reader can do about `code`, if the client knows it.
+pub fn recovery(code: &str) -> Option<Recovery> {
+    use Recovery::*;
+
+    match code {
+        "argument.too_short" | "argument.too_long" | "argument.not_ascii" => {
+            Some(Edit)
+        }
+        "row.empty" | "row.too_long" | "row.due_too_late" => Some(Edit),
+        "tag.empty" | "tag.too_long" | "tag.too_many" => Some(Edit),
+        "form.invalid" | "form.missing" | "form.invalid_value"
+        | "form.invalid_field" => Some(Edit),
+        "row.conflict" | "row.not_found" => Some(Reload),
+        "row.chaos" => Some(TryAgain),
+        "upload.too_large"
+        | "upload.unsupported_format"
+        | "upload.missing_file_name" => Some(Edit),
+        "quota.exceeded" | "auth.two_factor_locked" => Some(TryLater),
+        "auth.not_signed_in" | "auth.expired" => Some(SignIn),
+        "auth.invalid_credentials"
+        | "auth.name_taken"
+        | "auth.password_too_short"
+        | "auth.two_factor_required"
+        | "auth.invalid_two_factor_code" => Some(Edit),
+        "call.two_factor_required" => Some(SignIn),
+        "call.rate_limited" | "call.circuit_open" | "call.overloaded" => {
+            Some(TryLater)
+        }
+        "call.timed_out" => Some(TryAgain),
+        "app.panic" | "app.internal" => Some(ContactAdmin),
+        "server_fn.request" | "server_fn.response" => Some(TryAgain),
+        _ => None,
+    }
+}
+
+/// An error, shown by its code, with a hint at what to do next.
+#[component]
+pub fn ErrorMessage(
+    /// From [`ErrorCode::code`].
+    code: String,
+    /// From [`ErrorCode::params`].
+    params: ErrorParams,
+    /// The error's own text, for codes the client has no message for.
+    text: String,
+) -> impl IntoView {
+    let locale = use_locale();
+    let message = error_text(locale, &code, &params).unwrap_or(text);
+    let hint = recovery(&code).map(|recovery| recovery_hint(locale, recovery));
+
+    view! {
+        <span class="error-message" data-code=code>
+            {message}
+            {hint.map(|hint| format!(" {hint}"))}
+        </span>
+    }
+}
+
+/// [`ErrorMessage`] for `error`.
+pub fn error_message<E: ErrorCode + Display>(error: &E) -> impl IntoView {
+    view! {
+        <ErrorMessage
+            code=error.code().to_string()
+            params=error.params()
+            text=error.to_string()
+        />
+    }
+}
+
+/// `result`'s value, or its error as an [`ErrorMessage`].
+pub fn show_result<T, E>(result: Result<T, E>) -> AnyView
+where
+    T: IntoView + 'static,
+    E: ErrorCode + Display,
+{
+    match result {
+        Ok(value) => value.into_any(),
+        Err(e) => error_message(&e).into_any(),
+    }
+}
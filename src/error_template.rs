@@ -1,4 +1,4 @@
-use crate::errors::TodoAppError;
+use crate::errors::{HasStatusCode, TodoAppError};
 use leptos::prelude::*;
 #[cfg(feature = "ssr")]
 use leptos_axum::ResponseOptions;
@@ -46,3 +46,21 @@ pub fn ErrorTemplate(
         />
     }
 }
+
+/// Renders a standalone 500 page for a request whose handler panicked.
+///
+/// The regular shell can't be used here: the panic may have happened halfway
+/// through rendering it, so this builds a bare document in a fresh owner.
+#[cfg(feature = "ssr")]
+pub fn render_panic_page(request_id: &str) -> String {
+    let mut errors = Errors::default();
+    errors.insert_with_default_key(TodoAppError::Panic {
+        request_id: request_id.to_string(),
+    });
+    let body = Owner::new()
+        .with(|| view! { <ErrorTemplate outside_errors=errors /> }.to_html());
+    format!(
+        "<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\" \
+         /><title>Internal Server Error</title></head><body>{body}</body></html>"
+    )
+}
@@ -1,21 +1,1533 @@
+use crate::{codec::WithStatus, flags::Flag, quotas::QuotaKind, storage::Row};
 use http::status::StatusCode;
+use serde::{Deserialize, Serialize};
+use server_fn::{
+    codec::JsonEncoding,
+    error::{FromServerFnError, NoCustomError, ServerFnErrorErr},
+    ServerFnError,
+};
+use std::{collections::BTreeMap, fmt::Display};
 use thiserror::Error;
 
+/// A stable, machine-readable name for each way something can fail, like
+/// `row.too_long` or `auth.expired`. Messages may be reworded; codes never
+/// change once published, so clients match on them instead.
+pub trait ErrorCode {
+    fn code(&self) -> &str;
+
+    /// The values a message for [`code`](ErrorCode::code) can mention, like
+    /// `max` for `row.too_long`.
+    fn params(&self) -> ErrorParams {
+        ErrorParams::new()
+    }
+}
+
+/// An error's params, by name.
+pub type ErrorParams = BTreeMap<String, String>;
+
+fn params<const N: usize>(pairs: [(&str, &dyn Display); N]) -> ErrorParams {
+    pairs
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+/// The HTTP status a server fn answers with when it fails with this error,
+/// so a rejected input or a missing sign-in isn't a 500 like a crash is.
+///
+/// Error types encoded with [`WithStatus`] set it on their own; others can
+/// call [`set_error_status`].
+pub trait HasStatusCode {
+    fn status_code(&self) -> StatusCode;
+}
+
+/// Makes the server fn being handled answer with `error`'s status.
+#[cfg(feature = "ssr")]
+pub fn set_error_status<E: HasStatusCode + ?Sized>(error: &E) {
+    use leptos::prelude::use_context;
+    use leptos_axum::ResponseOptions;
+
+    if let Some(response) = use_context::<ResponseOptions>() {
+        response.set_status(error.status_code());
+    }
+}
+
+impl ErrorCode for ServerFnErrorErr {
+    fn code(&self) -> &str {
+        match self {
+            ServerFnErrorErr::Registration(_) => "server_fn.registration",
+            ServerFnErrorErr::UnsupportedRequestMethod(_) => {
+                "server_fn.unsupported_method"
+            }
+            ServerFnErrorErr::Request(_) => "server_fn.request",
+            ServerFnErrorErr::ServerError(_) => "server_fn.server",
+            ServerFnErrorErr::MiddlewareError(_) => "server_fn.middleware",
+            ServerFnErrorErr::Deserialization(_) => "server_fn.deserialization",
+            ServerFnErrorErr::Serialization(_) => "server_fn.serialization",
+            ServerFnErrorErr::Args(_) => "server_fn.args",
+            ServerFnErrorErr::MissingArg(_) => "server_fn.missing_arg",
+            ServerFnErrorErr::Response(_) => "server_fn.response",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            ServerFnErrorErr::Request(detail)
+            | ServerFnErrorErr::Response(detail) => {
+                params([("detail", detail)])
+            }
+            _ => ErrorParams::new(),
+        }
+    }
+}
+
+impl HasStatusCode for ServerFnErrorErr {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ServerFnErrorErr::UnsupportedRequestMethod(_) => {
+                StatusCode::METHOD_NOT_ALLOWED
+            }
+            ServerFnErrorErr::Deserialization(_)
+            | ServerFnErrorErr::Args(_)
+            | ServerFnErrorErr::MissingArg(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Never actually sent; it only stands in for "no custom error".
+impl ErrorCode for NoCustomError {
+    fn code(&self) -> &str {
+        "server_fn.custom"
+    }
+}
+
+impl HasStatusCode for NoCustomError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+impl<E: ErrorCode> ErrorCode for ServerFnError<E> {
+    fn code(&self) -> &str {
+        match self {
+            ServerFnError::WrappedServerError(e) => e.code(),
+            ServerFnError::Registration(_) => "server_fn.registration",
+            ServerFnError::Request(_) => "server_fn.request",
+            ServerFnError::Response(_) => "server_fn.response",
+            ServerFnError::ServerError(_) => "server_fn.server",
+            ServerFnError::MiddlewareError(_) => "server_fn.middleware",
+            ServerFnError::Deserialization(_) => "server_fn.deserialization",
+            ServerFnError::Serialization(_) => "server_fn.serialization",
+            ServerFnError::Args(_) => "server_fn.args",
+            ServerFnError::MissingArg(_) => "server_fn.missing_arg",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            ServerFnError::WrappedServerError(e) => e.params(),
+            ServerFnError::Request(detail)
+            | ServerFnError::Response(detail) => params([("detail", detail)]),
+            _ => ErrorParams::new(),
+        }
+    }
+}
+
+impl<E: HasStatusCode> HasStatusCode for ServerFnError<E> {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ServerFnError::WrappedServerError(e) => e.status_code(),
+            ServerFnError::Deserialization(_)
+            | ServerFnError::Args(_)
+            | ServerFnError::MissingArg(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Any [`ErrorCode`] error, as a server fn sends it: its code and params, so
+/// the client can say what went wrong in the reader's language, plus the
+/// server's own message for codes the client doesn't know.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize, Deserialize)]
+#[error("{message}")]
+pub struct AppError {
+    pub code: String,
+    pub params: ErrorParams,
+    pub message: String,
+    /// What the response's status was, from [`HasStatusCode`].
+    pub status: u16,
+}
+
+impl AppError {
+    pub fn new<E: ErrorCode + HasStatusCode + Display>(error: &E) -> Self {
+        AppError {
+            code: error.code().to_string(),
+            params: error.params(),
+            message: error.to_string(),
+            status: error.status_code().as_u16(),
+        }
+    }
+}
+
+impl HasStatusCode for AppError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status)
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+impl ErrorCode for AppError {
+    fn code(&self) -> &str {
+        &self.code
+    }
+
+    fn params(&self) -> ErrorParams {
+        self.params.clone()
+    }
+}
+
+impl FromServerFnError for AppError {
+    type Encoder = WithStatus<JsonEncoding>;
+
+    fn from_server_fn_error(value: ServerFnErrorErr) -> Self {
+        AppError::new(&value)
+    }
+}
+
+/// `From` for each coded error, so `?` turns them into an [`AppError`].
+///
+/// The conversion keeps only the error's own message, so in debug builds the
+/// server keeps its sources for the [dev overlay](crate::dev_overlay) here.
+macro_rules! into_app_error {
+    ($($error:ty),* $(,)?) => {
+        $(
+            impl From<$error> for AppError {
+                fn from(error: $error) -> Self {
+                    #[cfg(feature = "ssr")]
+                    crate::dev_overlay::record_error_chain(&error);
+                    AppError::new(&error)
+                }
+            }
+        )*
+    };
+}
+
+// `ServerFnError` isn't a `std::error::Error`, and has no sources to keep
+impl From<ServerFnError> for AppError {
+    fn from(error: ServerFnError) -> Self {
+        AppError::new(&error)
+    }
+}
+
+into_app_error!(
+    TodoAppError,
+    UploadError,
+    ArchiveError,
+    ScanError,
+    ImportError,
+    SnapshotError,
+    TagError,
+    AuthError,
+    ApiKeyError,
+    PasskeyError,
+    QuotaExceeded,
+    TokenError,
+    AddRowError,
+    UpdateRowError,
+    ProxyError,
+    MiddlewareError,
+    FlagError,
+    CrawlerError,
+    SandboxError,
+    TenantError,
+    BrandingError,
+    TaskError,
+    LoadError,
+    DevLogError,
+    FieldError,
+);
+
+/// Header carrying the ID that ties a panic page to its server log entry, and
+/// in debug builds every server fn call to its log lines, as
+/// [`DevRequestLayer`](crate::dev_overlay::DevRequestLayer) keeps them.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
 #[derive(Debug, Clone, Error)]
 pub enum TodoAppError {
     #[error("Not Found")]
     NotFound,
     #[error("Internal Server Error")]
     InternalServerError,
+    #[error("Internal Server Error (request ID {request_id})")]
+    Panic { request_id: String },
 }
 
-impl TodoAppError {
-    pub fn status_code(&self) -> StatusCode {
+impl HasStatusCode for TodoAppError {
+    fn status_code(&self) -> StatusCode {
         match self {
             TodoAppError::NotFound => StatusCode::NOT_FOUND,
-            TodoAppError::InternalServerError => {
+            TodoAppError::InternalServerError | TodoAppError::Panic { .. } => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+impl ErrorCode for TodoAppError {
+    fn code(&self) -> &str {
+        match self {
+            TodoAppError::NotFound => "app.not_found",
+            TodoAppError::InternalServerError => "app.internal",
+            TodoAppError::Panic { .. } => "app.panic",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            TodoAppError::Panic { request_id } => {
+                params([("request_id", request_id)])
+            }
+            _ => ErrorParams::new(),
+        }
+    }
+}
+
+/// Ways a multipart upload can be malformed.
+#[derive(Debug, Clone, Error)]
+pub enum UploadError {
+    #[error("expected a multipart/form-data body")]
+    NotMultipart,
+    #[error("couldn't read the multipart body: {0}")]
+    Multipart(String),
+    #[error("the `{field}` field has no file name")]
+    MissingFileName { field: String },
+    #[error("`{file_name}` is not a .csv or .jsonl file")]
+    UnsupportedFormat { file_name: String },
+    #[error("the `{field}` field is missing or invalid")]
+    InvalidField { field: String },
+    #[error("files can be at most {max} bytes")]
+    TooLarge { max: u64 },
+}
+
+impl ErrorCode for UploadError {
+    fn code(&self) -> &str {
+        match self {
+            UploadError::NotMultipart => "upload.not_multipart",
+            UploadError::Multipart(_) => "upload.malformed",
+            UploadError::MissingFileName { .. } => "upload.missing_file_name",
+            UploadError::UnsupportedFormat { .. } => {
+                "upload.unsupported_format"
+            }
+            UploadError::InvalidField { .. } => "upload.invalid_field",
+            UploadError::TooLarge { .. } => "upload.too_large",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            UploadError::MissingFileName { field }
+            | UploadError::InvalidField { field } => params([("field", field)]),
+            UploadError::UnsupportedFormat { file_name } => {
+                params([("file_name", file_name)])
+            }
+            UploadError::TooLarge { max } => params([("max", max)]),
+            _ => ErrorParams::new(),
+        }
+    }
+}
+
+impl HasStatusCode for UploadError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UploadError::NotMultipart => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            UploadError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+}
+
+/// Why the attachment store couldn't do what was asked.
+#[derive(Debug, Clone, Error)]
+pub enum BlobError {
+    #[error("there is no blob `{key}`")]
+    NotFound { key: String },
+    #[error("`{key}` isn't a blob key")]
+    InvalidKey { key: String },
+    #[error("{0}")]
+    Io(String),
+    #[error("invalid S3 endpoint {0}")]
+    InvalidEndpoint(String),
+    #[error("no S3 credentials: set them in `[blobs.s3]` or `{0}`")]
+    MissingCredential(String),
+    #[error("couldn't reach S3: {0}")]
+    Request(String),
+    #[error("S3 answered {status}: {message}")]
+    S3 { status: u16, message: String },
+}
+
+/// Why a text generator couldn't be set up, or stopped writing.
+#[derive(Debug, Clone, Error)]
+pub enum GenerationError {
+    #[error("no API key: set `[generation.openai] api_key` or `{0}`")]
+    MissingApiKey(String),
+    #[error("couldn't reach the model's API: {0}")]
+    Request(String),
+    #[error("the model's API answered {status}: {message}")]
+    Api { status: u16, message: String },
+}
+
+/// Why a zip of attachments couldn't be made.
+#[derive(Debug, Clone, Error)]
+pub enum ArchiveError {
+    #[error("pick at least one attachment")]
+    Empty,
+    #[error("a zip can hold at most {max} attachments")]
+    TooManyFiles { max: usize },
+    #[error(transparent)]
+    Blob(#[from] BlobError),
+    #[error("couldn't write the zip: {0}")]
+    Zip(String),
+}
+
+impl ErrorCode for ArchiveError {
+    fn code(&self) -> &str {
+        match self {
+            ArchiveError::Empty => "archive.empty",
+            ArchiveError::TooManyFiles { .. } => "archive.too_many_files",
+            ArchiveError::Blob(_) => "archive.storage",
+            ArchiveError::Zip(_) => "archive.zip",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            ArchiveError::TooManyFiles { max } => params([("max", max)]),
+            _ => ErrorParams::new(),
+        }
+    }
+}
+
+impl HasStatusCode for ArchiveError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ArchiveError::Empty | ArchiveError::TooManyFiles { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ArchiveError::Blob(BlobError::NotFound { .. }) => {
+                StatusCode::NOT_FOUND
+            }
+            ArchiveError::Blob(_) | ArchiveError::Zip(_) => {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
         }
     }
 }
+
+/// Why an attachment can't be downloaded.
+#[derive(Debug, Clone, Error)]
+pub enum ScanError {
+    #[error("`{file_name}` is still being scanned")]
+    Quarantined { file_name: String },
+    #[error("`{file_name}` was rejected: {reason}")]
+    Rejected { file_name: String, reason: String },
+    #[error("`{file_name}` couldn't be scanned: {message}")]
+    Failed { file_name: String, message: String },
+}
+
+impl ErrorCode for ScanError {
+    fn code(&self) -> &str {
+        match self {
+            ScanError::Quarantined { .. } => "attachment.quarantined",
+            ScanError::Rejected { .. } => "attachment.rejected",
+            ScanError::Failed { .. } => "attachment.scan_failed",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            ScanError::Quarantined { file_name }
+            | ScanError::Failed { file_name, .. } => {
+                params([("file_name", file_name)])
+            }
+            ScanError::Rejected { file_name, reason } => {
+                params([("file_name", file_name), ("reason", reason)])
+            }
+        }
+    }
+}
+
+impl HasStatusCode for ScanError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ScanError::Quarantined { .. } => StatusCode::CONFLICT,
+            ScanError::Rejected { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ScanError::Failed { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Why a single imported record was skipped.
+#[derive(Debug, Clone, Error)]
+pub enum ImportError {
+    #[error("not valid UTF-8")]
+    InvalidUtf8,
+    #[error("the CSV header has no `text` column")]
+    MissingTextColumn,
+    #[error("expected {expected} CSV fields, found {found}")]
+    FieldCount { expected: usize, found: usize },
+    #[error("invalid JSON: {0}")]
+    Json(String),
+    #[error("the record is longer than {max} bytes")]
+    RecordTooLong { max: usize },
+    #[error("text is empty")]
+    EmptyText,
+    #[error("text is longer than {max} characters")]
+    TextTooLong { max: usize },
+    #[error(transparent)]
+    Quota(#[from] QuotaExceeded),
+}
+
+impl ErrorCode for ImportError {
+    fn code(&self) -> &str {
+        match self {
+            ImportError::InvalidUtf8 => "import.invalid_utf8",
+            ImportError::MissingTextColumn => "import.missing_text_column",
+            ImportError::FieldCount { .. } => "import.field_count",
+            ImportError::Json(_) => "import.invalid_json",
+            ImportError::RecordTooLong { .. } => "import.record_too_long",
+            ImportError::EmptyText => "row.empty",
+            ImportError::TextTooLong { .. } => "row.too_long",
+            ImportError::Quota(e) => e.code(),
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            ImportError::FieldCount { expected, found } => {
+                params([("expected", expected), ("found", found)])
+            }
+            ImportError::RecordTooLong { max }
+            | ImportError::TextTooLong { max } => params([("max", max)]),
+            ImportError::Quota(e) => e.params(),
+            _ => ErrorParams::new(),
+        }
+    }
+}
+
+impl HasStatusCode for ImportError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ImportError::Quota(e) => e.status_code(),
+            _ => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+}
+
+/// Why a demo snapshot couldn't be restored, which leaves the rows as they
+/// were.
+#[derive(Debug, Clone, Error)]
+pub enum SnapshotError {
+    #[error("invalid snapshot: {0}")]
+    Json(String),
+    #[error("row {id} in the snapshot: {error}")]
+    InvalidRow { id: u64, error: String },
+    #[error("attachment `{id}` in the snapshot has an invalid ID")]
+    InvalidAttachment { id: String },
+}
+
+impl ErrorCode for SnapshotError {
+    fn code(&self) -> &str {
+        match self {
+            SnapshotError::Json(_) => "snapshot.invalid_json",
+            SnapshotError::InvalidRow { .. } => "snapshot.invalid_row",
+            SnapshotError::InvalidAttachment { .. } => {
+                "snapshot.invalid_attachment"
+            }
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            SnapshotError::InvalidRow { id, .. } => params([("id", id)]),
+            SnapshotError::InvalidAttachment { id } => params([("id", id)]),
+            SnapshotError::Json(_) => ErrorParams::new(),
+        }
+    }
+}
+
+impl HasStatusCode for SnapshotError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+}
+
+/// Why a tag name was rejected.
+#[derive(Debug, Clone, Error)]
+pub enum TagError {
+    #[error("tag is empty")]
+    Empty,
+    #[error("tag is longer than {max} characters")]
+    TooLong { max: usize },
+    #[error("a row can have at most {max} tags")]
+    TooMany { max: usize },
+}
+
+impl ErrorCode for TagError {
+    fn code(&self) -> &str {
+        match self {
+            TagError::Empty => "tag.empty",
+            TagError::TooLong { .. } => "tag.too_long",
+            TagError::TooMany { .. } => "tag.too_many",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            TagError::Empty => ErrorParams::new(),
+            TagError::TooLong { max } | TagError::TooMany { max } => {
+                params([("max", max)])
+            }
+        }
+    }
+}
+
+impl HasStatusCode for TagError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+}
+
+/// Why signing up or in failed, or a request needed a user it didn't have.
+#[derive(Debug, Clone, Error, Serialize, Deserialize)]
+pub enum AuthError {
+    #[error("you need to sign in first")]
+    NotSignedIn,
+    #[error("only admins can do that")]
+    Forbidden,
+    #[error("wrong user name or password")]
+    InvalidCredentials,
+    #[error("the name `{name}` is taken")]
+    NameTaken { name: String },
+    #[error("names must be 1 to {max} letters, digits, `-` or `_`")]
+    InvalidName { max: usize },
+    #[error("passwords must be at least {min} characters")]
+    PasswordTooShort { min: usize },
+    #[error("`{email}` doesn't look like an email address")]
+    InvalidEmail { email: String },
+    #[error("couldn't hash the password: {0}")]
+    Hashing(String),
+    #[error("enter the code from your authenticator app, or a recovery code")]
+    TwoFactorRequired,
+    #[error("that code is wrong or was already used")]
+    InvalidTwoFactorCode,
+    #[error("two-factor authentication is already on")]
+    TwoFactorEnabled,
+    #[error("two-factor authentication is off")]
+    TwoFactorDisabled,
+    #[error("start setting up two-factor authentication first")]
+    TwoFactorNotStarted,
+    #[error("too many wrong codes; try again in {minutes} minutes")]
+    TwoFactorLocked { minutes: u64 },
+}
+
+impl ErrorCode for AuthError {
+    fn code(&self) -> &str {
+        match self {
+            AuthError::NotSignedIn => "auth.not_signed_in",
+            AuthError::Forbidden => "auth.forbidden",
+            AuthError::InvalidCredentials => "auth.invalid_credentials",
+            AuthError::NameTaken { .. } => "auth.name_taken",
+            AuthError::InvalidName { .. } => "auth.invalid_name",
+            AuthError::PasswordTooShort { .. } => "auth.password_too_short",
+            AuthError::InvalidEmail { .. } => "auth.invalid_email",
+            AuthError::Hashing(_) => "auth.hashing",
+            AuthError::TwoFactorRequired => "auth.two_factor_required",
+            AuthError::InvalidTwoFactorCode => "auth.invalid_two_factor_code",
+            AuthError::TwoFactorEnabled => "auth.two_factor_enabled",
+            AuthError::TwoFactorDisabled => "auth.two_factor_disabled",
+            AuthError::TwoFactorNotStarted => "auth.two_factor_not_started",
+            AuthError::TwoFactorLocked { .. } => "auth.two_factor_locked",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            AuthError::NameTaken { name } => params([("name", name)]),
+            AuthError::InvalidName { max } => params([("max", max)]),
+            AuthError::PasswordTooShort { min } => params([("min", min)]),
+            AuthError::InvalidEmail { email } => params([("email", email)]),
+            AuthError::TwoFactorLocked { minutes } => {
+                params([("minutes", minutes)])
+            }
+            _ => ErrorParams::new(),
+        }
+    }
+}
+
+impl HasStatusCode for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::NotSignedIn
+            | AuthError::InvalidCredentials
+            | AuthError::TwoFactorRequired
+            | AuthError::InvalidTwoFactorCode => StatusCode::UNAUTHORIZED,
+            AuthError::Forbidden => StatusCode::FORBIDDEN,
+            AuthError::NameTaken { .. } => StatusCode::CONFLICT,
+            AuthError::InvalidName { .. }
+            | AuthError::PasswordTooShort { .. }
+            | AuthError::InvalidEmail { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            AuthError::Hashing(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AuthError::TwoFactorEnabled
+            | AuthError::TwoFactorDisabled
+            | AuthError::TwoFactorNotStarted => StatusCode::CONFLICT,
+            AuthError::TwoFactorLocked { .. } => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+}
+
+/// Why managing or using an API key failed.
+#[derive(Debug, Clone, Error)]
+pub enum ApiKeyError {
+    #[error("key names must be 1 to {max} characters")]
+    InvalidName { max: usize },
+    #[error("you can have at most {max} API keys")]
+    TooMany { max: usize },
+    #[error("there is no API key {id}")]
+    NotFound { id: String },
+    #[error("expected `Authorization: Bearer <API key or token>`")]
+    Unauthorized,
+}
+
+impl ErrorCode for ApiKeyError {
+    fn code(&self) -> &str {
+        match self {
+            ApiKeyError::InvalidName { .. } => "api_key.invalid_name",
+            ApiKeyError::TooMany { .. } => "api_key.too_many",
+            ApiKeyError::NotFound { .. } => "api_key.not_found",
+            ApiKeyError::Unauthorized => "api_key.unauthorized",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            ApiKeyError::InvalidName { max } | ApiKeyError::TooMany { max } => {
+                params([("max", max)])
+            }
+            ApiKeyError::NotFound { id } => params([("id", id)]),
+            ApiKeyError::Unauthorized => ErrorParams::new(),
+        }
+    }
+}
+
+impl HasStatusCode for ApiKeyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiKeyError::InvalidName { .. } | ApiKeyError::TooMany { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ApiKeyError::NotFound { .. } => StatusCode::NOT_FOUND,
+            ApiKeyError::Unauthorized => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+/// Why adding, using or removing a passkey failed.
+#[derive(Debug, Clone, Error)]
+pub enum PasskeyError {
+    #[error("passkey names must be 1 to {max} characters")]
+    InvalidName { max: usize },
+    #[error("you can have at most {max} passkeys")]
+    TooMany { max: usize },
+    #[error("there is no passkey {id}")]
+    NotFound { id: String },
+    #[error("that took too long; try again")]
+    Expired,
+    #[error("the passkey didn't check out: {message}")]
+    Rejected { message: String },
+    #[error("the browser didn't make or use a passkey: {message}")]
+    Browser { message: String },
+    #[error("passkeys aren't set up on this server")]
+    NotConfigured,
+}
+
+impl ErrorCode for PasskeyError {
+    fn code(&self) -> &str {
+        match self {
+            PasskeyError::InvalidName { .. } => "passkey.invalid_name",
+            PasskeyError::TooMany { .. } => "passkey.too_many",
+            PasskeyError::NotFound { .. } => "passkey.not_found",
+            PasskeyError::Expired => "passkey.expired",
+            PasskeyError::Rejected { .. } => "passkey.rejected",
+            PasskeyError::Browser { .. } => "passkey.browser",
+            PasskeyError::NotConfigured => "passkey.not_configured",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            PasskeyError::InvalidName { max }
+            | PasskeyError::TooMany { max } => params([("max", max)]),
+            PasskeyError::NotFound { id } => params([("id", id)]),
+            PasskeyError::Rejected { message }
+            | PasskeyError::Browser { message } => {
+                params([("message", message)])
+            }
+            PasskeyError::Expired | PasskeyError::NotConfigured => {
+                ErrorParams::new()
+            }
+        }
+    }
+}
+
+impl HasStatusCode for PasskeyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PasskeyError::InvalidName { .. }
+            | PasskeyError::TooMany { .. }
+            | PasskeyError::Browser { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            PasskeyError::NotFound { .. } => StatusCode::NOT_FOUND,
+            PasskeyError::Expired => StatusCode::GONE,
+            PasskeyError::Rejected { .. } => StatusCode::UNAUTHORIZED,
+            PasskeyError::NotConfigured => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+/// A user used up one of their daily quotas.
+#[derive(Debug, Clone, Error, Serialize, Deserialize)]
+#[error("you have reached today's limit of {limit} {kind}")]
+pub struct QuotaExceeded {
+    pub kind: QuotaKind,
+    pub limit: u64,
+}
+
+impl ErrorCode for QuotaExceeded {
+    fn code(&self) -> &str {
+        "quota.exceeded"
+    }
+
+    fn params(&self) -> ErrorParams {
+        params([("kind", &self.kind), ("limit", &self.limit)])
+    }
+}
+
+impl HasStatusCode for QuotaExceeded {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+}
+
+/// Why an email couldn't be sent.
+#[derive(Debug, Clone, Error)]
+pub enum MailError {
+    #[error("`{address}` is not a valid email address")]
+    InvalidAddress { address: String },
+    #[error("couldn't build the message: {0}")]
+    Build(String),
+    #[error("SMTP error: {0}")]
+    Smtp(String),
+    #[error("mail has not been set up")]
+    NotConfigured,
+}
+
+/// Why a bearer token was rejected.
+#[derive(Debug, Clone, Error)]
+pub enum TokenError {
+    #[error("the token is not a JWT we could have issued")]
+    Malformed,
+    #[error("the token is signed with an unknown or retired key")]
+    UnknownKey,
+    #[error("the token's signature doesn't match")]
+    BadSignature,
+    #[error("the token has expired")]
+    Expired,
+}
+
+impl ErrorCode for TokenError {
+    fn code(&self) -> &str {
+        match self {
+            TokenError::Malformed => "auth.malformed_token",
+            TokenError::UnknownKey => "auth.unknown_key",
+            TokenError::BadSignature => "auth.bad_signature",
+            TokenError::Expired => "auth.expired",
+        }
+    }
+}
+
+impl HasStatusCode for TokenError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNAUTHORIZED
+    }
+}
+
+/// Why adding a row failed, besides the usual auth and quota errors.
+#[derive(Debug, Clone, Error)]
+pub enum AddRowError {
+    /// Chaos mode is on, and this was one of the calls it fails.
+    #[error("Oh no! Couldn't add to database!")]
+    ChaosMode,
+    #[error("text is empty")]
+    EmptyText,
+    #[error("text is longer than {max} characters")]
+    TextTooLong { max: usize },
+    #[error("rows can be due at most {max} days from now")]
+    DueTooLate { max: u16 },
+}
+
+impl ErrorCode for AddRowError {
+    fn code(&self) -> &str {
+        match self {
+            AddRowError::ChaosMode => "row.chaos",
+            AddRowError::EmptyText => "row.empty",
+            AddRowError::TextTooLong { .. } => "row.too_long",
+            AddRowError::DueTooLate { .. } => "row.due_too_late",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            AddRowError::TextTooLong { max } => params([("max", max)]),
+            AddRowError::DueTooLate { max } => params([("max", max)]),
+            AddRowError::ChaosMode | AddRowError::EmptyText => {
+                ErrorParams::new()
+            }
+        }
+    }
+}
+
+impl HasStatusCode for AddRowError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AddRowError::ChaosMode => StatusCode::INTERNAL_SERVER_ERROR,
+            AddRowError::EmptyText
+            | AddRowError::TextTooLong { .. }
+            | AddRowError::DueTooLate { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+        }
+    }
+}
+
+/// Why editing a row's text failed.
+#[derive(Debug, Clone, Error, Serialize, Deserialize)]
+pub enum UpdateRowError {
+    /// The row changed since the edit started; `server_value` is what it
+    /// looks like now.
+    #[error("the row was changed by someone else")]
+    Conflict { server_value: Row },
+    #[error("there is no row {id}")]
+    NotFound { id: u64 },
+    #[error("text is empty")]
+    EmptyText,
+    #[error("text is longer than {max} characters")]
+    TextTooLong { max: usize },
+    #[error(transparent)]
+    Auth(#[from] AuthError),
+    #[error(transparent)]
+    ServerFnError(ServerFnErrorErr),
+}
+
+impl FromServerFnError for UpdateRowError {
+    type Encoder = JsonEncoding;
+
+    fn from_server_fn_error(value: ServerFnErrorErr) -> Self {
+        UpdateRowError::ServerFnError(value)
+    }
+}
+
+impl ErrorCode for UpdateRowError {
+    fn code(&self) -> &str {
+        match self {
+            UpdateRowError::Conflict { .. } => "row.conflict",
+            UpdateRowError::NotFound { .. } => "row.not_found",
+            UpdateRowError::EmptyText => "row.empty",
+            UpdateRowError::TextTooLong { .. } => "row.too_long",
+            UpdateRowError::Auth(e) => e.code(),
+            UpdateRowError::ServerFnError(e) => e.code(),
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            UpdateRowError::NotFound { id } => params([("id", id)]),
+            UpdateRowError::TextTooLong { max } => params([("max", max)]),
+            UpdateRowError::Auth(e) => e.params(),
+            UpdateRowError::ServerFnError(e) => e.params(),
+            UpdateRowError::Conflict { .. } | UpdateRowError::EmptyText => {
+                ErrorParams::new()
+            }
+        }
+    }
+}
+
+impl HasStatusCode for UpdateRowError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UpdateRowError::Conflict { .. } => StatusCode::CONFLICT,
+            UpdateRowError::NotFound { .. } => StatusCode::NOT_FOUND,
+            UpdateRowError::EmptyText | UpdateRowError::TextTooLong { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            UpdateRowError::Auth(e) => e.status_code(),
+            UpdateRowError::ServerFnError(e) => e.status_code(),
+        }
+    }
+}
+
+/// Why proxying a call to an external API failed.
+#[derive(Debug, Clone, Error, Serialize, Deserialize)]
+pub enum ProxyError {
+    #[error("`{name}` can't be passed on to {api}")]
+    ParamNotAllowed { api: String, name: String },
+    #[error("couldn't reach {api}: {message}")]
+    Unreachable { api: String, message: String },
+    #[error("{api} didn't answer in time")]
+    TimedOut { api: String },
+    #[error("{api} answered with status {status}")]
+    Upstream { api: String, status: u16 },
+    #[error("the response from {api} is over {limit} bytes")]
+    TooLarge { api: String, limit: usize },
+    #[error("the response from {api} broke off: {message}")]
+    Interrupted { api: String, message: String },
+    #[error(transparent)]
+    ServerFnError(ServerFnErrorErr),
+}
+
+impl FromServerFnError for ProxyError {
+    type Encoder = JsonEncoding;
+
+    fn from_server_fn_error(value: ServerFnErrorErr) -> Self {
+        ProxyError::ServerFnError(value)
+    }
+}
+
+impl ErrorCode for ProxyError {
+    fn code(&self) -> &str {
+        match self {
+            ProxyError::ParamNotAllowed { .. } => "proxy.param_not_allowed",
+            ProxyError::Unreachable { .. } => "proxy.unreachable",
+            ProxyError::TimedOut { .. } => "proxy.timed_out",
+            ProxyError::Upstream { .. } => "proxy.upstream",
+            ProxyError::TooLarge { .. } => "proxy.too_large",
+            ProxyError::Interrupted { .. } => "proxy.interrupted",
+            ProxyError::ServerFnError(e) => e.code(),
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            ProxyError::ParamNotAllowed { api, name } => {
+                params([("api", api), ("name", name)])
+            }
+            ProxyError::Unreachable { api, message }
+            | ProxyError::Interrupted { api, message } => {
+                params([("api", api), ("message", message)])
+            }
+            ProxyError::TimedOut { api } => params([("api", api)]),
+            ProxyError::Upstream { api, status } => {
+                params([("api", api), ("status", status)])
+            }
+            ProxyError::TooLarge { api, limit } => {
+                params([("api", api), ("limit", limit)])
+            }
+            ProxyError::ServerFnError(e) => e.params(),
+        }
+    }
+}
+
+impl HasStatusCode for ProxyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ProxyError::ParamNotAllowed { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ProxyError::TimedOut { .. } => StatusCode::GATEWAY_TIMEOUT,
+            ProxyError::Unreachable { .. }
+            | ProxyError::Upstream { .. }
+            | ProxyError::TooLarge { .. }
+            | ProxyError::Interrupted { .. } => StatusCode::BAD_GATEWAY,
+            ProxyError::ServerFnError(e) => e.status_code(),
+        }
+    }
+}
+
+/// Why a server fn middleware turned a call away.
+#[derive(Debug, Clone, Error)]
+pub enum MiddlewareError {
+    #[error("too many calls; try again in {retry_after_secs}s")]
+    RateLimited { retry_after_secs: u64 },
+    #[error("the request body is over {limit} bytes")]
+    BodyTooLarge { limit: usize },
+    #[error("this is failing too often; try again in {retry_after_secs}s")]
+    CircuitOpen { retry_after_secs: u64 },
+    #[error("over {max_concurrent} calls are already running")]
+    Overloaded { max_concurrent: usize },
+    #[error("gave up after {after_ms}ms")]
+    TimedOut { after_ms: u64 },
+    #[error("sign in with a two-factor code or a passkey to do that")]
+    TwoFactorRequired,
+}
+
+impl ErrorCode for MiddlewareError {
+    fn code(&self) -> &str {
+        match self {
+            MiddlewareError::RateLimited { .. } => "call.rate_limited",
+            MiddlewareError::BodyTooLarge { .. } => "call.body_too_large",
+            MiddlewareError::CircuitOpen { .. } => "call.circuit_open",
+            MiddlewareError::Overloaded { .. } => "call.overloaded",
+            MiddlewareError::TimedOut { .. } => "call.timed_out",
+            MiddlewareError::TwoFactorRequired => "call.two_factor_required",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            MiddlewareError::RateLimited { retry_after_secs }
+            | MiddlewareError::CircuitOpen { retry_after_secs } => {
+                params([("retry_after_secs", retry_after_secs)])
+            }
+            MiddlewareError::BodyTooLarge { limit } => {
+                params([("limit", limit)])
+            }
+            MiddlewareError::Overloaded { max_concurrent } => {
+                params([("max_concurrent", max_concurrent)])
+            }
+            MiddlewareError::TimedOut { after_ms } => {
+                params([("after_ms", after_ms)])
+            }
+            MiddlewareError::TwoFactorRequired => ErrorParams::new(),
+        }
+    }
+}
+
+impl HasStatusCode for MiddlewareError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            MiddlewareError::RateLimited { .. } => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            MiddlewareError::BodyTooLarge { .. } => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+            MiddlewareError::CircuitOpen { .. }
+            | MiddlewareError::Overloaded { .. } => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            MiddlewareError::TimedOut { .. } => StatusCode::GATEWAY_TIMEOUT,
+            MiddlewareError::TwoFactorRequired => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+/// A server fn was called while the flag for its example is off.
+#[derive(Debug, Clone, Error)]
+pub enum FlagError {
+    #[error("the {flag} example is switched off")]
+    Disabled { flag: Flag },
+}
+
+impl ErrorCode for FlagError {
+    fn code(&self) -> &str {
+        match self {
+            FlagError::Disabled { .. } => "flag.disabled",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            FlagError::Disabled { flag } => params([("flag", flag)]),
+        }
+    }
+}
+
+impl HasStatusCode for FlagError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// A streaming server fn was called by a crawler.
+#[derive(Debug, Clone, Error)]
+pub enum CrawlerError {
+    #[error("streams aren't served to crawlers")]
+    StreamRefused,
+}
+
+impl ErrorCode for CrawlerError {
+    fn code(&self) -> &str {
+        match self {
+            CrawlerError::StreamRefused => "crawler.stream_refused",
+        }
+    }
+}
+
+impl HasStatusCode for CrawlerError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+}
+
+/// Why the visitor's sandbox couldn't be used.
+#[derive(Debug, Clone, Error)]
+pub enum SandboxError {
+    #[error("there's no request to start a sandbox for")]
+    NoRequest,
+    #[error("you don't have a sandbox yet")]
+    NoSandbox,
+    #[error("signed in, your rows are your account's, not a sandbox's")]
+    SignedIn,
+}
+
+impl ErrorCode for SandboxError {
+    fn code(&self) -> &str {
+        match self {
+            SandboxError::NoRequest => "sandbox.no_request",
+            SandboxError::NoSandbox => "sandbox.missing",
+            SandboxError::SignedIn => "sandbox.signed_in",
+        }
+    }
+}
+
+impl HasStatusCode for SandboxError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SandboxError::NoRequest => StatusCode::INTERNAL_SERVER_ERROR,
+            SandboxError::NoSandbox => StatusCode::NOT_FOUND,
+            SandboxError::SignedIn => StatusCode::CONFLICT,
+        }
+    }
+}
+
+/// Why a request's tenant couldn't be worked out.
+#[derive(Debug, Clone, Error)]
+pub enum TenantError {
+    #[error("there is no tenant {name}")]
+    Unknown { name: String },
+    #[error("[tenants] names {count} tenants, over the {max} there can be")]
+    TooMany { count: usize, max: usize },
+}
+
+impl ErrorCode for TenantError {
+    fn code(&self) -> &str {
+        match self {
+            TenantError::Unknown { .. } => "tenant.unknown",
+            TenantError::TooMany { .. } => "tenant.too_many",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            TenantError::Unknown { name } => params([("name", name)]),
+            TenantError::TooMany { count, max } => {
+                params([("count", count), ("max", max)])
+            }
+        }
+    }
+}
+
+impl HasStatusCode for TenantError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TenantError::Unknown { .. } => StatusCode::NOT_FOUND,
+            TenantError::TooMany { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Why a tenant's branding wasn't saved.
+#[derive(Debug, Clone, Error)]
+pub enum BrandingError {
+    #[error("the title can't be empty")]
+    EmptyTitle,
+    #[error("the title is longer than {max} characters")]
+    TitleTooLong { max: usize },
+    #[error("`{value}` isn't a color like #3b5bdb")]
+    InvalidColor { value: String },
+    #[error("the logo needs a path on this site, starting with /")]
+    InvalidLogoUrl,
+}
+
+impl ErrorCode for BrandingError {
+    fn code(&self) -> &str {
+        match self {
+            BrandingError::EmptyTitle => "branding.empty_title",
+            BrandingError::TitleTooLong { .. } => "branding.title_too_long",
+            BrandingError::InvalidColor { .. } => "branding.invalid_color",
+            BrandingError::InvalidLogoUrl => "branding.invalid_logo_url",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            BrandingError::TitleTooLong { max } => params([("max", max)]),
+            BrandingError::InvalidColor { value } => params([("value", value)]),
+            BrandingError::EmptyTitle | BrandingError::InvalidLogoUrl => {
+                ErrorParams::new()
+            }
+        }
+    }
+}
+
+impl HasStatusCode for BrandingError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+}
+
+/// Why the call log couldn't be written, read or replayed.
+#[derive(Debug, Clone, Error)]
+pub enum CallLogError {
+    #[error("couldn't open {path}: {message}")]
+    Io { path: String, message: String },
+    #[error("line {line} of the call log is invalid: {message}")]
+    InvalidLine { line: usize, message: String },
+    #[error("the recorded call to {uri} is invalid: {message}")]
+    InvalidCall { uri: String, message: String },
+    #[error("couldn't call {target}: {message}")]
+    Request { target: String, message: String },
+}
+
+/// Why a log file couldn't be tailed.
+#[derive(Debug, Clone, Error)]
+pub enum TailError {
+    #[error("{path} isn't one of the files that can be tailed")]
+    NotAllowed { path: String },
+    #[error("couldn't read {path}: {message}")]
+    Io { path: String, message: String },
+}
+
+/// Why a task couldn't be run.
+#[derive(Debug, Clone, Error)]
+pub enum TaskError {
+    #[error("{max} tasks are running already; try again once one is done")]
+    Busy { max: usize },
+    #[error("couldn't start `{task}`: {message}")]
+    Spawn { task: String, message: String },
+}
+
+impl ErrorCode for TaskError {
+    fn code(&self) -> &str {
+        match self {
+            TaskError::Busy { .. } => "task.busy",
+            TaskError::Spawn { .. } => "task.spawn",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            TaskError::Busy { max } => params([("max", max)]),
+            TaskError::Spawn { task, .. } => params([("task", task)]),
+        }
+    }
+}
+
+impl HasStatusCode for TaskError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TaskError::Busy { .. } => StatusCode::TOO_MANY_REQUESTS,
+            TaskError::Spawn { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Why a synthetic load run couldn't start.
+#[derive(Debug, Clone, Error)]
+pub enum LoadError {
+    #[error("load can only be generated by debug builds")]
+    Disabled,
+    #[error("a load run is already going")]
+    AlreadyRunning,
+    #[error("the rate must be 1 to {max} operations per second")]
+    InvalidRate { max: u32 },
+    #[error("the duration must be 1 to {max} seconds")]
+    InvalidDuration { max: u64 },
+}
+
+impl ErrorCode for LoadError {
+    fn code(&self) -> &str {
+        match self {
+            LoadError::Disabled => "load.disabled",
+            LoadError::AlreadyRunning => "load.already_running",
+            LoadError::InvalidRate { .. } => "load.invalid_rate",
+            LoadError::InvalidDuration { .. } => "load.invalid_duration",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            LoadError::InvalidRate { max } => params([("max", max)]),
+            LoadError::InvalidDuration { max } => params([("max", max)]),
+            _ => ErrorParams::new(),
+        }
+    }
+}
+
+impl HasStatusCode for LoadError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            LoadError::Disabled => StatusCode::FORBIDDEN,
+            LoadError::AlreadyRunning => StatusCode::CONFLICT,
+            LoadError::InvalidRate { .. }
+            | LoadError::InvalidDuration { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+        }
+    }
+}
+
+/// Why the [dev overlay](crate::dev_overlay) couldn't get a request's log.
+#[derive(Debug, Clone, Error)]
+pub enum DevLogError {
+    #[error("request logs are only kept in debug builds")]
+    Disabled,
+}
+
+impl ErrorCode for DevLogError {
+    fn code(&self) -> &str {
+        match self {
+            DevLogError::Disabled => "dev.disabled",
+        }
+    }
+}
+
+impl HasStatusCode for DevLogError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+}
+
+/// Why a field of a submitted form couldn't be read, as
+/// [`forms`](crate::forms) decodes it.
+#[derive(Debug, Clone, Error)]
+pub enum FieldError {
+    #[error("this field is required")]
+    Missing,
+    #[error("`{value}` is not {expected}")]
+    InvalidValue { value: String, expected: String },
+    #[error("{0}")]
+    Invalid(String),
+}
+
+impl ErrorCode for FieldError {
+    fn code(&self) -> &str {
+        match self {
+            FieldError::Missing => "form.missing",
+            FieldError::InvalidValue { .. } => "form.invalid_value",
+            FieldError::Invalid(_) => "form.invalid_field",
+        }
+    }
+
+    fn params(&self) -> ErrorParams {
+        match self {
+            FieldError::InvalidValue { value, expected } => {
+                params([("value", value), ("expected", expected)])
+            }
+            FieldError::Missing | FieldError::Invalid(_) => ErrorParams::new(),
+        }
+    }
+}
+
+impl HasStatusCode for FieldError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+}
+
+/// Some of a form's fields need fixing before it can be submitted.
+#[derive(Debug, Clone, Error)]
+#[error("{count} fields need fixing")]
+pub struct InvalidForm {
+    pub count: usize,
+}
+
+impl ErrorCode for InvalidForm {
+    fn code(&self) -> &str {
+        "form.invalid"
+    }
+
+    fn params(&self) -> ErrorParams {
+        params([("count", &self.count)])
+    }
+}
+
+impl HasStatusCode for InvalidForm {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+}
+
+/// How a server fn bound to a form fails: with an error for each field that
+/// needs fixing, by its dotted name, or with any other error for the form as
+/// a whole.
+///
+/// Any error that converts to an [`AppError`] converts to one, so `?` works
+/// in such server fns as usual; gather field errors with
+/// [`FieldErrors`](crate::forms::FieldErrors).
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize, Deserialize)]
+#[error("{error}")]
+pub struct FormError {
+    /// `form.invalid` if any field needs fixing.
+    pub error: AppError,
+    pub fields: BTreeMap<String, AppError>,
+}
+
+impl<E: Into<AppError>> From<E> for FormError {
+    fn from(error: E) -> Self {
+        FormError {
+            error: error.into(),
+            fields: BTreeMap::new(),
+        }
+    }
+}
+
+impl ErrorCode for FormError {
+    fn code(&self) -> &str {
+        self.error.code()
+    }
+
+    fn params(&self) -> ErrorParams {
+        self.error.params()
+    }
+}
+
+impl HasStatusCode for FormError {
+    fn status_code(&self) -> StatusCode {
+        self.error.status_code()
+    }
+}
+
+impl FromServerFnError for FormError {
+    type Encoder = WithStatus<JsonEncoding>;
+
+    fn from_server_fn_error(value: ServerFnErrorErr) -> Self {
+        AppError::new(&value).into()
+    }
+}
+
+/// Why a `[sentry] dsn` can't be used.
+#[derive(Debug, Clone, Error)]
+pub enum DsnError {
+    #[error("the DSN is not a valid URL: {0}")]
+    InvalidUrl(String),
+    #[error("the DSN has no public key before the `@`")]
+    MissingKey,
+    #[error("the DSN doesn't end with a project ID")]
+    MissingProject,
+}
+
+/// Why a [`TaggedRkyvEncoding`](crate::codec::TaggedRkyvEncoding) frame
+/// couldn't be read.
+#[derive(Debug, Clone, Error)]
+pub enum FrameError {
+    #[error("the frame is too short to have a header")]
+    Truncated,
+    #[error("expected a frame of type {expected}, got {found}")]
+    WrongType { expected: u8, found: u8 },
+    #[error("got a version {found} frame, but version {expected} is read")]
+    WrongVersion { expected: u8, found: u8 },
+    #[error("invalid archive: {0}")]
+    Archive(String),
+}
@@ -0,0 +1,213 @@
+//! A row for every file that appears in `./watched_files`, for the owners
+//! who asked for one. A single task follows the shared watcher and adds
+//! the rows, then announces each one so its owner's open lists refetch.
+//!
+//! Asking lasts until it's taken back, or until the sandbox is reset or
+//! expires, so a forgotten sandbox doesn't go on collecting rows.
+
+use crate::{
+    auth::UserId,
+    channels::Tick,
+    codec::{Framed, FramedStream},
+};
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use server_fn::codec::GetUrl;
+
+/// Sent once a row has been made from a new file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileRow {
+    /// Who the row belongs to, and the only one it is sent to.
+    pub owner: UserId,
+    pub row_id: u64,
+    pub file_name: String,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::FileRow;
+    use crate::{
+        audit::AuditAction,
+        auth::UserId,
+        channels::DropOldest,
+        errors::QuotaExceeded,
+        flags::{self, Flag},
+        quotas::QuotaKind,
+        sandbox::until_reset,
+        storage::ROWS,
+        watcher::{self, WATCHED_DIR},
+    };
+    use dashmap::DashMap;
+    use futures::StreamExt;
+    use std::sync::LazyLock;
+    use tokio::task::JoinHandle;
+
+    /// Rows made from files for every owner; a reconnecting client catches
+    /// up on its own among the last 64.
+    pub static FILE_ROWS: LazyLock<DropOldest<FileRow>> =
+        LazyLock::new(|| DropOldest::new("file rows", 64));
+
+    /// The owners who want rows, each with the task that forgets them once
+    /// their sandbox goes.
+    static OWNERS: LazyLock<DashMap<UserId, JoinHandle<()>>> =
+        LazyLock::new(DashMap::new);
+
+    pub fn is_enabled(owner: UserId) -> bool {
+        OWNERS.contains_key(&owner)
+    }
+
+    /// Starts or stops making rows from new files for `owner`.
+    pub fn set_enabled(owner: UserId, enabled: bool) {
+        if !enabled {
+            if let Some((_, forget)) = OWNERS.remove(&owner) {
+                forget.abort();
+            }
+            return;
+        }
+        OWNERS.entry(owner).or_insert_with(|| {
+            tokio::spawn(async move {
+                until_reset(owner).await;
+                OWNERS.remove(&owner);
+            })
+        });
+    }
+
+    /// Makes a row for each owner in [`OWNERS`] whenever a file is created
+    /// in [`WATCHED_DIR`], while the file watcher flag is on. Runs until the
+    /// server shuts down.
+    pub async fn run_bridge() {
+        let changes = match watcher::subscribe(None) {
+            Ok(changes) => changes,
+            Err(e) => {
+                tracing::warn!(
+                    error = %e,
+                    "couldn't watch {WATCHED_DIR}; no rows will be made from files"
+                );
+                return;
+            }
+        };
+        let mut changes = std::pin::pin!(changes);
+        while let Some(change) = changes.next().await {
+            let Ok(change) = change.value else {
+                continue;
+            };
+            if !change.created || !flags::is_enabled(Flag::FileWatcher) {
+                continue;
+            }
+            let Some(file_name) = change.file_name() else {
+                continue;
+            };
+            // directories, and files that are gone again already, are skipped
+            let size = match tokio::fs::metadata(&change.path).await {
+                Ok(metadata) if metadata.is_file() => metadata.len(),
+                _ => continue,
+            };
+            let text = format!(
+                "New file `{}` ({size} bytes)",
+                file_name.replace('`', "'")
+            );
+            let owners =
+                OWNERS.iter().map(|entry| *entry.key()).collect::<Vec<_>>();
+            for owner in owners {
+                let added = ROWS.transaction(owner, |tx| {
+                    tx.consume_quota(QuotaKind::Rows, 1)?;
+                    let row = tx.insert(text.clone());
+                    tx.audit(row.id, AuditAction::Created);
+                    Ok::<_, QuotaExceeded>(row)
+                });
+                let row = match added {
+                    Ok(row) => row,
+                    Err(e) => {
+                        tracing::info!(
+                            owner,
+                            file_name,
+                            error = %e,
+                            "no row for a new file"
+                        );
+                        continue;
+                    }
+                };
+                FILE_ROWS.send(FileRow {
+                    owner,
+                    row_id: row.id,
+                    file_name: file_name.to_string(),
+                });
+            }
+        }
+    }
+}
+
+/// Whether the caller gets a row for every new file.
+#[server(input = GetUrl)]
+pub async fn file_rows_enabled() -> Result<bool, ServerFnError> {
+    use crate::sandbox::current_owner;
+
+    Ok(current_owner().is_some_and(is_enabled))
+}
+
+/// Starts or stops making a row for every new file for the caller.
+#[server]
+pub async fn set_file_rows(enabled: bool) -> Result<(), ServerFnError> {
+    use crate::{
+        flags::{require, Flag},
+        sandbox::require_owner,
+    };
+
+    require(Flag::FileWatcher)?;
+    set_enabled(require_owner()?, enabled);
+    Ok(())
+}
+
+/// Rows made from new files for the caller, from where it left off.
+#[server(input = GetUrl, output = Framed)]
+pub async fn file_row_events(
+    after: Option<u64>,
+) -> Result<FramedStream<Tick<FileRow>>, ServerFnError> {
+    use crate::{
+        channels::{with_heartbeat, HEARTBEAT_INTERVAL},
+        sandbox::current_owner,
+    };
+    use futures::StreamExt;
+
+    // visitors without a sandbox only ever get heartbeats
+    let owner = current_owner();
+    let rows = FILE_ROWS.subscribe_from(after).filter(move |row| {
+        futures::future::ready(Some(row.value.owner) == owner)
+    });
+    let ticks = with_heartbeat(rows, HEARTBEAT_INTERVAL);
+    Ok(FramedStream::new(ticks.map(Ok)))
+}
+
+/// A checkbox to get a row for every file added to `watched_files`.
+#[component]
+pub fn FileRowsToggle() -> impl IntoView {
+    let set = ServerAction::<SetFileRows>::new();
+    let enabled =
+        Resource::new(move || set.version().get(), |_| file_rows_enabled());
+
+    view! {
+        <label>
+            <input
+                type="checkbox"
+                prop:checked=move || {
+                    enabled.get().and_then(Result::ok).unwrap_or(false)
+                }
+                on:change=move |ev| {
+                    set.dispatch(SetFileRows {
+                        enabled: event_target_checked(&ev),
+                    });
+                }
+            />
+            " Add a row for every new file"
+        </label>
+        {move || {
+            set.value()
+                .get()
+                .and_then(Result::err)
+                .map(|e| view! { <p>"Couldn't change that: " {e.to_string()}</p> })
+        }}
+    }
+}
@@ -0,0 +1,71 @@
+//! A payload shaped like what the server fns send around, for comparing the
+//! encodings in `benches/` on equal terms.
+
+use serde::{Deserialize, Serialize};
+
+/// A page of rows, with enough nesting, text and numbers to exercise every
+/// encoding. Kept representable in TOML: no top-level arrays, and plain
+/// values before the tables.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    serde_lite::Serialize,
+    serde_lite::Deserialize,
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+)]
+pub struct Fixture {
+    pub owner: u64,
+    pub title: String,
+    pub tags: Vec<String>,
+    pub rows: Vec<FixtureRow>,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    serde_lite::Serialize,
+    serde_lite::Deserialize,
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+)]
+pub struct FixtureRow {
+    pub id: u64,
+    pub text: String,
+    pub completed: bool,
+    pub ordering: i64,
+    pub score: f64,
+}
+
+impl Fixture {
+    /// The same `rows` rows every time, so runs can be compared.
+    pub fn sample(rows: usize) -> Self {
+        Self {
+            owner: 42,
+            title: "Groceries (and a few errands) 🛒".to_string(),
+            tags: ["home", "weekly", "shared"].map(String::from).to_vec(),
+            rows: (0..rows).map(FixtureRow::sample).collect(),
+        }
+    }
+}
+
+impl FixtureRow {
+    /// Row `i` of every [`Fixture::sample`].
+    pub fn sample(i: usize) -> Self {
+        Self {
+            id: i as u64,
+            text: format!("Row {i}: pick up item #{}", i * 7 % 13),
+            completed: i % 3 == 0,
+            ordering: i as i64 * 1024 - 512,
+            score: i as f64 / 3.0,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+#[cfg(feature = "ssr")]
+use crate::auth::require_admin;
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+/// A part of the demo that can be switched off at runtime, without
+/// recompiling.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display,
+)]
+pub enum Flag {
+    /// The file watcher example, which keeps a stream open per tab.
+    #[strum(serialize = "file watcher")]
+    FileWatcher,
+    /// Make every third `add_row` call fail, as the "With an Action"
+    /// example shows off.
+    #[strum(serialize = "chaos mode")]
+    ChaosMode,
+}
+
+impl Flag {
+    pub const ALL: [Flag; 2] = [Flag::FileWatcher, Flag::ChaosMode];
+
+    /// Whether the flag is on when the server starts.
+    pub fn default_enabled(self) -> bool {
+        match self {
+            Flag::FileWatcher | Flag::ChaosMode => true,
+        }
+    }
+}
+
+/// Whether each flag is on, in the order of [`Flag::ALL`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Flags(pub Vec<(Flag, bool)>);
+
+impl Flags {
+    pub fn is_enabled(&self, flag: Flag) -> bool {
+        self.0
+            .iter()
+            .find(|(f, _)| *f == flag)
+            .map_or(flag.default_enabled(), |(_, enabled)| *enabled)
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{Flag, Flags};
+    use crate::errors::FlagError;
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock,
+    };
+
+    /// The state of every flag, indexed like [`Flag::ALL`].
+    static FLAGS: LazyLock<[AtomicBool; Flag::ALL.len()]> =
+        LazyLock::new(|| {
+            Flag::ALL.map(|f| AtomicBool::new(f.default_enabled()))
+        });
+
+    fn state(flag: Flag) -> &'static AtomicBool {
+        &FLAGS[flag as usize]
+    }
+
+    pub fn is_enabled(flag: Flag) -> bool {
+        state(flag).load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(flag: Flag, enabled: bool) {
+        state(flag).store(enabled, Ordering::Relaxed);
+    }
+
+    /// An error if `flag` is off, for the server fns of the example it
+    /// switches off.
+    pub fn require(flag: Flag) -> Result<(), FlagError> {
+        if is_enabled(flag) {
+            Ok(())
+        } else {
+            Err(FlagError::Disabled { flag })
+        }
+    }
+
+    pub fn snapshot() -> Flags {
+        Flags(Flag::ALL.map(|flag| (flag, is_enabled(flag))).to_vec())
+    }
+}
+
+#[server]
+pub async fn get_flags() -> Result<Flags, ServerFnError> {
+    Ok(snapshot())
+}
+
+#[server]
+#[middleware(crate::middleware::TwoFactorLayer)]
+pub async fn set_flag(flag: Flag, enabled: bool) -> Result<(), ServerFnError> {
+    require_admin()?;
+    tracing::info!("{flag} turned {}", if enabled { "on" } else { "off" });
+    set_enabled(flag, enabled);
+    Ok(())
+}
+
+/// The flags as of the page load, shared by every [`IfFlag`].
+#[derive(Clone, Copy)]
+struct FlagsResource(Resource<Result<Flags, ServerFnError>>);
+
+/// Fetches the flags while the page is rendered on the server, so the
+/// examples they switch off are left out of the HTML rather than removed
+/// after hydrating. Call it once, near the root.
+pub fn provide_flags() {
+    let flags = Resource::new_blocking(|| (), |_| get_flags());
+    provide_context(FlagsResource(flags));
+}
+
+/// Renders `children` only while `flag` is on.
+#[component]
+pub fn IfFlag(flag: Flag, children: ChildrenFn) -> impl IntoView {
+    let FlagsResource(flags) = expect_context();
+    let children = StoredValue::new(children);
+
+    view! {
+        <Suspense>
+            {move || Suspend::new(async move {
+                // if the flags can't be fetched, fall back to the defaults
+                let enabled = flags
+                    .await
+                    .map_or(flag.default_enabled(), |flags| flags.is_enabled(flag));
+                if enabled {
+                    children.read_value()().into_any()
+                } else {
+                    view! {
+                        <p>
+                            <em>{format!("The {flag} example is switched off.")}</em>
+                        </p>
+                    }
+                        .into_any()
+                }
+            })}
+        </Suspense>
+    }
+}
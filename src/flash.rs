@@ -0,0 +1,312 @@
+//! One-off messages for forms posted without JavaScript.
+//!
+//! A plain `<form>` post to a server fn is answered with a redirect back to
+//! the page it came from, which on its own says nothing about how the call
+//! went. Server fns [`set_flash`] a notice when they succeed, and
+//! [`redirect_failed_form_posts`] turns a failure into the same redirect
+//! with the error as a flash. They travel in a short-lived, signed cookie,
+//! which the next page takes and shows in its [`FlashMessages`].
+//!
+//! Calls from the app's own client never get one. They can [`show_toast`]
+//! instead, which shows a flash in the same place for a few seconds.
+
+use crate::{error_messages::error_message, errors::AppError};
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The cookie flashes travel in: a JSON list of [`Flash`]es and its
+/// HMAC-SHA256, both base64-encoded and joined by a `.`.
+pub const FLASH_COOKIE: &str = "flash";
+
+/// How long a flash waits to be shown before the browser drops it.
+pub const FLASH_MAX_AGE_SECS: u64 = 60;
+
+/// How long a toast is shown before it goes away by itself.
+const TOAST_DURATION: Duration = Duration::from_secs(6);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Flash {
+    /// What a form did.
+    Notice(String),
+    Error(AppError),
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{Flash, FLASH_COOKIE, FLASH_MAX_AGE_SECS};
+    use crate::{
+        auth::{cookie, set_cookie},
+        errors::{AppError, FormError, UpdateRowError},
+        middleware::is_server_fn_path,
+    };
+    use axum::{
+        body::Body,
+        extract::Request,
+        middleware::Next,
+        response::{IntoResponse, Response},
+    };
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use hmac::{Hmac, Mac};
+    use http::{
+        header::{ACCEPT, LOCATION, REFERER, SET_COOKIE},
+        request::Parts,
+        HeaderMap, HeaderValue, Method, StatusCode,
+    };
+    use leptos::prelude::use_context;
+    use leptos_axum::ResponseOptions;
+    use server_fn::{
+        error::{FromServerFnError, SERVER_FN_ERROR_HEADER},
+        Bytes, ServerFnError,
+    };
+    use sha2::Sha256;
+    use std::sync::LazyLock;
+
+    /// Error bodies longer than this aren't read for a flash.
+    const MAX_ERROR_BODY: usize = 16 * 1024;
+
+    /// The key flash cookies are signed with. It only has to outlive a
+    /// redirect, so a new one is made for each run of the server.
+    static FLASH_KEY: LazyLock<[u8; 32]> = LazyLock::new(|| {
+        let mut key = [0; 32];
+        getrandom::getrandom(&mut key)
+            .expect("couldn't get randomness for the flash key");
+        key
+    });
+
+    fn mac() -> Hmac<Sha256> {
+        Hmac::new_from_slice(&*FLASH_KEY).expect("HMAC takes any key")
+    }
+
+    /// Whether a request with `headers` is a browser navigating, as a
+    /// `<form>` does without JavaScript, rather than a `fetch`.
+    pub fn is_plain_form_post(headers: &HeaderMap) -> bool {
+        headers
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/html"))
+    }
+
+    fn flash_cookie(flashes: &[Flash]) -> Option<String> {
+        let json = serde_json::to_vec(flashes).ok()?;
+        let mut mac = mac();
+        mac.update(&json);
+        Some(format!(
+            "{FLASH_COOKIE}={}.{}; Path=/; HttpOnly; SameSite=Lax; \
+             Max-Age={FLASH_MAX_AGE_SECS}",
+            URL_SAFE_NO_PAD.encode(json),
+            URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+        ))
+    }
+
+    /// The flashes in a flash cookie's value, if this server signed it.
+    fn verify(value: &str) -> Option<Vec<Flash>> {
+        let (json, signature) = value.split_once('.')?;
+        let json = URL_SAFE_NO_PAD.decode(json).ok()?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        let mut mac = mac();
+        mac.update(&json);
+        mac.verify_slice(&signature).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+
+    /// Adds `flash` to those the response with `headers` already sets, so
+    /// one response can leave more than one.
+    fn add_flash(headers: &mut HeaderMap, flash: Flash) {
+        let mut flashes = Vec::new();
+        let mut others = Vec::new();
+        for set_cookie in headers.get_all(SET_COOKIE) {
+            let set = set_cookie
+                .to_str()
+                .ok()
+                .and_then(|c| c.strip_prefix(FLASH_COOKIE)?.strip_prefix('='))
+                .and_then(|c| verify(c.split(';').next()?));
+            match set {
+                Some(set) => flashes.extend(set),
+                None => others.push(set_cookie.clone()),
+            }
+        }
+        flashes.push(flash);
+        let Some(cookie) = flash_cookie(&flashes)
+            .and_then(|cookie| HeaderValue::from_str(&cookie).ok())
+        else {
+            return;
+        };
+        headers.remove(SET_COOKIE);
+        for other in others {
+            headers.append(SET_COOKIE, other);
+        }
+        headers.append(SET_COOKIE, cookie);
+    }
+
+    /// Shows `flash` on the page the server fn being handled redirects
+    /// to, if it was called by a plain form post.
+    pub fn set_flash(flash: Flash) {
+        let plain = use_context::<Parts>()
+            .is_some_and(|parts| is_plain_form_post(&parts.headers));
+        if let Some(response) =
+            plain.then(use_context::<ResponseOptions>).flatten()
+        {
+            add_flash(&mut response.0.write().unwrap().headers, flash);
+        }
+    }
+
+    /// The flashes the request being rendered brought along, which are
+    /// then cleared so they're only shown once. A cookie this server
+    /// didn't sign brings none.
+    pub fn take_flashes() -> Vec<Flash> {
+        let Some(value) = use_context::<Parts>()
+            .and_then(|parts| cookie(&parts.headers, FLASH_COOKIE))
+        else {
+            return Vec::new();
+        };
+        set_cookie(&format!(
+            "{FLASH_COOKIE}=; Path=/; HttpOnly; SameSite=Lax; Max-Age=0"
+        ));
+        verify(&value).unwrap_or_default()
+    }
+
+    /// The error in a failed server fn's response body, for any of the
+    /// error types the app's server fns answer with.
+    fn decode_error(body: Bytes) -> AppError {
+        if let Ok(error) = serde_json::from_slice::<AppError>(&body) {
+            return error;
+        }
+        if let Ok(error) = serde_json::from_slice::<FormError>(&body) {
+            return error.error;
+        }
+        if let Ok(error) = serde_json::from_slice::<UpdateRowError>(&body) {
+            return AppError::new(&error);
+        }
+        AppError::new(&ServerFnError::de(body))
+    }
+
+    /// Middleware that answers a server fn call that a plain form post made
+    /// and that failed with a redirect back to the form's page, carrying
+    /// the error as a flash, instead of the error itself.
+    pub async fn redirect_failed_form_posts(
+        req: Request,
+        next: Next,
+    ) -> Response {
+        let plain = req.method() == Method::POST
+            && is_server_fn_path(req.uri().path())
+            && is_plain_form_post(req.headers());
+        if !plain {
+            return next.run(req).await;
+        }
+        let back = req
+            .headers()
+            .get(REFERER)
+            .cloned()
+            .unwrap_or(HeaderValue::from_static("/"));
+        let res = next.run(req).await;
+        if !res.headers().contains_key(SERVER_FN_ERROR_HEADER) {
+            return res;
+        }
+        let (parts, body) = res.into_parts();
+        let error = match axum::body::to_bytes(body, MAX_ERROR_BODY).await {
+            Ok(body) => decode_error(body),
+            Err(_) => AppError::new(&ServerFnError::new("the call failed")),
+        };
+        tracing::debug!(code = %error.code, "flashing a failed form post");
+        let mut res =
+            (StatusCode::SEE_OTHER, [(LOCATION, back)], Body::empty())
+                .into_response();
+        // cookies the server fn set, such as a new session, still count
+        for cookie in parts.headers.get_all(SET_COOKIE) {
+            res.headers_mut().append(SET_COOKIE, cookie.clone());
+        }
+        add_flash(res.headers_mut(), Flash::Error(error));
+        res
+    }
+}
+
+/// The flashes [`FlashMessages`] shows, each with an id to dismiss it by.
+#[derive(Clone, Copy)]
+struct Shown {
+    flashes: RwSignal<Vec<(usize, Flash)>>,
+    next_id: StoredValue<usize>,
+}
+
+impl Shown {
+    fn push(self, flash: Flash) -> usize {
+        let id = self.next_id.get_value();
+        self.next_id.set_value(id + 1);
+        self.flashes.update(|flashes| flashes.push((id, flash)));
+        id
+    }
+
+    fn dismiss(self, id: usize) {
+        self.flashes
+            .update(|flashes| flashes.retain(|(i, _)| *i != id));
+    }
+}
+
+/// Makes the flashes the page was loaded with, and any toasts shown
+/// later, what [`FlashMessages`] shows.
+pub fn provide_flash_messages() {
+    // taken while rendering on the server, and sent along to hydrate with
+    let loaded = SharedValue::new(|| {
+        #[cfg(feature = "ssr")]
+        {
+            take_flashes()
+        }
+        #[cfg(not(feature = "ssr"))]
+        {
+            Vec::<Flash>::new()
+        }
+    })
+    .into_inner();
+    let shown = Shown {
+        flashes: RwSignal::new(Vec::new()),
+        next_id: StoredValue::new(0),
+    };
+    for flash in loaded {
+        shown.push(flash);
+    }
+    provide_context(shown);
+}
+
+/// Shows `flash` with the page's [`FlashMessages`] for a few seconds, as
+/// a call from the app's own client tells how it went.
+pub fn show_toast(flash: Flash) {
+    let Some(shown) = use_context::<Shown>() else {
+        return;
+    };
+    let id = shown.push(flash);
+    set_timeout(move || shown.dismiss(id), TOAST_DURATION);
+}
+
+/// The flashes the page was loaded with, until they're dismissed, and the
+/// toasts shown since.
+#[component]
+pub fn FlashMessages() -> impl IntoView {
+    let shown = expect_context::<Shown>();
+
+    view! {
+        <div class="flashes" aria-live="polite">
+            <For
+                each=move || shown.flashes.get()
+                key=|(id, _)| *id
+                children=move |(id, flash)| {
+                    let error = matches!(flash, Flash::Error(_));
+                    view! {
+                        <div class="flash" class:error=error role="status">
+                            {match flash {
+                                Flash::Notice(notice) => notice.into_any(),
+                                Flash::Error(error) => error_message(&error).into_any(),
+                            }}
+                            " "
+                            <button on:click=move |_| shown.dismiss(id) title="Dismiss">
+                                "×"
+                            </button>
+                        </div>
+                    }
+                }
+            />
+        </div>
+    }
+}
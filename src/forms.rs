@@ -0,0 +1,571 @@
+//! Form bodies bound to structs, for [`FormUrl`](crate::codec::FormUrl) and
+//! [`BoundForm`](crate::components::bound_form::BoundForm).
+//!
+//! A field is named by its path of serde names, joined with dots, so a
+//! nested struct's fields are `due.days` and `due.skip_weekends`, and a
+//! sequence is the same name repeated, one input each: `tags=a&tags=b`.
+//! Values are read the way a browser sends them:
+//!
+//! - a `bool` is a checkbox: `on` or `true` if it's checked, and left out
+//!   if it isn't, which reads as `false`
+//! - numbers are parsed as their type, and an `Option` is `None` when its
+//!   input is left blank
+//! - blank inputs of a sequence are skipped, so a form can offer a few
+//!   more than are needed
+//!
+//! Every field of a struct is read, sent or not, so `#[serde(default)]` has
+//! no effect: fields that may be left out need one of the types above.
+//! Errors name the field they're about, to show them next to its input.
+
+use crate::errors::{AppError, FieldError, FormError, InvalidForm};
+use serde::{
+    de::{
+        self, value::StringDeserializer, DeserializeOwned, DeserializeSeed,
+        Deserializer, MapAccess, SeqAccess, Visitor,
+    },
+    forward_to_deserialize_any, Serialize,
+};
+use serde_json::Value as Json;
+use std::{collections::BTreeMap, fmt::Display, vec};
+
+/// Encodes `value`, which must serialize as a struct or map, as a form
+/// body.
+pub fn to_form_string(value: &impl Serialize) -> Result<String, String> {
+    let json = serde_json::to_value(value).map_err(|e| e.to_string())?;
+    if !json.is_object() {
+        return Err("a form must be a struct or map".to_string());
+    }
+    let mut pairs = Vec::new();
+    flatten("", json, &mut pairs)?;
+    serde_urlencoded::to_string(pairs).map_err(|e| e.to_string())
+}
+
+fn flatten(
+    name: &str,
+    value: Json,
+    pairs: &mut Vec<(String, String)>,
+) -> Result<(), String> {
+    match value {
+        Json::Object(fields) => {
+            for (field, value) in fields {
+                flatten(&join(name, &field), value, pairs)?;
+            }
+        }
+        Json::Array(items) => {
+            for item in items {
+                if item.is_object() || item.is_array() {
+                    return Err(format!(
+                        "`{name}` must be a sequence of scalars to be a form \
+                         field"
+                    ));
+                }
+                flatten(name, item, pairs)?;
+            }
+        }
+        Json::Null | Json::Bool(false) => {}
+        Json::Bool(true) => pairs.push((name.to_string(), "on".to_string())),
+        Json::Number(n) => pairs.push((name.to_string(), n.to_string())),
+        Json::String(s) => pairs.push((name.to_string(), s)),
+    }
+    Ok(())
+}
+
+/// Decodes a form body, as a browser or [`to_form_string`] sends it.
+pub fn from_form_string<T: DeserializeOwned>(
+    body: &str,
+) -> Result<T, DecodeError> {
+    let pairs = serde_urlencoded::from_str::<Vec<(String, String)>>(body)
+        .map_err(|e| {
+            DecodeError::new("", FieldError::Invalid(e.to_string()))
+        })?;
+    let mut fields = Vec::new();
+    for (name, value) in pairs {
+        let path = name.split('.').collect::<Vec<_>>();
+        insert(&mut fields, &path, value)
+            .map_err(|error| DecodeError::new(&name, error))?;
+    }
+    T::deserialize(Field {
+        path: String::new(),
+        node: Node::Fields(fields),
+    })
+}
+
+/// Why a form body couldn't be decoded, and which field it was about, if
+/// any.
+#[derive(Debug, Clone)]
+pub struct DecodeError {
+    /// The field's dotted name, or empty for the form as a whole.
+    pub field: String,
+    pub error: FieldError,
+}
+
+impl DecodeError {
+    fn new(field: &str, error: FieldError) -> Self {
+        DecodeError {
+            field: field.to_string(),
+            error,
+        }
+    }
+
+    /// Names `field` as the one this is about, unless a field inside it
+    /// already is.
+    fn at(mut self, field: &str) -> Self {
+        if self.field.is_empty() {
+            self.field = field.to_string();
+        }
+        self
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.field.is_empty() {
+            self.error.fmt(f)
+        } else {
+            write!(f, "{}: {}", self.field, self.error)
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl de::Error for DecodeError {
+    fn custom<M: Display>(message: M) -> Self {
+        DecodeError::new("", FieldError::Invalid(message.to_string()))
+    }
+
+    fn invalid_value(
+        unexpected: de::Unexpected,
+        expected: &dyn de::Expected,
+    ) -> Self {
+        DecodeError::new(
+            "",
+            FieldError::InvalidValue {
+                value: unexpected.to_string(),
+                expected: expected.to_string(),
+            },
+        )
+    }
+}
+
+impl From<DecodeError> for FormError {
+    fn from(error: DecodeError) -> Self {
+        let mut errors = FieldErrors::new();
+        errors.add(&error.field, error.error);
+        errors.finish().unwrap_err()
+    }
+}
+
+/// A form's values, with the fields of nested structs gathered under their
+/// struct's name.
+enum Node {
+    /// Every value sent for one name, in order.
+    Values(Vec<String>),
+    Fields(Vec<(String, Node)>),
+    /// Nothing was sent, like for an unchecked checkbox.
+    Missing,
+}
+
+fn insert(
+    fields: &mut Vec<(String, Node)>,
+    path: &[&str],
+    value: String,
+) -> Result<(), FieldError> {
+    let (name, rest) = path.split_first().expect("split always gives one");
+    let index = match fields.iter().position(|(n, _)| n == name) {
+        Some(index) => index,
+        None => {
+            let node = if rest.is_empty() {
+                Node::Values(Vec::new())
+            } else {
+                Node::Fields(Vec::new())
+            };
+            fields.push((name.to_string(), node));
+            fields.len() - 1
+        }
+    };
+    match (&mut fields[index].1, rest.is_empty()) {
+        (Node::Values(values), true) => {
+            values.push(value);
+            Ok(())
+        }
+        (Node::Fields(fields), false) => insert(fields, rest, value),
+        _ => Err(FieldError::Invalid(format!(
+            "`{name}` is sent both as a value and as fields"
+        ))),
+    }
+}
+
+/// One field, read as whatever type is asked for.
+struct Field {
+    /// Its dotted name, to name the fields inside it.
+    path: String,
+    node: Node,
+}
+
+impl Field {
+    /// The value a scalar field gets: the last one, like most servers do.
+    fn last(self) -> Result<String, DecodeError> {
+        match self.node {
+            Node::Values(mut values) => Ok(values.pop().unwrap_or_default()),
+            Node::Missing => Err(DecodeError::new("", FieldError::Missing)),
+            Node::Fields(_) => Err(DecodeError::new(
+                "",
+                FieldError::Invalid("expected one value".to_string()),
+            )),
+        }
+    }
+
+    fn is_blank(&self) -> bool {
+        match &self.node {
+            Node::Values(values) => {
+                values.last().is_none_or(|value| value.trim().is_empty())
+            }
+            Node::Missing => true,
+            Node::Fields(_) => false,
+        }
+    }
+}
+
+/// The dotted name of the field `name` inside the one at `path`.
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{path}.{name}")
+    }
+}
+
+macro_rules! parse_as {
+    ($($method:ident => $visit:ident),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(
+                self,
+                visitor: V,
+            ) -> Result<V::Value, DecodeError> {
+                let text = self.last()?;
+                let text = text.trim();
+                if text.is_empty() {
+                    return Err(DecodeError::new("", FieldError::Missing));
+                }
+                let value = text.parse().map_err(|_| {
+                    DecodeError::new(
+                        "",
+                        FieldError::InvalidValue {
+                            value: text.to_string(),
+                            expected: "a number".to_string(),
+                        },
+                    )
+                })?;
+                visitor.$visit(value)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Field {
+    type Error = DecodeError;
+
+    fn deserialize_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        match self.node {
+            Node::Values(ref values) if values.len() == 1 => {
+                visitor.visit_string(self.last()?)
+            }
+            Node::Values(_) => self.deserialize_seq(visitor),
+            Node::Fields(_) => self.deserialize_map(visitor),
+            Node::Missing => visitor.visit_none(),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        if let Node::Missing = self.node {
+            return visitor.visit_bool(false);
+        }
+        let text = self.last()?;
+        match text.trim() {
+            "on" | "true" => visitor.visit_bool(true),
+            "" | "off" | "false" => visitor.visit_bool(false),
+            other => Err(DecodeError::new(
+                "",
+                FieldError::InvalidValue {
+                    value: other.to_string(),
+                    expected: "a checkbox".to_string(),
+                },
+            )),
+        }
+    }
+
+    parse_as! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        visitor.visit_string(self.last()?)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        if self.is_blank() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        let values = match self.node {
+            Node::Values(values) => values,
+            Node::Missing => Vec::new(),
+            Node::Fields(_) => {
+                return Err(DecodeError::new(
+                    "",
+                    FieldError::Invalid("expected a list".to_string()),
+                ))
+            }
+        };
+        let items = values
+            .into_iter()
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| Node::Values(vec![value]))
+            .collect::<Vec<_>>();
+        visitor.visit_seq(Items {
+            path: self.path,
+            items: items.into_iter(),
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        names: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        let mut sent = match self.node {
+            Node::Fields(fields) => fields,
+            Node::Missing => Vec::new(),
+            Node::Values(_) => {
+                return Err(DecodeError::new(
+                    "",
+                    FieldError::Invalid("expected fields".to_string()),
+                ))
+            }
+        };
+        // every field is given, so a missing one reads as unchecked or blank
+        let fields = names
+            .iter()
+            .map(|name| {
+                let node = sent
+                    .iter()
+                    .position(|(n, _)| n == name)
+                    .map_or(Node::Missing, |i| sent.swap_remove(i).1);
+                (name.to_string(), node)
+            })
+            .collect::<Vec<_>>();
+        visitor.visit_map(Fields {
+            path: self.path,
+            fields: fields.into_iter(),
+            next: None,
+        })
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        let fields = match self.node {
+            Node::Fields(fields) => fields,
+            Node::Missing => Vec::new(),
+            Node::Values(_) => {
+                return Err(DecodeError::new(
+                    "",
+                    FieldError::Invalid("expected fields".to_string()),
+                ))
+            }
+        };
+        visitor.visit_map(Fields {
+            path: self.path,
+            fields: fields.into_iter(),
+            next: None,
+        })
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        StringDeserializer::<DecodeError>::new(self.last()?)
+            .deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 bytes byte_buf unit_struct tuple_struct identifier
+    }
+}
+
+/// A struct's fields, each read under its own name, so errors say which one
+/// they're about.
+struct Fields {
+    path: String,
+    fields: vec::IntoIter<(String, Node)>,
+    next: Option<Field>,
+}
+
+impl<'de> MapAccess<'de> for Fields {
+    type Error = DecodeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, DecodeError> {
+        let Some((name, node)) = self.fields.next() else {
+            return Ok(None);
+        };
+        self.next = Some(Field {
+            path: join(&self.path, &name),
+            node,
+        });
+        seed.deserialize(StringDeserializer::new(name)).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, DecodeError> {
+        let field = self.next.take().expect("a key comes before its value");
+        let path = field.path.clone();
+        seed.deserialize(field).map_err(|e| e.at(&path))
+    }
+}
+
+/// A sequence's values, which all share its name.
+struct Items {
+    path: String,
+    items: vec::IntoIter<Node>,
+}
+
+impl<'de> SeqAccess<'de> for Items {
+    type Error = DecodeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, DecodeError> {
+        let Some(node) = self.items.next() else {
+            return Ok(None);
+        };
+        let item = Field {
+            path: self.path.clone(),
+            node,
+        };
+        seed.deserialize(item)
+            .map(Some)
+            .map_err(|e| e.at(&self.path))
+    }
+}
+
+/// Gathers the errors of a form's fields, to check them all at once before
+/// failing with a [`FormError`].
+#[derive(Debug, Default)]
+pub struct FieldErrors(BTreeMap<String, AppError>);
+
+impl FieldErrors {
+    pub fn new() -> Self {
+        FieldErrors::default()
+    }
+
+    /// Keeps `error` for the field named `field`, unless it already has one.
+    pub fn add(&mut self, field: &str, error: impl Into<AppError>) {
+        self.0
+            .entry(field.to_string())
+            .or_insert_with(|| error.into());
+    }
+
+    /// `result`'s value, or `None` after keeping its error for `field`.
+    pub fn check<T, E: Into<AppError>>(
+        &mut self,
+        field: &str,
+        result: Result<T, E>,
+    ) -> Option<T> {
+        result.map_err(|e| self.add(field, e)).ok()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Fails with every error kept, if there are any.
+    pub fn finish(self) -> Result<(), FormError> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        Err(FormError {
+            error: AppError::new(&InvalidForm {
+                count: self.0.len(),
+            }),
+            fields: self.0,
+        })
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{
+    errors::AuthError,
+    query::{from_query_string, to_query_string},
+    sandbox::session_owner,
+    storage::{Row, RowQuery, RowSort, RowStatus, Tag, ROWS},
+};
+use axum::{
+    extract::{OriginalUri, RawQuery},
+    http::{
+        header::{CACHE_CONTROL, ETAG, IF_NONE_MATCH, VARY},
+        HeaderMap, StatusCode,
+    },
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Where [`router`] is mounted.
+pub const FRAGMENTS_PATH: &str = "/fragments";
+const DEFAULT_PER_PAGE: usize = 20;
+const MAX_PER_PAGE: usize = 100;
+
+/// The query string of `GET /fragments/rows`, in the same flat form as
+/// [`find_rows`](crate::app::find_rows): `?q=milk&tag=a&tag=b&page=2`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct RowsParams {
+    /// From 1.
+    page: usize,
+    per_page: usize,
+    sort: RowSort,
+    status: RowStatus,
+    #[serde(rename = "q")]
+    contains: String,
+    #[serde(rename = "tag")]
+    tags: Vec<Tag>,
+}
+
+impl Default for RowsParams {
+    fn default() -> Self {
+        Self {
+            page: 1,
+            per_page: DEFAULT_PER_PAGE,
+            sort: RowSort::default(),
+            status: RowStatus::default(),
+            contains: String::new(),
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// The row list as bare HTML fragments, for htmx and other clients that
+/// don't run the app's WASM. Requests are authenticated by the session or
+/// sandbox cookie, like the app's own. Mount it at [`FRAGMENTS_PATH`].
+pub fn router<S: Clone + Send + Sync + 'static>() -> Router<S> {
+    Router::new().route("/rows", get(rows))
+}
+
+/// One page of the caller's rows. Responses carry an `ETag`, so a client
+/// polling for changes gets a `304 Not Modified` until something changes.
+async fn rows(
+    OriginalUri(uri): OriginalUri,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+) -> Response {
+    let Some(owner) = session_owner(&headers) else {
+        return (StatusCode::UNAUTHORIZED, AuthError::NotSignedIn.to_string())
+            .into_response();
+    };
+    let params = match from_query_string::<RowsParams>(
+        query.as_deref().unwrap_or_default(),
+    ) {
+        Ok(params) => RowsParams {
+            page: params.page.max(1),
+            per_page: params.per_page.clamp(1, MAX_PER_PAGE),
+            ..params
+        },
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let query = RowQuery {
+        sort: params.sort,
+        status: params.status,
+        contains: params.contains.clone(),
+        tags: params.tags.clone(),
+    };
+    let skip = (params.page - 1) * params.per_page;
+    // one row more than the page says whether there is a next one
+    let mut rows = ROWS
+        .list(owner, &query, skip + params.per_page + 1)
+        .into_iter()
+        .skip(skip)
+        .collect::<Vec<_>>();
+    let has_next = rows.len() > params.per_page;
+    rows.truncate(params.per_page);
+
+    // links keep the path the request came in on, so they work under a
+    // base path and when the fragment is embedded in another page
+    let link = |page: usize| {
+        to_query_string(&RowsParams {
+            page,
+            ..params.clone()
+        })
+        .ok()
+        .map(|query| format!("{}?{query}", uri.path()))
+    };
+    let previous = (params.page > 1).then(|| link(params.page - 1)).flatten();
+    let next = has_next.then(|| link(params.page + 1)).flatten();
+    let page = params.page;
+    let html = Owner::new()
+        .with(|| view! { <RowsFragment rows page previous next /> }.to_html());
+
+    let etag = format!("\"{:x}\"", Sha256::digest(html.as_bytes()));
+    let caching = [
+        // the rows are the caller's own, and change at any time
+        (CACHE_CONTROL, "private, no-cache".to_string()),
+        (VARY, "Cookie".to_string()),
+        (ETAG, etag.clone()),
+    ];
+    let unchanged = headers
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|tags| {
+            tags.split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag == etag)
+        });
+    if unchanged {
+        (StatusCode::NOT_MODIFIED, caching).into_response()
+    } else {
+        (caching, Html(html)).into_response()
+    }
+}
+
+/// A page of rows, with links to the pages either side that replace the
+/// fragment in place under htmx and navigate to it without.
+#[component]
+fn RowsFragment(
+    rows: Vec<Row>,
+    page: usize,
+    previous: Option<String>,
+    next: Option<String>,
+) -> impl IntoView {
+    let page_link = |href: String, label: &'static str| {
+        view! {
+            <a
+                href=href.clone()
+                hx-get=href
+                hx-target="closest .rows-fragment"
+                hx-swap="outerHTML"
+            >
+                {label}
+            </a>
+        }
+    };
+
+    view! {
+        <div class="rows-fragment" data-page=page>
+            {if rows.is_empty() {
+                view! { <p>"No rows."</p> }.into_any()
+            } else {
+                view! {
+                    <ul>
+                        {rows
+                            .into_iter()
+                            .map(|row| {
+                                view! {
+                                    <li data-row-id=row.id class:completed=row.completed>
+                                        <span inner_html=row.html></span>
+                                        {row
+                                            .tags
+                                            .into_iter()
+                                            .map(|tag| {
+                                                view! { <span class="tag">{tag.as_str().to_string()}</span> }
+                                            })
+                                            .collect::<Vec<_>>()}
+                                    </li>
+                                }
+                            })
+                            .collect::<Vec<_>>()}
+                    </ul>
+                }
+                    .into_any()
+            }}
+            <nav>
+                {previous.map(|href| page_link(href, "Previous"))}
+                {next.map(|href| page_link(href, "Next"))}
+            </nav>
+        </div>
+    }
+}
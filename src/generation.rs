@@ -0,0 +1,421 @@
+//! Text written a token at a time, as a language model writes it, streamed
+//! to a chat that shows each token as it arrives and can stop or redo a
+//! reply.
+//!
+//! The writing is done by a [`TextGenerator`] picked with
+//! `[generation] backend`: canned text with made-up pauses, or a chat
+//! completions API such as OpenAI's. Another model drops in by implementing
+//! the trait.
+
+use crate::codec::{Framed, FramedStream};
+use futures::{
+    future::{AbortHandle, Abortable},
+    StreamExt,
+};
+use leptos::{prelude::*, task::spawn_local};
+
+/// The longest prompt [`generate_text`] takes, in characters.
+pub const MAX_PROMPT_LEN: usize = 2000;
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use crate::{
+        errors::GenerationError,
+        settings::{GenerationSettings, GeneratorBackend, OpenAiSettings},
+    };
+    use futures::{stream, stream::BoxStream, Stream, StreamExt, TryStreamExt};
+    use reqwest::{header::CONTENT_TYPE, Client};
+    use serde_json::{json, Value as Json};
+    use std::{sync::OnceLock, time::Duration};
+
+    /// A reply, a token at a time; an error is its last item.
+    pub type TokenStream = BoxStream<'static, Result<String, GenerationError>>;
+
+    /// Writes replies to prompts.
+    pub trait TextGenerator: Send + Sync {
+        fn generate(&self, prompt: &str) -> TokenStream;
+    }
+
+    /// Set from `[generation]`; replies are simulated until then.
+    static GENERATOR: OnceLock<Box<dyn TextGenerator>> = OnceLock::new();
+
+    /// Applies the `[generation]` settings; call it once, before serving.
+    pub fn init(settings: &GenerationSettings) -> Result<(), GenerationError> {
+        let generator: Box<dyn TextGenerator> = match settings.backend {
+            GeneratorBackend::Simulated => Box::new(SimulatedGenerator),
+            GeneratorBackend::OpenAi => {
+                Box::new(OpenAiGenerator::new(&settings.openai)?)
+            }
+        };
+        _ = GENERATOR.set(generator);
+        Ok(())
+    }
+
+    pub fn generator() -> &'static dyn TextGenerator {
+        GENERATOR
+            .get_or_init(|| Box::new(SimulatedGenerator))
+            .as_ref()
+    }
+
+    /// The pauses between simulated tokens, at least.
+    const MIN_TOKEN_DELAY: Duration = Duration::from_millis(20);
+    /// How much longer than [`MIN_TOKEN_DELAY`] a pause can be.
+    const TOKEN_DELAY_SPREAD_MS: u64 = 130;
+
+    const SIMULATED_REPLIES: [&str; 3] = [
+        "You asked about \"{prompt}\". A good place to start is to split it \
+         into smaller questions, answer the one you're surest of, and see \
+         what that tells you about the rest.",
+        "Here's a thought on \"{prompt}\": most of the work is deciding what \
+         not to do. Write down the two options you keep coming back to and \
+         try the cheaper one first.",
+        "\"{prompt}\" is a fine question. I'm only a simulation streaming \
+         canned words with made-up pauses, but a real model would answer it \
+         the same way, one token at a time.",
+    ];
+
+    /// Canned replies, with a pause before each token like a model's.
+    pub struct SimulatedGenerator;
+
+    impl TextGenerator for SimulatedGenerator {
+        fn generate(&self, prompt: &str) -> TokenStream {
+            // a new seed each time, so regenerating gives another reply
+            let mut seed = [0; 8];
+            _ = getrandom::getrandom(&mut seed);
+            let mut random = XorShift(u64::from_le_bytes(seed) | 1);
+            let reply = SIMULATED_REPLIES
+                [random.next() as usize % SIMULATED_REPLIES.len()]
+            .replace("{prompt}", prompt.trim());
+            let tokens = reply
+                .split_inclusive(' ')
+                .map(str::to_string)
+                .collect::<Vec<_>>();
+            stream::iter(tokens)
+                .then(move |token| {
+                    let spread = random.next() % TOKEN_DELAY_SPREAD_MS;
+                    let delay = MIN_TOKEN_DELAY + Duration::from_millis(spread);
+                    async move {
+                        tokio::time::sleep(delay).await;
+                        Ok(token)
+                    }
+                })
+                .boxed()
+        }
+    }
+
+    /// Good enough to vary pauses; not for anything that has to be
+    /// unpredictable.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    /// Streams replies from a chat completions API.
+    pub struct OpenAiGenerator {
+        client: Client,
+        settings: OpenAiSettings,
+        api_key: String,
+    }
+
+    impl OpenAiGenerator {
+        /// An API key missing from `settings` is read from
+        /// `OPENAI_API_KEY`.
+        pub fn new(settings: &OpenAiSettings) -> Result<Self, GenerationError> {
+            let api_key = settings
+                .api_key
+                .clone()
+                .or_else(|| std::env::var("OPENAI_API_KEY").ok())
+                .ok_or_else(|| {
+                    GenerationError::MissingApiKey("OPENAI_API_KEY".to_string())
+                })?;
+            Ok(Self {
+                client: Client::new(),
+                settings: settings.clone(),
+                api_key,
+            })
+        }
+    }
+
+    impl TextGenerator for OpenAiGenerator {
+        fn generate(&self, prompt: &str) -> TokenStream {
+            let body = json!({
+                "model": self.settings.model,
+                "max_tokens": self.settings.max_tokens,
+                "stream": true,
+                "messages": [{ "role": "user", "content": prompt }],
+            });
+            let request = self
+                .client
+                .post(format!(
+                    "{}/chat/completions",
+                    self.settings.endpoint.trim_end_matches('/')
+                ))
+                .bearer_auth(&self.api_key)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+            let body = stream::once(async move {
+                let res = request
+                    .send()
+                    .await
+                    .map_err(|e| GenerationError::Request(e.to_string()))?;
+                let status = res.status();
+                if !status.is_success() {
+                    let message = res.text().await.unwrap_or_default();
+                    return Err(GenerationError::Api {
+                        status: status.as_u16(),
+                        message,
+                    });
+                }
+                Ok(res
+                    .bytes_stream()
+                    .map_err(|e| GenerationError::Request(e.to_string())))
+            })
+            .try_flatten();
+            completion_tokens(body).boxed()
+        }
+    }
+
+    /// The text of each chunk in a stream of chat completion chunks, sent
+    /// as server-sent events: `data: {...}` lines, then `data: [DONE]`.
+    fn completion_tokens<B: AsRef<[u8]> + Send + 'static>(
+        body: impl Stream<Item = Result<B, GenerationError>> + Send + 'static,
+    ) -> impl Stream<Item = Result<String, GenerationError>> + Send {
+        let state = Some((Box::pin(body), Vec::new()));
+        stream::unfold(state, |state| async move {
+            let (mut body, mut buffer) = state?;
+            loop {
+                if let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                    let line = buffer.drain(..=end).collect::<Vec<_>>();
+                    let line = String::from_utf8_lossy(&line);
+                    let Some(data) = line.trim().strip_prefix("data:") else {
+                        continue;
+                    };
+                    let data = data.trim();
+                    if data == "[DONE]" {
+                        return None;
+                    }
+                    let token = serde_json::from_str::<Json>(data)
+                        .ok()
+                        .and_then(|chunk| {
+                            let content =
+                                &chunk["choices"][0]["delta"]["content"];
+                            content.as_str().map(str::to_string)
+                        })
+                        .filter(|token| !token.is_empty());
+                    if let Some(token) = token {
+                        return Some((Ok(token), Some((body, buffer))));
+                    }
+                    continue;
+                }
+                match body.next().await? {
+                    Ok(chunk) => buffer.extend_from_slice(chunk.as_ref()),
+                    Err(e) => return Some((Err(e), None)),
+                }
+            }
+        })
+    }
+}
+
+/// Streams the reply to `prompt` a token at a time.
+#[server(output = Framed)]
+pub async fn generate_text(
+    prompt: String,
+) -> Result<FramedStream<String>, ServerFnError> {
+    use crate::crawlers::refuse_crawlers;
+
+    refuse_crawlers()?;
+    let prompt = prompt.trim();
+    if prompt.is_empty() {
+        return Err(ServerFnError::new("ask something first"));
+    }
+    if prompt.chars().count() > MAX_PROMPT_LEN {
+        return Err(ServerFnError::new(format!(
+            "prompts can be at most {MAX_PROMPT_LEN} characters long"
+        )));
+    }
+    let tokens = generator()
+        .generate(prompt)
+        .map(|token| token.map_err(ServerFnError::new));
+    Ok(FramedStream::new(tokens))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReplyState {
+    Writing,
+    Done,
+    Stopped,
+    Failed,
+}
+
+/// One prompt and its reply so far.
+#[derive(Debug, Clone)]
+struct Exchange {
+    id: usize,
+    prompt: String,
+    reply: String,
+    state: ReplyState,
+}
+
+/// A chat with the configured [`TextGenerator`], where replies show up a
+/// token at a time. The reply being written can be stopped, and the last
+/// one written again.
+#[component]
+pub fn ChatExample() -> impl IntoView {
+    let exchanges = RwSignal::new(Vec::<Exchange>::new());
+    let (prompt, set_prompt) = signal(String::new());
+    // stops the reply being written, by dropping its stream
+    let writing = StoredValue::new(None::<AbortHandle>);
+    let next_id = StoredValue::new(0usize);
+
+    let update = move |id: usize, f: &dyn Fn(&mut Exchange)| {
+        _ = exchanges.try_update(|exchanges| {
+            if let Some(exchange) = exchanges.iter_mut().find(|e| e.id == id) {
+                f(exchange);
+            }
+        });
+    };
+    let write = move |id: usize, prompt: String| {
+        let (abort, registration) = AbortHandle::new_pair();
+        writing.set_value(Some(abort));
+        let reply = async move {
+            let tokens = match generate_text(prompt).await {
+                Ok(tokens) => tokens,
+                Err(e) => {
+                    update(id, &|exchange| {
+                        exchange.reply = e.to_string();
+                        exchange.state = ReplyState::Failed;
+                    });
+                    return;
+                }
+            };
+            let mut tokens = tokens.into_inner();
+            while let Some(token) = tokens.next().await {
+                match token {
+                    Ok(token) => {
+                        update(id, &|exchange| exchange.reply.push_str(&token))
+                    }
+                    Err(e) => {
+                        update(id, &|exchange| {
+                            exchange.reply.push_str(&format!(" [{e}]"));
+                            exchange.state = ReplyState::Failed;
+                        });
+                        return;
+                    }
+                }
+            }
+            update(id, &|exchange| exchange.state = ReplyState::Done);
+        };
+        spawn_local(async move {
+            _ = Abortable::new(reply, registration).await;
+        });
+    };
+    let is_writing = move || {
+        exchanges.with(|exchanges| {
+            exchanges
+                .last()
+                .is_some_and(|e| e.state == ReplyState::Writing)
+        })
+    };
+    let stop = move |_| {
+        if let Some(abort) = writing.get_value() {
+            abort.abort();
+        }
+        if let Some(id) =
+            exchanges.with_untracked(|exchanges| exchanges.last().map(|e| e.id))
+        {
+            update(id, &|exchange| {
+                if exchange.state == ReplyState::Writing {
+                    exchange.state = ReplyState::Stopped;
+                }
+            });
+        }
+    };
+    let send = move |ev: leptos::ev::SubmitEvent| {
+        ev.prevent_default();
+        let prompt = prompt.get_untracked();
+        if prompt.trim().is_empty() || is_writing() {
+            return;
+        }
+        let id = next_id.get_value();
+        next_id.set_value(id + 1);
+        exchanges.update(|exchanges| {
+            exchanges.push(Exchange {
+                id,
+                prompt: prompt.clone(),
+                reply: String::new(),
+                state: ReplyState::Writing,
+            })
+        });
+        set_prompt.set(String::new());
+        write(id, prompt);
+    };
+    let regenerate = move |_| {
+        let Some((id, prompt)) = exchanges.with_untracked(|exchanges| {
+            exchanges.last().map(|e| (e.id, e.prompt.clone()))
+        }) else {
+            return;
+        };
+        update(id, &|exchange| {
+            exchange.reply.clear();
+            exchange.state = ReplyState::Writing;
+        });
+        write(id, prompt);
+    };
+
+    view! {
+        <h3>"Streaming generated text"</h3>
+        <p>
+            "The reply streams in as newline-delimited JSON, a token at a time. "
+            "Stopping drops the stream, which ends the call on the server too."
+        </p>
+        <ol class="chat">
+            <For
+                each=move || exchanges.get()
+                key=|exchange| (exchange.id, exchange.reply.len(), exchange.state)
+                let:exchange
+            >
+                <li>
+                    <p class="chat-prompt">{exchange.prompt}</p>
+                    <p
+                        class="chat-reply"
+                        class:pending=exchange.state == ReplyState::Writing
+                        class:error=exchange.state == ReplyState::Failed
+                    >
+                        {exchange.reply}
+                        {(exchange.state == ReplyState::Stopped).then_some(" (stopped)")}
+                    </p>
+                </li>
+            </For>
+        </ol>
+        <form on:submit=send>
+            <input
+                placeholder="Ask something"
+                maxlength=MAX_PROMPT_LEN.to_string()
+                prop:value=prompt
+                on:input=move |ev| set_prompt.set(event_target_value(&ev))
+            />
+            <button type="submit" disabled=is_writing>
+                "Send"
+            </button>
+            <button type="button" on:click=stop disabled=move || !is_writing()>
+                "Stop"
+            </button>
+            <button
+                type="button"
+                on:click=regenerate
+                disabled=move || is_writing() || exchanges.with(Vec::is_empty)
+            >
+                "Regenerate"
+            </button>
+        </form>
+    }
+}
@@ -0,0 +1,254 @@
+//! The languages the app speaks, and what it says in each about an error
+//! code.
+//!
+//! The server picks a [`Locale`] from the request's `Accept-Language` and
+//! puts it in `<html lang>`, where the browser reads it back when it
+//! hydrates, so both render errors the same way.
+
+use crate::{error_messages::Recovery, errors::ErrorParams};
+use leptos::prelude::*;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::De];
+
+    /// The language tag, as in `<html lang>`.
+    pub fn tag(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+        }
+    }
+
+    /// The locale for a language tag like `de` or `de-AT`.
+    pub fn from_tag(tag: &str) -> Option<Locale> {
+        let language = tag.split(['-', '_']).next()?.trim();
+        Locale::ALL
+            .into_iter()
+            .find(|locale| locale.tag().eq_ignore_ascii_case(language))
+    }
+
+    /// The best locale for an `Accept-Language` header: the one with the
+    /// highest `q`, or the first of those, falling back to the default.
+    pub fn negotiate(accept_language: &str) -> Locale {
+        let mut best = None;
+        for range in accept_language.split(',') {
+            let mut parts = range.split(';');
+            let Some(locale) = parts.next().and_then(Locale::from_tag) else {
+                continue;
+            };
+            let q = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((locale, q));
+            }
+        }
+        best.map(|(locale, _)| locale).unwrap_or_default()
+    }
+}
+
+/// The locale the page is rendered in.
+pub fn request_locale() -> Locale {
+    #[cfg(feature = "ssr")]
+    {
+        use http::{header::ACCEPT_LANGUAGE, request::Parts};
+
+        use_context::<Parts>()
+            .and_then(|parts| {
+                let header = parts.headers.get(ACCEPT_LANGUAGE)?;
+                Some(Locale::negotiate(header.to_str().ok()?))
+            })
+            .unwrap_or_default()
+    }
+    #[cfg(not(feature = "ssr"))]
+    {
+        document()
+            .document_element()
+            .map(|html| html.get_attribute("lang").unwrap_or_default())
+            .and_then(|tag| Locale::from_tag(&tag))
+            .unwrap_or_default()
+    }
+}
+
+/// Makes [`request_locale`] the locale for everything below.
+pub fn provide_locale() {
+    provide_context(request_locale());
+}
+
+pub fn use_locale() -> Locale {
+    use_context::<Locale>().unwrap_or_default()
+}
+
+/// `template` with each `{name}` replaced by its param, or `None` if it
+/// names a param that isn't there.
+pub fn fill(template: &str, params: &ErrorParams) -> Option<String> {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = start + rest[start..].find('}')?;
+        filled.push_str(&rest[..start]);
+        filled.push_str(params.get(&rest[start + 1..end])?);
+        rest = &rest[end + 1..];
+    }
+    filled.push_str(rest);
+    Some(filled)
+}
+
+/// The message for `code` in `locale`, with its params filled in, if the
+/// client knows the code.
+pub fn error_text(
+    locale: Locale,
+    code: &str,
+    params: &ErrorParams,
+) -> Option<String> {
+    let template = match locale {
+        Locale::En => english_error(code),
+        Locale::De => german_error(code),
+    };
+    fill(template?, params)
+}
+
+pub fn recovery_hint(locale: Locale, recovery: Recovery) -> &'static str {
+    use Recovery::*;
+
+    match (locale, recovery) {
+        (Locale::En, TryAgain) => "Try again.",
+        (Locale::En, TryLater) => "Try again in a little while.",
+        (Locale::En, SignIn) => "Sign in and try again.",
+        (Locale::En, Edit) => "Change it and try again.",
+        (Locale::En, Reload) => "Reload the page to see the latest.",
+        (Locale::En, ContactAdmin) => {
+            "Let an admin know if this keeps happening."
+        }
+        (Locale::De, TryAgain) => "Versuch es noch einmal.",
+        (Locale::De, TryLater) => "Versuch es gleich noch einmal.",
+        (Locale::De, SignIn) => "Melde dich an und versuch es noch einmal.",
+        (Locale::De, Edit) => "Ändere es und versuch es noch einmal.",
+        (Locale::De, Reload) => "Lade die Seite neu, um den Stand zu sehen.",
+        (Locale::De, ContactAdmin) => {
+            "Sag einem Admin Bescheid, wenn das öfter passiert."
+        }
+    }
+}
+
+fn english_error(code: &str) -> Option<&'static str> {
+    Some(match code {
+        "argument.too_short" => "That's too short.",
+        "argument.too_long" => "That's too long.",
+        "argument.not_ascii" => "Only plain ASCII characters are allowed.",
+        "row.empty" => "A row needs some text.",
+        "row.too_long" => "A row can be at most {max} characters long.",
+        "row.conflict" => "Someone else changed this row meanwhile.",
+        "row.not_found" => "That row doesn't exist anymore.",
+        "row.chaos" => "Oh no! Couldn't add to database!",
+        "row.due_too_late" => "Rows can be due at most {max} days from now.",
+        "tag.empty" => "Tags can't be empty.",
+        "tag.too_long" => "Tags can be at most {max} characters long.",
+        "tag.too_many" => "A row can have at most {max} tags.",
+        "upload.too_large" => "Files can be at most {max} bytes.",
+        "upload.unsupported_format" => "{file_name} can't be imported.",
+        "upload.missing_file_name" => "Pick a file first.",
+        "quota.exceeded" => "You've reached today's limit of {limit} {kind}.",
+        "auth.not_signed_in" => "You need to sign in.",
+        "auth.expired" => "Your sign-in has expired.",
+        "auth.forbidden" => "Only admins can do that.",
+        "auth.invalid_credentials" => "Wrong user name or password.",
+        "auth.name_taken" => "The name {name} is taken.",
+        "auth.password_too_short" => {
+            "Passwords need at least {min} characters."
+        }
+        "auth.two_factor_required" => {
+            "Enter the code from your authenticator app."
+        }
+        "auth.invalid_two_factor_code" => "That code is wrong.",
+        "auth.two_factor_locked" => {
+            "Too many wrong codes. Try again in {minutes} minutes."
+        }
+        "call.two_factor_required" => {
+            "Sign in with a two-factor code or a passkey first."
+        }
+        "call.rate_limited" => "Too many calls.",
+        "call.circuit_open" | "call.overloaded" => "This is busy right now.",
+        "call.timed_out" => "That took too long.",
+        "form.invalid" => "{count} fields need fixing.",
+        "form.missing" => "This needs filling in.",
+        "form.invalid_value" => "{value} isn't {expected}.",
+        "flag.disabled" => "This example is switched off.",
+        "dev.disabled" => "Request logs are only kept in debug builds.",
+        "app.panic" => "Something went wrong on the server ({request_id}).",
+        "app.internal" => "Something went wrong on the server.",
+        "server_fn.request" | "server_fn.response" => {
+            "Couldn't reach the server: {detail}"
+        }
+        _ => return None,
+    })
+}
+
+fn german_error(code: &str) -> Option<&'static str> {
+    Some(match code {
+        "argument.too_short" => "Das ist zu kurz.",
+        "argument.too_long" => "Das ist zu lang.",
+        "argument.not_ascii" => "Nur einfache ASCII-Zeichen sind erlaubt.",
+        "row.empty" => "Eine Zeile braucht etwas Text.",
+        "row.too_long" => "Eine Zeile darf höchstens {max} Zeichen haben.",
+        "row.conflict" => "Jemand anderes hat diese Zeile inzwischen geändert.",
+        "row.not_found" => "Diese Zeile gibt es nicht mehr.",
+        "row.chaos" => "Oh nein! Konnte nicht in die Datenbank schreiben!",
+        "row.due_too_late" => {
+            "Zeilen dürfen höchstens {max} Tage im Voraus fällig sein."
+        }
+        "tag.empty" => "Tags dürfen nicht leer sein.",
+        "tag.too_long" => "Tags dürfen höchstens {max} Zeichen haben.",
+        "tag.too_many" => "Eine Zeile darf höchstens {max} Tags haben.",
+        "upload.too_large" => "Dateien dürfen höchstens {max} Bytes groß sein.",
+        "upload.unsupported_format" => {
+            "{file_name} kann nicht importiert werden."
+        }
+        "upload.missing_file_name" => "Wähl zuerst eine Datei aus.",
+        "quota.exceeded" => "Du hast das heutige Limit von {limit} erreicht.",
+        "auth.not_signed_in" => "Du musst dich anmelden.",
+        "auth.expired" => "Deine Anmeldung ist abgelaufen.",
+        "auth.forbidden" => "Das dürfen nur Admins.",
+        "auth.invalid_credentials" => "Falscher Benutzername oder Passwort.",
+        "auth.name_taken" => "Der Name {name} ist schon vergeben.",
+        "auth.password_too_short" => {
+            "Passwörter brauchen mindestens {min} Zeichen."
+        }
+        "auth.two_factor_required" => {
+            "Gib den Code aus deiner Authenticator-App ein."
+        }
+        "auth.invalid_two_factor_code" => "Dieser Code ist falsch.",
+        "auth.two_factor_locked" => {
+            "Zu viele falsche Codes. Versuch es in {minutes} Minuten wieder."
+        }
+        "call.two_factor_required" => {
+            "Melde dich zuerst mit einem Zwei-Faktor-Code oder Passkey an."
+        }
+        "call.rate_limited" => "Zu viele Aufrufe.",
+        "call.circuit_open" | "call.overloaded" => {
+            "Das ist gerade ausgelastet."
+        }
+        "call.timed_out" => "Das hat zu lange gedauert.",
+        "form.invalid" => "{count} Felder müssen korrigiert werden.",
+        "form.missing" => "Das muss ausgefüllt werden.",
+        "form.invalid_value" => "{value} ist kein gültiger Wert.",
+        "flag.disabled" => "Dieses Beispiel ist abgeschaltet.",
+        "dev.disabled" => "Request-Logs gibt es nur in Debug-Builds.",
+        "app.panic" => {
+            "Auf dem Server ist etwas schiefgegangen ({request_id})."
+        }
+        "app.internal" => "Auf dem Server ist etwas schiefgegangen.",
+        "server_fn.request" | "server_fn.response" => {
+            "Der Server ist nicht erreichbar: {detail}"
+        }
+        _ => return None,
+    })
+}
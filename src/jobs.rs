@@ -0,0 +1,33 @@
+use crate::progress::{self, Audience, ProgressKind};
+use std::{future::Future, time::Duration};
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::Instrument;
+
+/// Runs `job` every `period`, starting right away, until the server shuts
+/// down. A run that overruns delays the next one rather than piling up.
+/// Each run is reported to admins as a [`ProgressKind::Job`].
+pub fn spawn_periodic<F, Fut>(name: &'static str, period: Duration, mut job: F)
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let run = progress::start(
+                Audience::Admins,
+                ProgressKind::Job,
+                name,
+                None,
+            );
+            let started = Instant::now();
+            job().instrument(tracing::info_span!("job", name)).await;
+            run.complete(Some(format!(
+                "done in {}ms",
+                started.elapsed().as_millis()
+            )));
+        }
+    });
+}
@@ -0,0 +1,264 @@
+#[cfg(feature = "ssr")]
+use crate::auth::require_user;
+use chrono::{DateTime, Local, Utc};
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How long a token from [`create_token`] is valid for, in seconds.
+pub const TOKEN_TTL_SECS: i64 = 15 * 60;
+/// Where the public keys that tokens are signed with are served.
+pub const JWKS_PATH: &str = "/.well-known/jwks.json";
+
+/// A freshly minted token, to be sent as `Authorization: Bearer <token>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IssuedToken {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{IssuedToken, TOKEN_TTL_SECS};
+    use crate::{
+        auth::{Role, User, UserId},
+        errors::TokenError,
+    };
+    use axum::Json;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use chrono::{DateTime, TimeDelta, Utc};
+    use ed25519_dalek::{Signature, Signer, SigningKey, Verifier};
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
+    use std::{
+        sync::{LazyLock, RwLock},
+        time::Duration,
+    };
+
+    /// How often [`rotate_keys`] should run. Keys are kept for a token
+    /// lifetime after they are replaced, so no valid token stops verifying.
+    pub const KEY_ROTATION_INTERVAL: Duration =
+        Duration::from_secs(24 * 60 * 60);
+
+    /// The keys tokens are signed and verified with.
+    static KEYS: LazyLock<RwLock<KeyRing>> =
+        LazyLock::new(|| RwLock::new(KeyRing::new()));
+
+    struct KeyRing {
+        /// Signs new tokens.
+        current: Key,
+        /// Replaced keys that may still have valid tokens out, with when
+        /// they were replaced.
+        retired: Vec<(Key, DateTime<Utc>)>,
+    }
+
+    struct Key {
+        /// The `kid` of the tokens it signs.
+        id: String,
+        signing: SigningKey,
+    }
+
+    impl Key {
+        fn generate() -> Self {
+            let mut seed = [0; 32];
+            getrandom::getrandom(&mut seed)
+                .expect("couldn't get randomness for a signing key");
+            Self {
+                id: uuid::Uuid::new_v4().simple().to_string(),
+                signing: SigningKey::from_bytes(&seed),
+            }
+        }
+
+        fn jwk(&self) -> Value {
+            json!({
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "alg": "EdDSA",
+                "use": "sig",
+                "kid": self.id,
+                "x": URL_SAFE_NO_PAD.encode(self.signing.verifying_key().as_bytes()),
+            })
+        }
+    }
+
+    impl KeyRing {
+        fn new() -> Self {
+            Self {
+                current: Key::generate(),
+                retired: Vec::new(),
+            }
+        }
+
+        fn keys(&self) -> impl Iterator<Item = &Key> {
+            [&self.current]
+                .into_iter()
+                .chain(self.retired.iter().map(|(key, _)| key))
+        }
+    }
+
+    /// Starts signing with a new key, and forgets keys whose tokens have
+    /// all expired.
+    pub async fn rotate_keys() {
+        let mut keys = KEYS.write().unwrap();
+        let now = Utc::now();
+        let ttl = TimeDelta::seconds(TOKEN_TTL_SECS);
+        keys.retired
+            .retain(|(_, retired_at)| *retired_at + ttl > now);
+        let old = std::mem::replace(&mut keys.current, Key::generate());
+        keys.retired.push((old, now));
+        tracing::info!(kid = %keys.current.id, "rotated token signing key");
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Header {
+        alg: String,
+        typ: String,
+        kid: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Claims {
+        /// The user ID, as a string like the spec wants.
+        sub: String,
+        name: String,
+        role: Role,
+        iat: i64,
+        exp: i64,
+    }
+
+    fn encode_part(value: &impl Serialize) -> String {
+        URL_SAFE_NO_PAD
+            .encode(serde_json::to_vec(value).expect("JWT parts serialize"))
+    }
+
+    fn decode_part<T: for<'de> Deserialize<'de>>(
+        part: &str,
+    ) -> Result<T, TokenError> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(part)
+            .map_err(|_| TokenError::Malformed)?;
+        serde_json::from_slice(&bytes).map_err(|_| TokenError::Malformed)
+    }
+
+    /// Mints an EdDSA-signed JWT for `user`, valid for [`TOKEN_TTL_SECS`].
+    pub fn issue(user: &User) -> IssuedToken {
+        let keys = KEYS.read().unwrap();
+        let now = Utc::now();
+        let expires_at = now + TimeDelta::seconds(TOKEN_TTL_SECS);
+        let header = Header {
+            alg: "EdDSA".to_string(),
+            typ: "JWT".to_string(),
+            kid: keys.current.id.clone(),
+        };
+        let claims = Claims {
+            sub: user.id.to_string(),
+            name: user.name.clone(),
+            role: user.role,
+            iat: now.timestamp(),
+            exp: expires_at.timestamp(),
+        };
+        let signed =
+            format!("{}.{}", encode_part(&header), encode_part(&claims));
+        let signature = keys.current.signing.sign(signed.as_bytes());
+        IssuedToken {
+            token: format!(
+                "{signed}.{}",
+                URL_SAFE_NO_PAD.encode(signature.to_bytes())
+            ),
+            expires_at,
+        }
+    }
+
+    /// The user a token from [`issue`] was minted for, if it is signed by
+    /// one of our keys and has not expired.
+    pub fn verify(token: &str) -> Result<User, TokenError> {
+        let (signed, signature) =
+            token.rsplit_once('.').ok_or(TokenError::Malformed)?;
+        let (header, claims) =
+            signed.split_once('.').ok_or(TokenError::Malformed)?;
+        let header = decode_part::<Header>(header)?;
+        if header.alg != "EdDSA" {
+            return Err(TokenError::Malformed);
+        }
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or(TokenError::Malformed)?;
+        {
+            let keys = KEYS.read().unwrap();
+            let key = keys
+                .keys()
+                .find(|key| key.id == header.kid)
+                .ok_or(TokenError::UnknownKey)?;
+            key.signing
+                .verifying_key()
+                .verify(signed.as_bytes(), &signature)
+                .map_err(|_| TokenError::BadSignature)?;
+        }
+        let claims = decode_part::<Claims>(claims)?;
+        if claims.exp <= Utc::now().timestamp() {
+            return Err(TokenError::Expired);
+        }
+        Ok(User {
+            id: claims
+                .sub
+                .parse::<UserId>()
+                .map_err(|_| TokenError::Malformed)?,
+            name: claims.name,
+            role: claims.role,
+        })
+    }
+
+    /// The public keys tokens may be signed with, as a JWKS document.
+    pub async fn jwks() -> Json<Value> {
+        let keys = KEYS.read().unwrap();
+        Json(json!({ "keys": keys.keys().map(Key::jwk).collect::<Vec<_>>() }))
+    }
+}
+
+/// Mints a short-lived JWT for the signed-in user, for clients that can't
+/// keep a session cookie. Verify it against the keys at [`JWKS_PATH`].
+#[server]
+pub async fn create_token() -> Result<IssuedToken, ServerFnError> {
+    let user = require_user()?;
+    Ok(issue(&user))
+}
+
+/// Mints tokens on demand and shows the latest one.
+#[component]
+pub fn TokenIssuer() -> impl IntoView {
+    let create = ServerAction::<CreateToken>::new();
+    let issued = move || {
+        create.value().get().map(|result| match result {
+            Ok(issued) => {
+                let expires_at = issued.expires_at.with_timezone(&Local);
+                view! {
+                    <p>
+                        <code class="token">{issued.token}</code>
+                        " expires at "
+                        {expires_at.format("%H:%M:%S").to_string()}
+                    </p>
+                }
+                .into_any()
+            }
+            Err(e) => view! { <p>{e.to_string()}</p> }.into_any(),
+        })
+    };
+
+    view! {
+        <p>
+            {format!(
+                "Tokens work like API keys but expire after {} minutes. They are \
+                 JWTs signed with one of the keys at ",
+                TOKEN_TTL_SECS / 60,
+            )} <code>{JWKS_PATH}</code> ", so other services can verify them too."
+        </p>
+        <ActionForm action=create>
+            <input type="submit" value="Create token" />
+        </ActionForm>
+        {issued}
+    }
+}
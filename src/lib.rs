@@ -1,13 +1,127 @@
+pub mod admin;
+pub mod api_keys;
+pub mod api_version;
 pub mod app;
+pub mod archives;
+#[cfg(feature = "embed-assets")]
+pub mod assets;
+pub mod attachments;
+pub mod audit;
+pub mod auth;
+pub mod base_path;
+#[cfg(feature = "ssr")]
+pub mod blobs;
+pub mod branding;
+pub mod cache;
+#[cfg(feature = "call-log")]
+pub mod call_log;
+pub mod channels;
+pub mod client_errors;
+pub mod clients;
+pub mod codec;
+pub mod commands;
+pub mod components;
+pub mod counter;
+pub mod crawlers;
+pub mod dev_overlay;
+pub mod dev_panel;
+pub mod docs;
+pub mod error_messages;
 pub mod error_template;
 pub mod errors;
+pub mod file_rows;
+pub mod fixtures;
+pub mod flags;
+pub mod flash;
+pub mod forms;
+#[cfg(feature = "ssr")]
+pub mod fragments;
+pub mod generation;
+pub mod i18n;
+#[cfg(feature = "ssr")]
+pub mod jobs;
+pub mod jwt;
+pub mod load;
+#[cfg(feature = "ssr")]
+pub mod mail;
+#[cfg(feature = "ssr")]
+pub mod markdown;
+pub mod metrics;
 #[cfg(feature = "ssr")]
 pub mod middleware;
+#[cfg(feature = "ssr")]
+pub mod multipart;
+pub mod offline;
+pub mod passkeys;
+pub mod persist;
+pub mod prefetch;
+pub mod profile;
+pub mod progress;
+pub mod proxy;
+pub mod query;
+pub mod quotas;
+#[cfg(feature = "ssr")]
+pub mod ranges;
+pub mod reminders;
+pub mod replicas;
+pub mod resilience;
+#[cfg(feature = "ssr")]
+pub mod rest;
+#[cfg(feature = "ssr")]
+pub mod router;
+pub mod rows;
+pub mod sandbox;
+pub mod scanning;
+#[cfg(feature = "ssr")]
+pub mod security;
+#[cfg(feature = "sentry")]
+pub mod sentry;
+pub mod seo;
+#[cfg(feature = "ssr")]
+pub mod settings;
+#[cfg(feature = "ssr")]
+pub mod singleflight;
+pub mod snapshots;
+pub mod storage;
+pub mod supervisor;
+pub mod tail;
+#[cfg(feature = "ssr")]
+pub mod telemetry;
+pub mod tenants;
+pub mod theme;
+pub mod thumbnails;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod totp;
+pub mod transcription;
+pub mod transports;
+pub mod trash;
+pub mod uploads;
+pub mod url_state;
+#[cfg(feature = "ssr")]
+pub mod watcher;
 
 #[cfg(feature = "hydrate")]
 #[wasm_bindgen::prelude::wasm_bindgen]
 pub fn hydrate() {
-    use crate::app::App;
-    console_error_panic_hook::set_once();
-    leptos::mount::hydrate_body(App);
+    use crate::{
+        app::App, base_path::BasePath, branding::Branding,
+        client_errors::set_panic_hook,
+    };
+    use leptos::prelude::provide_context;
+
+    set_panic_hook();
+
+    let base_path = BasePath::from_document();
+    if !base_path.is_root() {
+        server_fn::client::set_server_url(
+            base_path.as_str().to_string().leak(),
+        );
+    }
+    let branding = Branding::from_document();
+    leptos::mount::hydrate_body(move || {
+        provide_context(base_path);
+        provide_context(branding);
+        App()
+    });
 }
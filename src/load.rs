@@ -0,0 +1,359 @@
+use crate::{
+    admin::Meter,
+    metrics::{Latency, ServerFnLatency},
+    prefetch::{cached, RouteData},
+    seo::PageMeta,
+};
+#[cfg(feature = "ssr")]
+use crate::{auth::require_admin, errors::LoadError};
+use leptos::prelude::*;
+use leptos_router::components::A;
+use serde::{Deserialize, Serialize};
+use server_fn::codec::GetUrl;
+use std::time::Duration;
+
+/// The highest rate [`generate_load`] accepts, in operations per second.
+pub const MAX_LOAD_RPS: u32 = 2_000;
+/// The longest run [`generate_load`] accepts, in seconds.
+pub const MAX_LOAD_SECS: u64 = 60;
+
+/// How often [`LoadPage`] checks on the run.
+const STATUS_REFRESH: Duration = Duration::from_secs(1);
+
+/// The current or last load run, and how the server fns are holding up.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoadStatus {
+    pub running: bool,
+    /// The rate the run was started with, in operations per second.
+    pub rps: u32,
+    /// Storage operations performed by the run so far.
+    pub operations: u64,
+    /// How long the run's storage operations took.
+    pub storage: Latency,
+    pub server_fns: Vec<ServerFnLatency>,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::LoadStatus;
+    use crate::{
+        auth::UserId,
+        errors::LoadError,
+        metrics::{LatencyWindow, METRICS},
+        storage::{RowQuery, Transaction, ROWS},
+    };
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            LazyLock, Mutex,
+        },
+        time::{Duration, Instant},
+    };
+    use tokio::{
+        task::JoinSet,
+        time::{interval, MissedTickBehavior},
+    };
+
+    /// Owns the rows load runs write. IDs start at 1, so this is nobody's
+    /// account.
+    pub const LOAD_USER: UserId = 0;
+
+    pub static LOAD: LazyLock<LoadGenerator> =
+        LazyLock::new(LoadGenerator::default);
+
+    /// Runs one load run at a time.
+    #[derive(Default)]
+    pub struct LoadGenerator {
+        run: Mutex<Run>,
+        stop: AtomicBool,
+    }
+
+    #[derive(Default)]
+    struct Run {
+        running: bool,
+        rps: u32,
+        operations: u64,
+        latencies: LatencyWindow,
+    }
+
+    impl LoadGenerator {
+        /// Performs `rps` storage operations a second for `duration` in the
+        /// background, then removes the rows they wrote.
+        pub fn start(
+            &'static self,
+            rps: u32,
+            duration: Duration,
+        ) -> Result<(), LoadError> {
+            {
+                let mut run = self.run.lock().unwrap();
+                if run.running {
+                    return Err(LoadError::AlreadyRunning);
+                }
+                *run = Run {
+                    running: true,
+                    rps,
+                    ..Run::default()
+                };
+            }
+            self.stop.store(false, Ordering::Relaxed);
+            tokio::spawn(async move {
+                let deadline = Instant::now() + duration;
+                let mut ticks = interval(Duration::from_secs(1) / rps);
+                // falling behind shows up as fewer operations rather than as
+                // a burst to catch up
+                ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                let mut operations = JoinSet::new();
+                let mut n = 0;
+                while Instant::now() < deadline
+                    && !self.stop.load(Ordering::Relaxed)
+                {
+                    ticks.tick().await;
+                    n += 1;
+                    operations.spawn(async move {
+                        let started = Instant::now();
+                        operate(n);
+                        self.record(started.elapsed());
+                    });
+                    while operations.try_join_next().is_some() {}
+                }
+                while operations.join_next().await.is_some() {}
+                clear();
+                self.run.lock().unwrap().running = false;
+            });
+            Ok(())
+        }
+
+        /// Ends the current run early, if there is one.
+        pub fn stop(&self) {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+
+        fn record(&self, elapsed: Duration) {
+            let mut run = self.run.lock().unwrap();
+            run.operations += 1;
+            run.latencies.record(elapsed);
+        }
+
+        pub fn status(&self) -> LoadStatus {
+            let run = self.run.lock().unwrap();
+            LoadStatus {
+                running: run.running,
+                rps: run.rps,
+                operations: run.operations,
+                storage: run.latencies.summary(),
+                server_fns: METRICS.latencies(),
+            }
+        }
+    }
+
+    /// Runs `f` as a transaction on the rows load runs write. It uses no
+    /// quota and audits nothing, since the audit log would only fill up.
+    fn change<T>(f: impl FnOnce(&mut Transaction<'_>) -> T) -> T {
+        let Ok(changed) =
+            ROWS.transaction(LOAD_USER, |tx| Ok::<_, Infallible>(f(tx)));
+        changed
+    }
+
+    /// The `n`th operation of a run: a mix of what the row server fns do.
+    fn operate(n: u64) {
+        match n % 4 {
+            0 => {
+                change(|tx| tx.insert(format!("load row {n}")));
+            }
+            1 => {
+                ROWS.list(LOAD_USER, &RowQuery::default(), 50);
+            }
+            2 => {
+                ROWS.search(LOAD_USER, &["load".to_string()], 0, 20);
+            }
+            _ => {
+                change(|tx| tx.set_completed(n / 4, n % 8 == 3));
+            }
+        }
+    }
+
+    /// Removes every row load runs wrote.
+    fn clear() {
+        loop {
+            let rows = ROWS.page_after(LOAD_USER, None, 500);
+            if rows.is_empty() {
+                break;
+            }
+            change(|tx| {
+                for row in rows {
+                    tx.trash(row.id);
+                    tx.purge(row.id);
+                }
+            });
+        }
+    }
+}
+
+/// Hammers the storage layer with `rps` operations a second for
+/// `duration_secs`, to see how the server fns hold up. Admins only, and only
+/// in debug builds.
+#[server]
+#[middleware(crate::middleware::TwoFactorLayer)]
+pub async fn generate_load(
+    rps: u32,
+    duration_secs: u64,
+) -> Result<(), ServerFnError> {
+    require_admin()?;
+    if !cfg!(debug_assertions) {
+        return Err(LoadError::Disabled.into());
+    }
+    if !(1..=MAX_LOAD_RPS).contains(&rps) {
+        return Err(LoadError::InvalidRate { max: MAX_LOAD_RPS }.into());
+    }
+    if !(1..=MAX_LOAD_SECS).contains(&duration_secs) {
+        return Err(LoadError::InvalidDuration { max: MAX_LOAD_SECS }.into());
+    }
+    LOAD.start(rps, Duration::from_secs(duration_secs))?;
+    Ok(())
+}
+
+#[server]
+#[middleware(crate::middleware::TwoFactorLayer)]
+pub async fn stop_load() -> Result<(), ServerFnError> {
+    require_admin()?;
+    LOAD.stop();
+    Ok(())
+}
+
+#[server(input = GetUrl)]
+#[middleware(crate::middleware::TwoFactorLayer)]
+pub async fn load_status() -> Result<LoadStatus, ServerFnError> {
+    require_admin()?;
+    Ok(LOAD.status())
+}
+
+/// Starts load runs, and shows the p50/p95 latency of storage and of every
+/// server fn while they go.
+#[component]
+pub fn LoadPage() -> impl IntoView {
+    let generate = ServerAction::<GenerateLoad>::new();
+    let stop = ServerAction::<StopLoad>::new();
+    let (tick, set_tick) = signal(0_usize);
+    let status = Resource::new(
+        move || tick.get(),
+        |_| cached(RouteData::LoadStatus.key(), load_status),
+    );
+
+    Effect::new(move |_| {
+        if let Ok(handle) = set_interval_with_handle(
+            move || set_tick.update(|tick| *tick += 1),
+            STATUS_REFRESH,
+        ) {
+            on_cleanup(move || handle.clear());
+        }
+    });
+    let error = move || {
+        generate
+            .value()
+            .get()
+            .and_then(Result::err)
+            .map(|e| view! { <p>{e.to_string()}</p> })
+    };
+
+    view! {
+        <PageMeta
+            title="Load testing"
+            description="Generated load against the server functions."
+            private=true
+        />
+        <h2>"Load testing"</h2>
+        <p>
+            <A href="/admin">"Back to the admin page"</A>
+        </p>
+        <ActionForm action=generate>
+            <label>
+                "Operations per second "
+                <input type="number" name="rps" min="1" max=MAX_LOAD_RPS value="200" />
+            </label>
+            " "
+            <label>
+                "for "
+                <input
+                    type="number"
+                    name="duration_secs"
+                    min="1"
+                    max=MAX_LOAD_SECS
+                    value="10"
+                />
+                " seconds"
+            </label>
+            " "
+            <input type="submit" value="Start" />
+        </ActionForm>
+        <ActionForm action=stop>
+            <input type="submit" value="Stop" />
+        </ActionForm>
+        {error}
+        <Transition fallback=|| view! { <p>"Loading..."</p> }>
+            {move || Suspend::new(async move {
+                match status.await {
+                    Ok(status) => view! { <LoadStatusView status /> }.into_any(),
+                    Err(e) => view! { <p>{e.to_string()}</p> }.into_any(),
+                }
+            })}
+        </Transition>
+    }
+}
+
+#[component]
+fn LoadStatusView(status: LoadStatus) -> impl IntoView {
+    let slowest = status
+        .server_fns
+        .iter()
+        .map(|server_fn| server_fn.latency.p95_ms)
+        .fold(status.storage.p95_ms, f64::max);
+    let row = move |name: String, latency: Latency| {
+        let fraction = if slowest > 0.0 {
+            latency.p95_ms / slowest
+        } else {
+            0.0
+        };
+        view! {
+            <tr>
+                <td>
+                    <code>{name}</code>
+                </td>
+                <td>{latency.samples}</td>
+                <td>{format!("{:.2} ms", latency.p50_ms)}</td>
+                <td>
+                    <Meter fraction />
+                    {format!(" {:.2} ms", latency.p95_ms)}
+                </td>
+            </tr>
+        }
+    };
+
+    view! {
+        <p>
+            {if status.running {
+                format!("Running at {} operations per second: ", status.rps)
+            } else {
+                "Not running; last run: ".to_string()
+            }}
+            {format!("{} operations", status.operations)}
+        </p>
+        <table>
+            <tr>
+                <th>"Operation"</th>
+                <th>"Calls"</th>
+                <th>"p50"</th>
+                <th>"p95"</th>
+            </tr>
+            {row("storage (generated)".to_string(), status.storage)}
+            {status
+                .server_fns
+                .into_iter()
+                .map(|server_fn| row(server_fn.path, server_fn.latency))
+                .collect::<Vec<_>>()}
+        </table>
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{
+    auth::USERS, errors::MailError, reminders::Reminder,
+    settings::MailSettings, tenants::tenant_user,
+};
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use std::sync::OnceLock;
+
+/// Sends mail as configured by `[mail]`, or only logs it in dry-run mode.
+static MAILER: OnceLock<Mailer> = OnceLock::new();
+
+struct Mailer {
+    from: Mailbox,
+    /// `None` in dry-run mode.
+    transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    alert_to: Vec<Mailbox>,
+}
+
+/// Applies the `[mail]` settings; call it once, before serving.
+pub fn init(settings: &MailSettings) -> Result<(), MailError> {
+    let transport = if settings.dry_run || settings.smtp_host.is_empty() {
+        None
+    } else {
+        let builder = if settings.starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(
+                &settings.smtp_host,
+            )
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.smtp_host)
+        }
+        .map_err(|e| MailError::Smtp(e.to_string()))?;
+        let builder = match settings.smtp_port {
+            Some(port) => builder.port(port),
+            None => builder,
+        };
+        let builder = match (&settings.smtp_username, &settings.smtp_password) {
+            (Some(username), Some(password)) => builder.credentials(
+                Credentials::new(username.clone(), password.clone()),
+            ),
+            _ => builder,
+        };
+        Some(builder.build())
+    };
+    let mailer = Mailer {
+        from: parse_address(&settings.from)?,
+        transport,
+        alert_to: settings
+            .alert_to
+            .iter()
+            .map(|address| parse_address(address))
+            .collect::<Result<_, _>>()?,
+    };
+    _ = MAILER.set(mailer);
+    Ok(())
+}
+
+fn parse_address(address: &str) -> Result<Mailbox, MailError> {
+    address.parse().map_err(|_| MailError::InvalidAddress {
+        address: address.to_string(),
+    })
+}
+
+/// Sends a plain text message, or logs it in dry-run mode.
+pub async fn send(
+    to: &str,
+    subject: &str,
+    body: String,
+) -> Result<(), MailError> {
+    deliver(parse_address(to)?, subject, body).await
+}
+
+async fn deliver(
+    to: Mailbox,
+    subject: &str,
+    body: String,
+) -> Result<(), MailError> {
+    let mailer = MAILER.get().ok_or(MailError::NotConfigured)?;
+    let message = Message::builder()
+        .from(mailer.from.clone())
+        .to(to.clone())
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body)
+        .map_err(|e| MailError::Build(e.to_string()))?;
+    match &mailer.transport {
+        Some(transport) => {
+            transport
+                .send(message)
+                .await
+                .map_err(|e| MailError::Smtp(e.to_string()))?;
+        }
+        None => {
+            let message =
+                String::from_utf8_lossy(&message.formatted()).into_owned();
+            tracing::info!(%to, subject, "dry run, not sending:\n{message}");
+        }
+    }
+    Ok(())
+}
+
+/// Emails `reminder` to its owner, if they gave an address when they
+/// signed up. Failures are only logged.
+pub async fn send_reminder(reminder: Reminder) {
+    // reminders go by owner, which is the user's in one of the tenants
+    let Some(to) = USERS.email(tenant_user(reminder.user_id)) else {
+        return;
+    };
+    let title = reminder.text.lines().next().unwrap_or_default();
+    let subject = format!("Due {}: {title}", reminder.due);
+    let body = format!(
+        "Row {} is due on {}:\n\n{}\n",
+        reminder.row_id, reminder.due, reminder.text
+    );
+    if let Err(e) = send(&to, &subject, body).await {
+        tracing::warn!(
+            user_id = reminder.user_id,
+            "couldn't send reminder: {e}"
+        );
+    }
+}
+
+/// Emails everyone in `[mail] alert_to`. Failures are only logged.
+pub async fn alert_admins(subject: &str, body: String) {
+    let Some(mailer) = MAILER.get() else {
+        return;
+    };
+    for to in &mailer.alert_to {
+        if let Err(e) = deliver(to.clone(), subject, body.clone()).await {
+            tracing::warn!(%to, "couldn't send alert: {e}");
+        }
+    }
+}
@@ -1,8 +1,6 @@
-use crate::app::*;
-use axum::Router;
 use leptos::{config::get_configuration, logging};
-use leptos_axum::{generate_route_list, LeptosRoutes};
-use server_fns_axum::*;
+use server_fns_axum::{settings::AppSettings, *};
+use std::net::SocketAddr;
 
 #[allow(clippy::needless_return)]
 #[tokio::main]
@@ -10,22 +8,175 @@ async fn main() {
     simple_logger::init_with_level(log::Level::Error)
         .expect("couldn't initialize logging");
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().is_some_and(|arg| arg == "admin") {
+        std::process::exit(admin_command(&args[1..]).await);
+    }
+
+    let settings = AppSettings::load().expect("couldn't load settings");
+    let _telemetry = telemetry::init(&settings.telemetry);
+    middleware::init_logging(&settings.telemetry);
+    middleware::init_rate_limits(&settings.security);
+    #[cfg(feature = "call-log")]
+    call_log::init(&settings.call_log).expect("couldn't open the call log");
+    auth::init(
+        &settings.auth,
+        cfg!(feature = "tls") && settings.tls.is_some(),
+    );
+    passkeys::init(&settings.auth);
+    quotas::init(&settings.quotas);
+    tenants::init(&settings.tenants).expect("invalid [tenants] settings");
+    replicas::init(&settings.replicas);
+    scanning::init(&settings.scanning);
+    tail::init(&settings.tail);
+    blobs::init(&settings.blobs).expect("invalid [blobs] settings");
+    generation::init(&settings.generation)
+        .expect("invalid [generation] settings");
+    mail::init(&settings.mail).expect("invalid [mail] settings");
+    metrics::init_alerts(settings.mail.alert_error_rate);
+    #[cfg(feature = "sentry")]
+    sentry::init(&settings.sentry).expect("invalid [sentry] settings");
+    #[cfg(not(feature = "sentry"))]
+    if settings.sentry.dsn.is_some() {
+        logging::warn!(
+            "[sentry] dsn is set but the `sentry` feature is disabled; \
+             errors will not be exported"
+        );
+    }
+    tokio::spawn(reminders::run_scheduler());
+    tokio::spawn(file_rows::run_bridge());
+    tokio::spawn(replicas::replicate());
+    jobs::spawn_periodic(
+        "purge trash",
+        trash::PURGE_INTERVAL,
+        trash::purge_expired,
+    );
+    jobs::spawn_periodic(
+        "error rate alerts",
+        metrics::ALERT_CHECK_INTERVAL,
+        metrics::check_error_rates,
+    );
+    jobs::spawn_periodic(
+        "expire sandboxes",
+        sandbox::EXPIRE_INTERVAL,
+        sandbox::expire_idle,
+    );
+    jobs::spawn_periodic(
+        "rotate token keys",
+        jwt::KEY_ROTATION_INTERVAL,
+        jwt::rotate_keys,
+    );
     let conf = get_configuration(None).unwrap();
     let leptos_options = conf.leptos_options;
     let addr = leptos_options.site_addr;
-    let routes = generate_route_list(App);
+    let base_path = settings.base_path();
+    let app = router::app_router(&settings, leptos_options);
 
-    let app = Router::new()
-        .leptos_routes(&leptos_options, routes, {
-            let leptos_options = leptos_options.clone();
-            move || shell(leptos_options.clone())
-        })
-        .fallback(leptos_axum::file_and_error_handler(shell))
-        .with_state(leptos_options);
+    #[cfg(feature = "tls")]
+    if let Some(tls) = &settings.tls {
+        logging::log!("listening on https://{}{}", &addr, base_path.as_str());
+        tls::serve(app, addr, tls).await.unwrap();
+        return;
+    }
+    #[cfg(not(feature = "tls"))]
+    if settings.tls.is_some() {
+        logging::warn!(
+            "[tls] is configured but the `tls` feature is disabled; serving \
+             plain HTTP"
+        );
+    }
 
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    logging::log!("listening on http://{}", &addr);
-    axum::serve(listener, app.into_make_service())
-        .await
-        .unwrap();
+    logging::log!("listening on http://{}{}", &addr, base_path.as_str());
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
+}
+
+const ADMIN_USAGE: &str = "\
+usage: server_fns_axum admin replay <file> [--target <url>] [--realtime]
+       server_fns_axum admin generate-static
+
+replay re-issues the server fn calls recorded by the `call-log` feature
+against a running instance (by default the configured site address). With
+--realtime, calls are sent concurrently at their recorded offsets instead of
+one after another.
+
+generate-static pre-renders the static pages (/about, /guide) to HTML files
+under the site root, for after `cargo leptos build`.";
+
+/// Runs `admin <command>`, returning the exit code.
+async fn admin_command(args: &[String]) -> i32 {
+    match args {
+        #[cfg(feature = "call-log")]
+        [command, file, options @ ..] if command == "replay" => {
+            let mut target = None;
+            let mut realtime = false;
+            let mut options = options.iter();
+            while let Some(option) = options.next() {
+                match option.as_str() {
+                    "--target" => target = options.next().cloned(),
+                    "--realtime" => realtime = true,
+                    _ => {
+                        eprintln!("{ADMIN_USAGE}");
+                        return 2;
+                    }
+                }
+            }
+            let target = target.unwrap_or_else(|| {
+                let addr =
+                    get_configuration(None).unwrap().leptos_options.site_addr;
+                format!("http://{addr}")
+            });
+            let replayed = match call_log::read(std::path::Path::new(file)) {
+                Ok(calls) => {
+                    println!(
+                        "replaying {} calls against {target} (recorded over \
+                         {:.1} s)",
+                        calls.len(),
+                        call_log::span(&calls).as_secs_f64(),
+                    );
+                    call_log::replay(calls, &target, realtime).await
+                }
+                Err(e) => Err(e),
+            };
+            match replayed {
+                Ok(0) => 0,
+                Ok(mismatches) => {
+                    eprintln!("{mismatches} calls got a different reply");
+                    1
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    1
+                }
+            }
+        }
+        [command] if command == "generate-static" => {
+            let settings = match AppSettings::load() {
+                Ok(settings) => settings,
+                Err(e) => {
+                    eprintln!("couldn't load settings: {e}");
+                    return 1;
+                }
+            };
+            let leptos_options =
+                get_configuration(None).unwrap().leptos_options;
+            router::generate_static_pages(&settings, &leptos_options).await;
+            println!("static pages written to {}", leptos_options.site_root);
+            0
+        }
+        #[cfg(not(feature = "call-log"))]
+        [command, ..] if command == "replay" => {
+            eprintln!("replaying needs a build with `--features call-log`");
+            2
+        }
+        _ => {
+            eprintln!("{ADMIN_USAGE}");
+            2
+        }
+    }
 }
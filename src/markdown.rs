@@ -0,0 +1,13 @@
+use pulldown_cmark::{html, Options, Parser};
+
+/// Renders Markdown to HTML that is safe to insert into the page.
+///
+/// Markdown allows raw HTML, so the output of the renderer is only as
+/// trustworthy as its input; everything goes through ammonia's allowlist,
+/// which drops scripts, event handlers, `javascript:` URLs and the like.
+pub fn render(text: &str) -> String {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES;
+    let mut unsanitized = String::new();
+    html::push_html(&mut unsanitized, Parser::new_ext(text, options));
+    ammonia::clean(&unsanitized)
+}
@@ -0,0 +1,326 @@
+use serde::{Deserialize, Serialize};
+
+/// How often one server fn has been called, and how often it failed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerFnUsage {
+    pub path: String,
+    pub calls: u64,
+    /// Calls answered with a 4xx or 5xx status.
+    pub errors: u64,
+    /// Calls slower than their `LoggingLayer`'s threshold; only counted for
+    /// server fns that have one.
+    pub slow: u64,
+}
+
+impl ServerFnUsage {
+    /// The share of calls that failed, from 0 to 1.
+    pub fn error_rate(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.calls as f64
+        }
+    }
+}
+
+/// How long recent calls took, in milliseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Latency {
+    /// How many calls the percentiles are over.
+    pub samples: usize,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+}
+
+/// The latency of one server fn.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerFnLatency {
+    pub path: String,
+    pub latency: Latency,
+}
+
+/// Files received by the upload server fns.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct UploadVolume {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{Latency, ServerFnLatency, ServerFnUsage, UploadVolume};
+    use crate::{mail, middleware::is_server_fn_path};
+    use dashmap::DashMap;
+    use http::{Request, Response, StatusCode};
+    use pin_project_lite::pin_project;
+    use std::{
+        collections::{HashMap, VecDeque},
+        future::Future,
+        pin::Pin,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            LazyLock, Mutex, OnceLock,
+        },
+        task::{ready, Context, Poll},
+        time::{Duration, Instant},
+    };
+    use tower::{Layer, Service};
+
+    /// Counters since the server started.
+    pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+    #[derive(Debug, Default)]
+    pub struct Metrics {
+        /// Server fn path -> `(calls, errors)`.
+        calls: DashMap<String, (u64, u64)>,
+        latencies: DashMap<String, LatencyWindow>,
+        slow_calls: DashMap<String, u64>,
+        upload_files: AtomicU64,
+        upload_bytes: AtomicU64,
+    }
+
+    impl Metrics {
+        pub fn record_call(&self, path: &str, status: StatusCode) {
+            let mut counts = self.calls.entry(path.to_string()).or_default();
+            counts.0 += 1;
+            if status.is_client_error() || status.is_server_error() {
+                counts.1 += 1;
+            }
+        }
+
+        pub fn record_latency(&self, path: &str, elapsed: Duration) {
+            self.latencies
+                .entry(path.to_string())
+                .or_default()
+                .record(elapsed);
+        }
+
+        pub fn record_slow_call(&self, path: &str) {
+            *self.slow_calls.entry(path.to_string()).or_default() += 1;
+        }
+
+        pub fn record_upload(&self, bytes: u64) {
+            self.upload_files.fetch_add(1, Ordering::Relaxed);
+            self.upload_bytes.fetch_add(bytes, Ordering::Relaxed);
+        }
+
+        /// Every server fn that has been called, by path.
+        pub fn server_fns(&self) -> Vec<ServerFnUsage> {
+            let mut usage = self
+                .calls
+                .iter()
+                .map(|entry| ServerFnUsage {
+                    path: entry.key().clone(),
+                    calls: entry.0,
+                    errors: entry.1,
+                    slow: self
+                        .slow_calls
+                        .get(entry.key())
+                        .map_or(0, |slow| *slow),
+                })
+                .collect::<Vec<_>>();
+            usage.sort_by(|a, b| a.path.cmp(&b.path));
+            usage
+        }
+
+        /// The latency of every server fn that has been called, by path.
+        pub fn latencies(&self) -> Vec<ServerFnLatency> {
+            let mut latencies = self
+                .latencies
+                .iter()
+                .map(|entry| ServerFnLatency {
+                    path: entry.key().clone(),
+                    latency: entry.summary(),
+                })
+                .collect::<Vec<_>>();
+            latencies.sort_by(|a, b| a.path.cmp(&b.path));
+            latencies
+        }
+
+        pub fn uploads(&self) -> UploadVolume {
+            UploadVolume {
+                files: self.upload_files.load(Ordering::Relaxed),
+                bytes: self.upload_bytes.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// How many of the most recent calls [`LatencyWindow`] keeps.
+    const LATENCY_WINDOW: usize = 1_000;
+
+    /// The durations of the most recent calls, so percentiles follow what is
+    /// happening now rather than averaging over the server's lifetime.
+    #[derive(Debug, Default)]
+    pub struct LatencyWindow(VecDeque<Duration>);
+
+    impl LatencyWindow {
+        pub fn record(&mut self, elapsed: Duration) {
+            if self.0.len() == LATENCY_WINDOW {
+                self.0.pop_front();
+            }
+            self.0.push_back(elapsed);
+        }
+
+        pub fn summary(&self) -> Latency {
+            if self.0.is_empty() {
+                return Latency::default();
+            }
+            let mut sorted = self.0.iter().copied().collect::<Vec<_>>();
+            sorted.sort_unstable();
+            let percentile = |p: usize| {
+                let i = (sorted.len() * p / 100).min(sorted.len() - 1);
+                sorted[i].as_secs_f64() * 1000.0
+            };
+            Latency {
+                samples: sorted.len(),
+                p50_ms: percentile(50),
+                p95_ms: percentile(95),
+            }
+        }
+    }
+
+    /// How often [`check_error_rates`] runs.
+    pub const ALERT_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+    /// A server fn called fewer times than this between two checks never
+    /// raises an alert, so one failed call can't.
+    const ALERT_MIN_CALLS: u64 = 20;
+
+    /// From `[mail] alert_error_rate`.
+    static ALERT_ERROR_RATE: OnceLock<f64> = OnceLock::new();
+
+    /// Server fn path -> `(calls, errors)` at the last check.
+    static LAST_CHECK: LazyLock<Mutex<HashMap<String, (u64, u64)>>> =
+        LazyLock::new(Mutex::default);
+
+    /// Sets the error rate that [`check_error_rates`] alerts at; call it
+    /// once, before serving.
+    pub fn init_alerts(error_rate: f64) {
+        _ = ALERT_ERROR_RATE.set(error_rate);
+    }
+
+    /// Emails the admins about every server fn whose error rate since the
+    /// last check is over the threshold.
+    pub async fn check_error_rates() {
+        let Some(&threshold) = ALERT_ERROR_RATE.get() else {
+            return;
+        };
+        let spikes = {
+            let mut last = LAST_CHECK.lock().unwrap();
+            METRICS
+                .server_fns()
+                .into_iter()
+                .filter_map(|usage| {
+                    let (calls, errors) = last
+                        .insert(usage.path.clone(), (usage.calls, usage.errors))
+                        .unwrap_or_default();
+                    let since = ServerFnUsage {
+                        calls: usage.calls - calls,
+                        errors: usage.errors - errors,
+                        ..usage
+                    };
+                    (since.calls >= ALERT_MIN_CALLS
+                        && since.error_rate() > threshold)
+                        .then_some(since)
+                })
+                .collect::<Vec<_>>()
+        };
+        if spikes.is_empty() {
+            return;
+        }
+        let body = spikes
+            .iter()
+            .map(|usage| {
+                format!(
+                    "{}: {} of {} calls failed ({:.1}%)\n",
+                    usage.path,
+                    usage.errors,
+                    usage.calls,
+                    usage.error_rate() * 100.0
+                )
+            })
+            .collect::<String>();
+        tracing::warn!("server fn error rates spiked:\n{body}");
+        mail::alert_admins("Server fn error rates spiked", body).await;
+    }
+
+    /// Counts the calls to, errors from, and time taken by every server fn in
+    /// [`METRICS`].
+    #[derive(Clone, Copy, Default)]
+    pub struct MetricsLayer;
+
+    impl<S> Layer<S> for MetricsLayer {
+        type Service = MetricsService<S>;
+
+        fn layer(&self, inner: S) -> Self::Service {
+            MetricsService { inner }
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct MetricsService<T> {
+        inner: T,
+    }
+
+    impl<T, ReqBody, ResBody> Service<Request<ReqBody>> for MetricsService<T>
+    where
+        T: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    {
+        type Response = T::Response;
+        type Error = T::Error;
+        type Future = MetricsFuture<T::Future>;
+
+        fn poll_ready(
+            &mut self,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+            let path = req.uri().path();
+            MetricsFuture {
+                path: is_server_fn_path(path).then(|| path.to_string()),
+                started: Instant::now(),
+                inner: self.inner.call(req),
+            }
+        }
+    }
+
+    pin_project! {
+        pub struct MetricsFuture<T> {
+            path: Option<String>,
+            started: Instant,
+            #[pin]
+            inner: T,
+        }
+    }
+
+    impl<T, ResBody, E> Future for MetricsFuture<T>
+    where
+        T: Future<Output = Result<Response<ResBody>, E>>,
+    {
+        type Output = T::Output;
+
+        fn poll(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Self::Output> {
+            let this = self.project();
+            let res = ready!(this.inner.poll(cx))?;
+            // paths that aren't server fns 404, and aren't counted so that
+            // probing for them can't grow the table
+            if let Some(path) = this.path.take() {
+                if res.status() != StatusCode::NOT_FOUND {
+                    METRICS.record_call(&path, res.status());
+                    METRICS.record_latency(&path, this.started.elapsed());
+                }
+            }
+            Poll::Ready(Ok(res))
+        }
+    }
+}
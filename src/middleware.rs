@@ -1,30 +1,143 @@
-use axum::body::Body;
-use http::Request;
+use crate::{
+    api_version::ApiVersion,
+    attachments::MAX_ATTACHMENT_SIZE,
+    auth::{admin_without_two_factor, session_token},
+    codec::{Framed, RkyvChunks},
+    error_template::render_panic_page,
+    errors::{set_error_status, MiddlewareError, REQUEST_ID_HEADER},
+    metrics::METRICS,
+    rest::REST_PATH,
+    settings::{CorsSettings, SecuritySettings, TelemetrySettings},
+    tenants::Tenant,
+};
+use axum::{body::Body, extract::ConnectInfo};
+use chrono::NaiveTime;
+use futures::{
+    future::{self, Either, Ready},
+    StreamExt,
+};
+use http::{
+    header::{
+        CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, LINK, REFERRER_POLICY,
+        X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+    },
+    Extensions, HeaderMap, HeaderName, HeaderValue, Method, Request, Response,
+    StatusCode, Version,
+};
 use pin_project_lite::pin_project;
+use server_fn::{
+    codec::{Streaming, StreamingText},
+    ContentType,
+};
 use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::Display,
     future::Future,
+    net::{IpAddr, SocketAddr},
     pin::Pin,
-    task::{Context, Poll},
+    sync::{Arc, Mutex, OnceLock},
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+use tower::{layer::util::Stack, Layer, Service};
+use tower_http::{
+    catch_panic::CatchPanicLayer,
+    compression::{
+        predicate::{NotForContentType, Predicate},
+        CompressionLayer, DefaultPredicate,
+    },
+    cors::{AllowHeaders, AllowOrigin, CorsLayer},
 };
-use tower::{Layer, Service};
+use tracing::instrument::{Instrument, Instrumented};
+
+/// From `[telemetry]`: what [`LoggingLayer::default`] is configured with.
+static LOGGING: OnceLock<LoggingLayer> = OnceLock::new();
 
-pub struct LoggingLayer;
+/// Sets the slow-call threshold and body sample size that
+/// [`LoggingLayer::default`] uses; call it once, before serving.
+pub fn init_logging(settings: &TelemetrySettings) {
+    _ = LOGGING.set(LoggingLayer {
+        slow_after: Duration::from_millis(settings.slow_call_ms),
+        body_sample: settings.body_sample_bytes,
+    });
+}
+
+/// From `[security]`: the reverse proxies whose `X-Forwarded-For`
+/// [`RateLimitLayer`] believes.
+static TRUSTED_PROXIES: OnceLock<Vec<IpAddr>> = OnceLock::new();
+
+/// Sets which proxies [`RateLimitLayer`] takes a caller's address from;
+/// call it once, before serving.
+pub fn init_rate_limits(settings: &SecuritySettings) {
+    _ = TRUSTED_PROXIES.set(settings.trusted_proxies.clone());
+}
+
+/// Logs each call to the server fn it's applied to: its duration and
+/// response status, and optionally the start of its request body. Calls
+/// slower than the threshold are logged as warnings and counted in
+/// [`METRICS`].
+#[derive(Debug, Clone, Copy)]
+pub struct LoggingLayer {
+    slow_after: Duration,
+    /// How many bytes of the request body to log; 0 logs none.
+    body_sample: usize,
+}
+
+impl Default for LoggingLayer {
+    fn default() -> Self {
+        LOGGING.get().copied().unwrap_or(LoggingLayer {
+            slow_after: Duration::from_secs(1),
+            body_sample: 0,
+        })
+    }
+}
+
+impl LoggingLayer {
+    pub fn slow_after(self, slow_after: Duration) -> Self {
+        Self { slow_after, ..self }
+    }
+
+    pub fn sample_body(self, bytes: usize) -> Self {
+        Self {
+            body_sample: bytes,
+            ..self
+        }
+    }
+}
 
 impl<S> Layer<S> for LoggingLayer {
     type Service = LoggingService<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        LoggingService { inner }
+        LoggingService {
+            inner,
+            config: *self,
+        }
     }
 }
 
 pub struct LoggingService<T> {
     inner: T,
+    config: LoggingLayer,
 }
 
-impl<T> Service<Request<Body>> for LoggingService<T>
+/// Passes `body` through, copying its first `max` bytes into `sample` as
+/// they're read.
+fn tap_body(body: Body, max: usize, sample: Arc<Mutex<Vec<u8>>>) -> Body {
+    Body::from_stream(body.into_data_stream().inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            let mut sample = sample.lock().unwrap();
+            let room = max.saturating_sub(sample.len()).min(chunk.len());
+            sample.extend_from_slice(&chunk[..room]);
+        }
+    }))
+}
+
+impl<T, B> Service<Request<Body>> for LoggingService<T>
 where
-    T: Service<Request<Body>>,
+    T: Service<Request<Body>, Response = Response<B>>,
+    T::Error: Display,
 {
     type Response = T::Response;
     type Error = T::Error;
@@ -38,10 +151,29 @@ where
     }
 
     fn call(&mut self, req: Request<Body>) -> Self::Future {
-        println!("1. Running my middleware!");
+        let path = req.uri().path().to_string();
+        let max = self.config.body_sample;
+        let sample = (max > 0).then(Arc::<Mutex<Vec<u8>>>::default);
+        let req = match &sample {
+            Some(sample) => {
+                let sample = Arc::clone(sample);
+                req.map(|body| tap_body(body, max, sample))
+            }
+            None => req,
+        };
+
+        // only kept for the calls' breadcrumbs
+        let session = cfg!(feature = "sentry")
+            .then(|| session_token(req.headers()))
+            .flatten();
 
         LoggingServiceFuture {
             inner: self.inner.call(req),
+            path,
+            started: Instant::now(),
+            slow_after: self.config.slow_after,
+            sample,
+            session,
         }
     }
 }
@@ -50,23 +182,873 @@ pin_project! {
     pub struct LoggingServiceFuture<T> {
         #[pin]
         inner: T,
+        path: String,
+        started: Instant,
+        slow_after: Duration,
+        sample: Option<Arc<Mutex<Vec<u8>>>>,
+        session: Option<String>,
+    }
+}
+
+impl<T, B, E> Future for LoggingServiceFuture<T>
+where
+    T: Future<Output = Result<Response<B>, E>>,
+    E: Display,
+{
+    type Output = T::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let output = ready!(this.inner.poll(cx));
+        let elapsed = this.started.elapsed();
+        let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+        // a middleware error is sent as a 500
+        let (status, error) = match &output {
+            Ok(res) => (res.status(), None),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Some(e.to_string())),
+        };
+        let status = status.as_u16();
+        let body = this.sample.as_ref().map(|sample| {
+            String::from_utf8_lossy(&sample.lock().unwrap()).into_owned()
+        });
+        let path = this.path.as_str();
+        let slow = elapsed >= *this.slow_after;
+        #[cfg(feature = "sentry")]
+        if let Some(session) = this.session.as_deref() {
+            crate::sentry::add_breadcrumb(
+                session,
+                crate::sentry::Breadcrumb::server_fn_call(
+                    path, status, elapsed_ms, slow,
+                ),
+            );
+        }
+        if slow {
+            METRICS.record_slow_call(path);
+            tracing::warn!(
+                path,
+                status,
+                elapsed_ms,
+                body,
+                error,
+                "slow server fn call"
+            );
+        } else {
+            tracing::info!(
+                path,
+                status,
+                elapsed_ms,
+                body,
+                error,
+                "server fn call"
+            );
+        }
+        Poll::Ready(output)
+    }
+}
+
+/// What [`RateLimitLayer`] counts, for each caller.
+struct Window {
+    started: Instant,
+    calls: u32,
+}
+
+/// Lets each caller (by session, or else by the address it called from, and
+/// by tenant) make at most `limit` calls per `period` to the server fn it's applied
+/// to, and turns the rest away with [`MiddlewareError::RateLimited`].
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    limit: u32,
+    period: Duration,
+    windows: Arc<Mutex<HashMap<String, Window>>>,
+}
+
+impl RateLimitLayer {
+    pub fn new(limit: u32, period: Duration) -> Self {
+        Self {
+            limit,
+            period,
+            windows: Arc::default(),
+        }
+    }
+
+    pub fn per_minute(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60))
+    }
+
+    /// Counts a call by `caller`: how long until it may call again, if it
+    /// has to wait.
+    fn wait(&self, caller: String) -> Option<Duration> {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        // forget callers whose windows are over, now and then
+        if windows.len() >= 10_000 {
+            windows.retain(|_, window| now - window.started < self.period);
+        }
+        let window = windows.entry(caller).or_insert(Window {
+            started: now,
+            calls: 0,
+        });
+        if now - window.started >= self.period {
+            *window = Window {
+                started: now,
+                calls: 0,
+            };
+        }
+        window.calls += 1;
+        (window.calls > self.limit)
+            .then(|| self.period.saturating_sub(now - window.started))
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            limits: self.clone(),
+        }
+    }
+}
+
+pub struct RateLimitService<T> {
+    inner: T,
+    limits: RateLimitLayer,
+}
+
+fn caller(req: &Request<Body>) -> String {
+    let caller = session_token(req.headers())
+        .or_else(|| client_addr(req).map(|addr| addr.to_string()))
+        .unwrap_or_default();
+    let tenant = req.extensions().get::<Tenant>();
+    match tenant.and_then(|tenant| tenant.name.as_deref()) {
+        Some(tenant) => format!("{tenant}/{caller}"),
+        None => caller,
+    }
+}
+
+/// The address a call came from: the connection's peer, unless that is one
+/// of `[security] trusted_proxies`. Then it's the last address in
+/// `X-Forwarded-For` that none of them added, since a client can put
+/// anything before those. `None` if the server wasn't started with
+/// [`ConnectInfo`].
+fn client_addr(req: &Request<Body>) -> Option<IpAddr> {
+    let ConnectInfo(peer) =
+        req.extensions().get::<ConnectInfo<SocketAddr>>()?;
+    let trusted = TRUSTED_PROXIES.get().map_or(&[][..], Vec::as_slice);
+    if !trusted.contains(&peer.ip()) {
+        return Some(peer.ip());
+    }
+    let forwarded = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|addr| addr.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>();
+    let client = forwarded.into_iter().rev().find(|a| !trusted.contains(a));
+    Some(client.unwrap_or(peer.ip()))
+}
+
+impl<T> Service<Request<Body>> for RateLimitService<T>
+where
+    T: Service<Request<Body>>,
+    T::Error: From<MiddlewareError>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = Either<Ready<Result<T::Response, T::Error>>, T::Future>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        match self.limits.wait(caller(&req)) {
+            Some(wait) => {
+                let error = MiddlewareError::RateLimited {
+                    retry_after_secs: wait.as_secs().max(1),
+                };
+                set_error_status(&error);
+                Either::Left(future::ready(Err(error.into())))
+            }
+            None => Either::Right(self.inner.call(req)),
+        }
+    }
+}
+
+/// Turns away admins whose session didn't use a second factor to sign in,
+/// with [`MiddlewareError::TwoFactorRequired`]. For the admin server fns
+/// that can do the most harm, like restoring a tenant's rows or running
+/// tasks. Anyone else goes through, for the server fn to turn away as
+/// usual.
+#[derive(Clone, Copy, Default)]
+pub struct TwoFactorLayer;
+
+impl<S> Layer<S> for TwoFactorLayer {
+    type Service = TwoFactorService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TwoFactorService { inner }
+    }
+}
+
+pub struct TwoFactorService<T> {
+    inner: T,
+}
+
+impl<T> Service<Request<Body>> for TwoFactorService<T>
+where
+    T: Service<Request<Body>>,
+    T::Error: From<MiddlewareError>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = Either<Ready<Result<T::Response, T::Error>>, T::Future>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if admin_without_two_factor(req.headers()) {
+            let error = MiddlewareError::TwoFactorRequired;
+            set_error_status(&error);
+            Either::Left(future::ready(Err(error.into())))
+        } else {
+            Either::Right(self.inner.call(req))
+        }
+    }
+}
+
+/// Turns away request bodies over `limit` bytes with
+/// [`MiddlewareError::BodyTooLarge`]: up front if they say how long they
+/// are, and otherwise as soon as they go over while being read.
+#[derive(Clone, Copy)]
+pub struct BodyLimitLayer {
+    limit: usize,
+}
+
+impl BodyLimitLayer {
+    pub fn new(limit: usize) -> Self {
+        Self { limit }
+    }
+}
+
+impl<S> Layer<S> for BodyLimitLayer {
+    type Service = BodyLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BodyLimitService {
+            inner,
+            limit: self.limit,
+        }
+    }
+}
+
+pub struct BodyLimitService<T> {
+    inner: T,
+    limit: usize,
+}
+
+impl<T> Service<Request<Body>> for BodyLimitService<T>
+where
+    T: Service<Request<Body>>,
+    T::Error: From<MiddlewareError>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = Either<Ready<Result<T::Response, T::Error>>, T::Future>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let limit = self.limit;
+        let too_large = || MiddlewareError::BodyTooLarge { limit };
+        let declared = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok()?.parse::<usize>().ok());
+        if declared.is_some_and(|len| len > limit) {
+            set_error_status(&too_large());
+            return Either::Left(future::ready(Err(too_large().into())));
+        }
+        let req = req.map(|body| {
+            let mut read = 0;
+            Body::from_stream(body.into_data_stream().map(move |chunk| {
+                let chunk = chunk?;
+                read += chunk.len();
+                if read > limit {
+                    // read by the server fn, while the response can still
+                    // be given a status
+                    set_error_status(&too_large());
+                    Err(axum::Error::new(too_large()))
+                } else {
+                    Ok(chunk)
+                }
+            }))
+        });
+        Either::Right(self.inner.call(req))
+    }
+}
+
+/// Runs the rest of the stack inside a `middleware` span named after the
+/// preset, so traces show which policy a call went through.
+#[derive(Clone, Copy)]
+pub struct PresetSpanLayer {
+    preset: &'static str,
+}
+
+impl<S> Layer<S> for PresetSpanLayer {
+    type Service = PresetSpanService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PresetSpanService {
+            inner,
+            preset: self.preset,
+        }
+    }
+}
+
+pub struct PresetSpanService<T> {
+    inner: T,
+    preset: &'static str,
+}
+
+impl<T> Service<Request<Body>> for PresetSpanService<T>
+where
+    T: Service<Request<Body>>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = Instrumented<T::Future>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let span = tracing::info_span!("middleware", preset = self.preset);
+        let _entered = span.enter();
+        self.inner.call(req).in_current_span()
+    }
+}
+
+/// A named middleware preset: a [`PresetSpanLayer`], then [`LoggingLayer`],
+/// [`RateLimitLayer`] and [`BodyLimitLayer`], outermost first.
+pub type Preset = Stack<
+    BodyLimitLayer,
+    Stack<RateLimitLayer, Stack<LoggingLayer, PresetSpanLayer>>,
+>;
+
+fn preset(
+    name: &'static str,
+    calls_per_minute: u32,
+    max_body_bytes: usize,
+) -> Preset {
+    Stack::new(
+        BodyLimitLayer::new(max_body_bytes),
+        Stack::new(
+            RateLimitLayer::per_minute(calls_per_minute),
+            Stack::new(
+                LoggingLayer::default(),
+                PresetSpanLayer { preset: name },
+            ),
+        ),
+    )
+}
+
+/// The policy for ordinary server fns: logged and traced, 120 calls a
+/// minute per caller, and bodies up to 1 MiB. Apply it with
+/// `#[middleware(crate::middleware::standard())]`.
+pub fn standard() -> Preset {
+    preset("standard", 120, 1024 * 1024)
+}
+
+/// Like [`standard`], but for server fns that take files: bodies up to
+/// [`MAX_ATTACHMENT_SIZE`] plus room for the rest of the form, and 20 calls
+/// a minute.
+pub fn uploads() -> Preset {
+    preset("uploads", 20, MAX_ATTACHMENT_SIZE as usize + 64 * 1024)
+}
+
+/// Path prefixes under which server functions are registered.
+pub const SERVER_FN_PREFIXES: [&str; 2] = ["/api/", "/api2/"];
+
+pub fn is_server_fn_path(path: &str) -> bool {
+    SERVER_FN_PREFIXES
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+}
+
+/// Sets `Cache-Control` according to what is being served:
+///
+/// - `/pkg/*` files are fingerprinted by cargo-leptos (`hash-files`), so they
+///   are cached forever and marked `immutable`.
+/// - server function and REST API responses are never stored, unless the
+///   handler set its own `Cache-Control`.
+/// - everything else (mostly HTML) must be revalidated on every use.
+#[derive(Clone, Copy, Default)]
+pub struct CacheControlLayer;
+
+impl<S> Layer<S> for CacheControlLayer {
+    type Service = CacheControlService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CacheControlService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct CacheControlService<T> {
+    inner: T,
+}
+
+#[derive(Clone, Copy)]
+enum CachePolicy {
+    Immutable,
+    NoStore,
+    Revalidate,
+}
+
+impl CachePolicy {
+    fn for_path(path: &str) -> Self {
+        if path.starts_with("/pkg/") {
+            CachePolicy::Immutable
+        } else if is_server_fn_path(path)
+            || path
+                .strip_prefix(REST_PATH)
+                .is_some_and(|rest| rest.starts_with('/'))
+        {
+            CachePolicy::NoStore
+        } else {
+            CachePolicy::Revalidate
+        }
+    }
+
+    fn header_value(self) -> HeaderValue {
+        HeaderValue::from_static(match self {
+            CachePolicy::Immutable => "public, max-age=31536000, immutable",
+            CachePolicy::NoStore => "no-store",
+            CachePolicy::Revalidate => "no-cache",
+        })
+    }
+}
+
+impl<T, ReqBody, ResBody> Service<Request<ReqBody>> for CacheControlService<T>
+where
+    T: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = CacheControlFuture<T::Future>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        CacheControlFuture {
+            policy: CachePolicy::for_path(req.uri().path()),
+            inner: self.inner.call(req),
+        }
+    }
+}
+
+pin_project! {
+    pub struct CacheControlFuture<T> {
+        policy: CachePolicy,
+        #[pin]
+        inner: T,
+    }
+}
+
+impl<T, ResBody, E> Future for CacheControlFuture<T>
+where
+    T: Future<Output = Result<Response<ResBody>, E>>,
+{
+    type Output = T::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut res = ready!(this.inner.poll(cx))?;
+        let policy = *this.policy;
+        let headers = res.headers_mut();
+        // immutable assets always win over the file handler's own headers,
+        // but server fns and pages may opt into caching themselves
+        if matches!(policy, CachePolicy::Immutable)
+            || !headers.contains_key(CACHE_CONTROL)
+        {
+            headers.insert(CACHE_CONTROL, policy.header_value());
+        }
+        Poll::Ready(Ok(res))
+    }
+}
+
+/// Marks responses from deprecated [`ApiVersion`]s with `Deprecation`
+/// (RFC 9745) and `Sunset` (RFC 8594) headers, and a `Link` to the same
+/// endpoint in the current version, so clients notice before the old
+/// version goes away.
+#[derive(Clone, Copy, Default)]
+pub struct DeprecationLayer;
+
+impl<S> Layer<S> for DeprecationLayer {
+    type Service = DeprecationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DeprecationService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct DeprecationService<T> {
+    inner: T,
+}
+
+/// The headers for a call to `path`; none unless it's in a deprecated
+/// version.
+fn deprecation_headers(path: &str) -> Vec<(HeaderName, HeaderValue)> {
+    let Some((version, endpoint)) = ApiVersion::split_path(path) else {
+        return Vec::new();
+    };
+    let Some(retirement) = version.retirement() else {
+        return Vec::new();
+    };
+    let deprecated = retirement.deprecated.and_time(NaiveTime::MIN).and_utc();
+    let sunset = retirement.sunset.and_time(NaiveTime::MIN).and_utc();
+    // relative, so it stays right behind a base path
+    let successor = format!(
+        "<../{}/{endpoint}>; rel=\"successor-version\"",
+        ApiVersion::CURRENT
+    );
+    [
+        (
+            HeaderName::from_static("deprecation"),
+            format!("@{}", deprecated.timestamp()),
+        ),
+        (
+            HeaderName::from_static("sunset"),
+            sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+        ),
+        (LINK, successor),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| {
+        Some((name, HeaderValue::from_str(&value).ok()?))
+    })
+    .collect()
+}
+
+impl<T, ReqBody, ResBody> Service<Request<ReqBody>> for DeprecationService<T>
+where
+    T: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = DeprecationFuture<T::Future>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        DeprecationFuture {
+            headers: deprecation_headers(req.uri().path()),
+            inner: self.inner.call(req),
+        }
+    }
+}
+
+pin_project! {
+    pub struct DeprecationFuture<T> {
+        headers: Vec<(HeaderName, HeaderValue)>,
+        #[pin]
+        inner: T,
+    }
+}
+
+impl<T, ResBody, E> Future for DeprecationFuture<T>
+where
+    T: Future<Output = Result<Response<ResBody>, E>>,
+{
+    type Output = T::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut res = ready!(this.inner.poll(cx))?;
+        res.headers_mut().extend(std::mem::take(this.headers));
+        Poll::Ready(Ok(res))
+    }
+}
+
+/// Brotli/gzip compression for pages and buffered server fn responses.
+///
+/// Streaming server fns (`StreamingText`, `Streaming`, `Framed` and
+/// `RkyvChunks` outputs), SSE and websocket upgrades are excluded so each
+/// progress chunk reaches the client as soon as it is written instead of
+/// waiting in the compressor's buffer. So are row exports, which are
+/// `Streaming` outputs too but say they are CSV or JSON lines.
+pub fn compression_layer() -> CompressionLayer<impl Predicate> {
+    let not_upgrade =
+        |status: StatusCode, _: Version, _: &HeaderMap, _: &Extensions| {
+            status != StatusCode::SWITCHING_PROTOCOLS
+        };
+
+    CompressionLayer::new().br(true).gzip(true).compress_when(
+        DefaultPredicate::new()
+            .and(NotForContentType::const_new(StreamingText::CONTENT_TYPE))
+            .and(NotForContentType::const_new(Streaming::CONTENT_TYPE))
+            .and(NotForContentType::const_new(Framed::CONTENT_TYPE))
+            .and(NotForContentType::const_new(RkyvChunks::CONTENT_TYPE))
+            .and(NotForContentType::const_new("text/csv"))
+            .and(NotForContentType::const_new("application/x-ndjson"))
+            .and(not_upgrade),
+    )
+}
+
+/// Adds hardening headers to every response that doesn't already set them.
+///
+/// The Content-Security-Policy is not part of this layer: it needs the
+/// per-request nonce and is added while rendering the shell (see
+/// [`crate::security::set_content_security_policy`]).
+#[derive(Clone)]
+pub struct SecurityHeadersLayer {
+    headers: Arc<HeaderMap>,
+}
+
+impl SecurityHeadersLayer {
+    pub fn new(settings: &SecuritySettings) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        );
+        headers.insert(
+            REFERRER_POLICY,
+            HeaderValue::from_str(&settings.referrer_policy)
+                .expect("invalid referrer_policy setting"),
+        );
+        // legacy fallback for browsers that ignore `frame-ancestors`
+        if settings.frame_ancestors.is_empty() {
+            headers.insert(X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+        }
+        Self {
+            headers: Arc::new(headers),
+        }
+    }
+}
+
+impl<S> Layer<S> for SecurityHeadersLayer {
+    type Service = SecurityHeadersService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SecurityHeadersService {
+            inner,
+            headers: Arc::clone(&self.headers),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SecurityHeadersService<T> {
+    inner: T,
+    headers: Arc<HeaderMap>,
+}
+
+impl<T, ReqBody, ResBody> Service<Request<ReqBody>>
+    for SecurityHeadersService<T>
+where
+    T: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = SecurityHeadersFuture<T::Future>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        SecurityHeadersFuture {
+            headers: Arc::clone(&self.headers),
+            inner: self.inner.call(req),
+        }
+    }
+}
+
+pin_project! {
+    pub struct SecurityHeadersFuture<T> {
+        headers: Arc<HeaderMap>,
+        #[pin]
+        inner: T,
     }
 }
 
-impl<T> Future for LoggingServiceFuture<T>
+impl<T, ResBody, E> Future for SecurityHeadersFuture<T>
 where
-    T: Future,
+    T: Future<Output = Result<Response<ResBody>, E>>,
 {
     type Output = T::Output;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.project();
-        match this.inner.poll(cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(output) => {
-                println!("3. Running my middleware!");
-                Poll::Ready(output)
+        let mut res = ready!(this.inner.poll(cx))?;
+        let headers = res.headers_mut();
+        for (name, value) in this.headers.iter() {
+            if !headers.contains_key(name) {
+                headers.insert(name.clone(), value.clone());
             }
         }
+        Poll::Ready(Ok(res))
+    }
+}
+
+/// Applies the wrapped layer only to server function requests, leaving pages
+/// and static files untouched.
+#[derive(Clone)]
+pub struct ServerFnLayer<L> {
+    layer: L,
+}
+
+impl<L> ServerFnLayer<L> {
+    pub fn new(layer: L) -> Self {
+        Self { layer }
+    }
+}
+
+impl<S, L> Layer<S> for ServerFnLayer<L>
+where
+    S: Clone,
+    L: Layer<S>,
+{
+    type Service = ServerFnService<S, L::Service>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ServerFnService {
+            wrapped: self.layer.layer(inner.clone()),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ServerFnService<S, W> {
+    inner: S,
+    wrapped: W,
+}
+
+impl<S, W, ReqBody> Service<Request<ReqBody>> for ServerFnService<S, W>
+where
+    S: Service<Request<ReqBody>>,
+    W: Service<Request<ReqBody>, Response = S::Response, Error = S::Error>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Either<W::Future, S::Future>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        ready!(self.wrapped.poll_ready(cx))?;
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        if is_server_fn_path(req.uri().path()) {
+            Either::Left(self.wrapped.call(req))
+        } else {
+            Either::Right(self.inner.call(req))
+        }
+    }
+}
+
+/// Builds the CORS layer for server functions, or `None` if no other origins
+/// are allowed to call them.
+pub fn cors_layer(settings: &CorsSettings) -> Option<CorsLayer> {
+    if settings.allowed_origins.is_empty() {
+        return None;
+    }
+
+    let origins = settings
+        .allowed_origins
+        .iter()
+        .map(|origin| {
+            HeaderValue::from_str(origin).expect("invalid CORS origin")
+        })
+        .collect::<Vec<_>>();
+    let exposed_headers = settings
+        .exposed_headers
+        .iter()
+        .map(|name| {
+            HeaderName::from_bytes(name.as_bytes())
+                .expect("invalid CORS exposed header")
+        })
+        .collect::<Vec<_>>();
+
+    let mut layer = CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::GET, Method::POST])
+        .allow_headers(AllowHeaders::mirror_request())
+        .allow_credentials(settings.allow_credentials)
+        .expose_headers(exposed_headers);
+    if let Some(max_age) = settings.max_age_secs {
+        layer = layer.max_age(Duration::from_secs(max_age));
+    }
+    Some(layer)
+}
+
+/// Turns a panic in any handler (page render or server fn) into a 500 page
+/// rendered through [`ErrorTemplate`](crate::error_template::ErrorTemplate),
+/// instead of dropping the connection.
+pub fn catch_panic_layer(
+) -> CatchPanicLayer<fn(Box<dyn Any + Send + 'static>) -> Response<String>> {
+    CatchPanicLayer::custom(panic_response)
+}
+
+fn panic_response(err: Box<dyn Any + Send + 'static>) -> Response<String> {
+    let message = err
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| err.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("<non-string panic payload>");
+    let request_id = uuid::Uuid::new_v4().to_string();
+    tracing::error!(%request_id, panic = message, "handler panicked");
+
+    let mut res = Response::new(render_panic_page(&request_id));
+    *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+    let headers = res.headers_mut();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        headers.insert(REQUEST_ID_HEADER, value);
     }
+    res
 }
@@ -0,0 +1,35 @@
+use crate::errors::UploadError;
+use leptos::prelude::ServerFnError;
+use multer::Multipart;
+
+/// The part of an upload a chunk belongs to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldInfo {
+    pub name: Option<String>,
+    pub file_name: Option<String>,
+}
+
+/// Calls `on_chunk` with every chunk of every field of `data`, in the order
+/// they arrive. Stops at the first malformed part, or at the first error
+/// `on_chunk` returns, instead of treating either as the end of the upload.
+pub async fn for_each_chunk(
+    mut data: Multipart<'static>,
+    mut on_chunk: impl FnMut(&FieldInfo, &[u8]) -> Result<(), ServerFnError>,
+) -> Result<(), ServerFnError> {
+    while let Some(mut field) =
+        data.next_field().await.map_err(multipart_error)?
+    {
+        let info = FieldInfo {
+            name: field.name().map(str::to_string),
+            file_name: field.file_name().map(str::to_string),
+        };
+        while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+            on_chunk(&info, &chunk)?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn multipart_error(e: impl std::fmt::Display) -> ServerFnError {
+    ServerFnError::new(UploadError::Multipart(e.to_string()))
+}
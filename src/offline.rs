@@ -0,0 +1,118 @@
+//! Changes made while the server can't be reached, kept in `localStorage`
+//! and sent once the browser is back online, or the next time the page is
+//! loaded.
+
+use crate::{
+    app::add_row,
+    errors::AppError,
+    flash::{show_toast, Flash},
+    persist::{use_persisted, Persisted},
+};
+use leptos::{ev, prelude::*, task::spawn_local};
+use serde::{Deserialize, Serialize};
+
+/// A change waiting to be sent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QueuedMutation {
+    AddRow { text: String },
+}
+
+/// The changes waiting to be sent, oldest first.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OfflineQueue(pub Vec<QueuedMutation>);
+
+impl Persisted for OfflineQueue {
+    const KEY: &'static str = "offline_queue";
+}
+
+/// Whether `error` means the call never reached the server, so it's worth
+/// sending again later.
+pub fn is_unreachable(error: &AppError) -> bool {
+    error.code == "server_fn.request"
+}
+
+async fn send(mutation: QueuedMutation) -> Result<(), AppError> {
+    match mutation {
+        QueuedMutation::AddRow { text } => add_row(text).await.map(drop),
+    }
+}
+
+/// The stored [`OfflineQueue`], as [`use_offline_queue`] gives it.
+#[derive(Clone, Copy)]
+pub struct OfflineMutations {
+    queue: RwSignal<OfflineQueue>,
+    sent: RwSignal<usize>,
+    sending: StoredValue<bool>,
+}
+
+impl OfflineMutations {
+    pub fn push(self, mutation: QueuedMutation) {
+        self.queue
+            .update(|OfflineQueue(queue)| queue.push(mutation));
+    }
+
+    pub fn len(self) -> usize {
+        self.queue.with(|OfflineQueue(queue)| queue.len())
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bumped each time a queued change has been sent.
+    pub fn sent(self) -> ReadSignal<usize> {
+        self.sent.read_only()
+    }
+
+    /// Sends the queued changes in order, stopping at the first one that
+    /// still can't reach the server. One the server rejects is dropped and
+    /// shown as a toast, since sending it again wouldn't help.
+    pub fn flush(self) {
+        if self.sending.get_value() {
+            return;
+        }
+        self.sending.set_value(true);
+        spawn_local(async move {
+            while let Some(next) = self
+                .queue
+                .with_untracked(|OfflineQueue(queue)| queue.first().cloned())
+            {
+                let result = send(next).await;
+                if result.as_ref().is_err_and(is_unreachable) {
+                    break;
+                }
+                self.queue.update(|OfflineQueue(queue)| {
+                    queue.remove(0);
+                });
+                match result {
+                    Ok(()) => self.sent.update(|sent| *sent += 1),
+                    Err(e) => show_toast(Flash::Error(e)),
+                }
+            }
+            self.sending.set_value(false);
+        });
+    }
+}
+
+/// The stored [`OfflineQueue`], which is flushed once it's loaded and
+/// whenever the browser comes back online.
+pub fn use_offline_queue() -> OfflineMutations {
+    let mutations = OfflineMutations {
+        queue: use_persisted(),
+        sent: RwSignal::new(0),
+        sending: StoredValue::new(false),
+    };
+    // runs after the effect in `use_persisted` that loads the queue, as
+    // effects run in the order they're made
+    Effect::new(move |_| {
+        if !mutations
+            .queue
+            .with_untracked(|OfflineQueue(q)| q.is_empty())
+        {
+            mutations.flush();
+        }
+    });
+    let online = window_event_listener(ev::online, move |_| mutations.flush());
+    on_cleanup(move || online.remove());
+    mutations
+}
@@ -0,0 +1,604 @@
+//! Passkeys: signing in with a key the browser or a security key keeps,
+//! instead of a password.
+//!
+//! Adding or using a passkey is a ceremony in two steps. The server starts
+//! it with a challenge, the browser has the authenticator answer it, and
+//! the server checks the answer against the state it kept from the start.
+//! That state is kept for [`CEREMONY_SECS`], under an ID the browser sends
+//! back. Passkeys, like accounts, are only kept in memory.
+
+use crate::{
+    auth::User,
+    errors::{AppError, PasskeyError},
+};
+#[cfg(feature = "ssr")]
+use crate::{
+    auth::{require_user, start_session, USERS},
+    errors::AuthError,
+};
+use chrono::{DateTime, Local, Utc};
+use js_sys::Reflect;
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use server_fn::codec::{GetUrl, Json};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{CredentialCreationOptions, CredentialRequestOptions};
+use webauthn_rs_proto::{
+    CreationChallengeResponse, PublicKeyCredential,
+    RegisterPublicKeyCredential, RequestChallengeResponse,
+};
+
+/// The longest passkey name, in characters.
+pub const MAX_PASSKEY_NAME_LEN: usize = 64;
+/// The most passkeys a single user can have.
+pub const MAX_PASSKEYS: usize = 10;
+/// How long the browser has to answer a challenge.
+pub const CEREMONY_SECS: u64 = 300;
+
+/// A passkey as its owner sees it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PasskeyInfo {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// The start of a ceremony: what to hand the browser, and the ID to send
+/// back with its answer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasskeyChallenge<T> {
+    pub ceremony: String,
+    pub options: T,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{
+        PasskeyInfo, CEREMONY_SECS, MAX_PASSKEYS, MAX_PASSKEY_NAME_LEN,
+    };
+    use crate::{
+        auth::{User, UserId},
+        errors::PasskeyError,
+        settings::AuthSettings,
+    };
+    use chrono::Utc;
+    use dashmap::DashMap;
+    use sha2::{Digest, Sha256};
+    use std::{
+        sync::{LazyLock, Mutex, OnceLock},
+        time::{Duration, Instant},
+    };
+    use webauthn_rs::prelude::{
+        AuthenticationResult, Passkey, PasskeyAuthentication,
+        PasskeyRegistration, Url, Uuid, Webauthn, WebauthnBuilder,
+    };
+    use webauthn_rs_proto::{AllowCredentials, RequestChallengeResponse};
+
+    static WEBAUTHN: OnceLock<Webauthn> = OnceLock::new();
+
+    /// Every user's passkeys.
+    pub static PASSKEYS: LazyLock<PasskeyStore> =
+        LazyLock::new(PasskeyStore::default);
+
+    /// Registrations started, by ceremony ID.
+    static REGISTRATIONS: LazyLock<
+        DashMap<String, (Instant, UserId, String, PasskeyRegistration)>,
+    > = LazyLock::new(DashMap::new);
+
+    /// Sign-ins started, by ceremony ID; `None` for the ones started with
+    /// [`decoy_options`].
+    static AUTHENTICATIONS: LazyLock<
+        DashMap<String, (Instant, Option<(User, PasskeyAuthentication)>)>,
+    > = LazyLock::new(DashMap::new);
+
+    /// Mixed into [`decoy_options`]' credential IDs, so they can't be worked
+    /// out from a name.
+    static DECOY_SALT: LazyLock<Uuid> = LazyLock::new(Uuid::new_v4);
+
+    /// Applies the passkey settings in `[auth]`; call it once, before
+    /// serving. If they're invalid, passkeys are left off and the rest of
+    /// the server works as usual.
+    pub fn init(settings: &AuthSettings) {
+        let webauthn = Url::parse(&settings.passkey_origin)
+            .map_err(|e| e.to_string())
+            .and_then(|origin| {
+                WebauthnBuilder::new(&settings.passkey_rp_id, &origin)
+                    .and_then(|builder| {
+                        builder
+                            .rp_name(crate::seo::SITE_NAME)
+                            .allow_subdomains(true)
+                            .build()
+                    })
+                    .map_err(|e| e.to_string())
+            });
+        match webauthn {
+            Ok(webauthn) => _ = WEBAUTHN.set(webauthn),
+            Err(e) => tracing::warn!("passkeys are off: {e}"),
+        }
+    }
+
+    pub fn webauthn() -> Result<&'static Webauthn, PasskeyError> {
+        WEBAUTHN.get().ok_or(PasskeyError::NotConfigured)
+    }
+
+    /// The ID passkeys know `user` by. It's not secret, but it's the same
+    /// whatever the account is renamed to.
+    pub fn user_handle(user: UserId) -> Uuid {
+        Uuid::from_u64_pair(0, user)
+    }
+
+    /// Why webauthn-rs turned down a ceremony.
+    pub fn rejected(e: impl std::fmt::Display) -> PasskeyError {
+        PasskeyError::Rejected {
+            message: e.to_string(),
+        }
+    }
+
+    fn fresh(started: Instant) -> bool {
+        started.elapsed() <= Duration::from_secs(CEREMONY_SECS)
+    }
+
+    /// Keeps the state of a registration `user` started, returning the
+    /// ceremony's ID.
+    pub fn begin_registration(
+        user: UserId,
+        name: String,
+        state: PasskeyRegistration,
+    ) -> String {
+        REGISTRATIONS.retain(|_, (started, ..)| fresh(*started));
+        let ceremony = Uuid::new_v4().simple().to_string();
+        REGISTRATIONS
+            .insert(ceremony.clone(), (Instant::now(), user, name, state));
+        ceremony
+    }
+
+    /// The name and state of the registration `user` started as
+    /// `ceremony`, which can't be used again.
+    pub fn take_registration(
+        user: UserId,
+        ceremony: &str,
+    ) -> Result<(String, PasskeyRegistration), PasskeyError> {
+        match REGISTRATIONS.remove(ceremony) {
+            Some((_, (started, owner, name, state)))
+                if owner == user && fresh(started) =>
+            {
+                Ok((name, state))
+            }
+            _ => Err(PasskeyError::Expired),
+        }
+    }
+
+    /// Keeps the state of a sign-in, or `None` for a decoy, returning the
+    /// ceremony's ID.
+    pub fn begin_authentication(
+        sign_in: Option<(User, PasskeyAuthentication)>,
+    ) -> String {
+        AUTHENTICATIONS.retain(|_, (started, _)| fresh(*started));
+        let ceremony = Uuid::new_v4().simple().to_string();
+        AUTHENTICATIONS.insert(ceremony.clone(), (Instant::now(), sign_in));
+        ceremony
+    }
+
+    /// Who the sign-in started as `ceremony` is for, and its state, which
+    /// can't be used again. `None` if it was a decoy.
+    pub fn take_authentication(
+        ceremony: &str,
+    ) -> Result<Option<(User, PasskeyAuthentication)>, PasskeyError> {
+        match AUTHENTICATIONS.remove(ceremony) {
+            Some((_, (started, sign_in))) if fresh(started) => Ok(sign_in),
+            _ => Err(PasskeyError::Expired),
+        }
+    }
+
+    /// Sign-in options for `name`, who doesn't exist or has no passkeys,
+    /// shaped like real ones so nobody can tell which names have passkeys.
+    /// They allow one made-up credential, the same for `name` until the
+    /// server restarts, which no authenticator has.
+    pub fn decoy_options(
+        name: &str,
+    ) -> Result<RequestChallengeResponse, PasskeyError> {
+        let (mut options, _) = webauthn()?
+            .start_discoverable_authentication()
+            .map_err(rejected)?;
+        let id = Sha256::new()
+            .chain_update(DECOY_SALT.as_bytes())
+            .chain_update(name)
+            .finalize();
+        options.mediation = None;
+        options.public_key.allow_credentials = vec![AllowCredentials {
+            type_: "public-key".to_string(),
+            id: id.to_vec().into(),
+            transports: None,
+        }];
+        Ok(options)
+    }
+
+    #[derive(Debug, Default)]
+    pub struct PasskeyStore {
+        inner: Mutex<Vec<StoredPasskey>>,
+    }
+
+    #[derive(Debug)]
+    struct StoredPasskey {
+        owner: UserId,
+        info: PasskeyInfo,
+        passkey: Passkey,
+    }
+
+    impl PasskeyStore {
+        /// Checks `name` and that `owner` has room for another passkey,
+        /// before asking their browser to make one.
+        pub fn check_new(
+            &self,
+            owner: UserId,
+            name: &str,
+        ) -> Result<(), PasskeyError> {
+            if !(1..=MAX_PASSKEY_NAME_LEN).contains(&name.chars().count()) {
+                return Err(PasskeyError::InvalidName {
+                    max: MAX_PASSKEY_NAME_LEN,
+                });
+            }
+            if self.passkeys(owner).len() >= MAX_PASSKEYS {
+                return Err(PasskeyError::TooMany { max: MAX_PASSKEYS });
+            }
+            Ok(())
+        }
+
+        pub fn add(
+            &self,
+            owner: UserId,
+            name: String,
+            passkey: Passkey,
+        ) -> Result<PasskeyInfo, PasskeyError> {
+            self.check_new(owner, &name)?;
+            let info = PasskeyInfo {
+                id: Uuid::new_v4().simple().to_string(),
+                name,
+                created_at: Utc::now(),
+                last_used_at: None,
+            };
+            self.inner.lock().unwrap().push(StoredPasskey {
+                owner,
+                info: info.clone(),
+                passkey,
+            });
+            Ok(info)
+        }
+
+        /// `owner`'s passkeys, as the ceremonies need them.
+        pub fn passkeys(&self, owner: UserId) -> Vec<Passkey> {
+            self.inner
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|stored| stored.owner == owner)
+                .map(|stored| stored.passkey.clone())
+                .collect()
+        }
+
+        /// `owner`'s passkeys, oldest first.
+        pub fn list(&self, owner: UserId) -> Vec<PasskeyInfo> {
+            self.inner
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|stored| stored.owner == owner)
+                .map(|stored| stored.info.clone())
+                .collect()
+        }
+
+        /// Deletes one of `owner`'s passkeys, which can't sign in from then
+        /// on.
+        pub fn revoke(
+            &self,
+            owner: UserId,
+            id: &str,
+        ) -> Result<(), PasskeyError> {
+            let mut passkeys = self.inner.lock().unwrap();
+            let before = passkeys.len();
+            passkeys.retain(|stored| {
+                !(stored.owner == owner && stored.info.id == id)
+            });
+            if passkeys.len() < before {
+                Ok(())
+            } else {
+                Err(PasskeyError::NotFound { id: id.to_string() })
+            }
+        }
+
+        /// Notes that `owner` signed in with the passkey `result` is for,
+        /// and updates its signature counter.
+        pub fn used(&self, owner: UserId, result: &AuthenticationResult) {
+            let mut passkeys = self.inner.lock().unwrap();
+            if let Some(stored) = passkeys.iter_mut().find(|stored| {
+                stored.owner == owner
+                    && stored.passkey.cred_id() == result.cred_id()
+            }) {
+                stored.passkey.update_credential(result);
+                stored.info.last_used_at = Some(Utc::now());
+            }
+        }
+    }
+}
+
+/// The signed-in user's passkeys, oldest first.
+#[server(input = GetUrl)]
+pub async fn list_passkeys() -> Result<Vec<PasskeyInfo>, AppError> {
+    let user = require_user()?;
+    Ok(PASSKEYS.list(user.id))
+}
+
+/// Starts adding a passkey called `name` for the signed-in user.
+#[server]
+pub async fn start_passkey_registration(
+    name: String,
+) -> Result<PasskeyChallenge<CreationChallengeResponse>, AppError> {
+    let user = require_user()?;
+    let name = name.trim().to_string();
+    PASSKEYS.check_new(user.id, &name)?;
+    let existing = PASSKEYS
+        .passkeys(user.id)
+        .iter()
+        .map(|passkey| passkey.cred_id().clone())
+        .collect();
+    let (options, state) = webauthn()?
+        .start_passkey_registration(
+            user_handle(user.id),
+            &user.name,
+            &user.name,
+            Some(existing),
+        )
+        .map_err(rejected)?;
+    Ok(PasskeyChallenge {
+        ceremony: begin_registration(user.id, name, state),
+        options,
+    })
+}
+
+/// Adds the passkey the browser made for `ceremony`.
+#[server(input = Json)]
+pub async fn finish_passkey_registration(
+    ceremony: String,
+    credential: RegisterPublicKeyCredential,
+) -> Result<PasskeyInfo, AppError> {
+    let user = require_user()?;
+    let (name, state) = take_registration(user.id, &ceremony)?;
+    let passkey = webauthn()?
+        .finish_passkey_registration(&credential, &state)
+        .map_err(rejected)?;
+    let info = PASSKEYS.add(user.id, name, passkey)?;
+    tracing::info!(user = user.id, passkey = %info.id, "passkey added");
+    Ok(info)
+}
+
+/// Starts signing in as `name` with one of their passkeys. Names that don't
+/// exist or have no passkeys get a challenge just the same, which no
+/// passkey answers.
+#[server]
+pub async fn start_passkey_login(
+    name: String,
+) -> Result<PasskeyChallenge<RequestChallengeResponse>, AppError> {
+    let webauthn = webauthn()?;
+    let name = name.trim();
+    let found = USERS
+        .find(name)
+        .map(|(user, _)| (PASSKEYS.passkeys(user.id), user))
+        .filter(|(passkeys, _)| !passkeys.is_empty());
+    let Some((passkeys, user)) = found else {
+        return Ok(PasskeyChallenge {
+            ceremony: begin_authentication(None),
+            options: decoy_options(name)?,
+        });
+    };
+    let (options, state) = webauthn
+        .start_passkey_authentication(&passkeys)
+        .map_err(rejected)?;
+    Ok(PasskeyChallenge {
+        ceremony: begin_authentication(Some((user, state))),
+        options,
+    })
+}
+
+/// Signs in with the passkey the browser used for `ceremony`.
+#[server(input = Json)]
+pub async fn finish_passkey_login(
+    ceremony: String,
+    credential: PublicKeyCredential,
+) -> Result<User, AppError> {
+    // a decoy and a passkey that doesn't check out fail the same way
+    let (user, state) =
+        take_authentication(&ceremony)?.ok_or(AuthError::InvalidCredentials)?;
+    let result = webauthn()?
+        .finish_passkey_authentication(&credential, &state)
+        .map_err(|_| AuthError::InvalidCredentials)?;
+    PASSKEYS.used(user.id, &result);
+    start_session(user.clone(), true);
+    Ok(user)
+}
+
+#[server]
+pub async fn revoke_passkey(id: String) -> Result<(), AppError> {
+    let user = require_user()?;
+    Ok(PASSKEYS.revoke(user.id, &id)?)
+}
+
+/// Has the browser make a passkey, as `options` ask.
+async fn create_credential(
+    options: CreationChallengeResponse,
+) -> Result<RegisterPublicKeyCredential, PasskeyError> {
+    let options = CredentialCreationOptions::from(options);
+    let promise = window()
+        .navigator()
+        .credentials()
+        .create_with_options(&options)
+        .map_err(browser_error)?;
+    let credential = JsFuture::from(promise).await.map_err(browser_error)?;
+    Ok(web_sys::PublicKeyCredential::from(credential).into())
+}
+
+/// Has the browser answer a sign-in challenge with one of the passkeys
+/// `options` allow.
+async fn get_credential(
+    options: RequestChallengeResponse,
+) -> Result<PublicKeyCredential, PasskeyError> {
+    let options = CredentialRequestOptions::from(options);
+    let promise = window()
+        .navigator()
+        .credentials()
+        .get_with_options(&options)
+        .map_err(browser_error)?;
+    let credential = JsFuture::from(promise).await.map_err(browser_error)?;
+    Ok(web_sys::PublicKeyCredential::from(credential).into())
+}
+
+/// The browser's reason for a rejected promise or a thrown exception, such
+/// as the user cancelling.
+fn browser_error(e: JsValue) -> PasskeyError {
+    let message = Reflect::get(&e, &"message".into())
+        .ok()
+        .and_then(|message| message.as_string())
+        .unwrap_or_else(|| format!("{e:?}"));
+    PasskeyError::Browser { message }
+}
+
+/// Adds a passkey called `name` for the signed-in user, from start to
+/// finish.
+async fn register_passkey(name: String) -> Result<PasskeyInfo, AppError> {
+    let challenge = start_passkey_registration(name).await?;
+    let credential = create_credential(challenge.options).await?;
+    finish_passkey_registration(challenge.ceremony, credential).await
+}
+
+/// Signs in as `name` with a passkey, from start to finish.
+async fn passkey_login(name: String) -> Result<User, AppError> {
+    let challenge = start_passkey_login(name).await?;
+    let credential = get_credential(challenge.options).await?;
+    finish_passkey_login(challenge.ceremony, credential).await
+}
+
+/// A form to sign in with a passkey instead of a password.
+#[component]
+pub fn PasskeyLogin() -> impl IntoView {
+    let name = RwSignal::new(String::new());
+    // the browser's credential calls aren't `Send`
+    let login = Action::new_local(|name: &String| passkey_login(name.clone()));
+    Effect::new(move |_| {
+        if login.value().with(|v| matches!(v, Some(Ok(_)))) {
+            _ = window().location().reload();
+        }
+    });
+
+    view! {
+        <form on:submit=move |ev| {
+            ev.prevent_default();
+            login.dispatch(name.get_untracked());
+        }>
+            <input
+                placeholder="Name"
+                required
+                prop:value=name
+                on:input=move |ev| name.set(event_target_value(&ev))
+            />
+            <input type="submit" value="Sign in with a passkey" disabled=login.pending() />
+        </form>
+        {move || {
+            login.value().get().and_then(Result::err).map(|e| view! { <p>{e.to_string()}</p> })
+        }}
+    }
+}
+
+/// The signed-in user's passkeys, with a form to add one and buttons to
+/// revoke them.
+#[component]
+pub fn PasskeyDevices() -> impl IntoView {
+    let name = RwSignal::new(String::new());
+    let register =
+        Action::new_local(|name: &String| register_passkey(name.clone()));
+    let revoke = ServerAction::<RevokePasskey>::new();
+    let passkeys = Resource::new(
+        move || (register.version().get(), revoke.version().get()),
+        |_| list_passkeys(),
+    );
+    let error = move || {
+        register
+            .value()
+            .get()
+            .and_then(Result::err)
+            .or_else(|| revoke.value().get().and_then(Result::err))
+            .map(|e| view! { <p>{e.to_string()}</p> })
+    };
+    let format_time = |at: DateTime<Utc>| {
+        at.with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    };
+
+    view! {
+        <h3>"Passkeys"</h3>
+        <p>"Sign in with your fingerprint, face, screen lock or security key instead of your password."</p>
+        <form on:submit=move |ev| {
+            ev.prevent_default();
+            register.dispatch(name.get_untracked());
+        }>
+            <input
+                placeholder="Passkey name, like \"Laptop\""
+                maxlength=MAX_PASSKEY_NAME_LEN
+                required
+                prop:value=name
+                on:input=move |ev| name.set(event_target_value(&ev))
+            />
+            <input type="submit" value="Add passkey" disabled=register.pending() />
+        </form>
+        {error}
+        <Transition fallback=|| view! { <p>"Loading..."</p> }>
+            {move || Suspend::new(async move {
+                match passkeys.await {
+                    Ok(passkeys) if passkeys.is_empty() => {
+                        view! { <p>"You have no passkeys."</p> }.into_any()
+                    }
+                    Ok(passkeys) => {
+                        view! {
+                            <table>
+                                <tr>
+                                    <th>"Name"</th>
+                                    <th>"Added"</th>
+                                    <th>"Last used"</th>
+                                    <th></th>
+                                </tr>
+                                {passkeys
+                                    .into_iter()
+                                    .map(|passkey| {
+                                        view! {
+                                            <tr>
+                                                <td>{passkey.name}</td>
+                                                <td>{format_time(passkey.created_at)}</td>
+                                                <td>
+                                                    {passkey
+                                                        .last_used_at
+                                                        .map_or_else(|| "never".to_string(), format_time)}
+                                                </td>
+                                                <td>
+                                                    <ActionForm action=revoke>
+                                                        <input type="hidden" name="id" value=passkey.id />
+                                                        <input type="submit" value="Revoke" />
+                                                    </ActionForm>
+                                                </td>
+                                            </tr>
+                                        }
+                                    })
+                                    .collect::<Vec<_>>()}
+                            </table>
+                        }
+                            .into_any()
+                    }
+                    Err(e) => view! { <p>{e.to_string()}</p> }.into_any(),
+                }
+            })}
+        </Transition>
+    }
+}
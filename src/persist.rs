@@ -0,0 +1,108 @@
+//! State only the browser keeps, such as preferences, in `localStorage`.
+//!
+//! Each [`Persisted`] type is stored as JSON under its own key, ending in
+//! its [`VERSION`](Persisted::VERSION): `todo-demo.theme.v1`. A type that
+//! changes shape bumps its version, and the value stored under the newest
+//! older key goes through [`Persisted::migrate`] once, then moves to the
+//! new key.
+//!
+//! The server has no `localStorage`, so it renders each one as its default,
+//! and [`use_persisted`] switches to the stored value once the page has
+//! hydrated. Storage the browser refuses, such as in some private modes,
+//! reads as empty and ignores writes.
+
+use leptos::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value as Json;
+
+/// What every key starts with, so the app's keys stand out from others on
+/// the same origin.
+pub const KEY_PREFIX: &str = "todo-demo";
+
+pub trait Persisted: Serialize + DeserializeOwned {
+    /// The key's name, without the prefix or version.
+    const KEY: &'static str;
+    /// Bumped whenever the stored JSON changes shape.
+    const VERSION: u32 = 1;
+
+    /// The value `json` that version `version` stored, as it is now, or
+    /// `None` to start over from nothing.
+    fn migrate(version: u32, json: Json) -> Option<Self> {
+        _ = (version, json);
+        None
+    }
+}
+
+/// The full `localStorage` key of `version` of `T`.
+pub fn storage_key<T: Persisted>(version: u32) -> String {
+    format!("{KEY_PREFIX}.{}.v{version}", T::KEY)
+}
+
+fn local_storage() -> Option<web_sys::Storage> {
+    #[cfg(feature = "ssr")]
+    {
+        None
+    }
+    #[cfg(not(feature = "ssr"))]
+    {
+        window().local_storage().ok().flatten()
+    }
+}
+
+/// The stored `T`, migrated from an older version if that's all there is.
+pub fn load<T: Persisted>() -> Option<T> {
+    let storage = local_storage()?;
+    let get = |version| storage.get_item(&storage_key::<T>(version)).ok()?;
+    if let Some(json) = get(T::VERSION) {
+        return serde_json::from_str(&json).ok();
+    }
+    let (version, json) =
+        (1..T::VERSION).rev().find_map(|v| Some((v, get(v)?)))?;
+    // whether or not it migrates, the old keys are done with
+    for old in 1..T::VERSION {
+        _ = storage.remove_item(&storage_key::<T>(old));
+    }
+    let value = T::migrate(version, serde_json::from_str(&json).ok()?)?;
+    save(&value);
+    Some(value)
+}
+
+pub fn save<T: Persisted>(value: &T) {
+    if let (Some(storage), Ok(json)) =
+        (local_storage(), serde_json::to_string(value))
+    {
+        _ = storage.set_item(&storage_key::<T>(T::VERSION), &json);
+    }
+}
+
+pub fn forget<T: Persisted>() {
+    if let Some(storage) = local_storage() {
+        _ = storage.remove_item(&storage_key::<T>(T::VERSION));
+    }
+}
+
+/// A signal holding the stored `T`, which saves every change to it.
+///
+/// It starts as `T::default()`, as the server rendered it, and is only
+/// loaded after hydrating, so hydration sees the same page.
+pub fn use_persisted<T>() -> RwSignal<T>
+where
+    T: Persisted + Default + Clone + Send + Sync + 'static,
+{
+    let value = RwSignal::new(T::default());
+    // effects only run in the browser
+    Effect::new(move |_| {
+        if let Some(stored) = load::<T>() {
+            value.set(stored);
+        }
+    });
+    Effect::new(move |first: Option<()>| {
+        // the default it starts as isn't a change worth saving
+        value.with(|value| {
+            if first.is_some() {
+                save(value);
+            }
+        });
+    });
+    value
+}
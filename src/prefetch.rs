@@ -0,0 +1,296 @@
+//! Starting the calls a page makes first as soon as a link to it is hovered
+//! or focused, so its data is usually there by the time it's clicked.
+//!
+//! A prefetched result is kept on the client for [`PREFETCH_TTL_MS`] and is
+//! used once, by the next [`cached`] call with the same key; every later
+//! call goes to the server, so a page never shows data from before its own
+//! mutations. The server never prefetches, and [`cached`] just calls
+//! through there.
+//!
+//! Separately, the client remembers the last result it saw under each key
+//! (up to [`MAX_KNOWN`] of them), so a [`CachedResource`] can show it while
+//! the fresh one loads.
+
+use crate::{
+    admin::admin_stats, api_keys::list_api_keys, load::load_status,
+    rows::get_row, trash::list_trash,
+};
+use futures::{
+    future::{BoxFuture, Shared},
+    FutureExt,
+};
+use leptos::{prelude::*, task::spawn_local};
+use leptos_router::{components::A, hooks::use_location};
+use serde::{de::DeserializeOwned, Serialize};
+use server_fn::ServerFnError;
+use std::{any::Any, collections::BTreeMap, future::Future, sync::Mutex};
+
+/// How long a prefetched result is used for, in milliseconds.
+pub const PREFETCH_TTL_MS: f64 = 10_000.0;
+
+type Prefetched<T> = Shared<BoxFuture<'static, Result<T, ServerFnError>>>;
+
+struct Entry {
+    started_at: f64,
+    /// A [`Prefetched`] of whatever the call returns.
+    call: Box<dyn Any + Send>,
+}
+
+static PREFETCHED: Mutex<BTreeMap<String, Entry>> = Mutex::new(BTreeMap::new());
+
+/// How many last-seen results the client keeps.
+pub const MAX_KNOWN: usize = 256;
+
+struct Known {
+    /// Which [`remember`] call it came from, counting up.
+    seen: u64,
+    /// A `T` of whatever the call returns.
+    value: Box<dyn Any + Send>,
+}
+
+struct KnownResults {
+    results: BTreeMap<String, Known>,
+    seen: u64,
+}
+
+static KNOWN: Mutex<KnownResults> = Mutex::new(KnownResults {
+    results: BTreeMap::new(),
+    seen: 0,
+});
+
+/// Keeps `value` as the last result seen under `key`, dropping the oldest
+/// one past [`MAX_KNOWN`]. The server keeps nothing, since its results
+/// belong to whoever asked.
+pub fn remember<T: Send + 'static>(key: String, value: T) {
+    if cfg!(feature = "ssr") {
+        return;
+    }
+    let mut known = KNOWN.lock().unwrap();
+    known.seen += 1;
+    let seen = known.seen;
+    known.results.insert(
+        key,
+        Known {
+            seen,
+            value: Box::new(value),
+        },
+    );
+    if known.results.len() > MAX_KNOWN {
+        let oldest = known
+            .results
+            .iter()
+            .min_by_key(|(_, known)| known.seen)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            known.results.remove(&oldest);
+        }
+    }
+}
+
+/// The last result seen under `key`, if the client has one.
+pub fn last_known<T: Clone + 'static>(key: &str) -> Option<T> {
+    KNOWN
+        .lock()
+        .unwrap()
+        .results
+        .get(key)
+        .and_then(|known| known.value.downcast_ref::<T>())
+        .cloned()
+}
+
+/// Starts `fetch` and keeps its result under `key` for the next [`cached`]
+/// call, unless it has been started already.
+pub fn prefetch<T, Fut>(key: String, fetch: impl FnOnce() -> Fut)
+where
+    T: Clone + Send + Sync + 'static,
+    Fut: Future<Output = Result<T, ServerFnError>> + Send + 'static,
+{
+    if cfg!(feature = "ssr") {
+        return;
+    }
+    let now = js_sys::Date::now();
+    let call: Prefetched<T> = {
+        let mut prefetched = PREFETCHED.lock().unwrap();
+        prefetched.retain(|_, entry| now - entry.started_at < PREFETCH_TTL_MS);
+        if prefetched.contains_key(&key) {
+            return;
+        }
+        let call = fetch().boxed().shared();
+        prefetched.insert(
+            key.clone(),
+            Entry {
+                started_at: now,
+                call: Box::new(call.clone()),
+            },
+        );
+        call
+    };
+    spawn_local(async move {
+        match call.await {
+            Ok(value) => remember(key, value),
+            // a failed prefetch is forgotten, so the page makes the call
+            // itself
+            Err(_) => {
+                let mut prefetched = PREFETCHED.lock().unwrap();
+                if prefetched.get(&key).is_some_and(|e| e.started_at == now) {
+                    prefetched.remove(&key);
+                }
+            }
+        }
+    });
+}
+
+/// The result prefetched under `key`, if it was started within
+/// [`PREFETCH_TTL_MS`], or else `fetch`'s.
+pub async fn cached<T, Fut>(
+    key: String,
+    fetch: impl FnOnce() -> Fut,
+) -> Result<T, ServerFnError>
+where
+    T: Clone + Send + Sync + 'static,
+    Fut: Future<Output = Result<T, ServerFnError>>,
+{
+    let prefetched = if cfg!(feature = "ssr") {
+        None
+    } else {
+        let now = js_sys::Date::now();
+        PREFETCHED
+            .lock()
+            .unwrap()
+            .remove(&key)
+            .filter(|entry| now - entry.started_at < PREFETCH_TTL_MS)
+            .and_then(|entry| entry.call.downcast::<Prefetched<T>>().ok())
+    };
+    match prefetched {
+        Some(call) => call.await,
+        None => fetch().await,
+    }
+}
+
+/// A [`Resource`] that also has the last result seen under its key, to
+/// show while it loads.
+///
+/// On a hard load the resource is resolved during SSR and hydrates with
+/// that, so nothing is remembered yet and [`CachedResource::last_known`] is
+/// `None`. After a client-side navigation it usually has what the page
+/// showed last time, or what a list or a prefetch already loaded, until
+/// the fresh result replaces it.
+pub struct CachedResource<T: Send + Sync + 'static> {
+    key: Signal<Option<String>>,
+    resource: Resource<Result<T, ServerFnError>>,
+}
+
+impl<T: Send + Sync + 'static> Clone for CachedResource<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Send + Sync + 'static> Copy for CachedResource<T> {}
+
+impl<T> CachedResource<T>
+where
+    T: Clone + Send + Sync + Serialize + DeserializeOwned + 'static,
+{
+    /// Like [`Resource::new`], with `key` naming what `source` loads, or
+    /// `None` if it's not worth remembering.
+    pub fn new<S, Fut>(
+        source: impl Fn() -> S + Send + Sync + 'static,
+        key: impl Fn(&S) -> Option<String> + Send + Sync + 'static,
+        fetch: impl Fn(S) -> Fut + Send + Sync + 'static,
+    ) -> Self
+    where
+        S: Clone + PartialEq + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, ServerFnError>> + Send + 'static,
+    {
+        let source = Memo::new(move |_| source());
+        let key = Signal::derive(move || source.with(&key));
+        let resource = Resource::new(move || source.get(), fetch);
+        // also catches what SSR resolved, which never ran `fetch` here
+        Effect::new(move |_| {
+            if let (Some(key), Some(Ok(value))) = (key.get(), resource.get()) {
+                remember(key, value);
+            }
+        });
+        CachedResource { key, resource }
+    }
+
+    /// The fresh result, once it's loaded.
+    pub fn resource(&self) -> Resource<Result<T, ServerFnError>> {
+        self.resource
+    }
+
+    /// The last result seen under the current key. Tracks the key, not
+    /// what's remembered under it.
+    pub fn last_known(&self) -> Option<T> {
+        self.key.get().and_then(|key| last_known(&key))
+    }
+}
+
+/// What a route's page loads first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteData {
+    AdminStats,
+    ApiKeys,
+    LoadStatus,
+    Row(u64),
+    Trash,
+}
+
+impl RouteData {
+    /// What the page at `path` loads first, if it loads anything.
+    pub fn for_path(path: &str) -> Option<Self> {
+        match path.trim_end_matches('/') {
+            "/admin" => Some(RouteData::AdminStats),
+            "/api-keys" => Some(RouteData::ApiKeys),
+            "/load" => Some(RouteData::LoadStatus),
+            "/trash" => Some(RouteData::Trash),
+            path => path
+                .strip_prefix("/rows/")
+                .and_then(|id| id.parse().ok())
+                .map(RouteData::Row),
+        }
+    }
+
+    /// What it's prefetched under.
+    pub fn key(self) -> String {
+        format!("{self:?}")
+    }
+
+    pub fn prefetch(self) {
+        let key = self.key();
+        match self {
+            RouteData::AdminStats => prefetch(key, admin_stats),
+            RouteData::ApiKeys => prefetch(key, list_api_keys),
+            RouteData::LoadStatus => prefetch(key, load_status),
+            RouteData::Row(id) => prefetch(key, move || get_row(id)),
+            RouteData::Trash => prefetch(key, list_trash),
+        }
+    }
+}
+
+/// A link that prefetches what the page it leads to loads first, once it's
+/// hovered or focused. Links to the current page don't, since that data
+/// could be out of date by the time the page refetches it.
+#[component]
+pub fn PrefetchLink(
+    #[prop(into)] href: String,
+    children: Children,
+) -> impl IntoView {
+    let location = use_location();
+    let data = RouteData::for_path(&href);
+    let target = href.clone();
+    let warm = move || {
+        let here = location.pathname.get_untracked();
+        if let Some(data) = data.filter(|_| here != target) {
+            data.prefetch();
+        }
+    };
+    let warm_on_focus = warm.clone();
+
+    view! {
+        <span on:mouseenter=move |_| warm() on:focusin=move |_| warm_on_focus()>
+            <A href=href>{children()}</A>
+        </span>
+    }
+}
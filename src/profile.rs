@@ -0,0 +1,19 @@
+//! The signed-in user's own settings: the passkeys they can sign in with,
+//! and two-factor authentication.
+
+use crate::{passkeys::PasskeyDevices, seo::PageMeta, totp::TwoFactorSettings};
+use leptos::prelude::*;
+use leptos_router::components::A;
+
+#[component]
+pub fn ProfilePage() -> impl IntoView {
+    view! {
+        <PageMeta title="Profile" description="Your sign-in settings." private=true />
+        <h2>"Profile"</h2>
+        <p>
+            <A href="/">"Back to the demo"</A>
+        </p>
+        <PasskeyDevices />
+        <TwoFactorSettings />
+    }
+}
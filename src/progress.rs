@@ -0,0 +1,394 @@
+//! One way to report how long-running work is getting on, whatever it is:
+//! uploads, the periodic jobs and the tasks admins run all send
+//! [`ProgressFrame`]s, and [`ProgressStream`] shows any of them.
+//!
+//! Every piece of work gets a [`ProgressId`] and sends a `Started` frame,
+//! any number of `Progress` frames, and then `Completed` or `Failed`. All
+//! frames go out on one channel, and each subscriber only gets the ones
+//! meant for it: its own uploads, and the jobs and tasks if it's an admin.
+
+use crate::{
+    channels::{Polled, SocketMessage, SocketRequest},
+    clients::KeepaliveClient,
+    supervisor::{supervise_socket_or_poll, ConnectionBadge, ConnectionState},
+};
+use leptos::{prelude::*, task::spawn_local};
+use serde::{Deserialize, Serialize};
+use server_fn::{codec::JsonEncoding, BoxedStream, Websocket};
+use std::ops::ControlFlow;
+use strum::Display;
+
+/// How many pieces of work [`ProgressStream`] lists; the oldest finished
+/// ones are dropped first.
+pub const MAX_PROGRESS_ITEMS: usize = 20;
+
+/// Names one piece of work, unique until the server restarts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ProgressId(pub u64);
+
+/// What kind of work a frame is about.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display,
+)]
+pub enum ProgressKind {
+    #[strum(serialize = "upload")]
+    Upload,
+    #[strum(serialize = "job")]
+    Job,
+    #[strum(serialize = "task")]
+    Task,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProgressFrame {
+    pub id: ProgressId,
+    pub kind: ProgressKind,
+    pub event: ProgressEvent,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ProgressEvent {
+    /// Always the first frame. `total` is how much there is to do, if
+    /// it's known.
+    Started {
+        label: String,
+        total: Option<u64>,
+    },
+    /// `done` out of `total` so far.
+    Progress {
+        done: u64,
+        total: Option<u64>,
+    },
+    Completed {
+        message: Option<String>,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{ProgressEvent, ProgressFrame, ProgressId, ProgressKind};
+    use crate::{auth::UserId, channels::DropOldest};
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            LazyLock,
+        },
+        time::{Duration, Instant},
+    };
+
+    /// `Progress` frames closer together than this are skipped, so fast
+    /// work doesn't flood the channel.
+    pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Who a piece of work's frames are sent to.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Audience {
+        Owner(UserId),
+        Admins,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Envelope {
+        pub audience: Audience,
+        pub frame: ProgressFrame,
+    }
+
+    /// Frames for everyone; a reconnecting client catches up on its own
+    /// among the last 1024.
+    pub static PROGRESS: LazyLock<DropOldest<Envelope>> =
+        LazyLock::new(|| DropOldest::new("progress", 1024));
+
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+    /// Sends a `Started` frame for new work labelled `label` to `audience`,
+    /// returning what reports the rest of it.
+    pub fn start(
+        audience: Audience,
+        kind: ProgressKind,
+        label: impl Into<String>,
+        total: Option<u64>,
+    ) -> Reporter {
+        let reporter = Reporter {
+            audience,
+            id: ProgressId(NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+            kind,
+            total,
+            last_sent: None,
+            finished: false,
+        };
+        reporter.send(ProgressEvent::Started {
+            label: label.into(),
+            total,
+        });
+        reporter
+    }
+
+    /// Reports one piece of work. Dropping it before it's completed or
+    /// failed reports it failed.
+    #[derive(Debug)]
+    pub struct Reporter {
+        audience: Audience,
+        id: ProgressId,
+        kind: ProgressKind,
+        total: Option<u64>,
+        last_sent: Option<Instant>,
+        finished: bool,
+    }
+
+    impl Reporter {
+        pub fn id(&self) -> ProgressId {
+            self.id
+        }
+
+        fn send(&self, event: ProgressEvent) {
+            PROGRESS.send(Envelope {
+                audience: self.audience,
+                frame: ProgressFrame {
+                    id: self.id,
+                    kind: self.kind,
+                    event,
+                },
+            });
+        }
+
+        /// Sets how much there is to do, once it's known.
+        pub fn set_total(&mut self, total: u64) {
+            self.total = Some(total);
+        }
+
+        /// Reports `done` so far, unless the last report was very recent and
+        /// this isn't the end.
+        pub fn advance(&mut self, done: u64) {
+            let at_end = self.total == Some(done);
+            let recent = self
+                .last_sent
+                .is_some_and(|sent| sent.elapsed() < PROGRESS_INTERVAL);
+            if recent && !at_end {
+                return;
+            }
+            self.last_sent = Some(Instant::now());
+            self.send(ProgressEvent::Progress {
+                done,
+                total: self.total,
+            });
+        }
+
+        pub fn complete(mut self, message: Option<String>) {
+            self.finished = true;
+            self.send(ProgressEvent::Completed { message });
+        }
+
+        pub fn fail(mut self, error: impl Into<String>) {
+            self.finished = true;
+            self.send(ProgressEvent::Failed {
+                error: error.into(),
+            });
+        }
+    }
+
+    impl Drop for Reporter {
+        fn drop(&mut self) {
+            if !self.finished {
+                self.send(ProgressEvent::Failed {
+                    error: "stopped before it finished".to_string(),
+                });
+            }
+        }
+    }
+}
+
+/// A socket with the frames for the caller's uploads, and for jobs and
+/// tasks if it's an admin, from where it left off.
+#[server(
+    protocol = Websocket<JsonEncoding, JsonEncoding>,
+    client = KeepaliveClient
+)]
+pub async fn progress_socket(
+    requests: BoxedStream<SocketRequest, ServerFnError>,
+) -> Result<
+    BoxedStream<SocketMessage<ProgressFrame>, ServerFnError>,
+    ServerFnError,
+> {
+    use crate::channels::{serve_socket, Sequenced};
+    use futures::StreamExt;
+
+    // visitors without a sandbox only ever get pongs, unless they're admins
+    let visible = visible_to_caller();
+    let messages = serve_socket(requests, move |after| {
+        PROGRESS.subscribe_from(after).filter_map(move |envelope| {
+            let Sequenced { seq, value } = envelope;
+            let frame = visible(value).map(|value| Sequenced { seq, value });
+            futures::future::ready(frame)
+        })
+    });
+    Ok(messages.map(Ok).into())
+}
+
+/// The frames after `cursor` that [`progress_socket`] would send, for
+/// clients whose streams don't get through; only those about `id`, if
+/// given. Waits up to [`LONG_POLL_WAIT`](crate::channels::LONG_POLL_WAIT)
+/// for one to come along.
+#[server]
+pub async fn poll_progress(
+    id: Option<ProgressId>,
+    cursor: Option<u64>,
+) -> Result<Polled<ProgressFrame>, ServerFnError> {
+    use crate::channels::LONG_POLL_WAIT;
+
+    let visible = visible_to_caller();
+    let polled = PROGRESS
+        .poll_from(cursor, LONG_POLL_WAIT, move |envelope| {
+            let frame = visible(envelope)?;
+            id.is_none_or(|id| frame.id == id).then_some(frame)
+        })
+        .await;
+    Ok(polled)
+}
+
+/// Unwraps the frames meant for whoever is making the current request.
+#[cfg(feature = "ssr")]
+fn visible_to_caller(
+) -> impl Fn(Envelope) -> Option<ProgressFrame> + Send + 'static {
+    use crate::{auth::require_admin, sandbox::current_owner};
+
+    let owner = current_owner();
+    let admin = require_admin().is_ok();
+    move |envelope| {
+        let wanted = match envelope.audience {
+            Audience::Owner(id) => owner == Some(id),
+            Audience::Admins => admin,
+        };
+        wanted.then_some(envelope.frame)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ItemState {
+    Running,
+    Completed(Option<String>),
+    Failed(String),
+}
+
+/// Where one piece of work has got to, as far as its frames tell.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressItem {
+    pub id: ProgressId,
+    pub label: String,
+    pub done: u64,
+    pub total: Option<u64>,
+    pub state: ItemState,
+}
+
+/// Updates `items` with `frame`, newest work first, keeping at most
+/// [`MAX_PROGRESS_ITEMS`].
+pub fn apply(items: &mut Vec<ProgressItem>, frame: ProgressFrame) {
+    let index = match items.iter().position(|item| item.id == frame.id) {
+        Some(index) => index,
+        None => {
+            // a frame whose `Started` came before the subscription
+            items.insert(
+                0,
+                ProgressItem {
+                    id: frame.id,
+                    label: format!("{} #{}", frame.kind, frame.id.0),
+                    done: 0,
+                    total: None,
+                    state: ItemState::Running,
+                },
+            );
+            0
+        }
+    };
+    let item = &mut items[index];
+    match frame.event {
+        ProgressEvent::Started { label, total } => {
+            item.label = label;
+            item.total = total;
+        }
+        ProgressEvent::Progress { done, total } => {
+            item.done = done;
+            item.total = total;
+        }
+        ProgressEvent::Completed { message } => {
+            if let Some(total) = item.total {
+                item.done = total;
+            }
+            item.state = ItemState::Completed(message);
+        }
+        ProgressEvent::Failed { error } => {
+            item.state = ItemState::Failed(error)
+        }
+    }
+    while items.len() > MAX_PROGRESS_ITEMS {
+        let oldest_finished = items
+            .iter()
+            .rposition(|item| item.state != ItemState::Running);
+        items.remove(oldest_finished.unwrap_or(items.len() - 1));
+    }
+}
+
+/// Lists the caller's work of `kind` as its frames arrive, from when the
+/// component was mounted.
+#[component]
+pub fn ProgressStream(kind: ProgressKind) -> impl IntoView {
+    let (items, set_items) = signal(Vec::<ProgressItem>::new());
+    let (connection, set_connection) = signal(ConnectionState::Connecting);
+
+    Effect::new(move |_| {
+        spawn_local(supervise_socket_or_poll(
+            progress_socket,
+            |cursor| poll_progress(None, cursor),
+            set_connection,
+            move |frame: ProgressFrame| {
+                if frame.kind == kind {
+                    set_items.update(|items| apply(items, frame));
+                }
+                ControlFlow::Continue(())
+            },
+        ));
+    });
+
+    view! {
+        <ul class="progress-stream">
+            {move || {
+                items
+                    .get()
+                    .into_iter()
+                    .map(|item| view! { <ProgressRow item /> })
+                    .collect::<Vec<_>>()
+            }}
+        </ul>
+        <p>"Progress stream: " <ConnectionBadge state=connection /></p>
+    }
+}
+
+#[component]
+fn ProgressRow(item: ProgressItem) -> impl IntoView {
+    let status = match &item.state {
+        ItemState::Running => match item.total {
+            Some(total) => format!("{} of {total}", item.done),
+            None if item.done > 0 => format!("{} so far", item.done),
+            None => "running".to_string(),
+        },
+        ItemState::Completed(Some(message)) => message.clone(),
+        ItemState::Completed(None) => "done".to_string(),
+        ItemState::Failed(error) => format!("failed: {error}"),
+    };
+
+    view! {
+        <li>
+            {item.label} " "
+            {item
+                .total
+                .map(|total| {
+                    view! { <progress max=total value=item.done></progress> }
+                })} " " {status}
+        </li>
+    }
+}
@@ -0,0 +1,202 @@
+use crate::errors::ProxyError;
+use futures::StreamExt;
+use leptos::{prelude::*, task::spawn_local};
+use serde::{Deserialize, Serialize};
+use server_fn::codec::{ByteStream, GetUrl, Streaming};
+use strum::Display;
+
+/// The external APIs [`proxy_get`] can reach. Anything else can't be
+/// proxied, so the server can't be used to reach arbitrary hosts.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display,
+)]
+pub enum ExternalApi {
+    /// Open-Meteo's forecast API, which needs no key.
+    #[strum(serialize = "the weather API")]
+    Weather,
+}
+
+impl ExternalApi {
+    pub fn base_url(self) -> &'static str {
+        match self {
+            ExternalApi::Weather => "https://api.open-meteo.com/v1/forecast",
+        }
+    }
+
+    /// The query parameters passed on; a call with any other is refused.
+    pub fn allowed_params(self) -> &'static [&'static str] {
+        match self {
+            ExternalApi::Weather => &[
+                "latitude",
+                "longitude",
+                "current",
+                "hourly",
+                "daily",
+                "timezone",
+                "forecast_days",
+            ],
+        }
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::ExternalApi;
+    use crate::errors::ProxyError;
+    use reqwest::{Client, Url};
+    use std::{sync::LazyLock, time::Duration};
+
+    /// The most a proxied response may send before it's cut off.
+    pub const MAX_PROXIED_BYTES: usize = 4 * 1024 * 1024;
+
+    pub static CLIENT: LazyLock<Client> = LazyLock::new(|| {
+        Client::builder()
+            .connect_timeout(Duration::from_secs(5))
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("server_fns_axum/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("couldn't build the proxy's HTTP client")
+    });
+
+    impl ExternalApi {
+        /// The upstream URL for `query`, if it only uses allowed parameters.
+        pub fn url(self, query: &str) -> Result<Url, ProxyError> {
+            let mut url =
+                Url::parse(self.base_url()).expect("base URLs are valid");
+            url.set_query(Some(query).filter(|query| !query.is_empty()));
+            let allowed = self.allowed_params();
+            if let Some((name, _)) = url
+                .query_pairs()
+                .find(|(name, _)| !allowed.contains(&name.as_ref()))
+            {
+                return Err(ProxyError::ParamNotAllowed {
+                    api: self.to_string(),
+                    name: name.into_owned(),
+                });
+            }
+            Ok(url)
+        }
+
+        pub fn request_error(self, e: reqwest::Error) -> ProxyError {
+            if e.is_timeout() {
+                ProxyError::TimedOut {
+                    api: self.to_string(),
+                }
+            } else {
+                ProxyError::Unreachable {
+                    api: self.to_string(),
+                    message: e.to_string(),
+                }
+            }
+        }
+    }
+}
+
+/// Passes a GET with `query` on to `api`, and streams the response back as
+/// it arrives rather than buffering it. The upstream `Content-Type` is kept.
+#[server(input = GetUrl, output = Streaming, endpoint = "proxy_get")]
+pub async fn proxy_get(
+    api: ExternalApi,
+    query: String,
+) -> Result<ByteStream<ProxyError>, ProxyError> {
+    use http::header::CONTENT_TYPE;
+    use leptos_axum::ResponseOptions;
+
+    let res = CLIENT
+        .get(api.url(&query)?)
+        .send()
+        .await
+        .map_err(|e| api.request_error(e))?;
+    if !res.status().is_success() {
+        return Err(ProxyError::Upstream {
+            api: api.to_string(),
+            status: res.status().as_u16(),
+        });
+    }
+    if let Some(content_type) = res.headers().get(CONTENT_TYPE) {
+        expect_context::<ResponseOptions>()
+            .insert_header(CONTENT_TYPE, content_type.clone());
+    }
+
+    let mut read = 0;
+    let body = res.bytes_stream().map(move |chunk| {
+        let chunk = chunk.map_err(|e| ProxyError::Interrupted {
+            api: api.to_string(),
+            message: e.to_string(),
+        })?;
+        read += chunk.len();
+        if read > MAX_PROXIED_BYTES {
+            return Err(ProxyError::TooLarge {
+                api: api.to_string(),
+                limit: MAX_PROXIED_BYTES,
+            });
+        }
+        Ok(chunk)
+    });
+    Ok(ByteStream::new(body))
+}
+
+/// Fetches the current weather through [`proxy_get`], showing the bytes as
+/// they arrive.
+#[component]
+pub fn ProxyExample() -> impl IntoView {
+    let (query, set_query) = signal(
+        "latitude=52.52&longitude=13.41&current=temperature_2m,wind_speed_10m"
+            .to_string(),
+    );
+    let (received, set_received) = signal(None::<usize>);
+    let (reply, set_reply) = signal(None::<Result<String, ProxyError>>);
+
+    let fetch = move |_| {
+        let query = query.get_untracked();
+        set_received.set(Some(0));
+        set_reply.set(None);
+        spawn_local(async move {
+            let result = async {
+                let mut chunks =
+                    proxy_get(ExternalApi::Weather, query).await?.into_inner();
+                let mut body = Vec::new();
+                while let Some(chunk) = chunks.next().await {
+                    body.extend_from_slice(&chunk?);
+                    set_received.set(Some(body.len()));
+                }
+                Ok(String::from_utf8_lossy(&body).into_owned())
+            };
+            set_reply.set(Some(result.await));
+        });
+    };
+
+    view! {
+        <h3>"Proxying an external API"</h3>
+        <p>
+            "The server passes the call on to Open-Meteo and streams the answer back through a "
+            <code>"ByteStream"</code>
+            ". Only the query parameters the weather API allows are passed on; try adding another."
+        </p>
+        <input
+            size=70
+            prop:value=query
+            on:input=move |ev| set_query.set(event_target_value(&ev))
+        />
+        <button on:click=fetch>"Fetch"</button>
+        <ShowLet some=received let:received>
+            <span>{format!(" {received} bytes")}</span>
+        </ShowLet>
+        <ShowLet some=reply let:reply>
+            {match reply {
+                Ok(body) => {
+                    view! {
+                        <pre>
+                            <code>{body}</code>
+                        </pre>
+                    }
+                        .into_any()
+                }
+                Err(e) => view! { <p>{e.to_string()}</p> }.into_any(),
+            }}
+        </ShowLet>
+    }
+}
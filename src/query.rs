@@ -0,0 +1,260 @@
+//! Flat, human-readable query strings, for
+//! [`QueryUrl`](crate::codec::QueryUrl).
+//!
+//! Every field becomes one `name=value` parameter, under its serde name,
+//! and sequences become the same parameter repeated: `q=milk&tag=a&tag=b`.
+//! `None` and empty sequences are left out, so sequence fields that may be
+//! empty need `#[serde(default)]` (`#[server(default)]` on a server fn
+//! argument). Parameters are sorted by name, so equal arguments always give
+//! the same URL. Nested structs and maps aren't supported.
+
+use serde::{
+    de::{
+        self,
+        value::{Error, MapDeserializer, SeqDeserializer, StringDeserializer},
+        DeserializeOwned, Deserializer, IntoDeserializer, Visitor,
+    },
+    forward_to_deserialize_any, Serialize,
+};
+use serde_json::Value as Json;
+
+/// Encodes `value`, which must serialize as a struct or map, as a query
+/// string (without the leading `?`).
+pub fn to_query_string(value: &impl Serialize) -> Result<String, String> {
+    let Json::Object(fields) =
+        serde_json::to_value(value).map_err(|e| e.to_string())?
+    else {
+        return Err("query parameters must be a struct or map".to_string());
+    };
+    let mut pairs = Vec::new();
+    for (name, value) in fields {
+        match value {
+            Json::Array(items) => {
+                for item in items {
+                    pairs.push((name.clone(), scalar(&name, item)?));
+                }
+            }
+            Json::Null => {}
+            value => pairs.push((name.clone(), scalar(&name, value)?)),
+        }
+    }
+    serde_urlencoded::to_string(pairs).map_err(|e| e.to_string())
+}
+
+fn scalar(name: &str, value: Json) -> Result<String, String> {
+    match value {
+        Json::String(s) => Ok(s),
+        Json::Bool(b) => Ok(b.to_string()),
+        Json::Number(n) => Ok(n.to_string()),
+        _ => Err(format!(
+            "`{name}` must be a scalar or a sequence of scalars to be a query \
+             parameter"
+        )),
+    }
+}
+
+/// Decodes a query string made by [`to_query_string`], or by hand.
+pub fn from_query_string<T: DeserializeOwned>(
+    query: &str,
+) -> Result<T, String> {
+    let pairs = serde_urlencoded::from_str::<Vec<(String, String)>>(query)
+        .map_err(|e| e.to_string())?;
+    // repeated parameters are gathered up, in the order they first appear
+    let mut params = Vec::<(String, Values)>::new();
+    for (name, value) in pairs {
+        match params.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, values)) => values.0.push(value),
+            None => params.push((name, Values(vec![value]))),
+        }
+    }
+    T::deserialize(MapDeserializer::<_, Error>::new(params.into_iter()))
+        .map_err(|e| e.to_string())
+}
+
+/// Every value of one parameter.
+struct Values(Vec<String>);
+
+impl<'de> IntoDeserializer<'de, Error> for Values {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl Values {
+    /// The value a scalar field gets: the last one, like most servers do.
+    fn last(mut self) -> Param {
+        Param(self.0.pop().unwrap_or_default())
+    }
+}
+
+macro_rules! forward_to_last {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(
+                self,
+                visitor: V,
+            ) -> Result<V::Value, Error> {
+                self.last().$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Values {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        if self.0.len() == 1 {
+            self.last().deserialize_any(visitor)
+        } else {
+            self.deserialize_seq(visitor)
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_seq(SeqDeserializer::new(self.0.into_iter().map(Param)))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.last().deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    forward_to_last! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32
+        deserialize_i64 deserialize_u8 deserialize_u16 deserialize_u32
+        deserialize_u64 deserialize_f32 deserialize_f64 deserialize_char
+        deserialize_str deserialize_string deserialize_unit
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 bytes byte_buf unit_struct tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+/// One value, parsed as whatever type is asked for.
+struct Param(String);
+
+impl<'de> IntoDeserializer<'de, Error> for Param {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! parse_as {
+    ($($method:ident => $visit:ident),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(
+                self,
+                visitor: V,
+            ) -> Result<V::Value, Error> {
+                let value = self.0.parse().map_err(|_| {
+                    let unexpected = de::Unexpected::Str(&self.0);
+                    de::Error::invalid_value(unexpected, &visitor)
+                })?;
+                visitor.$visit(value)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Param {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_string(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        StringDeserializer::<Error>::new(self.0)
+            .deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    parse_as! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
@@ -0,0 +1,209 @@
+use crate::admin::Meter;
+#[cfg(feature = "ssr")]
+use crate::sandbox::current_owner;
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use strum::Display;
+
+/// How often [`UsageMeter`] checks for new usage.
+const USAGE_REFRESH: Duration = Duration::from_secs(30);
+
+/// Something a user may only do so much of each day (in UTC).
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display,
+)]
+pub enum QuotaKind {
+    #[strum(serialize = "rows added")]
+    Rows,
+    #[strum(serialize = "bytes uploaded")]
+    UploadBytes,
+}
+
+/// How much of each daily quota a user has used today.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaUsage {
+    pub rows: u64,
+    pub max_rows: u64,
+    pub upload_bytes: u64,
+    pub max_upload_bytes: u64,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{QuotaKind, QuotaUsage};
+    use crate::{auth::UserId, errors::QuotaExceeded, settings::QuotaSettings};
+    use chrono::{NaiveDate, Utc};
+    use dashmap::DashMap;
+    use std::sync::{LazyLock, OnceLock};
+
+    /// What every user has used of their quotas today.
+    pub static QUOTAS: LazyLock<QuotaTracker> =
+        LazyLock::new(QuotaTracker::default);
+
+    /// The limits from [`QuotaSettings`].
+    static LIMITS: OnceLock<QuotaSettings> = OnceLock::new();
+
+    /// Applies the `[quotas]` settings; call it once, before serving.
+    pub fn init(settings: &QuotaSettings) {
+        _ = LIMITS.set(settings.clone());
+    }
+
+    fn limit(kind: QuotaKind) -> u64 {
+        let limits = LIMITS.get_or_init(QuotaSettings::default);
+        match kind {
+            QuotaKind::Rows => limits.rows_per_day,
+            QuotaKind::UploadBytes => limits.upload_bytes_per_day,
+        }
+    }
+
+    #[derive(Debug, Default)]
+    pub struct QuotaTracker {
+        usage: DashMap<UserId, DailyUsage>,
+    }
+
+    /// One user's usage on `day`; a later day starts over from zero.
+    #[derive(Debug, Default)]
+    struct DailyUsage {
+        day: NaiveDate,
+        rows: u64,
+        upload_bytes: u64,
+    }
+
+    impl DailyUsage {
+        fn used(&mut self, kind: QuotaKind) -> &mut u64 {
+            match kind {
+                QuotaKind::Rows => &mut self.rows,
+                QuotaKind::UploadBytes => &mut self.upload_bytes,
+            }
+        }
+    }
+
+    impl QuotaTracker {
+        /// Runs `f` on `user`'s usage for today.
+        fn with_usage<T>(
+            &self,
+            user: UserId,
+            f: impl FnOnce(&mut DailyUsage) -> T,
+        ) -> T {
+            let today = Utc::now().date_naive();
+            let mut usage = self.usage.entry(user).or_default();
+            if usage.day != today {
+                *usage = DailyUsage {
+                    day: today,
+                    ..DailyUsage::default()
+                };
+            }
+            f(&mut usage)
+        }
+
+        /// Uses up `amount` of `user`'s `kind` quota, or none of it if that
+        /// would go over the limit.
+        pub fn consume(
+            &self,
+            user: UserId,
+            kind: QuotaKind,
+            amount: u64,
+        ) -> Result<(), QuotaExceeded> {
+            let limit = limit(kind);
+            self.with_usage(user, |usage| {
+                let used = usage.used(kind);
+                if used.saturating_add(amount) > limit {
+                    return Err(QuotaExceeded { kind, limit });
+                }
+                *used += amount;
+                Ok(())
+            })
+        }
+
+        /// Gives back what [`consume`](Self::consume) took for work that
+        /// didn't happen after all.
+        pub fn refund(&self, user: UserId, kind: QuotaKind, amount: u64) {
+            self.with_usage(user, |usage| {
+                let used = usage.used(kind);
+                *used = used.saturating_sub(amount);
+            });
+        }
+
+        pub fn usage(&self, user: UserId) -> QuotaUsage {
+            self.with_usage(user, |usage| QuotaUsage {
+                rows: usage.rows,
+                max_rows: limit(QuotaKind::Rows),
+                upload_bytes: usage.upload_bytes,
+                max_upload_bytes: limit(QuotaKind::UploadBytes),
+            })
+        }
+    }
+}
+
+/// The caller's usage of their daily quotas, or `None` for visitors who
+/// haven't started a sandbox.
+#[server]
+pub async fn quota_usage() -> Result<Option<QuotaUsage>, ServerFnError> {
+    Ok(current_owner().map(|owner| QUOTAS.usage(owner)))
+}
+
+/// How much of their daily quotas the caller has left, kept roughly
+/// up to date.
+#[component]
+pub fn UsageMeter() -> impl IntoView {
+    let (tick, set_tick) = signal(0_usize);
+    let usage = Resource::new(move || tick.get(), |_| quota_usage());
+
+    Effect::new(move |_| {
+        if let Ok(handle) = set_interval_with_handle(
+            move || set_tick.update(|tick| *tick += 1),
+            USAGE_REFRESH,
+        ) {
+            on_cleanup(move || handle.clear());
+        }
+    });
+    let fraction = |used: u64, max: u64| {
+        if max == 0 {
+            1.0
+        } else {
+            used as f64 / max as f64
+        }
+    };
+
+    view! {
+        <Transition>
+            {move || Suspend::new(async move {
+                usage
+                    .await
+                    .ok()
+                    .flatten()
+                    .map(|usage| {
+                        view! {
+                            <table class="usage">
+                                <tr>
+                                    <th>"Rows added today"</th>
+                                    <td>
+                                        <Meter fraction=fraction(usage.rows, usage.max_rows) />
+                                        {format!(" {} of {}", usage.rows, usage.max_rows)}
+                                    </td>
+                                </tr>
+                                <tr>
+                                    <th>"Uploaded today"</th>
+                                    <td>
+                                        <Meter fraction=fraction(
+                                            usage.upload_bytes,
+                                            usage.max_upload_bytes,
+                                        ) />
+                                        {format!(
+                                            " {} of {} KiB",
+                                            usage.upload_bytes.div_ceil(1024),
+                                            usage.max_upload_bytes / 1024,
+                                        )}
+                                    </td>
+                                </tr>
+                            </table>
+                        }
+                    })
+            })}
+        </Transition>
+    }
+}
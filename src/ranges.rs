@@ -0,0 +1,98 @@
+//! Conditional and `Range` requests for downloads, so browsers can resume
+//! them and seek through media. Only a single range of bytes is served; a
+//! request for several gets the whole file, which HTTP allows.
+
+use http::{
+    header::{IF_NONE_MATCH, IF_RANGE, RANGE},
+    HeaderMap,
+};
+use std::ops::Range;
+
+/// How to answer a download request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeAnswer {
+    /// 304: the copy the client named in `If-None-Match` is current.
+    NotModified,
+    /// 200, with the whole file.
+    Full,
+    /// 206, with these bytes of it.
+    Partial(Range<u64>),
+    /// 416: the range starts past the end of the file.
+    Unsatisfiable,
+}
+
+/// How to answer a request with `headers` for a file of `size` bytes,
+/// whose strong ETag is `etag`.
+pub fn answer(headers: &HeaderMap, etag: &str, size: u64) -> RangeAnswer {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let cached = header(IF_NONE_MATCH).is_some_and(|tags| {
+        tags.split(',').map(str::trim).any(|tag| {
+            tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag
+        })
+    });
+    if cached {
+        return RangeAnswer::NotModified;
+    }
+    // a range of some other version of the file would be spliced into
+    // the wrong bytes; dates never match, since files have none
+    if header(IF_RANGE).is_some_and(|tag| tag != etag) {
+        return RangeAnswer::Full;
+    }
+    match header(RANGE).and_then(parse) {
+        Some(spec) => spec.resolve(size),
+        None => RangeAnswer::Full,
+    }
+}
+
+/// The value of a `Content-Range` header for `range` of a file of `size`
+/// bytes.
+pub fn content_range(range: &Range<u64>, size: u64) -> String {
+    format!("bytes {}-{}/{size}", range.start, range.end - 1)
+}
+
+/// One range from a `Range` header, before the file's size is known.
+enum Spec {
+    /// `first-last`, both included.
+    Bounded(u64, u64),
+    /// `first-`
+    From(u64),
+    /// `-length`
+    Last(u64),
+}
+
+/// `None` for anything but a single, well-formed range of bytes, which is
+/// then ignored.
+fn parse(header: &str) -> Option<Spec> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+    match (first.is_empty(), last.is_empty()) {
+        (false, false) => {
+            let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+            (first <= last).then_some(Spec::Bounded(first, last))
+        }
+        (false, true) => Some(Spec::From(first.parse().ok()?)),
+        (true, false) => Some(Spec::Last(last.parse().ok()?)),
+        (true, true) => None,
+    }
+}
+
+impl Spec {
+    fn resolve(self, size: u64) -> RangeAnswer {
+        let range = match self {
+            Spec::Bounded(first, last) => {
+                first..size.min(last.saturating_add(1))
+            }
+            Spec::From(first) => first..size,
+            Spec::Last(length) => size.saturating_sub(length)..size,
+        };
+        if range.is_empty() {
+            RangeAnswer::Unsatisfiable
+        } else {
+            RangeAnswer::Partial(range)
+        }
+    }
+}
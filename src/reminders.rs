@@ -0,0 +1,138 @@
+use crate::{
+    auth::UserId,
+    channels::Tick,
+    codec::{Framed, FramedStream},
+    supervisor::{supervise, ConnectionState},
+};
+#[cfg(feature = "ssr")]
+use crate::{
+    channels::{with_heartbeat, HEARTBEAT_INTERVAL},
+    sandbox::current_owner,
+};
+use chrono::NaiveDate;
+#[cfg(feature = "ssr")]
+use futures::StreamExt;
+use leptos::{prelude::*, task::spawn_local};
+use serde::{Deserialize, Serialize};
+use server_fn::codec::GetUrl;
+use std::ops::ControlFlow;
+
+/// Sent when an open row comes due.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reminder {
+    /// Who the row belongs to, and the only one it is sent to.
+    pub user_id: UserId,
+    pub row_id: u64,
+    pub text: String,
+    pub due: NaiveDate,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::Reminder;
+    use crate::{auth::UserId, channels::DropOldest, mail, storage::ROWS};
+    use chrono::{Local, NaiveDate};
+    use std::{collections::HashSet, sync::LazyLock, time::Duration};
+
+    /// How often [`run_scheduler`] looks for rows that have come due.
+    pub const REMINDER_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// Reminders for every user; a reconnecting client catches up on its
+    /// own among the last 64.
+    pub static REMINDERS: LazyLock<DropOldest<Reminder>> =
+        LazyLock::new(|| DropOldest::new("reminders", 64));
+
+    /// Sends a [`Reminder`] on [`REMINDERS`], and by email, for every open
+    /// row the first time it is seen due. Runs until the server shuts down.
+    pub async fn run_scheduler() {
+        // keyed by due date too, so moving a row's date re-arms its reminder
+        let mut sent = HashSet::<(UserId, u64, NaiveDate)>::new();
+        let mut interval = tokio::time::interval(REMINDER_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let today = Local::now().date_naive();
+            let due = ROWS
+                .due_by(today)
+                .into_iter()
+                .filter_map(|(user_id, row)| {
+                    Some(((user_id, row.id, row.due?), row.text))
+                })
+                .collect::<Vec<_>>();
+            // forget rows that were completed, undated or deleted, so the
+            // set only ever holds what is currently due
+            sent.retain(|key| due.iter().any(|(due, _)| due == key));
+            for ((user_id, row_id, due), text) in due {
+                if sent.insert((user_id, row_id, due)) {
+                    tracing::info!(user_id, row_id, %due, "row came due");
+                    let reminder = Reminder {
+                        user_id,
+                        row_id,
+                        text,
+                        due,
+                    };
+                    tokio::spawn(mail::send_reminder(reminder.clone()));
+                    REMINDERS.send(reminder);
+                }
+            }
+        }
+    }
+}
+
+#[server(input = GetUrl, output = Framed)]
+pub async fn reminder_events(
+    after: Option<u64>,
+) -> Result<FramedStream<Tick<Reminder>>, ServerFnError> {
+    // visitors without a sandbox only ever get heartbeats
+    let owner = current_owner();
+    let reminders = REMINDERS.subscribe_from(after).filter(move |reminder| {
+        futures::future::ready(Some(reminder.value.user_id) == owner)
+    });
+    let ticks = with_heartbeat(reminders, HEARTBEAT_INTERVAL);
+    Ok(FramedStream::new(ticks.map(Ok)))
+}
+
+#[component]
+pub fn Reminders() -> impl IntoView {
+    let (reminders, set_reminders) = signal(Vec::<Reminder>::new());
+    let (connection, set_connection) = signal(ConnectionState::Connecting);
+
+    Effect::new(move |_| {
+        spawn_local(supervise(
+            reminder_events,
+            set_connection,
+            move |reminder| {
+                set_reminders.update(|reminders| reminders.push(reminder));
+                ControlFlow::Continue(())
+            },
+        ));
+    });
+
+    view! {
+        <h3>"Reminders"</h3>
+        <p>
+            "A scheduled task on the server checks for open rows that have come due "
+            "and announces each one to its owner's connected clients."
+        </p>
+        <ul>
+            {move || {
+                reminders
+                    .get()
+                    .into_iter()
+                    .rev()
+                    .map(|reminder| {
+                        view! {
+                            <li>
+                                {format!("Due {}: ", reminder.due)}
+                                {reminder.text}
+                            </li>
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            }}
+        </ul>
+        <p>"Reminder stream: " {move || connection.get().to_string()}</p>
+    }
+}
@@ -0,0 +1,262 @@
+//! Read replicas of the row store: copies of [`ROWS`](crate::storage::ROWS)
+//! that row counts, lists and searches can be served from, while every
+//! write goes to the primary.
+//!
+//! Each replica copies the whole primary every replication interval. A read
+//! goes to a replica only if it is up, its copy is recent enough for the
+//! caller's [`Staleness`], and the copy was taken after the caller's own
+//! last change, so nobody ever reads their rows from before a write of
+//! theirs. Otherwise, including when every replica is down, it goes to the
+//! primary.
+
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How stale a read may be, chosen per call.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum Staleness {
+    /// A replica will do, if it copied the primary within the configured
+    /// `max_staleness_ms`.
+    #[default]
+    Tolerated,
+    /// Only the primary will do.
+    Fresh,
+}
+
+/// How one replica is doing, for the admin page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplicaHealth {
+    pub index: usize,
+    /// Whether an admin has left it running.
+    pub up: bool,
+    /// Whether it is up and recent enough to serve reads.
+    pub healthy: bool,
+    /// How long ago it last copied the primary.
+    pub lag_ms: Option<u64>,
+    /// Reads it has served.
+    pub reads: u64,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{ReplicaHealth, Staleness};
+    use crate::{
+        auth::UserId,
+        settings::ReplicaSettings,
+        storage::{RowStore, ROWS},
+    };
+    use std::{
+        sync::{
+            atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+            Mutex, OnceLock,
+        },
+        time::{Duration, Instant},
+    };
+
+    static REPLICAS: OnceLock<Replicas> = OnceLock::new();
+
+    struct Replicas {
+        replicas: Vec<Replica>,
+        interval: Duration,
+        max_staleness: Duration,
+        /// Where the next read starts looking, so reads are spread out.
+        next: AtomicUsize,
+    }
+
+    #[derive(Default)]
+    struct Replica {
+        store: RowStore,
+        up: AtomicBool,
+        copied_at: Mutex<Option<Instant>>,
+        reads: AtomicU64,
+    }
+
+    impl Replica {
+        /// Whether it can serve a read that tolerates staleness.
+        fn healthy(&self, max_staleness: Duration) -> bool {
+            self.up.load(Ordering::Relaxed)
+                && self
+                    .copied_at()
+                    .is_some_and(|at| at.elapsed() <= max_staleness)
+        }
+
+        fn copied_at(&self) -> Option<Instant> {
+            *self.copied_at.lock().unwrap()
+        }
+    }
+
+    /// Applies the `[replicas]` settings; call it once, before serving.
+    pub fn init(settings: &ReplicaSettings) {
+        let replicas = (0..settings.count)
+            .map(|_| Replica {
+                up: AtomicBool::new(true),
+                ..Replica::default()
+            })
+            .collect();
+        _ = REPLICAS.set(Replicas {
+            replicas,
+            interval: Duration::from_millis(settings.replication_interval_ms),
+            max_staleness: Duration::from_millis(settings.max_staleness_ms),
+            next: AtomicUsize::new(0),
+        });
+    }
+
+    /// Has every replica that is up copy the primary each replication
+    /// interval, until the server shuts down. Returns straight away without
+    /// any replicas.
+    pub async fn replicate() {
+        let Some(replicas) = REPLICAS.get().filter(|r| !r.replicas.is_empty())
+        else {
+            return;
+        };
+        let mut interval = tokio::time::interval(replicas.interval);
+        loop {
+            interval.tick().await;
+            for replica in &replicas.replicas {
+                if replica.up.load(Ordering::Relaxed) {
+                    let copied_at = replica.store.copy_from(&ROWS);
+                    *replica.copied_at.lock().unwrap() = Some(copied_at);
+                }
+            }
+        }
+    }
+
+    /// Where to read `owner`'s rows from: the next healthy replica that has
+    /// `owner`'s last change, unless `staleness` rules replicas out, and the
+    /// primary otherwise.
+    pub fn reader(owner: UserId, staleness: Staleness) -> &'static RowStore {
+        let Some(replicas) = REPLICAS.get() else {
+            return &ROWS;
+        };
+        if staleness == Staleness::Fresh || replicas.replicas.is_empty() {
+            return &ROWS;
+        }
+        let written_at = ROWS.written_at(owner);
+        let start = replicas.next.fetch_add(1, Ordering::Relaxed);
+        let count = replicas.replicas.len();
+        (0..count)
+            .map(|n| &replicas.replicas[(start + n) % count])
+            .find(|replica| {
+                replica.healthy(replicas.max_staleness)
+                    && written_at.is_none_or(|written_at| {
+                        replica.copied_at().is_some_and(|at| at > written_at)
+                    })
+            })
+            .map_or(&ROWS, |replica| {
+                replica.reads.fetch_add(1, Ordering::Relaxed);
+                &replica.store
+            })
+    }
+
+    pub fn health() -> Vec<ReplicaHealth> {
+        let Some(replicas) = REPLICAS.get() else {
+            return Vec::new();
+        };
+        replicas
+            .replicas
+            .iter()
+            .enumerate()
+            .map(|(index, replica)| ReplicaHealth {
+                index,
+                up: replica.up.load(Ordering::Relaxed),
+                healthy: replica.healthy(replicas.max_staleness),
+                lag_ms: replica
+                    .copied_at()
+                    .map(|at| at.elapsed().as_millis() as u64),
+                reads: replica.reads.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Starts or stops replica `index`. A stopped replica stops copying
+    /// the primary, so it soon falls too far behind to serve reads.
+    /// Returns `false` if there is no such replica.
+    pub fn set_up(index: usize, up: bool) -> bool {
+        let replica = REPLICAS
+            .get()
+            .and_then(|replicas| replicas.replicas.get(index));
+        if let Some(replica) = replica {
+            replica.up.store(up, Ordering::Relaxed);
+        }
+        replica.is_some()
+    }
+}
+
+#[server(input = server_fn::codec::GetUrl)]
+pub async fn replica_health() -> Result<Vec<ReplicaHealth>, ServerFnError> {
+    crate::auth::require_admin()?;
+    Ok(health())
+}
+
+/// Takes a replica down or brings it back, to watch reads fail over.
+#[server]
+#[middleware(crate::middleware::TwoFactorLayer)]
+pub async fn set_replica_up(
+    index: usize,
+    up: bool,
+) -> Result<(), ServerFnError> {
+    crate::auth::require_admin()?;
+    if !set_up(index, up) {
+        return Err(ServerFnError::new(format!("there is no replica {index}")));
+    }
+    Ok(())
+}
+
+/// The replicas and how they are doing, with a button to take each one
+/// down or bring it back.
+#[component]
+pub fn ReplicaPanel() -> impl IntoView {
+    let set_up = ServerAction::<SetReplicaUp>::new();
+    let replicas =
+        Resource::new(move || set_up.version().get(), |_| replica_health());
+
+    view! {
+        <h3>"Read replicas"</h3>
+        <Transition>
+            {move || Suspend::new(async move {
+                replicas
+                    .await
+                    .map(|replicas| {
+                        if replicas.is_empty() {
+                            return view! {
+                                <p>"No replicas; every read goes to the primary."</p>
+                            }
+                                .into_any();
+                        }
+                        replicas
+                            .into_iter()
+                            .map(|replica| {
+                                let ReplicaHealth { index, up, .. } = replica;
+                                let state = match (up, replica.healthy) {
+                                    (false, _) => "down",
+                                    (true, true) => "healthy",
+                                    (true, false) => "behind",
+                                };
+                                let lag = replica
+                                    .lag_ms
+                                    .map(|ms| format!(", {ms}ms behind"))
+                                    .unwrap_or_default();
+                                view! {
+                                    <p>
+                                        {format!(
+                                            "Replica {index}: {state}{lag}, {} reads ",
+                                            replica.reads,
+                                        )}
+                                        <button on:click=move |_| {
+                                            set_up.dispatch(SetReplicaUp { index, up: !up });
+                                        }>{if up { "Take down" } else { "Bring back" }}</button>
+                                    </p>
+                                }
+                            })
+                            .collect::<Vec<_>>()
+                            .into_any()
+                    })
+            })}
+        </Transition>
+    }
+}
@@ -0,0 +1,375 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use strum::Display;
+
+/// How a server fn's calls are bounded and when its circuit breaker trips.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    /// Calls that take longer fail with a timeout, and count as failures.
+    pub timeout: Duration,
+    /// Calls over this many at once are turned away straight away.
+    pub max_concurrent: usize,
+    /// The breaker opens after this many failures within `failure_window`.
+    pub failure_threshold: usize,
+    pub failure_window: Duration,
+    /// How long the breaker stays open before letting one probe call
+    /// through.
+    pub open_for: Duration,
+}
+
+/// The state of a circuit breaker.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display,
+)]
+pub enum BreakerState {
+    /// Calls go through.
+    #[strum(serialize = "closed")]
+    Closed,
+    /// Calls are turned away without running.
+    #[strum(serialize = "open")]
+    Open,
+    /// The next call is a probe: if it works the breaker closes, and if not
+    /// it opens again.
+    #[strum(serialize = "half-open")]
+    HalfOpen,
+}
+
+/// A snapshot of one server fn's breaker.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BreakerStatus {
+    pub name: String,
+    pub state: BreakerState,
+    /// Failures within the policy's window.
+    pub recent_failures: usize,
+    pub in_flight: usize,
+    /// While open, how long until a probe is let through.
+    pub probe_in_ms: Option<u64>,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{BreakerState, BreakerStatus, Policy};
+    use crate::errors::{set_error_status, MiddlewareError};
+    use dashmap::DashMap;
+    use futures::future::{self, Either, Ready};
+    use http::{Request, Response};
+    use pin_project_lite::pin_project;
+    use std::{
+        collections::VecDeque,
+        future::Future,
+        pin::Pin,
+        sync::{Arc, LazyLock, Mutex},
+        task::{ready, Context, Poll},
+        time::Instant,
+    };
+    use tokio::{
+        sync::{OwnedSemaphorePermit, Semaphore},
+        time::Timeout,
+    };
+    use tower::{Layer, Service};
+
+    /// Every breaker, by the name it was created with.
+    static BREAKERS: LazyLock<DashMap<&'static str, Arc<Breaker>>> =
+        LazyLock::new(DashMap::default);
+
+    struct Breaker {
+        policy: Policy,
+        permits: Arc<Semaphore>,
+        circuit: Mutex<Circuit>,
+    }
+
+    #[derive(Default)]
+    struct Circuit {
+        failures: VecDeque<Instant>,
+        opened_at: Option<Instant>,
+        /// When the probe call in flight started, while half-open.
+        probe_started: Option<Instant>,
+    }
+
+    impl Breaker {
+        fn new(policy: Policy) -> Self {
+            Self {
+                policy,
+                permits: Arc::new(Semaphore::new(policy.max_concurrent)),
+                circuit: Mutex::default(),
+            }
+        }
+
+        /// Whether a call may run now, and if so the permit it holds while
+        /// it does.
+        fn admit(
+            self: &Arc<Self>,
+        ) -> Result<OwnedSemaphorePermit, MiddlewareError> {
+            let now = Instant::now();
+            let mut circuit = self.circuit.lock().unwrap();
+            if let Some(opened_at) = circuit.opened_at {
+                let open_until = opened_at + self.policy.open_for;
+                if now < open_until {
+                    return Err(MiddlewareError::CircuitOpen {
+                        retry_after_secs: (open_until - now).as_secs().max(1),
+                    });
+                }
+                // one probe at a time; a probe that never finished has
+                // timed out by now, so another may go
+                let probing = circuit
+                    .probe_started
+                    .is_some_and(|started| now - started < self.policy.timeout);
+                if probing {
+                    return Err(MiddlewareError::CircuitOpen {
+                        retry_after_secs: 1,
+                    });
+                }
+            }
+            let permit = Arc::clone(&self.permits)
+                .try_acquire_owned()
+                .map_err(|_| MiddlewareError::Overloaded {
+                    max_concurrent: self.policy.max_concurrent,
+                })?;
+            if circuit.opened_at.is_some() {
+                circuit.probe_started = Some(now);
+            }
+            Ok(permit)
+        }
+
+        fn record(&self, succeeded: bool) {
+            let now = Instant::now();
+            let mut circuit = self.circuit.lock().unwrap();
+            if succeeded {
+                if circuit.opened_at.is_some() {
+                    *circuit = Circuit::default();
+                }
+                return;
+            }
+            circuit.failures.push_back(now);
+            while circuit.failures.front().is_some_and(|failed| {
+                now - *failed > self.policy.failure_window
+            }) {
+                circuit.failures.pop_front();
+            }
+            if circuit.opened_at.is_some()
+                || circuit.failures.len() >= self.policy.failure_threshold
+            {
+                if circuit.opened_at.is_none() {
+                    tracing::warn!(
+                        failures = circuit.failures.len(),
+                        "circuit breaker opened"
+                    );
+                }
+                circuit.opened_at = Some(now);
+                circuit.probe_started = None;
+            }
+        }
+
+        fn status(&self, name: &str) -> BreakerStatus {
+            let now = Instant::now();
+            let circuit = self.circuit.lock().unwrap();
+            let recent_failures = circuit
+                .failures
+                .iter()
+                .filter(|failed| now - **failed <= self.policy.failure_window)
+                .count();
+            let open_until = circuit
+                .opened_at
+                .map(|opened_at| opened_at + self.policy.open_for);
+            let (state, probe_in_ms) = match open_until {
+                None => (BreakerState::Closed, None),
+                Some(until) if now < until => {
+                    (BreakerState::Open, Some((until - now).as_millis() as u64))
+                }
+                Some(_) => (BreakerState::HalfOpen, None),
+            };
+            BreakerStatus {
+                name: name.to_string(),
+                state,
+                recent_failures,
+                in_flight: self.policy.max_concurrent
+                    - self.permits.available_permits(),
+                probe_in_ms,
+            }
+        }
+    }
+
+    /// Every breaker's status, by name.
+    pub fn breaker_statuses() -> Vec<BreakerStatus> {
+        let mut statuses = BREAKERS
+            .iter()
+            .map(|entry| entry.status(entry.key()))
+            .collect::<Vec<_>>();
+        statuses.sort_by(|a, b| a.name.cmp(&b.name));
+        statuses
+    }
+
+    /// Applies a [`Policy`] to the server fn it's applied to: a timeout, a
+    /// limit on concurrent calls, and a circuit breaker that turns calls
+    /// away while the server fn keeps failing. Calls answered with a 5xx
+    /// status count as failures.
+    ///
+    /// Breakers are shared by name, so one server fn's breaker can be
+    /// looked up with [`breaker_statuses`].
+    #[derive(Clone)]
+    pub struct ResilienceLayer {
+        breaker: Arc<Breaker>,
+    }
+
+    impl ResilienceLayer {
+        pub fn new(name: &'static str, policy: Policy) -> Self {
+            let breaker = BREAKERS
+                .entry(name)
+                .or_insert_with(|| Arc::new(Breaker::new(policy)))
+                .clone();
+            Self { breaker }
+        }
+    }
+
+    impl<S> Layer<S> for ResilienceLayer {
+        type Service = ResilienceService<S>;
+
+        fn layer(&self, inner: S) -> Self::Service {
+            ResilienceService {
+                inner,
+                breaker: Arc::clone(&self.breaker),
+            }
+        }
+    }
+
+    pub struct ResilienceService<T> {
+        inner: T,
+        breaker: Arc<Breaker>,
+    }
+
+    impl<T, ReqBody, ResBody> Service<Request<ReqBody>> for ResilienceService<T>
+    where
+        T: Service<Request<ReqBody>, Response = Response<ResBody>>,
+        T::Error: From<MiddlewareError>,
+    {
+        type Response = T::Response;
+        type Error = T::Error;
+        type Future = Either<
+            Ready<Result<T::Response, T::Error>>,
+            ResilienceFuture<T::Future>,
+        >;
+
+        fn poll_ready(
+            &mut self,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+            match self.breaker.admit() {
+                Ok(permit) => Either::Right(ResilienceFuture {
+                    inner: tokio::time::timeout(
+                        self.breaker.policy.timeout,
+                        self.inner.call(req),
+                    ),
+                    breaker: Arc::clone(&self.breaker),
+                    _permit: permit,
+                }),
+                Err(e) => {
+                    set_error_status(&e);
+                    Either::Left(future::ready(Err(e.into())))
+                }
+            }
+        }
+    }
+
+    pin_project! {
+        pub struct ResilienceFuture<T> {
+            #[pin]
+            inner: Timeout<T>,
+            breaker: Arc<Breaker>,
+            _permit: OwnedSemaphorePermit,
+        }
+    }
+
+    impl<T, ResBody, E> Future for ResilienceFuture<T>
+    where
+        T: Future<Output = Result<Response<ResBody>, E>>,
+        E: From<MiddlewareError>,
+    {
+        type Output = T::Output;
+
+        fn poll(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Self::Output> {
+            let this = self.project();
+            let output = match ready!(this.inner.poll(cx)) {
+                Ok(output) => output,
+                Err(_) => {
+                    let error = MiddlewareError::TimedOut {
+                        after_ms: this.breaker.policy.timeout.as_millis()
+                            as u64,
+                    };
+                    set_error_status(&error);
+                    Err(error.into())
+                }
+            };
+            let succeeded = output
+                .as_ref()
+                .is_ok_and(|res| !res.status().is_server_error());
+            this.breaker.record(succeeded);
+            Poll::Ready(output)
+        }
+    }
+}
+
+#[server]
+pub async fn breakers() -> Result<Vec<BreakerStatus>, ServerFnError> {
+    Ok(breaker_statuses())
+}
+
+/// The state of every server fn's circuit breaker, refetched whenever
+/// `refresh` changes.
+#[component]
+pub fn BreakerPanel(#[prop(into)] refresh: Signal<usize>) -> impl IntoView {
+    let (clicks, set_clicks) = signal(0);
+    let statuses =
+        Resource::new(move || (refresh.get(), clicks.get()), |_| breakers());
+
+    view! {
+        <p>
+            "Circuit breakers "
+            <button on:click=move |_| set_clicks.update(|n| *n += 1)>"Refresh"</button>
+        </p>
+        <Transition>
+            {move || Suspend::new(async move {
+                match statuses.await {
+                    Ok(statuses) => {
+                        view! {
+                            <ul>
+                                {statuses
+                                    .into_iter()
+                                    .map(|status| {
+                                        let probe = status
+                                            .probe_in_ms
+                                            .map(|ms| format!(", probing in {:.1}s", ms as f64 / 1000.0))
+                                            .unwrap_or_default();
+                                        view! {
+                                            <li>
+                                                <code>{status.name}</code>
+                                                {format!(
+                                                    ": {} ({} recent failures, {} in flight{probe})",
+                                                    status.state,
+                                                    status.recent_failures,
+                                                    status.in_flight,
+                                                )}
+                                            </li>
+                                        }
+                                    })
+                                    .collect::<Vec<_>>()}
+                            </ul>
+                        }
+                            .into_any()
+                    }
+                    Err(e) => view! { <p>{e.to_string()}</p> }.into_any(),
+                }
+            })}
+        </Transition>
+    }
+}
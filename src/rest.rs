@@ -0,0 +1,162 @@
+use crate::{
+    api_keys::{API_KEYS, API_KEY_PREFIX},
+    audit::AuditAction,
+    auth::{User, UserId},
+    errors::ApiKeyError,
+    jwt,
+    quotas::QuotaKind,
+    rows::validate_text,
+    storage::{Row, RowQuery, Transaction, ROWS},
+    tenants::{tenant_owner, Tenant},
+};
+use axum::{
+    extract::{Path, Request},
+    http::{
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        StatusCode,
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Json, Router,
+};
+use serde::Deserialize;
+
+/// Where [`router`] is mounted.
+pub const REST_PATH: &str = "/rest";
+/// The most rows `GET /rest/rows` returns.
+const LIST_LIMIT: usize = 100;
+
+/// Who a request was authenticated as, and the name of the API key it was
+/// made with, if it wasn't made with a token from [`jwt::create_token`].
+#[derive(Clone)]
+struct Caller {
+    user: User,
+    api_key: Option<String>,
+    /// Whose rows it works with: the user's in the request's tenant.
+    owner: UserId,
+}
+
+impl Caller {
+    /// Checks a bearer credential, which is either an API key or a JWT.
+    fn authenticate(bearer: &str, tenant: &Tenant) -> Result<Self, String> {
+        let (user, api_key) = if bearer.starts_with(API_KEY_PREFIX) {
+            let (user, key) = API_KEYS
+                .authenticate(bearer)
+                .ok_or_else(|| ApiKeyError::Unauthorized.to_string())?;
+            tracing::debug!(user_id = user.id, key_id = %key.id, "API key used");
+            (user, Some(key.name))
+        } else {
+            let user = jwt::verify(bearer).map_err(|e| e.to_string())?;
+            (user, None)
+        };
+        Ok(Caller {
+            owner: tenant_owner(tenant, user.id),
+            user,
+            api_key,
+        })
+    }
+
+    /// Runs `f` as one [`RowStore::transaction`] on the caller's rows,
+    /// audited with the API key it was made with.
+    ///
+    /// [`RowStore::transaction`]: crate::storage::RowStore::transaction
+    fn transaction<T>(
+        &self,
+        f: impl FnOnce(&mut Transaction<'_>) -> Result<T, (StatusCode, String)>,
+    ) -> Result<T, (StatusCode, String)> {
+        ROWS.transaction(self.owner, |tx| {
+            if let Some(name) = &self.api_key {
+                tx.via_api_key(name);
+            }
+            f(tx)
+        })
+    }
+}
+
+/// A JSON API over the caller's rows, for scripts and apps that
+/// authenticate with an API key or a short-lived JWT rather than a session
+/// cookie. Mount it at [`REST_PATH`].
+pub fn router<S: Clone + Send + Sync + 'static>() -> Router<S> {
+    Router::new()
+        .route("/rows", get(list_rows).post(add_row))
+        .route("/rows/{id}", get(get_row).delete(delete_row))
+        .route_layer(middleware::from_fn(authenticate))
+}
+
+/// Rejects requests without a valid `Authorization: Bearer <credential>`,
+/// and passes a [`Caller`] on to the rest.
+async fn authenticate(mut req: Request, next: Next) -> Response {
+    let tenant = req
+        .extensions()
+        .get::<Tenant>()
+        .cloned()
+        .unwrap_or_default();
+    let caller = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| ApiKeyError::Unauthorized.to_string())
+        .and_then(|bearer| Caller::authenticate(bearer.trim(), &tenant));
+    match caller {
+        Ok(caller) => {
+            req.extensions_mut().insert(caller);
+            next.run(req).await
+        }
+        Err(e) => (StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, "Bearer")], e)
+            .into_response(),
+    }
+}
+
+fn no_row(id: u64) -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, format!("there is no row {id}"))
+}
+
+/// The caller's newest rows.
+async fn list_rows(Extension(caller): Extension<Caller>) -> Json<Vec<Row>> {
+    Json(ROWS.list(caller.owner, &RowQuery::default(), LIST_LIMIT))
+}
+
+async fn get_row(
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<u64>,
+) -> Result<Json<Row>, (StatusCode, String)> {
+    ROWS.get(caller.owner, id)
+        .map(Json)
+        .ok_or_else(|| no_row(id))
+}
+
+#[derive(Deserialize)]
+struct NewRow {
+    text: String,
+}
+
+async fn add_row(
+    Extension(caller): Extension<Caller>,
+    Json(new_row): Json<NewRow>,
+) -> Result<(StatusCode, Json<Row>), (StatusCode, String)> {
+    let text = validate_text(&new_row.text)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+    let row = caller.transaction(|tx| {
+        tx.consume_quota(QuotaKind::Rows, 1)
+            .map_err(|e| (StatusCode::TOO_MANY_REQUESTS, e.to_string()))?;
+        let row = tx.insert(text.to_string());
+        tx.audit(row.id, AuditAction::Created);
+        Ok(row)
+    })?;
+    Ok((StatusCode::CREATED, Json(row)))
+}
+
+/// Moves a row to the trash, like deleting it in the app.
+async fn delete_row(
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<u64>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    caller.transaction(|tx| {
+        tx.trash(id).ok_or_else(|| no_row(id))?;
+        tx.audit(id, AuditAction::Deleted);
+        Ok(())
+    })?;
+    Ok(StatusCode::NO_CONTENT)
+}
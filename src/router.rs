@@ -0,0 +1,157 @@
+#[cfg(feature = "embed-assets")]
+use crate::assets::embedded_file_and_error_handler as file_and_error_handler;
+use crate::{
+    app::{shell, App},
+    crawlers::CrawlerLayer,
+    dev_overlay::DevRequestLayer,
+    flash::redirect_failed_form_posts,
+    fragments, jwt,
+    metrics::MetricsLayer,
+    middleware::{
+        catch_panic_layer, compression_layer, cors_layer, CacheControlLayer,
+        DeprecationLayer, SecurityHeadersLayer, ServerFnLayer,
+    },
+    rest,
+    security::ContentSecurityPolicy,
+    seo,
+    settings::AppSettings,
+    telemetry::server_fn_trace_layer,
+    tenants::resolve_tenant,
+};
+use axum::{http::HeaderMap, response::Redirect, routing::get, Router};
+use leptos::{config::LeptosOptions, prelude::provide_context};
+#[cfg(not(feature = "embed-assets"))]
+use leptos_axum::file_and_error_handler_with_context as file_and_error_handler;
+use leptos_axum::{
+    generate_route_list,
+    generate_route_list_with_exclusions_and_ssg_and_context, LeptosRoutes,
+};
+
+/// What every page is rendered with, besides the request.
+fn server_context(
+    settings: &AppSettings,
+    leptos_options: &LeptosOptions,
+) -> impl Fn() + Clone + Send + Sync + 'static {
+    let base_path = settings.base_path();
+    let csp = settings.security.content_security_policy.then(|| {
+        ContentSecurityPolicy::new(&settings.security, leptos_options)
+    });
+    move || {
+        provide_context(base_path.clone());
+        if let Some(csp) = &csp {
+            provide_context(csp.clone());
+        }
+    }
+}
+
+/// Pre-renders the pages marked `SsrMode::Static` to HTML files under the
+/// site root, where [`app_router`] serves them from.
+///
+/// Pages that haven't been generated are rendered and written on their
+/// first request instead, so this is only needed to save that first render
+/// (or to ship the files to a CDN). A static page is rendered without a
+/// request, so it's the same for every visitor.
+pub async fn generate_static_pages(
+    settings: &AppSettings,
+    leptos_options: &LeptosOptions,
+) {
+    let shell = {
+        let leptos_options = leptos_options.clone();
+        move || shell(leptos_options.clone())
+    };
+    let (_, generator) =
+        generate_route_list_with_exclusions_and_ssg_and_context(
+            shell,
+            None,
+            server_context(settings, leptos_options),
+        );
+    generator.generate(leptos_options).await;
+}
+
+/// The whole app (pages, server fns, the REST API and static files) with
+/// every layer, ready to be served.
+///
+/// Only builds the router; background jobs and the `init` functions of the
+/// modules that need settings are up to the caller.
+pub fn app_router(
+    settings: &AppSettings,
+    leptos_options: LeptosOptions,
+) -> Router {
+    let routes = generate_route_list(App);
+    let sitemap_paths = seo::sitemap_paths(&routes);
+
+    let base_path = settings.base_path();
+    let provide_server_context = server_context(settings, &leptos_options);
+    let app_shell = {
+        let leptos_options = leptos_options.clone();
+        move || shell(leptos_options.clone())
+    };
+
+    // crawlers get every page rendered in order, so it's complete without
+    // the scripts that fill in out-of-order chunks
+    let pages = Router::new()
+        .leptos_routes_with_context(
+            &leptos_options,
+            routes,
+            provide_server_context.clone(),
+            app_shell.clone(),
+        )
+        .route_layer(CrawlerLayer::new(
+            provide_server_context.clone(),
+            app_shell,
+        ));
+
+    let app = Router::new()
+        .merge(pages)
+        .nest(rest::REST_PATH, rest::router())
+        .nest(fragments::FRAGMENTS_PATH, fragments::router())
+        .route(jwt::JWKS_PATH, get(jwt::jwks))
+        .route(
+            "/sitemap.xml",
+            get({
+                let base_path = base_path.clone();
+                move |headers: HeaderMap| async move {
+                    seo::sitemap(&sitemap_paths, &base_path, &headers)
+                }
+            }),
+        )
+        .fallback(file_and_error_handler(provide_server_context, shell))
+        .layer(catch_panic_layer())
+        .layer(axum::middleware::from_fn(redirect_failed_form_posts))
+        .layer(CacheControlLayer)
+        .layer(DeprecationLayer)
+        .layer(SecurityHeadersLayer::new(&settings.security))
+        // outside every server fn's own middleware, like rate limits, which
+        // count each tenant's callers apart
+        .layer(axum::middleware::from_fn(resolve_tenant));
+    // inside compression, so bodies are recorded as the server fn wrote them
+    #[cfg(feature = "call-log")]
+    let app = app.layer(axum::middleware::from_fn(crate::call_log::record));
+    let app = app.layer(compression_layer()).layer(MetricsLayer);
+    // outside `catch_panic_layer`, so panics are sent too
+    #[cfg(feature = "sentry")]
+    let app = app.layer(crate::sentry::SentryLayer);
+    // outermost, so whatever any layer logs is kept for the dev overlay
+    let app = app
+        .layer(server_fn_trace_layer())
+        .layer(DevRequestLayer)
+        .with_state(leptos_options);
+    let app = match cors_layer(&settings.cors) {
+        Some(cors) => app.layer(ServerFnLayer::new(cors)),
+        None => app,
+    };
+
+    // behind a sub-path proxy, mount everything (pages, /pkg assets and
+    // server fns) under the prefix; the proxy must forward the full path
+    if base_path.is_root() {
+        app
+    } else {
+        let root = base_path.as_str().to_string();
+        Router::new()
+            .route(
+                &base_path.join("/"),
+                get(move || async move { Redirect::permanent(&root) }),
+            )
+            .nest(base_path.as_str(), app)
+    }
+}
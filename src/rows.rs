@@ -0,0 +1,1772 @@
+use crate::{
+    attachments::{RowAttachments, RowWithAttachment},
+    audit::ActivityTimeline,
+    base_path::use_base_path,
+    components::flow::ShowWhen,
+    error_messages::error_message,
+    errors::{AppError, UpdateRowError},
+    file_rows::file_row_events,
+    flash::{show_toast, Flash},
+    prefetch::{cached, remember, CachedResource, PrefetchLink, RouteData},
+    replicas::Staleness,
+    seo::PageMeta,
+    storage::{
+        BulkOp, BulkOutcome, Row, RowQuery, RowSort, RowStatus, Tag, TagUsage,
+        MAX_ROW_TAGS,
+    },
+    supervisor::{supervise, ConnectionState},
+    uploads::{UploadQueue, DEFAULT_PARALLEL_UPLOADS},
+    url_state::{use_url_state, use_url_text},
+};
+#[cfg(feature = "ssr")]
+use crate::{
+    audit::AuditAction,
+    auth::UserId,
+    cache::{self, CacheTag, CACHE},
+    errors::{ImportError, UploadError},
+    flash::set_flash,
+    metrics::METRICS,
+    multipart::multipart_error,
+    quotas::{QuotaKind, QUOTAS},
+    replicas::reader,
+    sandbox::{current_owner, require_owner},
+    storage::{check_tag_count, words, ROWS},
+};
+use chrono::{Local, NaiveDate};
+use leptos::{
+    ev::{DragEvent, KeyboardEvent},
+    prelude::*,
+    task::spawn_local,
+};
+use leptos_router::{
+    components::{Form, A},
+    hooks::{use_params_map, use_query_map},
+};
+use serde::{Deserialize, Serialize};
+use server_fn::{
+    codec::{
+        ByteStream, GetUrl, Json, MultipartData, MultipartFormData, Streaming,
+    },
+    ServerFn,
+};
+use std::{
+    collections::BTreeSet,
+    ops::{ControlFlow, Range},
+    time::Duration,
+};
+use wasm_bindgen::JsCast;
+use web_sys::{FormData, HtmlFormElement, SubmitEvent};
+
+/// The longest text a row may have.
+pub const MAX_ROW_TEXT_LEN: usize = 280;
+
+/// File formats rows can be exported to and imported from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+impl ExportFormat {
+    /// Used both as the query parameter value and the file extension.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+
+    /// Guesses the format of an uploaded file from its extension.
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+        match extension.to_ascii_lowercase().as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "jsonl" | "ndjson" => Some(ExportFormat::Jsonl),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Jsonl => "application/x-ndjson",
+        }
+    }
+}
+
+/// How many rows are read from storage per chunk of the export.
+#[cfg(feature = "ssr")]
+const EXPORT_PAGE_SIZE: usize = 256;
+
+#[server(input = GetUrl, output = Streaming)]
+pub async fn export_rows_csv(
+    format: ExportFormat,
+) -> Result<ByteStream, ServerFnError> {
+    use futures::{stream, StreamExt};
+    use http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+        HeaderValue,
+    };
+
+    let owner = require_owner()?;
+    let response = expect_context::<leptos_axum::ResponseOptions>();
+    response.insert_header(
+        CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    response.insert_header(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!(
+            "attachment; filename=\"rows.{}\"",
+            format.as_str()
+        ))?,
+    );
+
+    let header = match format {
+        ExportFormat::Csv => Some(b"id,text\n".to_vec()),
+        ExportFormat::Jsonl => None,
+    };
+    // walk the table a page at a time, so only one page is ever in memory
+    let pages = stream::unfold(None, move |after| async move {
+        let page = ROWS.page_after(owner, after, EXPORT_PAGE_SIZE);
+        let last = page.last()?.id;
+        let mut chunk = Vec::new();
+        for row in &page {
+            write_row(&mut chunk, row, format);
+        }
+        Some((Ok(chunk), Some(last)))
+    });
+    Ok(ByteStream::new(stream::iter(header.map(Ok)).chain(pages)))
+}
+
+#[cfg(feature = "ssr")]
+fn write_row(out: &mut Vec<u8>, row: &Row, format: ExportFormat) {
+    match format {
+        ExportFormat::Csv => {
+            out.extend_from_slice(format!("{},", row.id).as_bytes());
+            out.extend_from_slice(csv_field(&row.text).as_bytes());
+        }
+        ExportFormat::Jsonl => serde_json::to_writer(&mut *out, row)
+            .expect("a row always serializes"),
+    }
+    out.push(b'\n');
+}
+
+/// Quotes a CSV field if it contains a delimiter, quote or line break.
+#[cfg(feature = "ssr")]
+fn csv_field(field: &str) -> std::borrow::Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+#[component]
+pub fn RowExport() -> impl IntoView {
+    let base_path = use_base_path();
+    let href = move |format: ExportFormat| {
+        base_path.join(&format!(
+            "{}?format={}",
+            ExportRowsCsv::PATH,
+            format.as_str()
+        ))
+    };
+
+    view! {
+        <h3>"Exporting rows"</h3>
+        <p>
+            "The export is streamed straight from storage a page at a time, so the "
+            "whole table is never buffered. These are plain links to the "
+            <code>"GetUrl"</code> " server fn, so they work without JavaScript too."
+        </p>
+        <a class="button" href=href(ExportFormat::Csv) download>
+            "Export as CSV"
+        </a>
+        " "
+        <a class="button" href=href(ExportFormat::Jsonl) download>
+            "Export as JSON Lines"
+        </a>
+    }
+}
+
+/// The outcome of importing one or more uploaded files.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub inserted: usize,
+    pub skipped: usize,
+    /// Why records were skipped, capped at [`MAX_REPORTED_ERRORS`] entries.
+    pub errors: Vec<LineError>,
+}
+
+/// A record that was skipped, identified by the line it starts on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineError {
+    pub file_name: String,
+    pub line: usize,
+    pub message: String,
+}
+
+pub const MAX_REPORTED_ERRORS: usize = 100;
+
+/// The longest record an import reads, in bytes: room for a row of
+/// [`MAX_ROW_TEXT_LEN`] characters with its quoting and other columns.
+pub const MAX_RECORD_LEN: usize = 16 * 1024;
+
+#[server(input = MultipartFormData)]
+pub async fn import_rows(
+    data: MultipartData,
+) -> Result<ImportReport, ServerFnError> {
+    let owner = require_owner()?;
+    let mut data = data
+        .into_inner()
+        .ok_or_else(|| ServerFnError::new(UploadError::NotMultipart))?;
+
+    let mut report = ImportReport::default();
+    while let Some(mut field) =
+        data.next_field().await.map_err(multipart_error)?
+    {
+        let file_name = field.file_name().unwrap_or_default().to_string();
+        let format =
+            ExportFormat::from_file_name(&file_name).ok_or_else(|| {
+                ServerFnError::new(UploadError::UnsupportedFormat {
+                    file_name: file_name.clone(),
+                })
+            })?;
+        // records are parsed as the upload arrives, not after buffering it
+        let mut importer = Importer::new(owner, file_name, format);
+        let mut size = 0;
+        while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+            QUOTAS.consume(
+                owner,
+                QuotaKind::UploadBytes,
+                chunk.len() as u64,
+            )?;
+            size += chunk.len() as u64;
+            importer.push(&chunk, &mut report);
+        }
+        importer.finish(&mut report);
+        METRICS.record_upload(size);
+    }
+    Ok(report)
+}
+
+/// Splits one uploaded file into records and inserts the valid ones.
+#[cfg(feature = "ssr")]
+struct Importer {
+    /// Who the imported rows are added for.
+    owner: UserId,
+    file_name: String,
+    format: ExportFormat,
+    /// Bytes of the record that is still being received.
+    buf: Vec<u8>,
+    /// How much of `buf` was already searched for the record's end.
+    scanned: usize,
+    /// Whether `buf[..scanned]` leaves a CSV field open.
+    quoted: bool,
+    /// Whether the record being received went over [`MAX_RECORD_LEN`], in
+    /// which case `buf` holds only what came after that.
+    oversized: bool,
+    /// Newlines in the parts of an oversized record that were dropped.
+    dropped_lines: usize,
+    /// The line the record in `buf` starts on.
+    line: usize,
+    /// `(column count, index of the text column)`, once the header is read.
+    csv_header: Option<(usize, Option<usize>)>,
+}
+
+#[cfg(feature = "ssr")]
+impl Importer {
+    fn new(owner: UserId, file_name: String, format: ExportFormat) -> Self {
+        Self {
+            owner,
+            file_name,
+            format,
+            buf: Vec::new(),
+            scanned: 0,
+            quoted: false,
+            oversized: false,
+            dropped_lines: 0,
+            line: 1,
+            csv_header: None,
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8], report: &mut ImportReport) {
+        self.buf.extend_from_slice(chunk);
+        while let Some(end) = self.record_end() {
+            let record = self.buf.drain(..=end).collect::<Vec<_>>();
+            self.record(&record[..end], report);
+        }
+        if self.buf.len() > MAX_RECORD_LEN {
+            // too long to import anyway, so only look for where it ends
+            self.oversized = true;
+            self.dropped_lines += newlines(&self.buf);
+            self.buf.clear();
+            self.scanned = 0;
+        }
+    }
+
+    fn finish(&mut self, report: &mut ImportReport) {
+        let record = std::mem::take(&mut self.buf);
+        self.record(&record, report);
+    }
+
+    /// The newline ending the first complete record, skipping newlines
+    /// inside quoted CSV fields. Picks up where the last call left off, so
+    /// each byte is only looked at once.
+    fn record_end(&mut self) -> Option<usize> {
+        for i in self.scanned..self.buf.len() {
+            match self.buf[i] {
+                b'"' if self.format == ExportFormat::Csv => {
+                    self.quoted = !self.quoted
+                }
+                b'\n' if !self.quoted => {
+                    self.scanned = 0;
+                    return Some(i);
+                }
+                _ => {}
+            }
+        }
+        self.scanned = self.buf.len();
+        None
+    }
+
+    fn record(&mut self, record: &[u8], report: &mut ImportReport) {
+        let line = self.line;
+        self.line +=
+            1 + newlines(record) + std::mem::take(&mut self.dropped_lines);
+        if std::mem::take(&mut self.oversized) {
+            let e = ImportError::RecordTooLong {
+                max: MAX_RECORD_LEN,
+            };
+            self.skip(line, e, report);
+            return;
+        }
+
+        let record = record.strip_suffix(b"\r").unwrap_or(record);
+        if record.iter().all(u8::is_ascii_whitespace) {
+            return;
+        }
+        let inserted = std::str::from_utf8(record)
+            .map_err(|_| ImportError::InvalidUtf8)
+            .and_then(|record| self.parse(record))
+            .and_then(|text| {
+                let Some(text) = text else {
+                    return Ok(false);
+                };
+                ROWS.transaction(self.owner, |tx| {
+                    tx.consume_quota(QuotaKind::Rows, 1)?;
+                    let row = tx.insert(text);
+                    tx.audit(row.id, AuditAction::Created);
+                    Ok(true)
+                })
+            });
+        match inserted {
+            Ok(false) => {}
+            Ok(true) => report.inserted += 1,
+            Err(e) => self.skip(line, e, report),
+        }
+    }
+
+    /// Reports the record starting on `line` as skipped because of `e`.
+    fn skip(&self, line: usize, e: ImportError, report: &mut ImportReport) {
+        report.skipped += 1;
+        if report.errors.len() < MAX_REPORTED_ERRORS {
+            report.errors.push(LineError {
+                file_name: self.file_name.clone(),
+                line,
+                message: e.to_string(),
+            });
+        }
+    }
+
+    /// The validated text of a record, or `None` for a CSV header.
+    fn parse(&mut self, record: &str) -> Result<Option<String>, ImportError> {
+        #[derive(Deserialize)]
+        struct ImportedRow {
+            text: String,
+        }
+
+        let text = match self.format {
+            ExportFormat::Jsonl => {
+                serde_json::from_str::<ImportedRow>(record)
+                    .map_err(|e| ImportError::Json(e.to_string()))?
+                    .text
+            }
+            ExportFormat::Csv => {
+                let mut fields = csv_fields(record);
+                let Some((columns, text_column)) = self.csv_header else {
+                    let text_column = fields.iter().position(|name| {
+                        name.trim().eq_ignore_ascii_case("text")
+                    });
+                    self.csv_header = Some((fields.len(), text_column));
+                    return Ok(None);
+                };
+                let text_column =
+                    text_column.ok_or(ImportError::MissingTextColumn)?;
+                if fields.len() != columns {
+                    return Err(ImportError::FieldCount {
+                        expected: columns,
+                        found: fields.len(),
+                    });
+                }
+                fields.swap_remove(text_column)
+            }
+        };
+        validate_text(&text).map(|text| Some(text.to_string()))
+    }
+}
+
+/// Trims `text` and checks it is fit to be stored as a row.
+#[cfg(feature = "ssr")]
+pub(crate) fn validate_text(text: &str) -> Result<&str, ImportError> {
+    let text = text.trim();
+    if text.is_empty() {
+        Err(ImportError::EmptyText)
+    } else if text.chars().count() > MAX_ROW_TEXT_LEN {
+        Err(ImportError::TextTooLong {
+            max: MAX_ROW_TEXT_LEN,
+        })
+    } else {
+        Ok(text)
+    }
+}
+
+#[cfg(feature = "ssr")]
+fn newlines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|byte| **byte == b'\n').count()
+}
+
+/// Splits one CSV record into its (unquoted) fields.
+#[cfg(feature = "ssr")]
+fn csv_fields(record: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = record.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[component]
+pub fn RowImport() -> impl IntoView {
+    let import =
+        Action::new_local(|data: &FormData| import_rows(data.clone().into()));
+
+    view! {
+        <h3>"Importing rows"</h3>
+        <p>
+            "Upload a " <code>".csv"</code> " file with a " <code>"text"</code>
+            " column, or a " <code>".jsonl"</code> " file with a "
+            <code>"text"</code> " field per line (such as the exports above). "
+            "Each record is validated; valid ones are inserted and the rest are reported."
+        </p>
+        <form on:submit=move |ev: SubmitEvent| {
+            ev.prevent_default();
+            let target = ev.target().unwrap().unchecked_into::<HtmlFormElement>();
+            let form_data = FormData::new_with_form(&target).unwrap();
+            import.dispatch_local(form_data);
+        }>
+            <input type="file" name="rows" accept=".csv,.jsonl,.ndjson" />
+            <input type="submit" value="Import" />
+        </form>
+        {move || {
+            if import.pending().get() {
+                view! { <p>"Importing..."</p> }.into_any()
+            } else {
+                match import.value().get() {
+                    None => ().into_any(),
+                    Some(Ok(report)) => view! { <ImportSummary report /> }.into_any(),
+                    Some(Err(e)) => view! { <p>"Import failed: " {e.to_string()}</p> }.into_any(),
+                }
+            }
+        }}
+    }
+}
+
+#[component]
+fn ImportSummary(report: ImportReport) -> impl IntoView {
+    let unreported = report.skipped - report.errors.len();
+    let errors = (!report.errors.is_empty()).then(|| {
+        view! {
+            <table>
+                <tr>
+                    <th>"File"</th>
+                    <th>"Line"</th>
+                    <th>"Problem"</th>
+                </tr>
+                {report
+                    .errors
+                    .into_iter()
+                    .map(|error| {
+                        view! {
+                            <tr>
+                                <td>{error.file_name}</td>
+                                <td>{error.line}</td>
+                                <td>{error.message}</td>
+                            </tr>
+                        }
+                    })
+                    .collect::<Vec<_>>()}
+            </table>
+        }
+    });
+
+    view! {
+        <table>
+            <tr>
+                <th>"Inserted"</th>
+                <td>{report.inserted}</td>
+            </tr>
+            <tr>
+                <th>"Skipped"</th>
+                <td>{report.skipped}</td>
+            </tr>
+        </table>
+        {errors}
+        {(unreported > 0).then(|| view! { <p>{format!("...and {unreported} more")}</p> })}
+    }
+}
+
+/// Results per page of [`search_rows`].
+pub const SEARCH_PAGE_SIZE: usize = 10;
+/// Longest snippet shown for a match, in characters.
+#[cfg(feature = "ssr")]
+const SNIPPET_LEN: usize = 120;
+
+/// One page of search matches.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchResults {
+    pub total: usize,
+    pub hits: Vec<SearchHit>,
+}
+
+/// A matching row, cut down to a snippet around its first match.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub id: u64,
+    pub snippet: String,
+    /// Byte ranges of `snippet` to highlight, in order.
+    pub highlights: Vec<Range<usize>>,
+}
+
+#[server(input = GetUrl)]
+pub async fn search_rows(
+    query: String,
+    page: usize,
+    #[server(default)] staleness: Staleness,
+) -> Result<SearchResults, ServerFnError> {
+    let terms = words(&query).map(|(_, word)| word).collect::<Vec<_>>();
+    let Some(owner) = current_owner().filter(|_| !terms.is_empty()) else {
+        return Ok(SearchResults::default());
+    };
+    let (total, rows) = reader(owner, staleness).search(
+        owner,
+        &terms,
+        page * SEARCH_PAGE_SIZE,
+        SEARCH_PAGE_SIZE,
+    );
+    let hits = rows
+        .into_iter()
+        .map(|row| {
+            let highlights = words(&row.text)
+                .filter(|(_, word)| terms.iter().any(|t| word.starts_with(t)))
+                .map(|(range, _)| range)
+                .collect::<Vec<_>>();
+            snippet(row.id, &row.text, highlights)
+        })
+        .collect();
+    Ok(SearchResults { total, hits })
+}
+
+/// Cuts `text` down to at most [`SNIPPET_LEN`] characters starting shortly
+/// before the first highlight, and shifts the highlights to match.
+#[cfg(feature = "ssr")]
+fn snippet(id: u64, text: &str, highlights: Vec<Range<usize>>) -> SearchHit {
+    const LEAD: usize = 20;
+    const ELLIPSIS: &str = "…";
+
+    if text.chars().count() <= SNIPPET_LEN {
+        return SearchHit {
+            id,
+            snippet: text.to_string(),
+            highlights,
+        };
+    }
+
+    let first = highlights.first().map_or(0, |range| range.start);
+    let start = text[..first]
+        .char_indices()
+        .rev()
+        .nth(LEAD.saturating_sub(1))
+        .map_or(0, |(i, _)| i);
+    let end = text[start..]
+        .char_indices()
+        .nth(SNIPPET_LEN)
+        .map_or(text.len(), |(i, _)| start + i);
+
+    let mut snippet = String::new();
+    let mut shift = -(start as isize);
+    if start > 0 {
+        snippet.push_str(ELLIPSIS);
+        shift += ELLIPSIS.len() as isize;
+    }
+    snippet.push_str(&text[start..end]);
+    if end < text.len() {
+        snippet.push_str(ELLIPSIS);
+    }
+    let highlights = highlights
+        .into_iter()
+        .filter(|range| range.start >= start && range.end <= end)
+        .map(|range| {
+            (range.start as isize + shift) as usize
+                ..(range.end as isize + shift) as usize
+        })
+        .collect();
+    SearchHit {
+        id,
+        snippet,
+        highlights,
+    }
+}
+
+/// How long typing has to pause before a search is sent.
+const SEARCH_DEBOUNCE: Duration = Duration::from_millis(250);
+
+#[component]
+pub fn RowSearch() -> impl IntoView {
+    let (query, set_query) = use_url_text("search");
+    // one-based in the URL, as the pages are numbered
+    let (page_param, set_page_param) = use_url_state::<usize>("search_page");
+    let page = Memo::new(move |_| page_param.get().unwrap_or(1).max(1) - 1);
+    let set_page = move |page: usize| {
+        set_page_param.set((page > 0).then_some(page + 1));
+    };
+    // bumped on every keystroke; a pending update only applies if no newer
+    // keystroke has happened since it was scheduled
+    let generation = StoredValue::new(0u64);
+    let on_input = move |ev| {
+        let value = event_target_value(&ev);
+        let current = generation.get_value() + 1;
+        generation.set_value(current);
+        set_timeout(
+            move || {
+                if generation.get_value() == current {
+                    set_page(0);
+                    set_query(value);
+                }
+            },
+            SEARCH_DEBOUNCE,
+        );
+    };
+    let results = Resource::new(
+        move || (query.get(), page.get()),
+        |(query, page)| search_rows(query, page, Staleness::Tolerated),
+    );
+
+    view! {
+        <h3>"Searching rows"</h3>
+        <p>
+            "Rows are indexed by word as they are added. Every word you type has to match "
+            "the start of a word in the row."
+        </p>
+        <input
+            type="search"
+            placeholder="Search rows"
+            value=move || query.get()
+            on:input=on_input
+        />
+        <Transition fallback=|| view! { <p>"Searching..."</p> }>
+            {move || Suspend::new(async move {
+                results
+                    .await
+                    .map(|results| {
+                        let pages = results.total.div_ceil(SEARCH_PAGE_SIZE);
+                        view! {
+                            <p>{format!("{} matching rows", results.total)}</p>
+                            <ul>
+                                {results
+                                    .hits
+                                    .into_iter()
+                                    .map(|hit| view! { <li><Highlighted hit /></li> })
+                                    .collect::<Vec<_>>()}
+                            </ul>
+                            <Show when=move || { pages > 1 }>
+                                <button
+                                    disabled=move || page.get() == 0
+                                    on:click=move |_| set_page(page.get() - 1)
+                                >
+                                    "Previous"
+                                </button>
+                                {move || format!(" page {} of {pages} ", page.get() + 1)}
+                                <button
+                                    disabled=move || page.get() + 1 >= pages
+                                    on:click=move |_| set_page(page.get() + 1)
+                                >
+                                    "Next"
+                                </button>
+                            </Show>
+                        }
+                    })
+            })}
+        </Transition>
+    }
+}
+
+/// Renders a hit's snippet with its highlighted ranges wrapped in `<mark>`.
+#[component]
+fn Highlighted(hit: SearchHit) -> impl IntoView {
+    let mut parts = Vec::new();
+    let mut at = 0;
+    for range in hit.highlights {
+        parts.push(hit.snippet[at..range.start].to_string().into_any());
+        parts.push(
+            view! { <mark>{hit.snippet[range.clone()].to_string()}</mark> }
+                .into_any(),
+        );
+        at = range.end;
+    }
+    parts.push(hit.snippet[at..].to_string().into_any());
+    parts
+}
+
+/// Most rows [`list_rows`] returns at once.
+pub const ROW_LIST_LIMIT: usize = 100;
+
+#[server]
+pub async fn list_rows(
+    query: RowQuery,
+    #[server(default)] staleness: Staleness,
+) -> Result<Vec<Row>, ServerFnError> {
+    // visitors who haven't started a sandbox have no rows to see
+    let Some(owner) = current_owner() else {
+        return Ok(Vec::new());
+    };
+    CACHE
+        .get_or_compute(
+            cache::key("list_rows", &(owner, &query)),
+            &[CacheTag::Rows(owner)],
+            async move {
+                let rows = reader(owner, staleness);
+                Ok(rows.list(owner, &query, ROW_LIST_LIMIT))
+            },
+        )
+        .await
+}
+
+#[cfg(feature = "ssr")]
+fn no_row(id: u64) -> ServerFnError {
+    ServerFnError::new(format!("there is no row {id}"))
+}
+
+#[server]
+pub async fn set_row_completed(
+    id: u64,
+    completed: bool,
+) -> Result<(), ServerFnError> {
+    let owner = require_owner()?;
+    let (action, notice) = if completed {
+        (AuditAction::Completed, "Marked the row completed.")
+    } else {
+        (AuditAction::Reopened, "Marked the row active.")
+    };
+    ROWS.transaction(owner, |tx| {
+        tx.set_completed(id, completed).ok_or_else(|| no_row(id))?;
+        tx.audit(id, action);
+        Ok::<_, ServerFnError>(())
+    })?;
+    set_flash(Flash::Notice(notice.to_string()));
+    Ok(())
+}
+
+/// Replaces a row's tags, returning the updated row.
+#[server]
+pub async fn set_row_tags(
+    id: u64,
+    tags: Vec<Tag>,
+) -> Result<Row, ServerFnError> {
+    check_tag_count(&tags).map_err(|e| ServerFnError::new(e.to_string()))?;
+    let owner = require_owner()?;
+    ROWS.transaction(owner, |tx| {
+        let row = tx.set_tags(id, tags).ok_or_else(|| no_row(id))?;
+        tx.audit(id, AuditAction::TagsSet(row.tags.clone()));
+        Ok(row)
+    })
+}
+
+/// Moves a row to the trash; its attachments stay until it is purged.
+#[server]
+pub async fn delete_row(id: u64) -> Result<(), ServerFnError> {
+    let owner = require_owner()?;
+    ROWS.transaction(owner, |tx| {
+        tx.trash(id).ok_or_else(|| no_row(id))?;
+        tx.audit(id, AuditAction::Deleted);
+        Ok::<_, ServerFnError>(())
+    })?;
+    set_flash(Flash::Notice("Moved the row to the trash.".to_string()));
+    Ok(())
+}
+
+/// Applies `op` to every row in `ids` at once, reporting how each one went.
+///
+/// Rows that fail (because they are gone, say) don't stop the others.
+#[server(input = Json)]
+pub async fn bulk_update(
+    ids: Vec<u64>,
+    op: BulkOp,
+) -> Result<Vec<BulkOutcome>, ServerFnError> {
+    let owner = require_owner()?;
+    let action = match &op {
+        BulkOp::Delete => AuditAction::Deleted,
+        BulkOp::Complete => AuditAction::Completed,
+        BulkOp::Reopen => AuditAction::Reopened,
+        BulkOp::AddTag(tag) => AuditAction::TagAdded(tag.clone()),
+        BulkOp::RemoveTag(tag) => AuditAction::TagRemoved(tag.clone()),
+    };
+    ROWS.transaction(owner, |tx| {
+        let outcomes = tx.bulk_update(&ids, &op);
+        for outcome in outcomes.iter().filter(|outcome| outcome.result.is_ok())
+        {
+            tx.audit(outcome.id, action.clone());
+        }
+        Ok(outcomes)
+    })
+}
+
+/// Moves a row to just before `before_id` in the manual order, or to the
+/// end without one.
+#[server]
+pub async fn reorder_rows(
+    moved_id: u64,
+    before_id: Option<u64>,
+) -> Result<Row, ServerFnError> {
+    let owner = require_owner()?;
+    ROWS.transaction(owner, |tx| {
+        let row = tx
+            .reorder(moved_id, before_id)
+            .ok_or_else(|| ServerFnError::new("there is no such row"))?;
+        tx.audit(moved_id, AuditAction::Moved);
+        Ok(row)
+    })
+}
+
+/// Replaces a row's text, failing with [`UpdateRowError::Conflict`] if the
+/// row has changed since it was at `expected_version`.
+#[server]
+pub async fn update_row(
+    id: u64,
+    text: String,
+    expected_version: u64,
+) -> Result<Row, UpdateRowError> {
+    let owner = require_owner()?;
+    let text = validate_text(&text).map_err(|e| match e {
+        ImportError::TextTooLong { max } => UpdateRowError::TextTooLong { max },
+        _ => UpdateRowError::EmptyText,
+    })?;
+    let row = ROWS.transaction(owner, |tx| {
+        let row = tx.update_text(id, text.to_string(), expected_version)?;
+        tx.audit(id, AuditAction::Edited);
+        Ok::<_, UpdateRowError>(row)
+    })?;
+    set_flash(Flash::Notice("Saved the row.".to_string()));
+    Ok(row)
+}
+
+/// Sets or clears a row's due date, returning the updated row.
+#[server]
+pub async fn set_row_due(
+    id: u64,
+    due: Option<NaiveDate>,
+) -> Result<Row, ServerFnError> {
+    let owner = require_owner()?;
+    ROWS.transaction(owner, |tx| {
+        let row = tx.set_due(id, due).ok_or_else(|| no_row(id))?;
+        tx.audit(id, AuditAction::DueSet(due));
+        Ok(row)
+    })
+}
+
+#[server(input = GetUrl)]
+pub async fn get_row(id: u64) -> Result<Row, ServerFnError> {
+    let owner = require_owner()?;
+    ROWS.get(owner, id).ok_or_else(|| no_row(id))
+}
+
+/// Every tag in use, with how many rows have it.
+#[server]
+pub async fn list_tags() -> Result<Vec<TagUsage>, ServerFnError> {
+    Ok(current_owner()
+        .map(|owner| ROWS.tags(owner))
+        .unwrap_or_default())
+}
+
+/// Reads a [`RowQuery`] from the `sort`, `status`, `contains` and (repeated)
+/// `tag` URL query parameters, falling back to the defaults for missing or
+/// unknown values.
+pub fn use_row_query() -> Memo<RowQuery> {
+    let params = use_query_map();
+    Memo::new(move |_| {
+        params.with(|params| RowQuery {
+            sort: params
+                .get("sort")
+                .and_then(|sort| sort.parse().ok())
+                .unwrap_or_default(),
+            status: params
+                .get("status")
+                .and_then(|status| status.parse().ok())
+                .unwrap_or_default(),
+            contains: params.get("contains").unwrap_or_default(),
+            tags: params
+                .get_all("tag")
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|tag| Tag::try_from(tag).ok())
+                .collect(),
+        })
+    })
+}
+
+#[component]
+pub fn RowList() -> impl IntoView {
+    let query = use_row_query();
+    let actions = RowActions::new();
+    let (raw, set_raw) = signal(false);
+    let (reorder_error, set_reorder_error) = signal(None::<String>);
+    let rows = Resource::new(
+        move || (query.get(), actions.version()),
+        |(query, _)| list_rows(query, Staleness::Tolerated),
+    );
+    // plain form posts get a flash for a failed toggle or delete; with
+    // JavaScript the same message comes as a toast
+    for value in [actions.toggle.value(), actions.delete.value()] {
+        Effect::new(move |_| {
+            if let Some(Err(e)) = value.get() {
+                show_toast(Flash::Error(AppError::new(&e)));
+            }
+        });
+    }
+    // so a row's page has something to show while it loads
+    Effect::new(move |_| {
+        for row in rows.get().and_then(Result::ok).unwrap_or_default() {
+            remember(RouteData::Row(row.id).key(), row);
+        }
+    });
+    let tag_usage = Resource::new(
+        move || {
+            actions.set_tags.version().get()
+                + actions.delete.version().get()
+                + actions.bulk.version().get()
+        },
+        |_| list_tags(),
+    );
+    let suggestions = Signal::derive(move || {
+        tag_usage
+            .get()
+            .and_then(Result::ok)
+            .map(|usage| usage.into_iter().map(|usage| usage.tag).collect())
+            .unwrap_or_default()
+    });
+    let filter_tags = RwSignal::new(query.with_untracked(|q| q.tags.clone()));
+    // keep the chips in step with the URL, e.g. on back/forward navigation
+    Effect::new(move || filter_tags.set(query.with(|q| q.tags.clone())));
+    // rows made from new files on the server show up without a reload
+    let (_, set_file_rows_connection) = signal(ConnectionState::Connecting);
+    Effect::new(move |_| {
+        spawn_local(supervise(
+            file_row_events,
+            set_file_rows_connection,
+            move |_| {
+                actions.saved.update(|n| *n += 1);
+                ControlFlow::Continue(())
+            },
+        ));
+    });
+
+    view! {
+        <h3>"Listing rows"</h3>
+        <p>
+            "Sorting and filtering happen on the server, driven by the URL's query string, "
+            "so every view can be shared and is rendered in full on the first load."
+        </p>
+        <Form method="GET" action="">
+            <select name="sort">
+                {[RowSort::Newest, RowSort::Oldest, RowSort::Alphabetical, RowSort::Manual]
+                    .map(|sort| {
+                        view! {
+                            <option
+                                value=sort.to_string()
+                                selected=move || query.with(|query| query.sort == sort)
+                            >
+                                {sort.to_string()}
+                            </option>
+                        }
+                    })}
+            </select>
+            <select name="status">
+                {[RowStatus::All, RowStatus::Active, RowStatus::Completed]
+                    .map(|status| {
+                        view! {
+                            <option
+                                value=status.to_string()
+                                selected=move || query.with(|query| query.status == status)
+                            >
+                                {status.to_string()}
+                            </option>
+                        }
+                    })}
+            </select>
+            <input
+                type="search"
+                name="contains"
+                placeholder="Text contains"
+                prop:value=move || query.with(|query| query.contains.clone())
+            />
+            <TagInput
+                id="row-filter-tags".to_string()
+                tags=filter_tags
+                suggestions
+                name="tag"
+            />
+            <input type="submit" value="Apply" />
+        </Form>
+        <label>
+            <input
+                type="checkbox"
+                prop:checked=raw
+                on:change=move |ev| set_raw.set(event_target_checked(&ev))
+            />
+            " Show Markdown source"
+        </label>
+        <BulkToolbar actions />
+        <Transition>
+            {move || Suspend::new(async move {
+                rows.await
+                    .map(|rows| {
+                        let manual = query.with_untracked(|query| query.sort == RowSort::Manual);
+                        // moved right away, and put back if the server refuses
+                        let order = RwSignal::new(rows);
+                        let move_before = Callback::new(move |before: Option<u64>| {
+                            let Some(moved) = actions.dragging.get_untracked() else {
+                                return;
+                            };
+                            let previous = order.get_untracked();
+                            if !order.try_update(|rows| move_row(rows, moved, before)).unwrap_or(false) {
+                                return;
+                            }
+                            set_reorder_error.set(None);
+                            spawn_local(async move {
+                                match reorder_rows(moved, before).await {
+                                    Ok(_) => actions.saved.update(|n| *n += 1),
+                                    Err(e) => {
+                                        order.set(previous);
+                                        set_reorder_error.set(Some(e.to_string()));
+                                    }
+                                }
+                            });
+                        });
+                        let move_before = manual.then_some(move_before);
+                        view! {
+                            <ul>
+                                <For
+                                    each=move || order.get()
+                                    key=|row| (row.id, row.version)
+                                    let:row
+                                >
+                                    <RowItem row actions suggestions raw move_before />
+                                </For>
+                            </ul>
+                            {move_before
+                                .map(|move_before| {
+                                    view! {
+                                        <p
+                                            class="drop-target"
+                                            on:dragover=|ev: DragEvent| ev.prevent_default()
+                                            on:drop=move |ev: DragEvent| {
+                                                ev.prevent_default();
+                                                move_before.run(None);
+                                            }
+                                        >
+                                            "Drop here to move a row to the end"
+                                        </p>
+                                    }
+                                })}
+                        }
+                    })
+            })}
+        </Transition>
+        {move || {
+            reorder_error.get().map(|e| view! { <p>"Reordering failed: " {e}</p> })
+        }}
+    }
+}
+
+/// The actions shared by every row of a [`RowList`].
+#[derive(Clone, Copy)]
+struct RowActions {
+    toggle: ServerAction<SetRowCompleted>,
+    set_tags: ServerAction<SetRowTags>,
+    set_due: ServerAction<SetRowDue>,
+    delete: ServerAction<DeleteRow>,
+    /// Files waiting to be attached, which outlive the list's re-renders.
+    uploads: UploadQueue,
+    bulk: ServerAction<BulkUpdate>,
+    /// The row being dragged to a new position.
+    dragging: RwSignal<Option<u64>>,
+    /// Rows ticked for the next bulk update.
+    selected: RwSignal<BTreeSet<u64>>,
+    /// Bumped by a [`RowEditor`] once it has saved.
+    saved: RwSignal<usize>,
+}
+
+impl RowActions {
+    fn new() -> Self {
+        let saved = RwSignal::new(0);
+        Self {
+            toggle: ServerAction::new(),
+            set_tags: ServerAction::new(),
+            set_due: ServerAction::new(),
+            delete: ServerAction::new(),
+            uploads: UploadQueue::new(
+                DEFAULT_PARALLEL_UPLOADS,
+                Callback::new(move |_| saved.update(|n| *n += 1)),
+            ),
+            bulk: ServerAction::new(),
+            dragging: RwSignal::new(None),
+            selected: RwSignal::new(BTreeSet::new()),
+            saved,
+        }
+    }
+
+    /// Changes whenever any row has been changed, to refetch the list.
+    fn version(&self) -> usize {
+        self.saved.get()
+            + self.toggle.version().get()
+            + self.set_tags.version().get()
+            + self.set_due.version().get()
+            + self.delete.version().get()
+            + self.bulk.version().get()
+    }
+}
+
+#[component]
+fn RowItem(
+    row: Row,
+    actions: RowActions,
+    suggestions: Signal<Vec<Tag>>,
+    /// Shows the Markdown source instead of the rendered text.
+    raw: ReadSignal<bool>,
+    /// Makes the row draggable; called with the row a dragged row is
+    /// dropped on.
+    #[prop(optional)]
+    move_before: Option<Callback<Option<u64>>>,
+) -> impl IntoView {
+    let id = row.id;
+    let overdue = row.is_overdue(Local::now().date_naive());
+    let tags = RwSignal::new(row.tags);
+    let Row {
+        version,
+        text,
+        html,
+        attachments,
+        ..
+    } = row;
+    let (editing, set_editing) = signal(false);
+    let on_change = Callback::new(move |tags| {
+        actions.set_tags.dispatch(SetRowTags { id, tags });
+    });
+    // an emptied date picker clears the due date
+    let on_due_change = move |ev| {
+        let due = event_target_value(&ev).parse().ok();
+        actions.set_due.dispatch(SetRowDue { id, due });
+    };
+    let on_saved = Callback::new(move |()| actions.saved.update(|n| *n += 1));
+    let on_cancel = Callback::new(move |()| set_editing.set(false));
+    let edit_href = use_base_path().join(&format!("/rows/{id}/edit"));
+
+    view! {
+        <li
+            class:overdue=overdue
+            draggable=move_before.map(|_| "true")
+            on:dragstart=move |ev: DragEvent| {
+                // links and images inside the row can be dragged too
+                if move_before.is_none() {
+                    return;
+                }
+                actions.dragging.set(Some(id));
+                if let Some(data) = ev.data_transfer() {
+                    _ = data.set_data("text/plain", &id.to_string());
+                }
+            }
+            on:dragend=move |_| actions.dragging.set(None)
+            on:dragover=move |ev: DragEvent| {
+                if move_before.is_some() {
+                    ev.prevent_default();
+                }
+            }
+            on:drop=move |ev: DragEvent| {
+                if let Some(move_before) = move_before {
+                    ev.prevent_default();
+                    move_before.run(Some(id));
+                }
+            }
+        >
+            {move_before
+                .map(|_| {
+                    view! {
+                        <span class="drag-handle" title="Drag to reorder">
+                            "⠿"
+                        </span>
+                    }
+                })}
+            <input
+                type="checkbox"
+                title="Select for a bulk update"
+                prop:checked=move || actions.selected.with(|selected| selected.contains(&id))
+                on:change=move |ev| {
+                    let checked = event_target_checked(&ev);
+                    actions
+                        .selected
+                        .update(|selected| {
+                            if checked {
+                                selected.insert(id);
+                            } else {
+                                selected.remove(&id);
+                            }
+                        });
+                }
+            />
+            <ActionForm action=actions.toggle>
+                <input type="hidden" name="id" value=row.id />
+                <input type="hidden" name="completed" value=(!row.completed).to_string() />
+                <button title=if row.completed { "Mark active" } else { "Mark completed" }>
+                    {if row.completed { "☑" } else { "☐" }}
+                </button>
+            </ActionForm>
+            " "
+            {move || {
+                if editing.get() {
+                    view! { <RowEditor id text=text.clone() version on_saved on_cancel /> }
+                        .into_any()
+                } else if raw.get() {
+                    view! { <code class="markdown-source">{text.clone()}</code> }.into_any()
+                } else {
+                    view! { <div class="markdown" inner_html=html.clone() /> }.into_any()
+                }
+            }}
+            <Show when=move || !editing.get()>
+                // edits in place, or on a page of its own without JavaScript
+                <a
+                    class="button"
+                    href=edit_href.clone()
+                    on:click=move |ev| {
+                        ev.prevent_default();
+                        set_editing.set(true);
+                    }
+                >
+                    "Edit"
+                </a>
+            </Show>
+            <PrefetchLink href=format!("/rows/{id}")>"Activity"</PrefetchLink>
+            <input
+                type="date"
+                title="Due date"
+                value=row.due.map(|due| due.to_string()).unwrap_or_default()
+                on:change=on_due_change
+            />
+            <TagInput id=format!("row-{id}-tags") tags suggestions on_change />
+            <ActionForm action=actions.delete>
+                <input type="hidden" name="id" value=id />
+                <input type="submit" value="Delete" />
+            </ActionForm>
+            <RowAttachments row_id=id attachments uploads=actions.uploads />
+        </li>
+    }
+}
+
+/// A row's text, status, tags and attachments.
+#[component]
+fn RowView(row: Row) -> impl IntoView {
+    let status = if row.completed { "Completed" } else { "Active" };
+    let due = row
+        .due
+        .map(|due| format!(", due {due}"))
+        .unwrap_or_default();
+    view! {
+        <h2>{format!("Row {}", row.id)}</h2>
+        <div class="markdown" inner_html=row.html />
+        <p>
+            {status} {due}
+            {row.tags.iter().map(|tag| format!(" #{tag}")).collect::<String>()}
+        </p>
+        <ul class="attachments">
+            {row
+                .attachments
+                .into_iter()
+                .map(|attachment| {
+                    view! {
+                        <li>
+                            {attachment.file_name} " "
+                            <RowWithAttachment row_id=row.id attachment_id=attachment.id />
+                        </li>
+                    }
+                })
+                .collect::<Vec<_>>()}
+        </ul>
+    }
+}
+
+/// A single row, by the `id` route parameter, with its activity timeline.
+#[component]
+pub fn RowDetail() -> impl IntoView {
+    let params = use_params_map();
+    let id = Memo::new(move |_| {
+        params.with(|params| params.get("id").and_then(|id| id.parse().ok()))
+    });
+    let row = CachedResource::new(
+        move || id.get(),
+        |id| id.map(|id| RouteData::Row(id).key()),
+        |id| async move {
+            match id {
+                Some(id) => {
+                    cached(RouteData::Row(id).key(), || get_row(id)).await
+                }
+                None => Err(ServerFnError::new("invalid row ID")),
+            }
+        },
+    );
+    // what the list or the last visit had, until the fresh row is in
+    let fallback = move || match row.last_known() {
+        Some(row) => view! {
+            <div class="stale" title="Refreshing...">
+                <RowView row />
+            </div>
+        }
+        .into_any(),
+        None => view! { <p>"Loading..."</p> }.into_any(),
+    };
+
+    view! {
+        <PageMeta
+            title=move || match id.get() {
+                Some(id) => format!("Row {id}"),
+                None => "Row".to_string(),
+            }
+            description="One row and its activity."
+            private=true
+        />
+        <p>
+            <A href="/">"Back to all rows"</A>
+        </p>
+        <Suspense fallback=move || fallback>
+            {move || Suspend::new(async move {
+                match row.resource().await {
+                    Ok(row) => view! { <RowView row /> }.into_any(),
+                    // deleted rows still have a history
+                    Err(e) => view! { <p>{e.to_string()}</p> }.into_any(),
+                }
+            })}
+        </Suspense>
+        <h3>"Activity"</h3>
+        {move || id.get().map(|row_id| view! { <ActivityTimeline row_id /> })}
+    }
+}
+
+/// A row's text in a plain form, where the list's "Edit" leads without
+/// JavaScript.
+#[component]
+pub fn RowEditPage() -> impl IntoView {
+    let params = use_params_map();
+    let id = Memo::new(move |_| {
+        params.with(|params| params.get("id").and_then(|id| id.parse().ok()))
+    });
+    let update = ServerAction::<UpdateRow>::new();
+    let row = Resource::new(
+        move || (id.get(), update.version().get()),
+        |(id, _)| async move {
+            match id {
+                Some(id) => get_row(id).await,
+                None => Err(ServerFnError::new("invalid row ID")),
+            }
+        },
+    );
+
+    view! {
+        <PageMeta
+            title=move || match id.get() {
+                Some(id) => format!("Editing row {id}"),
+                None => "Editing a row".to_string(),
+            }
+            description="Change a row's text."
+            private=true
+        />
+        <p>
+            <A href="/">"Back to all rows"</A>
+        </p>
+        <Transition fallback=|| view! { <p>"Loading..."</p> }>
+            {move || Suspend::new(async move {
+                match row.await {
+                    Ok(row) => {
+                        view! {
+                            <h2>{format!("Row {}", row.id)}</h2>
+                            <ActionForm action=update>
+                                <input type="hidden" name="id" value=row.id />
+                                <input
+                                    type="hidden"
+                                    name="expected_version"
+                                    value=row.version
+                                />
+                                <textarea name="text" maxlength=MAX_ROW_TEXT_LEN>
+                                    {row.text}
+                                </textarea>
+                                <button>"Save"</button>
+                            </ActionForm>
+                        }
+                            .into_any()
+                    }
+                    Err(e) => view! { <p>{e.to_string()}</p> }.into_any(),
+                }
+            })}
+        </Transition>
+        {move || {
+            update
+                .value()
+                .get()
+                .map(|result| match result {
+                    Ok(_) => view! { <p>"Saved."</p> }.into_any(),
+                    Err(e) => view! { <p>{error_message(&e)}</p> }.into_any(),
+                })
+        }}
+    }
+}
+
+/// Applies a [`BulkOp`] to the selected rows, and lists the rows it failed
+/// for.
+#[component]
+fn BulkToolbar(actions: RowActions) -> impl IntoView {
+    let (tag, set_tag) = signal(String::new());
+    let (tag_error, set_tag_error) = signal(None::<String>);
+    let none_selected = move || actions.selected.with(BTreeSet::is_empty);
+    let run = move |op: BulkOp| {
+        let ids = actions.selected.get_untracked().into_iter().collect();
+        actions.bulk.dispatch(BulkUpdate { ids, op });
+    };
+    let run_with_tag =
+        move |op: fn(Tag) -> BulkOp| match Tag::try_from(tag.get_untracked()) {
+            Ok(tag) => {
+                set_tag_error.set(None);
+                run(op(tag));
+            }
+            Err(e) => set_tag_error.set(Some(e.to_string())),
+        };
+    // rows that failed stay selected, ready for another try
+    Effect::new(move || {
+        if let Some(Ok(outcomes)) = actions.bulk.value().get() {
+            actions.selected.update(|selected| {
+                for outcome in outcomes.iter().filter(|o| o.result.is_ok()) {
+                    selected.remove(&outcome.id);
+                }
+            });
+        }
+    });
+    let failures = move || match actions.bulk.value().get() {
+        Some(Ok(outcomes)) => outcomes
+            .into_iter()
+            .filter_map(|outcome| {
+                let error = outcome.result.err()?;
+                Some(format!("Row {}: {error}", outcome.id))
+            })
+            .collect(),
+        Some(Err(e)) => vec![e.to_string()],
+        None => Vec::new(),
+    };
+
+    view! {
+        <div class="bulk-toolbar">
+            {move || format!("{} selected ", actions.selected.with(BTreeSet::len))}
+            <button disabled=none_selected on:click=move |_| run(BulkOp::Complete)>
+                "Complete"
+            </button>
+            <button disabled=none_selected on:click=move |_| run(BulkOp::Reopen)>
+                "Reopen"
+            </button>
+            <button disabled=none_selected on:click=move |_| run(BulkOp::Delete)>
+                "Delete"
+            </button>
+            " "
+            <input
+                type="text"
+                placeholder="Tag"
+                prop:value=tag
+                on:input=move |ev| set_tag.set(event_target_value(&ev))
+            />
+            <button disabled=none_selected on:click=move |_| run_with_tag(BulkOp::AddTag)>
+                "Add tag"
+            </button>
+            <button
+                disabled=none_selected
+                on:click=move |_| run_with_tag(BulkOp::RemoveTag)
+            >
+                "Remove tag"
+            </button>
+            " "
+            <button
+                disabled=none_selected
+                on:click=move |_| actions.selected.update(BTreeSet::clear)
+            >
+                "Clear selection"
+            </button>
+            {move || tag_error.get().map(|error| view! { <small>" " {error}</small> })}
+        </div>
+        <ShowWhen
+            value=Signal::derive(failures)
+            when=|failures: &Vec<String>| !failures.is_empty()
+            let:failures
+        >
+            <ul class="bulk-failures">
+                {failures.into_iter().map(|failure| view! { <li>{failure}</li> }).collect::<Vec<_>>()}
+            </ul>
+        </ShowWhen>
+    }
+}
+
+/// Edits a row's text as it was at `version`. If someone else changes the
+/// row first, saving asks whether to keep this edit, take theirs, or merge.
+#[component]
+fn RowEditor(
+    id: u64,
+    text: String,
+    version: u64,
+    on_saved: Callback<()>,
+    on_cancel: Callback<()>,
+) -> impl IntoView {
+    let update = ServerAction::<UpdateRow>::new();
+    let draft = RwSignal::new(text);
+    // moves forward when a conflict is resolved against a newer version
+    let base_version = RwSignal::new(version);
+    let save = move || {
+        update.dispatch(UpdateRow {
+            id,
+            text: draft.get_untracked(),
+            expected_version: base_version.get_untracked(),
+        });
+    };
+    Effect::new(move || {
+        if let Some(Ok(_)) = update.value().get() {
+            on_saved.run(());
+        }
+    });
+
+    let conflict = move || match update.value().get() {
+        Some(Err(UpdateRowError::Conflict { server_value })) => {
+            Some(server_value)
+        }
+        _ => None,
+    };
+    let error = move || match update.value().get() {
+        Some(Err(UpdateRowError::Conflict { .. })) | Some(Ok(_)) | None => None,
+        Some(Err(e)) => Some(e),
+    };
+    let resolve = move |theirs: &Row, text: String| {
+        base_version.set(theirs.version);
+        draft.set(text);
+        update.value().set(None);
+    };
+
+    view! {
+        <span class="row-editor">
+            <textarea
+                maxlength=MAX_ROW_TEXT_LEN
+                prop:value=draft
+                on:input=move |ev| draft.set(event_target_value(&ev))
+            />
+            <button disabled=move || update.pending().get() on:click=move |_| save()>
+                "Save"
+            </button>
+            <button on:click=move |_| on_cancel.run(())>"Cancel"</button>
+            {move || error().map(|error| view! { <p>{error_message(&error)}</p> })}
+            {move || {
+                conflict()
+                    .map(|theirs| {
+                        let mine = draft.get_untracked();
+                        let merged = merge_text(&theirs.text, &mine);
+                        let keep_mine = {
+                            let (theirs, mine) = (theirs.clone(), mine.clone());
+                            move |_| {
+                                resolve(&theirs, mine.clone());
+                                save();
+                            }
+                        };
+                        let merge = {
+                            let theirs = theirs.clone();
+                            move |_| resolve(&theirs, merged.clone())
+                        };
+                        view! {
+                            <div class="merge-dialog" role="dialog">
+                                <p>"Someone else changed this row while you were editing it."</p>
+                                <p>"Theirs: " <code>{theirs.text}</code></p>
+                                <p>"Yours: " <code>{mine}</code></p>
+                                <button on:click=keep_mine>"Keep mine"</button>
+                                <button on:click=move |_| on_saved.run(())>"Take theirs"</button>
+                                <button on:click=merge>"Merge"</button>
+                            </div>
+                        }
+                    })
+            }}
+        </span>
+    }
+}
+
+/// Moves the row `moved` to right before `before` (or to the end), returning
+/// whether anything changed.
+fn move_row(rows: &mut Vec<Row>, moved: u64, before: Option<u64>) -> bool {
+    if before == Some(moved) {
+        return false;
+    }
+    let Some(from) = rows.iter().position(|row| row.id == moved) else {
+        return false;
+    };
+    let row = rows.remove(from);
+    let to = before
+        .and_then(|before| rows.iter().position(|row| row.id == before))
+        .unwrap_or(rows.len());
+    rows.insert(to, row);
+    true
+}
+
+/// A starting point for merging two edits of a row: every line of `theirs`,
+/// then the lines of `mine` that it does not already have.
+fn merge_text(theirs: &str, mine: &str) -> String {
+    let mut merged = theirs.lines().collect::<Vec<_>>();
+    for line in mine.lines() {
+        if !merged.contains(&line) {
+            merged.push(line);
+        }
+    }
+    merged.join("\n")
+}
+
+/// Tags shown as removable chips, plus an input that adds one on Enter or
+/// `,` and suggests existing tags as you type.
+///
+/// With a `name`, every tag is also rendered as a hidden input of that name
+/// so the chips are submitted with the surrounding form.
+#[component]
+pub fn TagInput(
+    /// Unique on the page; the suggestion list's ID is derived from it.
+    id: String,
+    tags: RwSignal<Vec<Tag>>,
+    #[prop(into)] suggestions: Signal<Vec<Tag>>,
+    #[prop(optional)] name: Option<&'static str>,
+    /// Called with the new tags whenever one is added or removed.
+    #[prop(optional)]
+    on_change: Option<Callback<Vec<Tag>>>,
+) -> impl IntoView {
+    let list_id = format!("{id}-suggestions");
+    let (draft, set_draft) = signal(String::new());
+    let (error, set_error) = signal(None::<String>);
+
+    let changed = move || {
+        if let Some(on_change) = on_change {
+            on_change.run(tags.get_untracked());
+        }
+    };
+    let add = move || {
+        let tag = match Tag::try_from(draft.get_untracked()) {
+            Ok(tag) => tag,
+            Err(e) => {
+                set_error.set(Some(e.to_string()));
+                return;
+            }
+        };
+        set_draft.set(String::new());
+        if tags.with_untracked(|tags| tags.contains(&tag)) {
+            return;
+        }
+        if tags.with_untracked(Vec::len) >= MAX_ROW_TAGS {
+            let error = format!("at most {MAX_ROW_TAGS} tags are allowed");
+            set_error.set(Some(error));
+            return;
+        }
+        set_error.set(None);
+        tags.update(|tags| {
+            tags.push(tag);
+            tags.sort();
+        });
+        changed();
+    };
+    let remove = move |tag: &Tag| {
+        tags.update(|tags| tags.retain(|t| t != tag));
+        changed();
+    };
+    let on_keydown = move |ev: KeyboardEvent| {
+        let empty = draft.with_untracked(|draft| draft.trim().is_empty());
+        match ev.key().as_str() {
+            // an empty draft lets Enter submit the surrounding form
+            "Enter" | "," if !empty => {
+                ev.prevent_default();
+                add();
+            }
+            "," => ev.prevent_default(),
+            "Backspace" if draft.with_untracked(String::is_empty) => {
+                if let Some(last) =
+                    tags.with_untracked(|tags| tags.last().cloned())
+                {
+                    remove(&last);
+                }
+            }
+            _ => {}
+        }
+    };
+
+    view! {
+        <span class="tag-input">
+            <For each=move || tags.get() key=|tag| tag.clone() let:tag>
+                <span class="tag">
+                    {tag.to_string()}
+                    {name.map(|name| view! { <input type="hidden" name=name value=tag.to_string() /> })}
+                    <button
+                        type="button"
+                        title=format!("Remove {tag}")
+                        on:click=move |_| remove(&tag)
+                    >
+                        "×"
+                    </button>
+                </span>
+            </For>
+            <input
+                type="text"
+                placeholder="Add tag"
+                list=list_id.clone()
+                prop:value=draft
+                on:input=move |ev| set_draft.set(event_target_value(&ev))
+                on:keydown=on_keydown
+            />
+            <datalist id=list_id>
+                {move || {
+                    suggestions
+                        .get()
+                        .into_iter()
+                        .filter(|tag| tags.with(|tags| !tags.contains(tag)))
+                        .map(|tag| view! { <option value=tag.to_string() /> })
+                        .collect::<Vec<_>>()
+                }}
+            </datalist>
+            {move || error.get().map(|error| view! { <small>{error}</small> })}
+        </span>
+    }
+}
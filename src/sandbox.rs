@@ -0,0 +1,215 @@
+//! Anonymous visitors get a sandbox of their own, so trying the examples
+//! never touches anyone else's rows. A sandbox is named by a cookie, and
+//! owns rows, upload progress and watcher subscriptions just like an
+//! account does; signing in switches to the account's own.
+//!
+//! A sandbox is started when the demo page is rendered for a signed-out
+//! visitor (but not a crawler), or by the first call that needs one.
+
+use leptos::prelude::*;
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use crate::{
+        attachments::remove_files,
+        auth::{cookie, current_user, session_user, set_cookie, UserId},
+        cache::{CacheTag, CACHE},
+        errors::SandboxError,
+        storage::ROWS,
+        tenants::{
+            current_tenant, tenant_of, tenant_owner, tenant_owners, tenant_user,
+        },
+    };
+    use dashmap::DashMap;
+    use http::{request::Parts, HeaderMap};
+    use leptos::prelude::use_context;
+    use std::{
+        future::Future,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            LazyLock,
+        },
+        time::{Duration, Instant},
+    };
+    use tokio::sync::watch;
+
+    /// The cookie naming an anonymous visitor's sandbox.
+    pub const SANDBOX_COOKIE: &str = "sandbox";
+
+    /// Sandboxes own rows under IDs from here up, well clear of the IDs
+    /// accounts are given.
+    pub const FIRST_SANDBOX_OWNER: UserId = 1 << 48;
+
+    /// A sandbox nobody has used for this long is deleted, rows and all.
+    pub const SANDBOX_IDLE: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// How often [`expire_idle`] runs.
+    pub const EXPIRE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+    /// Cookie token -> the sandbox it names.
+    static SANDBOXES: LazyLock<DashMap<String, Sandbox>> =
+        LazyLock::new(DashMap::new);
+
+    static NEXT_OWNER: AtomicU64 = AtomicU64::new(FIRST_SANDBOX_OWNER);
+
+    struct Sandbox {
+        owner: UserId,
+        last_seen: Instant,
+        /// Bumped on every reset, which ends the sandbox's open streams.
+        resets: watch::Sender<u64>,
+    }
+
+    impl Sandbox {
+        fn new() -> Self {
+            Self {
+                owner: NEXT_OWNER.fetch_add(1, Ordering::Relaxed),
+                last_seen: Instant::now(),
+                resets: watch::Sender::new(0),
+            }
+        }
+    }
+
+    /// Whose rows a request with `headers` works with: the signed-in
+    /// user's, or else the visitor's sandbox's, if it has one, in the
+    /// request's tenant. For handlers outside Leptos, like
+    /// [`session_user`].
+    pub fn session_owner(headers: &HeaderMap) -> Option<UserId> {
+        let tenant = tenant_of(headers).ok()?;
+        let user = match session_user(headers) {
+            Some(user) => user.id,
+            None => {
+                let token = cookie(headers, SANDBOX_COOKIE)?;
+                let mut sandbox = SANDBOXES.get_mut(&token)?;
+                sandbox.last_seen = Instant::now();
+                sandbox.owner
+            }
+        };
+        Some(tenant_owner(&tenant, user))
+    }
+
+    /// Like [`session_owner`], for the current request. Never starts a
+    /// sandbox, so reading doesn't need one.
+    pub fn current_owner() -> Option<UserId> {
+        session_owner(&use_context::<Parts>()?.headers)
+    }
+
+    /// Like [`current_owner`], but starts a sandbox for a visitor without
+    /// one and sets its cookie. Only an error outside a request.
+    pub fn require_owner() -> Result<UserId, SandboxError> {
+        if let Some(owner) = current_owner() {
+            return Ok(owner);
+        }
+        use_context::<Parts>().ok_or(SandboxError::NoRequest)?;
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let sandbox = Sandbox::new();
+        let owner = sandbox.owner;
+        set_cookie(&format!(
+            "{SANDBOX_COOKIE}={token}; Path=/; HttpOnly; SameSite=Lax"
+        ));
+        SANDBOXES.insert(token, sandbox);
+        Ok(tenant_owner(&current_tenant(), owner))
+    }
+
+    /// Resolves when `owner`'s sandbox is reset or expires, so a stream
+    /// can end with it. Never resolves for accounts.
+    pub fn until_reset(owner: UserId) -> impl Future<Output = ()> + Send {
+        let resets = SANDBOXES
+            .iter()
+            .find(|sandbox| sandbox.owner == tenant_user(owner))
+            .map(|sandbox| sandbox.resets.subscribe());
+        async move {
+            match resets {
+                // a dropped sender (an expired sandbox) also ends the wait
+                Some(mut resets) => _ = resets.changed().await,
+                None => std::future::pending().await,
+            }
+        }
+    }
+
+    /// Deletes the sandbox `user`'s rows in every tenant, and their
+    /// attachments.
+    async fn clear(user: UserId) {
+        for owner in tenant_owners(user) {
+            let rows = ROWS.remove_owner(owner);
+            CACHE.invalidate(CacheTag::Rows(owner));
+            for row in rows {
+                remove_files(&row.attachments).await;
+            }
+        }
+    }
+
+    /// Empties the current visitor's sandbox and ends its open streams.
+    /// Signed-in users have no sandbox to reset. Quotas are kept, so a
+    /// reset doesn't start the day's allowance over.
+    pub async fn reset_current() -> Result<(), SandboxError> {
+        if current_user().is_some() {
+            return Err(SandboxError::SignedIn);
+        }
+        let token = use_context::<Parts>()
+            .and_then(|parts| cookie(&parts.headers, SANDBOX_COOKIE))
+            .ok_or(SandboxError::NoSandbox)?;
+        let owner = {
+            let sandbox =
+                SANDBOXES.get(&token).ok_or(SandboxError::NoSandbox)?;
+            sandbox.resets.send_modify(|n| *n += 1);
+            sandbox.owner
+        };
+        clear(owner).await;
+        Ok(())
+    }
+
+    /// Deletes the sandboxes nobody has used for [`SANDBOX_IDLE`].
+    pub async fn expire_idle() {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        SANDBOXES.retain(|_, sandbox| {
+            let idle = now - sandbox.last_seen > SANDBOX_IDLE;
+            if idle {
+                expired.push(sandbox.owner);
+            }
+            !idle
+        });
+        for owner in expired {
+            tracing::info!(owner, "expired sandbox");
+            clear(owner).await;
+        }
+    }
+}
+
+/// Deletes everything in the caller's sandbox and ends its open streams.
+#[server]
+pub async fn reset_sandbox() -> Result<(), ServerFnError> {
+    Ok(reset_current().await?)
+}
+
+/// A button that resets the visitor's sandbox, then reloads the page so
+/// every example starts over.
+#[component]
+pub fn ResetSandbox() -> impl IntoView {
+    let reset = Action::new(|_: &()| async {
+        let result = reset_sandbox().await;
+        if result.is_ok() {
+            _ = window().location().reload();
+        }
+        result
+    });
+
+    view! {
+        <p>
+            "Signed out, the examples work in a sandbox of your own, which is deleted after a day of disuse. "
+            <button on:click=move |_| {
+                reset.dispatch(());
+            }>"Reset my sandbox"</button>
+            {move || {
+                reset
+                    .value()
+                    .get()
+                    .and_then(Result::err)
+                    .map(|e| view! { <span>" " {e.to_string()}</span> })
+            }}
+        </p>
+    }
+}
@@ -0,0 +1,334 @@
+//! Uploaded attachments wait in quarantine until a [`Scanner`] has looked at
+//! them, and only the ones it clears are moved to where they can be
+//! downloaded from. Scans run in the background, and their progress is
+//! streamed to whoever wants to know.
+
+use serde::{Deserialize, Serialize};
+
+/// Where an attachment is in the scan it has to pass before it can be
+/// downloaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScanStatus {
+    /// Waiting in quarantine, or being scanned.
+    Quarantined,
+    /// Cleared, and downloadable.
+    Clean,
+    /// The scanner found a problem, and the file has been deleted.
+    Rejected(String),
+    /// The scanner couldn't decide, so the file stays in quarantine.
+    Failed(String),
+}
+
+impl ScanStatus {
+    /// Whether the scan is over, one way or the other.
+    pub fn is_done(&self) -> bool {
+        !matches!(self, ScanStatus::Quarantined)
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::ScanStatus;
+    use crate::{
+        blobs,
+        channels::{Latest, Sequenced},
+        settings::{ScanSettings, ScannerKind},
+    };
+    use dashmap::DashMap;
+    use futures::{future::BoxFuture, Stream};
+    use std::{
+        path::{Path, PathBuf},
+        sync::{LazyLock, OnceLock},
+    };
+    use tokio::{
+        fs,
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    /// Where uploads wait to be scanned, each named by its attachment's ID.
+    pub const QUARANTINE_DIR: &str = "./attachments/quarantine";
+    const CLAMD_CHUNK_SIZE: usize = 64 * 1024;
+
+    /// The magic numbers of Windows, Linux and macOS executables.
+    const EXECUTABLE_MAGIC: [&[u8]; 6] = [
+        b"MZ",
+        b"\x7fELF",
+        b"\xfe\xed\xfa\xce",
+        b"\xfe\xed\xfa\xcf",
+        b"\xce\xfa\xed\xfe",
+        b"\xcf\xfa\xed\xfe",
+    ];
+
+    /// Set from `[scanning]`; files are cleared without a scan until then.
+    static SCANNER: OnceLock<Box<dyn Scanner>> = OnceLock::new();
+
+    /// Scans started since the server did, by attachment ID.
+    static SCANS: LazyLock<DashMap<String, Scan>> = LazyLock::new(DashMap::new);
+
+    struct Scan {
+        status: ScanStatus,
+        channel: Latest<ScanStatus>,
+    }
+
+    /// Applies the `[scanning]` settings; call it once, before serving.
+    pub fn init(settings: &ScanSettings) {
+        let scanner: Box<dyn Scanner> = match settings.scanner {
+            ScannerKind::None => Box::new(NoopScanner),
+            ScannerKind::Clamd => Box::new(ClamdScanner {
+                address: settings.clamd_address.clone(),
+            }),
+            ScannerKind::Heuristic => Box::new(HeuristicScanner {
+                max_text_entropy: settings.max_text_entropy,
+            }),
+        };
+        _ = SCANNER.set(scanner);
+    }
+
+    fn scanner() -> &'static dyn Scanner {
+        SCANNER.get().map_or(&NoopScanner, Box::as_ref)
+    }
+
+    /// A quarantined file, as a scanner sees it.
+    pub struct Suspect<'a> {
+        pub path: &'a Path,
+        /// What the uploader said it is.
+        pub content_type: &'a str,
+        pub size: u64,
+    }
+
+    /// What a scanner made of a file.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Verdict {
+        Clean,
+        Rejected(String),
+    }
+
+    /// Decides whether a quarantined file may be downloaded. An error means
+    /// the scanner couldn't tell, and the file stays in quarantine.
+    pub trait Scanner: Send + Sync {
+        fn scan<'a>(
+            &'a self,
+            file: Suspect<'a>,
+        ) -> BoxFuture<'a, Result<Verdict, String>>;
+    }
+
+    /// Clears every file.
+    pub struct NoopScanner;
+
+    impl Scanner for NoopScanner {
+        fn scan<'a>(
+            &'a self,
+            _file: Suspect<'a>,
+        ) -> BoxFuture<'a, Result<Verdict, String>> {
+            Box::pin(async { Ok(Verdict::Clean) })
+        }
+    }
+
+    /// Streams each file to a ClamAV daemon with its `INSTREAM` command.
+    pub struct ClamdScanner {
+        pub address: String,
+    }
+
+    impl Scanner for ClamdScanner {
+        fn scan<'a>(
+            &'a self,
+            file: Suspect<'a>,
+        ) -> BoxFuture<'a, Result<Verdict, String>> {
+            Box::pin(async move {
+                let clamd_error = |e: std::io::Error| {
+                    format!("clamd at {}: {e}", self.address)
+                };
+                let mut source = fs::File::open(file.path)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let mut clamd = TcpStream::connect(&self.address)
+                    .await
+                    .map_err(clamd_error)?;
+                clamd.write_all(b"zINSTREAM\0").await.map_err(clamd_error)?;
+                // length-prefixed chunks, ended by an empty one
+                let mut buf = vec![0; CLAMD_CHUNK_SIZE];
+                loop {
+                    let n = source
+                        .read(&mut buf)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    clamd
+                        .write_all(&(n as u32).to_be_bytes())
+                        .await
+                        .map_err(clamd_error)?;
+                    if n == 0 {
+                        break;
+                    }
+                    clamd.write_all(&buf[..n]).await.map_err(clamd_error)?;
+                }
+                let mut reply = Vec::new();
+                clamd.read_to_end(&mut reply).await.map_err(clamd_error)?;
+                let reply = String::from_utf8_lossy(&reply);
+                let reply = reply.trim_end_matches(['\0', '\n']);
+                if reply == "stream: OK" {
+                    Ok(Verdict::Clean)
+                } else if let Some(signature) = reply
+                    .strip_prefix("stream: ")
+                    .and_then(|found| found.strip_suffix(" FOUND"))
+                {
+                    Ok(Verdict::Rejected(format!("clamd found {signature}")))
+                } else {
+                    Err(format!("clamd answered {reply:?}"))
+                }
+            })
+        }
+    }
+
+    /// Rejects executables, whatever they claim to be, and files declared
+    /// as text that aren't UTF-8 or have more entropy than text does.
+    pub struct HeuristicScanner {
+        /// In bits per byte.
+        pub max_text_entropy: f64,
+    }
+
+    impl Scanner for HeuristicScanner {
+        fn scan<'a>(
+            &'a self,
+            file: Suspect<'a>,
+        ) -> BoxFuture<'a, Result<Verdict, String>> {
+            Box::pin(async move {
+                // attachments are small enough to read whole
+                let bytes =
+                    fs::read(file.path).await.map_err(|e| e.to_string())?;
+                if EXECUTABLE_MAGIC
+                    .iter()
+                    .any(|magic| bytes.starts_with(magic))
+                {
+                    return Ok(Verdict::Rejected(
+                        "it's an executable".to_string(),
+                    ));
+                }
+                if file.content_type.starts_with("text/") {
+                    if std::str::from_utf8(&bytes).is_err() {
+                        return Ok(Verdict::Rejected(
+                            "it's declared as text but isn't UTF-8".to_string(),
+                        ));
+                    }
+                    let entropy = entropy(&bytes);
+                    if entropy > self.max_text_entropy {
+                        return Ok(Verdict::Rejected(format!(
+                            "it's declared as text but has {entropy:.1} bits \
+                             of entropy per byte"
+                        )));
+                    }
+                }
+                Ok(Verdict::Clean)
+            })
+        }
+    }
+
+    /// Shannon entropy of `bytes`, in bits per byte.
+    fn entropy(bytes: &[u8]) -> f64 {
+        let mut counts = [0usize; 256];
+        for byte in bytes {
+            counts[*byte as usize] += 1;
+        }
+        let len = bytes.len() as f64;
+        counts
+            .iter()
+            .filter(|count| **count > 0)
+            .map(|count| {
+                let p = *count as f64 / len;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
+    pub fn quarantine_path(id: &str) -> PathBuf {
+        Path::new(QUARANTINE_DIR).join(id)
+    }
+
+    fn set_status(id: &str, status: ScanStatus) {
+        let mut scan = SCANS.entry(id.to_string()).or_insert_with(|| Scan {
+            status: ScanStatus::Quarantined,
+            channel: Latest::new(format!("scan:{id}")),
+        });
+        scan.status = status.clone();
+        scan.channel.send(status);
+    }
+
+    /// Scans attachment `id`'s quarantined file in the background. A file
+    /// that's cleared is moved to the blob store under `id`, and then
+    /// `on_clean` runs; one that's rejected is deleted.
+    pub fn spawn_scan(
+        id: String,
+        content_type: String,
+        size: u64,
+        on_clean: impl FnOnce() + Send + 'static,
+    ) {
+        set_status(&id, ScanStatus::Quarantined);
+        tokio::spawn(async move {
+            let path = quarantine_path(&id);
+            let suspect = Suspect {
+                path: &path,
+                content_type: &content_type,
+                size,
+            };
+            let status = match scanner().scan(suspect).await {
+                Ok(Verdict::Clean) => {
+                    let stored = blobs::store()
+                        .put_file(&id, &path, &content_type)
+                        .await;
+                    match stored {
+                        Ok(()) => ScanStatus::Clean,
+                        Err(e) => ScanStatus::Failed(e.to_string()),
+                    }
+                }
+                Ok(Verdict::Rejected(reason)) => {
+                    tracing::warn!(%id, %reason, "rejected attachment");
+                    _ = fs::remove_file(&path).await;
+                    ScanStatus::Rejected(reason)
+                }
+                Err(e) => {
+                    tracing::warn!(%id, "couldn't scan attachment: {e}");
+                    ScanStatus::Failed(e)
+                }
+            };
+            // before the status is out, so whatever `on_clean` starts is
+            // there for anyone who sees it
+            if status == ScanStatus::Clean {
+                on_clean();
+            }
+            set_status(&id, status);
+        });
+    }
+
+    /// The status of attachment `id`'s scan, if one has run since the
+    /// server started.
+    pub fn status(id: &str) -> Option<ScanStatus> {
+        SCANS.get(id).map(|scan| scan.status.clone())
+    }
+
+    /// Attachment `id`'s status, then every change to it; `current` is the
+    /// status of an attachment with no scan on record.
+    pub fn subscribe(
+        id: &str,
+        current: ScanStatus,
+        after: Option<u64>,
+    ) -> impl Stream<Item = Sequenced<ScanStatus>> {
+        let scan = SCANS.entry(id.to_string()).or_insert_with(|| {
+            let channel = Latest::new(format!("scan:{id}"));
+            channel.send(current.clone());
+            Scan {
+                status: current,
+                channel,
+            }
+        });
+        scan.channel.subscribe_from(after)
+    }
+
+    /// Deletes attachment `id`'s quarantined file and scan, if it has them.
+    pub async fn remove(id: &str) {
+        SCANS.remove(id);
+        _ = fs::remove_file(quarantine_path(id)).await;
+    }
+}
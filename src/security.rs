@@ -0,0 +1,71 @@
+use crate::settings::SecuritySettings;
+use http::{
+    header::{CONTENT_SECURITY_POLICY, CONTENT_SECURITY_POLICY_REPORT_ONLY},
+    HeaderName, HeaderValue,
+};
+use leptos::{nonce::use_nonce, prelude::*};
+use leptos_axum::ResponseOptions;
+
+/// A Content-Security-Policy template, rendered once per page so it can
+/// carry the nonce leptos_axum attaches to the hydration and islands scripts.
+#[derive(Debug, Clone)]
+pub struct ContentSecurityPolicy {
+    header: HeaderName,
+    connect_src: String,
+    frame_ancestors: String,
+}
+
+impl ContentSecurityPolicy {
+    pub fn new(settings: &SecuritySettings, options: &LeptosOptions) -> Self {
+        let mut connect_src = vec!["'self'".to_string()];
+        // `cargo leptos watch` reloads the page over a websocket on another port
+        if options.watch {
+            connect_src.extend(["ws:".to_string(), "wss:".to_string()]);
+        }
+        connect_src.extend(settings.connect_src.iter().cloned());
+
+        let frame_ancestors = if settings.frame_ancestors.is_empty() {
+            "'none'".to_string()
+        } else {
+            settings.frame_ancestors.join(" ")
+        };
+
+        Self {
+            header: if settings.csp_report_only {
+                CONTENT_SECURITY_POLICY_REPORT_ONLY
+            } else {
+                CONTENT_SECURITY_POLICY
+            },
+            connect_src: connect_src.join(" "),
+            frame_ancestors,
+        }
+    }
+
+    pub fn render(&self, nonce: &str) -> String {
+        format!(
+            "default-src 'self'; \
+             script-src 'nonce-{nonce}' 'strict-dynamic' 'wasm-unsafe-eval'; \
+             style-src 'self' 'nonce-{nonce}'; style-src-attr 'unsafe-inline'; \
+             img-src 'self' data: blob:; connect-src {}; object-src 'none'; \
+             base-uri 'self'; form-action 'self'; frame-ancestors {}",
+            self.connect_src, self.frame_ancestors
+        )
+    }
+}
+
+/// Adds the Content-Security-Policy header for the page being rendered.
+///
+/// Called from the shell, which is where the nonce and the response options
+/// for the current request are both in context.
+pub fn set_content_security_policy() {
+    let (Some(csp), Some(nonce), Some(response)) = (
+        use_context::<ContentSecurityPolicy>(),
+        use_nonce(),
+        use_context::<ResponseOptions>(),
+    ) else {
+        return;
+    };
+    if let Ok(value) = HeaderValue::from_str(&csp.render(&nonce)) {
+        response.insert_header(csp.header.clone(), value);
+    }
+}
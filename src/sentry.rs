@@ -0,0 +1,400 @@
+//! Sends server fn failures and client error reports to Sentry, or anything
+//! else that takes Sentry's store API, when `[sentry] dsn` is set.
+//!
+//! Each event is tagged with who was signed in and a hash of their session,
+//! never the session token itself, and carries that session's latest server
+//! fn calls, as [`LoggingLayer`](crate::middleware::LoggingLayer) logged
+//! them, as breadcrumbs.
+
+use crate::{
+    auth::{session_token, session_user, User},
+    client_errors::ReceivedClientError,
+    errors::{DsnError, REQUEST_ID_HEADER},
+    middleware::is_server_fn_path,
+    settings::SentrySettings,
+};
+use chrono::{DateTime, Utc};
+use http::{header::CONTENT_TYPE, Method, Request, Response, StatusCode};
+use pin_project_lite::pin_project;
+use reqwest::Url;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::{LazyLock, Mutex, OnceLock},
+    task::{ready, Context, Poll},
+};
+use tower::{Layer, Service};
+
+/// The most sessions breadcrumbs are kept for at once.
+const MAX_SESSIONS: usize = 1024;
+
+/// Where events go, from a DSN like `https://<key>@<host>/<project>`.
+#[derive(Debug, Clone)]
+pub struct Dsn {
+    store_url: Url,
+    public_key: String,
+}
+
+impl FromStr for Dsn {
+    type Err = DsnError;
+
+    fn from_str(dsn: &str) -> Result<Self, Self::Err> {
+        let url =
+            Url::parse(dsn).map_err(|e| DsnError::InvalidUrl(e.to_string()))?;
+        let public_key = url.username().to_string();
+        if public_key.is_empty() {
+            return Err(DsnError::MissingKey);
+        }
+        let path = url.path().trim_end_matches('/');
+        let (prefix, project) =
+            path.rsplit_once('/').ok_or(DsnError::MissingProject)?;
+        if project.is_empty() {
+            return Err(DsnError::MissingProject);
+        }
+        let mut store_url = url.clone();
+        _ = store_url.set_username("");
+        _ = store_url.set_password(None);
+        store_url.set_path(&format!("{prefix}/api/{project}/store/"));
+        Ok(Dsn {
+            store_url,
+            public_key,
+        })
+    }
+}
+
+impl Dsn {
+    fn auth_header(&self) -> String {
+        format!(
+            "Sentry sentry_version=7, sentry_client={}/{}, sentry_key={}",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            self.public_key
+        )
+    }
+}
+
+struct Exporter {
+    dsn: Dsn,
+    environment: String,
+    max_breadcrumbs: usize,
+    client: reqwest::Client,
+}
+
+static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+
+/// Session token -> its latest breadcrumbs, oldest first.
+static BREADCRUMBS: LazyLock<Mutex<HashMap<String, VecDeque<Breadcrumb>>>> =
+    LazyLock::new(Default::default);
+
+/// Starts exporting to `[sentry] dsn`, if it's set; call it once, before
+/// serving.
+pub fn init(settings: &SentrySettings) -> Result<(), DsnError> {
+    let Some(dsn) = &settings.dsn else {
+        return Ok(());
+    };
+    _ = EXPORTER.set(Exporter {
+        dsn: dsn.parse()?,
+        environment: settings.environment.clone(),
+        max_breadcrumbs: settings.max_breadcrumbs,
+        client: reqwest::Client::new(),
+    });
+    Ok(())
+}
+
+/// One server fn call, in Sentry's breadcrumb format.
+#[derive(Debug, Clone, Serialize)]
+pub struct Breadcrumb {
+    pub timestamp: DateTime<Utc>,
+    pub category: &'static str,
+    pub level: &'static str,
+    pub message: String,
+    pub data: Value,
+}
+
+impl Breadcrumb {
+    pub fn server_fn_call(
+        path: &str,
+        status: u16,
+        elapsed_ms: f64,
+        slow: bool,
+    ) -> Self {
+        Breadcrumb {
+            timestamp: Utc::now(),
+            category: "server_fn",
+            level: if slow { "warning" } else { "info" },
+            message: path.to_string(),
+            data: json!({ "status": status, "elapsed_ms": elapsed_ms }),
+        }
+    }
+}
+
+/// Keeps `breadcrumb` for the session with `token`, dropping its oldest
+/// past `[sentry] max_breadcrumbs`. Nothing is kept while exporting is off.
+pub fn add_breadcrumb(token: &str, breadcrumb: Breadcrumb) {
+    let Some(exporter) = EXPORTER.get() else {
+        return;
+    };
+    let mut sessions = BREADCRUMBS.lock().unwrap();
+    if !sessions.contains_key(token) && sessions.len() >= MAX_SESSIONS {
+        // whichever session comes first; they all age out the same way
+        if let Some(other) = sessions.keys().next().cloned() {
+            sessions.remove(&other);
+        }
+    }
+    let breadcrumbs = sessions.entry(token.to_string()).or_default();
+    breadcrumbs.push_back(breadcrumb);
+    while breadcrumbs.len() > exporter.max_breadcrumbs {
+        breadcrumbs.pop_front();
+    }
+}
+
+fn breadcrumbs(token: Option<&str>) -> Vec<Breadcrumb> {
+    token
+        .and_then(|token| {
+            BREADCRUMBS
+                .lock()
+                .unwrap()
+                .get(token)
+                .map(|breadcrumbs| breadcrumbs.iter().cloned().collect())
+        })
+        .unwrap_or_default()
+}
+
+/// What to tag a session with instead of its token.
+fn session_hash(token: &str) -> String {
+    let digest = format!("{:x}", Sha256::digest(token.as_bytes()));
+    digest[..16].to_string()
+}
+
+/// What every event has: who it happened to and what they did before.
+fn event(
+    platform: &str,
+    message: &str,
+    user: Option<&User>,
+    token: Option<&str>,
+    mut tags: Map<String, Value>,
+) -> Map<String, Value> {
+    let mut event = Map::new();
+    event.insert(
+        "event_id".to_string(),
+        uuid::Uuid::new_v4().simple().to_string().into(),
+    );
+    event.insert("timestamp".to_string(), Utc::now().to_rfc3339().into());
+    event.insert("platform".to_string(), platform.into());
+    event.insert("level".to_string(), "error".into());
+    event.insert("message".to_string(), json!({ "formatted": message }));
+    if let Some(exporter) = EXPORTER.get() {
+        event.insert(
+            "environment".to_string(),
+            exporter.environment.clone().into(),
+        );
+    }
+    event.insert("release".to_string(), env!("CARGO_PKG_VERSION").into());
+    if let Some(user) = user {
+        event.insert(
+            "user".to_string(),
+            json!({ "id": user.id.to_string(), "username": user.name }),
+        );
+        tags.insert("role".to_string(), format!("{:?}", user.role).into());
+    }
+    if let Some(token) = token {
+        tags.insert("session".to_string(), session_hash(token).into());
+    }
+    event.insert("tags".to_string(), tags.into());
+    event.insert(
+        "breadcrumbs".to_string(),
+        json!({ "values": breadcrumbs(token) }),
+    );
+    event
+}
+
+/// Sends `event` in the background, if exporting is on. Failures are only
+/// logged, so an unreachable Sentry never fails a request.
+fn send(event: Map<String, Value>) {
+    let Some(exporter) = EXPORTER.get() else {
+        return;
+    };
+    let body = match serde_json::to_vec(&event) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::warn!(error = %e, "couldn't serialize a Sentry event");
+            return;
+        }
+    };
+    let request = exporter
+        .client
+        .post(exporter.dsn.store_url.clone())
+        .header("X-Sentry-Auth", exporter.dsn.auth_header())
+        .header(CONTENT_TYPE, "application/json")
+        .body(body);
+    tokio::spawn(async move {
+        match request.send().await {
+            Ok(res) if !res.status().is_success() => {
+                tracing::warn!(status = %res.status(), "Sentry rejected an event");
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::warn!(error = %e, "couldn't send an event to Sentry")
+            }
+        }
+    });
+}
+
+/// Sends a server fn call that failed with `status`.
+pub fn capture_server_fn_error(
+    method: &Method,
+    path: &str,
+    status: StatusCode,
+    request_id: Option<&str>,
+    user: Option<&User>,
+    token: Option<&str>,
+) {
+    let mut tags = Map::new();
+    tags.insert("kind".to_string(), "server_fn".into());
+    tags.insert("server_fn".to_string(), path.into());
+    tags.insert("status".to_string(), status.as_u16().into());
+    if let Some(request_id) = request_id {
+        tags.insert("request_id".to_string(), request_id.into());
+    }
+    let message = format!("{method} {path} failed with {status}");
+    let mut event = event("rust", &message, user, token, tags);
+    event.insert(
+        "request".to_string(),
+        json!({ "method": method.as_str(), "url": path }),
+    );
+    send(event);
+}
+
+/// Sends a report from the browser, as [`record`] kept it.
+///
+/// [`record`]: crate::client_errors::record
+pub fn capture_client_error(
+    received: &ReceivedClientError,
+    token: Option<&str>,
+) {
+    let error = &received.error;
+    let mut tags = Map::new();
+    tags.insert("kind".to_string(), error.kind.to_string().into());
+    tags.insert("route".to_string(), error.route.clone().into());
+    tags.insert("report_id".to_string(), received.id.clone().into());
+    if let Some(request_id) = &error.request_id {
+        tags.insert("request_id".to_string(), request_id.clone().into());
+    }
+    let mut event = event(
+        "javascript",
+        &error.message,
+        received.user.as_ref(),
+        token,
+        tags,
+    );
+    event.insert(
+        "exception".to_string(),
+        json!({ "values": [{
+            "type": error.kind.to_string(),
+            "value": error.message,
+        }] }),
+    );
+    if let Some(stack) = &error.stack {
+        event.insert("extra".to_string(), json!({ "stack": stack }));
+    }
+    send(event);
+}
+
+/// Sends every server fn response with a 5xx status, including panics, to
+/// Sentry.
+#[derive(Clone, Copy, Default)]
+pub struct SentryLayer;
+
+impl<S> Layer<S> for SentryLayer {
+    type Service = SentryService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SentryService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct SentryService<T> {
+    inner: T,
+}
+
+/// The request a [`SentryFuture`] may have to report.
+struct Call {
+    method: Method,
+    path: String,
+    token: Option<String>,
+    user: Option<User>,
+}
+
+impl<T, ReqBody, ResBody> Service<Request<ReqBody>> for SentryService<T>
+where
+    T: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = SentryFuture<T::Future>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let path = req.uri().path();
+        let exported = EXPORTER.get().is_some() && is_server_fn_path(path);
+        let call = exported.then(|| Call {
+            method: req.method().clone(),
+            path: path.to_string(),
+            token: session_token(req.headers()),
+            user: session_user(req.headers()),
+        });
+        SentryFuture {
+            call,
+            inner: self.inner.call(req),
+        }
+    }
+}
+
+pin_project! {
+    pub struct SentryFuture<T> {
+        call: Option<Call>,
+        #[pin]
+        inner: T,
+    }
+}
+
+impl<T, ResBody, E> Future for SentryFuture<T>
+where
+    T: Future<Output = Result<Response<ResBody>, E>>,
+{
+    type Output = T::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx))?;
+        if let Some(call) = this.call.take() {
+            if res.status().is_server_error() {
+                let request_id = res
+                    .headers()
+                    .get(REQUEST_ID_HEADER)
+                    .and_then(|id| id.to_str().ok());
+                capture_server_fn_error(
+                    &call.method,
+                    &call.path,
+                    res.status(),
+                    request_id,
+                    call.user.as_ref(),
+                    call.token.as_deref(),
+                );
+            }
+        }
+        Poll::Ready(Ok(res))
+    }
+}
@@ -0,0 +1,101 @@
+use leptos::prelude::*;
+use leptos_meta::{Meta, Title};
+
+/// The name every page's title ends with.
+pub const SITE_NAME: &str = "Server Function Demo";
+
+/// Routes left out of `/sitemap.xml`: they're per-user or admin-only, so
+/// their pages are also marked `noindex` with [`PageMeta`]'s `private`.
+pub const UNLISTED_PATHS: [&str; 5] =
+    ["/admin", "/api-keys", "/load", "/profile", "/trash"];
+
+/// The `<title>` and description of the page being rendered, and whether
+/// search engines should leave it out. Rendered on the server into the
+/// shell's `<head>`, and kept up to date while navigating.
+#[component]
+pub fn PageMeta(
+    #[prop(into)] title: TextProp,
+    description: &'static str,
+    #[prop(optional)] private: bool,
+) -> impl IntoView {
+    view! {
+        <Title text=title />
+        <Meta name="description" content=description />
+        {private.then(|| view! { <Meta name="robots" content="noindex" /> })}
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::UNLISTED_PATHS;
+    use crate::base_path::BasePath;
+    use axum::{
+        http::{
+            header::{CONTENT_TYPE, HOST},
+            HeaderMap,
+        },
+        response::IntoResponse,
+    };
+    use leptos_axum::AxumRouteListing;
+    use std::sync::Arc;
+
+    /// The paths `/sitemap.xml` lists: every route without parameters,
+    /// except the unlisted ones.
+    pub fn sitemap_paths(routes: &[AxumRouteListing]) -> Arc<[String]> {
+        let mut paths = routes
+            .iter()
+            .map(|route| route.path().to_string())
+            .filter(|path| {
+                !path.contains(['{', '*'])
+                    && !UNLISTED_PATHS.contains(&path.as_str())
+            })
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths.dedup();
+        paths.into()
+    }
+
+    /// `/sitemap.xml` for `paths`, with absolute URLs on the host the
+    /// request was made to.
+    pub fn sitemap(
+        paths: &[String],
+        base_path: &BasePath,
+        headers: &HeaderMap,
+    ) -> impl IntoResponse {
+        let host = headers
+            .get(HOST)
+            .and_then(|host| host.to_str().ok())
+            .unwrap_or("localhost");
+        let scheme = headers
+            .get("x-forwarded-proto")
+            .and_then(|proto| proto.to_str().ok())
+            .filter(|proto| matches!(*proto, "http" | "https"))
+            .unwrap_or("http");
+        let urls = paths
+            .iter()
+            .map(|path| {
+                let url = format!("{scheme}://{host}{}", base_path.join(path));
+                format!("  <url><loc>{}</loc></url>\n", escape(&url))
+            })
+            .collect::<String>();
+        (
+            [(CONTENT_TYPE, "application/xml")],
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset \
+                 xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n\
+                 {urls}</urlset>\n"
+            ),
+        )
+    }
+
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\'', "&apos;")
+    }
+}
@@ -0,0 +1,490 @@
+use crate::base_path::BasePath;
+use serde::Deserialize;
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Environment variable pointing at an alternative settings file.
+pub const SETTINGS_PATH_ENV: &str = "APP_SETTINGS";
+/// Environment variable overriding [`AppSettings::base_path`].
+pub const BASE_PATH_ENV: &str = "APP_BASE_PATH";
+const DEFAULT_SETTINGS_PATH: &str = "settings.toml";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    /// URL prefix the app is served under, e.g. `/demo` behind a reverse
+    /// proxy. Empty when served from the origin root.
+    pub base_path: String,
+    pub tls: Option<TlsSettings>,
+    pub security: SecuritySettings,
+    pub cors: CorsSettings,
+    pub telemetry: TelemetrySettings,
+    pub auth: AuthSettings,
+    pub quotas: QuotaSettings,
+    pub replicas: ReplicaSettings,
+    pub tenants: TenantSettings,
+    pub mail: MailSettings,
+    pub call_log: CallLogSettings,
+    pub scanning: ScanSettings,
+    pub blobs: BlobSettings,
+    pub generation: GenerationSettings,
+    pub tail: TailSettings,
+    pub sentry: SentrySettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsSettings {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// If set, a plain HTTP listener on this port redirects to HTTPS.
+    #[serde(default)]
+    pub redirect_port: Option<u16>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SecuritySettings {
+    /// Send a nonce-based `Content-Security-Policy` with every page.
+    pub content_security_policy: bool,
+    /// Use `Content-Security-Policy-Report-Only` instead of enforcing.
+    pub csp_report_only: bool,
+    /// Sources allowed in `frame-ancestors`; empty means `'none'`.
+    pub frame_ancestors: Vec<String>,
+    /// Sources allowed in `connect-src` besides `'self'`.
+    pub connect_src: Vec<String>,
+    pub referrer_policy: String,
+    /// Reverse proxies in front of the server, whose `X-Forwarded-For` says
+    /// which client a call is from. Anyone else's is ignored.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for SecuritySettings {
+    fn default() -> Self {
+        Self {
+            content_security_policy: true,
+            csp_report_only: false,
+            frame_ancestors: Vec::new(),
+            connect_src: Vec::new(),
+            referrer_policy: "strict-origin-when-cross-origin".to_string(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CorsSettings {
+    /// Origins allowed to call server functions; empty disables CORS.
+    pub allowed_origins: Vec<String>,
+    pub allow_credentials: bool,
+    /// Response headers readable by cross-origin callers.
+    pub exposed_headers: Vec<String>,
+    pub max_age_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TelemetrySettings {
+    /// `tracing` filter directives, e.g. `info,server_fns_axum=debug`.
+    pub log_filter: String,
+    /// OTLP/gRPC collector to export server fn spans to, e.g.
+    /// `http://localhost:4317`. Requires the `otel` feature.
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+    /// Server fns with a `LoggingLayer` warn about calls slower than this.
+    pub slow_call_ms: u64,
+    /// How much of each request body `LoggingLayer` logs; 0 logs none.
+    pub body_sample_bytes: usize,
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        Self {
+            log_filter: "info".to_string(),
+            otlp_endpoint: None,
+            service_name: "server_fns_axum".to_string(),
+            slow_call_ms: 1_000,
+            body_sample_bytes: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AuthSettings {
+    /// Names that get the admin role when they sign up.
+    pub admins: Vec<String>,
+    /// The domain passkeys belong to. Pages must be served from it or one
+    /// of its subdomains, like tenants' are.
+    pub passkey_rp_id: String,
+    /// Where the site is served from, as browsers see it.
+    pub passkey_origin: String,
+    /// How long a session lasts without being used.
+    pub session_idle_mins: u64,
+    /// How long a session lasts at most, however much it's used.
+    pub session_max_hours: u64,
+}
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        Self {
+            admins: Vec::new(),
+            passkey_rp_id: "localhost".to_string(),
+            passkey_origin: "http://localhost:3000".to_string(),
+            session_idle_mins: 24 * 60,
+            session_max_hours: 7 * 24,
+        }
+    }
+}
+
+/// Daily limits (in UTC) on what each user can do.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct QuotaSettings {
+    pub rows_per_day: u64,
+    /// Attachments and imports combined.
+    pub upload_bytes_per_day: u64,
+}
+
+impl Default for QuotaSettings {
+    fn default() -> Self {
+        Self {
+            rows_per_day: 500,
+            upload_bytes_per_day: 100 * 1024 * 1024,
+        }
+    }
+}
+
+/// Read replicas of the row store. With none, every read goes to the
+/// primary.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ReplicaSettings {
+    pub count: usize,
+    /// How often each replica copies the primary.
+    pub replication_interval_ms: u64,
+    /// Reads that tolerate staleness only go to a replica that copied the
+    /// primary at most this long ago.
+    pub max_staleness_ms: u64,
+}
+
+impl Default for ReplicaSettings {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            replication_interval_ms: 500,
+            max_staleness_ms: 2000,
+        }
+    }
+}
+
+/// The tenants one server serves side by side. With none, every request is
+/// in the default tenant.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TenantSettings {
+    /// The tenants besides the default one, by the name requests use.
+    pub names: Vec<String>,
+    /// The domain whose subdomains name tenants, e.g. `todo.example.com`
+    /// for `acme.todo.example.com`. Without it, only `X-Tenant` does.
+    pub base_domain: Option<String>,
+}
+
+/// Which scanner checks attachments before they can be downloaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScannerKind {
+    /// Clears every file.
+    #[default]
+    None,
+    /// Asks a ClamAV daemon over TCP.
+    Clamd,
+    /// Rejects executables, and text files that don't look like text.
+    Heuristic,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ScanSettings {
+    pub scanner: ScannerKind,
+    /// Where clamd listens, for `scanner = "clamd"`.
+    pub clamd_address: String,
+    /// Files declared as text with more entropy than this, in bits per
+    /// byte, are rejected by `scanner = "heuristic"`. Prose is around 4.5;
+    /// compressed or encrypted data is close to 8.
+    pub max_text_entropy: f64,
+}
+
+impl Default for ScanSettings {
+    fn default() -> Self {
+        Self {
+            scanner: ScannerKind::None,
+            clamd_address: "127.0.0.1:3310".to_string(),
+            max_text_entropy: 6.0,
+        }
+    }
+}
+
+/// Where cleared attachments are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlobBackend {
+    /// Files in `local_dir`.
+    #[default]
+    Local,
+    /// Objects in an S3 (or MinIO) bucket.
+    S3,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BlobSettings {
+    pub backend: BlobBackend,
+    pub local_dir: PathBuf,
+    pub s3: S3Settings,
+}
+
+impl Default for BlobSettings {
+    fn default() -> Self {
+        Self {
+            backend: BlobBackend::Local,
+            local_dir: PathBuf::from("./attachments"),
+            s3: S3Settings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct S3Settings {
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    /// Falls back to `AWS_ACCESS_KEY_ID`.
+    pub access_key_id: Option<String>,
+    /// Falls back to `AWS_SECRET_ACCESS_KEY`.
+    pub secret_access_key: Option<String>,
+    /// Put the bucket in the path (`endpoint/bucket/key`), as MinIO
+    /// expects, rather than in the host name.
+    pub path_style: bool,
+    /// Larger files are uploaded in parts of `part_size` bytes, which S3
+    /// won't take smaller than 5 MiB.
+    pub multipart_threshold: u64,
+    pub part_size: u64,
+    /// How long a presigned download URL works for.
+    pub presign_expiry_secs: u64,
+}
+
+impl Default for S3Settings {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://127.0.0.1:9000".to_string(),
+            bucket: "attachments".to_string(),
+            region: "us-east-1".to_string(),
+            access_key_id: None,
+            secret_access_key: None,
+            path_style: true,
+            multipart_threshold: 8 * 1024 * 1024,
+            part_size: 5 * 1024 * 1024,
+            presign_expiry_secs: 300,
+        }
+    }
+}
+
+/// What writes the replies of the text generation example.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GeneratorBackend {
+    /// Canned text, a token at a time with made-up pauses.
+    #[default]
+    Simulated,
+    /// A chat completions API, such as OpenAI's or a local server with the
+    /// same API.
+    OpenAi,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct GenerationSettings {
+    pub backend: GeneratorBackend,
+    pub openai: OpenAiSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OpenAiSettings {
+    /// The API's base URL, without `/chat/completions`.
+    pub endpoint: String,
+    pub model: String,
+    /// Falls back to `OPENAI_API_KEY`.
+    pub api_key: Option<String>,
+    /// The most tokens a reply can have.
+    pub max_tokens: u32,
+}
+
+impl Default for OpenAiSettings {
+    fn default() -> Self {
+        Self {
+            endpoint: "https://api.openai.com/v1".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            api_key: None,
+            max_tokens: 400,
+        }
+    }
+}
+
+/// Where the `call-log` feature records server fn calls.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CallLogSettings {
+    pub path: PathBuf,
+    /// Larger request and response bodies are passed through unrecorded.
+    pub max_body_bytes: usize,
+}
+
+impl Default for CallLogSettings {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("call_log.jsonl"),
+            max_body_bytes: 1024 * 1024,
+        }
+    }
+}
+
+/// The log files admins can follow from the admin page.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TailSettings {
+    /// Only these files can be tailed, named exactly as they are here.
+    pub files: Vec<PathBuf>,
+    /// How many lines from the end of a file are sent before new ones.
+    pub initial_lines: usize,
+}
+
+impl Default for TailSettings {
+    fn default() -> Self {
+        Self {
+            files: Vec::new(),
+            initial_lines: 100,
+        }
+    }
+}
+
+/// Where server fn failures and client error reports are sent, besides the
+/// log. Requires the `sentry` feature.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SentrySettings {
+    /// A Sentry DSN, e.g. `https://<key>@o0.ingest.sentry.io/<project>`.
+    /// Nothing is sent without one.
+    pub dsn: Option<String>,
+    pub environment: String,
+    /// How many of a session's latest server fn calls are sent with each
+    /// of its events.
+    pub max_breadcrumbs: usize,
+}
+
+impl Default for SentrySettings {
+    fn default() -> Self {
+        Self {
+            dsn: None,
+            environment: "development".to_string(),
+            max_breadcrumbs: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MailSettings {
+    /// Log messages instead of sending them. On by default, so a local
+    /// setup never sends real mail; also implied by an empty `smtp_host`.
+    pub dry_run: bool,
+    pub smtp_host: String,
+    /// Defaults to 587 with STARTTLS and 465 without.
+    pub smtp_port: Option<u16>,
+    /// Upgrade a plain connection with STARTTLS instead of connecting with
+    /// TLS right away.
+    pub starttls: bool,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub from: String,
+    /// Who gets alerts about server fn error rates.
+    pub alert_to: Vec<String>,
+    /// The share of failed calls (from 0 to 1) to a server fn, since the
+    /// last check, that raises an alert.
+    pub alert_error_rate: f64,
+}
+
+impl Default for MailSettings {
+    fn default() -> Self {
+        Self {
+            dry_run: true,
+            smtp_host: String::new(),
+            smtp_port: None,
+            starttls: true,
+            smtp_username: None,
+            smtp_password: None,
+            from: "Server Function Demo <demo@localhost>".to_string(),
+            alert_to: Vec::new(),
+            alert_error_rate: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    #[error("couldn't read settings file {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid settings file {path:?}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+}
+
+impl AppSettings {
+    /// Loads settings from `$APP_SETTINGS`, falling back to `settings.toml`.
+    ///
+    /// A missing default file is not an error: the demo runs with defaults.
+    pub fn load() -> Result<Self, SettingsError> {
+        let mut settings = match std::env::var_os(SETTINGS_PATH_ENV) {
+            Some(path) => Self::from_file(Path::new(&path))?,
+            None => {
+                let path = Path::new(DEFAULT_SETTINGS_PATH);
+                if path.exists() {
+                    Self::from_file(path)?
+                } else {
+                    Self::default()
+                }
+            }
+        };
+        if let Ok(base_path) = std::env::var(BASE_PATH_ENV) {
+            settings.base_path = base_path;
+        }
+        Ok(settings)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, SettingsError> {
+        let contents = std::fs::read_to_string(path).map_err(|source| {
+            SettingsError::Io {
+                path: path.to_path_buf(),
+                source,
+            }
+        })?;
+        toml::from_str(&contents).map_err(|source| SettingsError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    pub fn base_path(&self) -> BasePath {
+        BasePath::new(&self.base_path)
+    }
+}
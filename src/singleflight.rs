@@ -0,0 +1,98 @@
+use futures::{
+    future::{BoxFuture, Shared},
+    FutureExt,
+};
+use leptos::prelude::ServerFnError;
+use std::{
+    any::Any,
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+type Outcome = Arc<dyn Any + Send + Sync>;
+
+/// Deduplicates identical computations that are in flight at the same
+/// time: the first caller for a key starts it, and everyone who asks for
+/// the same key before it finishes waits for that one result instead of
+/// starting their own.
+#[derive(Default)]
+pub struct SingleFlight {
+    in_flight: Mutex<HashMap<String, Flight>>,
+    last_id: AtomicU64,
+    /// Callers that joined a computation someone else started.
+    coalesced: AtomicU64,
+}
+
+struct Flight {
+    id: u64,
+    outcome: Shared<BoxFuture<'static, Outcome>>,
+}
+
+impl SingleFlight {
+    /// The result of `compute`, or of the computation already in flight
+    /// under `key`.
+    ///
+    /// `compute` runs on whichever caller's task polls it, and keeps
+    /// running if the caller that started it goes away, so it must not
+    /// depend on the caller's request (its context, say).
+    pub async fn run<T, F>(
+        &self,
+        key: String,
+        compute: F,
+    ) -> Result<T, ServerFnError>
+    where
+        T: Clone + Send + Sync + 'static,
+        F: Future<Output = Result<T, ServerFnError>> + Send + 'static,
+    {
+        let (id, outcome) = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(flight) => {
+                    self.coalesced.fetch_add(1, Ordering::Relaxed);
+                    (flight.id, flight.outcome.clone())
+                }
+                None => {
+                    let id = self.last_id.fetch_add(1, Ordering::Relaxed);
+                    let outcome = compute
+                        .map(|result| Arc::new(result) as Outcome)
+                        .boxed()
+                        .shared();
+                    in_flight.insert(
+                        key.clone(),
+                        Flight {
+                            id,
+                            outcome: outcome.clone(),
+                        },
+                    );
+                    (id, outcome)
+                }
+            }
+        };
+
+        let outcome = outcome.await;
+        // whoever gets here first retires the flight, unless a new one has
+        // already taken its place
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if in_flight.get(&key).is_some_and(|flight| flight.id == id) {
+            in_flight.remove(&key);
+        }
+        drop(in_flight);
+
+        outcome
+            .downcast_ref::<Result<T, ServerFnError>>()
+            .cloned()
+            .unwrap_or_else(|| {
+                Err(ServerFnError::new(format!(
+                    "`{key}` was computed with a different type"
+                )))
+            })
+    }
+
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+}
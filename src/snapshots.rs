@@ -0,0 +1,128 @@
+//! Snapshots of every user's rows in one tenant, attachment metadata
+//! included, so a presenter can set up a demo once and go back to it in one
+//! click from the admin page.
+//!
+//! The snapshot is kept in the presenter's browser, not on the server. It
+//! holds no attachment contents: those stay on disk, and restoring never
+//! deletes any. Quotas, the audit log, sandboxes and other tenants' rows are
+//! left as they are.
+
+use crate::persist::{use_persisted, Persisted};
+#[cfg(feature = "ssr")]
+use crate::{
+    storage::ROWS,
+    tenants::{current_tenant, in_tenant},
+};
+use chrono::{DateTime, Local, Utc};
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Every user's rows in a tenant as [`snapshot_state`] found them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub taken_at: DateTime<Utc>,
+    /// The rows, as the store serializes them.
+    pub data: String,
+}
+
+/// The snapshot this browser last took, if any.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedSnapshot(pub Option<StateSnapshot>);
+
+impl Persisted for SavedSnapshot {
+    const KEY: &'static str = "state_snapshot";
+}
+
+/// Every user's rows in the caller's tenant, trashed or not, along with
+/// their attachments' metadata, for [`restore_state`] to put back.
+#[server]
+#[middleware(crate::middleware::TwoFactorLayer)]
+pub async fn snapshot_state() -> Result<StateSnapshot, ServerFnError> {
+    crate::auth::require_admin()?;
+    let tenant = current_tenant();
+    Ok(StateSnapshot {
+        taken_at: Utc::now(),
+        data: ROWS.snapshot(|owner| in_tenant(&tenant, owner)),
+    })
+}
+
+/// Puts every user's rows in the caller's tenant back as they were in
+/// `snapshot`, returning how many rows they have now. Rows `snapshot` holds
+/// for other tenants are skipped.
+#[server]
+#[middleware(crate::middleware::TwoFactorLayer)]
+pub async fn restore_state(
+    snapshot: StateSnapshot,
+) -> Result<usize, ServerFnError> {
+    crate::auth::require_admin()?;
+    let tenant = current_tenant();
+    ROWS.restore_snapshot(&snapshot.data, |owner| in_tenant(&tenant, owner))
+        .map_err(|e| ServerFnError::new(e.to_string()))
+}
+
+/// Buttons to take a snapshot, which this browser keeps, and to restore it.
+#[component]
+pub fn SnapshotPanel() -> impl IntoView {
+    let saved = use_persisted::<SavedSnapshot>();
+    let take = ServerAction::<SnapshotState>::new();
+    let restore = ServerAction::<RestoreState>::new();
+    Effect::new(move |_| {
+        if let Some(Ok(snapshot)) = take.value().get() {
+            saved.set(SavedSnapshot(Some(snapshot)));
+        }
+    });
+
+    let described = move || {
+        saved.with(|SavedSnapshot(snapshot)| match snapshot {
+            Some(snapshot) => format!(
+                "Saved in this browser: taken {}, {} KiB.",
+                snapshot
+                    .taken_at
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M"),
+                snapshot.data.len().div_ceil(1024),
+            ),
+            None => "No snapshot saved in this browser yet.".to_string(),
+        })
+    };
+    let restore_saved = move |_| {
+        if let SavedSnapshot(Some(snapshot)) = saved.get_untracked() {
+            restore.dispatch(RestoreState { snapshot });
+        }
+    };
+
+    view! {
+        <h3>"Demo snapshot"</h3>
+        <p>{described}</p>
+        <button
+            on:click=move |_| {
+                take.dispatch(SnapshotState {});
+            }
+            disabled=move || take.pending().get()
+        >
+            "Take snapshot"
+        </button>
+        " "
+        <button
+            on:click=restore_saved
+            disabled=move || {
+                restore.pending().get() || saved.with(|SavedSnapshot(snapshot)| snapshot.is_none())
+            }
+        >
+            "Restore snapshot"
+        </button>
+        {move || {
+            take.value().get().and_then(Result::err).map(|e| view! { <p>{e.to_string()}</p> })
+        }}
+        {move || {
+            restore
+                .value()
+                .get()
+                .map(|result| match result {
+                    Ok(rows) => format!("Restored {rows} rows."),
+                    Err(e) => e.to_string(),
+                })
+                .map(|message| view! { <p>{message}</p> })
+        }}
+    }
+}
@@ -0,0 +1,1147 @@
+use crate::errors::TagError;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, ops::Range};
+use strum::{Display, EnumString};
+
+/// The longest tag name, in characters.
+pub const MAX_TAG_LEN: usize = 32;
+/// The most tags a single row can have.
+pub const MAX_ROW_TAGS: usize = 8;
+/// How many days a deleted row stays in the trash before it is purged.
+pub const TRASH_DAYS: i64 = 30;
+
+/// A row added through one of the "add a row" examples.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Row {
+    pub id: u64,
+    /// Bumped by every change, so an edit can tell whether it started from
+    /// the row's current state.
+    #[serde(default)]
+    pub version: u64,
+    /// Markdown, as entered.
+    pub text: String,
+    /// `text` rendered to sanitized HTML by the server when the row is
+    /// added; never taken from a client.
+    #[serde(default)]
+    pub html: String,
+    #[serde(default)]
+    pub completed: bool,
+    /// Sorted and free of duplicates.
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+    #[serde(default)]
+    pub due: Option<NaiveDate>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// Position in the manual order: rows sort by it ascending, and a moved
+    /// row gets a value between its new neighbours'.
+    #[serde(default)]
+    pub ordering: f64,
+    #[serde(default)]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Metadata of a file attached to a row; the contents live on disk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment {
+    /// Also the name of the stored file, so it is never taken from a client.
+    pub id: String,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: u64,
+}
+
+impl Attachment {
+    /// Whether the uploader said this is an image; the contents may still
+    /// disagree.
+    pub fn is_image(&self) -> bool {
+        self.content_type.starts_with("image/")
+    }
+}
+
+impl Row {
+    /// Whether the row is still open after its due date.
+    pub fn is_overdue(&self, today: NaiveDate) -> bool {
+        !self.completed && self.due.is_some_and(|due| due < today)
+    }
+}
+
+/// A label that any number of rows can share.
+///
+/// Names are trimmed, lowercased and have inner whitespace replaced by `-`,
+/// so `"Needs Review"` and `"needs-review"` are the same tag.
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(try_from = "String", into = "String")]
+pub struct Tag(String);
+
+impl Tag {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for Tag {
+    type Error = TagError;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        let name = name
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join("-")
+            .to_lowercase();
+        if name.is_empty() {
+            Err(TagError::Empty)
+        } else if name.chars().count() > MAX_TAG_LEN {
+            Err(TagError::TooLong { max: MAX_TAG_LEN })
+        } else {
+            Ok(Tag(name))
+        }
+    }
+}
+
+/// Checks a row isn't given more than [`MAX_ROW_TAGS`] tags, wherever they
+/// are set.
+pub fn check_tag_count(tags: &[Tag]) -> Result<(), TagError> {
+    if tags.len() > MAX_ROW_TAGS {
+        Err(TagError::TooMany { max: MAX_ROW_TAGS })
+    } else {
+        Ok(())
+    }
+}
+
+impl From<Tag> for String {
+    fn from(tag: Tag) -> Self {
+        tag.0
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A deleted row, which can be restored until it is purged for good.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrashedRow {
+    pub row: Row,
+    pub deleted_at: DateTime<Utc>,
+    /// [`TRASH_DAYS`] after `deleted_at`.
+    pub purge_at: DateTime<Utc>,
+}
+
+/// A tag together with the number of rows that have it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagUsage {
+    pub tag: Tag,
+    pub rows: usize,
+}
+
+/// Orders for [`RowQuery`].
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    Display,
+    EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum RowSort {
+    #[default]
+    Newest,
+    Oldest,
+    Alphabetical,
+    /// The order rows were dragged into.
+    Manual,
+}
+
+/// Completion states for [`RowQuery`].
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    Display,
+    EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum RowStatus {
+    #[default]
+    All,
+    Active,
+    Completed,
+}
+
+/// Which rows to list, and in what order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RowQuery {
+    pub sort: RowSort,
+    pub status: RowStatus,
+    /// Only rows whose text contains this, ignoring case.
+    pub contains: String,
+    /// Only rows that have every one of these tags.
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+}
+
+impl RowQuery {
+    pub fn matches(&self, row: &Row) -> bool {
+        let status = match self.status {
+            RowStatus::All => true,
+            RowStatus::Active => !row.completed,
+            RowStatus::Completed => row.completed,
+        };
+        status
+            && self.tags.iter().all(|tag| row.tags.contains(tag))
+            && (self.contains.is_empty()
+                || row
+                    .text
+                    .to_lowercase()
+                    .contains(&self.contains.to_lowercase()))
+    }
+}
+
+/// Totals over every user's rows.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RowStats {
+    pub rows: usize,
+    pub completed: usize,
+    /// How many of the rows were added on each day (in UTC) that had any.
+    pub added_per_day: BTreeMap<NaiveDate, usize>,
+}
+
+/// A change applied to many rows at once.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BulkOp {
+    Delete,
+    Complete,
+    Reopen,
+    AddTag(Tag),
+    RemoveTag(Tag),
+}
+
+/// What happened to one of the rows of a bulk update.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BulkOutcome {
+    pub id: u64,
+    pub result: Result<(), String>,
+}
+
+/// Byte ranges of the words in `text`, paired with their lowercased form.
+///
+/// Shared by the search index and by highlighting, so both agree on what a
+/// word is.
+pub fn words(text: &str) -> impl Iterator<Item = (Range<usize>, String)> + '_ {
+    let mut start = None;
+    text.char_indices()
+        .map(Some)
+        .chain([None])
+        .filter_map(move |next| match (next, start) {
+            (Some((i, c)), None) if c.is_alphanumeric() => {
+                start = Some(i);
+                None
+            }
+            (Some((_, c)), Some(_)) if c.is_alphanumeric() => None,
+            (Some((i, _)), Some(from)) => {
+                start = None;
+                Some(from..i)
+            }
+            (None, Some(from)) => Some(from..text.len()),
+            _ => None,
+        })
+        .map(|range| (range.clone(), text[range].to_lowercase()))
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{
+        check_tag_count, words, Attachment, BulkOp, BulkOutcome, Row, RowQuery,
+        RowSort, RowStats, Tag, TagUsage, TrashedRow, MAX_ROW_TAGS, TRASH_DAYS,
+    };
+    use crate::{
+        audit::{AuditAction, AUDIT},
+        auth::UserId,
+        cache::{CacheTag, CACHE},
+        errors::{QuotaExceeded, SnapshotError, UpdateRowError},
+        markdown,
+        quotas::{QuotaKind, QUOTAS},
+        rows::validate_text,
+        thumbnails::is_valid_name,
+    };
+    use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+    use serde::{Deserialize, Serialize};
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        sync::{LazyLock, Mutex},
+        time::Instant,
+    };
+
+    /// The in-memory row table shared by all requests.
+    pub static ROWS: LazyLock<RowStore> = LazyLock::new(RowStore::default);
+
+    /// Every user's rows, each kept apart in a table of their own.
+    #[derive(Debug, Default)]
+    pub struct RowStore {
+        inner: Mutex<BTreeMap<UserId, Table>>,
+        /// When each user's rows last changed, which a replica must have
+        /// copied before it can serve them.
+        written: Mutex<BTreeMap<UserId, Instant>>,
+    }
+
+    /// One user's rows keyed by their ID, which only ever increases.
+    #[derive(Debug, Default, Clone)]
+    struct Table {
+        last_id: u64,
+        rows: BTreeMap<u64, Row>,
+        /// Lowercased word -> IDs of the rows containing it.
+        index: BTreeMap<String, BTreeSet<u64>>,
+        /// Tag -> IDs of the rows that have it; the other side of each
+        /// row's `tags`.
+        tagged: BTreeMap<Tag, BTreeSet<u64>>,
+        /// The largest `ordering` of any row, which new rows go after.
+        max_ordering: f64,
+        /// Deleted rows by ID, out of every index until they are restored.
+        trash: BTreeMap<u64, TrashedRow>,
+    }
+
+    /// What [`RowStore::snapshot`] keeps of one user's table. The indexes
+    /// are built again from the rows when it is restored.
+    #[derive(Serialize, Deserialize)]
+    struct TableSnapshot {
+        /// Raised to the highest row ID on restore, so it can be missing.
+        #[serde(default)]
+        last_id: u64,
+        rows: Vec<Row>,
+        trash: Vec<TrashedRow>,
+    }
+
+    /// A row from a snapshot, which came from a client, checked like a new
+    /// one and with its HTML rendered again. Its attachment IDs name stored
+    /// files, so any that couldn't be one fails the whole snapshot.
+    fn checked(mut row: Row) -> Result<Row, SnapshotError> {
+        let invalid =
+            |error: String| SnapshotError::InvalidRow { id: row.id, error };
+        let text = validate_text(&row.text)
+            .map_err(|e| invalid(e.to_string()))?
+            .to_string();
+        check_tag_count(&row.tags).map_err(|e| invalid(e.to_string()))?;
+        if let Some(attachment) = row
+            .attachments
+            .iter()
+            .find(|attachment| !is_valid_name(&attachment.id))
+        {
+            return Err(SnapshotError::InvalidAttachment {
+                id: attachment.id.clone(),
+            });
+        }
+        row.html = markdown::render(&text);
+        row.text = text;
+        Ok(row)
+    }
+
+    /// Removes `id` from the set under `key`, and the set once it is empty.
+    fn unlink<K: Ord>(map: &mut BTreeMap<K, BTreeSet<u64>>, key: &K, id: u64) {
+        if let Some(ids) = map.get_mut(key) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                map.remove(key);
+            }
+        }
+    }
+
+    // changes that are made on their own, in a transaction and as part of a
+    // bulk update
+    impl Table {
+        fn insert(&mut self, text: String) -> Row {
+            self.last_id += 1;
+            self.max_ordering += 1.0;
+            let row = Row {
+                id: self.last_id,
+                ordering: self.max_ordering,
+                version: 1,
+                html: markdown::render(&text),
+                text,
+                completed: false,
+                tags: Vec::new(),
+                due: None,
+                attachments: Vec::new(),
+                created_at: Utc::now(),
+            };
+            self.link(row.clone());
+            row
+        }
+
+        /// Adds `row` and indexes it.
+        fn link(&mut self, row: Row) {
+            for (_, word) in words(&row.text) {
+                self.index.entry(word).or_default().insert(row.id);
+            }
+            for tag in &row.tags {
+                self.tagged.entry(tag.clone()).or_default().insert(row.id);
+            }
+            self.max_ordering = self.max_ordering.max(row.ordering);
+            self.rows.insert(row.id, row);
+        }
+
+        fn remove(&mut self, id: u64) -> Option<Row> {
+            let row = self.rows.remove(&id)?;
+            for (_, word) in words(&row.text) {
+                unlink(&mut self.index, &word, id);
+            }
+            for tag in &row.tags {
+                unlink(&mut self.tagged, tag, id);
+            }
+            Some(row)
+        }
+
+        /// Moves a row to the trash, to be purged [`TRASH_DAYS`] from now.
+        fn trash(&mut self, id: u64) -> Option<&TrashedRow> {
+            let row = self.remove(id)?;
+            let deleted_at = Utc::now();
+            let trashed = TrashedRow {
+                row,
+                deleted_at,
+                purge_at: deleted_at + TimeDelta::days(TRASH_DAYS),
+            };
+            Some(self.trash.entry(id).insert_entry(trashed).into_mut())
+        }
+
+        fn restore(&mut self, id: u64) -> Option<Row> {
+            let mut row = self.trash.remove(&id)?.row;
+            row.version += 1;
+            self.link(row.clone());
+            Some(row)
+        }
+
+        fn purge(&mut self, id: u64) -> Option<Row> {
+            self.trash.remove(&id).map(|trashed| trashed.row)
+        }
+
+        fn reorder(&mut self, moved: u64, before: Option<u64>) -> Option<Row> {
+            if !self.rows.contains_key(&moved) {
+                return None;
+            }
+            if before != Some(moved) {
+                let ordering = match self.slot_before(moved, before)? {
+                    Some(ordering) => ordering,
+                    None => {
+                        // repeated moves into the same gap eventually run
+                        // out of precision
+                        self.renumber();
+                        self.slot_before(moved, before)?
+                            .expect("renumbered rows are a whole step apart")
+                    }
+                };
+                self.max_ordering = self.max_ordering.max(ordering);
+                let row = self.rows.get_mut(&moved)?;
+                row.ordering = ordering;
+                row.version += 1;
+            }
+            self.rows.get(&moved).cloned()
+        }
+
+        fn update_text(
+            &mut self,
+            id: u64,
+            text: String,
+            expected_version: u64,
+        ) -> Result<Row, UpdateRowError> {
+            let Table { rows, index, .. } = self;
+            let row =
+                rows.get_mut(&id).ok_or(UpdateRowError::NotFound { id })?;
+            if row.version != expected_version {
+                return Err(UpdateRowError::Conflict {
+                    server_value: row.clone(),
+                });
+            }
+            for (_, word) in words(&row.text) {
+                unlink(index, &word, id);
+            }
+            for (_, word) in words(&text) {
+                index.entry(word).or_default().insert(id);
+            }
+            row.html = markdown::render(&text);
+            row.text = text;
+            row.version += 1;
+            Ok(row.clone())
+        }
+
+        fn set_completed(&mut self, id: u64, completed: bool) -> Option<&Row> {
+            let row = self.rows.get_mut(&id)?;
+            row.completed = completed;
+            row.version += 1;
+            Some(row)
+        }
+
+        fn set_tags(&mut self, id: u64, mut tags: Vec<Tag>) -> Option<&Row> {
+            tags.sort();
+            tags.dedup();
+            let row = self.rows.get_mut(&id)?;
+            for tag in &row.tags {
+                unlink(&mut self.tagged, tag, id);
+            }
+            for tag in &tags {
+                self.tagged.entry(tag.clone()).or_default().insert(id);
+            }
+            row.tags = tags;
+            row.version += 1;
+            Some(row)
+        }
+
+        fn set_due(&mut self, id: u64, due: Option<NaiveDate>) -> Option<&Row> {
+            let row = self.rows.get_mut(&id)?;
+            row.due = due;
+            row.version += 1;
+            Some(row)
+        }
+
+        fn bulk_update(
+            &mut self,
+            ids: &[u64],
+            op: &BulkOp,
+        ) -> Vec<BulkOutcome> {
+            ids.iter()
+                .map(|&id| {
+                    let missing = || format!("there is no row {id}");
+                    let result = match op {
+                        BulkOp::Delete => {
+                            self.trash(id).map(|_| ()).ok_or_else(missing)
+                        }
+                        BulkOp::Complete => self
+                            .set_completed(id, true)
+                            .map(|_| ())
+                            .ok_or_else(missing),
+                        BulkOp::Reopen => self
+                            .set_completed(id, false)
+                            .map(|_| ())
+                            .ok_or_else(missing),
+                        BulkOp::AddTag(tag) | BulkOp::RemoveTag(tag) => self
+                            .rows
+                            .get(&id)
+                            .ok_or_else(missing)
+                            .and_then(|row| {
+                                let mut tags = row.tags.clone();
+                                tags.retain(|t| t != tag);
+                                if matches!(op, BulkOp::AddTag(_)) {
+                                    tags.push(tag.clone());
+                                }
+                                if tags.len() > MAX_ROW_TAGS {
+                                    Err(format!(
+                                        "already has {MAX_ROW_TAGS} tags"
+                                    ))
+                                } else {
+                                    Ok(tags)
+                                }
+                            })
+                            .map(|tags| {
+                                self.set_tags(id, tags);
+                            }),
+                    };
+                    BulkOutcome { id, result }
+                })
+                .collect()
+        }
+
+        /// The `ordering` for `moved` to go right before `before` (or after
+        /// every other row), or `None` if the neighbours are too close to
+        /// fit anything between them. Fails if `before` does not exist.
+        fn slot_before(
+            &self,
+            moved: u64,
+            before: Option<u64>,
+        ) -> Option<Option<f64>> {
+            let upper = match before {
+                Some(id) => Some(self.rows.get(&id)?.ordering),
+                None => None,
+            };
+            let lower = self
+                .rows
+                .values()
+                .filter(|row| row.id != moved)
+                .map(|row| row.ordering)
+                .filter(|ordering| upper.is_none_or(|upper| *ordering < upper))
+                .reduce(f64::max);
+            let slot = match (lower, upper) {
+                (Some(lower), Some(upper)) => lower + (upper - lower) / 2.0,
+                (None, Some(upper)) => upper - 1.0,
+                (Some(lower), None) => lower + 1.0,
+                (None, None) => 1.0,
+            };
+            let fits = lower.is_none_or(|lower| lower < slot)
+                && upper.is_none_or(|upper| slot < upper);
+            Some(fits.then_some(slot))
+        }
+
+        /// Spreads the manual order back out to 1, 2, 3, ..., keeping it as
+        /// it is.
+        fn renumber(&mut self) {
+            let mut rows = self.rows.values_mut().collect::<Vec<_>>();
+            rows.sort_by(|a, b| a.ordering.total_cmp(&b.ordering));
+            for (n, row) in rows.into_iter().enumerate() {
+                row.ordering = (n + 1) as f64;
+            }
+            self.max_ordering = self.rows.len() as f64;
+        }
+    }
+
+    /// One user's rows part way through [`RowStore::transaction`], along
+    /// with what is audited and what quota is used on the way.
+    pub struct Transaction<'a> {
+        owner: UserId,
+        table: &'a mut Table,
+        audit: Vec<(u64, AuditAction)>,
+        api_key: Option<String>,
+        consumed: Vec<(QuotaKind, u64)>,
+    }
+
+    impl Transaction<'_> {
+        pub fn owner(&self) -> UserId {
+            self.owner
+        }
+
+        /// Records `action` on row `row_id`, if the transaction succeeds.
+        pub fn audit(&mut self, row_id: u64, action: AuditAction) {
+            self.audit.push((row_id, action));
+        }
+
+        /// Audits the changes as made with the API key `name`.
+        pub fn via_api_key(&mut self, name: &str) {
+            self.api_key = Some(name.to_string());
+        }
+
+        /// Uses up `amount` of the owner's `kind` quota, which is given back
+        /// if the transaction fails.
+        pub fn consume_quota(
+            &mut self,
+            kind: QuotaKind,
+            amount: u64,
+        ) -> Result<(), QuotaExceeded> {
+            QUOTAS.consume(self.owner, kind, amount)?;
+            self.consumed.push((kind, amount));
+            Ok(())
+        }
+
+        pub fn insert(&mut self, text: String) -> Row {
+            self.table.insert(text)
+        }
+
+        pub fn get(&self, id: u64) -> Option<Row> {
+            self.table.rows.get(&id).cloned()
+        }
+
+        pub fn len(&self) -> usize {
+            self.table.rows.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        pub fn trash(&mut self, id: u64) -> Option<TrashedRow> {
+            self.table.trash(id).cloned()
+        }
+
+        pub fn restore(&mut self, id: u64) -> Option<Row> {
+            self.table.restore(id)
+        }
+
+        pub fn purge(&mut self, id: u64) -> Option<Row> {
+            self.table.purge(id)
+        }
+
+        pub fn reorder(
+            &mut self,
+            moved: u64,
+            before: Option<u64>,
+        ) -> Option<Row> {
+            self.table.reorder(moved, before)
+        }
+
+        pub fn update_text(
+            &mut self,
+            id: u64,
+            text: String,
+            expected_version: u64,
+        ) -> Result<Row, UpdateRowError> {
+            self.table.update_text(id, text, expected_version)
+        }
+
+        pub fn set_completed(
+            &mut self,
+            id: u64,
+            completed: bool,
+        ) -> Option<Row> {
+            self.table.set_completed(id, completed).cloned()
+        }
+
+        pub fn set_due(
+            &mut self,
+            id: u64,
+            due: Option<NaiveDate>,
+        ) -> Option<Row> {
+            self.table.set_due(id, due).cloned()
+        }
+
+        pub fn set_tags(&mut self, id: u64, tags: Vec<Tag>) -> Option<Row> {
+            self.table.set_tags(id, tags).cloned()
+        }
+
+        /// Records a file attached to row `id`, returning the updated row.
+        pub fn add_attachment(
+            &mut self,
+            id: u64,
+            attachment: Attachment,
+        ) -> Option<Row> {
+            let row = self.table.rows.get_mut(&id)?;
+            row.attachments.push(attachment);
+            row.version += 1;
+            Some(row.clone())
+        }
+
+        pub fn bulk_update(
+            &mut self,
+            ids: &[u64],
+            op: &BulkOp,
+        ) -> Vec<BulkOutcome> {
+            self.table.bulk_update(ids, op)
+        }
+    }
+
+    impl RowStore {
+        /// Runs `f` on `owner`'s table, or an empty one if they have none.
+        fn read_table<T>(
+            &self,
+            owner: UserId,
+            f: impl FnOnce(&Table) -> T,
+        ) -> T {
+            match self.inner.lock().unwrap().get(&owner) {
+                Some(table) => f(table),
+                None => f(&Table::default()),
+            }
+        }
+
+        /// Notes that `owner`'s rows change now; call it under the lock on
+        /// the tables, so it agrees with [`copy_from`](Self::copy_from).
+        fn wrote(&self, owner: UserId) {
+            self.written.lock().unwrap().insert(owner, Instant::now());
+        }
+
+        /// When `owner`'s rows last changed, if they have since the server
+        /// started.
+        pub fn written_at(&self, owner: UserId) -> Option<Instant> {
+            self.written.lock().unwrap().get(&owner).copied()
+        }
+
+        /// Replaces every row with a copy of `primary`'s, returning when the
+        /// copy was taken: it has every change made before then.
+        pub fn copy_from(&self, primary: &RowStore) -> Instant {
+            let (tables, copied_at) = {
+                let tables = primary.inner.lock().unwrap();
+                (tables.clone(), Instant::now())
+            };
+            *self.inner.lock().unwrap() = tables;
+            copied_at
+        }
+
+        /// The rows of every owner `keep` accepts, trashed or not, as JSON
+        /// that [`restore_snapshot`](Self::restore_snapshot) puts back.
+        pub fn snapshot(&self, keep: impl Fn(UserId) -> bool) -> String {
+            let tables = self.inner.lock().unwrap();
+            let snapshot = tables
+                .iter()
+                .filter(|(&owner, _)| keep(owner))
+                .map(|(&owner, table)| {
+                    let snapshot = TableSnapshot {
+                        last_id: table.last_id,
+                        rows: table.rows.values().cloned().collect(),
+                        trash: table.trash.values().cloned().collect(),
+                    };
+                    (owner, snapshot)
+                })
+                .collect::<BTreeMap<_, _>>();
+            serde_json::to_string(&snapshot).expect("rows serialize to JSON")
+        }
+
+        /// Replaces the rows of every owner `keep` accepts with the ones in
+        /// `snapshot`, returning how many rows (not counting trashed ones)
+        /// they have now. Other owners' rows, in the store or the snapshot,
+        /// are left out. Leaves the rows as they are if `snapshot` isn't one.
+        pub fn restore_snapshot(
+            &self,
+            snapshot: &str,
+            keep: impl Fn(UserId) -> bool,
+        ) -> Result<usize, SnapshotError> {
+            let snapshot: BTreeMap<UserId, TableSnapshot> =
+                serde_json::from_str(snapshot)
+                    .map_err(|e| SnapshotError::Json(e.to_string()))?;
+            let restored = snapshot
+                .into_iter()
+                .filter(|(owner, _)| keep(*owner))
+                .map(|(owner, snapshot)| {
+                    let mut table = Table {
+                        last_id: snapshot.last_id,
+                        ..Table::default()
+                    };
+                    for mut trashed in snapshot.trash {
+                        trashed.row = checked(trashed.row)?;
+                        table.trash.insert(trashed.row.id, trashed);
+                    }
+                    for row in snapshot.rows {
+                        table.link(checked(row)?);
+                    }
+                    // a hand-edited snapshot can hold IDs past its `last_id`,
+                    // which new rows must not be given again
+                    table.last_id = table
+                        .rows
+                        .keys()
+                        .chain(table.trash.keys())
+                        .copied()
+                        .fold(table.last_id, u64::max);
+                    Ok((owner, table))
+                })
+                .collect::<Result<BTreeMap<_, _>, SnapshotError>>()?;
+            let rows = restored.values().map(|table| table.rows.len()).sum();
+
+            let mut tables = self.inner.lock().unwrap();
+            let owners = tables
+                .keys()
+                .copied()
+                .filter(|owner| keep(*owner))
+                .chain(restored.keys().copied())
+                .collect::<BTreeSet<_>>();
+            for &owner in &owners {
+                self.wrote(owner);
+            }
+            tables.retain(|owner, _| !keep(*owner));
+            tables.extend(restored);
+            drop(tables);
+            for owner in owners {
+                CACHE.invalidate(CacheTag::Rows(owner));
+            }
+            Ok(rows)
+        }
+
+        /// Runs `f` as one change to `owner`'s rows, which nobody else sees
+        /// part way through.
+        ///
+        /// If `f` fails, none of it happens: the rows go back to how they
+        /// were, any quota it used is given back and nothing it audited is
+        /// recorded. Once it succeeds, its audit entries are recorded and
+        /// results cached from the rows are dropped.
+        pub fn transaction<T, E>(
+            &self,
+            owner: UserId,
+            f: impl FnOnce(&mut Transaction<'_>) -> Result<T, E>,
+        ) -> Result<T, E> {
+            let mut tables = self.inner.lock().unwrap();
+            self.wrote(owner);
+            let table = tables.entry(owner).or_default();
+            // the table is small enough that a copy beats an undo log
+            let before = table.clone();
+            let mut tx = Transaction {
+                owner,
+                table: &mut *table,
+                audit: Vec::new(),
+                api_key: None,
+                consumed: Vec::new(),
+            };
+            let result = f(&mut tx);
+            let Transaction {
+                audit,
+                api_key,
+                consumed,
+                ..
+            } = tx;
+            match &result {
+                Ok(_) => {
+                    // still under the lock, so entries go in the order the
+                    // changes were made
+                    for (row_id, action) in audit {
+                        match &api_key {
+                            Some(name) => AUDIT
+                                .record_with_key(owner, row_id, action, name),
+                            None => AUDIT.record(owner, row_id, action),
+                        }
+                    }
+                    drop(tables);
+                    CACHE.invalidate(CacheTag::Rows(owner));
+                }
+                Err(_) => {
+                    *table = before;
+                    for (kind, amount) in consumed {
+                        QUOTAS.refund(owner, kind, amount);
+                    }
+                }
+            }
+            result
+        }
+
+        /// Deletes all of `owner`'s rows, trashed or not, returning them so
+        /// the caller can clean up what they refer to.
+        pub fn remove_owner(&self, owner: UserId) -> Vec<Row> {
+            let mut tables = self.inner.lock().unwrap();
+            self.wrote(owner);
+            let Some(table) = tables.remove(&owner) else {
+                return Vec::new();
+            };
+            table
+                .rows
+                .into_values()
+                .chain(table.trash.into_values().map(|trashed| trashed.row))
+                .collect()
+        }
+
+        /// `owner`'s trash, the rows purged soonest first.
+        pub fn list_trash(&self, owner: UserId) -> Vec<TrashedRow> {
+            self.read_table(owner, |table| {
+                let mut trash =
+                    table.trash.values().cloned().collect::<Vec<_>>();
+                trash.sort_by_key(|trashed| trashed.purge_at);
+                trash
+            })
+        }
+
+        /// Purges every user's trashed rows that were due to be purged by
+        /// `now`, returning them with their owners.
+        pub fn purge_expired(&self, now: DateTime<Utc>) -> Vec<(UserId, Row)> {
+            let mut tables = self.inner.lock().unwrap();
+            let mut purged = Vec::new();
+            for (owner, table) in tables.iter_mut() {
+                // only the trash changes, which replicas don't serve
+                let expired = table
+                    .trash
+                    .extract_if(.., |_, trashed| trashed.purge_at <= now)
+                    .map(|(_, trashed)| (*owner, trashed.row));
+                purged.extend(expired);
+            }
+            purged
+        }
+
+        pub fn attachment(
+            &self,
+            owner: UserId,
+            row_id: u64,
+            id: &str,
+        ) -> Option<Attachment> {
+            self.read_table(owner, |table| {
+                table
+                    .rows
+                    .get(&row_id)?
+                    .attachments
+                    .iter()
+                    .find(|attachment| attachment.id == id)
+                    .cloned()
+            })
+        }
+
+        /// Whether any of `owner`'s rows has the attachment `id`.
+        pub fn has_attachment(&self, owner: UserId, id: &str) -> bool {
+            self.find_attachment(owner, id).is_some()
+        }
+
+        /// The attachment `id`, on whichever of `owner`'s rows has it.
+        pub fn find_attachment(
+            &self,
+            owner: UserId,
+            id: &str,
+        ) -> Option<Attachment> {
+            self.read_table(owner, |table| {
+                table.rows.values().find_map(|row| {
+                    row.attachments
+                        .iter()
+                        .find(|attachment| attachment.id == id)
+                        .cloned()
+                })
+            })
+        }
+
+        pub fn get(&self, owner: UserId, id: u64) -> Option<Row> {
+            self.read_table(owner, |table| table.rows.get(&id).cloned())
+        }
+
+        /// Every user's open rows due on or before `date`, with their
+        /// owners.
+        pub fn due_by(&self, date: NaiveDate) -> Vec<(UserId, Row)> {
+            self.inner
+                .lock()
+                .unwrap()
+                .iter()
+                .flat_map(|(owner, table)| {
+                    table.rows.values().map(move |row| (*owner, row))
+                })
+                .filter(|(_, row)| {
+                    !row.completed && row.due.is_some_and(|d| d <= date)
+                })
+                .map(|(owner, row)| (owner, row.clone()))
+                .collect()
+        }
+
+        /// Every tag `owner` uses, by name.
+        pub fn tags(&self, owner: UserId) -> Vec<TagUsage> {
+            self.read_table(owner, |table| {
+                table
+                    .tagged
+                    .iter()
+                    .map(|(tag, ids)| TagUsage {
+                        tag: tag.clone(),
+                        rows: ids.len(),
+                    })
+                    .collect()
+            })
+        }
+
+        /// Up to `limit` of `owner`'s rows matching `query`, in its order.
+        pub fn list(
+            &self,
+            owner: UserId,
+            query: &RowQuery,
+            limit: usize,
+        ) -> Vec<Row> {
+            self.read_table(owner, |table| {
+                // with tags, start from the rows that have all of them
+                // rather than scanning the whole table
+                let candidates = match query.tags.split_first() {
+                    Some((first, rest)) => {
+                        let mut ids = table
+                            .tagged
+                            .get(first)
+                            .cloned()
+                            .unwrap_or_default();
+                        for tag in rest {
+                            ids.retain(|id| {
+                                table
+                                    .tagged
+                                    .get(tag)
+                                    .is_some_and(|tagged| tagged.contains(id))
+                            });
+                        }
+                        ids.iter().filter_map(|id| table.rows.get(id)).collect()
+                    }
+                    None => table.rows.values().collect::<Vec<_>>(),
+                };
+                let matching =
+                    candidates.into_iter().filter(|row| query.matches(row));
+                let mut rows = match query.sort {
+                    // IDs increase with insertion, so ID order is age order
+                    RowSort::Newest => {
+                        matching.rev().take(limit).cloned().collect()
+                    }
+                    RowSort::Oldest => matching.take(limit).cloned().collect(),
+                    RowSort::Alphabetical | RowSort::Manual => {
+                        matching.cloned().collect::<Vec<_>>()
+                    }
+                };
+                match query.sort {
+                    RowSort::Alphabetical => {
+                        rows.sort_by_cached_key(|row| row.text.to_lowercase());
+                        rows.truncate(limit);
+                    }
+                    RowSort::Manual => {
+                        rows.sort_by(|a, b| a.ordering.total_cmp(&b.ordering));
+                        rows.truncate(limit);
+                    }
+                    RowSort::Newest | RowSort::Oldest => {}
+                }
+                rows
+            })
+        }
+
+        pub fn stats(&self) -> RowStats {
+            let tables = self.inner.lock().unwrap();
+            let mut stats = RowStats::default();
+            for row in tables.values().flat_map(|table| table.rows.values()) {
+                stats.rows += 1;
+                stats.completed += usize::from(row.completed);
+                *stats
+                    .added_per_day
+                    .entry(row.created_at.date_naive())
+                    .or_default() += 1;
+            }
+            stats
+        }
+
+        /// How many rows `owner` has.
+        pub fn len(&self, owner: UserId) -> usize {
+            self.read_table(owner, |table| table.rows.len())
+        }
+
+        pub fn is_empty(&self, owner: UserId) -> bool {
+            self.len(owner) == 0
+        }
+
+        /// Up to `limit` of `owner`'s rows with IDs greater than `after`, in
+        /// ID order.
+        ///
+        /// Paging by ID keeps the lock short and stays consistent while rows
+        /// are added, so callers can walk the whole table a page at a time.
+        pub fn page_after(
+            &self,
+            owner: UserId,
+            after: Option<u64>,
+            limit: usize,
+        ) -> Vec<Row> {
+            let start = after.map_or(0, |id| id.saturating_add(1));
+            self.read_table(owner, |table| {
+                table
+                    .rows
+                    .range(start..)
+                    .take(limit)
+                    .map(|(_, row)| row.clone())
+                    .collect()
+            })
+        }
+
+        /// `owner`'s rows containing a word starting with each of `terms`,
+        /// newest first, skipping `offset` and returning at most `limit` of
+        /// them, plus the total number of matches.
+        pub fn search(
+            &self,
+            owner: UserId,
+            terms: &[String],
+            offset: usize,
+            limit: usize,
+        ) -> (usize, Vec<Row>) {
+            self.read_table(owner, |table| {
+                let mut matches: Option<BTreeSet<u64>> = None;
+                for term in terms {
+                    let ids = table
+                        .index
+                        .range(term.clone()..)
+                        .take_while(|(word, _)| word.starts_with(term.as_str()))
+                        .flat_map(|(_, ids)| ids.iter().copied())
+                        .collect::<BTreeSet<_>>();
+                    matches = Some(match matches {
+                        Some(matches) => &matches & &ids,
+                        None => ids,
+                    });
+                }
+                let matches = matches.unwrap_or_default();
+                let rows = matches
+                    .iter()
+                    .rev()
+                    .skip(offset)
+                    .take(limit)
+                    .filter_map(|id| table.rows.get(id).cloned())
+                    .collect();
+                (matches.len(), rows)
+            })
+        }
+    }
+}
@@ -0,0 +1,425 @@
+use crate::{
+    channels::{
+        Polled, Sequenced, SocketMessage, SocketRequest, Tick,
+        HEARTBEAT_INTERVAL,
+    },
+    codec::FramedStream,
+};
+use futures::{
+    channel::oneshot,
+    future::{self, Either},
+    stream, StreamExt,
+};
+use leptos::prelude::*;
+use server_fn::{BoxedStream, ServerFnError};
+use std::{fmt, future::Future, ops::ControlFlow, time::Duration};
+
+/// A stream counts as dead once this much time passes without any frame.
+const HEARTBEAT_TIMEOUT: Duration =
+    Duration::from_secs(HEARTBEAT_INTERVAL.as_secs() * 5 / 2);
+/// A stream or socket that opens but sends nothing for this long is taken
+/// to be held up by something buffering it, such as a proxy. Streams start
+/// with a heartbeat and socket clients ping as soon as they open, so
+/// something should come back well within it.
+const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+/// Attempts in a row that fail without a single frame, after which
+/// [`supervise_or_poll`] gives up on streaming.
+const FAILURES_BEFORE_POLLING: u32 = 3;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Where a supervised stream is in its connect/reconnect cycle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    /// Waiting before reconnect attempt `attempt`, after `error`.
+    Reconnecting {
+        attempt: u32,
+        error: String,
+    },
+    /// Streaming didn't get through, so long polls are used instead.
+    Polling,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionState::Connecting => f.write_str("connecting"),
+            ConnectionState::Connected => f.write_str("connected"),
+            ConnectionState::Reconnecting { attempt, error } => {
+                write!(f, "reconnecting (attempt {attempt}) after: {error}")
+            }
+            ConnectionState::Polling => {
+                f.write_str("polling, as streamed responses don't get through")
+            }
+        }
+    }
+}
+
+/// Keeps a long-lived stream alive until `on_event` breaks out of it.
+///
+/// `connect` is called with the sequence number of the last event seen (if
+/// any) so the server can resume after it. Each event is handed to
+/// `on_event` at most once, in order: any at or before the last one applied
+/// are dropped. The stream is re-opened with
+/// exponential backoff whenever it ends, errors, or misses its heartbeats.
+///
+/// Also stops once `state` is disposed, that is when the component that owns
+/// it is unmounted.
+pub async fn supervise<T, F, Fut>(
+    connect: F,
+    state: WriteSignal<ConnectionState>,
+    mut on_event: impl FnMut(T) -> ControlFlow<()>,
+) where
+    F: Fn(Option<u64>) -> Fut,
+    Fut: Future<Output = Result<FramedStream<Tick<T>>, ServerFnError>>,
+{
+    let mut cursor = None;
+    run_stream(connect, state, &mut on_event, &mut cursor, false).await;
+}
+
+/// [`supervise`], falling back to long polls with `poll` once the stream
+/// stalls, as it does behind a proxy that buffers streamed responses.
+///
+/// A stream stalls when it opens but sends nothing, not even its first
+/// heartbeat, for [`STALL_TIMEOUT`], or when [`FAILURES_BEFORE_POLLING`]
+/// attempts in a row fail without a frame. Polling then carries on from
+/// the last event seen until the component is unmounted.
+pub async fn supervise_or_poll<T, F, Fut, P, PFut>(
+    connect: F,
+    poll: P,
+    state: WriteSignal<ConnectionState>,
+    mut on_event: impl FnMut(T) -> ControlFlow<()>,
+) where
+    F: Fn(Option<u64>) -> Fut,
+    Fut: Future<Output = Result<FramedStream<Tick<T>>, ServerFnError>>,
+    P: Fn(Option<u64>) -> PFut,
+    PFut: Future<Output = Result<Polled<T>, ServerFnError>>,
+{
+    let mut cursor = None;
+    if let Outcome::Stalled =
+        run_stream(connect, state, &mut on_event, &mut cursor, true).await
+    {
+        run_polls(poll, state, &mut on_event, cursor).await;
+    }
+}
+
+/// [`supervise`] for a subscription socket, such as
+/// [`progress_socket`](crate::progress::progress_socket).
+///
+/// `connect` is given what to send up the socket, which starts by resuming
+/// after the last event seen. Keeping the socket alive and noticing when
+/// it dies are left to its client,
+/// [`KeepaliveClient`](crate::clients::KeepaliveClient); the socket counts
+/// as connected once the first message, usually a `Pong`, comes back.
+pub async fn supervise_socket<T, F, Fut>(
+    connect: F,
+    state: WriteSignal<ConnectionState>,
+    mut on_event: impl FnMut(T) -> ControlFlow<()>,
+) where
+    F: Fn(BoxedStream<SocketRequest, ServerFnError>) -> Fut,
+    Fut: Future<
+        Output = Result<
+            BoxedStream<SocketMessage<T>, ServerFnError>,
+            ServerFnError,
+        >,
+    >,
+{
+    let mut cursor = None;
+    run_socket(connect, state, &mut on_event, &mut cursor, false).await;
+}
+
+/// [`supervise_socket`] with the fallback of [`supervise_or_poll`]: the
+/// client pings as soon as the socket opens, so a socket that stays silent
+/// for [`STALL_TIMEOUT`] isn't getting through.
+pub async fn supervise_socket_or_poll<T, F, Fut, P, PFut>(
+    connect: F,
+    poll: P,
+    state: WriteSignal<ConnectionState>,
+    mut on_event: impl FnMut(T) -> ControlFlow<()>,
+) where
+    F: Fn(BoxedStream<SocketRequest, ServerFnError>) -> Fut,
+    Fut: Future<
+        Output = Result<
+            BoxedStream<SocketMessage<T>, ServerFnError>,
+            ServerFnError,
+        >,
+    >,
+    P: Fn(Option<u64>) -> PFut,
+    PFut: Future<Output = Result<Polled<T>, ServerFnError>>,
+{
+    let mut cursor = None;
+    if let Outcome::Stalled =
+        run_socket(connect, state, &mut on_event, &mut cursor, true).await
+    {
+        run_polls(poll, state, &mut on_event, cursor).await;
+    }
+}
+
+/// Why a supervised stream or socket was given up on.
+enum Outcome {
+    /// `on_event` broke out, or the owner was unmounted.
+    Stopped,
+    /// Frames don't get through; only returned when falling back.
+    Stalled,
+}
+
+async fn run_stream<T, F, Fut>(
+    connect: F,
+    state: WriteSignal<ConnectionState>,
+    on_event: &mut impl FnMut(T) -> ControlFlow<()>,
+    cursor: &mut Option<u64>,
+    fallback: bool,
+) -> Outcome
+where
+    F: Fn(Option<u64>) -> Fut,
+    Fut: Future<Output = Result<FramedStream<Tick<T>>, ServerFnError>>,
+{
+    let mut attempt = 0;
+    let mut silent = 0;
+    loop {
+        if state.is_disposed() {
+            return Outcome::Stopped;
+        }
+        if attempt == 0 {
+            state.set(ConnectionState::Connecting);
+        }
+        let mut heard = false;
+        let error = match connect(*cursor).await {
+            Ok(stream) => {
+                let mut stream = stream.into_inner();
+                state.set(ConnectionState::Connected);
+                loop {
+                    let timeout = if fallback && !heard {
+                        STALL_TIMEOUT
+                    } else {
+                        HEARTBEAT_TIMEOUT
+                    };
+                    let next =
+                        future::select(stream.next(), Box::pin(sleep(timeout)))
+                            .await;
+                    match next {
+                        Either::Left((Some(Ok(_)), _))
+                            if state.is_disposed() =>
+                        {
+                            return Outcome::Stopped;
+                        }
+                        Either::Left((Some(Ok(tick)), _)) => {
+                            attempt = 0;
+                            silent = 0;
+                            heard = true;
+                            if let Tick::Event(event) = tick {
+                                if apply(cursor, event, on_event).is_break() {
+                                    return Outcome::Stopped;
+                                }
+                            }
+                        }
+                        Either::Left((Some(Err(e)), _)) => break e.to_string(),
+                        Either::Left((None, _)) => {
+                            break "stream closed by the server".to_string()
+                        }
+                        Either::Right(_) if fallback && !heard => {
+                            return Outcome::Stalled;
+                        }
+                        Either::Right(_) => {
+                            break "no heartbeat from the server".to_string()
+                        }
+                    }
+                }
+            }
+            Err(e) => e.to_string(),
+        };
+
+        if !heard {
+            silent += 1;
+            if fallback && silent >= FAILURES_BEFORE_POLLING {
+                return Outcome::Stalled;
+            }
+        }
+        attempt += 1;
+        state.set(ConnectionState::Reconnecting { attempt, error });
+        sleep(backoff(attempt)).await;
+    }
+}
+
+async fn run_socket<T, F, Fut>(
+    connect: F,
+    state: WriteSignal<ConnectionState>,
+    on_event: &mut impl FnMut(T) -> ControlFlow<()>,
+    cursor: &mut Option<u64>,
+    fallback: bool,
+) -> Outcome
+where
+    F: Fn(BoxedStream<SocketRequest, ServerFnError>) -> Fut,
+    Fut: Future<
+        Output = Result<
+            BoxedStream<SocketMessage<T>, ServerFnError>,
+            ServerFnError,
+        >,
+    >,
+{
+    let mut attempt = 0;
+    let mut silent = 0;
+    loop {
+        if state.is_disposed() {
+            return Outcome::Stopped;
+        }
+        if attempt == 0 {
+            state.set(ConnectionState::Connecting);
+        }
+        // the requests end once this socket is given up on, so nothing is
+        // left waiting to write to it
+        let (_open, closed) = oneshot::channel::<()>();
+        let resume = SocketRequest::Resume { after: *cursor };
+        let requests = stream::once(future::ready(Ok(resume)))
+            .chain(stream::once(closed).filter_map(|_| future::ready(None)));
+        let mut connected = false;
+        let error = match connect(requests.into()).await {
+            Ok(mut messages) => loop {
+                let next = if fallback && !connected {
+                    let stalled = Box::pin(sleep(STALL_TIMEOUT));
+                    match future::select(messages.next(), stalled).await {
+                        Either::Left((next, _)) => next,
+                        Either::Right(_) => return Outcome::Stalled,
+                    }
+                } else {
+                    messages.next().await
+                };
+                match next {
+                    Some(Ok(_)) if state.is_disposed() => {
+                        return Outcome::Stopped
+                    }
+                    Some(Ok(message)) => {
+                        if !connected {
+                            connected = true;
+                            attempt = 0;
+                            silent = 0;
+                            state.set(ConnectionState::Connected);
+                        }
+                        if let SocketMessage::Event(event) = message {
+                            if apply(cursor, event, on_event).is_break() {
+                                return Outcome::Stopped;
+                            }
+                        }
+                    }
+                    Some(Err(e)) => break e.to_string(),
+                    None => break "socket closed by the server".to_string(),
+                }
+            },
+            Err(e) => e.to_string(),
+        };
+
+        if !connected {
+            silent += 1;
+            if fallback && silent >= FAILURES_BEFORE_POLLING {
+                return Outcome::Stalled;
+            }
+        }
+        attempt += 1;
+        state.set(ConnectionState::Reconnecting { attempt, error });
+        sleep(backoff(attempt)).await;
+    }
+}
+
+/// Long-polls after `cursor` until `on_event` breaks out or the owner is
+/// unmounted, backing off while polls fail.
+async fn run_polls<T, P, PFut>(
+    poll: P,
+    state: WriteSignal<ConnectionState>,
+    on_event: &mut impl FnMut(T) -> ControlFlow<()>,
+    mut cursor: Option<u64>,
+) where
+    P: Fn(Option<u64>) -> PFut,
+    PFut: Future<Output = Result<Polled<T>, ServerFnError>>,
+{
+    let mut attempt = 0;
+    state.set(ConnectionState::Polling);
+    loop {
+        if state.is_disposed() {
+            return;
+        }
+        match poll(cursor).await {
+            Ok(_) if state.is_disposed() => return,
+            Ok(polled) => {
+                if attempt > 0 {
+                    attempt = 0;
+                    state.set(ConnectionState::Polling);
+                }
+                let mut items = polled.items;
+                items.sort_by_key(|event| event.seq);
+                for event in items {
+                    if apply(&mut cursor, event, on_event).is_break() {
+                        return;
+                    }
+                }
+                cursor = cursor.max(Some(polled.next));
+            }
+            Err(e) => {
+                attempt += 1;
+                let error = e.to_string();
+                state.set(ConnectionState::Reconnecting { attempt, error });
+                sleep(backoff(attempt)).await;
+            }
+        }
+    }
+}
+
+/// Hands `event` to `on_event`, unless it is at or before `applied`, the
+/// last one handed over: a repeat from a server resending what a client
+/// already has, or older news arriving late. `applied` only moves forward.
+fn apply<T>(
+    applied: &mut Option<u64>,
+    event: Sequenced<T>,
+    on_event: &mut impl FnMut(T) -> ControlFlow<()>,
+) -> ControlFlow<()> {
+    if applied.is_some_and(|applied| event.seq <= applied) {
+        return ControlFlow::Continue(());
+    }
+    *applied = Some(event.seq);
+    on_event(event.value)
+}
+
+/// A badge for where a supervised stream or socket is, with the last error
+/// as its tooltip while reconnecting.
+#[component]
+pub fn ConnectionBadge(
+    #[prop(into)] state: Signal<ConnectionState>,
+) -> impl IntoView {
+    let label = move || match state.get() {
+        ConnectionState::Connecting => "connecting".to_string(),
+        ConnectionState::Connected => "live".to_string(),
+        ConnectionState::Reconnecting { attempt, .. } => {
+            format!("reconnecting ({attempt})")
+        }
+        ConnectionState::Polling => "polling".to_string(),
+    };
+
+    view! {
+        <span
+            class="connection-badge"
+            class:connected=move || state.get() == ConnectionState::Connected
+            class:polling=move || state.get() == ConnectionState::Polling
+            title=move || state.get().to_string()
+        >
+            {label}
+        </span>
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+pub(crate) async fn sleep(duration: Duration) {
+    let (tx, rx) = futures::channel::oneshot::channel();
+    set_timeout(
+        move || {
+            _ = tx.send(());
+        },
+        duration,
+    );
+    _ = rx.await;
+}
@@ -0,0 +1,461 @@
+//! Following a log file from the browser, like `tail -f`. Only the files
+//! listed in the `[tail]` settings can be read, and only by admins.
+//!
+//! The server polls the file for growth rather than watching it, so it
+//! works the same for files on any filesystem, and notices a file that was
+//! truncated or replaced by log rotation and starts it over.
+
+use crate::codec::{Framed, FramedStream};
+use futures::StreamExt;
+use leptos::{prelude::*, task::spawn_local};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// The most lines the viewer keeps; older ones are dropped.
+pub const MAX_TAIL_LINES: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TailEvent {
+    /// The next line, without its line break.
+    Line(String),
+    /// The file got shorter or was replaced, so it's read again from the
+    /// start.
+    Truncated,
+    /// Sent while nothing is written to the file, so a stream whose client
+    /// has gone away is noticed and ended.
+    Heartbeat,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::TailEvent;
+    use crate::{errors::TailError, settings::TailSettings};
+    use futures::{stream, Stream, StreamExt};
+    use std::{
+        collections::VecDeque,
+        io::SeekFrom,
+        path::PathBuf,
+        sync::OnceLock,
+        time::{Duration, Instant},
+    };
+    use tokio::{
+        fs::File,
+        io::{AsyncReadExt, AsyncSeekExt},
+    };
+
+    /// How often a followed file is checked for new lines.
+    pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Longer lines are cut off here.
+    pub const MAX_LINE_LEN: usize = 16 * 1024;
+
+    /// How far back from the end the initial lines are looked for.
+    const BACKLOG_BYTES: u64 = 256 * 1024;
+
+    /// The most that's read from a file in one go.
+    const READ_LIMIT: u64 = 1024 * 1024;
+
+    static SETTINGS: OnceLock<TailSettings> = OnceLock::new();
+
+    /// Applies the `[tail]` settings; call it once, before serving.
+    pub fn init(settings: &TailSettings) {
+        _ = SETTINGS.set(settings.clone());
+    }
+
+    fn settings() -> &'static TailSettings {
+        SETTINGS.get_or_init(TailSettings::default)
+    }
+
+    /// The files that can be tailed, as they're named in the settings.
+    pub fn allowed_files() -> Vec<String> {
+        settings()
+            .files
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect()
+    }
+
+    /// `path`, if it names one of the allowed files exactly; nothing is
+    /// resolved, so `..` and links can't reach anything else.
+    pub fn allowed(path: &str) -> Result<PathBuf, TailError> {
+        settings()
+            .files
+            .iter()
+            .find(|file| file.as_os_str() == path)
+            .cloned()
+            .ok_or_else(|| TailError::NotAllowed {
+                path: path.to_string(),
+            })
+    }
+
+    /// The last lines of the file at `path`, then, with `follow`, every
+    /// line added to it until the stream is dropped.
+    pub async fn tail(
+        path: PathBuf,
+        follow: bool,
+    ) -> Result<
+        impl Stream<Item = Result<TailEvent, TailError>> + Send,
+        TailError,
+    > {
+        let io_error = {
+            let path = path.display().to_string();
+            move |e: std::io::Error| TailError::Io {
+                path: path.clone(),
+                message: e.to_string(),
+            }
+        };
+        let mut file = File::open(&path).await.map_err(&io_error)?;
+        let size = file.metadata().await.map_err(&io_error)?.len();
+        let start = size.saturating_sub(BACKLOG_BYTES);
+        file.seek(SeekFrom::Start(start)).await.map_err(&io_error)?;
+
+        let mut lines = Lines::default();
+        let mut backlog = Vec::new();
+        (&mut file)
+            .take(size - start)
+            .read_to_end(&mut backlog)
+            .await
+            .map_err(&io_error)?;
+        let mut initial = lines.push(&backlog);
+        // reading from the middle of the file starts mid-line
+        if start > 0 && !initial.is_empty() {
+            initial.remove(0);
+        }
+        if !follow {
+            initial.extend(lines.finish());
+        }
+        let skip = initial.len().saturating_sub(settings().initial_lines);
+        let initial = initial.into_iter().skip(skip).map(TailEvent::Line);
+
+        let tail = Tail {
+            path,
+            file,
+            pos: size,
+            lines,
+            pending: VecDeque::new(),
+            last_sent: Instant::now(),
+        };
+        let following = stream::unfold(
+            (tail, io_error),
+            |(mut tail, io_error)| async move {
+                let event = tail.next().await.map_err(&io_error);
+                Some((event, (tail, io_error)))
+            },
+        )
+        // an error ends the stream
+        .scan(false, |failed, event| {
+            let next = (!*failed).then_some(event);
+            *failed = next.as_ref().is_some_and(Result::is_err);
+            futures::future::ready(next)
+        });
+        let following = if follow {
+            following.left_stream()
+        } else {
+            stream::empty().right_stream()
+        };
+        Ok(stream::iter(initial).map(Ok).chain(following))
+    }
+
+    /// A followed file, read from `pos` on.
+    struct Tail {
+        path: PathBuf,
+        file: File,
+        pos: u64,
+        lines: Lines,
+        /// Lines read but not sent yet.
+        pending: VecDeque<TailEvent>,
+        last_sent: Instant,
+    }
+
+    impl Tail {
+        async fn next(&mut self) -> Result<TailEvent, std::io::Error> {
+            loop {
+                if let Some(event) = self.pending.pop_front() {
+                    self.last_sent = Instant::now();
+                    return Ok(event);
+                }
+                if self.last_sent.elapsed()
+                    >= crate::channels::HEARTBEAT_INTERVAL
+                {
+                    self.last_sent = Instant::now();
+                    return Ok(TailEvent::Heartbeat);
+                }
+                // the path rather than the open file, which after a rotation
+                // is the old one
+                let size = tokio::fs::metadata(&self.path).await?.len();
+                if size < self.pos {
+                    self.start_over().await?;
+                    continue;
+                }
+                if size == self.pos {
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                    continue;
+                }
+                let mut chunk = Vec::new();
+                (&mut self.file)
+                    .take((size - self.pos).min(READ_LIMIT))
+                    .read_to_end(&mut chunk)
+                    .await?;
+                if chunk.is_empty() {
+                    // the path names a new file that's already longer
+                    self.start_over().await?;
+                    continue;
+                }
+                self.pos += chunk.len() as u64;
+                self.pending.extend(
+                    self.lines.push(&chunk).into_iter().map(TailEvent::Line),
+                );
+            }
+        }
+
+        /// Reads whatever file is at the path now from its start.
+        async fn start_over(&mut self) -> Result<(), std::io::Error> {
+            self.file = File::open(&self.path).await?;
+            self.pos = 0;
+            self.lines = Lines::default();
+            self.pending.push_back(TailEvent::Truncated);
+            Ok(())
+        }
+    }
+
+    /// Splits bytes into lines as they're read, holding back the last
+    /// line until its line break arrives.
+    #[derive(Default)]
+    struct Lines {
+        partial: Vec<u8>,
+    }
+
+    impl Lines {
+        fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+            self.partial.extend_from_slice(bytes);
+            let mut lines = Vec::new();
+            while let Some(end) = self.partial.iter().position(|b| *b == b'\n')
+            {
+                lines.push(line(&self.partial[..end]));
+                self.partial.drain(..=end);
+            }
+            // a line that never ends is sent in pieces rather than kept
+            // growing
+            if self.partial.len() > MAX_LINE_LEN {
+                lines.push(line(&std::mem::take(&mut self.partial)));
+            }
+            lines
+        }
+
+        /// The last line, if the file doesn't end with a line break.
+        fn finish(self) -> Option<String> {
+            (!self.partial.is_empty()).then(|| line(&self.partial))
+        }
+    }
+
+    fn line(bytes: &[u8]) -> String {
+        let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+        let mut line = String::from_utf8_lossy(bytes).into_owned();
+        if line.len() > MAX_LINE_LEN {
+            let mut end = MAX_LINE_LEN;
+            while !line.is_char_boundary(end) {
+                end -= 1;
+            }
+            line.truncate(end);
+        }
+        line
+    }
+}
+
+/// The files admins can tail.
+#[server]
+#[middleware(crate::middleware::TwoFactorLayer)]
+pub async fn tail_files() -> Result<Vec<String>, ServerFnError> {
+    crate::auth::require_admin()?;
+    Ok(allowed_files())
+}
+
+/// Streams the last lines of `path`, one of the [`tail_files`], and then
+/// with `follow` every line added to it for as long as the caller reads.
+#[server(output = Framed)]
+#[middleware(crate::middleware::TwoFactorLayer)]
+pub async fn tail_file(
+    path: String,
+    follow: bool,
+) -> Result<FramedStream<TailEvent>, ServerFnError> {
+    crate::auth::require_admin()?;
+    let path = allowed(&path)?;
+    let events = tail(path, follow).await?;
+    Ok(FramedStream::new(
+        events.map(|event| event.map_err(ServerFnError::new)),
+    ))
+}
+
+/// Adds `line` to the end of `lines`, dropping the oldest past
+/// [`MAX_TAIL_LINES`].
+fn push_line(lines: &mut VecDeque<String>, line: String) {
+    if lines.len() == MAX_TAIL_LINES {
+        lines.pop_front();
+    }
+    lines.push_back(line);
+}
+
+/// Picks one of the [`tail_files`] and shows its lines as they're written.
+#[component]
+pub fn LogTail() -> impl IntoView {
+    let files = Resource::new(|| (), |_| tail_files());
+    let path = RwSignal::new(String::new());
+    let follow = RwSignal::new(true);
+    let lines = RwSignal::new(VecDeque::<String>::new());
+    // lines that came in while paused, shown once the viewer carries on
+    let held = RwSignal::new(VecDeque::<String>::new());
+    let paused = RwSignal::new(false);
+    let auto_scroll = RwSignal::new(true);
+    // bumped to stop the stream that's being read
+    let run = RwSignal::new(0_u64);
+    let (reading, set_reading) = signal(false);
+    let (error, set_error) = signal(None::<String>);
+    let log_ref = NodeRef::<leptos::html::Div>::new();
+
+    let start = move |_| {
+        let path = path.get_untracked();
+        if path.is_empty() {
+            return;
+        }
+        run.update(|n| *n += 1);
+        let this_run = run.get_untracked();
+        lines.set(VecDeque::new());
+        held.set(VecDeque::new());
+        set_error.set(None);
+        set_reading.set(true);
+        spawn_local(async move {
+            let is_current = move || {
+                run.try_get_untracked().is_some_and(|run| run == this_run)
+            };
+            let add = move |line: String| {
+                let target = if paused.get_untracked() { held } else { lines };
+                target.update(|lines| push_line(lines, line));
+            };
+            match tail_file(path, follow.get_untracked()).await {
+                Ok(events) => {
+                    let mut events = events.into_inner();
+                    while let Some(event) = events.next().await {
+                        if !is_current() {
+                            return;
+                        }
+                        match event {
+                            Ok(TailEvent::Line(line)) => add(line),
+                            Ok(TailEvent::Truncated) => {
+                                add("--- truncated; reading it again ---"
+                                    .into())
+                            }
+                            Ok(TailEvent::Heartbeat) => {}
+                            Err(e) => set_error.set(Some(e.to_string())),
+                        }
+                    }
+                }
+                Err(e) => set_error.set(Some(e.to_string())),
+            }
+            if is_current() {
+                set_reading.set(false);
+            }
+        });
+    };
+    let stop = move |_| {
+        run.update(|n| *n += 1);
+        set_reading.set(false);
+    };
+    let toggle_pause = move |_| {
+        if paused.get_untracked() {
+            let caught_up = std::mem::take(&mut *held.write());
+            lines.update(|lines| {
+                for line in caught_up {
+                    push_line(lines, line);
+                }
+            });
+        }
+        paused.update(|paused| *paused = !*paused);
+    };
+
+    // once the new lines have been rendered, keep the last one in view
+    Effect::new(move |_| {
+        lines.track();
+        if auto_scroll.get_untracked() {
+            request_animation_frame(move || {
+                if let Some(log) = log_ref.get_untracked() {
+                    log.set_scroll_top(log.scroll_height());
+                }
+            });
+        }
+    });
+
+    view! {
+        <h3>"Tail a log file"</h3>
+        <Suspense fallback=|| view! { <p>"Loading..."</p> }>
+            {move || Suspend::new(async move {
+                files
+                    .await
+                    .map(|files| {
+                        if path.get_untracked().is_empty() {
+                            if let Some(first) = files.first() {
+                                path.set(first.clone());
+                            }
+                        }
+                        view! {
+                            <select on:change=move |ev| path.set(event_target_value(&ev))>
+                                {files
+                                    .into_iter()
+                                    .map(|file| {
+                                        let selected = path.get_untracked() == file;
+                                        view! {
+                                            <option value=file.clone() selected=selected>
+                                                {file.clone()}
+                                            </option>
+                                        }
+                                    })
+                                    .collect::<Vec<_>>()}
+                            </select>
+                        }
+                    })
+            })}
+        </Suspense>
+        <label>
+            <input
+                type="checkbox"
+                prop:checked=follow
+                on:change=move |ev| follow.set(event_target_checked(&ev))
+            />
+            " Follow"
+        </label>
+        <label>
+            <input
+                type="checkbox"
+                prop:checked=auto_scroll
+                on:change=move |ev| auto_scroll.set(event_target_checked(&ev))
+            />
+            " Scroll to new lines"
+        </label>
+        <button on:click=start disabled=reading>
+            "Start"
+        </button>
+        <button on:click=stop disabled=move || !reading.get()>
+            "Stop"
+        </button>
+        <button on:click=toggle_pause>
+            {move || {
+                if paused.get() {
+                    format!("Carry on ({} new lines)", held.with(VecDeque::len))
+                } else {
+                    "Pause".to_string()
+                }
+            }}
+        </button>
+        <div class="log-tail" node_ref=log_ref>
+            {move || lines.with(|lines| lines.iter().cloned().collect::<Vec<_>>().join("\n"))}
+        </div>
+        <p>
+            {move || {
+                format!("Showing the last {} of at most {MAX_TAIL_LINES} lines.", lines.with(VecDeque::len))
+            }}
+        </p>
+        {move || error.get().map(|e| view! { <p>"Tailing failed: " {e}</p> })}
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{
+    dev_overlay::DevLogCapture, middleware::is_server_fn_path,
+    settings::TelemetrySettings,
+};
+use axum::body::Body;
+use http::{Request, Response};
+use std::time::Duration;
+use tower_http::{
+    classify::{ServerErrorsAsFailures, SharedClassifier},
+    trace::{MakeSpan, OnResponse, TraceLayer},
+};
+use tracing::{field::Empty, Span};
+use tracing_subscriber::{
+    layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
+};
+
+/// Keeps the span exporter alive; dropping it flushes pending spans.
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            _ = provider.shutdown();
+        }
+    }
+}
+
+/// Installs the global `tracing` subscriber: human-readable output on stdout
+/// and, with the `otel` feature and an `otlp_endpoint`, an OTLP span exporter.
+/// Debug builds also keep each server fn call's events for the
+/// [dev overlay](crate::dev_overlay).
+pub fn init(settings: &TelemetrySettings) -> Telemetry {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(&settings.log_filter));
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(cfg!(debug_assertions).then_some(DevLogCapture));
+
+    #[cfg(feature = "otel")]
+    {
+        use opentelemetry::trace::TracerProvider;
+
+        let provider = settings
+            .otlp_endpoint
+            .as_deref()
+            .map(|endpoint| otel::tracer_provider(endpoint, settings));
+        let otel_layer = provider.as_ref().map(|provider| {
+            tracing_opentelemetry::layer()
+                .with_tracer(provider.tracer(settings.service_name.clone()))
+        });
+        registry.with(otel_layer).init();
+        Telemetry { provider }
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        registry.init();
+        if settings.otlp_endpoint.is_some() {
+            tracing::warn!(
+                "otlp_endpoint is set but the `otel` feature is disabled; \
+                 spans will not be exported"
+            );
+        }
+        Telemetry {}
+    }
+}
+
+pub type ServerFnTraceLayer = TraceLayer<
+    SharedClassifier<ServerErrorsAsFailures>,
+    ServerFnSpan,
+    (),
+    RecordStatus,
+>;
+
+/// One span per server fn call, continuing the browser's trace if the request
+/// carries a `traceparent` header. Pages and static files aren't traced.
+pub fn server_fn_trace_layer() -> ServerFnTraceLayer {
+    TraceLayer::new_for_http()
+        .make_span_with(ServerFnSpan)
+        .on_request(())
+        .on_response(RecordStatus)
+}
+
+#[derive(Clone, Copy)]
+pub struct ServerFnSpan;
+
+impl MakeSpan<Body> for ServerFnSpan {
+    fn make_span(&mut self, req: &Request<Body>) -> Span {
+        if !is_server_fn_path(req.uri().path()) {
+            return Span::none();
+        }
+        let span = tracing::info_span!(
+            "server_fn",
+            otel.name = %req.uri().path(),
+            http.method = %req.method(),
+            http.status_code = Empty,
+        );
+        #[cfg(feature = "otel")]
+        otel::continue_trace(&span, req.headers());
+        span
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RecordStatus;
+
+impl OnResponse<Body> for RecordStatus {
+    fn on_response(self, res: &Response<Body>, latency: Duration, span: &Span) {
+        span.record("http.status_code", res.status().as_u16());
+        tracing::debug!(parent: span, ?latency, "finished server fn call");
+    }
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use crate::settings::TelemetrySettings;
+    use http::HeaderMap;
+    use opentelemetry::propagation::Extractor;
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::{
+        propagation::TraceContextPropagator, trace::SdkTracerProvider, Resource,
+    };
+    use tracing::Span;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    pub fn tracer_provider(
+        endpoint: &str,
+        settings: &TelemetrySettings,
+    ) -> SdkTracerProvider {
+        let exporter = SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()
+            .expect("couldn't create OTLP span exporter");
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(settings.service_name.clone())
+                    .build(),
+            )
+            .build();
+
+        opentelemetry::global::set_text_map_propagator(
+            TraceContextPropagator::new(),
+        );
+        opentelemetry::global::set_tracer_provider(provider.clone());
+        provider
+    }
+
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|key| key.as_str()).collect()
+        }
+    }
+
+    pub fn continue_trace(span: &Span, headers: &HeaderMap) {
+        let parent =
+            opentelemetry::global::get_text_map_propagator(|propagator| {
+                propagator.extract(&HeaderExtractor(headers))
+            });
+        _ = span.set_parent(parent);
+    }
+}
@@ -0,0 +1,241 @@
+//! Tenants: separate copies of the demo that one server serves side by
+//! side, as a starting point for SaaS-style apps.
+//!
+//! A request names its tenant with an `X-Tenant` header or, without one, a
+//! subdomain of `[tenants] base_domain` in its `Host`; a request that names
+//! none is in the default tenant. [`resolve_tenant`] turns away requests
+//! naming a tenant that isn't in `[tenants] names`.
+//!
+//! Each account and sandbox has a different owner ID in each tenant
+//! ([`tenant_owner`]), so rows, attachments, quotas and cached results,
+//! which all go by owner, are kept apart without knowing about tenants.
+//! Accounts and sessions are shared. Server fn rate limits count each
+//! tenant's callers separately.
+
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use server_fn::codec::GetUrl;
+
+/// The header naming a request's tenant, which wins over the subdomain.
+pub const TENANT_HEADER: &str = "x-tenant";
+
+/// A tenant the dev panel can switch to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TenantLink {
+    /// `None` for the default tenant.
+    pub name: Option<String>,
+    /// Its front page, if tenants have subdomains.
+    pub href: Option<String>,
+    pub current: bool,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{TenantLink, TENANT_HEADER};
+    use crate::{
+        auth::UserId,
+        errors::{HasStatusCode, TenantError},
+        settings::TenantSettings,
+    };
+    use axum::{
+        extract::Request,
+        middleware::Next,
+        response::{IntoResponse, Response},
+    };
+    use http::{header::HOST, request::Parts, HeaderMap};
+    use leptos::prelude::use_context;
+    use std::{iter, sync::OnceLock};
+
+    static SETTINGS: OnceLock<TenantSettings> = OnceLock::new();
+
+    /// Account and sandbox IDs stay below `1 << TENANT_SHIFT`; the bits
+    /// from here up are the tenant's.
+    const TENANT_SHIFT: u32 = 56;
+
+    /// The most tenants there can be besides the default one.
+    pub const MAX_TENANTS: usize = (1 << (u64::BITS - TENANT_SHIFT)) - 1;
+
+    /// A request's tenant, which [`resolve_tenant`] adds to its extensions.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct Tenant {
+        /// `None` for the default tenant.
+        pub name: Option<String>,
+        /// Where `name` is in `[tenants] names`, counting from 1, or 0 for
+        /// the default tenant.
+        index: u64,
+    }
+
+    /// Applies the `[tenants]` settings; call it once, before serving.
+    pub fn init(settings: &TenantSettings) -> Result<(), TenantError> {
+        if settings.names.len() > MAX_TENANTS {
+            return Err(TenantError::TooMany {
+                count: settings.names.len(),
+                max: MAX_TENANTS,
+            });
+        }
+        _ = SETTINGS.set(settings.clone());
+        Ok(())
+    }
+
+    /// The tenant `headers` name, or the default one if they name none.
+    pub fn tenant_of(headers: &HeaderMap) -> Result<Tenant, TenantError> {
+        let Some(settings) = SETTINGS.get().filter(|s| !s.names.is_empty())
+        else {
+            return Ok(Tenant::default());
+        };
+        let name = match headers.get(TENANT_HEADER) {
+            Some(name) => String::from_utf8_lossy(name.as_bytes()).into(),
+            None => match subdomain(headers, settings) {
+                Some(name) => name,
+                None => return Ok(Tenant::default()),
+            },
+        };
+        match settings.names.iter().position(|known| *known == name) {
+            Some(index) => Ok(Tenant {
+                name: Some(name),
+                index: index as u64 + 1,
+            }),
+            None => Err(TenantError::Unknown { name }),
+        }
+    }
+
+    /// What comes before `.{base_domain}` in the `Host`, if anything does.
+    fn subdomain(
+        headers: &HeaderMap,
+        settings: &TenantSettings,
+    ) -> Option<String> {
+        let base_domain = settings.base_domain.as_deref()?;
+        let host = headers.get(HOST)?.to_str().ok()?;
+        let host = host.split(':').next()?;
+        let name = host.strip_suffix(base_domain)?.strip_suffix('.')?;
+        Some(name.to_ascii_lowercase())
+    }
+
+    /// The current request's tenant, as [`resolve_tenant`] found it.
+    pub fn current_tenant() -> Tenant {
+        use_context::<Parts>()
+            .and_then(|parts| parts.extensions.get::<Tenant>().cloned())
+            .unwrap_or_default()
+    }
+
+    /// The owner ID the account or sandbox `user` has in `tenant`. In the
+    /// default tenant, that's `user` itself.
+    pub fn tenant_owner(tenant: &Tenant, user: UserId) -> UserId {
+        user | (tenant.index << TENANT_SHIFT)
+    }
+
+    /// The account or sandbox that has the owner ID `owner`, in whichever
+    /// tenant.
+    pub fn tenant_user(owner: UserId) -> UserId {
+        owner & ((1 << TENANT_SHIFT) - 1)
+    }
+
+    /// Whether the owner ID `owner` is in `tenant`, whichever account or
+    /// sandbox it is.
+    pub fn in_tenant(tenant: &Tenant, owner: UserId) -> bool {
+        owner >> TENANT_SHIFT == tenant.index
+    }
+
+    /// Every owner ID the account or sandbox `user` has, one per tenant.
+    pub fn tenant_owners(user: UserId) -> impl Iterator<Item = UserId> {
+        let tenants = SETTINGS.get().map_or(0, |s| s.names.len() as u64);
+        (0..=tenants).map(move |index| user | (index << TENANT_SHIFT))
+    }
+
+    /// Middleware adding the request's [`Tenant`] to its extensions, which
+    /// turns away requests naming a tenant that doesn't exist.
+    pub async fn resolve_tenant(mut req: Request, next: Next) -> Response {
+        match tenant_of(req.headers()) {
+            Ok(tenant) => {
+                req.extensions_mut().insert(tenant);
+                next.run(req).await
+            }
+            Err(e) => (e.status_code(), e.to_string()).into_response(),
+        }
+    }
+
+    /// Every tenant, with a link to its subdomain on the port the current
+    /// request came in on.
+    pub fn tenant_links() -> Vec<TenantLink> {
+        let Some(settings) = SETTINGS.get().filter(|s| !s.names.is_empty())
+        else {
+            return Vec::new();
+        };
+        let current = current_tenant().name;
+        let port = use_context::<Parts>()
+            .and_then(|parts| {
+                let host = parts.headers.get(HOST)?.to_str().ok()?;
+                let (_, port) = host.split_once(':')?;
+                Some(format!(":{port}"))
+            })
+            .unwrap_or_default();
+        iter::once(None)
+            .chain(settings.names.iter().cloned().map(Some))
+            .map(|name| {
+                let subdomain = match &name {
+                    Some(name) => format!("{name}."),
+                    None => String::new(),
+                };
+                let href = settings.base_domain.as_ref().map(|base_domain| {
+                    format!("//{subdomain}{base_domain}{port}/")
+                });
+                TenantLink {
+                    current: name == current,
+                    name,
+                    href,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The tenants to switch between, in debug builds; none in others.
+#[server(input = GetUrl)]
+pub async fn list_tenants() -> Result<Vec<TenantLink>, ServerFnError> {
+    if !cfg!(debug_assertions) {
+        return Ok(Vec::new());
+    }
+    Ok(tenant_links())
+}
+
+/// Links to every tenant's subdomain, for the dev panel. Shows nothing
+/// without tenants.
+#[component]
+pub fn TenantSwitcher() -> impl IntoView {
+    let tenants = Resource::new(|| (), |_| list_tenants());
+
+    view! {
+        <Suspense>
+            {move || Suspend::new(async move {
+                let tenants = tenants.await.unwrap_or_default();
+                (!tenants.is_empty())
+                    .then(|| {
+                        let links = tenants
+                            .into_iter()
+                            .map(|tenant| {
+                                let name = tenant
+                                    .name
+                                    .unwrap_or_else(|| "default".to_string());
+                                let label = if tenant.current {
+                                    format!("[{name}]")
+                                } else {
+                                    name
+                                };
+                                view! {
+                                    " "
+                                    {match tenant.href {
+                                        Some(href) => view! { <a href=href>{label}</a> }.into_any(),
+                                        None => label.into_any(),
+                                    }}
+                                }
+                            })
+                            .collect::<Vec<_>>();
+                        view! { <p>"Tenant:" {links}</p> }
+                    })
+            })}
+        </Suspense>
+    }
+}
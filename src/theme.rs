@@ -0,0 +1,80 @@
+//! A light or dark page, picked by the reader and kept in their browser.
+
+use crate::persist::{use_persisted, Persisted};
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    Display,
+    EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Theme {
+    /// Whatever the system prefers.
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::System, Theme::Light, Theme::Dark];
+
+    fn label(self) -> &'static str {
+        match self {
+            Theme::System => "System theme",
+            Theme::Light => "Light",
+            Theme::Dark => "Dark",
+        }
+    }
+}
+
+impl Persisted for Theme {
+    const KEY: &'static str = "theme";
+}
+
+/// Picks the [`Theme`], which goes on `<html data-theme>` for the style
+/// sheet. The server renders the system theme, so a picked one only
+/// applies once the page has hydrated.
+#[component]
+pub fn ThemePicker() -> impl IntoView {
+    let theme = use_persisted::<Theme>();
+    Effect::new(move |_| {
+        if let Some(html) = document().document_element() {
+            _ = html.set_attribute("data-theme", &theme.get().to_string());
+        }
+    });
+
+    view! {
+        <select
+            class="theme-picker"
+            title="Theme"
+            on:change=move |ev| {
+                if let Ok(picked) = event_target_value(&ev).parse() {
+                    theme.set(picked);
+                }
+            }
+        >
+            {Theme::ALL
+                .into_iter()
+                .map(|option| {
+                    view! {
+                        <option value=option.to_string() selected=move || theme.get() == option>
+                            {option.label()}
+                        </option>
+                    }
+                })
+                .collect::<Vec<_>>()}
+        </select>
+    }
+}
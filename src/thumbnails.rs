@@ -0,0 +1,250 @@
+use crate::{base_path::use_base_path, supervisor::sleep};
+#[cfg(feature = "ssr")]
+use crate::{sandbox::require_owner, storage::ROWS};
+use leptos::{prelude::*, task::spawn_local};
+use serde::{Deserialize, Serialize};
+use server_fn::{
+    codec::{ByteStream, GetUrl, Streaming},
+    ServerFn,
+};
+use std::time::Duration;
+use strum::{Display, EnumString};
+
+/// How often a preview asks whether its thumbnail is ready yet.
+const STATUS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The sizes thumbnails are generated in.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    Display,
+    EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ThumbnailSize {
+    Small,
+    Large,
+}
+
+impl ThumbnailSize {
+    pub const ALL: [ThumbnailSize; 2] =
+        [ThumbnailSize::Small, ThumbnailSize::Large];
+
+    /// The longest side of a thumbnail of this size.
+    pub fn pixels(self) -> u32 {
+        match self {
+            ThumbnailSize::Small => 64,
+            ThumbnailSize::Large => 256,
+        }
+    }
+}
+
+/// Where an attachment's thumbnail job is at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThumbnailStatus {
+    Pending,
+    Ready,
+    Failed(String),
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{ThumbnailSize, ThumbnailStatus};
+    use crate::blobs;
+    use dashmap::DashMap;
+    use image::{ImageError, ImageFormat, ImageReader, Limits};
+    use std::{
+        io::Cursor,
+        path::{Path, PathBuf},
+        sync::LazyLock,
+    };
+
+    const THUMBNAILS_DIR: &str = "./attachments/thumbnails";
+
+    /// The widest and tallest image thumbnails are made of. A small file can
+    /// claim to be far bigger, so this is checked before decoding it.
+    const MAX_IMAGE_SIDE: u32 = 8192;
+
+    /// The most memory decoding one image may take.
+    const MAX_DECODE_BYTES: u64 = 64 * 1024 * 1024;
+
+    /// Jobs started since the server did; older thumbnails are only on disk.
+    static JOBS: LazyLock<DashMap<String, ThumbnailStatus>> =
+        LazyLock::new(DashMap::new);
+
+    /// Whether `name` could be an attachment ID, so it is safe to use in a
+    /// path.
+    pub fn is_valid_name(name: &str) -> bool {
+        name.len() == 32 && name.bytes().all(|b| b.is_ascii_hexdigit())
+    }
+
+    pub fn thumbnail_path(name: &str, size: ThumbnailSize) -> PathBuf {
+        Path::new(THUMBNAILS_DIR).join(format!("{name}-{size}.png"))
+    }
+
+    /// Generates every size of thumbnail for the image stored as blob
+    /// `name` in the background. Thumbnails are kept on local disk, wherever
+    /// the image is.
+    pub fn spawn_job(name: String) {
+        JOBS.insert(name.clone(), ThumbnailStatus::Pending);
+        tokio::spawn(async move {
+            let status = match generate(&name).await {
+                Ok(()) => ThumbnailStatus::Ready,
+                Err(e) => {
+                    tracing::warn!(%name, "couldn't generate thumbnails: {e}");
+                    ThumbnailStatus::Failed(e)
+                }
+            };
+            JOBS.insert(name, status);
+        });
+    }
+
+    async fn generate(name: &str) -> Result<(), String> {
+        let image = blobs::read(name).await.map_err(|e| e.to_string())?;
+        let name = name.to_string();
+        tokio::task::spawn_blocking(move || render(&name, image))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    fn render(name: &str, image: Vec<u8>) -> Result<(), String> {
+        let mut reader = ImageReader::new(Cursor::new(image))
+            .with_guessed_format()
+            .map_err(|e| e.to_string())?;
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(MAX_IMAGE_SIDE);
+        limits.max_image_height = Some(MAX_IMAGE_SIDE);
+        limits.max_alloc = Some(MAX_DECODE_BYTES);
+        reader.limits(limits);
+        let image = reader.decode().map_err(|e| match e {
+            ImageError::Limits(_) => format!(
+                "the image is too big to make thumbnails of (at most \
+                 {MAX_IMAGE_SIDE}x{MAX_IMAGE_SIDE} pixels)"
+            ),
+            e => e.to_string(),
+        })?;
+        std::fs::create_dir_all(THUMBNAILS_DIR).map_err(|e| e.to_string())?;
+        for size in ThumbnailSize::ALL {
+            let path = thumbnail_path(name, size);
+            // written under a temporary name, so a thumbnail that exists is
+            // always complete
+            let partial = path.with_extension("partial");
+            image
+                .thumbnail(size.pixels(), size.pixels())
+                .save_with_format(&partial, ImageFormat::Png)
+                .map_err(|e| e.to_string())?;
+            std::fs::rename(&partial, &path).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    pub fn status(name: &str) -> ThumbnailStatus {
+        match JOBS.get(name) {
+            Some(status) => status.clone(),
+            None if thumbnail_path(name, ThumbnailSize::Small).exists() => {
+                ThumbnailStatus::Ready
+            }
+            None => ThumbnailStatus::Failed("there is no thumbnail".into()),
+        }
+    }
+
+    /// Deletes every thumbnail of `name`, whether or not it has any.
+    pub async fn remove(name: &str) {
+        JOBS.remove(name);
+        for size in ThumbnailSize::ALL {
+            _ = tokio::fs::remove_file(thumbnail_path(name, size)).await;
+        }
+    }
+}
+
+#[server(input = GetUrl)]
+pub async fn thumbnail_status(
+    name: String,
+) -> Result<ThumbnailStatus, ServerFnError> {
+    let owner = require_owner()?;
+    if !is_valid_name(&name) || !ROWS.has_attachment(owner, &name) {
+        return Err(ServerFnError::new("invalid thumbnail name"));
+    }
+    Ok(status(&name))
+}
+
+#[server(input = GetUrl, output = Streaming)]
+pub async fn thumbnail(
+    name: String,
+    size: ThumbnailSize,
+) -> Result<ByteStream, ServerFnError> {
+    use http::{
+        header::{CACHE_CONTROL, CONTENT_TYPE},
+        HeaderValue,
+    };
+
+    let owner = require_owner()?;
+    if !is_valid_name(&name) || !ROWS.has_attachment(owner, &name) {
+        return Err(ServerFnError::new("invalid thumbnail name"));
+    }
+    let bytes = tokio::fs::read(thumbnail_path(&name, size)).await?;
+
+    let response = expect_context::<leptos_axum::ResponseOptions>();
+    response.insert_header(CONTENT_TYPE, HeaderValue::from_static("image/png"));
+    // a name is never reused, so a thumbnail never changes; it is only
+    // ever shown to its owner, so shared caches must not keep it
+    response.insert_header(
+        CACHE_CONTROL,
+        HeaderValue::from_static("private, max-age=31536000, immutable"),
+    );
+    Ok(ByteStream::new(futures::stream::once(async { Ok(bytes) })))
+}
+
+/// Shows the thumbnail of `name` once its job is done.
+#[component]
+pub fn ThumbnailPreview(name: String, size: ThumbnailSize) -> impl IntoView {
+    let base_path = use_base_path();
+    let src =
+        base_path.join(&format!("{}?name={name}&size={size}", Thumbnail::PATH));
+    let (status, set_status) = signal(ThumbnailStatus::Pending);
+
+    Effect::new(move |_| {
+        let name = name.clone();
+        spawn_local(async move {
+            let done = loop {
+                match thumbnail_status(name.clone()).await {
+                    Ok(ThumbnailStatus::Pending) => {
+                        sleep(STATUS_POLL_INTERVAL).await;
+                    }
+                    Ok(done) => break done,
+                    Err(e) => break ThumbnailStatus::Failed(e.to_string()),
+                }
+            };
+            set_status.set(done);
+        });
+    });
+
+    move || match status.get() {
+        ThumbnailStatus::Pending => {
+            view! { <small>"Generating thumbnail..."</small> }.into_any()
+        }
+        ThumbnailStatus::Ready => view! {
+            <img
+                src=src.clone()
+                alt=""
+                width=size.pixels()
+                height=size.pixels()
+                class="thumbnail"
+            />
+        }
+        .into_any(),
+        ThumbnailStatus::Failed(e) => {
+            view! { <small title=e>"No thumbnail"</small> }.into_any()
+        }
+    }
+}
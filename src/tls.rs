@@ -0,0 +1,65 @@
+use crate::settings::TlsSettings;
+use axum::{handler::HandlerWithoutStateExt, response::Redirect, Router};
+use axum_server::tls_rustls::RustlsConfig;
+use http::{
+    header::HOST,
+    uri::{Authority, PathAndQuery, Scheme},
+    HeaderMap, StatusCode, Uri,
+};
+use leptos::logging;
+use std::net::SocketAddr;
+
+/// Serves `app` over HTTPS on `addr`, optionally spawning a plain HTTP
+/// listener that redirects every request to the HTTPS origin.
+pub async fn serve(
+    app: Router,
+    addr: SocketAddr,
+    tls: &TlsSettings,
+) -> std::io::Result<()> {
+    let config =
+        RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path).await?;
+
+    if let Some(port) = tls.redirect_port {
+        let http_addr = SocketAddr::new(addr.ip(), port);
+        tokio::spawn(redirect_http_to_https(http_addr, addr.port()));
+    }
+
+    axum_server::bind_rustls(addr, config)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+}
+
+async fn redirect_http_to_https(http_addr: SocketAddr, https_port: u16) {
+    let redirect = move |headers: HeaderMap, uri: Uri| async move {
+        match https_uri(&headers, uri, https_port) {
+            Some(uri) => Ok(Redirect::permanent(&uri.to_string())),
+            None => Err(StatusCode::BAD_REQUEST),
+        }
+    };
+
+    let listener = tokio::net::TcpListener::bind(http_addr)
+        .await
+        .expect("couldn't bind HTTP redirect listener");
+    logging::log!("redirecting http://{} to https", &http_addr);
+    axum::serve(listener, redirect.into_make_service())
+        .await
+        .expect("HTTP redirect listener failed");
+}
+
+fn https_uri(headers: &HeaderMap, uri: Uri, https_port: u16) -> Option<Uri> {
+    let host = headers.get(HOST)?.to_str().ok()?;
+    let host = host.parse::<Authority>().ok()?;
+    let authority = if https_port == 443 {
+        host.host().parse().ok()?
+    } else {
+        format!("{}:{https_port}", host.host()).parse().ok()?
+    };
+
+    let mut parts = uri.into_parts();
+    parts.scheme = Some(Scheme::HTTPS);
+    parts.authority = Some(authority);
+    if parts.path_and_query.is_none() {
+        parts.path_and_query = Some(PathAndQuery::from_static("/"));
+    }
+    Uri::from_parts(parts).ok()
+}
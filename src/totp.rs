@@ -0,0 +1,419 @@
+//! Two-factor authentication with time-based one-time passwords (TOTP), the
+//! six-digit codes authenticator apps show.
+//!
+//! Setting it up takes two steps. [`start_totp_enrollment`] makes a secret
+//! and returns it as an `otpauth://` URI, which the profile page shows as a
+//! QR code, drawn in the browser. [`confirm_totp_enrollment`] checks a code
+//! made from it, and only then turns two-factor authentication on and hands
+//! out the recovery codes. From then on, signing in with a password also
+//! takes a code, or a recovery code, each of which works only once.
+//! Sessions that used one, or a passkey, may call the server fns behind
+//! [`TwoFactorLayer`](crate::middleware::TwoFactorLayer).
+
+#[cfg(feature = "ssr")]
+use crate::auth::{confirm_two_factor, require_user};
+use crate::errors::AppError;
+use leptos::prelude::*;
+use qrcode::{render::svg, QrCode};
+use serde::{Deserialize, Serialize};
+use server_fn::codec::GetUrl;
+
+/// How many recovery codes a user gets when turning two-factor
+/// authentication on.
+pub const RECOVERY_CODES: usize = 10;
+/// Wrong codes in a row after which a user's codes are refused, right ones
+/// too, for [`LOCKOUT_MINS`].
+pub const MAX_CODE_FAILURES: u32 = 5;
+/// How long codes are refused after [`MAX_CODE_FAILURES`] wrong ones.
+pub const LOCKOUT_MINS: u64 = 15;
+
+/// Whether the signed-in user has two-factor authentication on.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TotpStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: usize,
+}
+
+/// A new secret for an authenticator app.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TotpEnrollment {
+    /// The `otpauth://` URI, for a QR code.
+    pub uri: String,
+    /// The secret in base32, for typing in instead.
+    pub secret: String,
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::{
+        TotpEnrollment, TotpStatus, LOCKOUT_MINS, MAX_CODE_FAILURES,
+        RECOVERY_CODES,
+    };
+    use crate::{
+        auth::{User, UserId},
+        errors::AuthError,
+        seo::SITE_NAME,
+    };
+    use sha2::{Digest, Sha256};
+    use std::{
+        collections::HashMap,
+        sync::{LazyLock, Mutex},
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    };
+    use totp_rs::{Algorithm, TOTP as Totp};
+    use uuid::Uuid;
+
+    /// How long each code lasts, in seconds.
+    const STEP_SECS: u64 = 30;
+
+    /// Every user's TOTP secret and recovery codes.
+    pub static TOTP: LazyLock<TotpStore> = LazyLock::new(TotpStore::default);
+
+    #[derive(Debug, Default)]
+    pub struct TotpStore {
+        inner: Mutex<HashMap<UserId, Enrollment>>,
+    }
+
+    #[derive(Debug)]
+    struct Enrollment {
+        totp: Totp,
+        /// Whether a code made from the secret was checked, which turns
+        /// two-factor authentication on.
+        confirmed: bool,
+        /// Hashed like API keys, since they are random too.
+        recovery_codes: Vec<String>,
+        /// The last time step a code was used for, so no code works twice.
+        last_step: u64,
+        /// Wrong codes since the last right one, so they can't be guessed.
+        failures: u32,
+        /// Until when codes are refused, after [`MAX_CODE_FAILURES`].
+        locked_until: Option<Instant>,
+    }
+
+    impl Enrollment {
+        /// Whether `code` is the code for the current time step, or the
+        /// one just before or after it, and wasn't used yet.
+        fn check_code(&mut self, code: &str) -> bool {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_secs());
+            let step = now / STEP_SECS;
+            let matching = (step.saturating_sub(1)..=step + 1)
+                .filter(|step| *step > self.last_step)
+                .find(|step| self.totp.generate(step * STEP_SECS) == code);
+            if let Some(step) = matching {
+                self.last_step = step;
+            }
+            matching.is_some()
+        }
+
+        /// Whether `code` is one of the recovery codes left, which it
+        /// uses up.
+        fn use_recovery_code(&mut self, code: &str) -> bool {
+            let hash = hash_code(code);
+            let before = self.recovery_codes.len();
+            self.recovery_codes.retain(|kept| *kept != hash);
+            self.recovery_codes.len() < before
+        }
+    }
+
+    fn hash_code(code: &str) -> String {
+        format!("{:x}", Sha256::digest(code.to_ascii_lowercase().as_bytes()))
+    }
+
+    /// Codes are often typed or pasted with spaces in them.
+    fn normalize(code: &str) -> String {
+        code.chars().filter(|c| !c.is_whitespace()).collect()
+    }
+
+    impl TotpStore {
+        /// Makes a new secret for `user`, in place of any they didn't
+        /// confirm.
+        pub fn start(&self, user: &User) -> Result<TotpEnrollment, AuthError> {
+            let mut enrollments = self.inner.lock().unwrap();
+            if enrollments.get(&user.id).is_some_and(|e| e.confirmed) {
+                return Err(AuthError::TwoFactorEnabled);
+            }
+            let secret =
+                [Uuid::new_v4().into_bytes(), Uuid::new_v4().into_bytes()]
+                    .concat();
+            let totp = Totp::new(
+                Algorithm::SHA1,
+                6,
+                1,
+                STEP_SECS,
+                secret,
+                Some(SITE_NAME.to_string()),
+                user.name.clone(),
+            )
+            .expect("a 256-bit secret and an issuer without `:` are valid");
+            let enrollment = TotpEnrollment {
+                uri: totp.get_url(),
+                secret: totp.get_secret_base32(),
+            };
+            enrollments.insert(
+                user.id,
+                Enrollment {
+                    totp,
+                    confirmed: false,
+                    recovery_codes: Vec::new(),
+                    last_step: 0,
+                    failures: 0,
+                    locked_until: None,
+                },
+            );
+            Ok(enrollment)
+        }
+
+        /// Turns two-factor authentication on for `user` if `code` is made
+        /// from the secret they were given, returning their recovery codes.
+        pub fn confirm(
+            &self,
+            user: UserId,
+            code: &str,
+        ) -> Result<Vec<String>, AuthError> {
+            let mut enrollments = self.inner.lock().unwrap();
+            let enrollment = match enrollments.get_mut(&user) {
+                Some(enrollment) if enrollment.confirmed => {
+                    return Err(AuthError::TwoFactorEnabled)
+                }
+                Some(enrollment) => enrollment,
+                None => return Err(AuthError::TwoFactorNotStarted),
+            };
+            if !enrollment.check_code(&normalize(code)) {
+                return Err(AuthError::InvalidTwoFactorCode);
+            }
+            let codes = (0..RECOVERY_CODES)
+                .map(|_| {
+                    let hex = Uuid::new_v4().simple().to_string();
+                    format!("{}-{}", &hex[..5], &hex[5..10])
+                })
+                .collect::<Vec<_>>();
+            enrollment.recovery_codes =
+                codes.iter().map(|code| hash_code(code)).collect();
+            enrollment.confirmed = true;
+            Ok(codes)
+        }
+
+        pub fn is_enabled(&self, user: UserId) -> bool {
+            let enrollments = self.inner.lock().unwrap();
+            enrollments.get(&user).is_some_and(|e| e.confirmed)
+        }
+
+        /// Checks that `code` is `user`'s current code or one of their
+        /// recovery codes, neither of which works again afterwards. After
+        /// [`MAX_CODE_FAILURES`] wrong ones in a row, every code is refused
+        /// for [`LOCKOUT_MINS`].
+        pub fn verify(
+            &self,
+            user: UserId,
+            code: &str,
+        ) -> Result<(), AuthError> {
+            let mut enrollments = self.inner.lock().unwrap();
+            let Some(enrollment) =
+                enrollments.get_mut(&user).filter(|e| e.confirmed)
+            else {
+                return Err(AuthError::InvalidTwoFactorCode);
+            };
+            let now = Instant::now();
+            if let Some(until) = enrollment.locked_until.filter(|u| now < *u) {
+                let minutes = (until - now).as_secs().div_ceil(60);
+                return Err(AuthError::TwoFactorLocked { minutes });
+            }
+            enrollment.locked_until = None;
+            let code = normalize(code);
+            if enrollment.check_code(&code)
+                || enrollment.use_recovery_code(&code)
+            {
+                enrollment.failures = 0;
+                return Ok(());
+            }
+            enrollment.failures += 1;
+            if enrollment.failures >= MAX_CODE_FAILURES {
+                enrollment.failures = 0;
+                enrollment.locked_until =
+                    Some(now + Duration::from_secs(LOCKOUT_MINS * 60));
+            }
+            Err(AuthError::InvalidTwoFactorCode)
+        }
+
+        pub fn status(&self, user: UserId) -> TotpStatus {
+            let enrollments = self.inner.lock().unwrap();
+            match enrollments.get(&user).filter(|e| e.confirmed) {
+                Some(enrollment) => TotpStatus {
+                    enabled: true,
+                    recovery_codes_left: enrollment.recovery_codes.len(),
+                },
+                None => TotpStatus::default(),
+            }
+        }
+
+        /// Turns two-factor authentication off for `user`, if `code` is
+        /// one [`verify`](Self::verify) accepts.
+        pub fn disable(
+            &self,
+            user: UserId,
+            code: &str,
+        ) -> Result<(), AuthError> {
+            if !self.is_enabled(user) {
+                return Err(AuthError::TwoFactorDisabled);
+            }
+            self.verify(user, code)?;
+            self.inner.lock().unwrap().remove(&user);
+            Ok(())
+        }
+    }
+}
+
+#[server(input = GetUrl)]
+pub async fn totp_status() -> Result<TotpStatus, AppError> {
+    let user = require_user()?;
+    Ok(TOTP.status(user.id))
+}
+
+/// Makes a secret for the signed-in user's authenticator app. Two-factor
+/// authentication stays off until [`confirm_totp_enrollment`].
+#[server]
+pub async fn start_totp_enrollment() -> Result<TotpEnrollment, AppError> {
+    let user = require_user()?;
+    Ok(TOTP.start(&user)?)
+}
+
+/// Turns two-factor authentication on if `code` comes from the new secret,
+/// returning the recovery codes, which are never shown again.
+#[server]
+pub async fn confirm_totp_enrollment(
+    code: String,
+) -> Result<Vec<String>, AppError> {
+    let user = require_user()?;
+    let codes = TOTP.confirm(user.id, &code)?;
+    // the code just proved the second factor, so there's no need to sign in
+    // again before using it
+    confirm_two_factor();
+    tracing::info!(user = user.id, "two-factor authentication on");
+    Ok(codes)
+}
+
+#[server]
+pub async fn disable_totp(code: String) -> Result<(), AppError> {
+    let user = require_user()?;
+    TOTP.disable(user.id, &code)?;
+    tracing::info!(user = user.id, "two-factor authentication off");
+    Ok(())
+}
+
+/// `text` as an SVG QR code.
+fn qr_svg(text: &str) -> String {
+    QrCode::new(text.as_bytes())
+        .map(|code| {
+            code.render::<svg::Color>().min_dimensions(200, 200).build()
+        })
+        .unwrap_or_default()
+}
+
+/// Whether the signed-in user has two-factor authentication on, with forms
+/// to set it up or turn it off.
+#[component]
+pub fn TwoFactorSettings() -> impl IntoView {
+    let start = ServerAction::<StartTotpEnrollment>::new();
+    let confirm = ServerAction::<ConfirmTotpEnrollment>::new();
+    let disable = ServerAction::<DisableTotp>::new();
+    let status = Resource::new(
+        move || (confirm.version().get(), disable.version().get()),
+        |_| totp_status(),
+    );
+    let error = move || {
+        start
+            .value()
+            .get()
+            .and_then(Result::err)
+            .or_else(|| confirm.value().get().and_then(Result::err))
+            .or_else(|| disable.value().get().and_then(Result::err))
+            .map(|e| view! { <p>{e.to_string()}</p> })
+    };
+    let recovery_codes = move || {
+        confirm.value().get().and_then(Result::ok).map(|codes| {
+            view! {
+                <p>
+                    "Keep these recovery codes somewhere safe. Each signs you in once without your authenticator app, and they won't be shown again."
+                </p>
+                <ul class="recovery-codes">
+                    {codes
+                        .into_iter()
+                        .map(|code| view! { <li><code>{code}</code></li> })
+                        .collect::<Vec<_>>()}
+                </ul>
+            }
+        })
+    };
+    let enrolling = move || {
+        start.value().get().and_then(Result::ok).map(|enrollment| {
+            view! {
+                <p>"Scan this with your authenticator app, then enter the code it shows."</p>
+                <div class="totp-qr" inner_html=qr_svg(&enrollment.uri)></div>
+                <p>"Or type in this key: " <code>{enrollment.secret}</code></p>
+                <ActionForm action=confirm>
+                    <input
+                        name="code"
+                        placeholder="Code"
+                        inputmode="numeric"
+                        autocomplete="one-time-code"
+                        required
+                    />
+                    <input type="submit" value="Turn on" />
+                </ActionForm>
+            }
+        })
+    };
+
+    view! {
+        <h3>"Two-factor authentication"</h3>
+        <Transition fallback=|| view! { <p>"Loading..."</p> }>
+            {move || Suspend::new(async move {
+                match status.await {
+                    Ok(status) if status.enabled => {
+                        view! {
+                            <p>
+                                {format!(
+                                    "On, with {} recovery codes left. Signing in with a password also takes a code.",
+                                    status.recovery_codes_left,
+                                )}
+                            </p>
+                            <ActionForm action=disable>
+                                <input
+                                    name="code"
+                                    placeholder="Code or recovery code"
+                                    autocomplete="one-time-code"
+                                    required
+                                />
+                                <input type="submit" value="Turn off" />
+                            </ActionForm>
+                        }
+                            .into_any()
+                    }
+                    Ok(_) => {
+                        view! {
+                            <p>"Off. Turn it on to also need a code from an authenticator app to sign in."</p>
+                            <button
+                                on:click=move |_| {
+                                    start.dispatch(StartTotpEnrollment {});
+                                }
+                                disabled=move || start.pending().get()
+                            >
+                                "Set up"
+                            </button>
+                            {enrolling}
+                        }
+                            .into_any()
+                    }
+                    Err(e) => view! { <p>{e.to_string()}</p> }.into_any(),
+                }
+            })}
+        </Transition>
+        {recovery_codes}
+        {error}
+    }
+}
@@ -0,0 +1,365 @@
+//! Upload and streaming at once: a file is uploaded with one server fn
+//! while another streams back what's been made of it so far. Text files
+//! come back word by word, cleaned up the way a transcript would be. There
+//! is no speech recognition here, so audio files only come back as the
+//! stretches of time each chunk covers.
+//!
+//! The client picks a job ID and subscribes to [`transcription_events`]
+//! before it starts [`transcribe_file`], so nothing is missed; both are
+//! tied together by that ID, like the two halves of
+//! `FileUploadWithProgress`.
+
+use crate::{
+    channels::Tick,
+    codec::{Framed, FramedStream},
+    supervisor::{supervise, ConnectionState},
+};
+use leptos::{prelude::*, task::spawn_local};
+use serde::{Deserialize, Serialize};
+use server_fn::codec::{MultipartData, MultipartFormData};
+use std::ops::ControlFlow;
+use web_sys::{FormData, HtmlInputElement};
+
+/// The largest file that can be transcribed, in bytes.
+pub const MAX_TRANSCRIPTION_SIZE: u64 = 2 * 1024 * 1024;
+
+/// What the server has made of the next part of a file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TranscriptEvent {
+    /// The next word of a text file.
+    Word(String),
+    /// A stretch of an audio file, in seconds from the start.
+    Audio {
+        start: f64,
+        end: f64,
+    },
+    /// The whole file has been processed.
+    Done {
+        words: usize,
+    },
+    Failed(String),
+}
+
+impl TranscriptEvent {
+    fn is_last(&self) -> bool {
+        matches!(
+            self,
+            TranscriptEvent::Done { .. } | TranscriptEvent::Failed(_)
+        )
+    }
+}
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use super::TranscriptEvent;
+    use crate::{
+        auth::UserId,
+        channels::{DropOldest, Sequenced},
+    };
+    use dashmap::DashMap;
+    use futures::Stream;
+    use std::{sync::LazyLock, time::Duration};
+
+    /// How many events a reconnecting subscriber can catch up on.
+    const HISTORY: usize = 4096;
+
+    /// A finished job's events are kept this long, for late subscribers.
+    const KEEP_FINISHED: Duration = Duration::from_secs(5 * 60);
+
+    /// A pause between words, so the transcript visibly streams in even
+    /// when the upload is instant.
+    pub const WORD_DELAY: Duration = Duration::from_millis(30);
+
+    /// A guess at how many bytes of compressed speech make a second.
+    pub const AUDIO_BYTES_PER_SEC: f64 = 16_000.0;
+
+    /// Jobs by owner and the ID the client picked.
+    static JOBS: LazyLock<
+        DashMap<(UserId, String), DropOldest<TranscriptEvent>>,
+    > = LazyLock::new(DashMap::new);
+
+    /// Whether `job` could be an ID the client picked.
+    pub fn is_valid_job(job: &str) -> bool {
+        !job.is_empty()
+            && job.len() <= 32
+            && job.bytes().all(|b| b.is_ascii_hexdigit())
+    }
+
+    fn channel(owner: UserId, job: &str) -> DropOldest<TranscriptEvent> {
+        JOBS.entry((owner, job.to_string()))
+            .or_insert_with(|| {
+                DropOldest::new(format!("transcript:{owner}:{job}"), HISTORY)
+            })
+            .clone()
+    }
+
+    pub fn send(owner: UserId, job: &str, event: TranscriptEvent) {
+        let last = event.is_last();
+        channel(owner, job).send(event);
+        if last {
+            let job = job.to_string();
+            tokio::spawn(async move {
+                tokio::time::sleep(KEEP_FINISHED).await;
+                JOBS.remove(&(owner, job));
+            });
+        }
+    }
+
+    pub fn subscribe(
+        owner: UserId,
+        job: &str,
+        after: Option<u64>,
+    ) -> impl Stream<Item = Sequenced<TranscriptEvent>> + Send + 'static {
+        channel(owner, job).subscribe_from(after)
+    }
+
+    /// Splits text into words as it arrives a chunk at a time, holding back
+    /// a word (or a UTF-8 sequence) that a chunk cuts in two.
+    #[derive(Default)]
+    pub struct Words {
+        bytes: Vec<u8>,
+    }
+
+    impl Words {
+        /// The words `chunk` completes, cleaned up.
+        pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<String>, String> {
+            self.bytes.extend_from_slice(chunk);
+            let text = match std::str::from_utf8(&self.bytes) {
+                Ok(text) => text,
+                // a sequence cut off at the end waits for the next chunk
+                Err(e) if e.error_len().is_none() => {
+                    std::str::from_utf8(&self.bytes[..e.valid_up_to()]).unwrap()
+                }
+                Err(_) => return Err("the file isn't UTF-8 text".to_string()),
+            };
+            // the last word may go on in the next chunk
+            let complete = text.rfind(char::is_whitespace).map_or(0, |i| {
+                i + text[i..].chars().next().unwrap().len_utf8()
+            });
+            let words = clean(&text[..complete]);
+            self.bytes.drain(..complete);
+            Ok(words)
+        }
+
+        /// The last word, once the file has ended.
+        pub fn finish(self) -> Result<Vec<String>, String> {
+            let text = String::from_utf8(self.bytes)
+                .map_err(|_| "the file isn't UTF-8 text".to_string())?;
+            Ok(clean(&text))
+        }
+    }
+
+    /// `text`'s words, lower-cased and without the punctuation around them.
+    fn clean(text: &str) -> Vec<String> {
+        text.split_whitespace()
+            .map(|word| {
+                word.trim_matches(|c: char| !c.is_alphanumeric())
+                    .to_lowercase()
+            })
+            .filter(|word| !word.is_empty())
+            .collect()
+    }
+}
+
+/// Streams what's been made of job `job`'s file so far, from where the
+/// caller left off.
+#[server(output = Framed)]
+pub async fn transcription_events(
+    job: String,
+    after: Option<u64>,
+) -> Result<FramedStream<Tick<TranscriptEvent>>, ServerFnError> {
+    use crate::{
+        channels::{with_heartbeat, HEARTBEAT_INTERVAL},
+        crawlers::refuse_crawlers,
+        sandbox::{require_owner, until_reset},
+    };
+    use futures::StreamExt;
+
+    refuse_crawlers()?;
+    let owner = require_owner()?;
+    if !is_valid_job(&job) {
+        return Err(ServerFnError::new("invalid job ID"));
+    }
+    let events = subscribe(owner, &job, after).take_until(until_reset(owner));
+    let ticks = with_heartbeat(events, HEARTBEAT_INTERVAL);
+    Ok(FramedStream::new(ticks.map(Ok)))
+}
+
+/// Processes the uploaded file as it arrives, for the job named by the
+/// `job` field, which has to come before the file in the form.
+#[server(input = MultipartFormData)]
+pub async fn transcribe_file(data: MultipartData) -> Result<(), ServerFnError> {
+    use crate::{
+        errors::UploadError,
+        multipart::multipart_error,
+        quotas::{QuotaKind, QUOTAS},
+        sandbox::require_owner,
+    };
+
+    let owner = require_owner()?;
+    let mut data = data
+        .into_inner()
+        .ok_or_else(|| ServerFnError::new(UploadError::NotMultipart))?;
+    let invalid = |field: &str| {
+        ServerFnError::new(UploadError::InvalidField {
+            field: field.to_string(),
+        })
+    };
+
+    let mut job = None;
+    while let Some(mut field) =
+        data.next_field().await.map_err(multipart_error)?
+    {
+        if field.name() == Some("job") {
+            let text = field.text().await.map_err(multipart_error)?;
+            job = Some(text.trim().to_string()).filter(|job| is_valid_job(job));
+            continue;
+        }
+        let job = job.ok_or_else(|| invalid("job"))?;
+        let content_type = field
+            .content_type()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        let audio = content_type.starts_with("audio/");
+        if !audio && !content_type.starts_with("text/") {
+            let e = "only text and audio files can be transcribed".to_string();
+            send(owner, &job, TranscriptEvent::Failed(e.clone()));
+            return Err(ServerFnError::new(e));
+        }
+
+        let mut words = Words::default();
+        let mut word_count = 0;
+        let mut size = 0u64;
+        let processed = async {
+            while let Some(chunk) =
+                field.chunk().await.map_err(multipart_error)?
+            {
+                QUOTAS.consume(
+                    owner,
+                    QuotaKind::UploadBytes,
+                    chunk.len() as u64,
+                )?;
+                let start = size as f64 / AUDIO_BYTES_PER_SEC;
+                size += chunk.len() as u64;
+                if size > MAX_TRANSCRIPTION_SIZE {
+                    return Err(ServerFnError::new(UploadError::TooLarge {
+                        max: MAX_TRANSCRIPTION_SIZE,
+                    }));
+                }
+                if audio {
+                    let end = size as f64 / AUDIO_BYTES_PER_SEC;
+                    send(owner, &job, TranscriptEvent::Audio { start, end });
+                    continue;
+                }
+                for word in words.push(&chunk).map_err(ServerFnError::new)? {
+                    word_count += 1;
+                    send(owner, &job, TranscriptEvent::Word(word));
+                    tokio::time::sleep(WORD_DELAY).await;
+                }
+            }
+            for word in words.finish().map_err(ServerFnError::new)? {
+                word_count += 1;
+                send(owner, &job, TranscriptEvent::Word(word));
+            }
+            Ok::<_, ServerFnError>(())
+        }
+        .await;
+        let last = match &processed {
+            Ok(()) => TranscriptEvent::Done { words: word_count },
+            Err(e) => TranscriptEvent::Failed(e.to_string()),
+        };
+        send(owner, &job, last);
+        return processed;
+    }
+    Err(invalid("file"))
+}
+
+/// A random job ID, for the client to tie its two calls together with.
+fn new_job() -> String {
+    let high = (js_sys::Math::random() * u32::MAX as f64) as u32;
+    let low = (js_sys::Math::random() * u32::MAX as f64) as u32;
+    format!("{high:08x}{low:08x}")
+}
+
+/// Uploads a text or audio file and shows what the server makes of it as
+/// it goes.
+#[component]
+pub fn TranscriptionExample() -> impl IntoView {
+    let input_ref = NodeRef::<leptos::html::Input>::new();
+    let (transcript, set_transcript) = signal(Vec::<String>::new());
+    let (outcome, set_outcome) = signal(None::<String>);
+    let (connection, set_connection) = signal(ConnectionState::Connecting);
+    let (running, set_running) = signal(false);
+
+    let on_click = move |_| {
+        let input: HtmlInputElement = input_ref.get().unwrap().into();
+        let Some(file) = input.files().and_then(|files| files.get(0)) else {
+            set_outcome.set(Some("Pick a file first.".to_string()));
+            return;
+        };
+        let job = new_job();
+        set_transcript.set(Vec::new());
+        set_outcome.set(None);
+        set_running.set(true);
+
+        spawn_local(supervise(
+            {
+                let job = job.clone();
+                move |after| transcription_events(job.clone(), after)
+            },
+            set_connection,
+            move |event: TranscriptEvent| {
+                let last = event.is_last();
+                match event {
+                    TranscriptEvent::Word(word) => {
+                        set_transcript.update(|words| words.push(word))
+                    }
+                    TranscriptEvent::Audio { start, end } => set_transcript
+                        .update(|words| {
+                            words.push(format!("[audio {start:.1}s-{end:.1}s]"))
+                        }),
+                    TranscriptEvent::Done { words } => {
+                        set_outcome.set(Some(format!("Done: {words} words.")))
+                    }
+                    TranscriptEvent::Failed(e) => {
+                        set_outcome.set(Some(format!("Failed: {e}")))
+                    }
+                }
+                if last {
+                    set_running.set(false);
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            },
+        ));
+        spawn_local(async move {
+            let data = FormData::new().unwrap();
+            // `transcribe_file` reads the job before the file
+            data.append_with_str("job", &job).unwrap();
+            data.append_with_blob_and_filename("file", &file, &file.name())
+                .unwrap();
+            // failures also come through the event stream
+            _ = transcribe_file(data.into()).await;
+        });
+    };
+
+    view! {
+        <h3>"Streaming transcription"</h3>
+        <p>
+            "One server function takes the upload while another streams back what the server makes of it. Text files come back word by word; audio files (there's no speech recognition here) as the stretches of time each chunk covers."
+        </p>
+        <input type="file" accept="text/*,audio/*" node_ref=input_ref />
+        <button on:click=on_click disabled=running>
+            "Transcribe"
+        </button>
+        <p class="transcript">{move || transcript.get().join(" ")}</p>
+        <Show when=running>
+            <p>"Event stream: " {move || connection.get().to_string()}</p>
+        </Show>
+        {move || outcome.get().map(|outcome| view! { <p>{outcome}</p> })}
+    }
+}
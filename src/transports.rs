@@ -0,0 +1,223 @@
+//! The same call, uppercasing some text and counting its characters, over
+//! three transports, timed on every keystroke to show what each costs:
+//!
+//! - A `POST` with a JSON body, sent afresh every time.
+//! - A `GET` with the text in the URL, which the browser may answer from its
+//!   cache for [`ECHO_CACHE_SECS`], so text typed before comes back without
+//!   a round trip.
+//! - A websocket opened once, which spares each call its own HTTP request
+//!   and headers, but answers one text at a time, in order.
+
+use futures::{channel::mpsc, Stream, StreamExt};
+use leptos::{prelude::*, task::spawn_local};
+use serde::{Deserialize, Serialize};
+use server_fn::{
+    codec::{GetUrl, Json, JsonEncoding},
+    BoxedStream, Websocket,
+};
+use std::{collections::VecDeque, future::Future, pin::Pin};
+
+/// How long the browser may keep an [`echo_get`] reply.
+pub const ECHO_CACHE_SECS: u64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Echo {
+    pub upper: String,
+    pub chars: usize,
+}
+
+impl Echo {
+    pub fn of(text: &str) -> Self {
+        Echo {
+            upper: text.to_uppercase(),
+            chars: text.chars().count(),
+        }
+    }
+}
+
+#[server(input = Json)]
+pub async fn echo_post(text: String) -> Result<Echo, ServerFnError> {
+    Ok(Echo::of(&text))
+}
+
+/// Like [`echo_post`], but cacheable, since the same text always gets the
+/// same reply.
+#[server(input = GetUrl)]
+pub async fn echo_get(text: String) -> Result<Echo, ServerFnError> {
+    use http::{header::CACHE_CONTROL, HeaderValue};
+    use leptos_axum::ResponseOptions;
+
+    let cache_control = format!("private, max-age={ECHO_CACHE_SECS}");
+    if let (Some(response), Ok(cache_control)) = (
+        use_context::<ResponseOptions>(),
+        HeaderValue::from_str(&cache_control),
+    ) {
+        response.insert_header(CACHE_CONTROL, cache_control);
+    }
+    Ok(Echo::of(&text))
+}
+
+/// Echoes each text sent over the socket, in order.
+#[server(protocol = Websocket<JsonEncoding, JsonEncoding>)]
+pub async fn echo_socket(
+    texts: BoxedStream<String, ServerFnError>,
+) -> Result<BoxedStream<Echo, ServerFnError>, ServerFnError> {
+    let texts: Pin<Box<dyn Stream<Item = _> + Send>> = texts.into();
+    Ok(texts.map(|text| text.map(|text| Echo::of(&text))).into())
+}
+
+/// What one transport's calls took.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Latency {
+    pub calls: u32,
+    pub last_ms: f64,
+    pub total_ms: f64,
+    /// The reply to the newest keystroke answered so far, or why it failed.
+    pub reply: Option<Result<Echo, String>>,
+    /// The keystroke `reply` is for.
+    newest: u64,
+}
+
+impl Latency {
+    fn record(&mut self, keystroke: u64, ms: f64, reply: Result<Echo, String>) {
+        self.calls += 1;
+        self.last_ms = ms;
+        self.total_ms += ms;
+        // a slow reply to an older keystroke doesn't replace a newer one
+        if keystroke >= self.newest {
+            self.newest = keystroke;
+            self.reply = Some(reply);
+        }
+    }
+
+    pub fn mean_ms(&self) -> Option<f64> {
+        (self.calls > 0).then(|| self.total_ms / f64::from(self.calls))
+    }
+}
+
+/// Records how long `call` takes in `stats`.
+fn time_call(
+    stats: RwSignal<Latency>,
+    keystroke: u64,
+    call: impl Future<Output = Result<Echo, ServerFnError>> + 'static,
+) {
+    let started = js_sys::Date::now();
+    spawn_local(async move {
+        let reply = call.await.map_err(|e| e.to_string());
+        let ms = js_sys::Date::now() - started;
+        _ = stats.try_update(|stats| stats.record(keystroke, ms, reply));
+    });
+}
+
+/// Sends every keystroke's text over all three transports at once, and
+/// shows how long each took to answer.
+#[component]
+pub fn TransportComparison() -> impl IntoView {
+    let post = RwSignal::new(Latency::default());
+    let get = RwSignal::new(Latency::default());
+    let socket = RwSignal::new(Latency::default());
+    let keystrokes = StoredValue::new(0u64);
+    // the socket's end to send on, and each text sent on it that hasn't
+    // been answered yet, with when it was sent
+    let sender = StoredValue::new(
+        None::<mpsc::UnboundedSender<Result<String, ServerFnError>>>,
+    );
+    let in_flight = StoredValue::new(VecDeque::<(u64, f64)>::new());
+
+    // effects only run in the browser, so the socket is opened there, once
+    Effect::new(move |_| {
+        let (tx, rx) = mpsc::unbounded();
+        sender.set_value(Some(tx));
+        spawn_local(async move {
+            let replies = match echo_socket(rx.into()).await {
+                Ok(replies) => replies,
+                Err(e) => {
+                    _ = socket
+                        .try_update(|s| s.reply = Some(Err(e.to_string())));
+                    return;
+                }
+            };
+            let mut replies: Pin<Box<dyn Stream<Item = _> + Send>> =
+                replies.into();
+            while let Some(reply) = replies.next().await {
+                let Some((keystroke, started)) = in_flight
+                    .try_update_value(|sent| sent.pop_front())
+                    .flatten()
+                else {
+                    break;
+                };
+                let ms = js_sys::Date::now() - started;
+                let reply = reply.map_err(|e| e.to_string());
+                if socket
+                    .try_update(|s| s.record(keystroke, ms, reply))
+                    .is_none()
+                {
+                    break;
+                }
+            }
+        });
+    });
+
+    let on_input = move |ev| {
+        let text = event_target_value(&ev);
+        let keystroke = keystrokes.get_value() + 1;
+        keystrokes.set_value(keystroke);
+        time_call(post, keystroke, echo_post(text.clone()));
+        time_call(get, keystroke, echo_get(text.clone()));
+        in_flight.update_value(|sent| {
+            sent.push_back((keystroke, js_sys::Date::now()))
+        });
+        let sent = sender.with_value(|tx| {
+            tx.as_ref()
+                .is_some_and(|tx| tx.unbounded_send(Ok(text)).is_ok())
+        });
+        if !sent {
+            in_flight.update_value(|sent| _ = sent.pop_back());
+        }
+    };
+
+    let row = move |name: &'static str, stats: RwSignal<Latency>| {
+        view! {
+            <tr>
+                <td>{name}</td>
+                <td>
+                    {move || match stats.get().reply {
+                        Some(Ok(echo)) => format!("{} ({} characters)", echo.upper, echo.chars),
+                        Some(Err(e)) => e,
+                        None => "-".to_string(),
+                    }}
+                </td>
+                <td>{move || stats.get().calls}</td>
+                <td>{move || format!("{:.0}", stats.get().last_ms)}</td>
+                <td>
+                    {move || {
+                        stats.get().mean_ms().map_or("-".to_string(), |ms| format!("{ms:.1}"))
+                    }}
+                </td>
+            </tr>
+        }
+    };
+
+    view! {
+        <h3>"Three transports, one call"</h3>
+        <p>
+            "Every keystroke is sent as a JSON " <code>"POST"</code> ", as a cacheable "
+            <code>"GET"</code> " and over a websocket that stays open, and each reply is timed. "
+            "Type something you've typed before to see the " <code>"GET"</code>
+            " come back from the browser's cache."
+        </p>
+        <input placeholder="Type to compare" on:input=on_input />
+        <table>
+            <tr>
+                <th>"Transport"</th>
+                <th>"Reply"</th>
+                <th>"Calls"</th>
+                <th>"Last (ms)"</th>
+                <th>"Average (ms)"</th>
+            </tr>
+            {row("POST (JSON)", post)}
+            {row("GET (cached)", get)}
+            {row("Websocket", socket)}
+        </table>
+    }
+}
@@ -0,0 +1,189 @@
+#[cfg(feature = "ssr")]
+use crate::{
+    attachments::remove_files,
+    audit::AuditAction,
+    sandbox::{current_owner, require_owner},
+    storage::ROWS,
+};
+use crate::{
+    prefetch::{cached, RouteData},
+    seo::PageMeta,
+    storage::{Row, TrashedRow, TRASH_DAYS},
+};
+use chrono::{DateTime, Local, Utc};
+use leptos::prelude::*;
+use leptos_router::components::A;
+use server_fn::codec::GetUrl;
+use std::time::Duration;
+
+/// How often the countdowns on the trash page are brought up to date.
+const COUNTDOWN_REFRESH: Duration = Duration::from_secs(60);
+
+#[cfg(feature = "ssr")]
+pub use server::*;
+
+#[cfg(feature = "ssr")]
+mod server {
+    use crate::{
+        attachments::remove_files,
+        audit::{AuditAction, AUDIT},
+        cache::{CacheTag, CACHE},
+        storage::ROWS,
+    };
+    use chrono::Utc;
+    use std::time::Duration;
+
+    /// How often [`purge_expired`] runs.
+    pub const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+    /// Purges every user's trashed rows whose time is up, along with their
+    /// attachments.
+    pub async fn purge_expired() {
+        for (user_id, row) in ROWS.purge_expired(Utc::now()) {
+            tracing::info!(user_id, row_id = row.id, "purged row");
+            AUDIT.record(user_id, row.id, AuditAction::Purged);
+            CACHE.invalidate(CacheTag::Rows(user_id));
+            remove_files(&row.attachments).await;
+        }
+    }
+}
+
+/// The caller's deleted rows, the ones purged soonest first.
+#[server(input = GetUrl)]
+pub async fn list_trash() -> Result<Vec<TrashedRow>, ServerFnError> {
+    Ok(current_owner()
+        .map(|owner| ROWS.list_trash(owner))
+        .unwrap_or_default())
+}
+
+/// Takes a row back out of the trash.
+#[server]
+pub async fn restore_row(id: u64) -> Result<Row, ServerFnError> {
+    let owner = require_owner()?;
+    ROWS.transaction(owner, |tx| {
+        let row = tx.restore(id).ok_or_else(|| not_in_trash(id))?;
+        tx.audit(id, AuditAction::Restored);
+        Ok(row)
+    })
+}
+
+/// Deletes a row in the trash for good, along with its attachments, without
+/// waiting for it to expire.
+#[server]
+pub async fn purge_row(id: u64) -> Result<(), ServerFnError> {
+    let owner = require_owner()?;
+    let row = ROWS.transaction(owner, |tx| {
+        let row = tx.purge(id).ok_or_else(|| not_in_trash(id))?;
+        tx.audit(id, AuditAction::Purged);
+        Ok::<_, ServerFnError>(row)
+    })?;
+    remove_files(&row.attachments).await;
+    Ok(())
+}
+
+#[cfg(feature = "ssr")]
+fn not_in_trash(id: u64) -> ServerFnError {
+    ServerFnError::new(format!("there is no row {id} in the trash"))
+}
+
+/// How long until `purge_at`, in the largest whole unit that fits.
+fn countdown(purge_at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let left = purge_at - now;
+    let (n, unit) = if left.num_days() > 0 {
+        (left.num_days(), "day")
+    } else if left.num_hours() > 0 {
+        (left.num_hours(), "hour")
+    } else if left.num_minutes() > 0 {
+        (left.num_minutes(), "minute")
+    } else {
+        return "purges any moment now".to_string();
+    };
+    let s = if n == 1 { "" } else { "s" };
+    format!("purges in {n} {unit}{s}")
+}
+
+/// Deleted rows, each with a countdown to when it is purged and buttons to
+/// restore or purge it right away.
+#[component]
+pub fn TrashPage() -> impl IntoView {
+    let restore = ServerAction::<RestoreRow>::new();
+    let purge = ServerAction::<PurgeRow>::new();
+    let trash = Resource::new(
+        move || (restore.version().get(), purge.version().get()),
+        |_| cached(RouteData::Trash.key(), list_trash),
+    );
+    let (now, set_now) = signal(Utc::now());
+
+    Effect::new(move |_| {
+        if let Ok(handle) = set_interval_with_handle(
+            move || set_now.set(Utc::now()),
+            COUNTDOWN_REFRESH,
+        ) {
+            on_cleanup(move || handle.clear());
+        }
+    });
+    let error = move || {
+        restore
+            .value()
+            .get()
+            .and_then(Result::err)
+            .or_else(|| purge.value().get().and_then(Result::err))
+            .map(|e| view! { <p>{e.to_string()}</p> })
+    };
+
+    view! {
+        <PageMeta title="Trash" description="Deleted rows, until they're purged." private=true />
+        <h2>"Trash"</h2>
+        <p>
+            <A href="/">"Back to the demo"</A>
+        </p>
+        <p>{format!("Deleted rows are purged for good after {TRASH_DAYS} days.")}</p>
+        {error}
+        <Transition fallback=|| view! { <p>"Loading..."</p> }>
+            {move || Suspend::new(async move {
+                match trash.await {
+                    Ok(trash) if trash.is_empty() => {
+                        view! { <p>"The trash is empty."</p> }.into_any()
+                    }
+                    Ok(trash) => {
+                        view! {
+                            <ul class="trash">
+                                {trash
+                                    .into_iter()
+                                    .map(|TrashedRow { row, deleted_at, purge_at }| {
+                                        let id = row.id;
+                                        let deleted_at = deleted_at.with_timezone(&Local);
+                                        view! {
+                                            <li>
+                                                <div class="markdown" inner_html=row.html />
+                                                <small>
+                                                    "Deleted "
+                                                    {deleted_at.format("%Y-%m-%d %H:%M").to_string()}
+                                                    ", "
+                                                    <time datetime=purge_at.to_rfc3339()>
+                                                        {move || countdown(purge_at, now.get())}
+                                                    </time>
+                                                </small>
+                                                " "
+                                                <ActionForm action=restore>
+                                                    <input type="hidden" name="id" value=id />
+                                                    <input type="submit" value="Restore" />
+                                                </ActionForm>
+                                                <ActionForm action=purge>
+                                                    <input type="hidden" name="id" value=id />
+                                                    <input type="submit" value="Purge now" />
+                                                </ActionForm>
+                                            </li>
+                                        }
+                                    })
+                                    .collect::<Vec<_>>()}
+                            </ul>
+                        }
+                            .into_any()
+                    }
+                    Err(e) => view! { <p>{e.to_string()}</p> }.into_any(),
+                }
+            })}
+        </Transition>
+    }
+}
@@ -0,0 +1,426 @@
+//! A drop zone for attachments, and the queue behind it. Files that are
+//! dropped, pasted, picked or photographed are queued, and attached with
+//! [`attach_file`] a few at a time; each one shows how it's getting on
+//! until it's attached.
+
+use crate::{
+    attachments::{attach_file, MAX_ATTACHMENT_SIZE},
+    storage::Attachment,
+};
+use js_sys::{Array, Promise, Reflect};
+use leptos::{
+    ev::{ClipboardEvent, DragEvent},
+    html::Video,
+    prelude::*,
+    task::spawn_local,
+};
+use std::collections::VecDeque;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    Blob, CanvasRenderingContext2d, File, FileList, FilePropertyBag, FormData,
+    HtmlCanvasElement, HtmlInputElement, HtmlVideoElement, MediaStream,
+    MediaStreamConstraints, MediaStreamTrack,
+};
+
+/// How many files a queue uploads at once, unless it's told otherwise.
+pub const DEFAULT_PARALLEL_UPLOADS: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum UploadStatus {
+    Queued,
+    Uploading,
+    Failed(String),
+}
+
+/// A file in an [`UploadQueue`]. It leaves the queue once it's attached.
+#[derive(Debug, Clone)]
+pub struct QueuedUpload {
+    pub id: usize,
+    pub row_id: u64,
+    pub file_name: String,
+    pub status: UploadStatus,
+}
+
+/// Files waiting to be attached to rows, uploaded `parallelism` at a time
+/// in the order they were queued. It's `Copy`, and lives as long as the
+/// component that made it, so row lists that re-render as files are
+/// attached don't lose their place in it.
+#[derive(Clone, Copy)]
+pub struct UploadQueue {
+    parallelism: usize,
+    items: RwSignal<Vec<QueuedUpload>>,
+    /// The files of the queued items, by ID.
+    pending: StoredValue<VecDeque<(usize, File)>, LocalStorage>,
+    running: StoredValue<usize>,
+    next_id: StoredValue<usize>,
+    on_attached: Callback<Attachment>,
+}
+
+impl UploadQueue {
+    /// `on_attached` runs with each file once it has been attached.
+    pub fn new(parallelism: usize, on_attached: Callback<Attachment>) -> Self {
+        Self {
+            parallelism: parallelism.max(1),
+            items: RwSignal::new(Vec::new()),
+            pending: StoredValue::new_local(VecDeque::new()),
+            running: StoredValue::new(0),
+            next_id: StoredValue::new(0),
+            on_attached,
+        }
+    }
+
+    /// Queues `files` to be attached to row `row_id`. Files that are too
+    /// big fail right away.
+    pub fn push(self, row_id: u64, files: impl IntoIterator<Item = File>) {
+        for file in files {
+            let id = self.next_id.get_value();
+            self.next_id.set_value(id + 1);
+            let status = if file.size() as u64 > MAX_ATTACHMENT_SIZE {
+                UploadStatus::Failed(format!(
+                    "it's over the {} MiB limit",
+                    MAX_ATTACHMENT_SIZE / (1024 * 1024)
+                ))
+            } else {
+                UploadStatus::Queued
+            };
+            let item = QueuedUpload {
+                id,
+                row_id,
+                file_name: file.name(),
+                status,
+            };
+            if item.status == UploadStatus::Queued {
+                self.pending.update_value(|pending| {
+                    pending.push_back((id, file));
+                });
+            }
+            self.items.update(|items| items.push(item));
+        }
+        self.pump();
+    }
+
+    /// `row_id`'s files that are queued, uploading or have failed.
+    pub fn items(self, row_id: u64) -> Vec<QueuedUpload> {
+        self.items.with(|items| {
+            items
+                .iter()
+                .filter(|item| item.row_id == row_id)
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Takes item `id` out of the list.
+    pub fn dismiss(self, id: usize) {
+        self.items
+            .update(|items| items.retain(|item| item.id != id));
+    }
+
+    fn set_status(self, id: usize, status: UploadStatus) {
+        self.items.update(|items| {
+            if let Some(item) = items.iter_mut().find(|item| item.id == id) {
+                item.status = status;
+            }
+        });
+    }
+
+    /// Starts queued uploads until `parallelism` of them are running.
+    fn pump(self) {
+        while self.running.get_value() < self.parallelism {
+            let Some((id, file)) =
+                self.pending.try_update_value(VecDeque::pop_front).flatten()
+            else {
+                return;
+            };
+            let Some(row_id) = self.items.with_untracked(|items| {
+                items
+                    .iter()
+                    .find(|item| item.id == id)
+                    .map(|item| item.row_id)
+            }) else {
+                // dismissed while it was queued
+                continue;
+            };
+            self.running.update_value(|running| *running += 1);
+            self.set_status(id, UploadStatus::Uploading);
+            spawn_local(async move {
+                let attached = upload(row_id, &file).await;
+                let gone = self
+                    .running
+                    .try_update_value(|running| *running -= 1)
+                    .is_none();
+                if gone {
+                    // the list that queued it has been unmounted
+                    return;
+                }
+                match attached {
+                    Ok(attachment) => {
+                        self.dismiss(id);
+                        self.on_attached.run(attachment);
+                    }
+                    Err(e) => self.set_status(id, UploadStatus::Failed(e)),
+                }
+                self.pump();
+            });
+        }
+    }
+}
+
+async fn upload(row_id: u64, file: &File) -> Result<Attachment, String> {
+    let data = FormData::new().unwrap();
+    // `attach_file` reads the row before the file
+    data.append_with_str("row_id", &row_id.to_string()).unwrap();
+    data.append_with_blob_and_filename("file", file, &file.name())
+        .unwrap();
+    attach_file(data.into()).await.map_err(|e| e.to_string())
+}
+
+fn files_of(list: &FileList) -> Vec<File> {
+    (0..list.length()).filter_map(|i| list.get(i)).collect()
+}
+
+/// The message of a rejected promise or a thrown exception.
+fn js_error(e: JsValue) -> String {
+    Reflect::get(&e, &"message".into())
+        .ok()
+        .and_then(|message| message.as_string())
+        .unwrap_or_else(|| format!("{e:?}"))
+}
+
+fn named_file(
+    blob: &Blob,
+    name: &str,
+    content_type: &str,
+) -> Result<File, String> {
+    let options = FilePropertyBag::new();
+    options.set_type(content_type);
+    File::new_with_blob_sequence_and_options(&Array::of1(blob), name, &options)
+        .map_err(js_error)
+}
+
+/// A name for a file made now, like `photo-20250101-120000.jpg`.
+fn timestamped(prefix: &str, extension: &str) -> String {
+    let now = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    format!("{prefix}-{now}.{extension}")
+}
+
+/// A pasted image renamed after when it was pasted; browsers call every
+/// screenshot `image.png`.
+fn pasted(file: File) -> File {
+    let content_type = file.type_();
+    let Some(extension) = content_type.strip_prefix("image/") else {
+        return file;
+    };
+    let name = timestamped("pasted", extension);
+    named_file(&file, &name, &content_type).unwrap_or(file)
+}
+
+async fn open_camera() -> Result<MediaStream, String> {
+    let devices = window().navigator().media_devices().map_err(|_| {
+        "the browser won't share a camera with this page; it needs HTTPS"
+            .to_string()
+    })?;
+    let constraints = MediaStreamConstraints::new();
+    constraints.set_video(&JsValue::TRUE);
+    let stream = devices
+        .get_user_media_with_constraints(&constraints)
+        .map_err(js_error)?;
+    let stream = JsFuture::from(stream).await.map_err(js_error)?;
+    Ok(stream.unchecked_into())
+}
+
+fn close_camera(stream: &MediaStream) {
+    for track in stream.get_tracks().iter() {
+        track.unchecked_into::<MediaStreamTrack>().stop();
+    }
+}
+
+/// What `video` shows right now, as a JPEG.
+async fn snapshot(video: &HtmlVideoElement) -> Result<File, String> {
+    let canvas = document()
+        .create_element("canvas")
+        .map_err(js_error)?
+        .unchecked_into::<HtmlCanvasElement>();
+    canvas.set_width(video.video_width());
+    canvas.set_height(video.video_height());
+    let context = canvas
+        .get_context("2d")
+        .map_err(js_error)?
+        .ok_or("the browser has no 2D canvas")?
+        .unchecked_into::<CanvasRenderingContext2d>();
+    context
+        .draw_image_with_html_video_element(video, 0.0, 0.0)
+        .map_err(js_error)?;
+    let blob = JsFuture::from(Promise::new(&mut |resolve, _| {
+        _ = canvas.to_blob_with_type(&resolve, "image/jpeg");
+    }))
+    .await
+    .map_err(js_error)?;
+    // an empty canvas makes no blob
+    if blob.is_null() {
+        return Err("the camera hasn't shown anything yet".to_string());
+    }
+    named_file(
+        &blob.unchecked_into(),
+        &timestamped("photo", "jpg"),
+        "image/jpeg",
+    )
+}
+
+/// Where files are dropped, pasted or picked to attach them to row
+/// `row_id`, and how each one is getting on.
+#[component]
+pub fn DropZone(row_id: u64, queue: UploadQueue) -> impl IntoView {
+    let (over, set_over) = signal(false);
+
+    view! {
+        <div
+            class="drop-zone"
+            class:over=over
+            tabindex="0"
+            on:dragover=move |ev: DragEvent| {
+                ev.prevent_default();
+                set_over.set(true);
+            }
+            on:dragleave=move |_| set_over.set(false)
+            on:drop=move |ev: DragEvent| {
+                ev.prevent_default();
+                set_over.set(false);
+                if let Some(files) = ev.data_transfer().and_then(|data| data.files()) {
+                    queue.push(row_id, files_of(&files));
+                }
+            }
+            on:paste=move |ev: ClipboardEvent| {
+                let files = ev
+                    .clipboard_data()
+                    .and_then(|data| data.files())
+                    .map(|files| files_of(&files).into_iter().map(pasted).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                // pasted text is left alone
+                if !files.is_empty() {
+                    ev.prevent_default();
+                    queue.push(row_id, files);
+                }
+            }
+        >
+            <label>
+                "Drop files here, paste them, or pick some: "
+                <input
+                    type="file"
+                    multiple
+                    on:change=move |ev| {
+                        let input = event_target::<HtmlInputElement>(&ev);
+                        if let Some(files) = input.files() {
+                            queue.push(row_id, files_of(&files));
+                        }
+                        input.set_value("");
+                    }
+                />
+            </label>
+            <CameraCapture row_id queue />
+        </div>
+        <ul class="upload-queue">
+            <For
+                each=move || queue.items(row_id)
+                key=|item| (item.id, item.status.clone())
+                let:item
+            >
+                <li>
+                    {item.file_name}
+                    {match item.status {
+                        UploadStatus::Queued => " (queued)".into_any(),
+                        UploadStatus::Uploading => " (uploading...)".into_any(),
+                        UploadStatus::Failed(e) => {
+                            let id = item.id;
+                            view! {
+                                " couldn't be attached: "
+                                {e}
+                                " "
+                                <button on:click=move |_| queue.dismiss(id)>"Dismiss"</button>
+                            }
+                                .into_any()
+                        }
+                    }}
+                </li>
+            </For>
+        </ul>
+    }
+}
+
+/// Photos for row `row_id`: a file input that phones open their camera app
+/// for, and where the browser lets the page use a camera, a live view to
+/// take snapshots of.
+#[component]
+fn CameraCapture(row_id: u64, queue: UploadQueue) -> impl IntoView {
+    let video_ref = NodeRef::<Video>::new();
+    let stream = StoredValue::new_local(None::<MediaStream>);
+    let (live, set_live) = signal(false);
+    let (error, set_error) = signal(None::<String>);
+
+    let stop = move || {
+        if let Some(stream) = stream.try_update_value(Option::take).flatten() {
+            close_camera(&stream);
+        }
+        if let Some(video) = video_ref.get_untracked() {
+            video.set_src_object(None);
+        }
+        _ = set_live.try_set(false);
+    };
+    on_cleanup(stop);
+    let start = move |_| {
+        set_error.set(None);
+        spawn_local(async move {
+            match open_camera().await {
+                Ok(media) => {
+                    if let Some(video) = video_ref.get_untracked() {
+                        video.set_src_object(Some(&media));
+                    }
+                    stream.set_value(Some(media));
+                    set_live.set(true);
+                }
+                Err(e) => set_error.set(Some(e)),
+            }
+        });
+    };
+    let snap = move |_| {
+        let Some(video) = video_ref.get_untracked() else {
+            return;
+        };
+        spawn_local(async move {
+            match snapshot(&video).await {
+                Ok(photo) => queue.push(row_id, [photo]),
+                Err(e) => set_error.set(Some(e)),
+            }
+        });
+    };
+
+    view! {
+        <p class="camera">
+            <label>
+                "Take a photo: "
+                <input
+                    type="file"
+                    accept="image/*"
+                    capture="environment"
+                    on:change=move |ev| {
+                        let input = event_target::<HtmlInputElement>(&ev);
+                        if let Some(files) = input.files() {
+                            queue.push(row_id, files_of(&files));
+                        }
+                        input.set_value("");
+                    }
+                />
+            </label>
+            <Show
+                when=move || live.get()
+                fallback=move || view! { <button on:click=start>"Use the camera"</button> }
+            >
+                <button on:click=snap>"Snap"</button>
+                <button on:click=move |_| stop()>"Close the camera"</button>
+            </Show>
+            {move || error.get().map(|e| view! { " Camera: " {e} })}
+        </p>
+        <video node_ref=video_ref autoplay playsinline muted hidden=move || !live.get() />
+    }
+}
@@ -0,0 +1,43 @@
+//! Example inputs kept in the page's query string, so reloading or sharing
+//! the URL brings them back.
+//!
+//! The server reads the same query when it renders the page, so the inputs
+//! and whatever they load already show their values before the page
+//! hydrates. Each input needs a parameter name no other example on its page
+//! uses.
+
+use leptos::prelude::*;
+use leptos_router::{hooks::query_signal_with_options, NavigateOptions};
+use std::str::FromStr;
+
+/// The value of the query parameter `name`, or `None` if it's missing or
+/// doesn't parse, and a setter that changes it, leaving it out for `None`.
+///
+/// Setting it replaces the current history entry instead of adding one,
+/// and doesn't scroll, so an input can set it as it changes.
+pub fn use_url_state<T>(
+    name: &'static str,
+) -> (Memo<Option<T>>, SignalSetter<Option<T>>)
+where
+    T: FromStr + ToString + PartialEq + Send + Sync + 'static,
+{
+    query_signal_with_options(
+        name,
+        NavigateOptions {
+            replace: true,
+            scroll: false,
+            ..Default::default()
+        },
+    )
+}
+
+/// [`use_url_state`] for text, where blank text leaves the parameter out.
+pub fn use_url_text(
+    name: &'static str,
+) -> (Signal<String>, impl Fn(String) + Copy + 'static) {
+    let (text, set_text) = use_url_state::<String>(name);
+    let set = move |value: String| {
+        set_text.set(Some(value).filter(|value| !value.trim().is_empty()))
+    };
+    (Signal::derive(move || text.get().unwrap_or_default()), set)
+}
@@ -0,0 +1,91 @@
+//! The one watcher on `./watched_files`, shared by everything that wants to
+//! hear about changes there: the file watcher example's streams and the
+//! task that turns new files into rows.
+
+use crate::channels::{DropOldest, Polled, Sequenced};
+use futures::Stream;
+use notify::{
+    Config, Error, Event, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+/// The directory that is watched, recursively.
+pub const WATCHED_DIR: &str = "./watched_files";
+
+/// Something changed at `path`.
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub path: PathBuf,
+    /// Whether the file or directory was just created, rather than
+    /// modified or removed.
+    pub created: bool,
+}
+
+impl Change {
+    pub fn file_name(&self) -> Option<&str> {
+        self.path.file_name().and_then(|name| name.to_str())
+    }
+}
+
+/// Changes (or watcher errors) in [`WATCHED_DIR`]. Each one matters, but a
+/// stalled subscriber shouldn't buffer them forever, so lagging subscribers
+/// lose the oldest.
+type Changes = DropOldest<Result<Change, String>>;
+
+static CHANGES: Mutex<Option<Changes>> = Mutex::new(None);
+
+/// Subscribes to the shared watcher, starting it on first use.
+pub fn subscribe(
+    after: Option<u64>,
+) -> Result<
+    impl Stream<Item = Sequenced<Result<Change, String>>> + Send + 'static,
+    Error,
+> {
+    Ok(changes()?.subscribe_from(after))
+}
+
+/// Long-polls the shared watcher, starting it on first use; see
+/// [`DropOldest::poll_from`].
+pub async fn poll<U>(
+    after: Option<u64>,
+    wait: Duration,
+    keep: impl FnMut(Result<Change, String>) -> Option<U>,
+) -> Result<Polled<U>, Error> {
+    let changes = changes()?;
+    Ok(changes.poll_from(after, wait, keep).await)
+}
+
+fn changes() -> Result<Changes, Error> {
+    let mut changes = CHANGES.lock().unwrap();
+    if let Some(changes) = &*changes {
+        return Ok(changes.clone());
+    }
+
+    let channel = Changes::new("watched_files", 64);
+    let mut watcher = RecommendedWatcher::new(
+        {
+            let channel = channel.clone();
+            move |res: Result<Event, Error>| match res {
+                Ok(ev) => {
+                    if let Some(path) = ev.paths.last() {
+                        channel.send(Ok(Change {
+                            path: path.clone(),
+                            created: ev.kind.is_create(),
+                        }));
+                    }
+                }
+                Err(e) => channel.send(Err(e.to_string())),
+            }
+        },
+        Config::default(),
+    )?;
+    watcher.watch(Path::new(WATCHED_DIR), RecursiveMode::Recursive)?;
+    std::mem::forget(watcher);
+
+    *changes = Some(channel.clone());
+    Ok(channel)
+}
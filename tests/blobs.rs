@@ -0,0 +1,52 @@
+//! Checks that a blob store only ever touches blobs, whatever key it's
+//! handed.
+
+use server_fns_axum::{
+    blobs::{BlobStore, LocalStore},
+    errors::BlobError,
+};
+use std::path::PathBuf;
+
+/// An empty directory for a store, next to a file that isn't a blob.
+fn scratch(name: &str) -> (PathBuf, PathBuf) {
+    let root = std::env::temp_dir()
+        .join(format!("blobs-{name}-{}", uuid::Uuid::new_v4().simple()));
+    let dir = root.join("attachments");
+    std::fs::create_dir_all(&dir).unwrap();
+    let outside = root.join("secret");
+    std::fs::write(&outside, "not a blob").unwrap();
+    (dir, outside)
+}
+
+#[tokio::test]
+async fn keys_that_are_not_attachment_ids_are_refused() {
+    let (dir, outside) = scratch("keys");
+    let store = LocalStore { dir };
+    let absolute = outside.to_str().unwrap();
+
+    for key in ["../secret", absolute, "", "0123456789abcdef"] {
+        let got = store.get(key, None).await.err();
+        assert!(
+            matches!(got, Some(BlobError::InvalidKey { .. })),
+            "{key:?} was read"
+        );
+        let deleted = store.delete(key).await;
+        assert!(
+            matches!(deleted, Err(BlobError::InvalidKey { .. })),
+            "{key:?} was deleted"
+        );
+    }
+    assert!(outside.exists());
+}
+
+#[tokio::test]
+async fn a_blob_can_be_stored_under_its_id() {
+    let (dir, outside) = scratch("ids");
+    let store = LocalStore { dir };
+    let key = uuid::Uuid::new_v4().simple().to_string();
+
+    store.put_file(&key, &outside, "text/plain").await.unwrap();
+
+    assert!(store.exists(&key).await.unwrap());
+    assert!(!outside.exists());
+}
@@ -0,0 +1,383 @@
+//! Round-trips arbitrary values through the custom codecs, and feeds them
+//! arbitrary bytes, checking that whatever the client half encodes the server
+//! half decodes unchanged, and that garbage is rejected with a
+//! `Deserialization` error rather than a panic.
+
+use futures::{executor::block_on, future, sink, stream, Sink, Stream};
+use proptest::prelude::*;
+use serde::{Deserialize, Serialize};
+use server_fn::{
+    codec::{Cbor, FromReq, FromRes, IntoReq, IntoRes},
+    error::{FromServerFnError, IntoAppError, ServerFnErrorErr},
+    request::{ClientReq, Req},
+    response::{ClientRes, TryRes},
+    Bytes, ServerFnError,
+};
+use server_fns_axum::{
+    app::{Toml, TomlEncoded},
+    codec::AlignedRkyv,
+    fixtures::Fixture,
+};
+use std::{borrow::Cow, future::Future};
+
+/// A request or response that goes straight from the half of a codec that
+/// writes it to the half that reads it.
+struct Loopback {
+    content_type: String,
+    body: Bytes,
+}
+
+impl<E: FromServerFnError> ClientReq<E> for Loopback {
+    type FormData = ();
+
+    fn try_new_req_query(
+        _path: &str,
+        content_type: &str,
+        _accepts: &str,
+        query: &str,
+        _method: http::Method,
+    ) -> Result<Self, E> {
+        Ok(Self {
+            content_type: content_type.to_string(),
+            body: Bytes::copy_from_slice(query.as_bytes()),
+        })
+    }
+
+    fn try_new_req_text(
+        _path: &str,
+        content_type: &str,
+        _accepts: &str,
+        body: String,
+        _method: http::Method,
+    ) -> Result<Self, E> {
+        Ok(Self {
+            content_type: content_type.to_string(),
+            body: body.into(),
+        })
+    }
+
+    fn try_new_req_bytes(
+        _path: &str,
+        content_type: &str,
+        _accepts: &str,
+        body: Bytes,
+        _method: http::Method,
+    ) -> Result<Self, E> {
+        Ok(Self {
+            content_type: content_type.to_string(),
+            body,
+        })
+    }
+
+    fn try_new_req_form_data(
+        _path: &str,
+        _accepts: &str,
+        _content_type: &str,
+        _body: Self::FormData,
+        _method: http::Method,
+    ) -> Result<Self, E> {
+        Err(unsupported("form data"))
+    }
+
+    fn try_new_req_multipart(
+        _path: &str,
+        _accepts: &str,
+        _body: Self::FormData,
+        _method: http::Method,
+    ) -> Result<Self, E> {
+        Err(unsupported("multipart"))
+    }
+
+    fn try_new_req_streaming(
+        _path: &str,
+        _accepts: &str,
+        _content_type: &str,
+        _body: impl Stream<Item = Bytes> + Send + 'static,
+        _method: http::Method,
+    ) -> Result<Self, E> {
+        Err(unsupported("streaming"))
+    }
+}
+
+impl<E: FromServerFnError + Send> Req<E> for Loopback {
+    type WebsocketResponse = ();
+
+    fn as_query(&self) -> Option<&str> {
+        std::str::from_utf8(&self.body).ok()
+    }
+
+    fn to_content_type(&self) -> Option<Cow<'_, str>> {
+        Some(Cow::Borrowed(&self.content_type))
+    }
+
+    fn accepts(&self) -> Option<Cow<'_, str>> {
+        None
+    }
+
+    fn referer(&self) -> Option<Cow<'_, str>> {
+        None
+    }
+
+    async fn try_into_bytes(self) -> Result<Bytes, E> {
+        Ok(self.body)
+    }
+
+    async fn try_into_string(self) -> Result<String, E> {
+        text(self.body)
+    }
+
+    fn try_into_stream(
+        self,
+    ) -> Result<impl Stream<Item = Result<Bytes, Bytes>> + Send + 'static, E>
+    {
+        Ok(stream::once(future::ready(Ok(self.body))))
+    }
+
+    fn try_into_websocket(
+        self,
+    ) -> impl Future<
+        Output = Result<
+            (
+                impl Stream<Item = Result<Bytes, Bytes>> + Send + 'static,
+                impl Sink<Bytes> + Send + 'static,
+                Self::WebsocketResponse,
+            ),
+            E,
+        >,
+    > + Send {
+        future::ready(Err::<
+            (stream::Empty<Result<Bytes, Bytes>>, sink::Drain<Bytes>, ()),
+            _,
+        >(unsupported("websockets")))
+    }
+}
+
+impl<E: FromServerFnError> TryRes<E> for Loopback {
+    fn try_from_string(content_type: &str, data: String) -> Result<Self, E> {
+        Ok(Self {
+            content_type: content_type.to_string(),
+            body: data.into(),
+        })
+    }
+
+    fn try_from_bytes(content_type: &str, data: Bytes) -> Result<Self, E> {
+        Ok(Self {
+            content_type: content_type.to_string(),
+            body: data,
+        })
+    }
+
+    fn try_from_stream(
+        _content_type: &str,
+        _data: impl Stream<Item = Result<Bytes, Bytes>> + Send + 'static,
+    ) -> Result<Self, E> {
+        Err(unsupported("streaming"))
+    }
+}
+
+impl<E: FromServerFnError> ClientRes<E> for Loopback {
+    fn try_into_string(self) -> impl Future<Output = Result<String, E>> + Send {
+        future::ready(text(self.body))
+    }
+
+    fn try_into_bytes(self) -> impl Future<Output = Result<Bytes, E>> + Send {
+        future::ready(Ok(self.body))
+    }
+
+    fn try_into_stream(
+        self,
+    ) -> Result<
+        impl Stream<Item = Result<Bytes, Bytes>> + Send + Sync + 'static,
+        E,
+    > {
+        Ok(stream::once(future::ready(Ok(self.body))))
+    }
+
+    fn status(&self) -> u16 {
+        200
+    }
+
+    fn status_text(&self) -> String {
+        "OK".to_string()
+    }
+
+    fn location(&self) -> String {
+        String::new()
+    }
+
+    fn has_redirect(&self) -> bool {
+        false
+    }
+}
+
+/// Like the real request types, treats a body that isn't UTF-8 as a
+/// deserialization error.
+fn text<E: FromServerFnError>(body: Bytes) -> Result<String, E> {
+    String::from_utf8(body.to_vec()).map_err(|e| {
+        ServerFnErrorErr::Deserialization(e.to_string()).into_app_error()
+    })
+}
+
+fn unsupported<E: FromServerFnError>(what: &str) -> E {
+    ServerFnErrorErr::Request(format!("{what} isn't supported"))
+        .into_app_error()
+}
+
+/// Encodes `value` as a request with codec `C`, then decodes it again.
+fn through_request<C, T>(value: T) -> Result<T, ServerFnError>
+where
+    T: IntoReq<C, Loopback, ServerFnError>
+        + FromReq<C, Loopback, ServerFnError>,
+{
+    let req = value.into_req("/api/loopback", "")?;
+    block_on(T::from_req(req))
+}
+
+/// Encodes `value` as a response with codec `C`, then decodes it again.
+fn through_response<C, T>(value: T) -> Result<T, ServerFnError>
+where
+    T: IntoRes<C, Loopback, ServerFnError>
+        + FromRes<C, Loopback, ServerFnError>,
+{
+    let res = block_on(value.into_res())?;
+    block_on(T::from_res(res))
+}
+
+/// Decodes `body` as a request with codec `C`.
+fn garbage_request<C, T>(body: Vec<u8>) -> Result<T, ServerFnError>
+where
+    T: FromReq<C, Loopback, ServerFnError>,
+{
+    block_on(T::from_req(Loopback {
+        content_type: String::new(),
+        body: body.into(),
+    }))
+}
+
+/// Representable in TOML: integers fit in an `i64`, and plain values come
+/// before tables.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+)]
+struct Note {
+    text: String,
+    count: i32,
+    done: bool,
+    tags: Vec<String>,
+    replies: Vec<Reply>,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+)]
+struct Reply {
+    author: String,
+    text: String,
+}
+
+fn reply() -> impl Strategy<Value = Reply> {
+    (any::<String>(), any::<String>())
+        .prop_map(|(author, text)| Reply { author, text })
+}
+
+fn note() -> impl Strategy<Value = Note> {
+    (
+        any::<String>(),
+        any::<i32>(),
+        any::<bool>(),
+        prop::collection::vec(any::<String>(), 0..8),
+        prop::collection::vec(reply(), 0..8),
+    )
+        .prop_map(|(text, count, done, tags, replies)| Note {
+            text,
+            count,
+            done,
+            tags,
+            replies,
+        })
+}
+
+/// Garbage may happen to decode, but must never fail any other way.
+fn decoded_or_rejected<T>(result: &Result<T, ServerFnError>) -> bool {
+    matches!(result, Ok(_) | Err(ServerFnError::Deserialization(_)))
+}
+
+proptest! {
+    #[test]
+    fn toml_round_trips(note in note()) {
+        let request =
+            through_request::<Toml, _>(TomlEncoded(note.clone())).unwrap();
+        prop_assert_eq!(&request.0, &note);
+        let response = through_response::<Toml, _>(TomlEncoded(note.clone()))
+            .unwrap();
+        prop_assert_eq!(&response.0, &note);
+    }
+
+    #[test]
+    fn cbor_round_trips(note in note()) {
+        prop_assert_eq!(through_request::<Cbor, _>(note.clone()).unwrap(), note.clone());
+        prop_assert_eq!(through_response::<Cbor, _>(note.clone()).unwrap(), note);
+    }
+
+    #[test]
+    fn rkyv_round_trips(note in note()) {
+        prop_assert_eq!(
+            through_request::<AlignedRkyv, _>(note.clone()).unwrap(),
+            note.clone()
+        );
+        prop_assert_eq!(
+            through_response::<AlignedRkyv, _>(note.clone()).unwrap(),
+            note
+        );
+    }
+
+    #[test]
+    fn garbage_is_a_deserialization_error(
+        body in prop::collection::vec(any::<u8>(), 0..512),
+    ) {
+        prop_assert!(decoded_or_rejected(
+            &garbage_request::<Toml, TomlEncoded<Note>>(body.clone())
+        ));
+        prop_assert!(decoded_or_rejected(
+            &garbage_request::<Cbor, Note>(body.clone())
+        ));
+        prop_assert!(decoded_or_rejected(
+            &garbage_request::<AlignedRkyv, Note>(body)
+        ));
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    #[test]
+    fn large_payloads_round_trip(rows in 0..5_000_usize) {
+        let fixture = Fixture::sample(rows);
+        prop_assert_eq!(
+            &through_request::<Toml, _>(TomlEncoded(fixture.clone())).unwrap().0,
+            &fixture
+        );
+        prop_assert_eq!(
+            &through_request::<Cbor, _>(fixture.clone()).unwrap(),
+            &fixture
+        );
+        prop_assert_eq!(
+            &through_request::<AlignedRkyv, _>(fixture.clone()).unwrap(),
+            &fixture
+        );
+    }
+}
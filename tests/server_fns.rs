@@ -0,0 +1,620 @@
+//! Boots the whole app on an ephemeral port and calls server fns over real
+//! HTTP, checking what actually goes over the wire.
+
+use leptos::config::LeptosOptions;
+use reqwest::{header::CONTENT_TYPE, multipart, Client, StatusCode};
+use server_fn::ServerFn;
+use server_fns_axum::{
+    app::{
+        AddRow, AddTodo, AsciiUppercase, AsciiUppercaseClassic, GetRows,
+        LengthOfInput,
+    },
+    auth::{self, SignUp},
+    client_errors::ClientErrors,
+    codec::{FormEncoded, QueryEncoded},
+    dev_overlay::{FetchRequestLog, RequestLog},
+    errors::{AppError, FormError, REQUEST_ID_HEADER},
+    generation::GenerateText,
+    middleware,
+    progress::PollProgress,
+    router::app_router,
+    rows::{DeleteRow, SetRowCompleted, UpdateRow},
+    settings::{AppSettings, AuthSettings, SecuritySettings},
+    transports::{Echo, EchoGet, EchoPost},
+};
+use std::net::{Ipv4Addr, SocketAddr};
+
+/// Serves the app with default settings, returning its base URL.
+async fn spawn_app() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let options = LeptosOptions::builder()
+        .output_name("server_fns_axum")
+        .site_addr(addr)
+        .build();
+    let app = app_router(&AppSettings::default(), options);
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .unwrap();
+    });
+    format!("http://{addr}")
+}
+
+/// A client that keeps cookies, signed up as a new user called `name`.
+async fn signed_in_client(base: &str, name: &str) -> Client {
+    let client = Client::builder().cookie_store(true).build().unwrap();
+    let res = client
+        .post(format!("{base}{}", SignUp::PATH))
+        .form(&[("name", name), ("password", "correct horse")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    client
+}
+
+/// The path of a server fn that is declared inside a component, so it has
+/// no type to take `PATH` from.
+fn nested_server_fn_path(name: &str) -> &'static str {
+    server_fn::axum::server_fn_paths()
+        .map(|(path, _)| path)
+        .find(|path| path.contains(name))
+        .unwrap_or_else(|| panic!("no server fn called {name}"))
+}
+
+#[tokio::test]
+async fn add_row_requires_signing_in() {
+    let base = spawn_app().await;
+    let res = Client::new()
+        .post(format!("{base}{}", AddRow::PATH))
+        .form(&[("text", "anonymous")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert!(res.headers().contains_key("serverfnerror"));
+    let error: AppError =
+        serde_json::from_str(&res.text().await.unwrap()).unwrap();
+    assert_eq!(error.code, "auth.not_signed_in");
+    assert_eq!(error.message, "you need to sign in first");
+}
+
+#[tokio::test]
+async fn failed_calls_keep_their_error_for_the_dev_overlay() {
+    let base = spawn_app().await;
+    let client = Client::new();
+    let res = client
+        .post(format!("{base}{}", AddRow::PATH))
+        .form(&[("text", "anonymous")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let request_id = res.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+
+    let res = client
+        .get(format!("{base}{}", FetchRequestLog::PATH))
+        .query(&[("request_id", request_id)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let log: RequestLog =
+        serde_json::from_str(&res.text().await.unwrap()).unwrap();
+    assert_eq!(log.chain, ["you need to sign in first"]);
+}
+
+#[tokio::test]
+async fn add_row_fails_every_third_call_and_get_rows_counts_the_rest() {
+    let base = spawn_app().await;
+    let client = signed_in_client(&base, "adder").await;
+    let add = |text: &'static str| {
+        client
+            .post(format!("{base}{}", AddRow::PATH))
+            .form(&[("text", text)])
+            .send()
+    };
+
+    let first = add("one").await.unwrap();
+    assert_eq!(first.status(), StatusCode::OK);
+    assert_eq!(first.headers()[CONTENT_TYPE], "application/json");
+    assert_eq!(first.text().await.unwrap(), "1");
+    assert_eq!(add("two").await.unwrap().text().await.unwrap(), "2");
+    let third = add("three").await.unwrap();
+    assert_eq!(third.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let error: AppError =
+        serde_json::from_str(&third.text().await.unwrap()).unwrap();
+    assert_eq!(error.code, "row.chaos");
+    assert!(error.params.is_empty());
+
+    let rows = client
+        .post(format!("{base}{}", GetRows::PATH))
+        .form(&[] as &[(&str, &str)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rows.status(), StatusCode::OK);
+    assert_eq!(rows.text().await.unwrap(), "2");
+}
+
+#[tokio::test]
+async fn invalid_arguments_are_unprocessable() {
+    let base = spawn_app().await;
+    for path in [AsciiUppercase::PATH, AsciiUppercaseClassic::PATH] {
+        let res = Client::new()
+            .post(format!("{base}{path}"))
+            .form(&[("text", "hi")])
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn add_todo_binds_a_form_and_names_the_fields_it_rejects() {
+    let base = spawn_app().await;
+    let client = signed_in_client(&base, "former").await;
+    let path = format!("{base}{}", FormEncoded::<AddTodo>::PATH);
+
+    let res = client
+        .post(&path)
+        .form(&[
+            ("todo.text", "Buy milk"),
+            ("todo.tags", "home"),
+            ("todo.tags", ""),
+            ("todo.tags", "Errands"),
+            ("todo.completed", "on"),
+            ("todo.due.days", "3"),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "1");
+
+    let long_tag = "x".repeat(100);
+    let res = client
+        .post(&path)
+        .form(&[
+            ("todo.text", " "),
+            ("todo.tags", long_tag.as_str()),
+            ("todo.due.days", "400"),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let error: FormError =
+        serde_json::from_str(&res.text().await.unwrap()).unwrap();
+    assert_eq!(error.error.code, "form.invalid");
+    let fields = error
+        .fields
+        .iter()
+        .map(|(name, error)| (name.as_str(), error.code.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        fields,
+        [
+            ("todo.due.days", "row.due_too_late"),
+            ("todo.tags", "tag.too_long"),
+            ("todo.text", "row.empty"),
+        ]
+    );
+
+    let res = client
+        .post(&path)
+        .form(&[("todo.text", "Call back"), ("todo.due.days", "soon")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let tags = (0..9).map(|n| format!("tag{n}")).collect::<Vec<_>>();
+    let mut form = vec![("todo.text", "Too many tags")];
+    form.extend(tags.iter().map(|tag| ("todo.tags", tag.as_str())));
+    let res = client.post(&path).form(&form).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let error: FormError =
+        serde_json::from_str(&res.text().await.unwrap()).unwrap();
+    assert_eq!(error.fields["todo.tags"].code, "tag.too_many");
+}
+
+#[tokio::test]
+async fn admin_only_server_fns_tell_anonymous_callers_from_members() {
+    let base = spawn_app().await;
+    let path = format!("{base}{}", ClientErrors::PATH);
+
+    let anonymous = Client::new().get(&path).send().await.unwrap();
+    assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+
+    let member = signed_in_client(&base, "member").await;
+    let res = member.get(&path).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let error: AppError =
+        serde_json::from_str(&res.text().await.unwrap()).unwrap();
+    assert_eq!(error.code, "auth.forbidden");
+    assert_eq!(error.status, 403);
+}
+
+#[tokio::test]
+async fn sensitive_admin_server_fns_need_a_second_factor() {
+    auth::init(
+        &AuthSettings {
+            admins: vec!["password-only-admin".to_string()],
+            ..AuthSettings::default()
+        },
+        false,
+    );
+    let base = spawn_app().await;
+    let admin = signed_in_client(&base, "password-only-admin").await;
+
+    let res = admin
+        .get(format!("{base}{}", ClientErrors::PATH))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let error: AppError =
+        serde_json::from_str(&res.text().await.unwrap()).unwrap();
+    assert_eq!(error.code, "call.two_factor_required");
+}
+
+#[tokio::test]
+async fn calls_over_the_rate_limit_are_too_many_requests() {
+    // the calls come through a proxy on localhost, which names the caller
+    middleware::init_rate_limits(&SecuritySettings {
+        trusted_proxies: vec![Ipv4Addr::LOCALHOST.into()],
+        ..SecuritySettings::default()
+    });
+    let base = spawn_app().await;
+    let client = Client::new();
+    // the standard preset allows 120 calls a minute per caller
+    let calls = (0..121).map(|_| {
+        client
+            .get(format!("{base}{}", QueryEncoded::<LengthOfInput>::PATH))
+            .query(&[("input", "hello")])
+            .header("x-forwarded-for", "203.0.113.7")
+            .send()
+    });
+    let statuses = futures::future::join_all(calls)
+        .await
+        .into_iter()
+        .map(|res| res.unwrap().status())
+        .collect::<Vec<_>>();
+
+    let count = |wanted: StatusCode| {
+        statuses.iter().filter(|status| **status == wanted).count()
+    };
+    assert_eq!(count(StatusCode::OK), 120);
+    assert_eq!(count(StatusCode::TOO_MANY_REQUESTS), 1);
+}
+
+#[tokio::test]
+async fn custom_path_takes_a_query_and_is_never_cached() {
+    let base = spawn_app().await;
+    assert_eq!(QueryEncoded::<LengthOfInput>::PATH, "/api2/custom_path");
+    let res = Client::new()
+        .get(format!("{base}{}", QueryEncoded::<LengthOfInput>::PATH))
+        .query(&[("input", "hello")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["cache-control"], "no-store");
+    assert_eq!(res.text().await.unwrap(), "5");
+}
+
+#[tokio::test]
+async fn multipart_upload_counts_every_byte() {
+    let base = spawn_app().await;
+    let form = multipart::Form::new()
+        .part("file", multipart::Part::bytes(vec![b'x'; 100_000]))
+        .part("notes", multipart::Part::text("hello"));
+    let res = Client::new()
+        .post(format!("{base}{}", nested_server_fn_path("file_length")))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "100005");
+}
+
+#[tokio::test]
+async fn multipart_upload_without_a_boundary_is_caught_as_a_panic() {
+    let base = spawn_app().await;
+    let res = Client::new()
+        .post(format!("{base}{}", nested_server_fn_path("file_length")))
+        .header(CONTENT_TYPE, "text/plain")
+        .body("not multipart")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(res.headers().contains_key(REQUEST_ID_HEADER));
+    assert!(res.headers()[CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+}
+
+/// A browser with scripting off: it navigates, posts forms as they are and
+/// keeps cookies, but doesn't follow redirects, so they can be checked.
+fn browser_without_js() -> Client {
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(
+        reqwest::header::ACCEPT,
+        "text/html,application/xhtml+xml".parse().unwrap(),
+    );
+    Client::builder()
+        .cookie_store(true)
+        .default_headers(headers)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+/// The text between the first `before` and the next `after` in `html`.
+fn between<'a>(html: &'a str, before: &str, after: &str) -> &'a str {
+    let start = html.find(before).unwrap_or_else(|| panic!("no {before}"))
+        + before.len();
+    let len = html[start..].find(after).unwrap();
+    &html[start..start + len]
+}
+
+#[tokio::test]
+async fn mutating_forms_work_without_javascript() {
+    let base = spawn_app().await;
+    let browser = browser_without_js();
+    let home = format!("{base}/");
+    let page = |url: String| {
+        let browser = browser.clone();
+        async move {
+            let res = browser.get(url).send().await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            res.text().await.unwrap()
+        }
+    };
+    // loading the page starts the visitor's sandbox
+    page(home.clone()).await;
+
+    let post = |path: &str, form: Vec<(&str, String)>, referer: &str| {
+        browser
+            .post(format!("{base}{path}"))
+            .header(reqwest::header::REFERER, referer)
+            .form(&form)
+            .send()
+    };
+    let res = post(AddRow::PATH, vec![("text", "Buy milk".into())], &home)
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FOUND);
+    assert_eq!(res.headers()["location"], home.as_str());
+
+    let html = page(home.clone()).await;
+    assert!(html.contains("Buy milk"));
+    // the row's "Edit" is a link to its own edit page
+    let edit_link = html.find("/edit\"").expect("no edit link");
+    let id = html[..edit_link].rsplit('/').next().unwrap().to_string();
+
+    let res = post(
+        SetRowCompleted::PATH,
+        vec![("id", id.clone()), ("completed", "true".into())],
+        &home,
+    )
+    .await
+    .unwrap();
+    assert_eq!(res.status(), StatusCode::FOUND);
+    let html = page(home.clone()).await;
+    assert!(html.contains("Marked the row completed."));
+    // a flash is only shown once
+    assert!(!page(home.clone())
+        .await
+        .contains("Marked the row completed."));
+
+    let edit = format!("{base}/rows/{id}/edit");
+    let html = page(edit.clone()).await;
+    let version = between(&html, r#"name="expected_version" value=""#, "\"");
+    let res = post(
+        UpdateRow::PATH,
+        vec![
+            ("id", id.clone()),
+            ("text", "Buy oat milk".into()),
+            ("expected_version", version.to_string()),
+        ],
+        &edit,
+    )
+    .await
+    .unwrap();
+    assert_eq!(res.status(), StatusCode::FOUND);
+    let html = page(edit.clone()).await;
+    assert!(html.contains("Saved the row."));
+    assert!(html.contains("Buy oat milk"));
+
+    // a failure comes back as a redirect too, with the error to show
+    let res = post(DeleteRow::PATH, vec![("id", "999999".into())], &home)
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    assert_eq!(res.headers()["location"], home.as_str());
+    assert!(page(home.clone()).await.contains("there is no row 999999"));
+
+    let res = post(DeleteRow::PATH, vec![("id", id.clone())], &home)
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FOUND);
+    assert!(page(home.clone())
+        .await
+        .contains("Moved the row to the trash."));
+
+    let form = multipart::Form::new()
+        .part("file_to_upload", multipart::Part::bytes(vec![b'x'; 5]));
+    let res = browser
+        .post(format!("{base}{}", nested_server_fn_path("file_length")))
+        .header(reqwest::header::REFERER, &home)
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FOUND);
+    assert!(page(home.clone()).await.contains("Uploaded 5 bytes."));
+}
+
+#[tokio::test]
+async fn flashes_the_server_did_not_sign_are_not_shown() {
+    let base = spawn_app().await;
+    // `[{"Notice":"Forged"}]`, with a signature the server never made
+    let res = browser_without_js()
+        .get(format!("{base}/"))
+        .header(
+            reqwest::header::COOKIE,
+            "flash=W3siTm90aWNlIjoiRm9yZ2VkIn1d.AAAA",
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(!res.text().await.unwrap().contains("Forged"));
+}
+
+#[tokio::test]
+async fn example_inputs_render_from_the_query_string() {
+    let base = spawn_app().await;
+    let html = Client::new()
+        .get(format!(
+            "{base}/?search=milk&search_page=2&rkyv=hello&accept=\
+             application%2Fcbor"
+        ))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains(r#"value="milk""#));
+    assert!(html.contains(r#"value="hello""#));
+    // what the input loads is rendered with it
+    assert!(html.contains("HELLO"));
+    assert!(html.contains(r#"<option value="application/cbor" selected"#));
+}
+
+#[tokio::test]
+async fn only_the_get_echo_may_be_cached() {
+    let base = spawn_app().await;
+    let client = Client::new();
+
+    let res = client
+        .get(format!("{base}{}?text=h%C3%A9llo", EchoGet::PATH))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["cache-control"], "private, max-age=60");
+    let echo: Echo = serde_json::from_str(&res.text().await.unwrap()).unwrap();
+    assert_eq!(echo, Echo::of("héllo"));
+    assert_eq!(echo.upper, "HÉLLO");
+    assert_eq!(echo.chars, 5);
+
+    let res = client
+        .post(format!("{base}{}", EchoPost::PATH))
+        .header(CONTENT_TYPE, "application/json")
+        .body(r#"{"text":"héllo"}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["cache-control"], "no-store");
+    let echo: Echo = serde_json::from_str(&res.text().await.unwrap()).unwrap();
+    assert_eq!(echo, Echo::of("héllo"));
+}
+
+#[tokio::test]
+async fn generated_text_streams_a_token_per_frame() {
+    let base = spawn_app().await;
+    let client = Client::new();
+    let res = client
+        .post(format!("{base}{}", GenerateText::PATH))
+        .form(&[("prompt", "tidy the garage")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[CONTENT_TYPE], "application/x-ndjson");
+    let body = res.text().await.unwrap();
+    let tokens = body
+        .lines()
+        .map(|line| {
+            let frame: serde_json::Value = serde_json::from_str(line).unwrap();
+            frame["data"].as_str().unwrap().to_string()
+        })
+        .collect::<Vec<_>>();
+    assert!(tokens.len() > 10);
+    assert!(tokens.concat().contains("\"tidy the garage\""));
+
+    let res = client
+        .post(format!("{base}{}", GenerateText::PATH))
+        .form(&[("prompt", "  ")])
+        .send()
+        .await
+        .unwrap();
+    assert!(res.text().await.unwrap().contains("ask something first"));
+}
+
+#[tokio::test]
+async fn progress_can_be_long_polled_from_a_cursor() {
+    let base = spawn_app().await;
+    let client = Client::builder().cookie_store(true).build().unwrap();
+    let form = multipart::Form::new().text("size", "5").part(
+        "file_to_upload",
+        multipart::Part::bytes(vec![b'x'; 5]).file_name("notes.txt"),
+    );
+    let res = client
+        .post(format!("{base}{}", nested_server_fn_path("upload_file")))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // the upload is over, but its frames are kept for catching up on, and
+    // the caller only gets its own
+    let poll = |form: Vec<(&'static str, String)>| {
+        let client = client.clone();
+        let url = format!("{base}{}", PollProgress::PATH);
+        async move {
+            let res = client.post(url).form(&form).send().await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = res.text().await.unwrap();
+            serde_json::from_str::<serde_json::Value>(&body).unwrap()
+        }
+    };
+    let polled = poll(vec![("cursor", "0".into())]).await;
+    let items = polled["items"].as_array().unwrap();
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[0]["value"]["event"]["Started"]["label"], "notes.txt");
+    assert_eq!(
+        items[2]["value"]["event"]["Completed"]["message"],
+        "5 bytes uploaded"
+    );
+    let last = items[2]["seq"].as_u64().unwrap();
+    assert!(polled["next"].as_u64().unwrap() >= last);
+
+    // narrowed to one piece of work, from part way through it
+    let id = items[0]["value"]["id"].as_u64().unwrap();
+    let after = items[0]["seq"].as_u64().unwrap();
+    let polled =
+        poll(vec![("id", id.to_string()), ("cursor", after.to_string())]).await;
+    let items = polled["items"].as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    assert!(items.iter().all(|item| item["value"]["id"] == id));
+}
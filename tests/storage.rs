@@ -0,0 +1,204 @@
+//! Runs multi-step changes through `RowStore::transaction` directly,
+//! checking that a step failing part way leaves no trace of the earlier ones,
+//! along with the store's replica copies and snapshots.
+
+use futures::{FutureExt, StreamExt};
+use server_fns_axum::{
+    audit::{AuditAction, AUDIT},
+    auth::UserId,
+    errors::{AppError, ErrorCode},
+    quotas::{QuotaKind, QUOTAS},
+    storage::{Row, RowStore, ROWS},
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A user nobody else in this test run has touched.
+fn new_owner() -> UserId {
+    static NEXT: AtomicU64 = AtomicU64::new(1 << 40);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Adds a row to `store` in a transaction of its own.
+fn add(store: &RowStore, owner: UserId, text: &str) -> Row {
+    store
+        .transaction(owner, |tx| Ok::<_, AppError>(tx.insert(text.into())))
+        .unwrap()
+}
+
+/// Whether anything has been audited on `owner`'s row `row_id`.
+fn audited(owner: UserId, row_id: u64) -> bool {
+    let mut entries = Box::pin(AUDIT.subscribe_row(owner, row_id, None));
+    entries.next().now_or_never().flatten().is_some()
+}
+
+#[test]
+fn a_committed_transaction_keeps_every_step() {
+    let owner = new_owner();
+    let row = ROWS
+        .transaction(owner, |tx| {
+            tx.consume_quota(QuotaKind::Rows, 1)?;
+            let row = tx.insert("water the plants".to_string());
+            tx.set_completed(row.id, true);
+            tx.audit(row.id, AuditAction::Created);
+            Ok::<_, AppError>(tx.get(row.id).unwrap())
+        })
+        .unwrap();
+
+    assert!(row.completed);
+    assert_eq!(ROWS.get(owner, row.id), Some(row.clone()));
+    assert_eq!(QUOTAS.usage(owner).rows, 1);
+    assert!(audited(owner, row.id));
+}
+
+#[test]
+fn a_failed_step_rolls_back_the_rows_quota_and_audit() {
+    let owner = new_owner();
+    let kept = add(&ROWS, owner, "buy oat milk");
+
+    let mut added = None;
+    let result = ROWS.transaction(owner, |tx| {
+        tx.consume_quota(QuotaKind::Rows, 1)?;
+        let row = tx.insert("call the plumber".to_string());
+        added = Some(row.id);
+        tx.audit(row.id, AuditAction::Created);
+        tx.trash(kept.id);
+        // a stale version, so this last step fails
+        let text = "call the plumber today".to_string();
+        let row = tx.update_text(row.id, text, 0)?;
+        Ok::<_, AppError>(row)
+    });
+
+    assert_eq!(result.unwrap_err().code, "row.conflict");
+    let added = added.unwrap();
+    assert_eq!(ROWS.get(owner, added), None);
+    assert_eq!(ROWS.get(owner, kept.id), Some(kept));
+    assert!(ROWS.list_trash(owner).is_empty());
+    assert_eq!(QUOTAS.usage(owner).rows, 0);
+    assert!(!audited(owner, added));
+}
+
+#[test]
+fn a_transaction_over_quota_adds_nothing() {
+    let owner = new_owner();
+    let max_rows = QUOTAS.usage(owner).max_rows;
+    QUOTAS.consume(owner, QuotaKind::Rows, max_rows).unwrap();
+
+    let result = ROWS.transaction(owner, |tx| {
+        tx.insert("one too many".to_string());
+        tx.consume_quota(QuotaKind::Rows, 1)?;
+        Ok::<_, AppError>(())
+    });
+
+    assert_eq!(result.unwrap_err().code, "quota.exceeded");
+    assert!(ROWS.is_empty(owner));
+    assert_eq!(QUOTAS.usage(owner).rows, max_rows);
+}
+
+#[test]
+fn a_replica_copy_has_every_earlier_change() {
+    let owner = new_owner();
+    let replica = RowStore::default();
+    let row = add(&ROWS, owner, "dust");
+
+    let copied_at = replica.copy_from(&ROWS);
+
+    assert!(ROWS.written_at(owner).is_some_and(|at| at <= copied_at));
+    assert_eq!(replica.get(owner, row.id), Some(row));
+    assert_eq!(replica.written_at(owner), None);
+}
+
+#[test]
+fn a_restored_snapshot_brings_back_the_rows_as_they_were() {
+    let owner = new_owner();
+    let store = RowStore::default();
+    let kept = add(&store, owner, "sweep the porch");
+    let trashed = add(&store, owner, "fix the gate");
+    store
+        .transaction(owner, |tx| Ok::<_, AppError>(tx.trash(trashed.id)))
+        .unwrap();
+    let snapshot = store.snapshot(|_| true);
+
+    let added = add(&store, owner, "paint the fence");
+    store.remove_owner(owner);
+    assert_eq!(store.restore_snapshot(&snapshot, |_| true).unwrap(), 1);
+
+    assert_eq!(store.get(owner, kept.id), Some(kept));
+    assert_eq!(store.get(owner, added.id), None);
+    assert_eq!(store.list_trash(owner).len(), 1);
+    assert_eq!(store.search(owner, &["porch".to_string()], 0, 10).0, 1);
+    assert!(add(&store, owner, "mow").id > trashed.id);
+    assert!(store.restore_snapshot("not a snapshot", |_| true).is_err());
+}
+
+#[test]
+fn a_restored_snapshot_never_hands_out_an_id_twice() {
+    let owner = new_owner();
+    let store = RowStore::default();
+    let kept = add(&store, owner, "sweep the porch");
+    let trashed = add(&store, owner, "fix the gate");
+    store
+        .transaction(owner, |tx| Ok::<_, AppError>(tx.trash(trashed.id)))
+        .unwrap();
+    let mut snapshot: serde_json::Value =
+        serde_json::from_str(&store.snapshot(|_| true)).unwrap();
+    snapshot[owner.to_string()]["last_id"] = 0.into();
+
+    store
+        .restore_snapshot(&snapshot.to_string(), |_| true)
+        .unwrap();
+
+    let added = add(&store, owner, "mow");
+    assert!(added.id > kept.id && added.id > trashed.id);
+}
+
+#[test]
+fn a_snapshot_leaves_out_owners_it_does_not_keep() {
+    let (mine, theirs) = (new_owner(), new_owner());
+    let store = RowStore::default();
+    let kept = add(&store, mine, "sweep the porch");
+    let snapshot = store.snapshot(|owner| owner == mine);
+    let other = add(&store, theirs, "feed the cat");
+
+    assert_eq!(store.restore_snapshot(&snapshot, |_| true).unwrap(), 1);
+    assert_eq!(store.get(theirs, other.id), None);
+
+    let snapshot = store.snapshot(|_| true);
+    let added = add(&store, mine, "paint the fence");
+    let other = add(&store, theirs, "feed the cat");
+    store
+        .restore_snapshot(&snapshot, |owner| owner == mine)
+        .unwrap();
+    assert_eq!(store.get(mine, added.id), None);
+    assert_eq!(store.get(mine, kept.id), Some(kept));
+    assert_eq!(store.get(theirs, other.id), Some(other));
+}
+
+#[test]
+fn a_restored_snapshot_is_checked_like_new_rows() {
+    let owner = new_owner();
+    let store = RowStore::default();
+    let row = add(&store, owner, "sweep the porch");
+    let mut snapshot: serde_json::Value =
+        serde_json::from_str(&store.snapshot(|_| true)).unwrap();
+    let planted = &mut snapshot[owner.to_string()]["rows"][0];
+    planted["html"] = "<script>alert(1)</script>".into();
+
+    store
+        .restore_snapshot(&snapshot.to_string(), |_| true)
+        .unwrap();
+    let restored = store.get(owner, row.id).unwrap();
+    assert!(!restored.html.contains("<script>"));
+    assert_eq!(restored.html, row.html);
+
+    snapshot[owner.to_string()]["rows"][0]["attachments"] = serde_json::json!([{
+        "id": "../../etc/passwd",
+        "file_name": "passwd",
+        "content_type": "text/plain",
+        "size": 1,
+    }]);
+    let error = store
+        .restore_snapshot(&snapshot.to_string(), |_| true)
+        .unwrap_err();
+    assert_eq!(error.code(), "snapshot.invalid_attachment");
+    assert!(store.get(owner, row.id).unwrap().attachments.is_empty());
+}
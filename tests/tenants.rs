@@ -0,0 +1,85 @@
+//! Works out tenants from request headers as `[tenants]` below configures
+//! them, and checks that each tenant gives users owner IDs of their own.
+
+use http::{header::HOST, HeaderMap, HeaderValue};
+use server_fns_axum::{
+    settings::TenantSettings,
+    tenants::{
+        init, tenant_of, tenant_owner, tenant_owners, tenant_user,
+        TENANT_HEADER,
+    },
+};
+use std::sync::Once;
+
+fn setup() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        init(&TenantSettings {
+            names: vec!["acme".to_string(), "globex".to_string()],
+            base_domain: Some("todo.test".to_string()),
+        })
+        .unwrap();
+    });
+}
+
+fn headers(pairs: &[(&str, &'static str)]) -> HeaderMap {
+    pairs
+        .iter()
+        .map(|(name, value)| {
+            (name.parse().unwrap(), HeaderValue::from_static(value))
+        })
+        .collect()
+}
+
+fn tenant_name(pairs: &[(&str, &'static str)]) -> Option<String> {
+    tenant_of(&headers(pairs)).unwrap().name
+}
+
+#[test]
+fn the_header_wins_over_the_subdomain() {
+    setup();
+    let host = HOST.as_str();
+
+    assert_eq!(tenant_name(&[]), None);
+    assert_eq!(tenant_name(&[(host, "todo.test:3000")]), None);
+    assert_eq!(
+        tenant_name(&[(host, "acme.todo.test:3000")]),
+        Some("acme".to_string())
+    );
+    assert_eq!(
+        tenant_name(&[(host, "acme.todo.test"), (TENANT_HEADER, "globex")]),
+        Some("globex".to_string())
+    );
+    assert_eq!(tenant_name(&[(host, "example.com")]), None);
+}
+
+#[test]
+fn unknown_tenants_are_turned_away() {
+    setup();
+
+    let subdomain =
+        tenant_of(&headers(&[(HOST.as_str(), "initech.todo.test")]));
+    let header = tenant_of(&headers(&[(TENANT_HEADER, "initech")]));
+
+    assert_eq!(
+        subdomain.unwrap_err().to_string(),
+        "there is no tenant initech"
+    );
+    assert!(header.is_err());
+}
+
+#[test]
+fn each_tenant_has_its_own_owner_ids() {
+    setup();
+    let user = 42;
+    let default = tenant_of(&HeaderMap::new()).unwrap();
+    let acme = tenant_of(&headers(&[(TENANT_HEADER, "acme")])).unwrap();
+    let globex = tenant_of(&headers(&[(TENANT_HEADER, "globex")])).unwrap();
+
+    let owners = [&default, &acme, &globex].map(|t| tenant_owner(t, user));
+
+    assert_eq!(owners[0], user);
+    assert_ne!(owners[1], owners[2]);
+    assert!(owners.iter().all(|&owner| tenant_user(owner) == user));
+    assert_eq!(tenant_owners(user).collect::<Vec<_>>(), owners);
+}
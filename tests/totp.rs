@@ -0,0 +1,60 @@
+//! Checks two-factor codes against `TotpStore` directly, including how it
+//! holds up against guessing.
+
+use server_fns_axum::{
+    auth::{Role, User},
+    errors::{AuthError, ErrorCode},
+    totp::{MAX_CODE_FAILURES, TOTP},
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use totp_rs::{Algorithm, Secret, TOTP as Totp};
+
+/// A user nobody else in this test run has touched, with two-factor
+/// authentication on, and their recovery codes.
+fn enrolled_user() -> (User, Vec<String>) {
+    static NEXT: AtomicU64 = AtomicU64::new(1 << 40);
+    let user = User {
+        id: NEXT.fetch_add(1, Ordering::Relaxed),
+        name: "guarded".to_string(),
+        role: Role::Member,
+    };
+    let enrollment = TOTP.start(&user).unwrap();
+    let secret = Secret::Encoded(enrollment.secret).to_bytes().unwrap();
+    let totp = Totp::new_unchecked(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        secret,
+        None,
+        user.name.clone(),
+    );
+    let code = totp.generate_current().unwrap();
+    let recovery_codes = TOTP.confirm(user.id, &code).unwrap();
+    (user, recovery_codes)
+}
+
+#[test]
+fn a_recovery_code_works_once() {
+    let (user, recovery_codes) = enrolled_user();
+
+    assert!(TOTP.verify(user.id, &recovery_codes[0]).is_ok());
+    assert!(TOTP.verify(user.id, &recovery_codes[0]).is_err());
+}
+
+#[test]
+fn too_many_wrong_codes_lock_out_even_the_right_one() {
+    let (user, recovery_codes) = enrolled_user();
+
+    for _ in 0..MAX_CODE_FAILURES {
+        let error = TOTP.verify(user.id, "000000x").unwrap_err();
+        assert!(matches!(error, AuthError::InvalidTwoFactorCode));
+    }
+    let error = TOTP.verify(user.id, &recovery_codes[0]).unwrap_err();
+
+    assert_eq!(error.code(), "auth.two_factor_locked");
+    assert_eq!(
+        TOTP.status(user.id).recovery_codes_left,
+        recovery_codes.len()
+    );
+}
@@ -0,0 +1,54 @@
+//! `CustomErrorTypes`: both kinds of custom error survive being encoded by
+//! the server and decoded by the client.
+
+use crate::{click, mount, sleep, text, type_into, LATENCY};
+use leptos::prelude::ServerFnError;
+use server_fn::ServerFn;
+use server_fns_axum::{
+    app::{
+        AsciiUppercase, AsciiUppercaseClassic, CustomErrorTypes,
+        InvalidArgument, MyErrors,
+    },
+    clients::{clear_stubs, stub, MockReply},
+};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+async fn shows_both_results() {
+    clear_stubs();
+    stub(AsciiUppercase::PATH, MockReply::json(&"HELLO THERE"));
+    stub(AsciiUppercaseClassic::PATH, MockReply::json(&"HELLO THERE"));
+    let container = mount(CustomErrorTypes).await;
+
+    type_into(&container, "input", "hello there");
+    click(&container, "button");
+    sleep(LATENCY).await;
+    assert_eq!(text(&container).matches("HELLO THERE").count(), 2);
+}
+
+#[wasm_bindgen_test]
+async fn decodes_custom_errors() {
+    clear_stubs();
+    stub(
+        AsciiUppercase::PATH,
+        MockReply::error(MyErrors::InvalidArgument(InvalidArgument::TooShort)),
+    );
+    stub(
+        AsciiUppercaseClassic::PATH,
+        MockReply::error(ServerFnError::WrappedServerError(
+            InvalidArgument::TooShort,
+        )),
+    );
+    let container = mount(CustomErrorTypes).await;
+
+    type_into(&container, "input", "hi");
+    click(&container, "button");
+    sleep(LATENCY).await;
+    // both arrive as the same code, shown in the reader's language
+    assert_eq!(text(&container).matches("That's too short.").count(), 2);
+    // the button leaves the errors to the results
+    assert!(container
+        .query_selector(".async-button-error")
+        .unwrap()
+        .is_none());
+}
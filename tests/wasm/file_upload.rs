@@ -0,0 +1,45 @@
+//! `FileUpload`: the form shows that it is uploading, then the length the
+//! server counted, or the error.
+
+use crate::{click, mount, sleep, text, LATENCY};
+use server_fns_axum::{
+    app::FileUpload,
+    clients::{clear_stubs, mock_calls, stub, MockReply},
+    errors::{AppError, UploadError},
+};
+use std::time::Duration;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const FILE_LENGTH: &str = "/api/file_length";
+
+#[wasm_bindgen_test]
+async fn shows_the_uploaded_length() {
+    clear_stubs();
+    stub(FILE_LENGTH, MockReply::json(&100_005).after(LATENCY));
+    let container = mount(FileUpload).await;
+    assert!(text(&container).contains("Upload a file."));
+
+    click(&container, "input[type=submit]");
+    sleep(Duration::from_millis(20)).await;
+    assert!(text(&container).contains("Uploading..."));
+
+    sleep(LATENCY * 2).await;
+    assert!(text(&container).contains("100005"));
+    assert_eq!(mock_calls(FILE_LENGTH), 1);
+}
+
+#[wasm_bindgen_test]
+async fn shows_upload_errors() {
+    clear_stubs();
+    stub(
+        FILE_LENGTH,
+        MockReply::error(AppError::new(&UploadError::NotMultipart)),
+    );
+    let container = mount(FileUpload).await;
+
+    click(&container, "input[type=submit]");
+    sleep(LATENCY).await;
+    // there's no message for `upload.not_multipart`, so the server's shows
+    assert!(text(&container).contains("expected a multipart/form-data body"));
+    assert!(!text(&container).contains("Uploading..."));
+}
@@ -0,0 +1,74 @@
+//! The interactive examples, rendered in a headless browser against stubbed
+//! server fns.
+//!
+//! Needs the `mock-client` feature, so that no request leaves the browser,
+//! and a WebDriver (`geckodriver` or `chromedriver`) on the `PATH`:
+//!
+//! ```bash
+//! cargo test --target wasm32-unknown-unknown --features hydrate,mock-client \
+//!     --test wasm
+//! ```
+
+#![cfg(target_arch = "wasm32")]
+
+mod custom_errors;
+mod file_upload;
+mod persist;
+mod spawn_local;
+mod with_an_action;
+
+use futures::channel::oneshot;
+use leptos::{mount::mount_to, prelude::*};
+use std::time::Duration;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_test::wasm_bindgen_test_configure;
+use web_sys::{HtmlElement, HtmlInputElement};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// How long the stubbed server fns take to answer, so there is time to look
+/// at the page while a call is pending.
+pub const LATENCY: Duration = Duration::from_millis(200);
+
+pub async fn sleep(duration: Duration) {
+    let (tx, rx) = oneshot::channel();
+    set_timeout(move || _ = tx.send(()), duration);
+    _ = rx.await;
+}
+
+/// Renders `component` into a new element, returning it once anything it
+/// loads on its own has been answered.
+pub async fn mount<F, V>(component: F) -> HtmlElement
+where
+    F: FnOnce() -> V + 'static,
+    V: IntoView + 'static,
+{
+    let container = document()
+        .create_element("div")
+        .unwrap()
+        .unchecked_into::<HtmlElement>();
+    document().body().unwrap().append_child(&container).unwrap();
+    mount_to(container.clone(), component).forget();
+    sleep(Duration::from_millis(50)).await;
+    container
+}
+
+fn find<T: JsCast>(container: &HtmlElement, selector: &str) -> T {
+    container
+        .query_selector(selector)
+        .unwrap()
+        .unwrap_or_else(|| panic!("nothing matches {selector}"))
+        .unchecked_into()
+}
+
+pub fn click(container: &HtmlElement, selector: &str) {
+    find::<HtmlElement>(container, selector).click();
+}
+
+pub fn type_into(container: &HtmlElement, selector: &str, value: &str) {
+    find::<HtmlInputElement>(container, selector).set_value(value);
+}
+
+pub fn text(container: &HtmlElement) -> String {
+    container.text_content().unwrap_or_default()
+}
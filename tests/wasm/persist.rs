@@ -0,0 +1,82 @@
+//! `persist`: values are stored under versioned keys, and a value stored by
+//! an older version is migrated once.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use server_fns_axum::persist::{forget, load, save, storage_key, Persisted};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// Was a `bool` in version 1.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Collapsed {
+    sections: Vec<String>,
+}
+
+impl Persisted for Collapsed {
+    const KEY: &'static str = "test_collapsed";
+    const VERSION: u32 = 2;
+
+    fn migrate(version: u32, json: Json) -> Option<Self> {
+        match (version, json) {
+            (1, Json::Bool(true)) => Some(Collapsed {
+                sections: vec!["all".into()],
+            }),
+            _ => None,
+        }
+    }
+}
+
+fn storage() -> web_sys::Storage {
+    web_sys::window().unwrap().local_storage().unwrap().unwrap()
+}
+
+#[wasm_bindgen_test]
+fn round_trips_under_a_versioned_key() {
+    let value = Collapsed {
+        sections: vec!["rows".into()],
+    };
+    save(&value);
+    assert_eq!(storage_key::<Collapsed>(2), "todo-demo.test_collapsed.v2");
+    assert!(storage()
+        .get_item("todo-demo.test_collapsed.v2")
+        .unwrap()
+        .is_some());
+    assert_eq!(load::<Collapsed>(), Some(value));
+
+    forget::<Collapsed>();
+    assert_eq!(load::<Collapsed>(), None);
+}
+
+#[wasm_bindgen_test]
+fn migrates_an_older_version_once() {
+    forget::<Collapsed>();
+    storage()
+        .set_item(&storage_key::<Collapsed>(1), "true")
+        .unwrap();
+
+    let migrated = Collapsed {
+        sections: vec!["all".into()],
+    };
+    assert_eq!(load::<Collapsed>(), Some(migrated));
+    assert_eq!(
+        storage().get_item(&storage_key::<Collapsed>(1)).unwrap(),
+        None
+    );
+    let stored = storage().get_item(&storage_key::<Collapsed>(2)).unwrap();
+    assert_eq!(stored.as_deref(), Some(r#"{"sections":["all"]}"#));
+    forget::<Collapsed>();
+}
+
+#[wasm_bindgen_test]
+fn drops_what_it_cant_migrate() {
+    forget::<Collapsed>();
+    storage()
+        .set_item(&storage_key::<Collapsed>(1), "false")
+        .unwrap();
+
+    assert_eq!(load::<Collapsed>(), None);
+    assert_eq!(
+        storage().get_item(&storage_key::<Collapsed>(1)).unwrap(),
+        None
+    );
+}
@@ -0,0 +1,57 @@
+//! `SpawnLocal`: the button shows what the server fn returned, and its
+//! error next to it.
+
+use crate::{click, mount, sleep, text, type_into, LATENCY};
+use leptos::prelude::ServerFnError;
+use server_fns_axum::{
+    app::SpawnLocal,
+    clients::{clear_stubs, mock_calls, stub, MockReply},
+};
+use wasm_bindgen_test::wasm_bindgen_test;
+use web_sys::HtmlElement;
+
+const SHOUTING_TEXT: &str = "/api/shouting_text";
+
+#[wasm_bindgen_test]
+async fn shows_the_shouted_text() {
+    clear_stubs();
+    stub(SHOUTING_TEXT, MockReply::json(&"HELLO").after(LATENCY));
+    let container = mount(SpawnLocal).await;
+    assert!(text(&container).contains("Click me"));
+
+    type_into(&container, "input", "hello");
+    click(&container, "button");
+    sleep(LATENCY / 2).await;
+    assert!(button_disabled(&container));
+    sleep(LATENCY * 2).await;
+    assert!(!button_disabled(&container));
+    assert!(text(&container).contains("HELLO"));
+    assert_eq!(mock_calls(SHOUTING_TEXT), 1);
+}
+
+#[wasm_bindgen_test]
+async fn shows_errors_next_to_the_button() {
+    clear_stubs();
+    stub(
+        SHOUTING_TEXT,
+        MockReply::error(ServerFnError::new("too quiet")),
+    );
+    let container = mount(SpawnLocal).await;
+
+    click(&container, "button");
+    sleep(LATENCY).await;
+    let error = container
+        .query_selector(".async-button-error")
+        .unwrap()
+        .expect("the error is shown");
+    assert!(error.text_content().unwrap().contains("too quiet"));
+    assert!(text(&container).contains("Click me"));
+}
+
+fn button_disabled(container: &HtmlElement) -> bool {
+    container
+        .query_selector("button")
+        .unwrap()
+        .unwrap()
+        .has_attribute("disabled")
+}
@@ -0,0 +1,97 @@
+//! `WithAnAction`: the row count goes up as soon as a row is submitted, and
+//! back down if adding it fails. A row that can't reach the server is kept
+//! and sent later.
+
+use crate::{click, mount, sleep, text, LATENCY};
+use server_fn::ServerFn;
+use server_fns_axum::{
+    app::{AddRow, GetRows, WithAnAction},
+    clients::{clear_stubs, mock_calls, stub, stub_sequence, MockReply},
+    errors::{AddRowError, AppError},
+    offline::{OfflineQueue, QueuedMutation},
+    persist::{forget, load, save},
+};
+use std::time::Duration;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+async fn counts_a_submitted_row_before_the_server_answers() {
+    clear_stubs();
+    forget::<OfflineQueue>();
+    stub_sequence(GetRows::PATH, [MockReply::json(&2), MockReply::json(&3)]);
+    stub(AddRow::PATH, MockReply::json(&3).after(LATENCY));
+    let container = mount(WithAnAction).await;
+    assert!(text(&container).contains("Total rows: 2"));
+
+    click(&container, "button");
+    sleep(Duration::from_millis(20)).await;
+    assert!(text(&container).contains("Total rows: 3 (saving...)"));
+
+    sleep(LATENCY * 2).await;
+    assert!(text(&container).contains("Total rows: 3"));
+    assert!(!text(&container).contains("saving"));
+    assert_eq!(mock_calls(AddRow::PATH), 1);
+    assert_eq!(mock_calls(GetRows::PATH), 2);
+}
+
+#[wasm_bindgen_test]
+async fn rolls_back_when_adding_fails() {
+    clear_stubs();
+    forget::<OfflineQueue>();
+    stub(GetRows::PATH, MockReply::json(&2));
+    stub(
+        AddRow::PATH,
+        MockReply::error(AppError::new(&AddRowError::ChaosMode)).after(LATENCY),
+    );
+    let container = mount(WithAnAction).await;
+
+    click(&container, "button");
+    sleep(Duration::from_millis(20)).await;
+    assert!(text(&container).contains("Total rows: 3 (saving...)"));
+
+    sleep(LATENCY * 2).await;
+    assert!(text(&container).contains("Total rows: 2"));
+    assert!(text(&container).contains("Couldn't add to database!"));
+}
+
+#[wasm_bindgen_test]
+async fn rolls_back_when_the_network_is_down() {
+    clear_stubs();
+    forget::<OfflineQueue>();
+    stub(GetRows::PATH, MockReply::json(&2));
+    stub(
+        AddRow::PATH,
+        MockReply::unreachable("offline").after(LATENCY),
+    );
+    let container = mount(WithAnAction).await;
+
+    click(&container, "button");
+    sleep(LATENCY * 2).await;
+    assert!(text(&container).contains("Total rows: 2"));
+    assert!(text(&container).contains("offline"));
+    assert!(text(&container).contains("1 rows waiting to be sent"));
+    assert_eq!(
+        load::<OfflineQueue>(),
+        Some(OfflineQueue(vec![QueuedMutation::AddRow {
+            text: String::new()
+        }]))
+    );
+    forget::<OfflineQueue>();
+}
+
+#[wasm_bindgen_test]
+async fn sends_rows_queued_on_an_earlier_visit() {
+    clear_stubs();
+    save(&OfflineQueue(vec![QueuedMutation::AddRow {
+        text: "Buy milk".into(),
+    }]));
+    stub_sequence(GetRows::PATH, [MockReply::json(&2), MockReply::json(&3)]);
+    stub(AddRow::PATH, MockReply::json(&3));
+    let container = mount(WithAnAction).await;
+
+    sleep(LATENCY).await;
+    assert_eq!(mock_calls(AddRow::PATH), 1);
+    assert!(text(&container).contains("Total rows: 3"));
+    assert!(!text(&container).contains("waiting to be sent"));
+    assert_eq!(load::<OfflineQueue>(), Some(OfflineQueue::default()));
+}